@@ -0,0 +1,436 @@
+use crate::error::DbError;
+use crate::row::Row;
+use crate::schema::Schema;
+use crate::statistics::TableStatistics;
+use parking_lot::{Mutex, RwLock};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the table rooted at page 0, which every database file has,
+/// whether it's in the catalog file or not.
+pub const DEFAULT_TABLE: &str = "users";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableInfo {
+    pub root_page_num: usize,
+    pub schema: Schema,
+    // Page of the dictionary encoding the strings of the table, if it's
+    // analyzed. See `Table::analyze`.
+    pub dictionary_page: Option<usize>,
+}
+
+/// Secondary index on a column of a table, see `SecondaryIndex`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexInfo {
+    pub column: String,
+    pub unique: bool,
+}
+
+// Tables, page of the metadata, statistics and indexes, in the order
+// they're saved.
+type Decoded = (
+    BTreeMap<String, TableInfo>,
+    Option<usize>,
+    BTreeMap<String, TableStatistics>,
+    BTreeMap<String, Vec<IndexInfo>>,
+);
+
+/// Tables of a database file, mapping their names to the root page of
+/// their tree and their schema.
+///
+/// It's saved next to the database file, e.g. `data.db.catalog`, and
+/// rewritten as a whole whenever a table is created, as it's small and
+/// rarely changes.
+///
+/// It also points to the page of the metadata of the file, if any, see
+/// `Metadata`, followed by the statistics of the analyzed tables, see
+/// `TableStatistics`, and the indexes of the tables, see `IndexInfo`.
+/// They're written after the tables, so a catalog saved before there was
+/// any of them is still read as is.
+///
+/// TRADEOFF: A separate file rather than a reserved page, as page 0 is
+/// already the root of the default table. The root page of a new table
+/// is flushed before the catalog is saved, so a crash in between leaks
+/// the page instead of leaving a table without its root.
+#[derive(Debug)]
+pub struct Catalog {
+    path: PathBuf,
+    tables: RwLock<BTreeMap<String, TableInfo>>,
+    // Only changed with the lock of the tables held, so saves don't race.
+    metadata_page: Mutex<Option<usize>>,
+    // Updated by every insert and delete, but only saved along with the
+    // rest of the catalog, e.g. by `set_statistics`.
+    statistics: Mutex<BTreeMap<String, TableStatistics>>,
+    indexes: Mutex<BTreeMap<String, Vec<IndexInfo>>>,
+    // Why the catalog file couldn't be read, if it couldn't, in which case
+    // it's never saved over, see `Catalog::unreadable`.
+    error: Option<DbError>,
+}
+
+impl Catalog {
+    /// Open the catalog of the database file, which only has the default
+    /// table if no table has been created yet, or fail if the catalog
+    /// file can't be read or decoded.
+    pub fn open(db_path: impl AsRef<Path>) -> Result<Catalog, DbError> {
+        let path = Self::path_of(db_path);
+        let decoded = match std::fs::read(&path) {
+            Ok(bytes) => Self::decode(&bytes).map_err(|err| {
+                DbError::Catalog(format!("catalog {} is corrupted: {err}", path.display()))
+            })?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Default::default(),
+            Err(err) => {
+                return Err(DbError::Io(format!(
+                    "fail to read catalog {}: {err}",
+                    path.display()
+                )))
+            }
+        };
+        Ok(Self::with_decoded(path, decoded, None))
+    }
+
+    /// Catalog of a database file whose catalog failed to open with the
+    /// error, which only has the default table and refuses to be saved,
+    /// so the tables it can't read aren't lost. `Table::check` reports the
+    /// error, see `Catalog::error`.
+    pub fn unreadable(db_path: impl AsRef<Path>, error: DbError) -> Catalog {
+        Self::with_decoded(Self::path_of(db_path), Default::default(), Some(error))
+    }
+
+    fn path_of(db_path: impl AsRef<Path>) -> PathBuf {
+        let mut path = db_path.as_ref().as_os_str().to_owned();
+        path.push(".catalog");
+        PathBuf::from(path)
+    }
+
+    fn with_decoded(path: PathBuf, decoded: Decoded, error: Option<DbError>) -> Catalog {
+        let (mut tables, metadata_page, statistics, indexes) = decoded;
+        tables
+            .entry(DEFAULT_TABLE.to_string())
+            .or_insert_with(|| TableInfo {
+                root_page_num: 0,
+                schema: Schema::users(),
+                dictionary_page: None,
+            });
+
+        Catalog {
+            path,
+            tables: RwLock::new(tables),
+            metadata_page: Mutex::new(metadata_page),
+            statistics: Mutex::new(statistics),
+            indexes: Mutex::new(indexes),
+            error,
+        }
+    }
+
+    /// Why the catalog file couldn't be read, if it was opened by
+    /// `unreadable`.
+    pub fn error(&self) -> Option<&DbError> {
+        self.error.as_ref()
+    }
+
+    fn decode(bytes: &[u8]) -> bincode::Result<Decoded> {
+        // Parts missing from a catalog saved before they existed are empty.
+        fn next<T: Default + DeserializeOwned>(reader: &mut &[u8]) -> bincode::Result<T> {
+            if reader.is_empty() {
+                Ok(T::default())
+            } else {
+                bincode::deserialize_from(reader)
+            }
+        }
+
+        let mut reader = bytes;
+        Ok((
+            bincode::deserialize_from(&mut reader)?,
+            next(&mut reader)?,
+            next(&mut reader)?,
+            next(&mut reader)?,
+        ))
+    }
+
+    pub fn get(&self, name: &str) -> Option<TableInfo> {
+        self.tables.read().get(name).cloned()
+    }
+
+    pub fn table_names(&self) -> Vec<String> {
+        self.tables.read().keys().cloned().collect()
+    }
+
+    /// Add a table, where `allocate_root` allocates the root page of its
+    /// tree. It's only called once the name is known to be free, and
+    /// other tables can't be created in the meantime.
+    pub fn create<F>(
+        &self,
+        name: &str,
+        schema: &Schema,
+        allocate_root: F,
+    ) -> Result<TableInfo, String>
+    where
+        F: FnOnce() -> Result<usize, String>,
+    {
+        let mut tables = self.tables.write();
+        if tables.contains_key(name) {
+            return Err(format!("table '{name}' already exists"));
+        }
+
+        let info = TableInfo {
+            root_page_num: allocate_root()?,
+            schema: schema.clone(),
+            dictionary_page: None,
+        };
+        tables.insert(name.to_string(), info.clone());
+
+        if let Err(err) = self.save(&tables) {
+            tables.remove(name);
+            return Err(format!("fail to save catalog: {err}"));
+        }
+
+        Ok(info)
+    }
+
+    /// Point the table to the dictionary saved in the given page.
+    pub fn set_dictionary(&self, name: &str, page_id: usize) -> Result<(), String> {
+        let mut tables = self.tables.write();
+        let info = tables
+            .get_mut(name)
+            .ok_or_else(|| format!("unknown table '{name}'"))?;
+        let previous = info.dictionary_page.replace(page_id);
+
+        if let Err(err) = self.save(&tables) {
+            tables.get_mut(name).unwrap().dictionary_page = previous;
+            return Err(format!("fail to save catalog: {err}"));
+        }
+        Ok(())
+    }
+
+    pub fn metadata_page(&self) -> Option<usize> {
+        *self.metadata_page.lock()
+    }
+
+    /// Point to the metadata saved by `write`, which is given the page of
+    /// the current metadata. Metadata is only updated by one `write` at a
+    /// time, so updates made in between aren't lost.
+    pub fn update_metadata<F>(&self, write: F) -> Result<usize, String>
+    where
+        F: FnOnce(Option<usize>) -> Result<usize, String>,
+    {
+        let tables = self.tables.write();
+        let previous = self.metadata_page();
+        let page_id = write(previous)?;
+        *self.metadata_page.lock() = Some(page_id);
+
+        if let Err(err) = self.save(&tables) {
+            *self.metadata_page.lock() = previous;
+            return Err(format!("fail to save catalog: {err}"));
+        }
+        Ok(page_id)
+    }
+
+    pub fn statistics(&self, name: &str) -> Option<TableStatistics> {
+        self.statistics.lock().get(name).cloned()
+    }
+
+    /// Replace the statistics of the table, e.g. once it's analyzed.
+    pub fn set_statistics(&self, name: &str, statistics: TableStatistics) -> Result<(), String> {
+        let tables = self.tables.write();
+        if !tables.contains_key(name) {
+            return Err(format!("unknown table '{name}'"));
+        }
+        let previous = self.statistics.lock().insert(name.to_string(), statistics);
+
+        if let Err(err) = self.save(&tables) {
+            let mut statistics = self.statistics.lock();
+            match previous {
+                Some(previous) => statistics.insert(name.to_string(), previous),
+                None => statistics.remove(name),
+            };
+            return Err(format!("fail to save catalog: {err}"));
+        }
+        Ok(())
+    }
+
+    /// Count an inserted row in the statistics of the table, if it's
+    /// analyzed.
+    pub fn record_insert(&self, name: &str, row: &Row) {
+        if let Some(statistics) = self.statistics.lock().get_mut(name) {
+            statistics.insert_row(row);
+        }
+    }
+
+    pub fn record_delete(&self, name: &str, key: u32) {
+        if let Some(statistics) = self.statistics.lock().get_mut(name) {
+            statistics.delete(key);
+        }
+    }
+
+    pub fn indexes(&self, name: &str) -> Vec<IndexInfo> {
+        self.indexes.lock().get(name).cloned().unwrap_or_default()
+    }
+
+    pub fn add_index(&self, name: &str, index: IndexInfo) -> Result<(), String> {
+        let tables = self.tables.write();
+        if !tables.contains_key(name) {
+            return Err(format!("unknown table '{name}'"));
+        }
+        let column = index.column.clone();
+        let mut indexes = self.indexes.lock();
+        let table_indexes = indexes.entry(name.to_string()).or_default();
+        if table_indexes.iter().any(|index| index.column == column) {
+            return Err(format!("index on '{name}.{column}' already exists"));
+        }
+        table_indexes.push(index);
+        drop(indexes);
+
+        if let Err(err) = self.save(&tables) {
+            if let Some(indexes) = self.indexes.lock().get_mut(name) {
+                indexes.retain(|index| index.column != column);
+            }
+            return Err(format!("fail to save catalog: {err}"));
+        }
+        Ok(())
+    }
+
+    /// Remove the table, e.g. when the transaction creating it aborts.
+    /// The pages of its tree are freed by the caller.
+    pub fn remove(&self, name: &str) -> Result<TableInfo, String> {
+        let mut tables = self.tables.write();
+        if name == DEFAULT_TABLE {
+            return Err(format!("table '{name}' can't be removed"));
+        }
+        let info = tables
+            .remove(name)
+            .ok_or_else(|| format!("unknown table '{name}'"))?;
+        let statistics = self.statistics.lock().remove(name);
+        let indexes = self.indexes.lock().remove(name);
+
+        if let Err(err) = self.save(&tables) {
+            tables.insert(name.to_string(), info);
+            if let Some(statistics) = statistics {
+                self.statistics.lock().insert(name.to_string(), statistics);
+            }
+            if let Some(indexes) = indexes {
+                self.indexes.lock().insert(name.to_string(), indexes);
+            }
+            return Err(format!("fail to save catalog: {err}"));
+        }
+        Ok(info)
+    }
+
+    // Write to a temporary file first, so a crash never leaves a partially
+    // written catalog behind.
+    fn save(&self, tables: &BTreeMap<String, TableInfo>) -> std::io::Result<()> {
+        if let Some(err) = &self.error {
+            return Err(std::io::Error::other(err.to_string()));
+        }
+
+        let mut bytes = bincode::serialize(tables).map_err(std::io::Error::other)?;
+        let metadata_page = bincode::serialize(&self.metadata_page());
+        bytes.extend(metadata_page.map_err(std::io::Error::other)?);
+        let statistics = bincode::serialize(&*self.statistics.lock());
+        bytes.extend(statistics.map_err(std::io::Error::other)?);
+        let indexes = bincode::serialize(&*self.indexes.lock());
+        bytes.extend(indexes.map_err(std::io::Error::other)?);
+
+        let mut tmp_path = self.path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, bytes)?;
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn create_and_reopen_catalog() {
+        let db_path = format!("test-{:?}.db", std::thread::current().id());
+        let catalog = Catalog::open(&db_path).unwrap();
+        assert_eq!(catalog.table_names(), vec!["users"]);
+        assert_eq!(catalog.get("users").unwrap().root_page_num, 0);
+
+        let info = catalog
+            .create("orders", &Schema::users(), || Ok(3))
+            .unwrap();
+        assert_eq!(info.root_page_num, 3);
+        assert_eq!(
+            catalog.create("orders", &Schema::users(), || Ok(4)),
+            Err("table 'orders' already exists".to_string())
+        );
+        assert_eq!(
+            catalog.create("items", &Schema::users(), || Err("no page".to_string())),
+            Err("no page".to_string())
+        );
+        drop(catalog);
+
+        let catalog = Catalog::open(&db_path).unwrap();
+        assert_eq!(catalog.table_names(), vec!["orders", "users"]);
+        assert_eq!(catalog.get("orders"), Some(info));
+
+        catalog.set_dictionary("orders", 5).unwrap();
+        assert_eq!(
+            catalog.set_dictionary("items", 6),
+            Err("unknown table 'items'".to_string())
+        );
+        let catalog = Catalog::open(&db_path).unwrap();
+        assert_eq!(catalog.get("orders").unwrap().dictionary_page, Some(5));
+
+        assert_eq!(catalog.remove("orders").unwrap().root_page_num, 3);
+        assert!(catalog.remove("users").is_err());
+        let catalog = Catalog::open(&db_path).unwrap();
+        assert_eq!(catalog.table_names(), vec!["users"]);
+        assert_eq!(catalog.metadata_page(), None);
+
+        assert_eq!(
+            catalog.update_metadata(|previous| Ok(previous.unwrap_or(6) + 1)),
+            Ok(7)
+        );
+        assert!(catalog
+            .update_metadata(|_| Err("no page".to_string()))
+            .is_err());
+        let catalog = Catalog::open(&db_path).unwrap();
+        assert_eq!(catalog.metadata_page(), Some(7));
+        assert_eq!(catalog.table_names(), vec!["users"]);
+
+        // Rows are only counted once the table is analyzed.
+        let row = |id: &str| Row::new(id, "john", "john@email.com").unwrap();
+        catalog.record_insert("users", &row("1"));
+        assert_eq!(catalog.statistics("users"), None);
+        let statistics = TableStatistics::build(&[1, 2, 3]);
+        catalog.set_statistics("users", statistics).unwrap();
+        catalog.record_insert("users", &row("4"));
+        assert!(catalog
+            .set_statistics("orders", TableStatistics::build(&[]))
+            .is_err());
+        let catalog = Catalog::open(&db_path).unwrap();
+        assert_eq!(catalog.metadata_page(), Some(7));
+        assert_eq!(catalog.statistics("users").unwrap().row_count, 3);
+
+        let _ = std::fs::remove_file(format!("{db_path}.catalog"));
+    }
+
+    #[test]
+    fn open_corrupted_catalog() {
+        let db_path = format!("test-{:?}.db", std::thread::current().id());
+        let catalog_path = format!("{db_path}.catalog");
+        std::fs::write(&catalog_path, [0xff; 3]).unwrap();
+
+        let err = Catalog::open(&db_path).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with(&format!("catalog {catalog_path} is corrupted: ")),
+            "{err}"
+        );
+
+        // The tables it can't read aren't saved over.
+        let catalog = Catalog::unreadable(&db_path, err.clone());
+        assert_eq!(catalog.error(), Some(&err));
+        assert_eq!(catalog.table_names(), vec!["users"]);
+        assert!(catalog
+            .create("orders", &Schema::users(), || Ok(3))
+            .is_err());
+        assert_eq!(std::fs::read(&catalog_path).unwrap(), [0xff; 3]);
+
+        let _ = std::fs::remove_file(catalog_path);
+    }
+}
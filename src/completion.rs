@@ -0,0 +1,142 @@
+use crate::catalog::Catalog;
+use crate::line_editor::Completer;
+use crate::query::META_COMMANDS;
+use crate::session::{SESSION_COMMANDS, SETTINGS};
+use std::sync::Arc;
+
+// Words starting a SQL statement, which are also the first word of our
+// positional statements, e.g. `insert 1 john john@email.com`, except for
+// `create` and `explain`.
+const STATEMENTS: [&str; 6] = ["select", "insert", "update", "delete", "create", "explain"];
+
+const KEYWORDS: [&str; 16] = [
+    "from", "where", "and", "or", "like", "into", "values", "table", "unique", "index", "on",
+    "primary", "key", "int", "integer", "text",
+];
+
+/// Completion of the REPL, where table and column names are looked up in
+/// the catalog on every completion, so tables created during the session
+/// are completed too.
+///
+/// - The first word completes to a statement, a meta command, e.g.
+///   `.tables`, or a session command, e.g. `\set`.
+/// - `\set` completes to the settings of the session, e.g. `max_rows`.
+/// - `from` and `into` complete to the tables.
+/// - Any other word completes to a keyword, or a column of the tables in
+///   the statement, or of every table if there's none yet, e.g. in
+///   `select * ` before the `from`.
+pub struct CatalogCompleter {
+    catalog: Arc<Catalog>,
+}
+
+impl CatalogCompleter {
+    pub fn new(catalog: Arc<Catalog>) -> Self {
+        Self { catalog }
+    }
+
+    fn columns(&self, words: &[&str]) -> Vec<String> {
+        let table_names = self.catalog.table_names();
+        let mut tables: Vec<&String> = table_names
+            .iter()
+            .filter(|name| words.contains(&name.as_str()))
+            .collect();
+        if tables.is_empty() {
+            tables = table_names.iter().collect();
+        }
+
+        tables
+            .into_iter()
+            .filter_map(|name| self.catalog.get(name))
+            .flat_map(|info| {
+                info.schema
+                    .columns()
+                    .iter()
+                    .map(|column| column.name.clone())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+impl Completer for CatalogCompleter {
+    fn candidates(&self, context: &str) -> Vec<String> {
+        let words: Vec<&str> = context
+            .split(|c: char| c.is_whitespace() || c == '(' || c == ',')
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        let Some(last) = words.last() else {
+            return STATEMENTS
+                .iter()
+                .chain(&META_COMMANDS)
+                .chain(&SESSION_COMMANDS)
+                .map(|word| word.to_string())
+                .collect();
+        };
+
+        if words[0] == "\\set" {
+            return match words.len() {
+                1 => SETTINGS.iter().map(|name| name.to_string()).collect(),
+                _ => Vec::new(),
+            };
+        }
+        if words[0].starts_with('.') {
+            return Vec::new();
+        }
+
+        let last = last.to_lowercase();
+        match last.as_str() {
+            "from" | "into" | "on" => self.catalog.table_names(),
+            // The name of a new table can't be completed.
+            "table" => Vec::new(),
+            _ => KEYWORDS
+                .iter()
+                .map(|keyword| keyword.to_string())
+                .chain(self.columns(&words))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::{handle_meta_command, MetaCommand};
+    use crate::schema::{none, primary, ColumnType, Schema};
+
+    #[test]
+    fn complete_from_catalog() {
+        let db_path = format!("test-{:?}.db", std::thread::current().id());
+        let catalog = Arc::new(Catalog::open(&db_path).unwrap());
+        let completer = CatalogCompleter::new(catalog.clone());
+        let candidates = |context: &str| completer.candidates(context);
+
+        assert!(candidates("").contains(&"select".to_string()));
+        assert!(candidates("").contains(&".tables".to_string()));
+        assert!(META_COMMANDS
+            .iter()
+            .all(|command| !matches!(handle_meta_command(command), MetaCommand::Unrecognized)));
+        assert_eq!(candidates("\\set "), SETTINGS);
+        assert!(candidates(".run ").is_empty());
+
+        assert_eq!(candidates("select * from "), vec!["users"]);
+        assert!(candidates("create table ").is_empty());
+
+        // Tables created later are completed too.
+        let schema = Schema::builder()
+            .col("id", ColumnType::Int, primary())
+            .col("item", ColumnType::Text(32), none())
+            .build()
+            .unwrap();
+        catalog.create("orders", &schema, || Ok(3)).unwrap();
+        assert_eq!(candidates("insert into "), vec!["orders", "users"]);
+
+        let where_clause = candidates("select * from orders ");
+        assert!(where_clause.contains(&"where".to_string()));
+        assert!(where_clause.contains(&"item".to_string()));
+        assert!(!where_clause.contains(&"email".to_string()));
+        assert!(candidates("select ").contains(&"email".to_string()));
+
+        let _ = std::fs::remove_file(format!("{db_path}.catalog"));
+    }
+}
@@ -1,9 +1,11 @@
-use super::table::RowID;
-use super::transaction::{Transaction, TransactionState};
-use parking_lot::{Condvar, Mutex, RwLock, RwLockUpgradableReadGuard};
+use super::transaction::{IsolationLevel, Transaction, TransactionState};
+use crate::error::DbError;
+use crate::table::RowID;
+use parking_lot::{Condvar, Mutex, MutexGuard, RwLock, RwLockUpgradableReadGuard};
 use std::collections::{HashMap, VecDeque};
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::trace;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -59,35 +61,83 @@ impl LockRequest {
 }
 
 type RequestQueue = Arc<(Mutex<LockRequestQueue>, Condvar)>;
+
+// TODO (Gap locks): Locks are only taken on existing rows, so an insert
+// never checks for a range conflict. Once Serializable takes gap locks, a
+// per page summary of the locked ranges, e.g. a bloom filter of key
+// prefixes, could let an insert skip the range check when there are no
+// gap locks around its key.
 pub struct LockManager {
     lock_table: Arc<RwLock<HashMap<RowID, RequestQueue>>>,
+    // How long a lock request waits before giving up, where `None` waits
+    // until the lock is granted.
+    timeout: Option<Duration>,
 }
 
 // The behaviour depends on the isolation level of the transaciton:
 //
 // - ReadUncommited: No shared lock is needed.
-// - ReadCommitted: Shared lock is release immediately.
+// - ReadCommitted: Shared lock is release immediately, see `unlock_after_read`.
 // - RepeatableRead: Strict 2PL, without index lock.
 // - Serializable: Strict 2PL and all locks.
 //
+// Exclusive locks are always held until the transaction ends, so no level
+// overwrites uncommitted rows, and only ReadUncommited reads them.
+//
 // Since, our current implmentation is a clustered table, where index and the row is stored
 // together, obtaining a lock on rid also obtained the lock on it's index. Hence,
-// we can't really have RepeatableRead level as we always hold the index lock together
-// with the row lock.
-//
-// Furthermore, ReadCommitted is also not possible since, we will be implmenting strict 2PL
-// in our system. Hence, we will be holding all the locks and only release it at the end.
+// RepeatableRead always holds the index lock together with the row lock, and
+// Serializable only differs from it once there are gap locks, see the TODO above.
 impl LockManager {
     pub fn new() -> Self {
         LockManager {
             lock_table: Arc::new(RwLock::new(HashMap::new())),
+            timeout: None,
         }
     }
 
-    pub fn lock_shared(&self, transaction: &mut Transaction, rid: RowID) -> bool {
+    /// Lock manager where a lock request that isn't granted within the
+    /// timeout fails with `DbError::LockTimeout`.
+    ///
+    /// The transaction is left as is, so it's up to the caller to abort
+    /// it, which also breaks a deadlock, since there's no deadlock
+    /// detection yet.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        LockManager {
+            timeout: Some(timeout),
+            ..Self::new()
+        }
+    }
+
+    // Wait for the queue to change, and return false if the timeout
+    // elapsed before.
+    fn wait(
+        &self,
+        condvar: &Condvar,
+        request_queue: &mut MutexGuard<LockRequestQueue>,
+        deadline: Option<Instant>,
+    ) -> bool {
+        match deadline {
+            Some(deadline) => !condvar.wait_until(request_queue, deadline).timed_out(),
+            None => {
+                condvar.wait(request_queue);
+                true
+            }
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    pub fn lock_shared(&self, transaction: &mut Transaction, rid: RowID) -> Result<bool, DbError> {
         trace!("lock_shared");
         if transaction.state == TransactionState::Aborted {
-            return false;
+            return Ok(false);
+        }
+        // Rows are read without waiting for the writers to commit.
+        if transaction.iso_level == IsolationLevel::ReadUncommited {
+            return Ok(true);
         }
 
         let lock_table = self.lock_table.upgradable_read();
@@ -149,7 +199,11 @@ impl LockManager {
             // on the behaviour of condvar.notfiy_one().
             if should_block {
                 trace!("lock_shared: waiting for lock");
-                condvar.wait(&mut request_queue);
+                // The request is only queued once granted, so there's
+                // nothing to remove from the queue.
+                if !self.wait(condvar, &mut request_queue, self.deadline()) {
+                    return Err(DbError::LockTimeout);
+                }
             }
 
             request.granted = true;
@@ -168,13 +222,17 @@ impl LockManager {
             transaction.shared_lock_sets.insert(rid);
         };
 
-        true
+        Ok(true)
     }
 
-    pub fn lock_exclusive(&self, transaction: &mut Transaction, rid: RowID) -> bool {
+    pub fn lock_exclusive(
+        &self,
+        transaction: &mut Transaction,
+        rid: RowID,
+    ) -> Result<bool, DbError> {
         trace!("lock_exclusive");
         if transaction.state == TransactionState::Aborted {
-            return false;
+            return Ok(false);
         }
 
         let lock_table = self.lock_table.upgradable_read();
@@ -210,12 +268,18 @@ impl LockManager {
             // any T is not holding a lock.
 
             // Hence, we have to continue to wait until the front element is not granted:
+            let deadline = self.deadline();
             while let Some(r) = request_queue.front() {
-                if r.granted {
-                    condvar.wait(&mut request_queue);
-                } else {
+                if !r.granted {
                     break;
                 }
+                if !self.wait(condvar, &mut request_queue, deadline) {
+                    // Take our request out of the queue, and wake up the
+                    // requests that were waiting behind it.
+                    request_queue.retain(|r| r.txn_id != transaction.txn_id || r.granted);
+                    condvar.notify_all();
+                    return Err(DbError::LockTimeout);
+                }
             }
 
             // We are looping manually to ensure that
@@ -234,7 +298,7 @@ impl LockManager {
             request.granted = true;
             transaction.exclusive_lock_sets.insert(rid);
             trace!("lock_exclusive end");
-            true
+            Ok(true)
         } else {
             request.granted = true;
 
@@ -246,14 +310,14 @@ impl LockManager {
 
             transaction.exclusive_lock_sets.insert(rid);
             trace!("lock_exclusive end");
-            true
+            Ok(true)
         }
     }
 
-    pub fn lock_upgrade(&self, transaction: &mut Transaction, rid: RowID) -> bool {
+    pub fn lock_upgrade(&self, transaction: &mut Transaction, rid: RowID) -> Result<bool, DbError> {
         trace!("lock_upgrade");
         if transaction.state == TransactionState::Aborted {
-            return false;
+            return Ok(false);
         }
 
         let lock_table = self.lock_table.read();
@@ -263,11 +327,15 @@ impl LockManager {
             let (request_queue, condvar) = &*inner.clone();
             let mut request_queue = request_queue.lock();
 
+            // On timeout, the shared lock is still held.
+            let deadline = self.deadline();
             while request_queue
                 .iter()
                 .any(|r| r.txn_id != transaction.txn_id && r.granted)
             {
-                condvar.wait(&mut request_queue)
+                if !self.wait(condvar, &mut request_queue, deadline) {
+                    return Err(DbError::LockTimeout);
+                }
             }
 
             // Adding assert to make sure it behaves correctly as I'm
@@ -288,14 +356,37 @@ impl LockManager {
                     true
                 });
 
-            result
+            Ok(result)
         } else {
-            false
+            Ok(false)
+        }
+    }
+
+    /// Release the shared lock taken to read the row, if the isolation
+    /// level doesn't hold it until the transaction ends, i.e. for
+    /// ReadCommitted. Rows read again might then have been changed by
+    /// transactions that committed in the meantime.
+    ///
+    /// Unlike `unlock`, the transaction can still take locks afterwards.
+    pub fn unlock_after_read(&self, transaction: &mut Transaction, rid: &RowID) {
+        if transaction.iso_level == IsolationLevel::ReadCommited && transaction.is_shared_lock(rid)
+        {
+            self.release(transaction, rid);
         }
     }
 
     pub fn unlock(&self, transaction: &mut Transaction, rid: &RowID) -> bool {
         trace!("unlock");
+        let released = self.release(transaction, rid);
+        // Locks are also released once the transaction ends, which keeps
+        // its state.
+        if released && transaction.state == TransactionState::Growing {
+            transaction.set_state(TransactionState::Shrinking);
+        }
+        released
+    }
+
+    fn release(&self, transaction: &mut Transaction, rid: &RowID) -> bool {
         let lock_table = self.lock_table.read();
 
         if let Some(inner) = lock_table.get(rid) {
@@ -311,10 +402,8 @@ impl LockManager {
             request_queue.remove(index);
             condvar.notify_one();
 
-            // Update transaction state
             transaction.shared_lock_sets.remove(rid);
             transaction.exclusive_lock_sets.remove(rid);
-            transaction.set_state(TransactionState::Shrinking);
 
             true
         } else {
@@ -334,7 +423,7 @@ mod test {
         let lm = LockManager::new();
         let mut transaction = Transaction::new(0, transaction::IsolationLevel::ReadCommited);
         let row_id = RowID::new(0, 0);
-        assert!(lm.lock_shared(&mut transaction, row_id));
+        assert_eq!(lm.lock_shared(&mut transaction, row_id), Ok(true));
         assert!(transaction.shared_lock_sets.contains(&row_id));
     }
 
@@ -343,7 +432,7 @@ mod test {
         let lm = LockManager::new();
         let mut transaction = Transaction::new(0, transaction::IsolationLevel::ReadCommited);
         let row_id = RowID::new(0, 0);
-        assert!(lm.lock_exclusive(&mut transaction, row_id));
+        assert_eq!(lm.lock_exclusive(&mut transaction, row_id), Ok(true));
         assert!(transaction.exclusive_lock_sets.contains(&row_id));
     }
 
@@ -354,13 +443,40 @@ mod test {
         let row_id = RowID::new(0, 0);
 
         // False, if we have no shared lock yet.
-        assert!(!lm.lock_upgrade(&mut transaction, row_id));
+        assert_eq!(lm.lock_upgrade(&mut transaction, row_id), Ok(false));
 
-        assert!(lm.lock_shared(&mut transaction, row_id));
-        assert!(lm.lock_upgrade(&mut transaction, row_id));
+        assert_eq!(lm.lock_shared(&mut transaction, row_id), Ok(true));
+        assert_eq!(lm.lock_upgrade(&mut transaction, row_id), Ok(true));
         assert!(transaction.exclusive_lock_sets.contains(&row_id));
     }
 
+    #[test]
+    fn lock_timeout() {
+        let lm = LockManager::with_timeout(Duration::from_millis(20));
+        let row_id = RowID::new(0, 0);
+        let mut t1 = Transaction::new(1, transaction::IsolationLevel::ReadCommited);
+        let mut t2 = Transaction::new(2, transaction::IsolationLevel::ReadCommited);
+        let mut t3 = Transaction::new(3, transaction::IsolationLevel::ReadCommited);
+
+        assert_eq!(lm.lock_shared(&mut t1, row_id), Ok(true));
+        assert_eq!(lm.lock_shared(&mut t2, row_id), Ok(true));
+        assert_eq!(lm.lock_upgrade(&mut t1, row_id), Err(DbError::LockTimeout));
+        assert!(t1.is_shared_lock(&row_id));
+        assert_eq!(
+            lm.lock_exclusive(&mut t3, row_id),
+            Err(DbError::LockTimeout)
+        );
+        assert!(!t3.is_exclusive_lock(&row_id));
+
+        // The request that timed out isn't left in the queue, so it doesn't
+        // block the requests after it.
+        assert!(lm.unlock(&mut t2, &row_id));
+        assert_eq!(lm.lock_upgrade(&mut t1, row_id), Ok(true));
+        assert_eq!(lm.lock_shared(&mut t2, row_id), Err(DbError::LockTimeout));
+        assert!(lm.unlock(&mut t1, &row_id));
+        assert_eq!(lm.lock_exclusive(&mut t3, row_id), Ok(true));
+    }
+
     #[test]
     fn concurrent_lock_sha_ex() {
         let lock_manager = Arc::new(LockManager::new());
@@ -442,11 +558,11 @@ mod test {
                     // It should block until successful once shared lock is released.
                     match mode {
                         LockMode::Shared => {
-                            assert!(lm.lock_shared(&mut transaction, row_id));
+                            assert_eq!(lm.lock_shared(&mut transaction, row_id), Ok(true));
                             assert!(transaction.shared_lock_sets.contains(&row_id));
                         }
                         LockMode::Exclusive => {
-                            assert!(lm.lock_exclusive(&mut transaction, row_id));
+                            assert_eq!(lm.lock_exclusive(&mut transaction, row_id), Ok(true));
                             assert!(transaction.exclusive_lock_sets.contains(&row_id));
                         }
                     }
@@ -490,7 +606,7 @@ mod test {
             let handle = thread::spawn(move || {
                 let mut transaction =
                     Transaction::new(i, transaction::IsolationLevel::ReadCommited);
-                assert!(lm.lock_shared(&mut transaction, row_id));
+                assert_eq!(lm.lock_shared(&mut transaction, row_id), Ok(true));
 
                 thread::sleep(Duration::from_millis(80));
 
@@ -505,9 +621,9 @@ mod test {
             thread::sleep(Duration::from_millis(50));
 
             let mut transaction = Transaction::new(0, transaction::IsolationLevel::ReadCommited);
-            assert!(lm.lock_shared(&mut transaction, row_id));
+            assert_eq!(lm.lock_shared(&mut transaction, row_id), Ok(true));
 
-            assert!(lm.lock_upgrade(&mut transaction, row_id));
+            assert_eq!(lm.lock_upgrade(&mut transaction, row_id), Ok(true));
             assert!(transaction.shared_lock_sets.is_empty());
             assert!(transaction.exclusive_lock_sets.contains(&row_id));
 
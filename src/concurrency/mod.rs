@@ -1,12 +1,11 @@
 mod lock_manager;
-mod table;
+mod timestamp_oracle;
 mod transaction;
 mod transaction_manager;
 
 pub use {
     lock_manager::LockManager,
-    table::{RowID, Table, TableIntoIter},
-    transaction::{IsolationLevel, Transaction},
+    transaction::{IsolationLevel, Transaction, TransactionState, WriteRecord, WriteRecordType},
     transaction_manager::TransactionManager,
 };
 
@@ -14,13 +13,18 @@ pub use {
 mod test {
     use super::lock_manager::LockManager;
     use super::transaction_manager::TransactionManager;
-    use super::{IsolationLevel, Table};
+    use super::{IsolationLevel, Transaction};
+    use crate::error::DbError;
     use crate::query::{
-        ExecutionContext, ExecutionEngine, IndexScanPlanNode, PlanNode, UpdatePlanNode,
+        ExecutionContext, ExecutionEngine, Expression, IndexScanPlanNode, PlanNode, UpdatePlanNode,
     };
     use crate::row::Row;
+    use crate::schema::Schema;
+    use crate::table::{RowID, Table};
+    use parking_lot::RwLock;
     use std::str::FromStr;
     use std::sync::Arc;
+    use std::time::Duration;
 
     #[test]
     fn repeatable_read() {
@@ -45,23 +49,25 @@ mod test {
             let lm = lock_manager.clone();
             let tb = table.clone();
             let handle = std::thread::spawn(move || {
-                let t1 = tm.begin(IsolationLevel::ReadCommited);
+                let t1 = tm.begin(IsolationLevel::RepeatableRead);
                 let ctx1 = Arc::new(ExecutionContext::new(tb.clone(), lm.clone(), t1.clone()));
                 let execution_engine = ExecutionEngine::new(ctx1);
                 let index_scan_plan_node = PlanNode::IndexScan(IndexScanPlanNode { key: 5 });
-                let result = execution_engine.execute(index_scan_plan_node.clone());
+                let result = execution_engine
+                    .execute(index_scan_plan_node.clone())
+                    .unwrap();
                 let (_rid, row) = &result[0];
                 assert_eq!(row.id, 5);
                 assert_eq!(row.username(), "user5");
 
                 // Make sure that T2 finish it's read write first before we attempt to read again.
                 std::thread::sleep(std::time::Duration::from_millis(15));
-                let (_, row) = &execution_engine.execute(index_scan_plan_node)[0];
+                let (_, row) = &execution_engine.execute(index_scan_plan_node).unwrap()[0];
                 assert_eq!(row.id, 5);
                 assert_eq!(row.username(), "user5");
 
                 let mut t1 = t1.write();
-                tm.commit(&tb, &mut t1);
+                tm.commit(&tb, &mut t1).unwrap();
             });
 
             // Transaction 2
@@ -73,18 +79,21 @@ mod test {
                 let ctx2 = Arc::new(ExecutionContext::new(tb.clone(), lm.clone(), t2.clone()));
                 let execution_engine = ExecutionEngine::new(ctx2);
                 let index_scan_plan_node = PlanNode::IndexScan(IndexScanPlanNode { key: 5 });
-                let update_plan_node = PlanNode::Update(UpdatePlanNode {
-                    child: Box::new(index_scan_plan_node.clone()),
-                    columns: vec!["username".to_string()],
-                    new_row: Row::new("0", "new_name", "").unwrap(),
-                });
+                let update_plan_node = PlanNode::Update(
+                    UpdatePlanNode::new(
+                        index_scan_plan_node.clone(),
+                        vec![("username", Expression::text("new_name"))],
+                        &Schema::users(),
+                    )
+                    .unwrap(),
+                );
 
                 // Make sure that T2 start later than T1..
                 std::thread::sleep(std::time::Duration::from_millis(10));
-                execution_engine.execute(index_scan_plan_node);
-                execution_engine.execute(update_plan_node);
+                execution_engine.execute(index_scan_plan_node).unwrap();
+                execution_engine.execute(update_plan_node).unwrap();
                 let mut t2 = t2.write();
-                tm.commit(&tb, &mut t2);
+                tm.commit(&tb, &mut t2).unwrap();
             });
 
             handle.join().unwrap();
@@ -122,20 +131,25 @@ mod test {
                 let ctx1 = Arc::new(ExecutionContext::new(tb.clone(), lm.clone(), t1.clone()));
                 let execution_engine = ExecutionEngine::new(ctx1);
                 let index_scan_plan_node = PlanNode::IndexScan(IndexScanPlanNode { key: 5 });
-                let update_plan_node = PlanNode::Update(UpdatePlanNode {
-                    child: Box::new(index_scan_plan_node.clone()),
-                    columns: vec!["username".to_string()],
-                    new_row: Row::new("0", "new_name", "").unwrap(),
-                });
-
-                let result = execution_engine.execute(index_scan_plan_node.clone());
+                let update_plan_node = PlanNode::Update(
+                    UpdatePlanNode::new(
+                        index_scan_plan_node.clone(),
+                        vec![("username", Expression::text("new_name"))],
+                        &Schema::users(),
+                    )
+                    .unwrap(),
+                );
+
+                let result = execution_engine
+                    .execute(index_scan_plan_node.clone())
+                    .unwrap();
                 let (_rid, row) = &result[0];
                 assert_eq!(row.id, 5);
                 assert_eq!(row.username(), "user5");
 
-                execution_engine.execute(update_plan_node);
+                execution_engine.execute(update_plan_node).unwrap();
 
-                let result = execution_engine.execute(index_scan_plan_node);
+                let result = execution_engine.execute(index_scan_plan_node).unwrap();
                 let (_rid, row) = &result[0];
                 assert_eq!(row.id, 5);
                 assert_eq!(row.username(), "new_name");
@@ -165,13 +179,13 @@ mod test {
                 // Make sure T1 started first
                 std::thread::sleep(std::time::Duration::from_millis(10));
 
-                let result = execution_engine.execute(index_scan_plan_node);
+                let result = execution_engine.execute(index_scan_plan_node).unwrap();
                 let (_rid, row) = &result[0];
                 assert_eq!(row.id, 5);
                 assert_eq!(row.username(), "user5");
 
                 let mut t2 = t2.write();
-                tm.commit(&tb, &mut t2);
+                tm.commit(&tb, &mut t2).unwrap();
             });
 
             handle.join().unwrap();
@@ -213,24 +227,30 @@ mod test {
                 let ctx1 = Arc::new(ExecutionContext::new(tb.clone(), lm.clone(), t1.clone()));
                 let execution_engine = ExecutionEngine::new(ctx1);
                 let index_scan_plan_node = PlanNode::IndexScan(IndexScanPlanNode { key: 5 });
-                let update_plan_node_a = PlanNode::Update(UpdatePlanNode {
-                    child: Box::new(index_scan_plan_node.clone()),
-                    columns: vec!["username".to_string()],
-                    new_row: Row::new("0", "t1_name", "").unwrap(),
-                });
-                let update_plan_node_b = PlanNode::Update(UpdatePlanNode {
-                    child: Box::new(index_scan_plan_node.clone()),
-                    columns: vec!["email".to_string()],
-                    new_row: Row::new("0", "", "t1_email").unwrap(),
-                });
-
-                execution_engine.execute(update_plan_node_a);
+                let update_plan_node_a = PlanNode::Update(
+                    UpdatePlanNode::new(
+                        index_scan_plan_node.clone(),
+                        vec![("username", Expression::text("t1_name"))],
+                        &Schema::users(),
+                    )
+                    .unwrap(),
+                );
+                let update_plan_node_b = PlanNode::Update(
+                    UpdatePlanNode::new(
+                        index_scan_plan_node.clone(),
+                        vec![("email", Expression::text("t1_email"))],
+                        &Schema::users(),
+                    )
+                    .unwrap(),
+                );
+
+                execution_engine.execute(update_plan_node_a).unwrap();
 
                 // Make sure that T2 finish before we continue
                 std::thread::sleep(std::time::Duration::from_millis(20));
-                execution_engine.execute(update_plan_node_b);
+                execution_engine.execute(update_plan_node_b).unwrap();
 
-                let result = execution_engine.execute(index_scan_plan_node);
+                let result = execution_engine.execute(index_scan_plan_node).unwrap();
                 let (_, row) = &result[0];
                 assert_eq!(row.username(), "t1_name");
                 assert_eq!(row.email(), "t1_email");
@@ -248,28 +268,34 @@ mod test {
                 let ctx2 = Arc::new(ExecutionContext::new(tb.clone(), lm.clone(), t2.clone()));
                 let execution_engine = ExecutionEngine::new(ctx2);
                 let index_scan_plan_node = PlanNode::IndexScan(IndexScanPlanNode { key: 5 });
-                let update_plan_node_a = PlanNode::Update(UpdatePlanNode {
-                    child: Box::new(index_scan_plan_node.clone()),
-                    columns: vec!["username".to_string()],
-                    new_row: Row::new("0", "t2_name", "").unwrap(),
-                });
-                let update_plan_node_b = PlanNode::Update(UpdatePlanNode {
-                    child: Box::new(index_scan_plan_node.clone()),
-                    columns: vec!["email".to_string()],
-                    new_row: Row::new("0", "", "t2_email").unwrap(),
-                });
+                let update_plan_node_a = PlanNode::Update(
+                    UpdatePlanNode::new(
+                        index_scan_plan_node.clone(),
+                        vec![("username", Expression::text("t2_name"))],
+                        &Schema::users(),
+                    )
+                    .unwrap(),
+                );
+                let update_plan_node_b = PlanNode::Update(
+                    UpdatePlanNode::new(
+                        index_scan_plan_node.clone(),
+                        vec![("email", Expression::text("t2_email"))],
+                        &Schema::users(),
+                    )
+                    .unwrap(),
+                );
 
                 // Make sure that T1 start first before continue:
                 std::thread::sleep(std::time::Duration::from_millis(10));
-                execution_engine.execute(update_plan_node_a);
-                execution_engine.execute(update_plan_node_b);
-                let result = execution_engine.execute(index_scan_plan_node);
+                execution_engine.execute(update_plan_node_a).unwrap();
+                execution_engine.execute(update_plan_node_b).unwrap();
+                let result = execution_engine.execute(index_scan_plan_node).unwrap();
                 let (_, row) = &result[0];
                 assert_eq!(row.username(), "t2_name");
                 assert_eq!(row.email(), "t2_email");
 
                 let mut t2 = t2.write();
-                tm.commit(&tb, &mut t2);
+                tm.commit(&tb, &mut t2).unwrap();
             });
 
             handle.join().unwrap();
@@ -279,15 +305,105 @@ mod test {
         }
     }
 
+    #[test]
+    fn anomalies_by_isolation_level() {
+        // Whether each level allows (dirty read, non-repeatable read). A
+        // read or write that would block fails with a lock timeout instead,
+        // so the transactions can be interleaved in a single thread.
+        let levels = [
+            (IsolationLevel::ReadUncommited, (true, true)),
+            (IsolationLevel::ReadCommited, (false, true)),
+            (IsolationLevel::RepeatableRead, (false, false)),
+            (IsolationLevel::Serializable, (false, false)),
+        ];
+
+        for (level, anomalies) in levels {
+            let lock_manager = Arc::new(LockManager::with_timeout(Duration::from_millis(20)));
+            let tm = TransactionManager::new(lock_manager.clone());
+            let table = Arc::new(setup_table(&tm, lock_manager.clone()));
+            let engine = |transaction: &Arc<RwLock<Transaction>>| {
+                ExecutionEngine::new(Arc::new(ExecutionContext::new(
+                    table.clone(),
+                    lock_manager.clone(),
+                    transaction.clone(),
+                )))
+            };
+            let read = || PlanNode::IndexScan(IndexScanPlanNode { key: 5 });
+            let write = |username: &str| {
+                let plan_node = UpdatePlanNode::new(
+                    read(),
+                    vec![("username", Expression::text(username))],
+                    &Schema::users(),
+                );
+                PlanNode::Update(plan_node.unwrap())
+            };
+            let username = |result: Result<Vec<(RowID, Row)>, DbError>| {
+                result.map(|rows| rows[0].1.username())
+            };
+
+            // Dirty read
+            //  T1               T2
+            // BEGIN
+            // W(A) -> dirty
+            //                  BEGIN
+            //                  R(A)
+            // ABORT
+            let t1 = tm.begin(IsolationLevel::RepeatableRead);
+            engine(&t1).execute(write("dirty")).unwrap();
+            let t2 = tm.begin(level);
+            let read_a = username(engine(&t2).execute(read()));
+            let dirty_read = match read_a {
+                Ok(username) => username == "dirty",
+                Err(err) => {
+                    assert_eq!(err, DbError::LockTimeout);
+                    false
+                }
+            };
+            tm.abort(&table, &mut t1.write());
+            tm.commit(&table, &mut t2.write()).unwrap();
+
+            // Non-repeatable read
+            //  T1               T2
+            // BEGIN
+            // R(A) -> user5
+            //                  BEGIN
+            //                  W(A) -> changed
+            //                  COMMIT
+            // R(A)
+            // COMMIT
+            let t1 = tm.begin(level);
+            assert_eq!(
+                username(engine(&t1).execute(read())),
+                Ok("user5".to_string())
+            );
+            let t2 = tm.begin(IsolationLevel::RepeatableRead);
+            match engine(&t2).execute(write("changed")) {
+                Ok(_) => tm.commit(&table, &mut t2.write()).unwrap(),
+                Err(err) => {
+                    assert_eq!(err, DbError::LockTimeout);
+                    tm.abort(&table, &mut t2.write());
+                }
+            }
+            let non_repeatable_read =
+                username(engine(&t1).execute(read())) == Ok("changed".to_string());
+            tm.commit(&table, &mut t1.write()).unwrap();
+
+            assert_eq!((dirty_read, non_repeatable_read), anomalies, "{level:?}");
+            drop(table);
+            cleanup_table();
+        }
+    }
+
     fn setup_table(tm: &TransactionManager, lm: Arc<LockManager>) -> Table {
-        let table = Table::new(format!("test-{:?}.db", std::thread::current().id()), 4, lm);
+        let table =
+            Table::with_lock_manager(format!("test-{:?}.db", std::thread::current().id()), 4, lm);
         let transaction = tm.begin(IsolationLevel::ReadCommited);
         let mut t = transaction.write();
         for i in 1..10 {
             let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
-            table.insert(&row, &mut t);
+            table.insert_in(&row, &mut t).unwrap();
         }
-        tm.commit(&table, &mut t);
+        tm.commit(&table, &mut t).unwrap();
 
         table
     }
@@ -0,0 +1,182 @@
+use parking_lot::Mutex;
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Timestamps reserved on disk at once, so a commit only writes the file
+// once every that many commits.
+const RESERVE_BATCH: u64 = 1024;
+
+/// Hands out increasing commit timestamps, which keep increasing across
+/// restarts when the oracle is saved to a file. See
+/// `TransactionManager::with_timestamp_oracle`.
+///
+/// Instead of saving every timestamp, the oracle saves a ceiling it can
+/// hand out timestamps up to, and saves a new one when it runs out. A
+/// reopened oracle starts from the saved ceiling, so the timestamps handed
+/// out before a crash are skipped rather than reused.
+///
+/// The stable timestamp is the largest one every commit up to has
+/// finished, e.g. for a reader to see a consistent state as of it.
+#[derive(Debug)]
+pub struct TimestampOracle {
+    // File the ceiling is saved in, if any.
+    path: Option<PathBuf>,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    // Last timestamp handed out.
+    last: u64,
+    // Timestamps up to it can be handed out without saving a new ceiling.
+    reserved: u64,
+    // Timestamps of the commits that haven't finished yet.
+    in_flight: BTreeSet<u64>,
+}
+
+impl TimestampOracle {
+    /// An oracle that starts from 0 whenever it's created.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            state: Mutex::new(State {
+                last: 0,
+                reserved: u64::MAX,
+                in_flight: BTreeSet::new(),
+            }),
+        }
+    }
+
+    /// Open the oracle saved in the file, or start from 0 if it doesn't
+    /// exist.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let ceiling = match std::fs::read_to_string(&path) {
+            Ok(content) => content.trim().parse::<u64>().map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid commit timestamp in {}: {err}", path.display()),
+                )
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            state: Mutex::new(State {
+                last: ceiling,
+                reserved: ceiling,
+                in_flight: BTreeSet::new(),
+            }),
+        })
+    }
+
+    /// Hand out the next timestamp to a commit, which has to `finish` it
+    /// once its writes are applied.
+    ///
+    /// Fails if a new ceiling can't be saved, as handing out timestamps
+    /// past the saved one would reuse them after a restart.
+    pub fn next(&self) -> io::Result<u64> {
+        let mut state = self.state.lock();
+        if state.last == state.reserved {
+            let reserved = state.reserved + RESERVE_BATCH;
+            self.save(reserved).map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("fail to save commit timestamp {reserved}: {err}"),
+                )
+            })?;
+            state.reserved = reserved;
+        }
+
+        state.last += 1;
+        let timestamp = state.last;
+        state.in_flight.insert(timestamp);
+        Ok(timestamp)
+    }
+
+    pub fn finish(&self, timestamp: u64) {
+        self.state.lock().in_flight.remove(&timestamp);
+    }
+
+    /// Largest timestamp handed out so far.
+    pub fn current(&self) -> u64 {
+        self.state.lock().last
+    }
+
+    /// Largest timestamp that every commit up to, and including, has
+    /// finished.
+    pub fn stable(&self) -> u64 {
+        let state = self.state.lock();
+        match state.in_flight.first() {
+            Some(timestamp) => timestamp - 1,
+            None => state.last,
+        }
+    }
+
+    // Write to a temporary file first, so a crash never leaves a partially
+    // written ceiling behind.
+    fn save(&self, ceiling: u64) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut file = std::fs::File::create(&tmp_path)?;
+        writeln!(file, "{ceiling}")?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn timestamps_increase_across_restarts() {
+        let path = format!("test-{:?}.ts", std::thread::current().id());
+        let _ = std::fs::remove_file(&path);
+
+        let oracle = TimestampOracle::open(&path).unwrap();
+        assert_eq!((oracle.current(), oracle.stable()), (0, 0));
+        let first = oracle.next().unwrap();
+        let second = oracle.next().unwrap();
+        assert_eq!((first, second), (1, 2));
+
+        // The second commit finishing first doesn't make it stable.
+        oracle.finish(second);
+        assert_eq!(oracle.stable(), 0);
+        oracle.finish(first);
+        assert_eq!(oracle.stable(), 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1024\n");
+        drop(oracle);
+
+        // Timestamps handed out before the restart are skipped.
+        let oracle = TimestampOracle::open(&path).unwrap();
+        assert_eq!(oracle.current(), RESERVE_BATCH);
+        assert_eq!(oracle.next().unwrap(), RESERVE_BATCH + 1);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}\n", RESERVE_BATCH * 2)
+        );
+
+        // A ceiling that can't be saved fails the commit instead of
+        // handing out timestamps that would be reused.
+        let dir = format!("test-{:?}.ts.dir", std::thread::current().id());
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        let oracle = TimestampOracle::open(format!("{dir}/missing/ts")).unwrap();
+        assert!(oracle.next().is_err());
+        assert_eq!(oracle.current(), 0);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        std::fs::write(&path, "not a timestamp").unwrap();
+        assert!(TimestampOracle::open(&path).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,12 +1,21 @@
-use super::table::RowID;
+use crate::error::DbError;
 use crate::row::Row;
-use std::collections::HashSet;
+use crate::table::RowID;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// Writes a transaction can make unless its transaction manager is given
+// another limit, see `TransactionManager::with_max_write_set`.
+pub const DEFAULT_MAX_WRITE_SET: usize = 1_000_000;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum WriteRecordType {
     Insert,
     Delete,
     Update,
+    CreateTable,
 }
 
 #[derive(Debug)]
@@ -16,6 +25,8 @@ pub struct WriteRecord {
     pub wr_type: WriteRecordType,
     pub old_row: Option<Row>,
     pub columns: Vec<String>,
+    // Table created by the transaction, for `CreateTable`.
+    pub table: Option<String>,
 }
 
 impl WriteRecord {
@@ -26,15 +37,67 @@ impl WriteRecord {
             key,
             old_row: None,
             columns: vec![],
+            table: None,
+        }
+    }
+
+    /// Record of a table created by the transaction, which is removed from
+    /// the catalog if the transaction aborts.
+    pub fn create_table(name: &str) -> Self {
+        Self {
+            table: Some(name.to_string()),
+            ..Self::new(WriteRecordType::CreateTable, RowID::new(0, 0), 0)
         }
     }
 }
 
-#[derive(Debug)]
+/// Anomalies each level allows, where a dirty read is a read of a row
+/// that isn't committed yet, and a non-repeatable read is a row changing
+/// between two reads of the same transaction:
+///
+/// | Level          | Dirty read | Non-repeatable read | Phantom |
+/// |----------------|------------|---------------------|---------|
+/// | ReadUncommited | yes        | yes                 | yes     |
+/// | ReadCommited   | no         | yes                 | yes     |
+/// | RepeatableRead | no         | no                  | yes     |
+/// | Serializable   | no         | no                  | yes*    |
+///
+/// *Serializable should prevent phantoms too, but there are no gap locks
+/// yet, see `LockManager`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IsolationLevel {
     ReadUncommited,
     ReadCommited,
     RepeatableRead,
+    Serializable,
+}
+
+impl FromStr for IsolationLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level {
+            "read_uncommitted" => Ok(IsolationLevel::ReadUncommited),
+            "read_committed" => Ok(IsolationLevel::ReadCommited),
+            "repeatable_read" => Ok(IsolationLevel::RepeatableRead),
+            "serializable" => Ok(IsolationLevel::Serializable),
+            _ => Err(format!(
+                "unknown isolation level '{level}', expected read_uncommitted, read_committed, repeatable_read or serializable"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for IsolationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self {
+            IsolationLevel::ReadUncommited => "read_uncommitted",
+            IsolationLevel::ReadCommited => "read_committed",
+            IsolationLevel::RepeatableRead => "repeatable_read",
+            IsolationLevel::Serializable => "serializable",
+        };
+        write!(f, "{level}")
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -50,12 +113,31 @@ pub struct Transaction {
     pub txn_id: u32,
     pub iso_level: IsolationLevel,
     pub state: TransactionState,
+    // Timestamp the transaction committed at, see `TimestampOracle`.
+    pub commit_ts: Option<u64>,
     write_sets: Vec<WriteRecord>,
+    // Writes the write set can hold, see `Transaction::check_write_set`.
+    max_write_set: usize,
     pub shared_lock_sets: HashSet<RowID>,
     pub exclusive_lock_sets: HashSet<RowID>,
 
     // The LSN of the last record written by the transaciton
     prev_lsn: Option<u32>,
+
+    // Statements reproducing the writes of the transaction, appended to
+    // the statement log on commit. See `Table::with_statement_log`.
+    statements: Vec<String>,
+
+    // Metadata written by the transaction, which is only saved on commit.
+    // See `Table::meta_put_in`.
+    metadata: BTreeMap<String, Vec<u8>>,
+
+    // When the transaction last finished a statement, or None while it
+    // runs one. See `TransactionManager::reap_idle`.
+    idle_since: Option<Instant>,
+    // How long the transaction can stay idle before it's rolled back, if
+    // ever.
+    idle_timeout: Option<Duration>,
 }
 
 impl Transaction {
@@ -64,11 +146,66 @@ impl Transaction {
             txn_id,
             iso_level,
             state: TransactionState::Growing,
+            commit_ts: None,
             write_sets: Vec::new(),
+            max_write_set: DEFAULT_MAX_WRITE_SET,
             shared_lock_sets: HashSet::new(),
             exclusive_lock_sets: HashSet::new(),
             prev_lsn: None,
+            statements: Vec::new(),
+            metadata: BTreeMap::new(),
+            idle_since: Some(Instant::now()),
+            idle_timeout: None,
+        }
+    }
+
+    pub fn with_max_write_set(mut self, max_write_set: usize) -> Self {
+        self.max_write_set = max_write_set;
+        self
+    }
+
+    /// Check that the write set can hold another write before it's made,
+    /// aborting the transaction if it can't, so its writes are rolled back
+    /// and the write set stays bounded.
+    ///
+    /// TODO (Transactions): The write set could spill to pages of its own
+    /// instead, so a transaction of any size can commit.
+    pub fn check_write_set(&mut self) -> Result<(), DbError> {
+        if self.write_sets.len() >= self.max_write_set {
+            self.state = TransactionState::Aborted;
+            return Err(DbError::TooLargeTransaction(self.max_write_set));
         }
+        Ok(())
+    }
+
+    /// Mark the transaction as running a statement, so it's never rolled
+    /// back for being idle meanwhile, or as idle from now on once the
+    /// statement is done.
+    pub fn set_busy(&mut self, busy: bool) {
+        self.idle_since = if busy { None } else { Some(Instant::now()) };
+    }
+
+    /// How long the transaction has been idle, or None if it's running a
+    /// statement.
+    pub fn idle_for(&self) -> Option<Duration> {
+        self.idle_since.map(|since| since.elapsed())
+    }
+
+    pub fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// Whether the transaction has been idle for longer than its idle
+    /// timeout, see `TransactionManager::reap_idle`.
+    pub fn is_idle_expired(&self) -> bool {
+        match (self.idle_for(), self.idle_timeout) {
+            (Some(idle), Some(timeout)) => idle > timeout,
+            _ => false,
+        }
+    }
+
+    pub fn prev_lsn(&self) -> Option<u32> {
+        self.prev_lsn
     }
 
     pub fn update_prev_lsn(&mut self, lsn: u32) {
@@ -87,6 +224,27 @@ impl Transaction {
         self.write_sets.pop()
     }
 
+    pub fn push_statement(&mut self, statement: String) {
+        self.statements.push(statement);
+    }
+
+    pub fn take_statements(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.statements)
+    }
+
+    pub fn put_metadata(&mut self, key: &str, value: &[u8]) {
+        self.metadata.insert(key.to_string(), value.to_vec());
+    }
+
+    /// Value the transaction wrote for the key, if any.
+    pub fn metadata(&self, key: &str) -> Option<&[u8]> {
+        self.metadata.get(key).map(Vec::as_slice)
+    }
+
+    pub fn take_metadata(&mut self) -> BTreeMap<String, Vec<u8>> {
+        std::mem::take(&mut self.metadata)
+    }
+
     pub fn is_shared_lock(&self, rid: &RowID) -> bool {
         self.shared_lock_sets.contains(rid)
     }
@@ -1,30 +1,101 @@
 use super::lock_manager::LockManager;
-use super::table::Table;
-use super::transaction::{IsolationLevel, Transaction, TransactionState, WriteRecordType};
-use parking_lot::RwLock;
+use super::timestamp_oracle::TimestampOracle;
+use super::transaction::{
+    IsolationLevel, Transaction, TransactionState, WriteRecordType, DEFAULT_MAX_WRITE_SET,
+};
+use crate::metrics::Operation;
+use crate::table::Table;
+use parking_lot::{Mutex, RwLock};
 use std::collections::{HashMap, HashSet};
-use std::sync::{self, atomic::AtomicU32, Arc};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU32};
+use std::sync::{self, Arc};
+use std::time::{Duration, Instant};
+use tracing::warn;
 
 pub struct TransactionManager {
     next_txn_id: AtomicU32,
     transaction_map: Arc<RwLock<HashMap<u32, Arc<RwLock<Transaction>>>>>,
     lock_manager: Arc<LockManager>,
+    commit_hooks: RwLock<Vec<CommitHook>>,
+    timestamp_oracle: TimestampOracle,
+    // Writes each transaction can make, see `with_max_write_set`.
+    max_write_set: usize,
+    // Notices for the clients of the transactions rolled back while idle,
+    // by transaction id, see `reap_idle`.
+    notices: Mutex<HashMap<u32, String>>,
+    reaper_started: AtomicBool,
+}
+
+// How often the reaper looks for idle transactions, which bounds how long
+// past its idle timeout a transaction can keep its locks.
+const REAPER_INTERVAL: Duration = Duration::from_millis(100);
+
+type CommitHook = Arc<dyn Fn(&CommitSummary) + Send + Sync>;
+
+/// The keys written by a committed transaction, passed to the callbacks
+/// registered with `TransactionManager::on_commit`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitSummary {
+    pub txn_id: u32,
+    pub commit_ts: u64,
+    pub inserted: Vec<u32>,
+    pub deleted: Vec<u32>,
+    pub updated: Vec<u32>,
 }
 
-// A couple of things we have potentially not implemented:
-//
-//   - Cleaning up `transaction_map`. Currently, aborted and committed transactions
-//     are not removed from the map yet.
 impl TransactionManager {
     pub fn new(lock_manager: Arc<LockManager>) -> Self {
         Self {
             next_txn_id: AtomicU32::new(1),
             transaction_map: Arc::new(RwLock::new(HashMap::new())),
             lock_manager,
+            commit_hooks: RwLock::new(Vec::new()),
+            timestamp_oracle: TimestampOracle::in_memory(),
+            max_write_set: DEFAULT_MAX_WRITE_SET,
+            notices: Mutex::new(HashMap::new()),
+            reaper_started: AtomicBool::new(false),
         }
     }
 
-    pub fn execute<F, T>(&self, table: &Table, iso_level: IsolationLevel, f: F) -> T
+    /// Limit the writes of each transaction, so a transaction deleting or
+    /// updating most of a big table fails with
+    /// `DbError::TooLargeTransaction` and is aborted, rather than growing
+    /// its write set without bound.
+    pub fn with_max_write_set(mut self, max_write_set: usize) -> Self {
+        self.max_write_set = max_write_set;
+        self
+    }
+
+    /// Hand out commit timestamps from the given oracle, e.g. one saved
+    /// next to the database file, so they keep increasing across restarts.
+    pub fn with_timestamp_oracle(mut self, timestamp_oracle: TimestampOracle) -> Self {
+        self.timestamp_oracle = timestamp_oracle;
+        self
+    }
+
+    /// Largest commit timestamp that every transaction committed up to
+    /// has finished committing, see `TimestampOracle::stable`.
+    pub fn stable_timestamp(&self) -> u64 {
+        self.timestamp_oracle.stable()
+    }
+
+    /// Register a callback that runs after each transaction commits, e.g.
+    /// to invalidate a cache of the written keys.
+    ///
+    /// Callbacks run in the committing thread once its locks are released,
+    /// so they can start new transactions, and never run for aborted
+    /// transactions. The commit record is flushed to the log before they
+    /// run, so a callback never sees a commit that a crash could undo.
+    pub fn on_commit<F>(&self, callback: F)
+    where
+        F: Fn(&CommitSummary) + Send + Sync + 'static,
+    {
+        self.commit_hooks.write().push(Arc::new(callback));
+    }
+
+    /// Run `f` in a new transaction, and commit it unless `f` ended it.
+    pub fn execute<F, T>(&self, table: &Table, iso_level: IsolationLevel, f: F) -> io::Result<T>
     where
         F: FnOnce(Arc<RwLock<Transaction>>, &TransactionManager) -> T,
     {
@@ -35,36 +106,87 @@ impl TransactionManager {
         // are not aborted or committed.
         let mut t = transaction.write();
         if t.state != TransactionState::Aborted && t.state != TransactionState::Committed {
-            self.commit(table, &mut t);
+            self.commit(table, &mut t)?;
         }
 
-        result
+        Ok(result)
     }
 
     pub fn begin(&self, iso_level: IsolationLevel) -> Arc<RwLock<Transaction>> {
+        // The id is taken under the lock of the map, so a transaction is
+        // never missing from the map with an id below the `watermark`.
+        let mut map = self.transaction_map.write();
         let txn_id = self
             .next_txn_id
             .fetch_add(1, sync::atomic::Ordering::SeqCst);
 
-        let transaction = Arc::new(RwLock::new(Transaction::new(txn_id, iso_level)));
-
-        let mut map = self.transaction_map.write();
+        let transaction =
+            Transaction::new(txn_id, iso_level).with_max_write_set(self.max_write_set);
+        let transaction = Arc::new(RwLock::new(transaction));
         map.insert(txn_id, Arc::clone(&transaction));
         drop(map);
 
         transaction
     }
 
-    pub fn commit(&self, table: &Table, transaction: &mut Transaction) {
+    /// Commit the transaction, or roll it back if it can't be given a
+    /// commit timestamp, e.g. as the oracle fails to save its ceiling.
+    pub fn commit(&self, table: &Table, transaction: &mut Transaction) -> io::Result<()> {
+        let started = Instant::now();
+        let commit_ts = match self.timestamp_oracle.next() {
+            Ok(commit_ts) => commit_ts,
+            Err(err) => {
+                self.abort(table, transaction);
+                return Err(err);
+            }
+        };
         transaction.set_state(TransactionState::Committed);
+        transaction.commit_ts = Some(commit_ts);
 
+        let mut summary = CommitSummary {
+            txn_id: transaction.txn_id,
+            commit_ts,
+            ..CommitSummary::default()
+        };
         while let Some(wr) = transaction.pop_write_set() {
-            if wr.wr_type == WriteRecordType::Delete {
-                table.apply_delete(wr.key);
+            match wr.wr_type {
+                WriteRecordType::Insert => summary.inserted.push(wr.key),
+                WriteRecordType::Delete => {
+                    table.apply_delete(wr.key);
+                    summary.deleted.push(wr.key);
+                }
+                WriteRecordType::Update => summary.updated.push(wr.key),
+                // The catalog is saved when the table is created.
+                WriteRecordType::CreateTable => {}
             }
         }
+        // TRADEOFF: Metadata is saved after the rows are, so a failure,
+        // e.g. the namespace outgrowing its page, is only logged.
+        if let Err(err) = table.apply_metadata(transaction.take_metadata()) {
+            warn!(
+                "fail to save the metadata of transaction {}: {err}",
+                transaction.txn_id
+            );
+        }
+        table.log_end(transaction);
+        table.log_statements(transaction.take_statements());
 
         self.release_locks(transaction);
+        self.forget(transaction.txn_id);
+        self.timestamp_oracle.finish(commit_ts);
+        table.record_latency(Operation::Commit, started);
+
+        // The write set is popped from the latest write.
+        summary.inserted.reverse();
+        summary.deleted.reverse();
+        summary.updated.reverse();
+        // Callbacks run without holding the lock of the callbacks too, so
+        // they can commit other transactions or register callbacks.
+        let hooks = self.commit_hooks.read().clone();
+        for hook in hooks {
+            hook(&summary);
+        }
+        Ok(())
     }
 
     pub fn abort(&self, table: &Table, transaction: &mut Transaction) {
@@ -73,14 +195,110 @@ impl TransactionManager {
         while let Some(wr) = transaction.pop_write_set() {
             match wr.wr_type {
                 WriteRecordType::Insert => table.apply_delete(wr.key),
-                WriteRecordType::Delete => table.rollback_delete(&wr.rid),
+                WriteRecordType::Delete => table.rollback_delete(&wr.rid, transaction),
                 WriteRecordType::Update => {
-                    table.rollback_update(&wr.rid, &wr.old_row.unwrap(), &wr.columns)
+                    table.rollback_update(&wr.rid, &wr.old_row.unwrap(), &wr.columns, transaction)
+                }
+                WriteRecordType::CreateTable => {
+                    table.rollback_create_table(&wr.table.unwrap(), transaction)
                 }
             }
         }
+        table.log_end(transaction);
 
         self.release_locks(transaction);
+        self.forget(transaction.txn_id);
+    }
+
+    /// Transactions begun and neither committed nor rolled back yet.
+    pub fn active_transactions(&self) -> usize {
+        self.transaction_map.read().len()
+    }
+
+    /// Id of the oldest transaction in progress, or of the next one to
+    /// begin if there are none, so every transaction with a lower id has
+    /// ended.
+    ///
+    /// TODO (MVCC): Rows are updated in place, so there are no versions
+    /// older than the watermark to garbage collect yet.
+    pub fn watermark(&self) -> u32 {
+        let map = self.transaction_map.read();
+        map.keys()
+            .min()
+            .copied()
+            .unwrap_or_else(|| self.next_txn_id.load(sync::atomic::Ordering::SeqCst))
+    }
+
+    /// Roll back the transactions idle for longer than their idle timeout,
+    /// e.g. a REPL session left in the middle of a transaction, so the
+    /// locks they keep until they end, see `LockManager`, stop blocking
+    /// every other transaction. Return the ids of the transactions rolled
+    /// back, and queue a notice for each of them, see `take_notice`.
+    ///
+    /// Transactions running a statement are skipped, as are the ones
+    /// locked by another thread, which aren't idle either.
+    pub fn reap_idle(&self, table: &Table) -> Vec<u32> {
+        let transactions: Vec<_> = self.transaction_map.read().values().cloned().collect();
+
+        let mut reaped = Vec::new();
+        for transaction in transactions {
+            let Some(mut t) = transaction.try_write() else {
+                continue;
+            };
+            let ended = matches!(
+                t.state,
+                TransactionState::Committed | TransactionState::Aborted
+            );
+            if ended || !t.is_idle_expired() {
+                continue;
+            }
+
+            let idle = t.idle_for().unwrap_or_default();
+            self.abort(table, &mut t);
+            warn!(
+                "rolled back transaction {} after being idle for {idle:?}",
+                t.txn_id
+            );
+            self.notices.lock().insert(
+                t.txn_id,
+                format!(
+                    "transaction {} was rolled back after being idle for {}ms, and its locks were released",
+                    t.txn_id,
+                    idle.as_millis()
+                ),
+            );
+            reaped.push(t.txn_id);
+        }
+        reaped
+    }
+
+    /// Notice queued for the client of a transaction rolled back by
+    /// `reap_idle`, which is only returned once.
+    pub fn take_notice(&self, txn_id: u32) -> Option<String> {
+        self.notices.lock().remove(&txn_id)
+    }
+
+    /// Run `reap_idle` in the background every `REAPER_INTERVAL`, until
+    /// the table is dropped. The reaper is only started once, however
+    /// many times it's called.
+    pub fn start_reaper(&self, table: &Arc<Table>) {
+        if self
+            .reaper_started
+            .swap(true, sync::atomic::Ordering::SeqCst)
+        {
+            return;
+        }
+
+        // The transaction manager is owned by the table, so the reaper
+        // only holds on to the table while it reaps.
+        let table = Arc::downgrade(table);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(REAPER_INTERVAL);
+            let Some(table) = table.upgrade() else {
+                return;
+            };
+            table.transaction_manager().reap_idle(&table);
+        });
     }
 
     fn release_locks(&self, transaction: &mut Transaction) {
@@ -98,6 +316,13 @@ impl TransactionManager {
         }
     }
 
+    // Ended transactions are only referenced by their callers from now
+    // on, e.g. a session, rather than kept in the map forever.
+    fn forget(&self, txn_id: u32) {
+        self.transaction_map.write().remove(&txn_id);
+    }
+
+    #[cfg(test)]
     fn get_transaction(&self, txn_id: &u32) -> Arc<RwLock<Transaction>> {
         let map = self.transaction_map.read();
         map.get(txn_id).expect("transaction not found").clone()
@@ -106,19 +331,46 @@ impl TransactionManager {
 
 #[cfg(test)]
 mod test {
-    use super::{IsolationLevel, LockManager, TransactionManager, TransactionState};
-    use crate::{concurrency::table::Table, row::Row};
+    use super::{CommitSummary, IsolationLevel, LockManager, TransactionManager, TransactionState};
+    use crate::concurrency::timestamp_oracle::TimestampOracle;
+    use crate::{row::Row, table::Table};
+    use parking_lot::Mutex;
     use std::str::FromStr;
     use std::sync::Arc;
+    use std::time::Duration;
 
     fn setup_table(lm: Arc<LockManager>) -> Table {
-        Table::new(format!("test-{:?}.db", std::thread::current().id()), 4, lm)
+        Table::with_lock_manager(format!("test-{:?}.db", std::thread::current().id()), 4, lm)
     }
 
     fn cleanup_table() {
         let _ = std::fs::remove_file(format!("test-{:?}.db", std::thread::current().id()));
     }
 
+    #[test]
+    fn watermark_of_oldest_active_transaction() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(lm);
+        assert_eq!(tm.watermark(), 1);
+
+        let first = tm.begin(IsolationLevel::ReadCommited);
+        let second = tm.begin(IsolationLevel::ReadCommited);
+        let third = tm.begin(IsolationLevel::ReadCommited);
+        assert_eq!((tm.active_transactions(), tm.watermark()), (3, 1));
+
+        // Ending a newer transaction doesn't move the watermark, but ending
+        // the oldest one does.
+        tm.commit(&table, &mut second.write()).unwrap();
+        assert_eq!((tm.active_transactions(), tm.watermark()), (2, 1));
+        tm.abort(&table, &mut first.write());
+        assert_eq!((tm.active_transactions(), tm.watermark()), (1, 3));
+        tm.commit(&table, &mut third.write()).unwrap();
+        assert_eq!((tm.active_transactions(), tm.watermark()), (0, 4));
+
+        cleanup_table();
+    }
+
     #[test]
     fn transaction_operations() {
         let lm = Arc::new(LockManager::new());
@@ -129,8 +381,7 @@ mod test {
         assert_eq!(transaction.state, TransactionState::Growing);
         drop(transaction);
 
-        let map = tm.transaction_map.read();
-        assert_eq!(map.len(), 1);
+        assert_eq!(tm.active_transactions(), 1);
 
         let tx = tm.get_transaction(&1);
         let mut tx = tx.write();
@@ -138,8 +389,10 @@ mod test {
         assert_eq!(tx.state, TransactionState::Growing);
 
         let table = setup_table(lm);
-        tm.commit(&table, &mut tx);
+        tm.commit(&table, &mut tx).unwrap();
         assert_eq!(tx.state, TransactionState::Committed);
+        // The ended transaction isn't kept around in the map.
+        assert_eq!(tm.active_transactions(), 0);
 
         cleanup_table();
     }
@@ -152,14 +405,15 @@ mod test {
         let row = Row::from_str("1 apple apple@apple.com").unwrap();
         tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
             let mut t = transaction.write();
-            let rid = table.insert(&row, &mut t).unwrap();
+            let rid = table.insert_in(&row, &mut t).unwrap();
             drop(t);
 
             let mut t = transaction.write();
             let inserted_row = table.get(rid, &mut t).unwrap();
 
             assert_eq!(row, inserted_row);
-        });
+        })
+        .unwrap();
 
         cleanup_table();
     }
@@ -170,30 +424,28 @@ mod test {
         let tm = TransactionManager::new(lm.clone());
         let table = setup_table(lm.clone());
         let row = Row::from_str("1 apple apple@apple.com").unwrap();
-        let rid = tm.execute(&table, IsolationLevel::ReadCommited, |transaction, tm| {
-            let mut t = transaction.write();
-            let rid = table.insert(&row, &mut t).unwrap();
-            drop(t);
+        let rid = tm
+            .execute(&table, IsolationLevel::ReadCommited, |transaction, tm| {
+                let mut t = transaction.write();
+                let rid = table.insert_in(&row, &mut t).unwrap();
+                drop(t);
 
-            let mut t = transaction.write();
-            tm.abort(&table, &mut t);
+                let mut t = transaction.write();
+                tm.abort(&table, &mut t);
 
-            assert_eq!(t.state, TransactionState::Aborted);
-            rid
-        });
+                assert_eq!(t.state, TransactionState::Aborted);
+                rid
+            })
+            .unwrap();
 
         tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
             let mut t = transaction.write();
             assert_eq!(table.get(rid, &mut t), None);
-        });
+        })
+        .unwrap();
 
-        // We should have an aborted transaciton.
-        let map = tm.transaction_map.read();
-        let transaction = map.iter().find(|(_, t)| {
-            let t = t.read();
-            t.state == TransactionState::Aborted
-        });
-        assert!(transaction.is_some());
+        // The aborted transaction is removed from the map.
+        assert_eq!(tm.active_transactions(), 0);
 
         cleanup_table();
     }
@@ -204,27 +456,24 @@ mod test {
         let tm = TransactionManager::new(lm.clone());
         let table = setup_table(lm.clone());
         let row = Row::from_str("1 apple apple@apple.com").unwrap();
-        let rid = tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
-            let mut t = transaction.write();
-            table.insert(&row, &mut t).unwrap()
-        });
+        let rid = tm
+            .execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
+                let mut t = transaction.write();
+                table.insert_in(&row, &mut t).unwrap()
+            })
+            .unwrap();
 
         // Delete and abort
         tm.execute(&table, IsolationLevel::ReadCommited, |transaction, tm| {
             let mut t = transaction.write();
-            assert!(table.delete(&row, &rid, &mut t));
+            assert_eq!(table.delete_in(&row, &rid, &mut t), Ok(true));
             tm.abort(&table, &mut t);
             assert_eq!(t.state, TransactionState::Aborted);
-        });
+        })
+        .unwrap();
 
-        // We should have an aborted transaciton.
-        let map = tm.transaction_map.read();
-        let transaction = map.iter().find(|(_, t)| {
-            let t = t.read();
-            t.state == TransactionState::Aborted
-        });
-        assert!(transaction.is_some());
-        drop(map);
+        // The aborted transaction is removed from the map.
+        assert_eq!(tm.active_transactions(), 0);
 
         // Make sure row is still there
         tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
@@ -234,18 +483,21 @@ mod test {
 
             let row = row.unwrap();
             assert!(!row.is_deleted);
-        });
+        })
+        .unwrap();
 
         // Finally delete and commit it
         tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
             let mut t = transaction.write();
-            assert!(table.delete(&row, &rid, &mut t));
-        });
+            assert_eq!(table.delete_in(&row, &rid, &mut t), Ok(true));
+        })
+        .unwrap();
 
         tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
             let mut t = transaction.write();
             assert_eq!(table.get(rid, &mut t), None);
-        });
+        })
+        .unwrap();
 
         cleanup_table();
     }
@@ -256,29 +508,29 @@ mod test {
         let tm = TransactionManager::new(lm.clone());
         let table = setup_table(lm.clone());
         let row = Row::from_str("1 apple apple@apple.com").unwrap();
-        let rid = tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
-            let mut t = transaction.write();
-            table.insert(&row, &mut t).unwrap()
-        });
+        let rid = tm
+            .execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
+                let mut t = transaction.write();
+                table.insert_in(&row, &mut t).unwrap()
+            })
+            .unwrap();
 
         // Update and abort
         let new_row = Row::from_str("1 john tim@apple.com").unwrap();
         let columns = vec!["username".to_string(), "email".to_string()];
         tm.execute(&table, IsolationLevel::ReadCommited, |transaction, tm| {
             let mut t = transaction.write();
-            assert!(table.update(&row, &new_row, &columns, &rid, &mut t));
+            assert_eq!(
+                table.update(&row, &new_row, &columns, &rid, &mut t),
+                Ok(true)
+            );
             tm.abort(&table, &mut t);
             assert_eq!(t.state, TransactionState::Aborted);
-        });
+        })
+        .unwrap();
 
-        // We should have an aborted transaciton.
-        let map = tm.transaction_map.read();
-        let transaction = map.iter().find(|(_, t)| {
-            let t = t.read();
-            t.state == TransactionState::Aborted
-        });
-        assert!(transaction.is_some());
-        drop(map);
+        // The aborted transaction is removed from the map.
+        assert_eq!(tm.active_transactions(), 0);
 
         // Make sure row is still there and the value is unchange
         tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
@@ -289,13 +541,18 @@ mod test {
             let row = row.unwrap();
             assert_eq!(row.username(), "apple");
             assert_eq!(row.email(), "apple@apple.com");
-        });
+        })
+        .unwrap();
 
         // Finally delete and commit it
         tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
             let mut t = transaction.write();
-            assert!(table.update(&row, &new_row, &columns, &rid, &mut t));
-        });
+            assert_eq!(
+                table.update(&row, &new_row, &columns, &rid, &mut t),
+                Ok(true)
+            );
+        })
+        .unwrap();
 
         tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
             let mut t = transaction.write();
@@ -305,8 +562,147 @@ mod test {
             let row = row.unwrap();
             assert_eq!(row.username(), "john");
             assert_eq!(row.email(), "tim@apple.com");
-        });
+        })
+        .unwrap();
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn run_commit_hooks_for_committed_transactions_only() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(lm.clone());
+        let summaries = Arc::new(Mutex::new(Vec::new()));
+        let hook_summaries = summaries.clone();
+        tm.on_commit(move |summary| hook_summaries.lock().push(summary.clone()));
+
+        let row = Row::from_str("1 apple apple@apple.com").unwrap();
+        let rid = tm
+            .execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
+                let mut t = transaction.write();
+                let rid = table.insert_in(&row, &mut t).unwrap();
+                let row = Row::from_str("2 pear pear@apple.com").unwrap();
+                table.insert_in(&row, &mut t).unwrap();
+                rid
+            })
+            .unwrap();
+
+        // Aborted transactions don't run the callbacks.
+        tm.execute(&table, IsolationLevel::ReadCommited, |transaction, tm| {
+            let mut t = transaction.write();
+            assert_eq!(table.delete_in(&row, &rid, &mut t), Ok(true));
+            tm.abort(&table, &mut t);
+        })
+        .unwrap();
+
+        let new_row = Row::from_str("1 john tim@apple.com").unwrap();
+        let columns = vec!["username".to_string()];
+        tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
+            let mut t = transaction.write();
+            assert_eq!(
+                table.update(&row, &new_row, &columns, &rid, &mut t),
+                Ok(true)
+            );
+        })
+        .unwrap();
+
+        assert_eq!(
+            *summaries.lock(),
+            vec![
+                CommitSummary {
+                    txn_id: 1,
+                    commit_ts: 1,
+                    inserted: vec![1, 2],
+                    ..CommitSummary::default()
+                },
+                CommitSummary {
+                    txn_id: 3,
+                    commit_ts: 2,
+                    updated: vec![1],
+                    ..CommitSummary::default()
+                },
+            ]
+        );
+        assert_eq!(tm.stable_timestamp(), 2);
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn reap_idle_transactions() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(lm);
+        let row = Row::from_str("1 apple apple@apple.com").unwrap();
+
+        let idle = tm.begin(IsolationLevel::RepeatableRead);
+        table.insert_in(&row, &mut idle.write()).unwrap();
+        idle.write().set_idle_timeout(Some(Duration::ZERO));
+
+        let busy = tm.begin(IsolationLevel::RepeatableRead);
+        busy.write().set_idle_timeout(Some(Duration::ZERO));
+        busy.write().set_busy(true);
+
+        // Transactions without an idle timeout are never rolled back.
+        let _patient = tm.begin(IsolationLevel::RepeatableRead);
+
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(tm.reap_idle(&table), vec![1]);
+        assert_eq!(idle.read().state, TransactionState::Aborted);
+        assert_eq!(busy.read().state, TransactionState::Growing);
+        assert!(tm
+            .take_notice(1)
+            .unwrap()
+            .starts_with("transaction 1 was rolled back"));
+        assert_eq!(tm.take_notice(1), None);
+
+        // The insert is rolled back.
+        tm.execute(&table, IsolationLevel::ReadCommited, |transaction, _tm| {
+            let mut t = transaction.write();
+            assert_eq!(table.insert_in(&row, &mut t).map(|_| ()), Ok(()));
+        })
+        .unwrap();
+        assert!(tm.reap_idle(&table).is_empty());
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn commit_timestamps_survive_restarts() {
+        let path = format!("test-{:?}.db.ts", std::thread::current().id());
+        let _ = std::fs::remove_file(&path);
+        let lm = Arc::new(LockManager::new());
+        let table = setup_table(lm.clone());
+        let commit = |tm: &TransactionManager| {
+            let transaction = tm.begin(IsolationLevel::ReadCommited);
+            let mut t = transaction.write();
+            tm.commit(&table, &mut t).unwrap();
+            t.commit_ts.unwrap()
+        };
+
+        let tm = TransactionManager::new(lm.clone())
+            .with_timestamp_oracle(TimestampOracle::open(&path).unwrap());
+        assert_eq!(commit(&tm), 1);
+        assert_eq!(commit(&tm), 2);
+        assert_eq!(tm.stable_timestamp(), 2);
+        drop(tm);
+
+        let tm = TransactionManager::new(lm.clone())
+            .with_timestamp_oracle(TimestampOracle::open(&path).unwrap());
+        assert!(commit(&tm) > 2);
+        drop(tm);
+
+        // A commit that can't save its timestamp is rolled back.
+        let tm = TransactionManager::new(lm.clone())
+            .with_timestamp_oracle(TimestampOracle::open(format!("{path}.missing/ts")).unwrap());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        assert!(tm.commit(&table, &mut t).is_err());
+        assert_eq!(t.state, TransactionState::Aborted);
+        assert_eq!(tm.active_transactions(), 0);
 
+        let _ = std::fs::remove_file(&path);
         cleanup_table();
     }
 }
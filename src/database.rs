@@ -0,0 +1,284 @@
+use crate::concurrency::{self, IsolationLevel, TransactionState};
+use crate::error::DbError;
+use crate::options::DbOptions;
+use crate::query::{
+    execute_in, prepare_statement, query_in, query_page, query_planned, ResultSet, Statement,
+};
+use crate::table::Table;
+use parking_lot::RwLock;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A database file opened by another program, which runs the same input
+/// as the REPL does against it:
+///
+/// ```no_run
+/// use sqlite::{Database, DbOptions};
+///
+/// let db = Database::open("data.db", DbOptions::new()).unwrap();
+/// db.execute("insert 1 john john@email.com").unwrap();
+///
+/// let txn = db.begin();
+/// txn.execute("update users set username = 'wick' where id = 1").unwrap();
+/// txn.commit().unwrap();
+/// ```
+pub struct Database {
+    table: Arc<Table>,
+}
+
+impl Database {
+    /// Open the file, or create it if it doesn't exist, and check it the
+    /// way `sqlite check` does, returning the problems found if any.
+    pub fn open(path: impl AsRef<Path>, options: DbOptions) -> Result<Self, DbError> {
+        let table = Table::with_options(path, &options);
+        table.check().map_err(DbError::CheckFailed)?;
+
+        Ok(Self {
+            table: Arc::new(table),
+        })
+    }
+
+    /// Execute the input in a transaction of its own and return what the
+    /// REPL prints for it, where the input is a statement, an `explain`,
+    /// or a meta command such as `.tables`.
+    pub fn execute(&self, input: &str) -> Result<String, DbError> {
+        crate::execute_input(&self.table, input)
+    }
+
+    /// Same as `execute`, but return the rows of the select instead of
+    /// printing them.
+    pub fn query(&self, sql: &str) -> Result<ResultSet, DbError> {
+        let statement = prepare_statement(sql).map_err(DbError::InvalidInput)?;
+        let _op = self.table.enter()?;
+        if let Some(query) = &statement.query {
+            return query_planned(&self.table, query);
+        }
+        self.table
+            .with_table(statement.table.as_deref(), |table| table.query(&statement))
+    }
+
+    /// Select a page of at most `page_size` rows in id order, after the row
+    /// with id `after`, or the first page if it's None, along with the id
+    /// to pass as `after` for the next page, which is None after the last
+    /// one.
+    ///
+    /// A page resumes after the id of the last row of the previous one
+    /// rather than at an offset, so rows inserted or deleted before it in
+    /// the meantime can't cause rows to be skipped or repeated.
+    pub fn page(
+        &self,
+        after: Option<u32>,
+        page_size: usize,
+    ) -> Result<(ResultSet, Option<u32>), DbError> {
+        let _op = self.table.enter()?;
+        let result_set = query_page(&self.table, after, page_size)?;
+        let next = match result_set.rows().last() {
+            Some(rows) if result_set.len() == page_size => Some(rows[0].id),
+            _ => None,
+        };
+        Ok((result_set, next))
+    }
+
+    /// Start a transaction, where the rows read are locked until it ends,
+    /// like `begin` in the REPL.
+    pub fn begin(&self) -> Transaction<'_> {
+        let transaction = self
+            .table
+            .transaction_manager()
+            .begin(IsolationLevel::RepeatableRead);
+        Transaction {
+            database: self,
+            transaction,
+        }
+    }
+
+    /// Table the database is opened with, e.g. to run a REPL `Session`.
+    pub fn table(&self) -> &Arc<Table> {
+        &self.table
+    }
+
+    /// Flush every page to the file, rejecting any operation from now on.
+    pub fn close(&self) -> Result<(), DbError> {
+        self.table.close()
+    }
+}
+
+/// Transaction started by `Database::begin`, which is rolled back when
+/// it's dropped without being committed.
+///
+/// A statement that fails rolls the transaction back as well, after
+/// which the transaction can't be used anymore.
+pub struct Transaction<'a> {
+    database: &'a Database,
+    transaction: Arc<RwLock<concurrency::Transaction>>,
+}
+
+impl Transaction<'_> {
+    /// Execute the statement and return what the REPL prints for it, e.g.
+    /// `inserted 1 rows`.
+    pub fn execute(&self, sql: &str) -> Result<String, DbError> {
+        self.run(sql, execute_in)
+    }
+
+    /// Same as `execute`, but return the rows selected, or written by a
+    /// write.
+    pub fn query(&self, sql: &str) -> Result<ResultSet, DbError> {
+        self.run(sql, query_in)
+    }
+
+    pub fn commit(self) -> Result<(), DbError> {
+        self.end(TransactionState::Committed)
+    }
+
+    pub fn rollback(self) -> Result<(), DbError> {
+        self.end(TransactionState::Aborted)
+    }
+
+    fn run<T>(
+        &self,
+        sql: &str,
+        f: impl FnOnce(
+            &Arc<Table>,
+            &Statement,
+            &Arc<RwLock<concurrency::Transaction>>,
+        ) -> Result<T, DbError>,
+    ) -> Result<T, DbError> {
+        self.check_active()?;
+        let statement = prepare_statement(sql).map_err(DbError::InvalidInput)?;
+        let _op = self.database.table.enter()?;
+        f(&self.database.table, &statement, &self.transaction)
+    }
+
+    fn end(&self, state: TransactionState) -> Result<(), DbError> {
+        self.check_active()?;
+        let table = &self.database.table;
+        let mut t = self.transaction.write();
+        if state == TransactionState::Committed {
+            table.transaction_manager().commit(table, &mut t)?;
+        } else {
+            table.transaction_manager().abort(table, &mut t);
+        }
+        Ok(())
+    }
+
+    fn check_active(&self) -> Result<(), DbError> {
+        match self.transaction.read().state {
+            TransactionState::Committed | TransactionState::Aborted => {
+                Err(DbError::TransactionEnded)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        // Committed and rolled back transactions are already ended.
+        let _ = self.end(TransactionState::Aborted);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::{LatchProtocol, ReplacementPolicy, SyncMode};
+    use std::time::Duration;
+
+    #[test]
+    fn embed_database() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let db = Database::open(&path, DbOptions::new().with_pool_size(16)).unwrap();
+        db.execute("insert 1 john john@email.com").unwrap();
+        db.execute("insert 2 wick wick@email.com").unwrap();
+
+        let txn = db.begin();
+        assert_eq!(
+            txn.execute("delete from users where id = 1"),
+            Ok("deleted 1 rows".to_string())
+        );
+        assert_eq!(txn.query("select 2").unwrap().len(), 1);
+        txn.commit().unwrap();
+        assert_eq!(
+            db.execute("select"),
+            Ok("(2, wick, wick@email.com)\n".to_string())
+        );
+
+        // Dropping a transaction rolls it back.
+        {
+            let txn = db.begin();
+            txn.execute("delete from users where id = 2").unwrap();
+        }
+        assert_eq!(db.query("select").unwrap().len(), 1);
+
+        // A page resumes after the last row of the previous one, even if
+        // that row is deleted in between.
+        db.execute("insert 3 john john@email.com").unwrap();
+        db.execute("insert 4 jane jane@email.com").unwrap();
+        let (_, after) = db.page(None, 2).unwrap();
+        assert_eq!(after, Some(3));
+        db.execute("delete 3").unwrap();
+        let (page, after) = db.page(after, 2).unwrap();
+        assert_eq!(page.to_string(), "(4, jane, jane@email.com)\n");
+        assert_eq!(after, None);
+
+        // A failed statement rolls the transaction back too.
+        let txn = db.begin();
+        assert!(txn.execute("insert 2 wick wick@email.com").is_err());
+        assert_eq!(txn.commit(), Err(DbError::TransactionEnded));
+
+        db.close().unwrap();
+        assert_eq!(db.execute("select"), Err(DbError::TableClosed));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+        let _ = std::fs::remove_file(format!("{path}.hot"));
+    }
+
+    #[test]
+    fn open_with_options() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let wal = format!("test-{:?}.wal", std::thread::current().id());
+        let options = DbOptions::new()
+            .with_pool_size(4)
+            .with_sync_mode(SyncMode::Full)
+            .with_replacement_policy(ReplacementPolicy::Lfu)
+            .with_lock_timeout(Duration::from_millis(20))
+            .with_wal(&wal)
+            .with_max_top_n_rows(10)
+            .with_max_dirty_percent(25)
+            .with_latch_protocol(LatchProtocol::SmoLatch);
+        let db = Database::open(&path, options).unwrap();
+        for i in 1..=50 {
+            db.execute(&format!("insert {i} user{i} user{i}@email.com"))
+                .unwrap();
+        }
+        assert_eq!(db.query("select").unwrap().len(), 50);
+        let metrics = db.execute(".metrics").unwrap();
+        assert!(metrics.contains(", max 25%, "), "{metrics}");
+        assert!(metrics.contains("latch protocol: SmoLatch, "), "{metrics}");
+        assert_eq!(
+            db.execute("explain select * from users order by username limit 5 offset 10"),
+            Ok("Limit limit=5 offset=10\n  Sort order_by=username ASC\n    SeqScan".to_string())
+        );
+
+        // The second writer gives up on the lock held by the first.
+        let first = db.begin();
+        first.execute("delete 1").unwrap();
+        let second = db.begin();
+        assert_eq!(
+            second.execute("delete 1"),
+            Err(DbError::TransactionAborted(Box::new(DbError::LockTimeout)))
+        );
+        first.commit().unwrap();
+        assert_eq!(db.query("select").unwrap().len(), 49);
+
+        db.close().unwrap();
+        for file in [
+            path.clone(),
+            format!("{path}.catalog"),
+            format!("{path}.hot"),
+            format!("{wal}.000001"),
+        ] {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+}
@@ -0,0 +1,781 @@
+use crate::export;
+use crate::health::Health;
+use crate::metrics;
+use crate::migration::{self, Migration};
+use crate::pgwire;
+use crate::recovery::StatementLog;
+use crate::replay;
+use crate::row::Row;
+use crate::server::Server;
+use crate::storage::format_dump;
+use crate::table::{CopyResult, Table};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use threadpool::ThreadPool;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Dump {
+        jobs: usize,
+        db: String,
+        dir: String,
+    },
+    Restore {
+        jobs: usize,
+        db: String,
+        dir: String,
+    },
+    Health {
+        db: String,
+    },
+    Check {
+        db: String,
+    },
+    FormatDump {
+        db: String,
+        page: usize,
+    },
+    Serve {
+        listen: String,
+        statement_log: Option<String>,
+        // Address Prometheus scrapes the latency metrics from, if any.
+        metrics_listen: Option<String>,
+        // Speak the line protocol of `Server` instead of PostgreSQL's.
+        line: bool,
+        db: String,
+    },
+    Bootstrap {
+        log: String,
+        db: String,
+    },
+    Export {
+        db: String,
+        out: String,
+    },
+    Replay {
+        jobs: usize,
+        speed: Option<f64>,
+        log: String,
+        db: String,
+    },
+    Migrate {
+        batch_size: usize,
+        // Time to sleep between batches, in milliseconds.
+        pause_ms: u64,
+        db: String,
+        name: String,
+    },
+}
+
+const USAGE: &str = "usage: sqlite (dump|restore) [--jobs N] <db file> <dir>
+       sqlite health <db file>
+       sqlite check <db file>
+       sqlite format-dump <db file> <page>
+       sqlite serve [--listen ADDR] [--statement-log FILE] [--metrics-listen ADDR] [--line] <db file>
+       sqlite bootstrap <statement log> <db file>
+       sqlite export <db file> <sqlite file>
+       sqlite replay [--jobs N] [--speed X] <log file> <db file>
+       sqlite migrate [--batch-size N] [--pause MS] <db file> <migration>";
+
+const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:5432";
+
+/// Parse the command line arguments, excluding the program name.
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let (command, rest) = args.split_first().ok_or(USAGE)?;
+    if command == "health" {
+        let [db] = <[String; 1]>::try_from(rest.to_vec()).map_err(|_| USAGE)?;
+        return Ok(Command::Health { db });
+    }
+    if command == "check" {
+        let [db] = <[String; 1]>::try_from(rest.to_vec()).map_err(|_| USAGE)?;
+        return Ok(Command::Check { db });
+    }
+    if command == "format-dump" {
+        let [db, page] = <[String; 2]>::try_from(rest.to_vec()).map_err(|_| USAGE)?;
+        let page = page.parse().map_err(|_| "page must be a number")?;
+        return Ok(Command::FormatDump { db, page });
+    }
+    if command == "export" {
+        let [db, out] = <[String; 2]>::try_from(rest.to_vec()).map_err(|_| USAGE)?;
+        return Ok(Command::Export { db, out });
+    }
+    if command == "bootstrap" {
+        let [log, db] = <[String; 2]>::try_from(rest.to_vec()).map_err(|_| USAGE)?;
+        return Ok(Command::Bootstrap { log, db });
+    }
+
+    let mut jobs = 1;
+    let mut speed = None;
+    let mut batch_size = migration::DEFAULT_BATCH_SIZE;
+    let mut pause_ms = 0;
+    let mut listen = DEFAULT_LISTEN_ADDR.to_string();
+    let mut statement_log = None;
+    let mut metrics_listen = None;
+    let mut line = false;
+    let mut positional = Vec::new();
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        if arg == "--listen" && command == "serve" {
+            listen = rest.next().ok_or("--listen requires an address")?.clone();
+        } else if arg == "--statement-log" && command == "serve" {
+            statement_log = Some(
+                rest.next()
+                    .ok_or("--statement-log requires a file")?
+                    .clone(),
+            );
+        } else if arg == "--metrics-listen" && command == "serve" {
+            metrics_listen = Some(
+                rest.next()
+                    .ok_or("--metrics-listen requires an address")?
+                    .clone(),
+            );
+        } else if arg == "--line" && command == "serve" {
+            line = true;
+        } else if arg == "--speed" && command == "replay" {
+            speed = Some(
+                rest.next()
+                    .and_then(|speed| speed.parse::<f64>().ok())
+                    .filter(|speed| *speed > 0.0)
+                    .ok_or("--speed must be a positive number")?,
+            );
+        } else if arg == "--batch-size" && command == "migrate" {
+            batch_size = rest
+                .next()
+                .and_then(|size| size.parse::<usize>().ok())
+                .filter(|size| *size > 0)
+                .ok_or("--batch-size must be a positive number")?;
+        } else if arg == "--pause" && command == "migrate" {
+            pause_ms = rest
+                .next()
+                .and_then(|pause| pause.parse::<u64>().ok())
+                .ok_or("--pause must be a number of milliseconds")?;
+        } else if arg == "--jobs" {
+            jobs = rest
+                .next()
+                .and_then(|jobs| jobs.parse::<usize>().ok())
+                .filter(|jobs| *jobs > 0)
+                .ok_or("--jobs must be a positive number")?;
+        } else {
+            positional.push(arg.clone());
+        }
+    }
+
+    if command == "serve" {
+        let [db] = <[String; 1]>::try_from(positional).map_err(|_| USAGE)?;
+        return Ok(Command::Serve {
+            listen,
+            statement_log,
+            metrics_listen,
+            line,
+            db,
+        });
+    }
+
+    let [db, dir] = <[String; 2]>::try_from(positional).map_err(|_| USAGE)?;
+    match command.as_str() {
+        "replay" => Ok(Command::Replay {
+            jobs,
+            speed,
+            log: db,
+            db: dir,
+        }),
+        "migrate" => Ok(Command::Migrate {
+            batch_size,
+            pause_ms,
+            db,
+            name: dir,
+        }),
+        "dump" => Ok(Command::Dump { jobs, db, dir }),
+        "restore" => Ok(Command::Restore { jobs, db, dir }),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// What a command prints, and the exit code of the process once it's
+/// printed.
+#[derive(Debug, PartialEq)]
+pub struct Output {
+    pub text: String,
+    pub exit_code: i32,
+}
+
+impl From<String> for Output {
+    fn from(text: String) -> Self {
+        Self { text, exit_code: 0 }
+    }
+}
+
+/// Run the command, where an error exits the process with code 1.
+pub fn run(command: Command) -> Result<Output, String> {
+    match command {
+        // Probe the database, e.g. by an orchestration system, where the
+        // exit code is `Health::exit_code`.
+        Command::Health { db } => {
+            let table = Table::new(db, 8);
+            let health = Health::check(&table);
+            Ok(Output {
+                text: health.to_string(),
+                exit_code: health.exit_code(),
+            })
+        }
+        command => execute(command).map(Output::from),
+    }
+}
+
+fn execute(command: Command) -> Result<String, String> {
+    match command {
+        // Check the file and the tables in its catalog, see `Table::check`.
+        Command::Check { db } => {
+            let table = Table::new(db, 8);
+            table.check().map(|_| "ok".to_string())
+        }
+        // Print the byte layout of a page, without opening the table, see
+        // `format_dump`.
+        Command::FormatDump { db, page } => format_dump(db, page),
+        // Serve clients speaking the PostgreSQL protocol, or the line
+        // protocol of `Server` with `--line`, until the process is killed.
+        //
+        // With a statement log, the committed writes are appended to it,
+        // so a replica can be bootstrapped from it. With a metrics
+        // address, the latency of the operations is served to Prometheus
+        // from it, see `LatencyMetrics`.
+        Command::Serve {
+            listen,
+            statement_log,
+            metrics_listen,
+            line,
+            db,
+        } => {
+            let listener = TcpListener::bind(&listen).map_err(|err| err.to_string())?;
+            let metrics_listener = metrics_listen
+                .map(|listen| TcpListener::bind(listen).map_err(|err| err.to_string()))
+                .transpose()?;
+            let mut table = Table::new(db, 8);
+            if let Some(path) = statement_log {
+                let statement_log =
+                    StatementLog::open(&path).map_err(|err| format!("{path}: {err}"))?;
+                table = table.with_statement_log(Arc::new(statement_log));
+            }
+            let table = Arc::new(table);
+            if let Some(listener) = metrics_listener {
+                let table = table.clone();
+                std::thread::spawn(move || {
+                    if let Err(err) =
+                        metrics::serve_prometheus(listener, || table.prometheus_metrics())
+                    {
+                        eprintln!("metrics exporter stopped: {err}");
+                    }
+                });
+            }
+            if line {
+                Server::new(listener, table.clone())
+                    .serve()
+                    .map_err(|err| err.to_string())?;
+            } else {
+                pgwire::serve(listener, table.clone()).map_err(|err| err.to_string())?;
+            }
+            table.flush();
+            Ok(String::new())
+        }
+        // Replay a statement log, as fast as possible unless a speed
+        // relative to the capture is given.
+        Command::Replay {
+            jobs,
+            speed,
+            log,
+            db,
+        } => {
+            let log = std::fs::read_to_string(&log).map_err(|err| format!("{log}: {err}"))?;
+            let entries = replay::parse_log(&log)?;
+            let table = Arc::new(Table::new(db, 8 * jobs));
+            let report = replay::replay(table.clone(), entries, jobs, speed);
+            table.flush();
+            Ok(report.to_string())
+        }
+        // Reproduce the database a statement log was written for, on a
+        // fresh database.
+        Command::Bootstrap { log, db } => {
+            let transactions = StatementLog::read(&log)?;
+            let table = Arc::new(Table::new(db, 8));
+            let replayed = replay::bootstrap(&table, transactions)?;
+            table.flush();
+            Ok(format!("replayed {replayed} transactions"))
+        }
+        // Rewrite the rows of the table with a built-in migration, in
+        // batches, resuming after the last batch committed by a previous
+        // run, see `Migration`.
+        Command::Migrate {
+            batch_size,
+            pause_ms,
+            db,
+            name,
+        } => {
+            let transform = migration::builtin(&name).ok_or_else(|| {
+                format!(
+                    "unknown migration '{name}', expected one of: {}",
+                    migration::MIGRATIONS.join(", ")
+                )
+            })?;
+            let table = Table::new(db, 8);
+            let migration = Migration::new(&name, transform)
+                .with_batch_size(batch_size)
+                .with_pause(std::time::Duration::from_millis(pause_ms));
+            let rewritten = migration.run(&table, &table.transaction_manager())?;
+            table.flush();
+            Ok(format!("migrated {rewritten} rows"))
+        }
+        Command::Export { db, out } => {
+            let table = Table::new(db, 8);
+            let rows = export::sqlite::export(&table, out).map_err(|err| err.to_string())?;
+            Ok(format!("exported {rows} rows"))
+        }
+        Command::Health { .. } => unreachable!(),
+        Command::Dump { jobs, db, dir } => {
+            let table = Arc::new(Table::new(db, 8 * jobs));
+            let rows = dump(&table, dir, jobs).map_err(|err| err.to_string())?;
+            Ok(format!("dumped {rows} rows"))
+        }
+        // A single job restores an empty table with a bulk load, which is
+        // faster than inserting the rows and leaves fuller leaves.
+        Command::Restore { jobs, db, dir } => {
+            let table = Arc::new(Table::new(db, 8 * jobs));
+            let is_empty = table.is_empty().map_err(|err| err.to_string())?;
+            let results = if jobs == 1 && is_empty {
+                bulk_restore(&table, dir)?
+            } else {
+                table.begin_ingest();
+                let results = restore(&table, dir, jobs);
+                table.end_ingest();
+                results.map_err(|err| err.to_string())?
+            };
+            table.flush();
+
+            let mut output = String::new();
+            let mut rows = 0;
+            for (path, result) in results {
+                rows += result.rows;
+                for (line_num, reason) in result.rejected {
+                    output.push_str(&format!("{}:{line_num}: {reason}\n", path.display()));
+                }
+            }
+            output.push_str(&format!("restored {rows} rows"));
+            Ok(output)
+        }
+    }
+}
+
+// Each part is written and read by a single worker.
+fn part_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("part-{index:04}.csv"))
+}
+
+/// Dump every row of the table as CSV into the directory, with a file
+/// for each key range of the root node.
+///
+/// The key ranges are dumped in parallel by `jobs` workers from a
+/// snapshot of the table, so writes to the table are not blocked and
+/// don't show up in the dump while it's running. Return the total
+/// number of rows dumped.
+pub fn dump(table: &Arc<Table>, dir: impl AsRef<Path>, jobs: usize) -> std::io::Result<usize> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+
+    let pool = ThreadPool::new(jobs);
+    let (tx, rx) = mpsc::channel();
+    let snapshot = table.snapshot()?;
+    let ranges = table
+        .snapshot_key_ranges(&snapshot)
+        .map_err(std::io::Error::other)?;
+    let num_of_ranges = ranges.len();
+
+    for (i, (start, end)) in ranges.into_iter().enumerate() {
+        let table = table.clone();
+        let snapshot = snapshot.clone();
+        let path = part_path(dir, i);
+        let tx = tx.clone();
+
+        pool.execute(move || {
+            let result = File::create(path).and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                let mut rows = 0;
+                let mut result = Ok(());
+
+                table
+                    .for_each_in_snapshot(&snapshot, start, end, |row| {
+                        if result.is_ok() {
+                            result =
+                                writeln!(writer, "{},{},{}", row.id, row.username(), row.email());
+                            rows += 1;
+                        }
+                    })
+                    .map_err(std::io::Error::other)?;
+
+                result.and_then(|_| writer.flush()).map(|_| rows)
+            });
+            tx.send(result).unwrap();
+        });
+    }
+
+    rx.iter()
+        .take(num_of_ranges)
+        .try_fold(0, |total, rows| rows.map(|rows| total + rows))
+}
+
+/// Restore the table from a directory produced by `dump`, where the
+/// files are restored in parallel by `jobs` workers.
+///
+/// Return the result of each file, ordered by the file path.
+pub fn restore(
+    table: &Arc<Table>,
+    dir: impl AsRef<Path>,
+    jobs: usize,
+) -> std::io::Result<Vec<(PathBuf, CopyResult)>> {
+    let paths = part_paths(dir)?;
+    let pool = ThreadPool::new(jobs);
+    let (tx, rx) = mpsc::channel();
+    let num_of_paths = paths.len();
+
+    for path in paths {
+        let table = table.clone();
+        let tx = tx.clone();
+
+        pool.execute(move || {
+            let result = File::open(&path)
+                .map(|file| table.copy_from(BufReader::new(file)))
+                .map(|result| (path, result));
+            tx.send(result).unwrap();
+        });
+    }
+
+    let mut results = rx
+        .iter()
+        .take(num_of_paths)
+        .collect::<std::io::Result<Vec<_>>>()?;
+    results.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(results)
+}
+
+/// Restore the empty table from a directory produced by `dump` with a
+/// single bulk load, see `Table::bulk_load`, as the parts are dumped in
+/// key order. Unlike `restore`, a row out of order fails the restore.
+pub fn bulk_restore(
+    table: &Table,
+    dir: impl AsRef<Path>,
+) -> Result<Vec<(PathBuf, CopyResult)>, String> {
+    let paths = part_paths(dir).map_err(|err| err.to_string())?;
+    let mut parts = PartRows {
+        paths: paths.into_iter(),
+        lines: None,
+        line_num: 0,
+        results: Vec::new(),
+        error: None,
+    };
+    table.bulk_load(&mut parts).map_err(|err| err.to_string())?;
+    match parts.error {
+        Some(reason) => Err(reason),
+        None => Ok(parts.results),
+    }
+}
+
+// Parts of a dump, in the order they're dumped.
+fn part_paths(dir: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "csv") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+// Rows of the parts one after the other, where lines are rejected the
+// same as `Table::copy_from` does.
+struct PartRows {
+    paths: std::vec::IntoIter<PathBuf>,
+    lines: Option<Lines<BufReader<File>>>,
+    line_num: usize,
+    results: Vec<(PathBuf, CopyResult)>,
+    // Part that couldn't be opened, which stops the rows.
+    error: Option<String>,
+}
+
+impl Iterator for PartRows {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Row> {
+        loop {
+            let Some(lines) = &mut self.lines else {
+                let path = self.paths.next()?;
+                match File::open(&path) {
+                    Ok(file) => self.lines = Some(BufReader::new(file).lines()),
+                    Err(err) => {
+                        self.error = Some(format!("{}: {err}", path.display()));
+                        return None;
+                    }
+                }
+                self.results.push((path, CopyResult::default()));
+                self.line_num = 0;
+                continue;
+            };
+            let Some(line) = lines.next() else {
+                self.lines = None;
+                continue;
+            };
+
+            self.line_num += 1;
+            let (_, result) = self.results.last_mut().unwrap();
+            match line {
+                Ok(line) if line.trim().is_empty() => {}
+                Ok(line) => match Row::from_csv(&line) {
+                    Ok(row) => {
+                        result.rows += 1;
+                        return Some(row);
+                    }
+                    Err(reason) => result.rejected.push((self.line_num, reason)),
+                },
+                Err(err) => {
+                    result.rejected.push((self.line_num, err.to_string()));
+                    self.lines = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::prepare_statement;
+    use std::str::FromStr;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_dump_and_restore_args() {
+        assert_eq!(
+            parse_args(&args("dump --jobs 4 data.db out")),
+            Ok(Command::Dump {
+                jobs: 4,
+                db: "data.db".to_string(),
+                dir: "out".to_string()
+            })
+        );
+        assert_eq!(
+            parse_args(&args("restore data.db out")),
+            Ok(Command::Restore {
+                jobs: 1,
+                db: "data.db".to_string(),
+                dir: "out".to_string()
+            })
+        );
+        assert_eq!(
+            parse_args(&args("health data.db")),
+            Ok(Command::Health {
+                db: "data.db".to_string()
+            })
+        );
+        assert!(parse_args(&args("health data.db out")).is_err());
+        assert_eq!(
+            parse_args(&args("check data.db")),
+            Ok(Command::Check {
+                db: "data.db".to_string()
+            })
+        );
+        assert_eq!(
+            parse_args(&args("format-dump data.db 3")),
+            Ok(Command::FormatDump {
+                db: "data.db".to_string(),
+                page: 3
+            })
+        );
+        assert!(parse_args(&args("format-dump data.db root")).is_err());
+        assert_eq!(
+            parse_args(&args("serve --listen 0.0.0.0:5433 data.db")),
+            Ok(Command::Serve {
+                listen: "0.0.0.0:5433".to_string(),
+                statement_log: None,
+                metrics_listen: None,
+                line: false,
+                db: "data.db".to_string()
+            })
+        );
+        assert_eq!(
+            parse_args(&args("serve --statement-log data.statements data.db")),
+            Ok(Command::Serve {
+                listen: DEFAULT_LISTEN_ADDR.to_string(),
+                statement_log: Some("data.statements".to_string()),
+                metrics_listen: None,
+                line: false,
+                db: "data.db".to_string()
+            })
+        );
+        assert_eq!(
+            parse_args(&args("serve --metrics-listen 127.0.0.1:9187 data.db")),
+            Ok(Command::Serve {
+                listen: DEFAULT_LISTEN_ADDR.to_string(),
+                statement_log: None,
+                metrics_listen: Some("127.0.0.1:9187".to_string()),
+                line: false,
+                db: "data.db".to_string()
+            })
+        );
+        assert_eq!(
+            parse_args(&args("serve --line data.db")),
+            Ok(Command::Serve {
+                listen: DEFAULT_LISTEN_ADDR.to_string(),
+                statement_log: None,
+                metrics_listen: None,
+                line: true,
+                db: "data.db".to_string()
+            })
+        );
+        assert_eq!(
+            parse_args(&args("bootstrap data.statements replica.db")),
+            Ok(Command::Bootstrap {
+                log: "data.statements".to_string(),
+                db: "replica.db".to_string()
+            })
+        );
+        assert_eq!(
+            parse_args(&args("export data.db data.sqlite")),
+            Ok(Command::Export {
+                db: "data.db".to_string(),
+                out: "data.sqlite".to_string()
+            })
+        );
+        assert_eq!(
+            parse_args(&args("replay --jobs 2 --speed 1.5 statements.log data.db")),
+            Ok(Command::Replay {
+                jobs: 2,
+                speed: Some(1.5),
+                log: "statements.log".to_string(),
+                db: "data.db".to_string()
+            })
+        );
+        assert!(parse_args(&args("replay --speed 0 statements.log data.db")).is_err());
+        assert_eq!(
+            parse_args(&args(
+                "migrate --batch-size 50 --pause 10 data.db lowercase_email"
+            )),
+            Ok(Command::Migrate {
+                batch_size: 50,
+                pause_ms: 10,
+                db: "data.db".to_string(),
+                name: "lowercase_email".to_string()
+            })
+        );
+        assert!(parse_args(&args("migrate --batch-size 0 data.db lowercase_email")).is_err());
+        assert!(parse_args(&args("dump --speed 2 data.db out")).is_err());
+        assert!(parse_args(&args("dump --jobs 0 data.db out")).is_err());
+        assert!(parse_args(&args("dump data.db")).is_err());
+        assert!(parse_args(&args("load data.db out")).is_err());
+    }
+
+    #[test]
+    fn health_exit_code() {
+        let id = format!("{:?}", std::thread::current().id());
+        let db = format!("test-{id}.db");
+        let sidecars = [format!("{db}.catalog"), format!("{db}.hot")];
+        let files = [db.as_str(), &sidecars[0], &sidecars[1]];
+        cleanup(&files, "");
+
+        let output = run(Command::Health { db: db.clone() }).unwrap();
+        assert_eq!(output.text, "pool: ok\ndisk: ok\nhealthy");
+        assert_eq!(output.exit_code, 0);
+
+        let mut permissions = std::fs::metadata(&db).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&db, permissions).unwrap();
+        let output = run(Command::Health { db: db.clone() }).unwrap();
+        assert!(output.text.ends_with("unhealthy"));
+        assert_eq!(output.exit_code, 1);
+
+        cleanup(&files, "");
+    }
+
+    #[test]
+    fn dump_and_restore_in_parallel() {
+        let id = format!("{:?}", std::thread::current().id());
+        let source = format!("test-{id}.db");
+        let target = format!("test-{id}-restore.db");
+        let dir = format!("test-{id}-dump");
+        cleanup(&[&source, &target], &dir);
+
+        let table = Arc::new(Table::new(&source, 8));
+        for i in 1..1000 {
+            let row = crate::row::Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            table.insert(&row);
+        }
+        assert!(table.key_ranges().unwrap().len() > 1);
+
+        let rows = dump(&table, &dir, 4).unwrap();
+        assert_eq!(rows, 999);
+        assert_eq!(
+            std::fs::read_dir(&dir).unwrap().count(),
+            table.key_ranges().unwrap().len()
+        );
+
+        let restored = Arc::new(Table::new(&target, 8));
+        let results = restore(&restored, &dir, 4).unwrap();
+        let rows: usize = results.iter().map(|(_, result)| result.rows).sum();
+        assert_eq!(rows, 999);
+        assert!(results.iter().all(|(_, result)| result.rejected.is_empty()));
+
+        let statement = prepare_statement("select").unwrap();
+        assert_eq!(table.select(&statement), restored.select(&statement));
+
+        cleanup(&[&source, &target], &dir);
+    }
+
+    #[test]
+    fn bulk_restore_in_key_order() {
+        let id = format!("{:?}", std::thread::current().id());
+        let source = format!("test-{id}.db");
+        let target = format!("test-{id}-restore.db");
+        let dir = format!("test-{id}-dump");
+        cleanup(&[&source, &target], &dir);
+
+        let table = Arc::new(Table::new(&source, 8));
+        for i in 1..100 {
+            let row = crate::row::Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            table.insert(&row);
+        }
+        dump(&table, &dir, 4).unwrap();
+        std::fs::write(part_path(Path::new(&dir), 0), {
+            let part = std::fs::read_to_string(part_path(Path::new(&dir), 0)).unwrap();
+            format!("{part}not a row\n")
+        })
+        .unwrap();
+
+        let restored = Table::new(&target, 8);
+        assert!(restored.is_empty().unwrap());
+        let results = bulk_restore(&restored, &dir).unwrap();
+        let rows: usize = results.iter().map(|(_, result)| result.rows).sum();
+        assert_eq!(rows, 99);
+        assert_eq!(results[0].1.rejected.len(), 1);
+        assert!(!restored.is_empty().unwrap());
+
+        let statement = prepare_statement("select").unwrap();
+        assert_eq!(table.select(&statement), restored.select(&statement));
+        assert_eq!(restored.check(), Ok(()));
+
+        // Only an empty table can be bulk loaded.
+        assert!(bulk_restore(&restored, &dir).is_err());
+
+        cleanup(&[&source, &target], &dir);
+    }
+
+    fn cleanup(files: &[&str], dir: &str) {
+        for file in files {
+            let _ = std::fs::remove_file(file);
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
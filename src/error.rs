@@ -0,0 +1,140 @@
+use std::fmt;
+
+/// Error of the database, typed so callers can react to the kind of
+/// error, e.g. a server mapping it to an error code, while the REPL only
+/// prints it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbError {
+    // The catalog can't be changed as asked, e.g. a table that already
+    // exists, or can't be saved.
+    Catalog(String),
+    // The file fails the checks run when it's opened, one problem per
+    // line, see `Table::check`.
+    CheckFailed(String),
+    DuplicateKey,
+    // The input can't be run as it is, e.g. a statement that can't be
+    // parsed or rows of a bulk load out of order. Only the message is
+    // kept, the same as the REPL prints it.
+    InvalidInput(String),
+    // An I/O error of the disk manager or the WAL. Only the message is
+    // kept, so the error stays `Clone` and comparable.
+    Io(String),
+    // A lock wasn't granted within the timeout of the lock manager,
+    // see `LockManager::with_timeout`.
+    LockTimeout,
+    // Every frame of the buffer pool is pinned, so the page can't be
+    // loaded for now.
+    NoFreeFrame,
+    // The page is in the file, but its bytes can't be decoded, e.g. a
+    // torn write or a checksum mismatch.
+    PageCorrupted { page_id: usize, reason: String },
+    // The frame of the page is latched by another thread, the fetch
+    // is retried.
+    PageLatched(usize),
+    // The buffer pool doesn't have enough pages for the write,
+    // see `Pager::check_pool_size`.
+    PoolTooSmall(String),
+    // The table is a read-only replica, see `Table::read_only`.
+    ReadOnly,
+    TableClosed,
+    // The statement failed with the error, so the transaction it ran in
+    // is rolled back, see `query_v1::execute_in`.
+    TransactionAborted(Box<DbError>),
+    // The transaction is already committed or rolled back, see
+    // `database::Transaction`.
+    TransactionEnded,
+    // A unique index already has the value of the row written, see
+    // `SecondaryIndex`.
+    UniqueViolation(String),
+    // The transaction already has as many writes as its write set can
+    // hold, see `TransactionManager::with_max_write_set`.
+    TooLargeTransaction(usize),
+}
+
+impl DbError {
+    /// Whether the row written violates a constraint of the table, i.e.
+    /// its primary key or a unique index.
+    pub fn is_constraint_violation(&self) -> bool {
+        match self {
+            DbError::DuplicateKey | DbError::UniqueViolation(_) => true,
+            DbError::TransactionAborted(err) => err.is_constraint_violation(),
+            _ => false,
+        }
+    }
+
+    /// Whether the same operation might succeed if it's tried again a
+    /// bit later, e.g. once the latch of the page is released.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, DbError::NoFreeFrame | DbError::PageLatched(_))
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Catalog(reason) => write!(f, "{reason}"),
+            DbError::CheckFailed(problems) => write!(f, "{problems}"),
+            DbError::DuplicateKey => write!(f, "duplicate key"),
+            DbError::InvalidInput(reason) => write!(f, "{reason}"),
+            DbError::Io(reason) => write!(f, "I/O error: {reason}"),
+            DbError::LockTimeout => write!(f, "lock wait timeout"),
+            DbError::NoFreeFrame => write!(f, "every frame of the buffer pool is pinned"),
+            DbError::PageCorrupted { page_id, reason } => write!(
+                f,
+                "page {page_id} is corrupted: {reason}, run `sqlite check` on the file"
+            ),
+            DbError::PageLatched(page_id) => write!(f, "page {page_id} is latched, try again"),
+            DbError::PoolTooSmall(reason) => write!(f, "{reason}"),
+            DbError::ReadOnly => write!(f, "table is read-only"),
+            DbError::TableClosed => write!(f, "table is closed"),
+            DbError::TransactionAborted(err) => {
+                write!(f, "{err}, the transaction is rolled back")
+            }
+            DbError::TransactionEnded => write!(f, "the transaction has already ended"),
+            DbError::UniqueViolation(reason) => write!(f, "{reason}"),
+            DbError::TooLargeTransaction(max) => write!(
+                f,
+                "transaction too large: more than {max} writes, split it into smaller transactions"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::TransactionAborted(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for DbError {
+    fn from(err: std::io::Error) -> Self {
+        DbError::Io(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn classify_errors() {
+        let aborted = DbError::TransactionAborted(Box::new(DbError::DuplicateKey));
+        assert!(aborted.is_constraint_violation());
+        assert_eq!(
+            aborted.to_string(),
+            "duplicate key, the transaction is rolled back"
+        );
+        assert_eq!(aborted.source().unwrap().to_string(), "duplicate key");
+
+        assert!(DbError::PageLatched(3).is_transient());
+        assert!(!DbError::LockTimeout.is_transient());
+
+        let err = DbError::from(std::io::Error::other("disk full"));
+        assert_eq!(err, DbError::Io("disk full".to_string()));
+        assert_eq!(err.to_string(), "I/O error: disk full");
+    }
+}
@@ -0,0 +1,134 @@
+use crate::row::Row;
+
+// TODO (Arrow): The arrow crate can't be added as a dependency yet, even as
+// an optional one, since it can't be fetched in our build environment. So the
+// columns are laid out like Arrow arrays instead, where each buffer maps 1:1
+// to an Arrow buffer and can be wrapped into an `arrow::RecordBatch` without
+// copying once the dependency is behind an `arrow` feature.
+
+/// A variable length UTF-8 column, laid out like an Arrow `StringArray`:
+/// the value `i` is `values[offsets[i]..offsets[i + 1]]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utf8Array {
+    pub offsets: Vec<i32>,
+    pub values: Vec<u8>,
+}
+
+impl Utf8Array {
+    fn with_capacity(len: usize) -> Self {
+        let mut offsets = Vec::with_capacity(len + 1);
+        offsets.push(0);
+        Self {
+            offsets,
+            values: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, value: &str) {
+        self.values.extend_from_slice(value.as_bytes());
+        self.offsets.push(self.values.len() as i32);
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn value(&self, i: usize) -> &str {
+        let start = self.offsets[i] as usize;
+        let end = self.offsets[i + 1] as usize;
+        std::str::from_utf8(&self.values[start..end]).unwrap()
+    }
+}
+
+/// A chunk of rows, with a column for each field of `Row`. None of the
+/// columns are nullable, so there is no validity bitmap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordBatch {
+    pub id: Vec<u32>,
+    pub username: Utf8Array,
+    pub email: Utf8Array,
+}
+
+impl RecordBatch {
+    fn with_capacity(len: usize) -> Self {
+        Self {
+            id: Vec::with_capacity(len),
+            username: Utf8Array::with_capacity(len),
+            email: Utf8Array::with_capacity(len),
+        }
+    }
+
+    fn push(&mut self, row: &Row) {
+        self.id.push(row.id);
+        self.username.push(&row.username());
+        self.email.push(&row.email());
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.id.len()
+    }
+}
+
+/// Build batches of at most `chunk_size` rows, from rows pushed in key
+/// order.
+pub struct BatchBuilder {
+    chunk_size: usize,
+    batch: RecordBatch,
+    batches: Vec<RecordBatch>,
+}
+
+impl BatchBuilder {
+    pub fn new(chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        Self {
+            chunk_size,
+            batch: RecordBatch::with_capacity(chunk_size),
+            batches: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: &Row) {
+        self.batch.push(row);
+        if self.batch.num_rows() == self.chunk_size {
+            let batch = RecordBatch::with_capacity(self.chunk_size);
+            self.batches.push(std::mem::replace(&mut self.batch, batch));
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<RecordBatch> {
+        if self.batch.num_rows() > 0 {
+            self.batches.push(self.batch);
+        }
+        self.batches
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::table::Table;
+    use std::str::FromStr;
+
+    #[test]
+    fn table_to_arrow_batches() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let table = Table::new(&path, 8);
+        for i in 1..=25 {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            table.insert(&row);
+        }
+
+        let batches = table.to_arrow_batches(10);
+        let sizes: Vec<_> = batches.iter().map(|batch| batch.num_rows()).collect();
+        assert_eq!(sizes, vec![10, 10, 5]);
+
+        let last = &batches[2];
+        assert_eq!(last.id, vec![21, 22, 23, 24, 25]);
+        assert_eq!(last.username.len(), 5);
+        assert_eq!(last.username.value(0), "user21");
+        assert_eq!(last.email.value(4), "user25@email.com");
+        assert_eq!(&last.username.offsets[..3], &[0, 6, 12]);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
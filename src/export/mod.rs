@@ -0,0 +1,2 @@
+pub mod arrow;
+pub mod sqlite;
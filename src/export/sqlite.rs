@@ -0,0 +1,471 @@
+use crate::table::Table;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+// Export the table as a minimal SQLite database, with a single table
+// created by `SCHEMA` and no indexes, so it can be inspected with the
+// sqlite3 shell or any other SQLite tooling.
+//
+// See https://www.sqlite.org/fileformat.html
+const PAGE_SIZE: usize = 4096;
+pub const TABLE_NAME: &str = "users";
+const SCHEMA: &str = "CREATE TABLE users(id INTEGER PRIMARY KEY, username TEXT, email TEXT)";
+
+const LEAF_TABLE_PAGE: u8 = 0x0D;
+const INTERIOR_TABLE_PAGE: u8 = 0x05;
+
+// Page 1 holds the schema, and the root of our table is always page 2.
+// The leaf and interior pages are written after them.
+const SCHEMA_PAGE: u32 = 1;
+const ROOT_PAGE: u32 = 2;
+
+// A value of a record, where `id` is stored as NULL in the record since
+// an INTEGER PRIMARY KEY column is an alias of the rowid.
+enum Value<'a> {
+    Null,
+    Integer(i64),
+    Text(&'a str),
+}
+
+/// Write every row of the table into a new SQLite database at `path`,
+/// and return the number of rows exported.
+///
+/// Rows are streamed from the leaf chain into leaf pages that are written
+/// as soon as they are full. Only the page number and max rowid of each
+/// page are kept in memory to build the interior pages at the end.
+pub fn export(table: &Table, path: impl AsRef<Path>) -> std::io::Result<usize> {
+    let mut writer = Writer::create(path)?;
+    let mut result = Ok(());
+    let mut rows = 0;
+
+    table.for_each_row(|row| {
+        if result.is_ok() {
+            let username = row.username();
+            let email = row.email();
+            let record = record(&[Value::Null, Value::Text(&username), Value::Text(&email)]);
+            result = writer.push_row(row.id as i64, &record);
+            rows += 1;
+        }
+    });
+
+    result?;
+    writer.finish()?;
+    Ok(rows)
+}
+
+struct Writer {
+    file: File,
+    next_page: u32,
+    leaf: PageBuilder,
+    // The last full leaf, which is only written once we know it's not
+    // the only leaf, as a single leaf has to be the root page.
+    pending_leaf: Option<(Vec<u8>, i64)>,
+    // Page number and max rowid of the leaves written so far.
+    leaves: Vec<(u32, i64)>,
+}
+
+impl Writer {
+    fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut file = File::create(path)?;
+        // Reserve the schema and root page, which are written last.
+        file.write_all(&[0; PAGE_SIZE * 2])?;
+
+        Ok(Self {
+            file,
+            next_page: ROOT_PAGE + 1,
+            leaf: PageBuilder::new(LEAF_TABLE_PAGE, 0),
+            pending_leaf: None,
+            leaves: Vec::new(),
+        })
+    }
+
+    fn push_row(&mut self, rowid: i64, record: &[u8]) -> std::io::Result<()> {
+        let mut cell = Vec::new();
+        put_varint(&mut cell, record.len() as u64);
+        put_varint(&mut cell, rowid as u64);
+        cell.extend_from_slice(record);
+
+        if !self.leaf.fits(cell.len()) {
+            self.finish_leaf()?;
+        }
+        self.leaf.push(cell, rowid);
+        Ok(())
+    }
+
+    fn finish_leaf(&mut self) -> std::io::Result<()> {
+        let leaf = std::mem::replace(&mut self.leaf, PageBuilder::new(LEAF_TABLE_PAGE, 0));
+        let max_rowid = leaf.max_rowid;
+
+        if let Some((bytes, max_rowid)) = self.pending_leaf.take() {
+            let page = self.append_page(&bytes)?;
+            self.leaves.push((page, max_rowid));
+        }
+        self.pending_leaf = Some((leaf.finish(None), max_rowid));
+        Ok(())
+    }
+
+    fn append_page(&mut self, bytes: &[u8]) -> std::io::Result<u32> {
+        let page = self.next_page;
+        self.write_page(page, bytes)?;
+        self.next_page += 1;
+        Ok(page)
+    }
+
+    fn write_page(&mut self, page: u32, bytes: &[u8]) -> std::io::Result<()> {
+        self.file
+            .seek(SeekFrom::Start((page as u64 - 1) * PAGE_SIZE as u64))?;
+        self.file.write_all(bytes)
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        self.finish_leaf()?;
+        let (bytes, max_rowid) = self.pending_leaf.take().unwrap();
+
+        let root = if self.leaves.is_empty() {
+            bytes
+        } else {
+            let page = self.append_page(&bytes)?;
+            self.leaves.push((page, max_rowid));
+            self.build_interior_pages()?
+        };
+        self.write_page(ROOT_PAGE, &root)?;
+
+        let schema = self.schema_page();
+        self.write_page(SCHEMA_PAGE, &schema)?;
+        self.file.sync_all()
+    }
+
+    // Build the interior pages level by level until a single page is left,
+    // which is returned as the root page.
+    fn build_interior_pages(&mut self) -> std::io::Result<Vec<u8>> {
+        let mut children = std::mem::take(&mut self.leaves);
+
+        loop {
+            let mut pages = Vec::new();
+            let mut page = InteriorPageBuilder::default();
+            for (child, max_rowid) in children {
+                if !page.fits_another_child() {
+                    pages.push(std::mem::take(&mut page).finish());
+                }
+                page.push(child, max_rowid);
+            }
+            pages.push(page.finish());
+
+            if pages.len() == 1 {
+                return Ok(pages.pop().unwrap().0);
+            }
+
+            children = Vec::with_capacity(pages.len());
+            for (bytes, max_rowid) in pages {
+                children.push((self.append_page(&bytes)?, max_rowid));
+            }
+        }
+    }
+
+    fn schema_page(&self) -> Vec<u8> {
+        let record = record(&[
+            Value::Text("table"),
+            Value::Text(TABLE_NAME),
+            Value::Text(TABLE_NAME),
+            Value::Integer(ROOT_PAGE as i64),
+            Value::Text(SCHEMA),
+        ]);
+
+        let mut cell = Vec::new();
+        put_varint(&mut cell, record.len() as u64);
+        put_varint(&mut cell, 1);
+        cell.extend_from_slice(&record);
+
+        // The b-tree page header of page 1 comes after the database header.
+        let mut page = PageBuilder::new(LEAF_TABLE_PAGE, 100);
+        page.push(cell, 1);
+        let mut bytes = page.finish(None);
+        bytes[..100].copy_from_slice(&self.database_header());
+        bytes
+    }
+
+    fn database_header(&self) -> [u8; 100] {
+        let mut header = [0; 100];
+        header[..16].copy_from_slice(b"SQLite format 3\0");
+        header[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+        // File format write and read version, where 1 is legacy (no WAL).
+        header[18] = 1;
+        header[19] = 1;
+        // Max and min embedded payload fraction and leaf payload fraction,
+        // which must be 64, 32 and 32.
+        header[21] = 64;
+        header[22] = 32;
+        header[23] = 32;
+        // File change counter.
+        header[24..28].copy_from_slice(&1_u32.to_be_bytes());
+        header[28..32].copy_from_slice(&(self.next_page - 1).to_be_bytes());
+        // Schema cookie and schema format number.
+        header[40..44].copy_from_slice(&1_u32.to_be_bytes());
+        header[44..48].copy_from_slice(&4_u32.to_be_bytes());
+        // Text encoding, where 1 is UTF-8.
+        header[56..60].copy_from_slice(&1_u32.to_be_bytes());
+        // Version valid for, which must match the file change counter for
+        // the database size above to be trusted, and SQLite version number.
+        header[92..96].copy_from_slice(&1_u32.to_be_bytes());
+        header[96..100].copy_from_slice(&3_045_000_u32.to_be_bytes());
+        header
+    }
+}
+
+// Cells of a table b-tree page, where the cell content is laid out from
+// the end of the page and the cell pointers follow the page header.
+struct PageBuilder {
+    page_type: u8,
+    // Offset of the b-tree page header, which is 100 on page 1.
+    header_offset: usize,
+    cells: Vec<Vec<u8>>,
+    used: usize,
+    max_rowid: i64,
+}
+
+impl PageBuilder {
+    fn new(page_type: u8, header_offset: usize) -> Self {
+        Self {
+            page_type,
+            header_offset,
+            cells: Vec::new(),
+            used: 0,
+            max_rowid: 0,
+        }
+    }
+
+    fn header_size(&self) -> usize {
+        if self.page_type == INTERIOR_TABLE_PAGE {
+            12
+        } else {
+            8
+        }
+    }
+
+    fn fits(&self, cell_len: usize) -> bool {
+        let pointers = 2 * (self.cells.len() + 1);
+        self.header_offset + self.header_size() + pointers + self.used + cell_len <= PAGE_SIZE
+    }
+
+    fn push(&mut self, cell: Vec<u8>, rowid: i64) {
+        self.used += cell.len();
+        self.max_rowid = self.max_rowid.max(rowid);
+        self.cells.push(cell);
+    }
+
+    fn finish(self, right_most: Option<u32>) -> Vec<u8> {
+        let mut page = vec![0; PAGE_SIZE];
+        let header = self.header_offset;
+        let mut content_start = PAGE_SIZE;
+        let mut pointer = header + self.header_size();
+
+        for cell in &self.cells {
+            content_start -= cell.len();
+            page[content_start..content_start + cell.len()].copy_from_slice(cell);
+            page[pointer..pointer + 2].copy_from_slice(&(content_start as u16).to_be_bytes());
+            pointer += 2;
+        }
+
+        page[header] = self.page_type;
+        page[header + 3..header + 5].copy_from_slice(&(self.cells.len() as u16).to_be_bytes());
+        page[header + 5..header + 7].copy_from_slice(&(content_start as u16).to_be_bytes());
+        if let Some(right_most) = right_most {
+            page[header + 8..header + 12].copy_from_slice(&right_most.to_be_bytes());
+        }
+        page
+    }
+}
+
+// Children of an interior page, where every child but the last has a cell
+// with the max rowid of the child, and the last child is the right most
+// pointer of the page.
+#[derive(Default)]
+struct InteriorPageBuilder {
+    children: Vec<(u32, i64)>,
+    used: usize,
+}
+
+impl InteriorPageBuilder {
+    fn cell(child: u32, max_rowid: i64) -> Vec<u8> {
+        let mut cell = child.to_be_bytes().to_vec();
+        put_varint(&mut cell, max_rowid as u64);
+        cell
+    }
+
+    // Adding another child turns the current right most child into a
+    // cell, so it fits if every current child fits as a cell.
+    fn fits_another_child(&self) -> bool {
+        12 + self.used <= PAGE_SIZE
+    }
+
+    fn push(&mut self, child: u32, max_rowid: i64) {
+        self.used += InteriorPageBuilder::cell(child, max_rowid).len() + 2;
+        self.children.push((child, max_rowid));
+    }
+
+    fn finish(mut self) -> (Vec<u8>, i64) {
+        let (right_most, max_rowid) = self.children.pop().unwrap();
+
+        let mut page = PageBuilder::new(INTERIOR_TABLE_PAGE, 0);
+        for (child, max_rowid) in self.children {
+            page.push(InteriorPageBuilder::cell(child, max_rowid), max_rowid);
+        }
+        (page.finish(Some(right_most)), max_rowid)
+    }
+}
+
+// Encode the values as a record, which is a header of the serial type of
+// each value followed by the values.
+fn record(values: &[Value]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut body = Vec::new();
+
+    for value in values {
+        match value {
+            Value::Null => put_varint(&mut types, 0),
+            Value::Integer(value) => {
+                let (serial_type, len) = match value {
+                    -128..=127 => (1, 1),
+                    -32768..=32767 => (2, 2),
+                    -2147483648..=2147483647 => (4, 4),
+                    _ => (6, 8),
+                };
+                put_varint(&mut types, serial_type);
+                body.extend_from_slice(&value.to_be_bytes()[8 - len..]);
+            }
+            Value::Text(text) => {
+                put_varint(&mut types, text.len() as u64 * 2 + 13);
+                body.extend_from_slice(text.as_bytes());
+            }
+        }
+    }
+
+    // The header size includes the varint of the size itself.
+    let mut header_size = types.len() + 1;
+    if header_size >= 128 {
+        header_size += 1;
+    }
+
+    let mut record = Vec::with_capacity(header_size + body.len());
+    put_varint(&mut record, header_size as u64);
+    record.extend(types);
+    record.extend(body);
+    record
+}
+
+// Encode a SQLite varint, which is big endian with 7 bits per byte, except
+// the 9th byte that uses all 8 bits.
+fn put_varint(buf: &mut Vec<u8>, value: u64) {
+    if value > 0x00ff_ffff_ffff_ffff {
+        let mut bytes = [0; 9];
+        bytes[8] = value as u8;
+        let mut value = value >> 8;
+        for byte in bytes[..8].iter_mut().rev() {
+            *byte = (value & 0x7f) as u8 | 0x80;
+            value >>= 7;
+        }
+        buf.extend_from_slice(&bytes);
+        return;
+    }
+
+    let mut bytes = Vec::new();
+    let mut value = value;
+    loop {
+        bytes.push((value & 0x7f) as u8);
+        value >>= 7;
+        if value == 0 {
+            break;
+        }
+    }
+
+    let last = bytes.len() - 1;
+    for (i, byte) in bytes.iter().rev().enumerate() {
+        buf.push(if i == last { *byte } else { byte | 0x80 });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::row::Row;
+    use std::str::FromStr;
+
+    #[test]
+    fn encode_varint() {
+        let varint = |value| {
+            let mut buf = Vec::new();
+            put_varint(&mut buf, value);
+            buf
+        };
+
+        assert_eq!(varint(0), vec![0]);
+        assert_eq!(varint(127), vec![0x7f]);
+        assert_eq!(varint(128), vec![0x81, 0x00]);
+        assert_eq!(varint(300), vec![0x82, 0x2c]);
+        assert_eq!(varint(u64::MAX), vec![0xff; 9]);
+    }
+
+    #[test]
+    fn encode_record() {
+        let record = record(&[Value::Null, Value::Integer(2), Value::Text("ab")]);
+        assert_eq!(record, vec![4, 0, 1, 17, 2, b'a', b'b']);
+    }
+
+    #[test]
+    fn export_table_into_sqlite_file() {
+        let id = format!("{:?}", std::thread::current().id());
+        let db = format!("test-{id}.db");
+        let out = format!("test-{id}.sqlite");
+
+        let table = Table::new(&db, 8);
+        for i in 1..500 {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            table.insert(&row);
+        }
+
+        assert_eq!(export(&table, &out).unwrap(), 499);
+
+        let bytes = std::fs::read(&out).unwrap();
+        assert_eq!(&bytes[..16], b"SQLite format 3\0");
+        let num_of_pages = u32::from_be_bytes(bytes[28..32].try_into().unwrap());
+        assert_eq!(num_of_pages as usize * PAGE_SIZE, bytes.len());
+
+        // Schema is a leaf on page 1, and the rows don't fit a single
+        // leaf so the root on page 2 is an interior page.
+        assert_eq!(bytes[100], LEAF_TABLE_PAGE);
+        assert_eq!(bytes[PAGE_SIZE], INTERIOR_TABLE_PAGE);
+
+        // The first row is in the first leaf, which is page 3.
+        let leaf = &bytes[PAGE_SIZE * 2..PAGE_SIZE * 3];
+        assert_eq!(leaf[0], LEAF_TABLE_PAGE);
+        let offset = u16::from_be_bytes(leaf[8..10].try_into().unwrap()) as usize;
+        let cell = record(&[
+            Value::Null,
+            Value::Text("user1"),
+            Value::Text("user1@email.com"),
+        ]);
+        assert_eq!(&leaf[offset + 2..offset + 2 + cell.len()], &cell);
+
+        let _ = std::fs::remove_file(db);
+        let _ = std::fs::remove_file(out);
+    }
+
+    #[test]
+    fn export_empty_table() {
+        let id = format!("{:?}", std::thread::current().id());
+        let db = format!("test-{id}.db");
+        let out = format!("test-{id}.sqlite");
+
+        let table = Table::new(&db, 8);
+        assert_eq!(export(&table, &out).unwrap(), 0);
+
+        let bytes = std::fs::read(&out).unwrap();
+        assert_eq!(bytes.len(), PAGE_SIZE * 2);
+        assert_eq!(bytes[PAGE_SIZE], LEAF_TABLE_PAGE);
+        assert_eq!(&bytes[PAGE_SIZE + 3..PAGE_SIZE + 5], &[0, 0]);
+
+        let _ = std::fs::remove_file(db);
+        let _ = std::fs::remove_file(out);
+    }
+}
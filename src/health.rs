@@ -0,0 +1,81 @@
+use crate::table::Table;
+use std::fmt;
+
+/// Result of each health check of a database.
+///
+/// - `pool`: the buffer pool has at least one frame that isn't pinned.
+/// - `disk`: the database file is still writable.
+#[derive(Debug)]
+pub struct Health {
+    checks: Vec<(&'static str, Result<(), String>)>,
+}
+
+impl Health {
+    pub fn check(table: &Table) -> Self {
+        Self {
+            checks: vec![("pool", table.check_pool()), ("disk", table.check_disk())],
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.checks.iter().all(|(_, result)| result.is_ok())
+    }
+
+    /// Exit code of the process when probing the database, which is 0 if
+    /// every check passes, and 1 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        if self.is_healthy() {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, result) in &self.checks {
+            match result {
+                Ok(_) => writeln!(f, "{name}: ok")?,
+                Err(reason) => writeln!(f, "{name}: {reason}")?,
+            }
+        }
+
+        let status = if self.is_healthy() {
+            "healthy"
+        } else {
+            "unhealthy"
+        };
+        write!(f, "{status}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report_each_check() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let table = Table::new(&path, 8);
+
+        let health = Health::check(&table);
+        assert!(health.is_healthy());
+        assert_eq!(health.exit_code(), 0);
+        assert_eq!(health.to_string(), "pool: ok\ndisk: ok\nhealthy");
+
+        let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions).unwrap();
+
+        let health = Health::check(&table);
+        assert!(!health.is_healthy());
+        assert_eq!(health.exit_code(), 1);
+        assert_eq!(
+            health.to_string(),
+            format!("pool: ok\ndisk: database file not writable: {path} is read only\nunhealthy")
+        );
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::error::DbError;
+use crate::query::{Expression, Value};
+use crate::row::Row;
+use parking_lot::RwLock;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+/// Secondary index on a text column of a table, mapping each value of the
+/// column to the ids of the rows with that value, in the order of the
+/// values. See `Table::create_index`.
+///
+/// Rows are looked up through the primary index by their id, rather than
+/// by their RowID, as rows move to another page when leaves split or
+/// merge.
+///
+/// A unique index rejects a row whose value another row already has, with
+/// `DbError::UniqueViolation`. Rows are indexed as soon as they're written,
+/// so the value of a row inserted by a transaction that isn't committed
+/// yet is taken too, and the one of a row it deletes is only freed once
+/// it commits.
+///
+/// TRADEOFF: The index is kept in memory, and rebuilt from the rows of the
+/// table when it's opened, rather than saved as a B+ tree of its own, as
+/// leaves only hold rows for now. Only its column is saved in the catalog.
+#[derive(Debug)]
+pub struct SecondaryIndex {
+    column: String,
+    unique: bool,
+    entries: RwLock<BTreeMap<String, BTreeSet<u32>>>,
+}
+
+impl SecondaryIndex {
+    pub fn new(column: &str) -> Self {
+        Self {
+            column: column.to_string(),
+            unique: false,
+            entries: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn with_unique(mut self, unique: bool) -> Self {
+        self.unique = unique;
+        self
+    }
+
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Index the row, returning whether it wasn't indexed already.
+    pub fn insert(&self, row: &Row) -> Result<bool, DbError> {
+        let Some(value) = self.value(row) else {
+            return Ok(false);
+        };
+        let mut entries = self.entries.write();
+        self.check(&entries, &value, row.id)?;
+        Ok(entries.entry(value).or_default().insert(row.id))
+    }
+
+    pub fn remove(&self, row: &Row) {
+        if let Some(value) = self.value(row) {
+            remove_id(&mut self.entries.write(), &value, row.id);
+        }
+    }
+
+    /// Move the row to its new value, unless a unique index already has
+    /// it, in which case the index is left as is.
+    pub fn update(&self, old_row: &Row, new_row: &Row) -> Result<(), DbError> {
+        let (old_value, new_value) = (self.value(old_row), self.value(new_row));
+        if old_value == new_value {
+            return Ok(());
+        }
+
+        let mut entries = self.entries.write();
+        if let Some(value) = &new_value {
+            self.check(&entries, value, new_row.id)?;
+        }
+        if let Some(value) = old_value {
+            remove_id(&mut entries, &value, old_row.id);
+        }
+        if let Some(value) = new_value {
+            entries.entry(value).or_default().insert(new_row.id);
+        }
+        Ok(())
+    }
+
+    /// Ids of the rows with the value, in ascending order.
+    pub fn lookup(&self, value: &str) -> Vec<u32> {
+        self.entries
+            .read()
+            .get(value)
+            .map(|ids| ids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Ids of the rows with a value from `start` up to, but excluding,
+    /// `end`, or with any value after `start` if there is no end, in
+    /// ascending order.
+    pub fn lookup_range(&self, start: &str, end: Option<&str>) -> Vec<u32> {
+        let entries = self.entries.read();
+        let end = match end {
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+        let ids: BTreeSet<u32> = entries
+            .range::<str, _>((Bound::Included(start), end))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect();
+        ids.into_iter().collect()
+    }
+
+    // Fail if the index is unique and another row has the value.
+    fn check(
+        &self,
+        entries: &BTreeMap<String, BTreeSet<u32>>,
+        value: &str,
+        id: u32,
+    ) -> Result<(), DbError> {
+        let taken = entries
+            .get(value)
+            .is_some_and(|ids| ids.iter().any(|other| *other != id));
+        if self.unique && taken {
+            return Err(DbError::UniqueViolation(format!(
+                "duplicate value {} in unique index on '{}'",
+                Value::Text(value.to_string()),
+                self.column
+            )));
+        }
+        Ok(())
+    }
+
+    fn value(&self, row: &Row) -> Option<String> {
+        match Expression::column(&self.column).evaluate(row) {
+            Some(Value::Text(value)) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+fn remove_id(entries: &mut BTreeMap<String, BTreeSet<u32>>, value: &str, id: u32) {
+    if let Some(ids) = entries.get_mut(value) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            entries.remove(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn secondary_index_operations() {
+        let index = SecondaryIndex::new("username");
+        let row = |id: u32, username: &str| {
+            Row::new(&id.to_string(), username, &format!("{username}@email.com")).unwrap()
+        };
+        assert_eq!(index.insert(&row(3, "john")), Ok(true));
+        assert_eq!(index.insert(&row(1, "john")), Ok(true));
+        assert_eq!(index.insert(&row(2, "jane")), Ok(true));
+        assert_eq!(index.insert(&row(2, "jane")), Ok(false));
+        assert_eq!(index.lookup("john"), vec![1, 3]);
+        assert_eq!(index.lookup("jane"), vec![2]);
+        assert!(index.lookup("alice").is_empty());
+        assert_eq!(index.lookup_range("ja", Some("jb")), vec![2]);
+        assert_eq!(index.lookup_range("j", None), vec![1, 2, 3]);
+        assert!(index.lookup_range("john", Some("john")).is_empty());
+
+        index.update(&row(3, "john"), &row(3, "alice")).unwrap();
+        assert_eq!(index.lookup("john"), vec![1]);
+        assert_eq!(index.lookup("alice"), vec![3]);
+
+        index.remove(&row(2, "jane"));
+        assert!(index.lookup("jane").is_empty());
+        assert_eq!(index.entries.read().len(), 2);
+    }
+
+    #[test]
+    fn unique_index_rejects_duplicates() {
+        let index = SecondaryIndex::new("username").with_unique(true);
+        let row = |id: u32, username: &str| {
+            Row::new(&id.to_string(), username, &format!("{username}@email.com")).unwrap()
+        };
+        let duplicate = Err(DbError::UniqueViolation(
+            "duplicate value 'john' in unique index on 'username'".to_string(),
+        ));
+        assert_eq!(index.insert(&row(1, "john")), Ok(true));
+        assert_eq!(index.insert(&row(2, "john")), duplicate.clone());
+        assert_eq!(index.insert(&row(1, "john")), Ok(false));
+        assert_eq!(index.insert(&row(2, "jane")), Ok(true));
+
+        assert_eq!(
+            index.update(&row(2, "jane"), &row(2, "john")),
+            duplicate.map(|_| ())
+        );
+        assert_eq!(index.lookup("jane"), vec![2]);
+        index.remove(&row(1, "john"));
+        assert_eq!(index.update(&row(2, "jane"), &row(2, "john")), Ok(()));
+        assert_eq!(index.lookup("john"), vec![2]);
+    }
+}
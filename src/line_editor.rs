@@ -0,0 +1,779 @@
+use crate::query::SqlStatement;
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Statements kept in the history, unless `history_size` is set.
+pub const DEFAULT_HISTORY_SIZE: usize = 500;
+const HISTORY_FILE: &str = ".sqlite_history";
+
+/// Path of the history file of the REPL, in the directory of the database.
+pub fn history_path(db_path: impl AsRef<Path>) -> PathBuf {
+    db_path
+        .as_ref()
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(HISTORY_FILE)
+}
+
+/// A statement is complete once it ends with `;`, except meta commands,
+/// e.g. `.tables`, and positional statements, e.g. `insert 1 john
+/// john@email.com`, which are always a single line.
+pub fn is_complete(statement: &str) -> bool {
+    let statement = statement.trim();
+    statement.is_empty()
+        || statement.starts_with(['.', '\\'])
+        || statement.ends_with(';')
+        || !SqlStatement::is_sql(statement)
+}
+
+/// Statements entered in the REPL, oldest first.
+///
+/// Statements are appended to the file as they're added, so they're kept
+/// even if the REPL doesn't exit cleanly. The file is only rewritten to
+/// drop the oldest statements once there are more than `max_entries`.
+#[derive(Debug)]
+pub struct History {
+    path: Option<PathBuf>,
+    entries: VecDeque<String>,
+    max_entries: usize,
+}
+
+impl History {
+    /// History that isn't saved, e.g. for tests.
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            path: None,
+            entries: VecDeque::new(),
+            max_entries,
+        }
+    }
+
+    /// Load the history from the file, which is created on the first
+    /// statement added if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>, max_entries: usize) -> Self {
+        let mut history = Self {
+            path: Some(path.as_ref().to_path_buf()),
+            ..Self::new(max_entries)
+        };
+        if let Ok(content) = std::fs::read_to_string(path) {
+            history.entries = content.lines().map(String::from).collect();
+        }
+        history.set_max_entries(max_entries);
+        history
+    }
+
+    /// Keep the last `max_entries` statements, where 0 disables the
+    /// history.
+    pub fn set_max_entries(&mut self, max_entries: usize) {
+        self.max_entries = max_entries;
+        if self.entries.len() > max_entries {
+            let excess = self.entries.len() - max_entries;
+            self.entries.drain(..excess);
+            self.save();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// Add a statement, unless it's empty or the same as the last one.
+    /// Statements spanning several lines are saved as a single line.
+    pub fn add(&mut self, statement: &str) {
+        let statement = statement.split_whitespace().collect::<Vec<_>>().join(" ");
+        if self.max_entries == 0 || statement.is_empty() || self.entries.back() == Some(&statement)
+        {
+            return;
+        }
+
+        self.entries.push_back(statement);
+        if self.entries.len() > self.max_entries {
+            self.entries.pop_front();
+            self.save();
+        } else if let Some(path) = &self.path {
+            // TRADEOFF: Failing to save the history shouldn't stop the
+            // REPL, so it's only lost for the next run.
+            let _ = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", self.entries.back().unwrap()));
+        }
+    }
+
+    // Index of the latest statement before `before` containing the query.
+    fn search(&self, query: &str, before: usize) -> Option<usize> {
+        (0..before.min(self.len()))
+            .rev()
+            .find(|index| self.entries[*index].contains(query))
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            let content: String = self
+                .entries
+                .iter()
+                .map(|entry| format!("{entry}\n"))
+                .collect();
+            let _ = std::fs::write(path, content);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    // Ctrl-U
+    KillLine,
+    Tab,
+    // Ctrl-R
+    Search,
+    // Ctrl-G or Esc
+    Cancel,
+    // Ctrl-C
+    Interrupt,
+    // Ctrl-D
+    Eof,
+    Unknown,
+}
+
+// Read a key typed in raw mode, mapping the Emacs bindings of readline,
+// e.g. Ctrl-A, and the escape sequences of the arrow keys. Return `None`
+// at the end of the input.
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte(input)? else {
+        return Ok(None);
+    };
+
+    let key = match byte {
+        0x01 => Key::Home,
+        0x02 => Key::Left,
+        0x03 => Key::Interrupt,
+        0x04 => Key::Eof,
+        0x05 => Key::End,
+        0x06 => Key::Right,
+        0x07 => Key::Cancel,
+        0x08 | 0x7f => Key::Backspace,
+        b'\t' => Key::Tab,
+        b'\r' | b'\n' => Key::Enter,
+        0x0e => Key::Down,
+        0x10 => Key::Up,
+        0x12 => Key::Search,
+        0x15 => Key::KillLine,
+        0x1b => read_escape_sequence(input)?,
+        byte if byte < 0x20 => Key::Unknown,
+        byte => {
+            // Read the rest of a multi-byte character.
+            let len = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..len {
+                bytes.extend(read_byte(input)?);
+            }
+            std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|string| string.chars().next())
+                .map_or(Key::Unknown, Key::Char)
+        }
+    };
+
+    Ok(Some(key))
+}
+
+// TRADEOFF: A lone Esc waits for the next key, which it swallows, as
+// there's no timeout telling it apart from the start of a sequence.
+fn read_escape_sequence(input: &mut impl Read) -> io::Result<Key> {
+    let key = match read_byte(input)? {
+        Some(b'[') => match read_byte(input)? {
+            Some(b'A') => Key::Up,
+            Some(b'B') => Key::Down,
+            Some(b'C') => Key::Right,
+            Some(b'D') => Key::Left,
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            // e.g. `ESC [ 3 ~` for Delete.
+            Some(digit @ b'0'..=b'9') => match read_byte(input)? {
+                Some(b'~') => match digit {
+                    b'1' | b'7' => Key::Home,
+                    b'3' => Key::Delete,
+                    b'4' | b'8' => Key::End,
+                    _ => Key::Unknown,
+                },
+                _ => Key::Unknown,
+            },
+            _ => Key::Unknown,
+        },
+        Some(b'O') => match read_byte(input)? {
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            _ => Key::Unknown,
+        },
+        _ => Key::Cancel,
+    };
+
+    Ok(key)
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0; 1];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Continue,
+    // Complete the word before the cursor, see `LineState::complete`.
+    Complete,
+    Submit(String),
+    Interrupt,
+    Eof,
+}
+
+// Reverse incremental search through the history, started by Ctrl-R.
+#[derive(Debug, Default)]
+struct Search {
+    query: String,
+    // Index of the statement matching the query, if any.
+    found: Option<usize>,
+}
+
+// The line being edited, independent of the terminal.
+#[derive(Debug, Default)]
+struct LineState {
+    chars: Vec<char>,
+    cursor: usize,
+    // Statement of the history shown when browsing it with Up and Down,
+    // where `None` is the line being typed, which is kept in `draft`.
+    history_index: Option<usize>,
+    draft: Vec<char>,
+    search: Option<Search>,
+}
+
+impl LineState {
+    fn handle(&mut self, key: Key, history: &History) -> Outcome {
+        if self.search.is_some() {
+            return self.handle_search(key, history);
+        }
+
+        match key {
+            Key::Char(c) => {
+                self.chars.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Enter => return Outcome::Submit(self.chars.iter().collect()),
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.chars.len() => {
+                self.chars.remove(self.cursor);
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.chars.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.chars.len(),
+            Key::Up => self.browse(history, true),
+            Key::Down => self.browse(history, false),
+            Key::KillLine => {
+                self.chars.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Search => self.search = Some(Search::default()),
+            Key::Tab => return Outcome::Complete,
+            Key::Interrupt => return Outcome::Interrupt,
+            // Like readline, Ctrl-D only ends the input on an empty line,
+            // and deletes the character under the cursor otherwise.
+            Key::Eof if self.chars.is_empty() => return Outcome::Eof,
+            Key::Eof => return self.handle(Key::Delete, history),
+            _ => {}
+        }
+
+        Outcome::Continue
+    }
+
+    fn handle_search(&mut self, key: Key, history: &History) -> Outcome {
+        let search = self.search.as_mut().unwrap();
+        match key {
+            Key::Char(c) => {
+                search.query.push(c);
+                // The current match is kept while it still matches.
+                let before = search.found.map_or(history.len(), |found| found + 1);
+                search.found = history.search(&search.query, before);
+            }
+            Key::Backspace => {
+                search.query.pop();
+                search.found = history.search(&search.query, history.len());
+            }
+            Key::Search => {
+                let before = search.found.unwrap_or(history.len());
+                if let Some(found) = history.search(&search.query, before) {
+                    search.found = Some(found);
+                }
+            }
+            Key::Cancel | Key::Interrupt => self.search = None,
+            // Any other key takes the match as the line, then applies to it.
+            key => {
+                if let Some(found) = search.found {
+                    self.set_line(history.get(found).unwrap().chars().collect());
+                }
+                self.search = None;
+                return self.handle(key, history);
+            }
+        }
+
+        Outcome::Continue
+    }
+
+    fn browse(&mut self, history: &History, older: bool) {
+        let index = match (self.history_index, older) {
+            (None, true) if !history.is_empty() => Some(history.len() - 1),
+            (Some(index), true) if index > 0 => Some(index - 1),
+            (Some(index), false) if index + 1 < history.len() => Some(index + 1),
+            (Some(_), false) => None,
+            _ => return,
+        };
+
+        if self.history_index.is_none() {
+            self.draft = self.chars.clone();
+        }
+        self.history_index = index;
+        let line = match index {
+            Some(index) => history.get(index).unwrap().chars().collect(),
+            None => std::mem::take(&mut self.draft),
+        };
+        self.set_line(line);
+    }
+
+    fn set_line(&mut self, chars: Vec<char>) {
+        self.cursor = chars.len();
+        self.chars = chars;
+    }
+
+    // Complete the word before the cursor with the candidates starting
+    // with it, ignoring case. A single candidate replaces the word, and
+    // several extend it to their common prefix. Return the candidates to
+    // list if the word can't be extended any further.
+    fn complete(&mut self, completer: &dyn Completer) -> Vec<String> {
+        let start = self.chars[..self.cursor]
+            .iter()
+            .rposition(|c| is_word_boundary(*c))
+            .map_or(0, |i| i + 1);
+        let context: String = self.chars[..start].iter().collect();
+        let word: String = self.chars[start..self.cursor].iter().collect();
+
+        let mut candidates: Vec<String> = completer
+            .candidates(&context)
+            .into_iter()
+            .filter(|candidate| starts_with_ignore_case(candidate, &word))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+
+        let completion = match candidates.as_slice() {
+            [] => return Vec::new(),
+            [candidate] => format!("{candidate} "),
+            [first, rest @ ..] => {
+                let len = rest.iter().fold(first.chars().count(), |len, candidate| {
+                    first
+                        .chars()
+                        .zip(candidate.chars())
+                        .take(len)
+                        .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+                        .count()
+                });
+                if len <= word.chars().count() {
+                    return candidates;
+                }
+                first.chars().take(len).collect()
+            }
+        };
+
+        self.chars.splice(start..self.cursor, completion.chars());
+        self.cursor = start + completion.chars().count();
+        Vec::new()
+    }
+
+    // Redraw the line, then move the cursor back to where it is.
+    fn render(&self, prompt: &str, history: &History) -> String {
+        let (prompt, text, cursor) = match &self.search {
+            Some(search) => {
+                let found = search.found.and_then(|found| history.get(found));
+                let prompt = format!("(reverse-i-search)`{}': ", search.query);
+                let text: String = found.unwrap_or_default().to_string();
+                let cursor = text.chars().count();
+                (prompt, text, cursor)
+            }
+            None => (prompt.to_string(), self.chars.iter().collect(), self.cursor),
+        };
+
+        let mut output = format!("\r{prompt}{text}\x1b[K");
+        let back = text.chars().count() - cursor;
+        if back > 0 {
+            output.push_str(&format!("\x1b[{back}D"));
+        }
+        output
+    }
+}
+
+fn is_word_boundary(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ','
+}
+
+fn starts_with_ignore_case(string: &str, prefix: &str) -> bool {
+    string
+        .get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
+
+/// Words that can be typed at the cursor, e.g. the names of the tables
+/// after `from`, for the Tab key to complete the word being typed.
+pub trait Completer {
+    /// Candidates for the word after `context`, i.e. the line up to the
+    /// word being completed. They're filtered by the editor, so they
+    /// don't have to start with the word.
+    fn candidates(&self, context: &str) -> Vec<String>;
+}
+
+// Put the terminal in raw mode, so keys are read as they're typed instead
+// of once a line is entered, and restore it when dropped.
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        // SAFETY: termios is a plain C struct, which tcgetattr fills in.
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let original = termios;
+        // Output processing is kept, so `\n` still starts a new line.
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        termios.c_iflag &= !(libc::IXON | libc::ICRNL);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        // SAFETY: termios is initialized by tcgetattr above.
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: original is the termios read by tcgetattr.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original) };
+    }
+}
+
+enum Line {
+    Text(String),
+    Interrupt,
+    Eof,
+}
+
+/// Read statements typed into the REPL, with the history and the line
+/// editing keys of readline, e.g. Up and Down to browse the history,
+/// Ctrl-R to search it, and Ctrl-A or Ctrl-E to move to either end.
+///
+/// Input that isn't typed into a terminal, e.g. a script piped into the
+/// REPL, is read line by line as is.
+pub struct LineEditor {
+    history: History,
+    completer: Option<Box<dyn Completer>>,
+    terminal: bool,
+}
+
+impl LineEditor {
+    pub fn new(history: History) -> Self {
+        // SAFETY: isatty only checks the file descriptor.
+        let terminal = unsafe {
+            libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1
+        };
+        Self {
+            history,
+            completer: None,
+            terminal,
+        }
+    }
+
+    /// Complete the word before the cursor on Tab, listing the
+    /// candidates when there's more than one.
+    pub fn with_completer(mut self, completer: impl Completer + 'static) -> Self {
+        self.completer = Some(Box::new(completer));
+        self
+    }
+
+    pub fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    /// Read a statement, which spans several lines until it's complete,
+    /// see `is_complete`, and add it to the history. Return `None` at the
+    /// end of the input, e.g. on Ctrl-D.
+    ///
+    /// Ctrl-C drops the statement being typed, and starts over.
+    pub fn read_statement(
+        &mut self,
+        prompt: &str,
+        continuation: &str,
+    ) -> io::Result<Option<String>> {
+        let mut lines: Vec<String> = Vec::new();
+        loop {
+            let prompt = if lines.is_empty() {
+                prompt
+            } else {
+                continuation
+            };
+            match self.read_line(prompt)? {
+                Line::Text(line) => lines.push(line),
+                Line::Interrupt => {
+                    lines.clear();
+                    continue;
+                }
+                Line::Eof => return Ok(None),
+            }
+
+            let statement = lines.join(" ");
+            if is_complete(&statement) {
+                self.history.add(&statement);
+                return Ok(Some(statement));
+            }
+        }
+    }
+
+    fn read_line(&self, prompt: &str) -> io::Result<Line> {
+        let mut stdout = io::stdout();
+        if !self.terminal {
+            write!(stdout, "{prompt}")?;
+            stdout.flush()?;
+
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line)? == 0 {
+                return Ok(Line::Eof);
+            }
+            return Ok(Line::Text(line.trim_end_matches(['\r', '\n']).to_string()));
+        }
+
+        let _raw_mode = RawMode::enable()?;
+        let mut stdin = io::stdin().lock();
+        let mut state = LineState::default();
+        loop {
+            write!(stdout, "{}", state.render(prompt, &self.history))?;
+            stdout.flush()?;
+
+            let Some(key) = read_key(&mut stdin)? else {
+                return Ok(Line::Eof);
+            };
+            match state.handle(key, &self.history) {
+                Outcome::Continue => {}
+                Outcome::Complete => {
+                    let Some(completer) = &self.completer else {
+                        continue;
+                    };
+                    let candidates = state.complete(completer.as_ref());
+                    if !candidates.is_empty() {
+                        write!(stdout, "\r\n{}\r\n", candidates.join("  "))?;
+                    }
+                }
+                Outcome::Submit(line) => {
+                    write!(stdout, "\r\n")?;
+                    return Ok(Line::Text(line));
+                }
+                Outcome::Interrupt => {
+                    write!(stdout, "^C\r\n")?;
+                    return Ok(Line::Interrupt);
+                }
+                Outcome::Eof => {
+                    write!(stdout, "\r\n")?;
+                    return Ok(Line::Eof);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keep_history_across_runs() {
+        let path = format!("test-{:?}.history", std::thread::current().id());
+        let _ = std::fs::remove_file(&path);
+
+        let mut history = History::open(&path, 3);
+        history.add("select * from users\nwhere id = 1;");
+        history.add(".tables");
+        history.add(".tables");
+        history.add("  ");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(0), Some("select * from users where id = 1;"));
+
+        let mut history = History::open(&path, 3);
+        assert_eq!(history.len(), 2);
+        history.add(".scan");
+        history.add(".next");
+        assert_eq!(history.get(0), Some(".tables"));
+
+        history.set_max_entries(1);
+        let history = History::open(&path, 3);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history.get(0), Some(".next"));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn edit_line_and_search_history() {
+        let mut history = History::new(10);
+        for statement in ["insert 1 john john@email.com", "select 1", ".tables"] {
+            history.add(statement);
+        }
+
+        let keys = |state: &mut LineState, input: &[u8]| {
+            let mut input = input;
+            let mut outcome = Outcome::Continue;
+            while let Some(key) = read_key(&mut input).unwrap() {
+                outcome = state.handle(key, &history);
+            }
+            outcome
+        };
+
+        // Type, move left and insert, then delete the first character.
+        let mut state = LineState::default();
+        keys(&mut state, "selct 2".as_bytes());
+        keys(&mut state, b"\x1b[D\x1b[D\x1b[D\x1b[De\x01\x1b[3~");
+        assert_eq!(
+            keys(&mut state, b"\r"),
+            Outcome::Submit("elect 2".to_string())
+        );
+
+        // Browse back to the oldest statement, then forward to the draft.
+        let mut state = LineState::default();
+        keys(&mut state, b"draft\x1b[A\x1b[A\x1b[A\x1b[A");
+        assert_eq!(
+            state.chars.iter().collect::<String>(),
+            "insert 1 john john@email.com"
+        );
+        keys(&mut state, b"\x1b[B\x1b[B\x1b[B");
+        assert_eq!(state.chars.iter().collect::<String>(), "draft");
+
+        // Search for an older match, then take it and edit it.
+        let mut state = LineState::default();
+        keys(&mut state, b"\x121");
+        assert_eq!(
+            state.render("db > ", &history),
+            "\r(reverse-i-search)`1': select 1\x1b[K"
+        );
+        keys(&mut state, b"\x12");
+        assert_eq!(state.search.as_ref().unwrap().found, Some(0));
+        keys(
+            &mut state,
+            b"\x05\x7f\x7f\x7f\x7f\x7f\x7f\x7f\x7f\x7f\x7f\x7f\x7f\x7f\x7f\x7f",
+        );
+        assert_eq!(
+            keys(&mut state, b"\r"),
+            Outcome::Submit("insert 1 john".to_string())
+        );
+
+        let mut state = LineState::default();
+        assert_eq!(keys(&mut state, b"a\x03"), Outcome::Interrupt);
+        let mut state = LineState::default();
+        assert_eq!(keys(&mut state, b"\x04"), Outcome::Eof);
+    }
+
+    struct Words(Vec<&'static str>);
+
+    impl Completer for Words {
+        fn candidates(&self, context: &str) -> Vec<String> {
+            match context.trim() {
+                "select * from" => self.0.iter().map(|word| word.to_string()).collect(),
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    #[test]
+    fn complete_word_before_cursor() {
+        let completer = Words(vec!["users", "orders", "order_items"]);
+        let mut state = LineState::default();
+        let type_line = |state: &mut LineState, line: &str| {
+            state.set_line(line.chars().collect());
+        };
+
+        type_line(&mut state, "select * from u");
+        assert_eq!(state.handle(Key::Tab, &History::new(0)), Outcome::Complete);
+        assert!(state.complete(&completer).is_empty());
+        assert_eq!(
+            state.chars.iter().collect::<String>(),
+            "select * from users "
+        );
+
+        // Several candidates extend the word to their common prefix, then
+        // are listed.
+        type_line(&mut state, "select * from O");
+        assert!(state.complete(&completer).is_empty());
+        assert_eq!(
+            state.chars.iter().collect::<String>(),
+            "select * from order"
+        );
+        assert_eq!(state.complete(&completer), vec!["order_items", "orders"]);
+
+        // Only the word before the cursor is completed.
+        type_line(&mut state, "select * from u where id = 1");
+        state.cursor = "select * from u".len();
+        state.complete(&completer);
+        assert_eq!(
+            state.chars.iter().collect::<String>(),
+            "select * from users  where id = 1"
+        );
+        assert_eq!(state.cursor, "select * from users ".len());
+
+        type_line(&mut state, "select * from x");
+        assert!(state.complete(&completer).is_empty());
+        assert_eq!(state.chars.iter().collect::<String>(), "select * from x");
+    }
+
+    #[test]
+    fn sql_statements_span_lines_until_semicolon() {
+        assert!(!is_complete("select * from users"));
+        assert!(!is_complete("create table orders (id int primary key,"));
+        assert!(is_complete("select * from users\nwhere id = 1;"));
+        assert!(is_complete("select 1"));
+        assert!(is_complete("insert 1 john john@email.com"));
+        assert!(is_complete(".tables"));
+        assert!(is_complete("\\set max_rows 10"));
+        assert!(is_complete(""));
+    }
+}
@@ -0,0 +1,650 @@
+use crate::row::Row;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, MapAccess, Visitor};
+use serde::ser::{self, Impossible, Serialize, SerializeStruct};
+use std::fmt;
+
+// Map Rust structs to and from `Row` by field name, so embedders can use
+// their own types instead of building rows from strings, e.g.
+//
+//     #[derive(Serialize, Deserialize)]
+//     struct User { id: u32, username: String, email: String }
+//
+// Field names are only checked at runtime, against `COLUMNS`.
+const COLUMNS: [&str; 3] = ["id", "username", "email"];
+
+/// Convert a struct into a row, where every column must be a field of the
+/// struct, and every field of the struct must be a column.
+pub fn to_row<T: Serialize + ?Sized>(value: &T) -> Result<Row, String> {
+    value.serialize(RowSerializer).map_err(|err| err.0)
+}
+
+/// Convert a row into a struct, where every field of the struct must be
+/// a column. Columns that are not a field of the struct are skipped.
+pub fn from_row<T: DeserializeOwned>(row: &Row) -> Result<T, String> {
+    T::deserialize(RowDeserializer { row }).map_err(|err| err.0)
+}
+
+#[derive(Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+fn unsupported<T>(what: &str) -> Result<T, Error> {
+    Err(Error(format!("expected a struct, found {what}")))
+}
+
+struct RowSerializer;
+
+impl ser::Serializer for RowSerializer {
+    type Ok = Row;
+    type Error = Error;
+    type SerializeSeq = Impossible<Row, Error>;
+    type SerializeTuple = Impossible<Row, Error>;
+    type SerializeTupleStruct = Impossible<Row, Error>;
+    type SerializeTupleVariant = Impossible<Row, Error>;
+    type SerializeMap = Impossible<Row, Error>;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = Impossible<Row, Error>;
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<StructSerializer, Error> {
+        Ok(StructSerializer::default())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Row, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _: bool) -> Result<Row, Error> {
+        unsupported("a bool")
+    }
+
+    fn serialize_i8(self, _: i8) -> Result<Row, Error> {
+        unsupported("an integer")
+    }
+
+    fn serialize_i16(self, _: i16) -> Result<Row, Error> {
+        unsupported("an integer")
+    }
+
+    fn serialize_i32(self, _: i32) -> Result<Row, Error> {
+        unsupported("an integer")
+    }
+
+    fn serialize_i64(self, _: i64) -> Result<Row, Error> {
+        unsupported("an integer")
+    }
+
+    fn serialize_u8(self, _: u8) -> Result<Row, Error> {
+        unsupported("an integer")
+    }
+
+    fn serialize_u16(self, _: u16) -> Result<Row, Error> {
+        unsupported("an integer")
+    }
+
+    fn serialize_u32(self, _: u32) -> Result<Row, Error> {
+        unsupported("an integer")
+    }
+
+    fn serialize_u64(self, _: u64) -> Result<Row, Error> {
+        unsupported("an integer")
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<Row, Error> {
+        unsupported("a float")
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<Row, Error> {
+        unsupported("a float")
+    }
+
+    fn serialize_char(self, _: char) -> Result<Row, Error> {
+        unsupported("a char")
+    }
+
+    fn serialize_str(self, _: &str) -> Result<Row, Error> {
+        unsupported("a string")
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<Row, Error> {
+        unsupported("bytes")
+    }
+
+    fn serialize_none(self) -> Result<Row, Error> {
+        unsupported("none")
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Row, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Row, Error> {
+        unsupported("a unit")
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Row, Error> {
+        unsupported("a unit struct")
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+    ) -> Result<Row, Error> {
+        unsupported("an enum")
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Row, Error> {
+        unsupported("an enum")
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        unsupported("a sequence")
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Error> {
+        unsupported("a tuple")
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        unsupported("a tuple struct")
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        unsupported("an enum")
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        unsupported("a map")
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        unsupported("an enum")
+    }
+}
+
+// Value of a field, where `Null` is a `None` of an `Option` field.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Integer(i64),
+    Text(String),
+}
+
+#[derive(Default)]
+struct StructSerializer {
+    values: [Option<Value>; COLUMNS.len()],
+}
+
+impl SerializeStruct for StructSerializer {
+    type Ok = Row;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let index = COLUMNS
+            .iter()
+            .position(|column| *column == key)
+            .ok_or_else(|| Error(format!("unknown column '{key}'")))?;
+        let value = value
+            .serialize(ValueSerializer)
+            .map_err(|err| Error(format!("column '{key}': {err}")))?;
+        self.values[index] = Some(value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Row, Error> {
+        let mut columns = Vec::with_capacity(COLUMNS.len());
+        for (column, value) in COLUMNS.iter().zip(self.values) {
+            let value = match (*column, value) {
+                (_, None) => return Err(Error(format!("missing column '{column}'"))),
+                ("id", Some(Value::Integer(id))) => id.to_string(),
+                ("id", Some(_)) => return Err(Error("column 'id': expected an integer".into())),
+                (_, Some(Value::Null)) => String::new(),
+                (_, Some(Value::Text(text))) => text,
+                (_, Some(Value::Integer(_))) => {
+                    return Err(Error(format!("column '{column}': expected a string")))
+                }
+            };
+            columns.push(value);
+        }
+
+        Row::from_columns(&columns[0], &columns[1], &columns[2]).map_err(Error)
+    }
+}
+
+struct ValueSerializer;
+
+impl ValueSerializer {
+    fn unsupported(what: &str) -> Result<Value, Error> {
+        Err(Error(format!("unsupported type {what}")))
+    }
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = Impossible<Value, Error>;
+    type SerializeTuple = Impossible<Value, Error>;
+    type SerializeTupleStruct = Impossible<Value, Error>;
+    type SerializeTupleVariant = Impossible<Value, Error>;
+    type SerializeMap = Impossible<Value, Error>;
+    type SerializeStruct = Impossible<Value, Error>;
+    type SerializeStructVariant = Impossible<Value, Error>;
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(Value::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        // Anything above i64 is out of the range of an id anyway.
+        Ok(Value::Integer(v.try_into().unwrap_or(i64::MAX)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::Text(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::Text(v.to_string()))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _: bool) -> Result<Value, Error> {
+        Self::unsupported("bool")
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<Value, Error> {
+        Self::unsupported("float")
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<Value, Error> {
+        Self::unsupported("float")
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<Value, Error> {
+        Self::unsupported("bytes")
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Self::unsupported("unit")
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Value, Error> {
+        Self::unsupported("unit struct")
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+    ) -> Result<Value, Error> {
+        Self::unsupported("enum")
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Value, Error> {
+        Self::unsupported("enum")
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(Error("unsupported type sequence".into()))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(Error("unsupported type tuple".into()))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(Error("unsupported type tuple struct".into()))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Error("unsupported type enum".into()))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(Error("unsupported type map".into()))
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct, Error> {
+        Err(Error("unsupported type struct".into()))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(Error("unsupported type enum".into()))
+    }
+}
+
+// Deserialize a row as a map of column name to value, which works for
+// both structs and maps.
+struct RowDeserializer<'a> {
+    row: &'a Row,
+}
+
+impl<'de, 'a> de::Deserializer<'de> for RowDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(RowMapAccess {
+            row: self.row,
+            index: 0,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct RowMapAccess<'a> {
+    row: &'a Row,
+    index: usize,
+}
+
+impl<'de, 'a> MapAccess<'de> for RowMapAccess<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match COLUMNS.get(self.index) {
+            Some(column) => seed.deserialize(column.into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let column = COLUMNS[self.index];
+        self.index += 1;
+
+        let value = match column {
+            "id" => ColumnDeserializer::Integer(self.row.id),
+            "username" => ColumnDeserializer::Text(self.row.username()),
+            _ => ColumnDeserializer::Text(self.row.email()),
+        };
+        seed.deserialize(value)
+            .map_err(|err| Error(format!("column '{column}': {err}")))
+    }
+}
+
+enum ColumnDeserializer {
+    Integer(u32),
+    Text(String),
+}
+
+impl<'de> de::Deserializer<'de> for ColumnDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            ColumnDeserializer::Integer(value) => visitor.visit_u32(value),
+            ColumnDeserializer::Text(value) => visitor.visit_string(value),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::prepare_statement;
+    use crate::table::Table;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        id: u32,
+        username: String,
+        email: Option<String>,
+    }
+
+    #[test]
+    fn map_struct_to_and_from_row() {
+        let user = User {
+            id: 1,
+            username: "john".to_string(),
+            email: Some("john@email.com".to_string()),
+        };
+        let row = to_row(&user).unwrap();
+        assert_eq!(row, Row::new("1", "john", "john@email.com").unwrap());
+        assert_eq!(from_row::<User>(&row), Ok(user));
+
+        // Columns that are not a field are skipped.
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Name {
+            username: String,
+        }
+        assert_eq!(
+            from_row::<Name>(&row),
+            Ok(Name {
+                username: "john".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn validate_fields_against_columns() {
+        #[derive(Serialize, Deserialize)]
+        struct Extra {
+            id: u32,
+            username: String,
+            email: String,
+            age: u32,
+        }
+        let extra = Extra {
+            id: 1,
+            username: "john".to_string(),
+            email: "john@email.com".to_string(),
+            age: 20,
+        };
+        assert_eq!(to_row(&extra), Err("unknown column 'age'".to_string()));
+
+        #[derive(Serialize)]
+        struct Missing {
+            id: u32,
+        }
+        assert_eq!(
+            to_row(&Missing { id: 1 }),
+            Err("missing column 'username'".to_string())
+        );
+
+        #[derive(Serialize)]
+        struct Mistyped {
+            id: String,
+            username: String,
+            email: String,
+        }
+        let mistyped = Mistyped {
+            id: "1".to_string(),
+            username: "john".to_string(),
+            email: "john@email.com".to_string(),
+        };
+        assert_eq!(
+            to_row(&mistyped),
+            Err("column 'id': expected an integer".to_string())
+        );
+
+        let user = User {
+            id: 1,
+            username: "a".repeat(33),
+            email: None,
+        };
+        assert_eq!(to_row(&user), Err("Name is too long.".to_string()));
+        assert_eq!(
+            to_row(&1),
+            Err("expected a struct, found an integer".to_string())
+        );
+
+        let row = Row::new("1", "john", "john@email.com").unwrap();
+        assert_eq!(
+            from_row::<Extra>(&row).err(),
+            Some("missing field `age`".to_string())
+        );
+    }
+
+    #[test]
+    fn insert_and_query_structs() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let table = Table::new(&path, 8);
+
+        for id in 1..=3 {
+            let user = User {
+                id,
+                username: format!("user{id}"),
+                email: None,
+            };
+            table.insert_serde(&user).unwrap();
+        }
+        assert!(table
+            .insert_serde(&User {
+                id: 1,
+                username: "user1".to_string(),
+                email: None
+            })
+            .is_err());
+
+        let statement = prepare_statement("select").unwrap();
+        let users: Vec<User> = table.query_as(&statement).unwrap();
+        assert_eq!(users.len(), 3);
+        assert_eq!(
+            users[2],
+            User {
+                id: 3,
+                username: "user3".to_string(),
+                email: Some(String::new())
+            }
+        );
+
+        let _ = std::fs::remove_file(path);
+    }
+}
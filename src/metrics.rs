@@ -0,0 +1,509 @@
+use crate::storage::TreeStats;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::time::{Duration, Instant, SystemTime};
+
+// Samples kept to show how the tree changes over time, the oldest being
+// dropped first.
+const MAX_SAMPLES: usize = 64;
+
+// Leaves below these fills suggest a vacuum, as deletes left most of their
+// space unused. Leaves are split in halves, so a tree that's only
+// inserted into is around 50% full on average.
+const MIN_AVERAGE_FILL: usize = 40;
+const MIN_P10_FILL: usize = 15;
+
+// Trees with fewer leaves aren't alerted on, as a few emptied leaves
+// don't cost much to scan.
+const MIN_LEAVES: usize = 8;
+
+/// History of the shape of a tree, sampled by `Table::stats`, which warns
+/// when its leaves are getting too empty.
+///
+/// TODO (Vacuum): There's no vacuum to rewrite the tree yet, so the warning
+/// can only be acted upon by dumping and restoring the table.
+#[derive(Debug, Default)]
+pub struct TreeMetrics {
+    samples: VecDeque<(SystemTime, TreeStats)>,
+}
+
+impl TreeMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sample, returning the alerts it raises, if any.
+    pub fn record(&mut self, stats: TreeStats) -> Vec<String> {
+        let alerts = alerts(&stats);
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((SystemTime::now(), stats));
+        alerts
+    }
+
+    pub fn latest(&self) -> Option<&TreeStats> {
+        self.samples.back().map(|(_, stats)| stats)
+    }
+
+    pub fn vacuum_recommended(&self) -> bool {
+        self.latest().is_some_and(|stats| !alerts(stats).is_empty())
+    }
+}
+
+fn alerts(stats: &TreeStats) -> Vec<String> {
+    let mut alerts = Vec::new();
+    if stats.leaves() < MIN_LEAVES {
+        return alerts;
+    }
+
+    let average = stats.average_fill();
+    if average < MIN_AVERAGE_FILL {
+        alerts.push(format!(
+            "average leaf fill {average}% is below {MIN_AVERAGE_FILL}%"
+        ));
+    }
+    let p10 = stats.fill_percentile(0.1);
+    if p10 < MIN_P10_FILL {
+        alerts.push(format!("p10 leaf fill {p10}% is below {MIN_P10_FILL}%"));
+    }
+    alerts
+}
+
+impl fmt::Display for TreeMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((time, stats)) = self.samples.back() else {
+            return write!(f, "no samples");
+        };
+
+        let fanout = stats.average_fanout_tenths();
+        writeln!(f, "height: {}", stats.height)?;
+        writeln!(f, "leaves: {}", stats.leaves())?;
+        writeln!(
+            f,
+            "leaf fill: avg {}%, p10 {}%, p50 {}%, p90 {}%",
+            stats.average_fill(),
+            stats.fill_percentile(0.1),
+            stats.fill_percentile(0.5),
+            stats.fill_percentile(0.9)
+        )?;
+        writeln!(
+            f,
+            "internal nodes: {}, avg fanout: {}.{}",
+            stats.internal_nodes,
+            fanout / 10,
+            fanout % 10
+        )?;
+
+        let (first_time, first) = self.samples.front().unwrap();
+        if self.samples.len() > 1 {
+            let elapsed = time.duration_since(*first_time).unwrap_or_default();
+            writeln!(
+                f,
+                "since {}s ago: height {:+}, leaves {:+}, avg leaf fill {:+}%",
+                elapsed.as_secs(),
+                stats.height as i64 - first.height as i64,
+                stats.leaves() as i64 - first.leaves() as i64,
+                stats.average_fill() as i64 - first.average_fill() as i64
+            )?;
+        }
+
+        write!(f, "vacuum recommended: {}", self.vacuum_recommended())
+    }
+}
+
+// Latencies are bucketed by their highest bits, the first of which is
+// always set, so each power of two is split into 8 buckets, and the
+// bucket of a latency is at most 12.5% off.
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+
+// Percentiles shown by `.metrics detail` and exported to Prometheus.
+const PERCENTILES: [(f64, &str); 4] = [(0.5, "p50"), (0.9, "p90"), (0.99, "p99"), (0.999, "p99.9")];
+
+/// Operation on a table whose latency is recorded, see `LatencyMetrics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Insert,
+    PointSelect,
+    // Any select that isn't by id, including the ones only scanning the
+    // range of ids their predicate is restricted to.
+    FullScan,
+    Delete,
+    Commit,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 5] = [
+        Operation::Insert,
+        Operation::PointSelect,
+        Operation::FullScan,
+        Operation::Delete,
+        Operation::Commit,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Insert => "insert",
+            Operation::PointSelect => "point_select",
+            Operation::FullScan => "full_scan",
+            Operation::Delete => "delete",
+            Operation::Commit => "commit",
+        }
+    }
+}
+
+/// Histogram of latencies in microseconds, in the style of an HDR
+/// histogram: buckets grow with the latency, so the tail is as precise,
+/// relatively, as the median, in a few hundred counters.
+#[derive(Debug, Default, Clone)]
+pub struct LatencyHistogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: u64,
+    max: u64,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let micros = latency.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = bucket_of(micros);
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(micros);
+        self.max = self.max.max(micros);
+    }
+
+    /// Latency in microseconds the given fraction of the recorded ones
+    /// are at or below, up to the precision of their bucket.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        let rank = ((percentile * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return (bucket_end(bucket) - 1).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    pub fn mean(&self) -> u64 {
+        self.sum.checked_div(self.count).unwrap_or_default()
+    }
+}
+
+fn bucket_of(micros: u64) -> usize {
+    if micros < SUB_BUCKETS {
+        return micros as usize;
+    }
+    let shift = 63 - micros.leading_zeros() - SUB_BUCKET_BITS;
+    ((shift as u64 + 1) * SUB_BUCKETS + (micros >> shift) - SUB_BUCKETS) as usize
+}
+
+fn bucket_start(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < SUB_BUCKETS {
+        return bucket;
+    }
+    let shift = bucket / SUB_BUCKETS - 1;
+    (SUB_BUCKETS + bucket % SUB_BUCKETS) << shift
+}
+
+fn bucket_end(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < SUB_BUCKETS {
+        return bucket + 1;
+    }
+    let shift = bucket / SUB_BUCKETS - 1;
+    (SUB_BUCKETS + bucket % SUB_BUCKETS + 1) << shift
+}
+
+/// Latency histograms of each `Operation` on the tables of a file,
+/// recorded by `Table` for both auto-commit and transactional operations,
+/// and by `TransactionManager::commit`.
+///
+/// TRADEOFF: Histograms are never reset, so they show the latencies since
+/// the file was opened rather than over a recent window, the same as
+/// Prometheus expects from a summary.
+#[derive(Debug, Default)]
+pub struct LatencyMetrics {
+    histograms: [Mutex<LatencyHistogram>; Operation::ALL.len()],
+}
+
+impl LatencyMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, operation: Operation, started: Instant) {
+        self.histograms[operation as usize]
+            .lock()
+            .record(started.elapsed());
+    }
+
+    pub fn histogram(&self, operation: Operation) -> LatencyHistogram {
+        self.histograms[operation as usize].lock().clone()
+    }
+
+    /// Count, median and tail latency of each operation, or with `detail`
+    /// more percentiles and the buckets latencies are recorded in.
+    pub fn report(&self, detail: bool) -> String {
+        let mut lines = Vec::new();
+        for operation in Operation::ALL {
+            let histogram = self.histogram(operation);
+            if histogram.count == 0 {
+                lines.push(format!("{}: no samples", operation.name()));
+                continue;
+            }
+            if !detail {
+                lines.push(format!(
+                    "{}: {} ops, p50 {}, p99 {}, max {}",
+                    operation.name(),
+                    histogram.count,
+                    micros(histogram.percentile(0.5)),
+                    micros(histogram.percentile(0.99)),
+                    micros(histogram.max)
+                ));
+                continue;
+            }
+
+            let percentiles: Vec<String> = PERCENTILES
+                .iter()
+                .map(|(p, label)| format!("{label} {}", micros(histogram.percentile(*p))))
+                .collect();
+            lines.push(format!(
+                "{}: {} ops, mean {}, {}, max {}",
+                operation.name(),
+                histogram.count,
+                micros(histogram.mean()),
+                percentiles.join(", "),
+                micros(histogram.max)
+            ));
+            for (bucket, count) in histogram.buckets.iter().enumerate() {
+                if *count > 0 {
+                    lines.push(format!(
+                        "  [{}, {}): {count}",
+                        micros(bucket_start(bucket)),
+                        micros(bucket_end(bucket))
+                    ));
+                }
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// The histograms in the Prometheus text format, as a summary of the
+    /// latency in seconds labelled by operation.
+    pub fn prometheus(&self) -> String {
+        let name = "minidb_operation_latency_seconds";
+        let mut output =
+            format!("# HELP {name} Latency of table operations.\n# TYPE {name} summary\n");
+        for operation in Operation::ALL {
+            let histogram = self.histogram(operation);
+            let label = operation.name();
+            for (p, _) in PERCENTILES {
+                output.push_str(&format!(
+                    "{name}{{operation=\"{label}\",quantile=\"{p}\"}} {}\n",
+                    seconds(histogram.percentile(p))
+                ));
+            }
+            output.push_str(&format!(
+                "{name}_sum{{operation=\"{label}\"}} {}\n",
+                seconds(histogram.sum)
+            ));
+            output.push_str(&format!(
+                "{name}_count{{operation=\"{label}\"}} {}\n",
+                histogram.count
+            ));
+        }
+        output
+    }
+}
+
+/// A metric with a single value in the Prometheus text format, e.g. a
+/// gauge of the buffer pool.
+pub fn prometheus_metric(name: &str, help: &str, kind: &str, value: impl fmt::Display) -> String {
+    format!("# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n")
+}
+
+/// Serve the metrics `render` returns to Prometheus over HTTP, one
+/// scrape at a time, until the listener fails.
+pub fn serve_prometheus(listener: TcpListener, render: impl Fn() -> String) -> io::Result<()> {
+    for stream in listener.incoming() {
+        if let Err(err) = scrape(stream?, &render()) {
+            eprintln!("scrape failed: {err}");
+        }
+    }
+    Ok(())
+}
+
+// Answer an HTTP request with the metrics, whatever its path, as they're
+// the only thing served.
+fn scrape(mut stream: impl Read + Write, body: &str) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|end| end == b"\r\n\r\n") {
+        let read = stream.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+fn micros(micros: u64) -> String {
+    match micros {
+        0..=999 => format!("{micros}us"),
+        1_000..=999_999 => format!("{:.1}ms", micros as f64 / 1_000.0),
+        _ => format!("{:.2}s", micros as f64 / 1_000_000.0),
+    }
+}
+
+fn seconds(micros: u64) -> f64 {
+    micros as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stats(leaf_fill: Vec<usize>) -> TreeStats {
+        TreeStats {
+            height: 2,
+            internal_nodes: 1,
+            children: leaf_fill.len(),
+            leaf_fill,
+        }
+    }
+
+    #[test]
+    fn tree_metrics_alerts() {
+        let mut metrics = TreeMetrics::new();
+        assert_eq!(metrics.to_string(), "no samples");
+
+        // Small trees aren't alerted on.
+        assert!(metrics.record(stats(vec![1, 2, 3])).is_empty());
+        assert!(!metrics.vacuum_recommended());
+
+        let healthy = stats(vec![50; 10]);
+        assert_eq!(healthy.fill_percentile(0.1), 50);
+        assert_eq!(healthy.average_fanout_tenths(), 100);
+        assert!(metrics.record(healthy).is_empty());
+
+        let mut fill = vec![5; 5];
+        fill.extend([50; 5]);
+        let alerts = metrics.record(stats(fill));
+        assert_eq!(
+            alerts,
+            vec![
+                "average leaf fill 27% is below 40%",
+                "p10 leaf fill 5% is below 15%"
+            ]
+        );
+        assert!(metrics.vacuum_recommended());
+        assert_eq!(metrics.samples.len(), 3);
+
+        let output = metrics.to_string();
+        assert!(output.contains("leaf fill: avg 27%, p10 5%, p50 5%, p90 50%"));
+        assert!(output.contains("height +0, leaves +7, avg leaf fill +25%"));
+        assert!(output.ends_with("vacuum recommended: true"));
+
+        for _ in 0..MAX_SAMPLES {
+            metrics.record(stats(vec![50; 10]));
+        }
+        assert_eq!(metrics.samples.len(), MAX_SAMPLES);
+        assert!(!metrics.vacuum_recommended());
+    }
+
+    #[test]
+    fn latency_histogram_percentiles() {
+        for micros in [0, 7, 8, 15, 16, 17, 100, 1_000, 123_456] {
+            let bucket = bucket_of(micros);
+            assert!(bucket_start(bucket) <= micros && micros < bucket_end(bucket));
+            assert_eq!(bucket_of(bucket_start(bucket)), bucket);
+        }
+
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.percentile(0.99), 0);
+        for micros in 1..=1_000 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        assert_eq!(histogram.count, 1_000);
+        assert_eq!(histogram.mean(), 500);
+        // Within the precision of a bucket.
+        let p50 = histogram.percentile(0.5);
+        assert!((500..=500 + 500 / 8).contains(&p50), "{p50}");
+        let p99 = histogram.percentile(0.99);
+        assert!((990..=1_000).contains(&p99), "{p99}");
+        assert_eq!(histogram.percentile(1.0), 1_000);
+    }
+
+    // A stream that reads what the client sent, and records what the
+    // server writes back.
+    struct Stream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Write for &mut Stream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for &mut Stream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    #[test]
+    fn scrape_metrics() {
+        let mut stream = Stream {
+            input: io::Cursor::new(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n".to_vec()),
+            output: Vec::new(),
+        };
+        scrape(&mut stream, "metric 1\n").unwrap();
+        assert_eq!(
+            String::from_utf8(stream.output).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: 9\r\nConnection: close\r\n\r\nmetric 1\n"
+        );
+    }
+
+    #[test]
+    fn latency_metrics_report() {
+        let metrics = LatencyMetrics::new();
+        metrics.record(Operation::Insert, Instant::now());
+        let report = metrics.report(false);
+        assert!(report.starts_with("insert: 1 ops, p50 "), "{report}");
+        assert!(report.contains("commit: no samples"));
+        assert!(metrics.report(true).contains("p99.9 "));
+
+        let prometheus = metrics.prometheus();
+        assert!(prometheus.contains("# TYPE minidb_operation_latency_seconds summary"));
+        assert!(
+            prometheus.contains("minidb_operation_latency_seconds_count{operation=\"insert\"} 1\n")
+        );
+        assert!(
+            prometheus.contains("minidb_operation_latency_seconds_count{operation=\"commit\"} 0\n")
+        );
+    }
+}
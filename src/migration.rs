@@ -0,0 +1,226 @@
+use crate::concurrency::{IsolationLevel, TransactionManager};
+use crate::row::{Row, MAX_ID};
+use crate::table::Table;
+use std::time::Duration;
+
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// Migrations `sqlite migrate` runs by name, see `Migration`.
+pub const MIGRATIONS: [&str; 1] = ["lowercase_email"];
+
+/// Rewrites every row of a table with `transform`, e.g. to backfill a
+/// computed value or convert a column to another format, while the table
+/// keeps serving other transactions.
+///
+/// Rows are rewritten in batches of `batch_size` rows, each in its own
+/// transaction, so a batch only holds the locks of its own rows. The id
+/// to resume from is saved in the metadata of the file by the transaction
+/// of each batch, so a migration interrupted by a crash or an error
+/// resumes after the last committed batch when run again, and a finished
+/// one doesn't rewrite anything.
+///
+/// TODO (Schema changes): Rows have a fixed set of columns, so a migration
+/// can only rewrite the values of the existing columns, not add one.
+pub struct Migration<F> {
+    name: String,
+    transform: F,
+    batch_size: usize,
+    pause: Duration,
+}
+
+impl<F> Migration<F>
+where
+    F: Fn(&Row) -> Option<Row>,
+{
+    /// `transform` returns the new row, or `None` to leave the row as is.
+    pub fn new(name: &str, transform: F) -> Self {
+        Self {
+            name: name.to_string(),
+            transform,
+            batch_size: DEFAULT_BATCH_SIZE,
+            pause: Duration::ZERO,
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Time to sleep between batches, leaving room for other transactions
+    /// on a busy table.
+    pub fn with_pause(mut self, pause: Duration) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Run the remaining batches, returning the number of rows rewritten.
+    pub fn run(&self, table: &Table, tm: &TransactionManager) -> Result<usize, String> {
+        let mut rewritten = 0;
+        while let Some(count) = self.run_batch(table, tm)? {
+            rewritten += count;
+            if !self.pause.is_zero() {
+                std::thread::sleep(self.pause);
+            }
+        }
+        Ok(rewritten)
+    }
+
+    /// Run the next batch, returning the number of rows rewritten, or
+    /// `None` once every row has been migrated.
+    ///
+    /// The batch is aborted, and the error returned, if one of its rows
+    /// can't be locked.
+    pub fn run_batch(
+        &self,
+        table: &Table,
+        tm: &TransactionManager,
+    ) -> Result<Option<usize>, String> {
+        let Some(start) = self.progress(table)? else {
+            return Ok(None);
+        };
+
+        let rows: Vec<Row> = table
+            .iter_range(start, None)
+            .map(|(_, row)| row)
+            .filter(|row| !row.is_deleted)
+            .take(self.batch_size)
+            .collect();
+        let Some(last) = rows.last().map(|row| row.id) else {
+            table.meta_put(&self.progress_key(), &next_id(MAX_ID))?;
+            return Ok(None);
+        };
+
+        let transaction = tm.begin(IsolationLevel::RepeatableRead);
+        let mut t = transaction.write();
+        let mut rewritten = 0;
+        for row in &rows {
+            let Some(new_row) = (self.transform)(row).filter(|new_row| new_row != row) else {
+                continue;
+            };
+
+            let mut columns = Vec::new();
+            if new_row.username() != row.username() {
+                columns.push("username".to_string());
+            }
+            if new_row.email() != row.email() {
+                columns.push("email".to_string());
+            }
+            let updated = match table.get_row_id(row.id, &mut t) {
+                Some(rid) => table
+                    .update(row, &new_row, &columns, &rid, &mut t)
+                    .map_err(|err| err.to_string()),
+                None => Ok(false),
+            };
+            match updated {
+                Ok(true) => rewritten += 1,
+                Ok(false) => {
+                    tm.abort(table, &mut t);
+                    return Err(format!("fail to migrate row {}", row.id));
+                }
+                Err(err) => {
+                    tm.abort(table, &mut t);
+                    return Err(format!("fail to migrate row {}: {err}", row.id));
+                }
+            }
+        }
+
+        table.meta_put_in(&self.progress_key(), &next_id(last), &mut t);
+        tm.commit(table, &mut t)
+            .map_err(|err| format!("fail to commit migration batch: {err}"))?;
+        Ok(Some(rewritten))
+    }
+
+    /// Id the next batch starts from, or `None` if the migration is done.
+    pub fn progress(&self, table: &Table) -> Result<Option<u32>, String> {
+        let Some(bytes) = table.meta_get(&self.progress_key())? else {
+            return Ok(Some(0));
+        };
+        let bytes: [u8; 8] = bytes
+            .try_into()
+            .map_err(|_| format!("invalid progress of migration '{}'", self.name))?;
+        let next = u64::from_le_bytes(bytes);
+        Ok((next <= MAX_ID as u64).then_some(next as u32))
+    }
+
+    fn progress_key(&self) -> String {
+        format!("migration.{}", self.name)
+    }
+}
+
+/// Transform of the migration with the given name, one of `MIGRATIONS`.
+pub fn builtin(name: &str) -> Option<fn(&Row) -> Option<Row>> {
+    match name {
+        "lowercase_email" => Some(lowercase_email),
+        _ => None,
+    }
+}
+
+// Emails are compared as they're stored, so the ones written before they
+// were lowercased on input don't match their lowercased version.
+fn lowercase_email(row: &Row) -> Option<Row> {
+    let email = row.email().to_lowercase();
+    Row::new(&row.id.to_string(), &row.username(), &email).ok()
+}
+
+// Saved as a u64, as the id after `MAX_ID` marks the migration as done.
+fn next_id(id: u32) -> [u8; 8] {
+    (id as u64 + 1).to_le_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::concurrency::LockManager;
+    use std::sync::Arc;
+
+    #[test]
+    fn migration_resumes_after_interruption() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let lock_manager = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lock_manager.clone());
+        let table = Table::with_lock_manager(path.clone(), 4, lock_manager);
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        for i in 1..=25 {
+            let row = Row::new(
+                &i.to_string(),
+                &format!("user{i}"),
+                &format!("User{i}@Email.com"),
+            );
+            table.insert_in(&row.unwrap(), &mut t).unwrap();
+        }
+        tm.commit(&table, &mut t).unwrap();
+        drop(t);
+
+        let lowercase_email = builtin("lowercase_email").unwrap();
+        let migration = Migration::new("lowercase_email", lowercase_email).with_batch_size(10);
+        assert_eq!(migration.progress(&table), Ok(Some(0)));
+        assert_eq!(migration.run_batch(&table, &tm), Ok(Some(10)));
+        assert_eq!(migration.progress(&table), Ok(Some(11)));
+        assert_eq!(
+            table.get_fast(10).unwrap().unwrap().email(),
+            "user10@email.com"
+        );
+        assert_eq!(
+            table.get_fast(11).unwrap().unwrap().email(),
+            "User11@Email.com"
+        );
+
+        // Interrupted, then resumed from the saved progress by another run.
+        let migration = Migration::new("lowercase_email", lowercase_email)
+            .with_batch_size(10)
+            .with_pause(Duration::from_millis(1));
+        assert_eq!(migration.run(&table, &tm), Ok(15));
+        assert_eq!(migration.progress(&table), Ok(None));
+        assert_eq!(
+            table.get_fast(25).unwrap().unwrap().email(),
+            "user25@email.com"
+        );
+        assert_eq!(migration.run(&table, &tm), Ok(0));
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+        let _ = std::fs::remove_file(format!("{path}.hot"));
+    }
+}
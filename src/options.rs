@@ -0,0 +1,132 @@
+use crate::query::DEFAULT_MAX_TOP_N_ROWS;
+use crate::storage::{LatchProtocol, ReplacementPolicy, SyncMode, DEFAULT_MAX_DIRTY_PERCENT};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Pages of the buffer pool, unless `DbOptions::with_pool_size` is given.
+const DEFAULT_POOL_SIZE: usize = 8;
+
+/// Options a database file is opened with, see `Table::with_options`,
+/// `Pager::with_options` and `Database::open`.
+///
+/// TODO (Page size): Pages are always `PAGE_SIZE` bytes, as the layout
+/// of the nodes is computed from it at compile time.
+#[derive(Debug, Clone)]
+pub struct DbOptions {
+    pool_size: usize,
+    sync_mode: SyncMode,
+    replacement_policy: ReplacementPolicy,
+    lock_timeout: Option<Duration>,
+    wal: Option<PathBuf>,
+    max_top_n_rows: usize,
+    max_dirty_percent: usize,
+    latch_protocol: LatchProtocol,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self {
+            pool_size: DEFAULT_POOL_SIZE,
+            sync_mode: SyncMode::default(),
+            replacement_policy: ReplacementPolicy::default(),
+            lock_timeout: None,
+            wal: None,
+            max_top_n_rows: DEFAULT_MAX_TOP_N_ROWS,
+            max_dirty_percent: DEFAULT_MAX_DIRTY_PERCENT,
+            latch_protocol: LatchProtocol::default(),
+        }
+    }
+}
+
+impl DbOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cache up to `pool_size` pages of the file in memory.
+    pub fn with_pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    /// Sync each page written to the file with `SyncMode::Full`, instead
+    /// of leaving it to the OS.
+    pub fn with_sync_mode(mut self, sync_mode: SyncMode) -> Self {
+        self.sync_mode = sync_mode;
+        self
+    }
+
+    pub fn with_replacement_policy(mut self, replacement_policy: ReplacementPolicy) -> Self {
+        self.replacement_policy = replacement_policy;
+        self
+    }
+
+    /// Fail a statement with `DbError::LockTimeout` once it waits for a
+    /// lock longer than the timeout, instead of waiting forever.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Log the writes to the WAL at the path, so the ones not flushed to
+    /// the file yet are recovered after a crash, see `LogManager`.
+    pub fn with_wal(mut self, path: impl AsRef<Path>) -> Self {
+        self.wal = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Keep at most `rows` rows in memory for an ORDER BY with a LIMIT,
+    /// instead of `DEFAULT_MAX_TOP_N_ROWS`, before sorting every row, see
+    /// `LimitPlanNode::plan`.
+    pub fn with_max_top_n_rows(mut self, rows: usize) -> Self {
+        self.max_top_n_rows = rows;
+        self
+    }
+
+    /// Have writers flush pages themselves once more than `percent` of
+    /// the buffer pool is dirty, instead of `DEFAULT_MAX_DIRTY_PERCENT`,
+    /// see `Pager::throttle_writes`.
+    pub fn with_max_dirty_percent(mut self, percent: usize) -> Self {
+        self.max_dirty_percent = percent.min(100);
+        self
+    }
+
+    /// Split and merge pages under the SMO latch with
+    /// `LatchProtocol::SmoLatch`, instead of latch crabbing alone.
+    pub fn with_latch_protocol(mut self, latch_protocol: LatchProtocol) -> Self {
+        self.latch_protocol = latch_protocol;
+        self
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.pool_size
+    }
+
+    pub fn sync_mode(&self) -> SyncMode {
+        self.sync_mode
+    }
+
+    pub fn replacement_policy(&self) -> ReplacementPolicy {
+        self.replacement_policy
+    }
+
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
+    }
+
+    pub fn wal(&self) -> Option<&Path> {
+        self.wal.as_deref()
+    }
+
+    pub fn max_top_n_rows(&self) -> usize {
+        self.max_top_n_rows
+    }
+
+    pub fn max_dirty_percent(&self) -> usize {
+        self.max_dirty_percent
+    }
+
+    pub fn latch_protocol(&self) -> LatchProtocol {
+        self.latch_protocol
+    }
+}
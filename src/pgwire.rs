@@ -0,0 +1,502 @@
+use crate::error::DbError;
+use crate::query::{
+    execute_update, prepare_statement, query_planned, ResultSet, Statement, StatementType,
+};
+use crate::row::Row;
+use crate::schema::{Column, ColumnType};
+use crate::table::Table;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+
+// A subset of the PostgreSQL frontend/backend protocol (version 3.0), which
+// is enough for clients like psql to connect and run our statements through
+// the simple query protocol.
+//
+// See https://www.postgresql.org/docs/current/protocol-flow.html
+const PROTOCOL_VERSION: i32 = 196608;
+const SSL_REQUEST_CODE: i32 = 80877103;
+const GSSENC_REQUEST_CODE: i32 = 80877104;
+const CANCEL_REQUEST_CODE: i32 = 80877102;
+
+// Object ID of the types of our columns.
+const INT4_OID: i32 = 23;
+const TEXT_OID: i32 = 25;
+
+/// Accept connections and serve each of them in its own thread.
+pub fn serve(listener: TcpListener, table: Arc<Table>) -> std::io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let table = table.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = handle_connection(stream, &table) {
+                eprintln!("connection closed: {err}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Serve a single client until it terminates the connection.
+pub fn handle_connection(mut stream: impl Read + Write, table: &Arc<Table>) -> std::io::Result<()> {
+    if !startup(&mut stream)? {
+        return Ok(());
+    }
+
+    loop {
+        let mut tag = [0; 1];
+        if stream.read(&mut tag)? == 0 {
+            return Ok(());
+        }
+
+        let body = read_body(&mut stream)?;
+        match tag[0] {
+            b'Q' => {
+                let query = String::from_utf8_lossy(&body);
+                let query = query.trim_end_matches('\0');
+                simple_query(&mut stream, table, query)?;
+            }
+            b'X' => return Ok(()),
+            tag => {
+                let message = format!("unsupported message type '{}'", tag as char);
+                error_response(&mut stream, "0A000", &message)?;
+                ready_for_query(&mut stream)?;
+            }
+        }
+    }
+}
+
+// Handle the startup message, and return false if the client doesn't
+// want a session, e.g. for a cancel request.
+fn startup(stream: &mut (impl Read + Write)) -> std::io::Result<bool> {
+    loop {
+        let body = read_body(stream)?;
+        if body.len() < 4 {
+            return Err(std::io::Error::other("invalid startup message"));
+        }
+
+        match i32::from_be_bytes(body[..4].try_into().unwrap()) {
+            // We don't support encryption, which the client should handle
+            // by sending the startup message again without it.
+            SSL_REQUEST_CODE | GSSENC_REQUEST_CODE => stream.write_all(b"N")?,
+            CANCEL_REQUEST_CODE => return Ok(false),
+            PROTOCOL_VERSION => break,
+            version => {
+                let message = format!("unsupported protocol version {version}");
+                error_response(stream, "08P01", &message)?;
+                return Ok(false);
+            }
+        }
+    }
+
+    // Parameters of the startup message, e.g. user and database, are
+    // ignored as there is no authentication and only a single table.
+    write_message(stream, b'R', &0_i32.to_be_bytes())?;
+    for (name, value) in [
+        ("server_version", "14.0"),
+        ("server_encoding", "UTF8"),
+        ("client_encoding", "UTF8"),
+        ("DateStyle", "ISO"),
+        ("integer_datetimes", "on"),
+        ("standard_conforming_strings", "on"),
+    ] {
+        let mut body = Vec::new();
+        put_cstring(&mut body, name);
+        put_cstring(&mut body, value);
+        write_message(stream, b'S', &body)?;
+    }
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&(std::process::id() as i32).to_be_bytes());
+    body.extend_from_slice(&0_i32.to_be_bytes());
+    write_message(stream, b'K', &body)?;
+
+    ready_for_query(stream)?;
+    Ok(true)
+}
+
+// TODO (Cursor spooling): Only the simple query protocol is supported, so
+// there are no server side cursors, and a select sends every row at once.
+//
+// Once DECLARE/FETCH are parsed, a full scan can keep a `ScanCursor` per
+// cursor and read the next rows on FETCH. Cursors with a predicate or an
+// order other than the key would need rows beyond a memory budget spooled
+// to temporary pages instead, freed with `Pager::free_tree` once the cursor
+// is closed.
+fn simple_query(stream: &mut impl Write, table: &Arc<Table>, query: &str) -> std::io::Result<()> {
+    // Clients like psql terminate statements with a semicolon.
+    let query = query.trim().trim_end_matches(';').trim();
+    if query.is_empty() {
+        write_message(stream, b'I', &[])?;
+        return ready_for_query(stream);
+    }
+
+    let _op = match table.enter() {
+        Ok(op) => op,
+        Err(err) => {
+            error_response(stream, sqlstate(&err), &err.to_string())?;
+            return ready_for_query(stream);
+        }
+    };
+
+    let statement = match prepare_statement(query) {
+        Ok(statement) => statement,
+        Err(reason) => {
+            error_response(stream, "42601", &reason)?;
+            return ready_for_query(stream);
+        }
+    };
+
+    if *statement.statement_type() == StatementType::CreateTable {
+        let name = statement.table.as_deref().unwrap();
+        match table.create_table(name, statement.schema.as_ref().unwrap()) {
+            Ok(()) => command_complete(stream, "CREATE TABLE")?,
+            Err(err) => error_response(stream, sqlstate(&err), &err.to_string())?,
+        }
+        return ready_for_query(stream);
+    }
+    if *statement.statement_type() == StatementType::Update {
+        match execute_update(table, &statement) {
+            Ok(output) if output.starts_with("updated") => command_complete(stream, "UPDATE 1")?,
+            Ok(_) => command_complete(stream, "UPDATE 0")?,
+            Err(reason) if reason.contains("duplicate value") => {
+                error_response(stream, "23505", &reason)?
+            }
+            Err(reason) => error_response(stream, "22023", &reason)?,
+        }
+        return ready_for_query(stream);
+    }
+
+    if let Some(query) = &statement.query {
+        match query_planned(table, query) {
+            Ok(result_set) => select_response(stream, &result_set)?,
+            Err(err) => error_response(stream, sqlstate(&err), &err.to_string())?,
+        }
+        return ready_for_query(stream);
+    }
+
+    match table.with_table(statement.table.as_deref(), |table| {
+        execute(stream, table, &statement)
+    }) {
+        Ok(result) => result?,
+        Err(err) => error_response(stream, "42P01", &err.to_string())?,
+    }
+
+    ready_for_query(stream)
+}
+
+fn insert(table: &Table, statement: &Statement) -> Result<usize, DbError> {
+    match &statement.row {
+        Some(row) => table.insert_row(row).map(|_| 1),
+        None => table
+            .insert_batch(&statement.rows)
+            .into_iter()
+            .try_fold(0, |inserted, result| result.map(|_| inserted + 1)),
+    }
+}
+
+fn execute(stream: &mut impl Write, table: &Table, statement: &Statement) -> std::io::Result<()> {
+    match statement.statement_type() {
+        StatementType::Select => select_response(stream, &table.query(statement))?,
+        // A multi-row insert isn't atomic, the rows before the first
+        // rejected one stay inserted even though the error is reported.
+        StatementType::Insert => match insert(table, statement) {
+            Ok(inserted) => command_complete(stream, &format!("INSERT 0 {inserted}"))?,
+            Err(err) => error_response(stream, sqlstate(&err), &err.to_string())?,
+        },
+        StatementType::Delete => {
+            let deleted = table.delete_row(statement.row.as_ref().unwrap());
+            command_complete(stream, &format!("DELETE {}", deleted as usize))?;
+        }
+        StatementType::CreateIndex { unique } => {
+            match table.create_index(statement.column.as_deref().unwrap(), *unique) {
+                Ok(_) => command_complete(stream, "CREATE INDEX")?,
+                Err(err) => error_response(stream, sqlstate(&err), &err.to_string())?,
+            }
+        }
+        StatementType::CreateTable | StatementType::Update => unreachable!(),
+    }
+
+    Ok(())
+}
+
+fn select_response(stream: &mut impl Write, result_set: &ResultSet) -> std::io::Result<()> {
+    row_description(stream, result_set.columns())?;
+    for rows in result_set.rows() {
+        data_row(stream, rows)?;
+    }
+    command_complete(stream, &format!("SELECT {}", result_set.len()))
+}
+
+fn row_description(stream: &mut impl Write, columns: &[Column]) -> std::io::Result<()> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&(columns.len() as i16).to_be_bytes());
+    for column in columns {
+        let (type_oid, type_len) = match column.column_type {
+            ColumnType::Int => (INT4_OID, 4),
+            ColumnType::Text(_) => (TEXT_OID, -1),
+        };
+        put_cstring(&mut body, &column.name);
+        // Table OID and column attribute number.
+        body.extend_from_slice(&0_i32.to_be_bytes());
+        body.extend_from_slice(&0_i16.to_be_bytes());
+        body.extend_from_slice(&type_oid.to_be_bytes());
+        body.extend_from_slice(&(type_len as i16).to_be_bytes());
+        // Type modifier and text format.
+        body.extend_from_slice(&(-1_i32).to_be_bytes());
+        body.extend_from_slice(&0_i16.to_be_bytes());
+    }
+
+    write_message(stream, b'T', &body)
+}
+
+// A row per table of the result, e.g. both rows of a join.
+fn data_row(stream: &mut impl Write, rows: &[Row]) -> std::io::Result<()> {
+    let values: Vec<_> = rows
+        .iter()
+        .flat_map(|row| [row.id.to_string(), row.username(), row.email()])
+        .collect();
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&(values.len() as i16).to_be_bytes());
+    for value in values {
+        body.extend_from_slice(&(value.len() as i32).to_be_bytes());
+        body.extend_from_slice(value.as_bytes());
+    }
+
+    write_message(stream, b'D', &body)
+}
+
+fn command_complete(stream: &mut impl Write, tag: &str) -> std::io::Result<()> {
+    let mut body = Vec::new();
+    put_cstring(&mut body, tag);
+    write_message(stream, b'C', &body)
+}
+
+// SQLSTATE code of the error, as listed in Appendix A of the PostgreSQL
+// documentation.
+fn sqlstate(err: &DbError) -> &'static str {
+    match err {
+        DbError::Catalog(reason) if reason.ends_with("already exists") => "42P07",
+        DbError::Catalog(_) => "XX000",
+        DbError::CheckFailed(_) => "XX002",
+        DbError::InvalidInput(_) => "0A000",
+        DbError::DuplicateKey | DbError::UniqueViolation(_) => "23505",
+        DbError::Io(_) => "58030",
+        DbError::LockTimeout | DbError::PageLatched(_) => "55P03",
+        DbError::NoFreeFrame | DbError::PoolTooSmall(_) => "53200",
+        DbError::PageCorrupted { .. } => "XX001",
+        DbError::ReadOnly => "25006",
+        DbError::TableClosed => "57P01",
+        DbError::TooLargeTransaction(_) => "54000",
+        DbError::TransactionEnded => "25P01",
+        DbError::TransactionAborted(err) => sqlstate(err),
+    }
+}
+
+fn error_response(stream: &mut impl Write, code: &str, message: &str) -> std::io::Result<()> {
+    let mut body = Vec::new();
+    for (field, value) in [
+        (b'S', "ERROR"),
+        (b'V', "ERROR"),
+        (b'C', code),
+        (b'M', message),
+    ] {
+        body.push(field);
+        put_cstring(&mut body, value);
+    }
+    body.push(0);
+
+    write_message(stream, b'E', &body)
+}
+
+// Idle, as we don't support transaction blocks.
+fn ready_for_query(stream: &mut impl Write) -> std::io::Result<()> {
+    write_message(stream, b'Z', b"I")
+}
+
+// Read the body of a message, where the length prefix includes itself.
+fn read_body(stream: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+
+    let len = i32::from_be_bytes(len);
+    if len < 4 {
+        return Err(std::io::Error::other(format!(
+            "invalid message length {len}"
+        )));
+    }
+
+    let mut body = vec![0; len as usize - 4];
+    stream.read_exact(&mut body)?;
+    Ok(body)
+}
+
+fn write_message(stream: &mut impl Write, tag: u8, body: &[u8]) -> std::io::Result<()> {
+    let mut message = Vec::with_capacity(body.len() + 5);
+    message.push(tag);
+    message.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+    message.extend_from_slice(body);
+    stream.write_all(&message)?;
+    stream.flush()
+}
+
+fn put_cstring(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(0);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    // A stream that reads what the client sent, and records what the
+    // server writes back.
+    struct Stream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Write for &mut Stream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for &mut Stream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    fn startup_message() -> Vec<u8> {
+        let mut body = PROTOCOL_VERSION.to_be_bytes().to_vec();
+        put_cstring(&mut body, "user");
+        put_cstring(&mut body, "postgres");
+        body.push(0);
+
+        let mut message = (body.len() as i32 + 4).to_be_bytes().to_vec();
+        message.extend(body);
+        message
+    }
+
+    fn query(sql: &str) -> Vec<u8> {
+        let mut body = Vec::new();
+        put_cstring(&mut body, sql);
+
+        let mut message = vec![b'Q'];
+        message.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        message.extend(body);
+        message
+    }
+
+    // Split the server output into (tag, body) of each message.
+    fn messages(mut output: &[u8]) -> Vec<(char, Vec<u8>)> {
+        let mut messages = Vec::new();
+        while !output.is_empty() {
+            let tag = output[0] as char;
+            let len = i32::from_be_bytes(output[1..5].try_into().unwrap()) as usize;
+            messages.push((tag, output[5..len + 1].to_vec()));
+            output = &output[len + 1..];
+        }
+        messages
+    }
+
+    fn run(table: &Arc<Table>, input: Vec<u8>) -> Vec<(char, Vec<u8>)> {
+        let mut stream = Stream {
+            input: Cursor::new(input),
+            output: Vec::new(),
+        };
+        handle_connection(&mut stream, table).unwrap();
+        messages(&stream.output)
+    }
+
+    #[test]
+    fn startup_with_ssl_request() {
+        let table = setup_test_table();
+
+        let mut input = 8_i32.to_be_bytes().to_vec();
+        input.extend_from_slice(&SSL_REQUEST_CODE.to_be_bytes());
+
+        let mut stream = Stream {
+            input: Cursor::new([input, startup_message(), vec![b'X', 0, 0, 0, 4]].concat()),
+            output: Vec::new(),
+        };
+        handle_connection(&mut stream, &table).unwrap();
+
+        // SSL is rejected with a single byte before the startup messages.
+        assert_eq!(stream.output[0], b'N');
+        let tags: String = messages(&stream.output[1..])
+            .iter()
+            .map(|(tag, _)| tag)
+            .collect();
+        assert_eq!(tags, "RSSSSSSKZ");
+
+        clean_test();
+    }
+
+    #[test]
+    fn simple_query_protocol() {
+        let table = setup_test_table();
+
+        let input = [
+            startup_message(),
+            query("insert 1 john john@email.com;"),
+            query("insert 1 john john@email.com"),
+            query("select"),
+            query("delete 1"),
+            query("bogus"),
+            query(""),
+            vec![b'X', 0, 0, 0, 4],
+        ]
+        .concat();
+        let messages = run(&table, input);
+        let messages: Vec<_> = messages
+            .into_iter()
+            .skip_while(|(tag, _)| *tag != 'Z')
+            .skip(1)
+            .collect();
+
+        let tags: String = messages.iter().map(|(tag, _)| tag).collect();
+        assert_eq!(tags, "CZEZTDCZCZEZIZ");
+
+        assert_eq!(messages[0].1, b"INSERT 0 1\0");
+        assert!(String::from_utf8_lossy(&messages[2].1).contains("C23505\0Mduplicate key\0"));
+
+        let row_description = &messages[4].1;
+        assert_eq!(&row_description[..2], &3_i16.to_be_bytes());
+        assert_eq!(&row_description[2..5], b"id\0");
+
+        let mut data_row = 3_i16.to_be_bytes().to_vec();
+        for value in ["1", "john", "john@email.com"] {
+            data_row.extend_from_slice(&(value.len() as i32).to_be_bytes());
+            data_row.extend_from_slice(value.as_bytes());
+        }
+        assert_eq!(messages[5].1, data_row);
+        assert_eq!(messages[6].1, b"SELECT 1\0");
+        assert_eq!(messages[8].1, b"DELETE 1\0");
+        assert!(String::from_utf8_lossy(&messages[10].1).contains("C42601\0"));
+
+        clean_test();
+    }
+
+    fn setup_test_table() -> Arc<Table> {
+        Arc::new(Table::new(
+            format!("test-{:?}.db", std::thread::current().id()),
+            8,
+        ))
+    }
+
+    fn clean_test() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+        let _ = std::fs::remove_file(format!("{path}.hot"));
+    }
+}
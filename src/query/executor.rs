@@ -1,18 +1,31 @@
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
+use super::expression::{Expression, Value};
 use super::query_plan::{
-    DeletePlanNode, IndexScanPlanNode, PlanNode, SeqScanPlanNode, UpdatePlanNode,
+    DeletePlanNode, FilterPlanNode, IndexLookup, IndexScanPlanNode, InsertPlanNode, LimitPlanNode,
+    MergeJoinPlanNode, OrderBy, PaginatePlanNode, PlanNode, RangeScanPlanNode,
+    SecondaryIndexScanPlanNode, SemiJoinPlanNode, SeqScanPlanNode, SortPlanNode, SubqueryPredicate,
+    TopNPlanNode, UpdatePlanNode, ValuesPlanNode,
 };
+use super::result_set::ResultSet;
 use crate::{
-    concurrency::{LockManager, RowID, Table, TableIntoIter, Transaction},
+    concurrency::{LockManager, Transaction},
+    error::DbError,
     row::Row,
+    table::{RowID, Table, TableIntoIter},
 };
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
 use std::sync::Arc;
 
 pub struct ExecutionContext {
     table: Arc<Table>,
     lock_manager: Arc<LockManager>,
     transaction: Arc<RwLock<Transaction>>,
+    // Error that stopped the executor tree, e.g. a lock wait timeout.
+    // Executors only return rows, so the one that fails stops returning
+    // rows and leaves the error here for the caller.
+    error: Mutex<Option<DbError>>,
 }
 
 impl ExecutionContext {
@@ -25,6 +38,56 @@ impl ExecutionContext {
             table,
             lock_manager,
             transaction,
+            error: Mutex::new(None),
+        }
+    }
+
+    // Stop the executor tree with the error, keeping the first one.
+    fn fail(&self, err: DbError) {
+        self.error.lock().get_or_insert(err);
+    }
+
+    // Read the row with the locks the isolation level of the transaction
+    // needs, see `LockManager`. The row is read again once locked, as it
+    // might have changed while waiting, and `None` is returned if the row
+    // with the key isn't at the RID anymore.
+    fn read_row(&self, rid: RowID, key: u32) -> Result<Option<Row>, DbError> {
+        let mut t = self.transaction.write();
+        if !(t.is_shared_lock(&rid) || t.is_exclusive_lock(&rid)) {
+            self.lock_manager.lock_shared(&mut t, rid)?;
+        }
+
+        let row = self.table.get(rid, &mut t).filter(|row| row.id == key);
+        self.lock_manager.unlock_after_read(&mut t, &rid);
+        Ok(row)
+    }
+
+    // Next row of the scan matching the predicate, read with `read_row`.
+    //
+    // TRADEOFF: Rows are matched before they're locked, so a row that
+    // only matches once an uncommitted update commits is skipped instead
+    // of waited for.
+    fn next_locked(
+        &self,
+        iter: &mut TableIntoIter,
+        predicate: Option<&Expression>,
+    ) -> Option<(RowID, Row)> {
+        let matches = |row: &Row| predicate.is_none_or(|predicate| predicate.matches(row));
+        loop {
+            let Some((rid, row)) = iter.find(|(_, row)| matches(row)) else {
+                if let Some(err) = iter.take_error() {
+                    self.fail(err);
+                }
+                return None;
+            };
+            match self.read_row(rid, row.id) {
+                Ok(Some(row)) if matches(&row) => return Some((rid, row)),
+                Ok(_) => continue,
+                Err(err) => {
+                    self.fail(err);
+                    return None;
+                }
+            }
         }
     }
 }
@@ -40,38 +103,117 @@ impl ExecutionEngine {
         }
     }
 
-    pub fn execute(&self, plan_node: PlanNode) -> Vec<(RowID, Row)> {
-        let mut result_set = Vec::new();
-        let mut executor: Box<dyn Executor> = match plan_node {
-            PlanNode::IndexScan(plan_node) => Box::new(IndexScanExecutor::new(
-                self.execution_context.clone(),
-                plan_node,
-            )),
-            PlanNode::SeqScan(plan_node) => Box::new(SequenceScanExecutor::new(
-                self.execution_context.clone(),
-                plan_node,
-            )),
-            PlanNode::Update(plan_node) => Box::new(UpdateExecutor::new(
-                self.execution_context.clone(),
-                plan_node,
-            )),
-            PlanNode::Delete(plan_node) => Box::new(DeleteExecutor::new(
-                self.execution_context.clone(),
-                plan_node,
-            )),
-            _ => unimplemented!("oops"),
+    /// Execute the plan and collect the whole result set, or return the
+    /// error that stopped it, e.g. `DbError::LockTimeout`.
+    ///
+    /// A join returns the rows of more than one table, and is only
+    /// executed by `query`.
+    pub fn execute(&self, plan_node: PlanNode) -> Result<Vec<(RowID, Row)>, DbError> {
+        let mut results = self.stream(plan_node);
+        let rows = results.by_ref().collect();
+        results.take_error().map_or(Ok(rows), Err)
+    }
+
+    /// Same as `execute`, but return the rows as a `ResultSet`, e.g. to
+    /// print them or export them as CSV, along with the rows of the other
+    /// table of a join, see `Executor::next_joined`.
+    pub fn query(&self, plan_node: PlanNode) -> Result<ResultSet, DbError> {
+        let ctx = &self.execution_context;
+        let table = ctx.table.name();
+        let joined_table = plan_node.join().map(|join| join.table.clone());
+        let tables = match &joined_table {
+            Some(joined_table) => vec![table, joined_table.as_deref().unwrap_or(table)],
+            None => vec![table],
         };
 
-        while let Some(result) = executor.next() {
-            result_set.push(result);
+        let mut executor = create_executor(ctx.clone(), plan_node.push_down_predicates());
+        let mut rows = Vec::new();
+        while let Some((_, joined)) = executor.next_joined() {
+            rows.push(joined);
+        }
+        match ctx.error.lock().take() {
+            Some(err) => Err(err),
+            None => Ok(ResultSet::joined(&tables, rows)),
+        }
+    }
+
+    /// Build the executor tree for the given plan and return an iterator
+    /// that pull results from the root executor lazily. Nothing is executed
+    /// until the iterator is consumed.
+    pub fn stream(&self, plan_node: PlanNode) -> ResultIter {
+        let plan_node = plan_node.push_down_predicates();
+
+        ResultIter {
+            execution_context: self.execution_context.clone(),
+            executor: create_executor(self.execution_context.clone(), plan_node),
         }
+    }
+}
+
+#[must_use = "executors are lazy and do nothing unless consumed"]
+pub struct ResultIter {
+    execution_context: Arc<ExecutionContext>,
+    executor: Box<dyn Executor>,
+}
+
+impl ResultIter {
+    /// Error that ended the results early, if any. Once taken, the
+    /// context can execute other plans.
+    pub fn take_error(&self) -> Option<DbError> {
+        self.execution_context.error.lock().take()
+    }
+}
+
+impl Iterator for ResultIter {
+    type Item = (RowID, Row);
 
-        result_set
+    fn next(&mut self) -> Option<Self::Item> {
+        self.executor.next()
+    }
+}
+
+/// Recursively build the executor tree for the given plan node,
+/// where each executor pull its input from its children executor.
+pub fn create_executor(ctx: Arc<ExecutionContext>, plan_node: PlanNode) -> Box<dyn Executor> {
+    match plan_node {
+        PlanNode::IndexScan(plan_node) => Box::new(IndexScanExecutor::new(ctx, plan_node)),
+        PlanNode::SeqScan(plan_node) => Box::new(SequenceScanExecutor::new(ctx, plan_node)),
+        PlanNode::RangeScan(plan_node) => Box::new(RangeScanExecutor::new(ctx, plan_node)),
+        PlanNode::SecondaryIndexScan(plan_node) => {
+            Box::new(SecondaryIndexScanExecutor::new(ctx, plan_node))
+        }
+        PlanNode::Filter(plan_node) => Box::new(FilterExecutor::new(ctx, plan_node)),
+        PlanNode::Values(plan_node) => Box::new(ValuesExecutor::new(plan_node)),
+        PlanNode::TopN(plan_node) => Box::new(TopNExecutor::new(ctx, plan_node)),
+        PlanNode::Sort(plan_node) => Box::new(SortExecutor::new(ctx, plan_node)),
+        PlanNode::Limit(plan_node) => Box::new(LimitExecutor::new(ctx, plan_node)),
+        PlanNode::Paginate(plan_node) => Box::new(PaginateExecutor::new(ctx, plan_node)),
+        PlanNode::MergeJoin(plan_node) => Box::new(MergeJoinExecutor::new(ctx, plan_node)),
+        PlanNode::SemiJoin(plan_node) => Box::new(SemiJoinExecutor::new(ctx, plan_node)),
+        PlanNode::Update(plan_node) => Box::new(UpdateExecutor::new(ctx, plan_node)),
+        PlanNode::Insert(plan_node) => Box::new(InsertExecutor::new(ctx, plan_node)),
+        PlanNode::Delete(plan_node) => Box::new(DeleteExecutor::new(ctx, plan_node)),
     }
 }
 
 pub trait Executor {
     fn next(&mut self) -> Option<(RowID, Row)>;
+
+    /// Same as `next`, but along with the rows the row is joined with,
+    /// which come after it, e.g. the right row of a `MergeJoinExecutor`.
+    fn next_joined(&mut self) -> Option<(RowID, Vec<Row>)> {
+        self.next().map(|(rid, row)| (rid, vec![row]))
+    }
+}
+
+// Next row of the child, along with the rows it's joined with if `joined`,
+// for the executors that buffer rows of either kind, e.g. a sort.
+fn next_rows(child: &mut dyn Executor, joined: bool) -> Option<(RowID, Vec<Row>)> {
+    if joined {
+        child.next_joined()
+    } else {
+        child.next().map(|(rid, row)| (rid, vec![row]))
+    }
 }
 
 pub struct SequenceScanExecutor {
@@ -94,273 +236,1672 @@ impl Executor for SequenceScanExecutor {
     fn next(&mut self) -> Option<(RowID, Row)> {
         let table = &self.execution_context.table;
         if self.iter.is_none() {
-            self.iter = Some(table.iter());
+            self.iter = Some(table.iter_with_hint(self.plan_node.access_pattern()));
         };
 
         let iter = self.iter.as_mut().unwrap();
-        iter.next()
+        self.execution_context
+            .next_locked(iter, self.plan_node.predicate.as_ref())
     }
 }
 
-// Currently our index scan executor only support getting
-// 1 row. and index scan by row.id.
-pub struct IndexScanExecutor {
+pub struct RangeScanExecutor {
     execution_context: Arc<ExecutionContext>,
-    plan_node: IndexScanPlanNode,
-    ended: bool,
+    plan_node: RangeScanPlanNode,
+    iter: Option<TableIntoIter>,
 }
 
-impl IndexScanExecutor {
-    pub fn new(ctx: Arc<ExecutionContext>, plan_node: IndexScanPlanNode) -> Self {
+impl RangeScanExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: RangeScanPlanNode) -> Self {
         Self {
             plan_node,
             execution_context: ctx,
-            ended: false,
+            iter: None,
         }
     }
 }
 
-impl Executor for IndexScanExecutor {
+impl Executor for RangeScanExecutor {
     fn next(&mut self) -> Option<(RowID, Row)> {
-        if self.ended {
-            None
-        } else {
-            let table = &self.execution_context.table;
-            let mut t = self.execution_context.transaction.write();
-            self.ended = true;
+        let table = &self.execution_context.table;
+        if self.iter.is_none() {
+            self.iter = Some(table.iter_range(self.plan_node.start, self.plan_node.end));
+        };
 
-            // Get Row ID first, so we could ask for a lock from the lock manager.
-            //
-            // We can only get the row after lock manager grant us the lock.
-            table
-                .get_row_id(self.plan_node.key, &mut t)
-                .and_then(|row_id| {
-                    // For the simplicity of implementation,
-                    // let's always take an exclusive lock.
-                    //
-                    // Later on, we'll use lock_upgrade to
-                    // upgrade our shared lock to exclusive lock
-                    // in update/delete exectuor.
-                    if !(t.is_shared_lock(&row_id) || t.is_exclusive_lock(&row_id)) {
-                        self.execution_context
-                            .lock_manager
-                            // TODO: We should pass &row_id
-                            .lock_shared(&mut t, row_id);
-                    }
+        let iter = self.iter.as_mut().unwrap();
+        self.execution_context
+            .next_locked(iter, self.plan_node.predicate.as_ref())
+    }
+}
+
+pub struct FilterExecutor {
+    plan_node: FilterPlanNode,
+    child: Box<dyn Executor>,
+}
+
+impl FilterExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: FilterPlanNode) -> Self {
+        let child = create_executor(ctx, *plan_node.child.clone());
+        Self { plan_node, child }
+    }
+}
 
-                    // TODO: we should probably just pass &row_id as well
-                    table.get(row_id, &mut t).map(|row| (row_id, row))
-                })
+impl Executor for FilterExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        while let Some((rid, row)) = self.child.next() {
+            if self.plan_node.predicate.matches(&row) {
+                return Some((rid, row));
+            }
         }
+
+        None
     }
 }
 
-pub struct DeleteExecutor {
-    execution_context: Arc<ExecutionContext>,
-    plan_node: DeletePlanNode,
-    affected_row: usize,
-    iter: Option<SequenceScanExecutor>,
+pub struct ValuesExecutor {
+    rows: std::vec::IntoIter<Row>,
+    slot_num: usize,
+}
+
+impl ValuesExecutor {
+    pub fn new(plan_node: ValuesPlanNode) -> Self {
+        Self {
+            rows: plan_node.rows.into_iter(),
+            slot_num: 0,
+        }
+    }
+}
+
+impl Executor for ValuesExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        // Since the rows are not stored anywhere, we return a detached
+        // row id instead.
+        let row = self.rows.next()?;
+        let rid = RowID::detached(self.slot_num);
+        self.slot_num += 1;
+        Some((rid, row))
+    }
+}
+
+// A row being sorted, by a top N or a sort. The sort keys are evaluated
+// once when the row is read from the child, instead of on every
+// comparison.
+struct SortEntry {
+    order_by: Arc<[OrderBy]>,
+    keys: Vec<Option<Value>>,
+    // The order the row is produced by the child, used to break ties
+    // so the output is the same as a stable sort.
+    seq: usize,
+    rid: RowID,
+    // The row, followed by the rows it's joined with, if any. Only the
+    // row itself is sorted on.
+    rows: Vec<Row>,
+}
+
+impl SortEntry {
+    fn new(order_by: &Arc<[OrderBy]>, seq: usize, rid: RowID, rows: Vec<Row>) -> Self {
+        Self {
+            order_by: order_by.clone(),
+            keys: OrderBy::sort_keys(order_by, &rows[0]),
+            seq,
+            rid,
+            rows,
+        }
+    }
+
+    fn into_row(self) -> (RowID, Row) {
+        (self.rid, self.rows.into_iter().next().unwrap())
+    }
+}
+
+impl Ord for SortEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        OrderBy::compare_keys(&self.order_by, &self.keys, &other.keys)
+            .then(self.seq.cmp(&other.seq))
+    }
+}
+
+impl PartialOrd for SortEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortEntry {}
+
+// Keep only the first N rows of the child in a bounded max heap,
+// where the top of the heap is the row that would be evicted next.
+//
+// This takes O(N) memory instead of buffering the whole child
+// like a full sort would.
+pub struct TopNExecutor {
+    plan_node: TopNPlanNode,
+    child: Box<dyn Executor>,
+    result: Option<std::vec::IntoIter<SortEntry>>,
+}
+
+impl TopNExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: TopNPlanNode) -> Self {
+        let child = create_executor(ctx, *plan_node.child.clone());
+        Self {
+            plan_node,
+            child,
+            result: None,
+        }
+    }
+
+    fn build(&mut self, joined: bool) -> Vec<SortEntry> {
+        let limit = self.plan_node.limit;
+        if limit == 0 {
+            return Vec::new();
+        }
+
+        let order_by: Arc<[OrderBy]> = self.plan_node.order_by.clone().into();
+        let mut heap = BinaryHeap::with_capacity(limit + 1);
+        let mut seq = 0;
+
+        while let Some((rid, rows)) = next_rows(self.child.as_mut(), joined) {
+            let entry = SortEntry::new(&order_by, seq, rid, rows);
+            seq += 1;
+
+            if heap.len() < limit {
+                heap.push(entry);
+            } else if heap.peek().is_some_and(|top| entry < *top) {
+                // Only replace the top when the new row is ordered before it,
+                // so we don't allocate for rows that are going to be evicted.
+                heap.pop();
+                heap.push(entry);
+            }
+        }
+
+        heap.into_sorted_vec()
+    }
+
+    fn next_entry(&mut self, joined: bool) -> Option<SortEntry> {
+        if self.result.is_none() {
+            self.result = Some(self.build(joined).into_iter());
+        }
+
+        self.result.as_mut().unwrap().next()
+    }
+}
+
+impl Executor for TopNExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        self.next_entry(false).map(SortEntry::into_row)
+    }
+
+    fn next_joined(&mut self) -> Option<(RowID, Vec<Row>)> {
+        self.next_entry(true).map(|entry| (entry.rid, entry.rows))
+    }
+}
+
+// Rows of the child sorted in memory at a time, as one sorted run.
+const SORT_RUN_ROWS: usize = 4096;
+
+// Sort the whole child, by cutting it into sorted runs of at most
+// `SORT_RUN_ROWS` rows and merging the runs as rows are returned.
+//
+// TODO (External sort): Runs are kept in memory for now, so the whole
+// child is still buffered. Spilling each run to a temporary file once
+// it's sorted would bound the memory to a run per merge input.
+pub struct SortExecutor {
+    plan_node: SortPlanNode,
+    child: Box<dyn Executor>,
+    runs: Vec<std::vec::IntoIter<SortEntry>>,
+    // The next row of every run that's not exhausted, with the index of
+    // its run. The smallest row is at the top.
+    heads: Option<BinaryHeap<Reverse<(SortEntry, usize)>>>,
+}
+
+impl SortExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: SortPlanNode) -> Self {
+        let child = create_executor(ctx, *plan_node.child.clone());
+        Self {
+            plan_node,
+            child,
+            runs: Vec::new(),
+            heads: None,
+        }
+    }
+
+    fn build_runs(&mut self, joined: bool) {
+        let order_by: Arc<[OrderBy]> = self.plan_node.order_by.clone().into();
+        let mut run = Vec::new();
+        let mut seq = 0;
+
+        while let Some((rid, rows)) = next_rows(self.child.as_mut(), joined) {
+            run.push(SortEntry::new(&order_by, seq, rid, rows));
+            seq += 1;
+
+            if run.len() == SORT_RUN_ROWS {
+                self.push_run(std::mem::take(&mut run));
+            }
+        }
+        if !run.is_empty() {
+            self.push_run(run);
+        }
+    }
+
+    fn push_run(&mut self, mut run: Vec<SortEntry>) {
+        // Ties are broken by `seq`, so an unstable sort is stable here.
+        run.sort_unstable();
+        self.runs.push(run.into_iter());
+    }
+
+    fn merge_heads(&mut self) -> BinaryHeap<Reverse<(SortEntry, usize)>> {
+        self.runs
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, run)| run.next().map(|entry| Reverse((entry, i))))
+            .collect()
+    }
+
+    fn next_entry(&mut self, joined: bool) -> Option<SortEntry> {
+        if self.heads.is_none() {
+            self.build_runs(joined);
+            self.heads = Some(self.merge_heads());
+        }
+
+        let heads = self.heads.as_mut().unwrap();
+        let Reverse((entry, i)) = heads.pop()?;
+        if let Some(next) = self.runs[i].next() {
+            heads.push(Reverse((next, i)));
+        }
+        Some(entry)
+    }
+}
+
+impl Executor for SortExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        self.next_entry(false).map(SortEntry::into_row)
+    }
+
+    fn next_joined(&mut self) -> Option<(RowID, Vec<Row>)> {
+        self.next_entry(true).map(|entry| (entry.rid, entry.rows))
+    }
+}
+
+pub struct LimitExecutor {
+    // Rows of the child left to skip, then to return.
+    offset: usize,
+    limit: usize,
+    child: Box<dyn Executor>,
+}
+
+impl LimitExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: LimitPlanNode) -> Self {
+        let child = create_executor(ctx, *plan_node.child);
+        Self {
+            offset: plan_node.offset,
+            limit: plan_node.limit,
+            child,
+        }
+    }
+
+    // Skip the offset, then return the rows `next` pulls from the child
+    // up to the limit.
+    fn next_with<T>(&mut self, mut next: impl FnMut(&mut dyn Executor) -> Option<T>) -> Option<T> {
+        if self.limit == 0 {
+            return None;
+        }
+
+        while self.offset > 0 {
+            next(self.child.as_mut())?;
+            self.offset -= 1;
+        }
+
+        let row = next(self.child.as_mut())?;
+        self.limit -= 1;
+        Some(row)
+    }
+}
+
+impl Executor for LimitExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        self.next_with(|child| child.next())
+    }
+
+    fn next_joined(&mut self) -> Option<(RowID, Vec<Row>)> {
+        self.next_with(|child| child.next_joined())
+    }
+}
+
+// Only return rows with an id strictly greater than the cursor, which
+// is the last row returned, or `after` before the first row.
+//
+// Besides skipping rows before the cursor of the previous page, this
+// also makes sure a row is never repeated within a page, e.g. when a
+// leaf is split while the child is scanning through it.
+pub struct PaginateExecutor {
+    page_size: usize,
+    cursor: Option<u32>,
+    returned: usize,
+    child: Box<dyn Executor>,
+}
+
+impl PaginateExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: PaginatePlanNode) -> Self {
+        let child = create_executor(ctx, *plan_node.child);
+        Self {
+            page_size: plan_node.page_size,
+            cursor: plan_node.after,
+            returned: 0,
+            child,
+        }
+    }
+}
+
+impl Executor for PaginateExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        if self.returned >= self.page_size {
+            return None;
+        }
+
+        while let Some((rid, row)) = self.child.next() {
+            if self.cursor.is_some_and(|cursor| row.id <= cursor) {
+                continue;
+            }
+
+            self.cursor = Some(row.id);
+            self.returned += 1;
+            return Some((rid, row));
+        }
+
+        None
+    }
+}
+
+// Both children must produce rows in ascending order of their join key.
+//
+// We only buffer the rows of the right child that share the same key
+// as the current left row, so duplicate keys on both sides still
+// produce every matching pair, without materializing either side.
+//
+// The right child runs in a context of its own when it scans another
+// table, in the same transaction, see `MergeJoinPlanNode::table`.
+pub struct MergeJoinExecutor {
+    plan_node: MergeJoinPlanNode,
+    ctx: Arc<ExecutionContext>,
+    right_ctx: Arc<ExecutionContext>,
+    left: Box<dyn Executor>,
+    right: Box<dyn Executor>,
+    left_current: Option<(RowID, Row, Value)>,
+    right_next: Option<(RowID, Row, Value)>,
+    right_group: Vec<(RowID, Row)>,
+    right_group_key: Option<Value>,
+    right_group_index: usize,
+}
+
+impl MergeJoinExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: MergeJoinPlanNode) -> Self {
+        let left = create_executor(ctx.clone(), *plan_node.left.clone());
+        let right_table = match &plan_node.table {
+            Some(name) if name != ctx.table.name() => ctx.table.open_table(name).map(Arc::new),
+            _ => Ok(ctx.table.clone()),
+        };
+        let (right_ctx, right) = match right_table {
+            Ok(table) => {
+                let right_ctx = Arc::new(ExecutionContext::new(
+                    table,
+                    ctx.lock_manager.clone(),
+                    ctx.transaction.clone(),
+                ));
+                let right = create_executor(right_ctx.clone(), *plan_node.right.clone());
+                (right_ctx, right)
+            }
+            Err(err) => {
+                ctx.fail(err);
+                let right: Box<dyn Executor> =
+                    Box::new(ValuesExecutor::new(ValuesPlanNode { rows: Vec::new() }));
+                (ctx.clone(), right)
+            }
+        };
+        Self {
+            plan_node,
+            ctx,
+            right_ctx,
+            left,
+            right,
+            left_current: None,
+            right_next: None,
+            right_group: Vec::new(),
+            right_group_key: None,
+            right_group_index: 0,
+        }
+    }
+
+    // Return the next pair of matching left and right rows.
+    fn next_pair(&mut self) -> Option<((RowID, Row), (RowID, Row))> {
+        loop {
+            if let Some((rid, row, _)) = &self.left_current {
+                if let Some(right) = self.right_group.get(self.right_group_index) {
+                    self.right_group_index += 1;
+                    return Some(((*rid, row.clone()), right.clone()));
+                }
+            }
+
+            let (rid, row, key) = self.next_left()?;
+            self.right_group_index = 0;
+
+            // Consecutive left rows with the same key reuse the same group.
+            if self.right_group_key.as_ref() != Some(&key) {
+                self.fill_right_group(&key);
+            }
+            self.left_current = Some((rid, row, key));
+        }
+    }
+
+    // Rows without a join key never match anything, so skip them.
+    fn next_left(&mut self) -> Option<(RowID, Row, Value)> {
+        while let Some((rid, row)) = self.left.next() {
+            if let Some(key) = self.plan_node.left_key.evaluate(&row) {
+                return Some((rid, row, key));
+            }
+        }
+
+        None
+    }
+
+    fn next_right(&mut self) -> Option<(RowID, Row, Value)> {
+        if let Some(next) = self.right_next.take() {
+            return Some(next);
+        }
+
+        while let Some((rid, row)) = self.right.next() {
+            if let Some(key) = self.plan_node.right_key.evaluate(&row) {
+                return Some((rid, row, key));
+            }
+        }
+
+        // Stop the join with the error of the right child, if any.
+        let err = self.right_ctx.error.lock().take();
+        if let Some(err) = err {
+            self.ctx.fail(err);
+        }
+        None
+    }
+
+    // Advance the right child until its key is no longer smaller than
+    // the given key, and collect every right row equal to it.
+    fn fill_right_group(&mut self, key: &Value) {
+        self.right_group.clear();
+        self.right_group_key = Some(key.clone());
+
+        while let Some((rid, row, right_key)) = self.next_right() {
+            match right_key.compare(key) {
+                Some(Ordering::Equal) => self.right_group.push((rid, row)),
+                Some(Ordering::Greater) => {
+                    self.right_next = Some((rid, row, right_key));
+                    break;
+                }
+                // Keys of a different type are never equal, so we treat
+                // them as smaller and skip over them.
+                Some(Ordering::Less) | None => {}
+            }
+        }
+    }
+}
+
+impl Executor for MergeJoinExecutor {
+    // A joined row has the columns of both tables, so it's only returned
+    // along with the right row, by `next_joined`.
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        self.ctx.fail(DbError::InvalidInput(
+            "a join only returns joined rows".to_string(),
+        ));
+        None
+    }
+
+    fn next_joined(&mut self) -> Option<(RowID, Vec<Row>)> {
+        self.next_pair()
+            .map(|((rid, left), (_, right))| (rid, vec![left, right]))
+    }
+}
+
+// The materialized result of the subquery.
+enum SubqueryResult {
+    Values(HashSet<Value>),
+    Exists(bool),
+}
+
+pub struct SemiJoinExecutor {
+    plan_node: SemiJoinPlanNode,
+    child: Box<dyn Executor>,
+    subquery: Box<dyn Executor>,
+    result: Option<SubqueryResult>,
+}
+
+impl SemiJoinExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: SemiJoinPlanNode) -> Self {
+        let child = create_executor(ctx.clone(), *plan_node.child.clone());
+        let subquery = create_executor(ctx, *plan_node.subquery.clone());
+        Self {
+            plan_node,
+            child,
+            subquery,
+            result: None,
+        }
+    }
+
+    fn materialize(&mut self) -> SubqueryResult {
+        match &self.plan_node.predicate {
+            SubqueryPredicate::In { column, .. } => {
+                let mut values = HashSet::new();
+                while let Some((_, row)) = self.subquery.next() {
+                    if let Some(value) = column.evaluate(&row) {
+                        values.insert(value);
+                    }
+                }
+                SubqueryResult::Values(values)
+            }
+            // We only need to know if there is at least one row.
+            SubqueryPredicate::Exists => SubqueryResult::Exists(self.subquery.next().is_some()),
+        }
+    }
 }
 
-impl DeleteExecutor {
-    pub fn new(ctx: Arc<ExecutionContext>, plan_node: DeletePlanNode) -> Self {
-        Self {
-            plan_node,
-            execution_context: ctx,
-            affected_row: 0,
-            iter: None,
-        }
+impl Executor for SemiJoinExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        if self.result.is_none() {
+            self.result = Some(self.materialize());
+        }
+
+        let result = self.result.as_ref().unwrap();
+        match (result, &self.plan_node.predicate) {
+            (SubqueryResult::Values(values), SubqueryPredicate::In { expression, .. }) => {
+                while let Some((rid, row)) = self.child.next() {
+                    if expression
+                        .evaluate(&row)
+                        .is_some_and(|value| values.contains(&value))
+                    {
+                        return Some((rid, row));
+                    }
+                }
+
+                None
+            }
+            (SubqueryResult::Exists(true), _) => self.child.next(),
+            _ => None,
+        }
+    }
+}
+
+// Currently our index scan executor only support getting
+// 1 row. and index scan by row.id.
+pub struct IndexScanExecutor {
+    execution_context: Arc<ExecutionContext>,
+    plan_node: IndexScanPlanNode,
+    ended: bool,
+}
+
+impl IndexScanExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: IndexScanPlanNode) -> Self {
+        Self {
+            plan_node,
+            execution_context: ctx,
+            ended: false,
+        }
+    }
+}
+
+impl Executor for IndexScanExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        if self.ended {
+            None
+        } else {
+            let ctx = &self.execution_context;
+            let mut t = ctx.transaction.write();
+            self.ended = true;
+
+            // Get Row ID first, so we could ask for a lock from the lock manager.
+            //
+            // We can only get the row after lock manager grant us the lock.
+            let row_id = ctx.table.get_row_id(self.plan_node.key, &mut t)?;
+            drop(t);
+
+            // Later on, we'll use lock_upgrade to
+            // upgrade our shared lock to exclusive lock
+            // in update/delete exectuor.
+            match ctx.read_row(row_id, self.plan_node.key) {
+                Ok(row) => row.map(|row| (row_id, row)),
+                Err(err) => {
+                    ctx.fail(err);
+                    None
+                }
+            }
+        }
+    }
+}
+
+// Look the ids up in the secondary index first, then read each row like
+// the index scan does, so only the rows with the value are locked.
+pub struct SecondaryIndexScanExecutor {
+    execution_context: Arc<ExecutionContext>,
+    plan_node: SecondaryIndexScanPlanNode,
+    keys: Option<std::vec::IntoIter<u32>>,
+}
+
+impl SecondaryIndexScanExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: SecondaryIndexScanPlanNode) -> Self {
+        Self {
+            plan_node,
+            execution_context: ctx,
+            keys: None,
+        }
+    }
+}
+
+impl Executor for SecondaryIndexScanExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        let ctx = &self.execution_context;
+        if self.keys.is_none() {
+            // The planner only plans it for indexed columns, but the index
+            // might have been dropped along with its table since.
+            let plan_node = &self.plan_node;
+            let keys = match &plan_node.lookup {
+                IndexLookup::Value(value) => ctx.table.index_lookup(&plan_node.column, value),
+                IndexLookup::Range { start, end } => {
+                    ctx.table
+                        .index_range_lookup(&plan_node.column, start, end.as_deref())
+                }
+            };
+            self.keys = Some(keys.unwrap_or_default().into_iter());
+        }
+
+        for key in self.keys.as_mut().unwrap() {
+            let Some(row_id) = ctx.table.get_row_id(key, &mut ctx.transaction.write()) else {
+                continue;
+            };
+            match ctx.read_row(row_id, key) {
+                Ok(Some(row)) => {
+                    let predicate = self.plan_node.predicate.as_ref();
+                    if predicate.is_none_or(|predicate| predicate.matches(&row)) {
+                        return Some((row_id, row));
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    ctx.fail(err);
+                    return None;
+                }
+            }
+        }
+        None
+    }
+}
+
+// Insert the rows of its child within the transaction, returning them
+// with the id of the row they were inserted at. The inserts are in the
+// write set of the transaction, so they're removed if it aborts.
+pub struct InsertExecutor {
+    execution_context: Arc<ExecutionContext>,
+    affected_row: usize,
+    child: Box<dyn Executor>,
+}
+
+impl InsertExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: InsertPlanNode) -> Self {
+        let child = create_executor(ctx.clone(), *plan_node.child);
+
+        Self {
+            execution_context: ctx,
+            affected_row: 0,
+            child,
+        }
+    }
+}
+
+impl Executor for InsertExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        let (_, row) = self.child.next()?;
+        let mut t = self.execution_context.transaction.write();
+        let inserted = self.execution_context.table.insert_in(&row, &mut t);
+        drop(t);
+        match inserted {
+            Ok(rid) => {
+                self.affected_row += 1;
+                Some((rid, row))
+            }
+            Err(err) => {
+                self.execution_context.fail(err);
+                None
+            }
+        }
+    }
+}
+
+pub struct DeleteExecutor {
+    execution_context: Arc<ExecutionContext>,
+    affected_row: usize,
+    child: Box<dyn Executor>,
+}
+
+impl DeleteExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: DeletePlanNode) -> Self {
+        let child = create_executor(ctx.clone(), *plan_node.child);
+
+        Self {
+            execution_context: ctx,
+            affected_row: 0,
+            child,
+        }
+    }
+}
+
+impl Executor for DeleteExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        if let Some((rid, row)) = self.child.next() {
+            let mut t = self.execution_context.transaction.write();
+            let deleted = self.execution_context.table.delete_in(&row, &rid, &mut t);
+            drop(t);
+            if let Err(err) = deleted {
+                self.execution_context.fail(err);
+                return None;
+            }
+            self.affected_row += 1;
+            Some((rid, row))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct UpdateExecutor {
+    execution_context: Arc<ExecutionContext>,
+    plan_node: UpdatePlanNode,
+    columns: Vec<String>,
+    affected_row: usize,
+    child: Box<dyn Executor>,
+}
+
+impl UpdateExecutor {
+    pub fn new(ctx: Arc<ExecutionContext>, plan_node: UpdatePlanNode) -> Self {
+        let child = create_executor(ctx.clone(), *plan_node.child.clone());
+
+        Self {
+            columns: plan_node.columns(),
+            plan_node,
+            execution_context: ctx,
+            affected_row: 0,
+            child,
+        }
+    }
+
+    // Evaluate the assignments against the row. The assignments were
+    // type checked when planned, so every value fits its column.
+    fn new_row(&self, row: &Row) -> Row {
+        let mut username = row.username();
+        let mut email = row.email();
+
+        for assignment in &self.plan_node.assignments {
+            let value = match assignment.expression.evaluate(row) {
+                Some(Value::Text(value)) => value,
+                value => unreachable!("invalid value {value:?} for a text column"),
+            };
+
+            match assignment.column.as_str() {
+                "username" => username = value,
+                "email" => email = value,
+                column => unreachable!("invalid column name: {column}"),
+            }
+        }
+
+        Row::new(&row.id.to_string(), &username, &email).unwrap()
+    }
+}
+
+impl Executor for UpdateExecutor {
+    fn next(&mut self) -> Option<(RowID, Row)> {
+        if let Some((rid, row)) = self.child.next() {
+            let new_row = self.new_row(&row);
+            let mut t = self.execution_context.transaction.write();
+            let updated =
+                self.execution_context
+                    .table
+                    .update(&row, &new_row, &self.columns, &rid, &mut t);
+            drop(t);
+            if let Err(err) = updated {
+                self.execution_context.fail(err);
+                return None;
+            }
+            self.affected_row += 1;
+            Some((rid, row))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        concurrency::{IsolationLevel, TransactionManager, TransactionState},
+        query::expression::{ComparisonOp, Expression},
+        query::query_plan::{SeqScanPlanNode, DEFAULT_MAX_TOP_N_ROWS},
+        schema::Schema,
+    };
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[test]
+    fn execution_engine() {
+        let plan_node = SeqScanPlanNode { predicate: None };
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+
+        let execution_engine = ExecutionEngine::new(ctx);
+        let result = execution_engine
+            .execute(PlanNode::SeqScan(plan_node))
+            .unwrap();
+        assert_eq!(result.len(), 49);
+        let mut id = 1;
+
+        for (_, row) in result {
+            assert_eq!(row.id, id);
+            id += 1;
+        }
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn execution_engine_stream() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
+
+        // Results are pulled from the executor tree lazily, so
+        // we can stop consuming it anytime.
+        let plan_node = PlanNode::Update(
+            UpdatePlanNode::new(
+                PlanNode::SeqScan(SeqScanPlanNode { predicate: None }),
+                vec![("username", Expression::text("updated"))],
+                &Schema::users(),
+            )
+            .unwrap(),
+        );
+        let result: Vec<_> = execution_engine.stream(plan_node).take(5).collect();
+        assert_eq!(result.len(), 5);
+
+        let plan_node = PlanNode::SeqScan(SeqScanPlanNode { predicate: None });
+        let updated = execution_engine
+            .stream(plan_node)
+            .filter(|(_, row)| row.username() == "updated")
+            .count();
+        assert_eq!(updated, 5);
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn index_scan_executor() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
+
+        let plan_node = IndexScanPlanNode { key: 15 };
+        let result = execution_engine
+            .execute(PlanNode::IndexScan(plan_node))
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        let (_, row) = &result[0];
+        assert_eq!(row.id, 15);
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn secondary_index_scan_executor() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        table.create_index("username", false).unwrap();
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
+
+        let plan_node = SecondaryIndexScanPlanNode {
+            column: "username".to_string(),
+            lookup: IndexLookup::Range {
+                start: "user4".to_string(),
+                end: Some("user5".to_string()),
+            },
+            predicate: Some(Expression::like(Expression::column("username"), "user4%")),
+        };
+        let result = execution_engine
+            .execute(PlanNode::SecondaryIndexScan(plan_node))
+            .unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, [4, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49]);
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn seq_scan_executor() {
+        let plan_node = SeqScanPlanNode { predicate: None };
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let mut executor = SequenceScanExecutor::new(ctx, plan_node);
+
+        let mut id = 1;
+        while let Some((_rid, row)) = executor.next() {
+            assert_eq!(row.id, id);
+            id += 1;
+        }
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn seq_scan_executor_with_predicate() {
+        let predicate = Expression::comparison(
+            Expression::column("username"),
+            ComparisonOp::Equal,
+            Expression::text("user2"),
+        );
+        let plan_node = SeqScanPlanNode {
+            predicate: Some(predicate),
+        };
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let mut executor = SequenceScanExecutor::new(ctx, plan_node);
+
+        let (_, row) = executor.next().unwrap();
+        assert_eq!(row.id, 2);
+        assert!(executor.next().is_none());
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn range_scan_executor() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx.clone());
+
+        // The range spans several leaves.
+        let plan_node = RangeScanPlanNode {
+            start: 10,
+            end: Some(30),
+            predicate: None,
+        };
+        let result = execution_engine
+            .execute(PlanNode::RangeScan(plan_node))
+            .unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, (10..30).collect::<Vec<u32>>());
+
+        let plan_node = RangeScanPlanNode {
+            start: 45,
+            end: None,
+            predicate: Some(Expression::like(Expression::column("username"), "user4%")),
+        };
+        let result = execution_engine
+            .execute(PlanNode::RangeScan(plan_node))
+            .unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, vec![45, 46, 47, 48, 49]);
+
+        let plan_node = RangeScanPlanNode {
+            start: 60,
+            end: None,
+            predicate: None,
+        };
+        assert!(execution_engine
+            .execute(PlanNode::RangeScan(plan_node))
+            .unwrap()
+            .is_empty());
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn filter_executor() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
+
+        // Filter over index scan
+        let plan_node = PlanNode::Filter(FilterPlanNode {
+            predicate: Expression::comparison(
+                Expression::column("username"),
+                ComparisonOp::Equal,
+                Expression::text("user15"),
+            ),
+            child: Box::new(PlanNode::IndexScan(IndexScanPlanNode { key: 15 })),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].1.id, 15);
+
+        let plan_node = PlanNode::Filter(FilterPlanNode {
+            predicate: Expression::comparison(
+                Expression::column("username"),
+                ComparisonOp::Equal,
+                Expression::text("user16"),
+            ),
+            child: Box::new(PlanNode::IndexScan(IndexScanPlanNode { key: 15 })),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        assert!(result.is_empty());
+
+        // Filter over seq scan, which get pushed down into the seq scan
+        let plan_node = PlanNode::Filter(FilterPlanNode {
+            predicate: Expression::comparison(
+                Expression::column("id"),
+                ComparisonOp::GreaterThan,
+                Expression::integer(40),
+            ),
+            child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, (41..50).collect::<Vec<u32>>());
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn values_executor() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
+
+        let rows: Vec<Row> = (100..105)
+            .map(|i| Row::from_str(&format!("{i} value{i} value{i}@email.com")).unwrap())
+            .collect();
+        let values_plan_node = PlanNode::Values(ValuesPlanNode { rows: rows.clone() });
+        let result = execution_engine.execute(values_plan_node.clone()).unwrap();
+        assert_eq!(result.len(), 5);
+        for (i, (rid, row)) in result.into_iter().enumerate() {
+            assert!(rid.is_detached());
+            assert_eq!(row, rows[i]);
+        }
+
+        // Values can be used to test other operators in isolation.
+        let plan_node = PlanNode::Filter(FilterPlanNode {
+            predicate: Expression::comparison(
+                Expression::column("id"),
+                ComparisonOp::GreaterThanOrEqual,
+                Expression::integer(103),
+            ),
+            child: Box::new(values_plan_node),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, vec![103, 104]);
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn top_n_executor() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
+
+        // Top 5 by id descending over the whole table
+        let plan_node = PlanNode::TopN(TopNPlanNode {
+            order_by: vec![OrderBy::desc(Expression::column("id"))],
+            limit: 5,
+            child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, vec![49, 48, 47, 46, 45]);
+
+        // Order by text, where "user1" < "user10" < "user11" ...
+        let plan_node = PlanNode::TopN(TopNPlanNode {
+            order_by: vec![OrderBy::asc(Expression::column("username"))],
+            limit: 3,
+            child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, vec![1, 10, 11]);
+
+        // Limit larger than the input return everything in order
+        let plan_node = PlanNode::TopN(TopNPlanNode {
+            order_by: vec![OrderBy::desc(Expression::column("id"))],
+            limit: 100,
+            child: Box::new(PlanNode::Filter(FilterPlanNode {
+                predicate: Expression::comparison(
+                    Expression::column("id"),
+                    ComparisonOp::LessThan,
+                    Expression::integer(4),
+                ),
+                child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+            })),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, vec![3, 2, 1]);
+
+        let plan_node = PlanNode::TopN(TopNPlanNode {
+            order_by: vec![OrderBy::asc(Expression::column("id"))],
+            limit: 0,
+            child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+        });
+        assert!(execution_engine.execute(plan_node).unwrap().is_empty());
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn top_n_executor_is_stable() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
+
+        let rows: Vec<Row> = [(1, "b"), (2, "a"), (3, "b"), (4, "a"), (5, "c")]
+            .iter()
+            .map(|(id, name)| Row::from_str(&format!("{id} {name} {name}@email.com")).unwrap())
+            .collect();
+        let plan_node = PlanNode::TopN(TopNPlanNode {
+            order_by: vec![OrderBy::asc(Expression::column("username"))],
+            limit: 3,
+            child: Box::new(PlanNode::Values(ValuesPlanNode { rows })),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, vec![2, 4, 1]);
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn sort_executor() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
+
+        let plan_node = PlanNode::Sort(SortPlanNode {
+            order_by: vec![OrderBy::desc(Expression::column("id"))],
+            child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, (1..50).rev().collect::<Vec<u32>>());
+
+        // Enough rows for several sorted runs to be merged, which keep
+        // rows with the same key in the order of the child.
+        let rows: Vec<Row> = (1..=SORT_RUN_ROWS as u32 * 2 + 10)
+            .map(|id| {
+                let name = format!("user{}", id % 7);
+                Row::from_str(&format!("{id} {name} {name}@email.com")).unwrap()
+            })
+            .collect();
+        let mut expected = rows.clone();
+        expected.sort_by_key(|row| Reverse(row.username()));
+
+        let plan_node = PlanNode::Sort(SortPlanNode {
+            order_by: vec![OrderBy::desc(Expression::column("username"))],
+            child: Box::new(PlanNode::Values(ValuesPlanNode { rows })),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, expected.iter().map(|row| row.id).collect::<Vec<u32>>());
+
+        cleanup_table();
     }
-}
 
-impl Executor for DeleteExecutor {
-    fn next(&mut self) -> Option<(RowID, Row)> {
-        if self.iter.is_none() {
-            self.iter = Some(SequenceScanExecutor::new(
-                self.execution_context.clone(),
-                self.plan_node.child.clone(),
-            ));
-        }
+    #[test]
+    fn limit_executor() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
 
-        let executor = self.iter.as_mut().unwrap();
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
+        let ids = |plan_node| -> Vec<u32> {
+            let result = execution_engine.execute(plan_node).unwrap();
+            result.iter().map(|(_, row)| row.id).collect()
+        };
+        let seq_scan = || PlanNode::SeqScan(SeqScanPlanNode { predicate: None });
+
+        assert_eq!(
+            ids(LimitPlanNode::plan(
+                seq_scan(),
+                3,
+                0,
+                DEFAULT_MAX_TOP_N_ROWS
+            )),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            ids(LimitPlanNode::plan(
+                seq_scan(),
+                3,
+                45,
+                DEFAULT_MAX_TOP_N_ROWS
+            )),
+            vec![46, 47, 48]
+        );
+        assert_eq!(
+            ids(LimitPlanNode::plan(
+                seq_scan(),
+                3,
+                47,
+                DEFAULT_MAX_TOP_N_ROWS
+            )),
+            vec![48, 49]
+        );
+        assert_eq!(
+            ids(LimitPlanNode::plan(
+                seq_scan(),
+                3,
+                100,
+                DEFAULT_MAX_TOP_N_ROWS
+            )),
+            vec![]
+        );
+        assert_eq!(
+            ids(LimitPlanNode::plan(
+                seq_scan(),
+                0,
+                0,
+                DEFAULT_MAX_TOP_N_ROWS
+            )),
+            vec![]
+        );
+
+        let sort = SortPlanNode::plan(seq_scan(), vec![OrderBy::desc(Expression::column("id"))]);
+        assert_eq!(
+            ids(LimitPlanNode::plan(
+                sort.clone(),
+                2,
+                1,
+                DEFAULT_MAX_TOP_N_ROWS
+            )),
+            vec![48, 47]
+        );
+        assert_eq!(ids(LimitPlanNode::plan(sort, 2, 1, 2)), vec![48, 47]);
 
-        if let Some((rid, row)) = executor.next() {
-            let mut t = self.execution_context.transaction.write();
-            self.execution_context.table.delete(&row, &rid, &mut t);
-            drop(t);
-            self.affected_row += 1;
-            Some((rid, row))
-        } else {
-            None
-        }
+        cleanup_table();
     }
-}
 
-pub struct UpdateExecutor {
-    execution_context: Arc<ExecutionContext>,
-    plan_node: UpdatePlanNode,
-    affected_row: usize,
-    iter: Option<Box<dyn Executor>>,
-}
+    #[test]
+    fn merge_join_executor() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
 
-impl UpdateExecutor {
-    pub fn new(ctx: Arc<ExecutionContext>, plan_node: UpdatePlanNode) -> Self {
-        Self {
-            plan_node,
-            execution_context: ctx,
-            affected_row: 0,
-            iter: None,
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx.clone());
+
+        // Table has id 1 to 49, join it with sorted values that have
+        // duplicate keys and keys that don't exist in the table.
+        let rows: Vec<Row> = [0, 3, 3, 5, 30, 60]
+            .iter()
+            .map(|id| Row::from_str(&format!("{id} value{id} value{id}@email.com")).unwrap())
+            .collect();
+        let plan_node = MergeJoinPlanNode {
+            left_key: Expression::column("id"),
+            right_key: Expression::column("id"),
+            left: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+            right: Box::new(PlanNode::Values(ValuesPlanNode { rows })),
+            table: None,
+        };
+
+        let mut executor = MergeJoinExecutor::new(ctx, plan_node.clone());
+        let mut pairs = Vec::new();
+        while let Some(((_, left), (_, right))) = executor.next_pair() {
+            assert_eq!(left.id, right.id);
+            pairs.push((left.username(), right.username()));
         }
+        assert_eq!(
+            pairs,
+            vec![
+                ("user3".to_string(), "value3".to_string()),
+                ("user3".to_string(), "value3".to_string()),
+                ("user5".to_string(), "value5".to_string()),
+                ("user30".to_string(), "value30".to_string()),
+            ]
+        );
+
+        // Join a scan with itself, with a filter on one side
+        let plan_node = PlanNode::merge_join(
+            PlanNode::SeqScan(SeqScanPlanNode { predicate: None }),
+            PlanNode::Filter(FilterPlanNode {
+                predicate: Expression::comparison(
+                    Expression::column("id"),
+                    ComparisonOp::GreaterThan,
+                    Expression::integer(45),
+                ),
+                child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+            }),
+            Expression::column("id"),
+            Expression::column("id"),
+            None,
+        )
+        .unwrap();
+        let result_set = execution_engine.query(plan_node.clone()).unwrap();
+        let ids: Vec<(u32, u32)> = result_set
+            .rows()
+            .iter()
+            .map(|rows| (rows[0].id, rows[1].id))
+            .collect();
+        assert_eq!(ids, vec![(46, 46), (47, 47), (48, 48), (49, 49)]);
+
+        // Only the rows of both sides together are returned.
+        assert!(matches!(
+            execution_engine.execute(plan_node),
+            Err(DbError::InvalidInput(_))
+        ));
+
+        cleanup_table();
     }
-}
 
-impl Executor for UpdateExecutor {
-    fn next(&mut self) -> Option<(RowID, Row)> {
-        if self.iter.is_none() {
-            match self.plan_node.child.as_ref() {
-                PlanNode::IndexScan(plan_node) => {
-                    self.iter = Some(Box::new(IndexScanExecutor::new(
-                        self.execution_context.clone(),
-                        plan_node.clone(),
-                    )));
-                }
-                PlanNode::SeqScan(plan_node) => {
-                    self.iter = Some(Box::new(SequenceScanExecutor::new(
-                        self.execution_context.clone(),
-                        plan_node.clone(),
-                    )));
-                }
-                _ => panic!("unsupported plan node for child"),
-            }
+    #[test]
+    fn merge_join_executor_across_tables() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = Arc::new(setup_table(&tm, lm.clone()));
+        table.create_table("orders", &Schema::users()).unwrap();
+        let orders = table.open_table("orders").unwrap();
+        for id in [2, 3, 60] {
+            let row = Row::from_str(&format!("{id} order{id} order{id}@email.com")).unwrap();
+            orders.insert(&row);
         }
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
 
-        let executor = self.iter.as_mut().unwrap();
+        let ctx = Arc::new(ExecutionContext::new(table, lm.clone(), transaction));
+        let execution_engine = ExecutionEngine::new(ctx);
 
-        if let Some((rid, row)) = executor.next() {
-            let mut t = self.execution_context.transaction.write();
-            self.execution_context.table.update(
-                &row,
-                &self.plan_node.new_row,
-                &self.plan_node.columns,
-                &rid,
-                &mut t,
-            );
-            drop(t);
-            self.affected_row += 1;
-            Some((rid, row))
-        } else {
-            None
-        }
-    }
-}
+        let plan_node = PlanNode::merge_join(
+            PlanNode::SeqScan(SeqScanPlanNode { predicate: None }),
+            PlanNode::SeqScan(SeqScanPlanNode { predicate: None }),
+            Expression::column("id"),
+            Expression::column("id"),
+            Some("orders".to_string()),
+        )
+        .unwrap();
+        let result_set = execution_engine.query(plan_node).unwrap();
+        assert_eq!(
+            result_set.to_string(),
+            "(2, user2, user2@email.com, 2, order2, order2@email.com)\n\
+             (3, user3, user3@email.com, 3, order3, order3@email.com)\n"
+        );
+        assert_eq!(result_set.columns()[3].name, "orders.id");
+
+        let plan_node = PlanNode::merge_join(
+            PlanNode::SeqScan(SeqScanPlanNode { predicate: None }),
+            PlanNode::SeqScan(SeqScanPlanNode { predicate: None }),
+            Expression::column("id"),
+            Expression::column("id"),
+            Some("items".to_string()),
+        )
+        .unwrap();
+        assert!(matches!(
+            execution_engine.query(plan_node),
+            Err(DbError::Catalog(_))
+        ));
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::{
-        concurrency::{IsolationLevel, TransactionManager},
-        query::query_plan::SeqScanPlanNode,
-    };
-    use std::str::FromStr;
+        cleanup_table();
+    }
 
     #[test]
-    fn execution_engine() {
-        let plan_node = SeqScanPlanNode {
-            predicate: "".to_string(),
-        };
+    fn merge_join_executor_with_duplicates_on_both_sides() {
         let lm = Arc::new(LockManager::new());
         let tm = TransactionManager::new(lm.clone());
         let table = setup_table(&tm, lm.clone());
         let transaction = tm.begin(IsolationLevel::ReadCommited);
 
-        let ctx = Arc::new(ExecutionContext {
-            table: Arc::new(table),
-            lock_manager: lm.clone(),
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
             transaction,
-        });
-
-        let execution_engine = ExecutionEngine::new(ctx);
-        let result = execution_engine.execute(PlanNode::SeqScan(plan_node));
-        assert_eq!(result.len(), 49);
-        let mut id = 1;
+        ));
+
+        let values = |ids: &[u32]| {
+            let rows = ids
+                .iter()
+                .map(|id| Row::from_str(&format!("{id} value{id} value{id}@email.com")).unwrap())
+                .collect();
+            Box::new(PlanNode::Values(ValuesPlanNode { rows }))
+        };
+        let plan_node = MergeJoinPlanNode {
+            left_key: Expression::column("id"),
+            right_key: Expression::column("id"),
+            left: values(&[1, 2, 2, 4]),
+            right: values(&[2, 2, 3, 4, 4]),
+            table: None,
+        };
 
-        for (_, row) in result {
-            assert_eq!(row.id, id);
-            id += 1;
+        let mut executor = MergeJoinExecutor::new(ctx, plan_node);
+        let mut ids = Vec::new();
+        while let Some(((_, left), (_, right))) = executor.next_pair() {
+            assert_eq!(left.id, right.id);
+            ids.push(left.id);
         }
+        assert_eq!(ids, vec![2, 2, 2, 2, 4, 4]);
 
         cleanup_table();
     }
 
     #[test]
-    fn index_scan_executor() {
+    fn semi_join_executor_with_in_subquery() {
         let lm = Arc::new(LockManager::new());
         let tm = TransactionManager::new(lm.clone());
         let table = setup_table(&tm, lm.clone());
         let transaction = tm.begin(IsolationLevel::ReadCommited);
 
-        let ctx = Arc::new(ExecutionContext {
-            table: Arc::new(table),
-            lock_manager: lm.clone(),
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
             transaction,
-        });
+        ));
         let execution_engine = ExecutionEngine::new(ctx);
 
-        let plan_node = IndexScanPlanNode { key: 15 };
-        let result = execution_engine.execute(PlanNode::IndexScan(plan_node));
-        assert_eq!(result.len(), 1);
-        let (_, row) = &result[0];
-        assert_eq!(row.id, 15);
+        // select * where id in (select id where username = 'user7' or ...)
+        let rows: Vec<Row> = [7, 20, 20, 99]
+            .iter()
+            .map(|id| Row::from_str(&format!("{id} value{id} value{id}@email.com")).unwrap())
+            .collect();
+        let plan_node = PlanNode::SemiJoin(SemiJoinPlanNode {
+            predicate: SubqueryPredicate::In {
+                expression: Expression::column("id"),
+                column: Expression::column("id"),
+            },
+            child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+            subquery: Box::new(PlanNode::Values(ValuesPlanNode { rows })),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, vec![7, 20]);
+
+        // select * where username in (select username where id > 47)
+        let plan_node = PlanNode::SemiJoin(SemiJoinPlanNode {
+            predicate: SubqueryPredicate::In {
+                expression: Expression::column("username"),
+                column: Expression::column("username"),
+            },
+            child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+            subquery: Box::new(PlanNode::Filter(FilterPlanNode {
+                predicate: Expression::comparison(
+                    Expression::column("id"),
+                    ComparisonOp::GreaterThan,
+                    Expression::integer(47),
+                ),
+                child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+            })),
+        });
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, vec![48, 49]);
 
         cleanup_table();
     }
 
     #[test]
-    fn seq_scan_executor() {
-        // Okay, this is just sample, we would need to implement
-        // expression evaluation for it to work.
-        let predicate = "name = 'user2'".to_string();
-        let plan_node = SeqScanPlanNode { predicate };
+    fn semi_join_executor_with_exists_subquery() {
         let lm = Arc::new(LockManager::new());
         let tm = TransactionManager::new(lm.clone());
         let table = setup_table(&tm, lm.clone());
         let transaction = tm.begin(IsolationLevel::ReadCommited);
 
-        let ctx = Arc::new(ExecutionContext {
-            table: Arc::new(table),
-            lock_manager: lm.clone(),
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
             transaction,
-        });
-        let mut executor = SequenceScanExecutor::new(ctx, plan_node);
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
 
-        let mut id = 1;
-        while let Some((_rid, row)) = executor.next() {
-            assert_eq!(row.id, id);
-            id += 1;
-        }
+        let exists = |subquery| {
+            PlanNode::SemiJoin(SemiJoinPlanNode {
+                predicate: SubqueryPredicate::Exists,
+                child: Box::new(PlanNode::IndexScan(IndexScanPlanNode { key: 1 })),
+                subquery: Box::new(subquery),
+            })
+        };
+        let subquery = |id| {
+            PlanNode::SeqScan(SeqScanPlanNode {
+                predicate: Some(Expression::comparison(
+                    Expression::column("id"),
+                    ComparisonOp::Equal,
+                    Expression::integer(id),
+                )),
+            })
+        };
+
+        let result = execution_engine.execute(exists(subquery(10))).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].1.id, 1);
+
+        let result = execution_engine.execute(exists(subquery(100))).unwrap();
+        assert!(result.is_empty());
 
         cleanup_table();
     }
 
     #[test]
     fn delete_executor_with_seq_scan() {
-        let predicate = "".to_string();
-        let seq_plan_node = SeqScanPlanNode {
-            predicate: predicate.clone(),
-        };
+        let seq_plan_node = SeqScanPlanNode { predicate: None };
         let lm = Arc::new(LockManager::new());
         let tm = TransactionManager::new(lm.clone());
         let table = setup_table(&tm, lm.clone());
         let transaction = tm.begin(IsolationLevel::ReadCommited);
 
-        let ctx = Arc::new(ExecutionContext {
-            table: Arc::new(table),
-            lock_manager: lm.clone(),
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
             transaction,
-        });
+        ));
 
         let plan_node = DeletePlanNode {
-            child: seq_plan_node,
+            child: Box::new(PlanNode::SeqScan(seq_plan_node)),
         };
         let mut executor = DeleteExecutor::new(ctx.clone(), plan_node);
 
@@ -371,10 +1912,10 @@ mod test {
         assert_eq!(count, 49);
 
         let mut t = ctx.transaction.write();
-        tm.commit(&ctx.table, &mut t);
+        tm.commit(&ctx.table, &mut t).unwrap();
         drop(t);
 
-        let seq_plan_node = SeqScanPlanNode { predicate };
+        let seq_plan_node = SeqScanPlanNode { predicate: None };
         let mut executor = SequenceScanExecutor::new(ctx, seq_plan_node);
         assert!(executor.next().is_none());
 
@@ -382,29 +1923,248 @@ mod test {
     }
 
     #[test]
-    fn update_executor_with_seq_scan() {
-        let predicate = "".to_string();
-        let seq_plan_node = SeqScanPlanNode {
-            predicate: predicate.clone(),
+    fn delete_executor_too_large_transaction() {
+        let lm = Arc::new(LockManager::new());
+        let table = Arc::new(setup_table(
+            &TransactionManager::new(lm.clone()),
+            lm.clone(),
+        ));
+        let tm = TransactionManager::new(lm.clone()).with_max_write_set(10);
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            table.clone(),
+            lm.clone(),
+            transaction.clone(),
+        ));
+        let execution_engine = ExecutionEngine::new(ctx.clone());
+        let plan_node = DeletePlanNode {
+            child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
         };
+        assert_eq!(
+            execution_engine.execute(PlanNode::Delete(plan_node)),
+            Err(DbError::TooLargeTransaction(10))
+        );
+
+        // The transaction is aborted, so its deletes are rolled back.
+        let mut t = transaction.write();
+        assert_eq!(t.state, TransactionState::Aborted);
+        tm.abort(&table, &mut t);
+        drop(t);
+
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let ctx = Arc::new(ExecutionContext::new(table, lm, transaction));
+        let seq_scan = PlanNode::SeqScan(SeqScanPlanNode { predicate: None });
+        assert_eq!(
+            ExecutionEngine::new(ctx).execute(seq_scan).unwrap().len(),
+            49
+        );
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn delete_executor_with_index_scan() {
         let lm = Arc::new(LockManager::new());
         let tm = TransactionManager::new(lm.clone());
         let table = setup_table(&tm, lm.clone());
         let transaction = tm.begin(IsolationLevel::ReadCommited);
 
-        let ctx = Arc::new(ExecutionContext {
-            table: Arc::new(table),
-            lock_manager: lm.clone(),
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
             transaction,
-        });
+        ));
+        let execution_engine = ExecutionEngine::new(ctx.clone());
 
-        let new_row = Row::new("0", "user1", "email").unwrap();
-        let columns = vec!["username".to_string()];
-        let plan_node = UpdatePlanNode {
-            child: Box::new(PlanNode::SeqScan(seq_plan_node)),
-            new_row,
-            columns,
+        let plan_node = DeletePlanNode {
+            child: Box::new(PlanNode::scan(Some(Expression::comparison(
+                Expression::column("id"),
+                ComparisonOp::Equal,
+                Expression::integer(15),
+            )))),
+        };
+        assert!(matches!(*plan_node.child, PlanNode::IndexScan(_)));
+
+        let result = execution_engine
+            .execute(PlanNode::Delete(plan_node))
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].1.id, 15);
+
+        let mut t = ctx.transaction.write();
+        tm.commit(&ctx.table, &mut t).unwrap();
+        drop(t);
+
+        let result = execution_engine
+            .execute(PlanNode::SeqScan(SeqScanPlanNode { predicate: None }))
+            .unwrap();
+        assert_eq!(result.len(), 48);
+        assert!(result.iter().all(|(_, row)| row.id != 15));
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn insert_executor() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = Arc::new(setup_table(&tm, lm.clone()));
+        let rows = |ids: &[u32]| -> Vec<Row> {
+            ids.iter()
+                .map(|i| Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap())
+                .collect()
         };
+
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let ctx = Arc::new(ExecutionContext::new(
+            table.clone(),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx.clone());
+        let plan_node = PlanNode::Insert(InsertPlanNode::values(rows(&[50, 51])));
+        assert_eq!(plan_node.to_string(), "Insert\n  Values rows=2");
+        let result = execution_engine.execute(plan_node).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1].1.id, 51);
+        let mut t = ctx.transaction.write();
+        assert_eq!(table.get(result[1].0, &mut t).unwrap().id, 51);
+        tm.commit(&table, &mut t).unwrap();
+        drop(t);
+
+        // The rows inserted before a duplicate key stay in the write set,
+        // and are removed when the transaction aborts.
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let ctx = Arc::new(ExecutionContext::new(
+            table.clone(),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx.clone());
+        let plan_node = PlanNode::Insert(InsertPlanNode::values(rows(&[52, 1, 53])));
+        assert_eq!(
+            execution_engine.execute(plan_node),
+            Err(DbError::DuplicateKey)
+        );
+        let mut t = ctx.transaction.write();
+        tm.abort(&table, &mut t);
+        drop(t);
+
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let ctx = Arc::new(ExecutionContext::new(table, lm, transaction));
+        let result = ExecutionEngine::new(ctx)
+            .execute(PlanNode::SeqScan(SeqScanPlanNode { predicate: None }))
+            .unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, (1..52).collect::<Vec<_>>());
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn insert_executor_with_unique_index() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = Arc::new(setup_table(&tm, lm.clone()));
+        assert_eq!(
+            table.create_index("username", true),
+            Ok("created unique index on 'users.username'".to_string())
+        );
+
+        // A row taking the username of another one aborts the transaction,
+        // and the rows inserted before it are rolled back with their index
+        // entries.
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let ctx = Arc::new(ExecutionContext::new(
+            table.clone(),
+            lm.clone(),
+            transaction,
+        ));
+        let rows = vec![
+            Row::from_str("50 john john@email.com").unwrap(),
+            Row::from_str("51 user7 other@email.com").unwrap(),
+            Row::from_str("52 jane jane@email.com").unwrap(),
+        ];
+        let plan_node = PlanNode::Insert(InsertPlanNode::values(rows));
+        assert_eq!(
+            ExecutionEngine::new(ctx.clone()).execute(plan_node),
+            Err(DbError::UniqueViolation(
+                "duplicate value 'user7' in unique index on 'username'".to_string()
+            ))
+        );
+        let mut t = ctx.transaction.write();
+        assert_eq!(t.state, TransactionState::Aborted);
+        assert_eq!(table.index_lookup("username", "john"), Some(vec![50]));
+        tm.abort(&table, &mut t);
+        drop(t);
+        assert_eq!(table.index_lookup("username", "john"), Some(vec![]));
+        assert_eq!(table.index_lookup("username", "user7"), Some(vec![7]));
+        assert_eq!(table.index_lookup("username", "jane"), Some(vec![]));
+
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let ctx = Arc::new(ExecutionContext::new(table.clone(), lm, transaction));
+        let result = ExecutionEngine::new(ctx)
+            .execute(PlanNode::SeqScan(SeqScanPlanNode { predicate: None }))
+            .unwrap();
+        assert_eq!(result.len(), 49);
+
+        // The username is free to take once its row changes it, but not by
+        // an update either until then.
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        let columns = vec!["username".to_string()];
+        let row = Row::from_str("8 user8 user8@email.com").unwrap();
+        let rid = table.get_row_id(8, &mut t).unwrap();
+        let new_row = Row::from_str("8 user7 user8@email.com").unwrap();
+        assert!(table
+            .update(&row, &new_row, &columns, &rid, &mut t)
+            .is_err());
+        assert_eq!(t.state, TransactionState::Aborted);
+        tm.abort(&table, &mut t);
+        drop(t);
+        assert_eq!(table.index_lookup("username", "user8"), Some(vec![8]));
+
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        let row = Row::from_str("7 user7 user7@email.com").unwrap();
+        let rid = table.get_row_id(7, &mut t).unwrap();
+        let new_row = Row::from_str("7 john user7@email.com").unwrap();
+        assert_eq!(
+            table.update(&row, &new_row, &columns, &rid, &mut t),
+            Ok(true)
+        );
+        tm.commit(&table, &mut t).unwrap();
+        drop(t);
+        assert!(table
+            .insert_row(&Row::from_str("51 user7 other@email.com").unwrap())
+            .is_ok());
+
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+        cleanup_table();
+    }
+
+    #[test]
+    fn update_executor_with_seq_scan() {
+        let seq_plan_node = SeqScanPlanNode { predicate: None };
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+
+        let plan_node = UpdatePlanNode::new(
+            PlanNode::SeqScan(seq_plan_node),
+            vec![("username", Expression::text("user1"))],
+            &Schema::users(),
+        )
+        .unwrap();
         let mut executor = UpdateExecutor::new(ctx.clone(), plan_node);
 
         let mut count = 0;
@@ -414,10 +2174,10 @@ mod test {
         assert_eq!(count, 49);
 
         let mut t = ctx.transaction.write();
-        tm.commit(&ctx.table, &mut t);
+        tm.commit(&ctx.table, &mut t).unwrap();
         drop(t);
 
-        let seq_plan_node = SeqScanPlanNode { predicate };
+        let seq_plan_node = SeqScanPlanNode { predicate: None };
         let mut executor = SequenceScanExecutor::new(ctx, seq_plan_node);
         while let Some((_, row)) = executor.next() {
             assert_eq!(row.username(), "user1");
@@ -434,28 +2194,33 @@ mod test {
         let table = setup_table(&tm, lm.clone());
         let transaction = tm.begin(IsolationLevel::ReadCommited);
 
-        let ctx = Arc::new(ExecutionContext {
-            table: Arc::new(table),
-            lock_manager: lm.clone(),
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
             transaction,
-        });
+        ));
         let execution_engine = ExecutionEngine::new(ctx);
 
         let child_plan_node = IndexScanPlanNode { key: 15 };
-        let update_plan_node = UpdatePlanNode {
-            child: Box::new(PlanNode::IndexScan(child_plan_node.clone())),
-            columns: vec!["email".to_string()],
-            new_row: Row::new("0", "0", "new@email.com").unwrap(),
-        };
-
-        let result = execution_engine.execute(PlanNode::Update(update_plan_node));
+        let update_plan_node = UpdatePlanNode::new(
+            PlanNode::IndexScan(child_plan_node.clone()),
+            vec![("email", Expression::text("new@email.com"))],
+            &Schema::users(),
+        )
+        .unwrap();
+
+        let result = execution_engine
+            .execute(PlanNode::Update(update_plan_node))
+            .unwrap();
         assert_eq!(result.len(), 1);
         let (_, row) = &result[0];
         assert_eq!(row.id, 15);
         // We can't assert email here since, our current implementation doesn't return
         // the updated row.
 
-        let result = execution_engine.execute(PlanNode::IndexScan(child_plan_node));
+        let result = execution_engine
+            .execute(PlanNode::IndexScan(child_plan_node))
+            .unwrap();
         assert_eq!(result.len(), 1);
         let (_, row) = &result[0];
         assert_eq!(row.id, 15);
@@ -464,20 +2229,178 @@ mod test {
         cleanup_table();
     }
 
+    #[test]
+    fn update_executor_evaluate_assignments_per_row() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_table(&tm, lm.clone());
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            Arc::new(table),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
+
+        // Both assignments see the row before the update.
+        let seq_scan = || PlanNode::SeqScan(SeqScanPlanNode { predicate: None });
+        let plan_node = UpdatePlanNode::parse(
+            seq_scan(),
+            "email = username, username = 'it''s me'",
+            &Schema::users(),
+        )
+        .unwrap();
+        assert_eq!(
+            execution_engine
+                .execute(PlanNode::Update(plan_node))
+                .unwrap()
+                .len(),
+            49
+        );
+
+        for (_, row) in execution_engine.execute(seq_scan()).unwrap() {
+            assert_eq!(row.username(), "it's me");
+            assert_eq!(row.email(), format!("user{}", row.id));
+        }
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn update_executor_lock_timeout() {
+        let lm = Arc::new(LockManager::with_timeout(Duration::from_millis(20)));
+        let tm = TransactionManager::new(lm.clone());
+        let table = Arc::new(setup_table(&tm, lm.clone()));
+        let index_scan = || PlanNode::IndexScan(IndexScanPlanNode { key: 5 });
+
+        let reader = ExecutionEngine::new(Arc::new(ExecutionContext::new(
+            table.clone(),
+            lm.clone(),
+            tm.begin(IsolationLevel::RepeatableRead),
+        )));
+        assert_eq!(reader.execute(index_scan()).unwrap().len(), 1);
+
+        // The shared lock of the reader is only released once it commits,
+        // so the update can't upgrade its own shared lock in time.
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let ctx = Arc::new(ExecutionContext::new(
+            table.clone(),
+            lm.clone(),
+            transaction.clone(),
+        ));
+        let writer = ExecutionEngine::new(ctx);
+        let plan_node = UpdatePlanNode::new(
+            index_scan(),
+            vec![("username", Expression::text("updated"))],
+            &Schema::users(),
+        )
+        .unwrap();
+        assert_eq!(
+            writer.execute(PlanNode::Update(plan_node)),
+            Err(DbError::LockTimeout)
+        );
+
+        tm.abort(&table, &mut transaction.write());
+        let (_, row) = &reader.execute(index_scan()).unwrap()[0];
+        assert_eq!(row.username(), "user5");
+
+        cleanup_table();
+    }
+
+    #[test]
+    fn paginate_executor_resume_after_last_key() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = Arc::new(setup_table(&tm, lm.clone()));
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+
+        let ctx = Arc::new(ExecutionContext::new(
+            table.clone(),
+            lm.clone(),
+            transaction,
+        ));
+        let execution_engine = ExecutionEngine::new(ctx);
+        let seq_scan = || PlanNode::SeqScan(SeqScanPlanNode { predicate: None });
+
+        let plan_node = PlanNode::paginate(seq_scan(), None, 10).unwrap();
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, (1..=10).collect::<Vec<_>>());
+
+        // Rows before the cursor are deleted and inserted by another
+        // transaction, which would shift a position based cursor.
+        for i in 1..5 {
+            table.apply_delete(i);
+        }
+        let other = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = other.write();
+        for i in [2, 100, 101] {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            table.insert_in(&row, &mut t).unwrap();
+        }
+        tm.commit(&table, &mut t).unwrap();
+        drop(t);
+
+        let after = result.last().map(|(_, row)| row.id);
+        let plan_node = PlanNode::paginate(seq_scan(), after, 10).unwrap();
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, (11..=20).collect::<Vec<_>>());
+
+        let plan_node = PlanNode::paginate(seq_scan(), Some(45), 10).unwrap();
+        let result = execution_engine.execute(plan_node).unwrap();
+        let ids: Vec<u32> = result.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, vec![46, 47, 48, 49, 100, 101]);
+
+        // Rows that are not ordered by id can't be paginated by key.
+        let plan_node = PlanNode::TopN(TopNPlanNode {
+            order_by: vec![OrderBy::desc(Expression::column("id"))],
+            limit: 5,
+            child: Box::new(seq_scan()),
+        });
+        assert!(PlanNode::paginate(plan_node, None, 10).is_err());
+
+        // The executor itself never returns a row at or before the cursor.
+        let rows: Vec<Row> = [3, 1, 4, 1, 5, 9, 2, 6]
+            .iter()
+            .map(|id| Row::from_str(&format!("{id} user{id} user{id}@email.com")).unwrap())
+            .collect();
+        let mut executor = PaginateExecutor::new(
+            execution_engine.execution_context.clone(),
+            PaginatePlanNode {
+                after: Some(1),
+                page_size: 3,
+                child: Box::new(PlanNode::Values(ValuesPlanNode { rows })),
+            },
+        );
+        let mut ids = Vec::new();
+        while let Some((_, row)) = executor.next() {
+            ids.push(row.id);
+        }
+        assert_eq!(ids, vec![3, 4, 5]);
+
+        cleanup_table();
+    }
+
     fn setup_table(tm: &TransactionManager, lm: Arc<LockManager>) -> Table {
-        let table = Table::new(format!("test-{:?}.db", std::thread::current().id()), 4, lm);
+        let table =
+            Table::with_lock_manager(format!("test-{:?}.db", std::thread::current().id()), 4, lm);
         let transaction = tm.begin(IsolationLevel::ReadCommited);
         let mut t = transaction.write();
         for i in 1..50 {
             let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
-            table.insert(&row, &mut t);
+            table.insert_in(&row, &mut t).unwrap();
         }
-        tm.commit(&table, &mut t);
+        tm.commit(&table, &mut t).unwrap();
 
         table
     }
 
     fn cleanup_table() {
-        let _ = std::fs::remove_file(format!("test-{:?}.db", std::thread::current().id()));
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+        let _ = std::fs::remove_file(format!("{path}.hot"));
     }
 }
@@ -0,0 +1,398 @@
+use crate::row::Row;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Value {
+    Integer(i64),
+    Text(String),
+}
+
+impl Value {
+    // Values of different type can't be compared, e.g. comparing
+    // an id to a string will always be false.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+            (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ComparisonOp {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+impl ComparisonOp {
+    /// Return the operator to use when swapping the operands,
+    /// e.g. `5 < id` is the same as `id > 5`.
+    pub fn flip(&self) -> Self {
+        match self {
+            ComparisonOp::LessThan => ComparisonOp::GreaterThan,
+            ComparisonOp::LessThanOrEqual => ComparisonOp::GreaterThanOrEqual,
+            ComparisonOp::GreaterThan => ComparisonOp::LessThan,
+            ComparisonOp::GreaterThanOrEqual => ComparisonOp::LessThanOrEqual,
+            op => *op,
+        }
+    }
+
+    fn matches(&self, ordering: Ordering) -> bool {
+        match self {
+            ComparisonOp::Equal => ordering == Ordering::Equal,
+            ComparisonOp::NotEqual => ordering != Ordering::Equal,
+            ComparisonOp::LessThan => ordering == Ordering::Less,
+            ComparisonOp::LessThanOrEqual => ordering != Ordering::Greater,
+            ComparisonOp::GreaterThan => ordering == Ordering::Greater,
+            ComparisonOp::GreaterThanOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Expression {
+    Column(String),
+    Constant(Value),
+    Comparison {
+        left: Box<Expression>,
+        op: ComparisonOp,
+        right: Box<Expression>,
+    },
+    // `%` matches any sequence of characters, and `_` matches
+    // exactly one character.
+    Like {
+        expression: Box<Expression>,
+        pattern: String,
+    },
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    pub fn column(name: &str) -> Self {
+        Expression::Column(name.to_string())
+    }
+
+    pub fn integer(value: i64) -> Self {
+        Expression::Constant(Value::Integer(value))
+    }
+
+    pub fn text(value: &str) -> Self {
+        Expression::Constant(Value::Text(value.to_string()))
+    }
+
+    pub fn comparison(left: Expression, op: ComparisonOp, right: Expression) -> Self {
+        Expression::Comparison {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        }
+    }
+
+    pub fn like(expression: Expression, pattern: &str) -> Self {
+        Expression::Like {
+            expression: Box::new(expression),
+            pattern: pattern.to_string(),
+        }
+    }
+
+    pub fn and(left: Expression, right: Expression) -> Self {
+        Expression::And(Box::new(left), Box::new(right))
+    }
+
+    pub fn or(left: Expression, right: Expression) -> Self {
+        Expression::Or(Box::new(left), Box::new(right))
+    }
+
+    /// Return the bounds of the literal prefix of a LIKE pattern, where
+    /// every matching value `v` satisfy `lower <= v < upper`.
+    ///
+    /// This allows an index on the column to be scanned by range instead,
+    /// with the LIKE kept as a residual filter. Return None if the pattern
+    /// has no literal prefix, e.g. '%user'. The upper bound is None when
+    /// there is no string bigger than the prefix.
+    pub fn like_prefix_bounds(pattern: &str) -> Option<(String, Option<String>)> {
+        let prefix: String = pattern
+            .chars()
+            .take_while(|c| *c != '%' && *c != '_')
+            .collect();
+
+        if prefix.is_empty() {
+            return None;
+        }
+
+        // Increment the last character that can be incremented,
+        // e.g. 'user1' to 'user2'.
+        let mut upper: Vec<char> = prefix.chars().collect();
+        while let Some(c) = upper.pop() {
+            if let Some(next) = char::from_u32(c as u32 + 1) {
+                upper.push(next);
+                return Some((prefix, Some(upper.into_iter().collect())));
+            }
+        }
+
+        Some((prefix, None))
+    }
+
+    /// Evaluate the expression into a value for the given row.
+    ///
+    /// Return None if the expression doesn't produce a value, e.g.
+    /// referencing a column that doesn't exist.
+    pub fn evaluate(&self, row: &Row) -> Option<Value> {
+        match self {
+            Expression::Column(name) => match name.as_str() {
+                "id" => Some(Value::Integer(row.id as i64)),
+                "username" => Some(Value::Text(row.username())),
+                "email" => Some(Value::Text(row.email())),
+                _ => None,
+            },
+            Expression::Constant(value) => Some(value.clone()),
+            Expression::Comparison { .. }
+            | Expression::Like { .. }
+            | Expression::And(..)
+            | Expression::Or(..) => None,
+        }
+    }
+
+    /// Return true if the row satisfy the expression.
+    pub fn matches(&self, row: &Row) -> bool {
+        match self {
+            Expression::Comparison { left, op, right } => {
+                match (left.evaluate(row), right.evaluate(row)) {
+                    (Some(left), Some(right)) => left
+                        .compare(&right)
+                        .is_some_and(|ordering| op.matches(ordering)),
+                    _ => false,
+                }
+            }
+            Expression::Like {
+                expression,
+                pattern,
+            } => match expression.evaluate(row) {
+                Some(Value::Text(value)) => like_matches(&value, pattern),
+                _ => false,
+            },
+            Expression::And(left, right) => left.matches(row) && right.matches(row),
+            Expression::Or(left, right) => left.matches(row) || right.matches(row),
+            _ => false,
+        }
+    }
+}
+
+fn like_matches(value: &str, pattern: &str) -> bool {
+    let value: Vec<char> = value.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    let (mut v, mut p) = (0, 0);
+    // The position of the last `%` in the pattern, and the position
+    // in the value it is currently matched up to, so we can backtrack.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some('_') => {
+                v += 1;
+                p += 1;
+            }
+            Some(c) if *c == value[v] => {
+                v += 1;
+                p += 1;
+            }
+            _ => match backtrack {
+                // Let the last `%` consume one more character.
+                Some((percent, matched)) => {
+                    p = percent + 1;
+                    v = matched + 1;
+                    backtrack = Some((percent, v));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    // Any trailing `%` match the empty string.
+    pattern[p..].iter().all(|c| *c == '%')
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(value) => write!(f, "{value}"),
+            Value::Text(value) => write!(f, "'{}'", value.replace('\'', "''")),
+        }
+    }
+}
+
+impl fmt::Display for ComparisonOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            ComparisonOp::Equal => "=",
+            ComparisonOp::NotEqual => "!=",
+            ComparisonOp::LessThan => "<",
+            ComparisonOp::LessThanOrEqual => "<=",
+            ComparisonOp::GreaterThan => ">",
+            ComparisonOp::GreaterThanOrEqual => ">=",
+        };
+        write!(f, "{op}")
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Column(name) => write!(f, "{name}"),
+            Expression::Constant(value) => write!(f, "{value}"),
+            Expression::Comparison { left, op, right } => write!(f, "({left} {op} {right})"),
+            Expression::Like {
+                expression,
+                pattern,
+            } => {
+                let pattern = Value::Text(pattern.clone());
+                write!(f, "({expression} LIKE {pattern})")
+            }
+            Expression::And(left, right) => write!(f, "({left} AND {right})"),
+            Expression::Or(left, right) => write!(f, "({left} OR {right})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn evaluate_column_and_constant() {
+        let row = Row::new("1", "john", "john@email.com").unwrap();
+
+        assert_eq!(
+            Expression::column("id").evaluate(&row),
+            Some(Value::Integer(1))
+        );
+        assert_eq!(
+            Expression::column("username").evaluate(&row),
+            Some(Value::Text("john".to_string()))
+        );
+        assert_eq!(Expression::column("age").evaluate(&row), None);
+        assert_eq!(
+            Expression::text("wick").evaluate(&row),
+            Some(Value::Text("wick".to_string()))
+        );
+    }
+
+    #[test]
+    fn matches_comparison() {
+        let row = Row::new("5", "john", "john@email.com").unwrap();
+
+        let expr = Expression::comparison(
+            Expression::column("username"),
+            ComparisonOp::Equal,
+            Expression::text("john"),
+        );
+        assert!(expr.matches(&row));
+
+        let expr = Expression::comparison(
+            Expression::column("id"),
+            ComparisonOp::GreaterThan,
+            Expression::integer(5),
+        );
+        assert!(!expr.matches(&row));
+
+        let expr = Expression::comparison(
+            Expression::column("id"),
+            ComparisonOp::LessThanOrEqual,
+            Expression::integer(5),
+        );
+        assert!(expr.matches(&row));
+
+        // Comparing different type is always false.
+        let expr = Expression::comparison(
+            Expression::column("id"),
+            ComparisonOp::NotEqual,
+            Expression::text("5"),
+        );
+        assert!(!expr.matches(&row));
+    }
+
+    #[test]
+    fn matches_like() {
+        let row = Row::new("5", "user15", "user15@email.com").unwrap();
+
+        let like = |pattern| Expression::like(Expression::column("username"), pattern);
+        assert!(like("user15").matches(&row));
+        assert!(like("user1%").matches(&row));
+        assert!(like("%15").matches(&row));
+        assert!(like("%er%").matches(&row));
+        assert!(like("u_er1_").matches(&row));
+        assert!(like("%").matches(&row));
+        assert!(like("u%1%5").matches(&row));
+        assert!(!like("user1").matches(&row));
+        assert!(!like("user2%").matches(&row));
+        assert!(!like("_ser1").matches(&row));
+        assert!(!like("%16").matches(&row));
+
+        // LIKE only apply to text
+        assert!(!Expression::like(Expression::column("id"), "5").matches(&row));
+    }
+
+    #[test]
+    fn matches_and_or() {
+        let row = Row::new("5", "john", "john@email.com").unwrap();
+
+        let id = |op, value| Expression::comparison(Expression::column("id"), op, value);
+        let is_john = Expression::comparison(
+            Expression::column("username"),
+            ComparisonOp::Equal,
+            Expression::text("john"),
+        );
+
+        let expr = Expression::and(
+            id(ComparisonOp::GreaterThan, Expression::integer(1)),
+            is_john.clone(),
+        );
+        assert!(expr.matches(&row));
+        assert_eq!(expr.to_string(), "((id > 1) AND (username = 'john'))");
+
+        let expr = Expression::and(
+            id(ComparisonOp::GreaterThan, Expression::integer(5)),
+            is_john.clone(),
+        );
+        assert!(!expr.matches(&row));
+
+        let expr = Expression::or(
+            id(ComparisonOp::GreaterThan, Expression::integer(5)),
+            is_john,
+        );
+        assert!(expr.matches(&row));
+
+        let expr = Expression::or(
+            id(ComparisonOp::Equal, Expression::integer(1)),
+            id(ComparisonOp::Equal, Expression::integer(2)),
+        );
+        assert!(!expr.matches(&row));
+    }
+
+    #[test]
+    fn like_prefix_bounds() {
+        assert_eq!(
+            Expression::like_prefix_bounds("user1%"),
+            Some(("user1".to_string(), Some("user2".to_string())))
+        );
+        assert_eq!(
+            Expression::like_prefix_bounds("ab_d"),
+            Some(("ab".to_string(), Some("ac".to_string())))
+        );
+        assert_eq!(Expression::like_prefix_bounds("%user"), None);
+        assert_eq!(Expression::like_prefix_bounds("_user"), None);
+    }
+}
@@ -1,9 +1,17 @@
 mod executor;
+mod expression;
+mod parser;
+mod planner;
 mod query_plan;
 mod query_v1;
+mod result_set;
 
+#[cfg(test)]
+pub use executor::{ExecutionContext, ExecutionEngine};
 pub use {
-    executor::{ExecutionContext, ExecutionEngine},
+    expression::{Expression, Value},
+    parser::SqlStatement,
     query_plan::*,
     query_v1::*,
+    result_set::{ExportFormat, ResultSet},
 };
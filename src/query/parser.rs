@@ -0,0 +1,930 @@
+use super::expression::{ComparisonOp, Expression, Value};
+use super::query_plan::{OrderBy, SubqueryPredicate};
+use crate::row::Row;
+use crate::schema::{none, primary, ColumnType, Schema};
+
+// A tokenizer and recursive descent parser for the subset of SQL we
+// support:
+//
+//     SELECT * FROM <table> [JOIN <table> ON <operand> = <operand>] [WHERE <predicate>]
+//         [ORDER BY <operand> [ASC|DESC], ...] [LIMIT <count> [OFFSET <count>]]
+//     INSERT INTO <table> VALUES (<value>, <value>, ...)
+//     DELETE FROM <table> [WHERE <predicate>]
+//     CREATE TABLE <table> (<column> <type> [PRIMARY KEY], ...)
+//     CREATE INDEX ON <table> (<column>)
+//
+// where a type is INT or TEXT(<length>), a predicate is `<operand> <op> <operand>` or `<operand> LIKE
+// '<pattern>'`, combined with AND and OR and grouped by parentheses, and
+// an operand is a column, an integer or a quoted text. AND binds tighter
+// than OR. Keywords are case insensitive.
+//
+// The predicate of a select may also have a subquery, see `Subquery`, as
+// one of the conditions ANDed at its top.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlStatement {
+    Select {
+        table: String,
+        join: Option<Join>,
+        predicate: Option<Expression>,
+        subquery: Option<Box<Subquery>>,
+        order_by: Vec<OrderBy>,
+        // Rows to return, if limited, after skipping `offset` rows.
+        limit: Option<usize>,
+        offset: usize,
+    },
+    Insert {
+        table: String,
+        // Values of each row, e.g. `VALUES (1, 'a', 'b'), (2, 'c', 'd')`.
+        rows: Vec<Vec<Value>>,
+    },
+    Delete {
+        table: String,
+        predicate: Option<Expression>,
+    },
+    CreateTable {
+        table: String,
+        schema: Schema,
+    },
+    CreateIndex {
+        table: String,
+        column: String,
+        unique: bool,
+    },
+}
+
+/// `JOIN <table> ON <left key> = <right key>`, where the left key is
+/// evaluated on the rows of the selected table, and the right key on the
+/// rows of the joined one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+    pub table: String,
+    pub left_key: Expression,
+    pub right_key: Expression,
+}
+
+/// `<operand> IN (SELECT <column> FROM <table> [WHERE <predicate>])` or
+/// `EXISTS (SELECT * FROM <table> [WHERE <predicate>])`, where the subquery
+/// is uncorrelated, i.e. its predicate is only evaluated on its own rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subquery {
+    pub predicate: SubqueryPredicate,
+    pub table: String,
+    pub filter: Option<Expression>,
+}
+
+impl SqlStatement {
+    /// Return true if the input looks like SQL rather than our positional
+    /// statements, e.g. `select 1` or `insert 1 john john@email.com`,
+    /// which never have `INTO`, `FROM`, `TABLE`, `INDEX`, `UNIQUE` or `*`
+    /// as their second word.
+    pub fn is_sql(input: &str) -> bool {
+        input.split_whitespace().nth(1).is_some_and(|word| {
+            word.eq_ignore_ascii_case("into")
+                || word.eq_ignore_ascii_case("from")
+                || word.eq_ignore_ascii_case("table")
+                || word.eq_ignore_ascii_case("index")
+                || word.eq_ignore_ascii_case("unique")
+                || word.starts_with('*')
+        })
+    }
+
+    pub fn table(&self) -> &str {
+        match self {
+            SqlStatement::Select { table, .. }
+            | SqlStatement::Insert { table, .. }
+            | SqlStatement::Delete { table, .. }
+            | SqlStatement::CreateTable { table, .. }
+            | SqlStatement::CreateIndex { table, .. } => table,
+        }
+    }
+
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let statement = parser.statement()?;
+
+        parser.consume_symbol(";");
+        match parser.peek() {
+            None => Ok(statement),
+            Some(token) => Err(format!("unexpected {token} at the end of statement")),
+        }
+    }
+}
+
+/// Type check the values against the columns of the users table, in
+/// order, and build the row.
+pub(super) fn values_to_row(values: &[Value]) -> Result<Row, String> {
+    let schema = Schema::users();
+    let columns = schema.columns();
+    if values.len() != columns.len() {
+        return Err(format!(
+            "expected {} values but got {}",
+            columns.len(),
+            values.len()
+        ));
+    }
+
+    let mut fields = Vec::with_capacity(values.len());
+    for (column, value) in columns.iter().zip(values) {
+        match (column.column_type, value) {
+            (ColumnType::Int, Value::Integer(value)) => fields.push(value.to_string()),
+            (ColumnType::Text(_), Value::Text(value)) => fields.push(value.clone()),
+            (ColumnType::Int, _) => return Err(format!("column '{}' is not a Text", column.name)),
+            (ColumnType::Text(_), _) => {
+                return Err(format!("column '{}' is not an Int", column.name))
+            }
+        }
+    }
+
+    Row::from_columns(&fields[0], &fields[1], &fields[2])
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    // Keywords are identifiers too, and are told apart by the parser.
+    Identifier(String),
+    Integer(i64),
+    Text(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 11] = ["<=", ">=", "!=", "<>", "=", "<", ">", "*", "(", ")", ","];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(i, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == ';' {
+            chars.next();
+            tokens.push(Token::Symbol(";"));
+        } else if c == '\'' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    // A quote is escaped by doubling it, e.g. 'it''s'.
+                    Some((_, '\'')) if matches!(chars.peek(), Some((_, '\''))) => {
+                        chars.next();
+                        text.push('\'');
+                    }
+                    Some((_, '\'')) => break,
+                    Some((_, c)) => text.push(c),
+                    None => return Err("unterminated text".to_string()),
+                }
+            }
+            tokens.push(Token::Text(text));
+        } else if c.is_ascii_digit() || (c == '-' && next_is_digit(input, i)) {
+            chars.next();
+            let mut end = i + 1;
+            while let Some(&(j, c)) = chars.peek() {
+                if !c.is_ascii_digit() {
+                    break;
+                }
+                end = j + 1;
+                chars.next();
+            }
+            let integer = input[i..end]
+                .parse()
+                .map_err(|_| format!("invalid integer {}", &input[i..end]))?;
+            tokens.push(Token::Integer(integer));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = i;
+            while let Some(&(j, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = j + 1;
+                chars.next();
+            }
+            tokens.push(Token::Identifier(input[i..end].to_string()));
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| input[i..].starts_with(*symbol))
+                .ok_or_else(|| format!("unexpected character '{c}'"))?;
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn next_is_digit(input: &str, i: usize) -> bool {
+    input[i + 1..].starts_with(|c: char| c.is_ascii_digit())
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn statement(&mut self) -> Result<SqlStatement, String> {
+        if self.consume_keyword("select") {
+            self.expect_symbol("*")?;
+            self.expect_keyword("from")?;
+            let table = self.identifier()?;
+            let join = self.join_clause()?;
+            let (predicate, subquery) = self.select_where_clause()?;
+            let order_by = self.order_by_clause()?;
+            let (limit, offset) = self.limit_clause()?;
+            Ok(SqlStatement::Select {
+                table,
+                join,
+                predicate,
+                subquery,
+                order_by,
+                limit,
+                offset,
+            })
+        } else if self.consume_keyword("insert") {
+            self.expect_keyword("into")?;
+            let table = self.identifier()?;
+            self.expect_keyword("values")?;
+            let mut rows = vec![self.values()?];
+            while self.consume_symbol(",") {
+                rows.push(self.values()?);
+            }
+            Ok(SqlStatement::Insert { table, rows })
+        } else if self.consume_keyword("delete") {
+            self.expect_keyword("from")?;
+            let table = self.identifier()?;
+            let predicate = self.where_clause()?;
+            Ok(SqlStatement::Delete { table, predicate })
+        } else if self.consume_keyword("create") {
+            let unique = self.consume_keyword("unique");
+            if unique || self.consume_keyword("index") {
+                if unique {
+                    self.expect_keyword("index")?;
+                }
+                self.expect_keyword("on")?;
+                let table = self.identifier()?;
+                self.expect_symbol("(")?;
+                let column = self.identifier()?;
+                self.expect_symbol(")")?;
+                return Ok(SqlStatement::CreateIndex {
+                    table,
+                    column,
+                    unique,
+                });
+            }
+            self.expect_keyword("table")?;
+            let table = self.identifier()?;
+            let schema = self.columns()?;
+            Ok(SqlStatement::CreateTable { table, schema })
+        } else {
+            Err(self.unexpected("SELECT, INSERT, DELETE or CREATE"))
+        }
+    }
+
+    // `(<column> <type> [PRIMARY KEY], ...)`
+    fn columns(&mut self) -> Result<Schema, String> {
+        self.expect_symbol("(")?;
+
+        let mut builder = Schema::builder();
+        loop {
+            let name = self.identifier()?;
+            let column_type = self.column_type()?;
+            let options = if self.consume_keyword("primary") {
+                self.expect_keyword("key")?;
+                primary()
+            } else {
+                none()
+            };
+            builder = builder.col(&name, column_type, options);
+
+            if !self.consume_symbol(",") {
+                break;
+            }
+        }
+
+        self.expect_symbol(")")?;
+        builder.build()
+    }
+
+    // `INT`, `INTEGER` or `TEXT(<length>)`
+    fn column_type(&mut self) -> Result<ColumnType, String> {
+        if self.consume_keyword("int") || self.consume_keyword("integer") {
+            return Ok(ColumnType::Int);
+        }
+
+        if !self.consume_keyword("text") {
+            return Err(self.unexpected("INT or TEXT"));
+        }
+        self.expect_symbol("(")?;
+        let length = match self.next() {
+            Some(Token::Integer(length)) if length >= 0 => length as usize,
+            _ => return Err(self.unexpected_previous("a length")),
+        };
+        self.expect_symbol(")")?;
+        Ok(ColumnType::Text(length))
+    }
+
+    // `JOIN <table> ON <operand> = <operand>`
+    fn join_clause(&mut self) -> Result<Option<Join>, String> {
+        if !self.consume_keyword("join") {
+            return Ok(None);
+        }
+
+        let table = self.identifier()?;
+        self.expect_keyword("on")?;
+        let left_key = self.operand()?;
+        self.expect_symbol("=")?;
+        let right_key = self.operand()?;
+        Ok(Some(Join {
+            table,
+            left_key,
+            right_key,
+        }))
+    }
+
+    fn where_clause(&mut self) -> Result<Option<Expression>, String> {
+        if self.consume_keyword("where") {
+            self.predicate().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Same as `where_clause`, along with the subquery ANDed with the
+    // predicate, if any.
+    fn select_where_clause(
+        &mut self,
+    ) -> Result<(Option<Expression>, Option<Box<Subquery>>), String> {
+        if !self.consume_keyword("where") {
+            return Ok((None, None));
+        }
+
+        let mut conditions = Vec::new();
+        let mut subquery = None;
+        loop {
+            match self.subquery_condition()? {
+                Some(_) if subquery.is_some() => {
+                    return Err("only one subquery is supported".to_string())
+                }
+                Some(condition) => subquery = Some(Box::new(condition)),
+                None => conditions.push(self.condition()?),
+            }
+
+            if !self.consume_keyword("and") {
+                break;
+            }
+        }
+
+        let mut predicate = conditions.into_iter().reduce(Expression::and);
+        while self.consume_keyword("or") {
+            let (Some(left), None) = (predicate.take(), &subquery) else {
+                return Err("a subquery can't be ORed with other conditions".to_string());
+            };
+            predicate = Some(Expression::or(left, self.conjunction()?));
+        }
+        Ok((predicate, subquery))
+    }
+
+    // `EXISTS (<subquery>)` or `<operand> IN (<subquery>)`, or None if the
+    // next condition is neither.
+    fn subquery_condition(&mut self) -> Result<Option<Subquery>, String> {
+        if self.consume_keyword("exists") {
+            let (_, table, filter) = self.subquery(false)?;
+            return Ok(Some(Subquery {
+                predicate: SubqueryPredicate::Exists,
+                table,
+                filter,
+            }));
+        }
+
+        let start = self.position;
+        let expression = match self.operand() {
+            Ok(expression) if self.consume_keyword("in") => expression,
+            _ => {
+                self.position = start;
+                return Ok(None);
+            }
+        };
+        let (column, table, filter) = self.subquery(true)?;
+        Ok(Some(Subquery {
+            predicate: SubqueryPredicate::In {
+                expression,
+                column: column.unwrap(),
+            },
+            table,
+            filter,
+        }))
+    }
+
+    // `(SELECT <column> FROM <table> [WHERE <predicate>])`, or with `*`
+    // instead of a column if it doesn't select one.
+    fn subquery(
+        &mut self,
+        selects_column: bool,
+    ) -> Result<(Option<Expression>, String, Option<Expression>), String> {
+        self.expect_symbol("(")?;
+        self.expect_keyword("select")?;
+        let column = if selects_column {
+            Some(Expression::Column(self.identifier()?))
+        } else {
+            self.expect_symbol("*")?;
+            None
+        };
+        self.expect_keyword("from")?;
+        let table = self.identifier()?;
+        let filter = self.where_clause()?;
+        self.expect_symbol(")")?;
+        Ok((column, table, filter))
+    }
+
+    // `ORDER BY <operand> [ASC|DESC], ...`, where the direction defaults
+    // to ascending.
+    fn order_by_clause(&mut self) -> Result<Vec<OrderBy>, String> {
+        if !self.consume_keyword("order") {
+            return Ok(Vec::new());
+        }
+        self.expect_keyword("by")?;
+
+        let mut order_by = Vec::new();
+        loop {
+            let expression = self.operand()?;
+            if self.consume_keyword("desc") {
+                order_by.push(OrderBy::desc(expression));
+            } else {
+                self.consume_keyword("asc");
+                order_by.push(OrderBy::asc(expression));
+            }
+
+            if !self.consume_symbol(",") {
+                break;
+            }
+        }
+        Ok(order_by)
+    }
+
+    // `LIMIT <count> [OFFSET <count>]`
+    fn limit_clause(&mut self) -> Result<(Option<usize>, usize), String> {
+        if !self.consume_keyword("limit") {
+            return Ok((None, 0));
+        }
+
+        let limit = self.count()?;
+        let offset = if self.consume_keyword("offset") {
+            self.count()?
+        } else {
+            0
+        };
+        Ok((Some(limit), offset))
+    }
+
+    fn count(&mut self) -> Result<usize, String> {
+        match self.next() {
+            Some(Token::Integer(count)) if count >= 0 => Ok(count as usize),
+            _ => Err(self.unexpected_previous("a count")),
+        }
+    }
+
+    // `<conjunction> [OR <conjunction>]...`
+    fn predicate(&mut self) -> Result<Expression, String> {
+        let mut left = self.conjunction()?;
+        while self.consume_keyword("or") {
+            left = Expression::or(left, self.conjunction()?);
+        }
+
+        Ok(left)
+    }
+
+    // `<condition> [AND <condition>]...`
+    fn conjunction(&mut self) -> Result<Expression, String> {
+        let mut left = self.condition()?;
+        while self.consume_keyword("and") {
+            left = Expression::and(left, self.condition()?);
+        }
+
+        Ok(left)
+    }
+
+    fn condition(&mut self) -> Result<Expression, String> {
+        if self.consume_symbol("(") {
+            let predicate = self.predicate()?;
+            self.expect_symbol(")")?;
+            return Ok(predicate);
+        }
+
+        let left = self.operand()?;
+
+        if self.consume_keyword("in") {
+            return Err("IN is only supported ANDed with the WHERE clause of a select".to_string());
+        }
+        if self.consume_keyword("like") {
+            return match self.next() {
+                Some(Token::Text(pattern)) => Ok(Expression::like(left, &pattern)),
+                _ => Err(self.unexpected_previous("a quoted pattern")),
+            };
+        }
+
+        let op = match self.next() {
+            Some(Token::Symbol("=")) => ComparisonOp::Equal,
+            Some(Token::Symbol("!=" | "<>")) => ComparisonOp::NotEqual,
+            Some(Token::Symbol("<")) => ComparisonOp::LessThan,
+            Some(Token::Symbol("<=")) => ComparisonOp::LessThanOrEqual,
+            Some(Token::Symbol(">")) => ComparisonOp::GreaterThan,
+            Some(Token::Symbol(">=")) => ComparisonOp::GreaterThanOrEqual,
+            _ => return Err(self.unexpected_previous("a comparison")),
+        };
+        let right = self.operand()?;
+
+        Ok(Expression::comparison(left, op, right))
+    }
+
+    fn operand(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Identifier(name)) => Ok(Expression::Column(name)),
+            Some(Token::Integer(value)) => Ok(Expression::integer(value)),
+            Some(Token::Text(value)) => Ok(Expression::Constant(Value::Text(value))),
+            _ => Err(self.unexpected_previous("a column or a value")),
+        }
+    }
+
+    // `(<value>, <value>, ...)`
+    fn values(&mut self) -> Result<Vec<Value>, String> {
+        self.expect_symbol("(")?;
+
+        let mut values = Vec::new();
+        loop {
+            match self.next() {
+                Some(Token::Integer(value)) => values.push(Value::Integer(value)),
+                Some(Token::Text(value)) => values.push(Value::Text(value)),
+                _ => return Err(self.unexpected_previous("a value")),
+            }
+
+            if !self.consume_symbol(",") {
+                break;
+            }
+        }
+
+        self.expect_symbol(")")?;
+        Ok(values)
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Identifier(name)) => Ok(name),
+            _ => Err(self.unexpected_previous("a name")),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Identifier(name)) if name.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn consume_symbol(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(s)) if *s == symbol => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.consume_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&keyword.to_uppercase()))
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        if self.consume_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{symbol}'")))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> String {
+        match self.peek() {
+            Some(token) => format!("expected {expected} but got {token}"),
+            None => format!("expected {expected} but got end of statement"),
+        }
+    }
+
+    // Same as `unexpected`, for the token that was just consumed.
+    fn unexpected_previous(&mut self, expected: &str) -> String {
+        self.position -= 1;
+        self.unexpected(expected)
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Identifier(name) => write!(f, "{name}"),
+            Token::Integer(value) => write!(f, "{value}"),
+            Token::Text(value) => write!(f, "{}", Value::Text(value.clone())),
+            Token::Symbol(symbol) => write!(f, "'{symbol}'"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_statements() {
+        assert_eq!(
+            SqlStatement::parse("SELECT * FROM users WHERE id = 5;"),
+            Ok(SqlStatement::Select {
+                table: "users".to_string(),
+                join: None,
+                subquery: None,
+                predicate: Some(Expression::comparison(
+                    Expression::column("id"),
+                    ComparisonOp::Equal,
+                    Expression::integer(5),
+                )),
+                order_by: vec![],
+                limit: None,
+                offset: 0,
+            })
+        );
+        assert_eq!(
+            SqlStatement::parse("select * from users order by username desc, id"),
+            Ok(SqlStatement::Select {
+                table: "users".to_string(),
+                join: None,
+                predicate: None,
+                subquery: None,
+                order_by: vec![
+                    OrderBy::desc(Expression::column("username")),
+                    OrderBy::asc(Expression::column("id")),
+                ],
+                limit: None,
+                offset: 0,
+            })
+        );
+        assert!(SqlStatement::parse("select * from users order id").is_err());
+        assert_eq!(
+            SqlStatement::parse("select * from users limit 10 offset 5"),
+            Ok(SqlStatement::Select {
+                table: "users".to_string(),
+                join: None,
+                predicate: None,
+                subquery: None,
+                order_by: vec![],
+                limit: Some(10),
+                offset: 5,
+            })
+        );
+        assert!(SqlStatement::parse("select * from users limit -1").is_err());
+        assert_eq!(
+            SqlStatement::parse("select * from users join users on username = email"),
+            Ok(SqlStatement::Select {
+                table: "users".to_string(),
+                join: Some(Join {
+                    table: "users".to_string(),
+                    left_key: Expression::column("username"),
+                    right_key: Expression::column("email"),
+                }),
+                predicate: None,
+                subquery: None,
+                order_by: vec![],
+                limit: None,
+                offset: 0,
+            })
+        );
+        assert!(SqlStatement::parse("select * from users join users on id > id").is_err());
+        assert_eq!(
+            SqlStatement::parse(
+                "select * from users where id > 1 and id in (select id from users where id < 5)"
+            ),
+            Ok(SqlStatement::Select {
+                table: "users".to_string(),
+                join: None,
+                predicate: Some(Expression::comparison(
+                    Expression::column("id"),
+                    ComparisonOp::GreaterThan,
+                    Expression::integer(1),
+                )),
+                subquery: Some(Box::new(Subquery {
+                    predicate: SubqueryPredicate::In {
+                        expression: Expression::column("id"),
+                        column: Expression::column("id"),
+                    },
+                    table: "users".to_string(),
+                    filter: Some(Expression::comparison(
+                        Expression::column("id"),
+                        ComparisonOp::LessThan,
+                        Expression::integer(5),
+                    )),
+                })),
+                order_by: vec![],
+                limit: None,
+                offset: 0,
+            })
+        );
+        assert_eq!(
+            SqlStatement::parse("select * from users where exists (select * from users)"),
+            Ok(SqlStatement::Select {
+                table: "users".to_string(),
+                join: None,
+                predicate: None,
+                subquery: Some(Box::new(Subquery {
+                    predicate: SubqueryPredicate::Exists,
+                    table: "users".to_string(),
+                    filter: None,
+                })),
+                order_by: vec![],
+                limit: None,
+                offset: 0,
+            })
+        );
+        assert!(SqlStatement::parse("select * from users offset 5").is_err());
+        assert_eq!(
+            SqlStatement::parse("insert into users values (1, 'it''s me', 'me@email.com')"),
+            Ok(SqlStatement::Insert {
+                table: "users".to_string(),
+                rows: vec![vec![
+                    Value::Integer(1),
+                    Value::Text("it's me".to_string()),
+                    Value::Text("me@email.com".to_string()),
+                ]],
+            })
+        );
+        assert_eq!(
+            SqlStatement::parse("insert into users values (1, 'a', 'a@b.c'), (2, 'b', 'b@c.d')"),
+            Ok(SqlStatement::Insert {
+                table: "users".to_string(),
+                rows: vec![
+                    vec![
+                        Value::Integer(1),
+                        Value::Text("a".to_string()),
+                        Value::Text("a@b.c".to_string()),
+                    ],
+                    vec![
+                        Value::Integer(2),
+                        Value::Text("b".to_string()),
+                        Value::Text("b@c.d".to_string()),
+                    ],
+                ],
+            })
+        );
+        assert!(SqlStatement::parse("insert into users values (1, 'a', 'a@b.c'),").is_err());
+        assert_eq!(
+            SqlStatement::parse("Delete From users Where username Like 'user1%'"),
+            Ok(SqlStatement::Delete {
+                table: "users".to_string(),
+                predicate: Some(Expression::like(Expression::column("username"), "user1%")),
+            })
+        );
+        assert_eq!(
+            SqlStatement::parse("select * from users where id>=-2"),
+            Ok(SqlStatement::Select {
+                table: "users".to_string(),
+                join: None,
+                subquery: None,
+                predicate: Some(Expression::comparison(
+                    Expression::column("id"),
+                    ComparisonOp::GreaterThanOrEqual,
+                    Expression::integer(-2),
+                )),
+                order_by: vec![],
+                limit: None,
+                offset: 0,
+            })
+        );
+
+        assert_eq!(
+            SqlStatement::parse(
+                "create table orders (id INT primary key, username text(32), email TEXT(255))"
+            ),
+            Ok(SqlStatement::CreateTable {
+                table: "orders".to_string(),
+                schema: Schema::users(),
+            })
+        );
+
+        assert_eq!(
+            SqlStatement::parse("CREATE INDEX ON users (username)"),
+            Ok(SqlStatement::CreateIndex {
+                table: "users".to_string(),
+                column: "username".to_string(),
+                unique: false,
+            })
+        );
+        assert_eq!(
+            SqlStatement::parse("create unique index on users (email)"),
+            Ok(SqlStatement::CreateIndex {
+                table: "users".to_string(),
+                column: "email".to_string(),
+                unique: true,
+            })
+        );
+        assert!(SqlStatement::parse("CREATE UNIQUE TABLE users (id INT)").is_err());
+
+        let predicate = |input| match SqlStatement::parse(input) {
+            Ok(SqlStatement::Select { predicate, .. }) => predicate.unwrap().to_string(),
+            result => panic!("expected a select but got {result:?}"),
+        };
+        assert_eq!(
+            predicate("select * from users where id > 1 AND id < 5 or username = 'john'"),
+            "(((id > 1) AND (id < 5)) OR (username = 'john'))"
+        );
+        assert_eq!(
+            predicate("select * from users where id > 1 and (id < 5 or username = 'john')"),
+            "((id > 1) AND ((id < 5) OR (username = 'john')))"
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |input| SqlStatement::parse(input).unwrap_err();
+
+        assert_eq!(
+            parse("update users"),
+            "expected SELECT, INSERT, DELETE or CREATE but got update"
+        );
+        assert_eq!(parse("select id from users"), "expected '*' but got id");
+        assert_eq!(
+            parse("select * from"),
+            "expected a name but got end of statement"
+        );
+        assert_eq!(
+            parse("select * from users where id"),
+            "expected a comparison but got end of statement"
+        );
+        assert_eq!(
+            parse("insert into users values (1, 'john'"),
+            "expected ')' but got end of statement"
+        );
+        assert_eq!(
+            parse("delete from users where id = 1 id = 2"),
+            "unexpected id at the end of statement"
+        );
+        assert_eq!(
+            parse("select * from users where (id = 1 or id = 2"),
+            "expected ')' but got end of statement"
+        );
+        assert_eq!(
+            parse("select * from users where id = 1 and"),
+            "expected a column or a value but got end of statement"
+        );
+        assert_eq!(
+            parse("select * from users where id in (select * from users)"),
+            "expected a name but got '*'"
+        );
+        assert_eq!(
+            parse("select * from users where exists (select * from users) or id = 1"),
+            "a subquery can't be ORed with other conditions"
+        );
+        assert_eq!(
+            parse("delete from users where id in (select id from users)"),
+            "IN is only supported ANDed with the WHERE clause of a select"
+        );
+        assert_eq!(
+            parse("select * from users where id = 'a"),
+            "unterminated text"
+        );
+        assert_eq!(
+            parse("select * from users where id = ?"),
+            "unexpected character '?'"
+        );
+        assert_eq!(
+            parse("create table orders (id int primary key, name varchar)"),
+            "expected INT or TEXT but got varchar"
+        );
+        assert_eq!(
+            parse("create table orders (id int, name text(8))"),
+            "schema must have a primary key"
+        );
+        assert_eq!(
+            parse("create index users (username)"),
+            "expected ON but got users"
+        );
+    }
+}
@@ -0,0 +1,623 @@
+use super::expression::{ComparisonOp, Expression, Value};
+use super::parser::{values_to_row, Join, SqlStatement, Subquery};
+use super::query_plan::{
+    DeletePlanNode, IndexLookup, InsertPlanNode, LimitPlanNode, OrderBy, PlanNode,
+    RangeScanPlanNode, SecondaryIndexScanPlanNode, SemiJoinPlanNode, SeqScanPlanNode, SortPlanNode,
+    SubqueryPredicate, DEFAULT_MAX_TOP_N_ROWS,
+};
+use crate::catalog::Catalog;
+use crate::schema::Schema;
+use std::sync::Arc;
+
+// Percentage of the rows of a table a range or index scan can be
+// estimated to return before a sequence scan is planned instead.
+const MAX_RANGE_SCAN_PERCENT: usize = 50;
+
+/// Build the plan of a parsed statement, checking the table and the
+/// columns it refers to against the catalog.
+///
+/// A predicate on a single id looks the row up through the index, one on
+/// a range of ids only scans the leaves of the range, and anything else
+/// scans the whole table with the predicate as its filter, see
+/// `PlanNode::scan`.
+///
+/// Once the table is analyzed, a range expected to hold more than half of
+/// the rows, see `TableStatistics`, is read by a sequence scan instead, as
+/// the pages of a sequence scan are evicted first, rather than pushing the
+/// rest of the buffer pool out.
+///
+/// A predicate that would scan the whole table, but requires an indexed
+/// column to equal a value, e.g. `username = 'john'`, looks the rows up
+/// through the index of the column instead, see `Table::create_index`,
+/// unless the distinct values of the column are too few for a value to
+/// be expected in less than half of the rows. Otherwise, a LIKE on an
+/// indexed column with a literal prefix, e.g. `username LIKE 'jo%'`, scans
+/// the range of values starting with the prefix in the index, keeping the
+/// LIKE as the predicate the rows are filtered by.
+///
+/// A join is planned as a merge join, see `PlanNode::merge_join`, where a
+/// side that isn't scanned in the order of its join key, i.e. by id, is
+/// sorted by it first. The joined table is scanned in a context of its
+/// own, see `MergeJoinPlanNode::table`, and its columns are checked
+/// against its own schema.
+///
+/// A subquery is planned as a child pipeline of a semi-join, which only
+/// returns the rows of the scan matching the subquery, see
+/// `SemiJoinPlanNode`.
+///
+/// An ORDER BY with a LIMIT only keeps the rows up to the limit in a heap,
+/// unless there are more of them than the planner is configured with, see
+/// `LimitPlanNode::plan`.
+///
+/// TODO (Catalog): Plans don't refer to a table yet, besides the joined
+/// one, and run against the table of the `ExecutionContext` they're
+/// executed in, which the caller has to open by the name of the
+/// statement, see `SqlStatement::table`.
+pub struct Planner {
+    catalog: Arc<Catalog>,
+    max_top_n: usize,
+}
+
+impl Planner {
+    pub fn new(catalog: Arc<Catalog>) -> Self {
+        Self {
+            catalog,
+            max_top_n: DEFAULT_MAX_TOP_N_ROWS,
+        }
+    }
+
+    /// Keep at most `rows` rows in the heap of a top-N, instead of
+    /// `DEFAULT_MAX_TOP_N_ROWS`.
+    pub fn with_max_top_n(mut self, rows: usize) -> Self {
+        self.max_top_n = rows;
+        self
+    }
+
+    pub fn plan(&self, statement: SqlStatement) -> Result<PlanNode, String> {
+        let table = statement.table().to_string();
+        let table = table.as_str();
+        let schema = match &statement {
+            SqlStatement::CreateTable { .. } => {
+                return Err("CREATE TABLE has no plan, see `Table::create_table`".to_string())
+            }
+            SqlStatement::CreateIndex { .. } => {
+                return Err("CREATE INDEX has no plan, see `Table::create_index`".to_string())
+            }
+            _ => self
+                .catalog
+                .get(table)
+                .map(|info| info.schema)
+                .ok_or_else(|| format!("unknown table '{table}'"))?,
+        };
+
+        match statement {
+            SqlStatement::Select {
+                join,
+                predicate,
+                subquery,
+                order_by,
+                limit,
+                offset,
+                ..
+            } => {
+                if let Some(predicate) = &predicate {
+                    check_columns(predicate, &schema)?;
+                }
+                for order_by in &order_by {
+                    check_columns(&order_by.expression, &schema)?;
+                }
+
+                let mut plan_node = self.scan(table, predicate);
+                if let Some(subquery) = subquery {
+                    plan_node = self.semi_join(table, plan_node, *subquery, &schema)?;
+                }
+                if let Some(join) = join {
+                    plan_node = self.join(table, plan_node, join, &schema)?;
+                }
+                let plan_node = SortPlanNode::plan(plan_node, order_by);
+                Ok(match limit {
+                    Some(limit) => LimitPlanNode::plan(plan_node, limit, offset, self.max_top_n),
+                    None => plan_node,
+                })
+            }
+            SqlStatement::Insert { rows, .. } => {
+                let rows = rows
+                    .iter()
+                    .map(|values| values_to_row(values))
+                    .collect::<Result<_, _>>()?;
+                Ok(PlanNode::Insert(InsertPlanNode::values(rows)))
+            }
+            SqlStatement::Delete { predicate, .. } => {
+                if let Some(predicate) = &predicate {
+                    check_columns(predicate, &schema)?;
+                }
+                Ok(PlanNode::Delete(DeletePlanNode {
+                    child: Box::new(self.scan(table, predicate)),
+                }))
+            }
+            SqlStatement::CreateTable { .. } | SqlStatement::CreateIndex { .. } => unreachable!(),
+        }
+    }
+
+    // A subquery can only select from the table of the statement for now,
+    // as the semi-join runs it in the same context.
+    fn semi_join(
+        &self,
+        table: &str,
+        child: PlanNode,
+        subquery: Subquery,
+        schema: &Schema,
+    ) -> Result<PlanNode, String> {
+        if subquery.table != table {
+            return Err(format!(
+                "a subquery on '{table}' can only select from '{table}'"
+            ));
+        }
+        if let SubqueryPredicate::In { expression, column } = &subquery.predicate {
+            check_columns(expression, schema)?;
+            check_columns(column, schema)?;
+        }
+        if let Some(filter) = &subquery.filter {
+            check_columns(filter, schema)?;
+        }
+
+        Ok(PlanNode::SemiJoin(SemiJoinPlanNode {
+            predicate: subquery.predicate,
+            child: Box::new(child),
+            subquery: Box::new(self.scan(&subquery.table, subquery.filter)),
+        }))
+    }
+
+    // Each side is only sorted by its join key if it's not already
+    // scanned in that order, see `SortPlanNode::plan`.
+    fn join(
+        &self,
+        table: &str,
+        left: PlanNode,
+        join: Join,
+        schema: &Schema,
+    ) -> Result<PlanNode, String> {
+        let right_schema = self
+            .catalog
+            .get(&join.table)
+            .map(|info| info.schema)
+            .ok_or_else(|| format!("unknown table '{}'", join.table))?;
+        check_columns(&join.left_key, schema)?;
+        check_columns(&join.right_key, &right_schema)?;
+
+        let sorted = |plan_node, key: &Expression| {
+            SortPlanNode::plan(plan_node, vec![OrderBy::asc(key.clone())])
+        };
+        let left = sorted(left, &join.left_key);
+        let right = sorted(self.scan(&join.table, None), &join.right_key);
+        let right_table = (join.table != table).then_some(join.table);
+        PlanNode::merge_join(left, right, join.left_key, join.right_key, right_table)
+            .ok_or_else(|| "the sides of the join aren't ordered by their key".to_string())
+    }
+
+    fn scan(&self, table: &str, predicate: Option<Expression>) -> PlanNode {
+        let plan_node = PlanNode::scan(predicate);
+        if let PlanNode::SeqScan(SeqScanPlanNode {
+            predicate: Some(predicate),
+        }) = &plan_node
+        {
+            let indexes: Vec<String> = self
+                .catalog
+                .indexes(table)
+                .into_iter()
+                .map(|index| index.column)
+                .collect();
+            let selective = |column: &str| {
+                let Some(statistics) = self.catalog.statistics(table) else {
+                    return true;
+                };
+                statistics.estimate_equal(column).is_none_or(|estimate| {
+                    estimate * 100 <= statistics.row_count * MAX_RANGE_SCAN_PERCENT
+                })
+            };
+            let indexed = indexed_value(predicate, &indexes)
+                .filter(|(column, _)| selective(column))
+                .map(|(column, value)| (column, IndexLookup::Value(value)))
+                .or_else(|| indexed_prefix(predicate, &indexes));
+            if let Some((column, lookup)) = indexed {
+                return PlanNode::SecondaryIndexScan(SecondaryIndexScanPlanNode {
+                    column,
+                    lookup,
+                    predicate: Some(predicate.clone()),
+                });
+            }
+        }
+
+        let (PlanNode::RangeScan(range), Some(statistics)) =
+            (&plan_node, self.catalog.statistics(table))
+        else {
+            return plan_node;
+        };
+
+        let estimate = statistics.estimate_range(range.start, range.end);
+        if estimate * 100 <= statistics.row_count * MAX_RANGE_SCAN_PERCENT {
+            return plan_node;
+        }
+        let PlanNode::RangeScan(RangeScanPlanNode { predicate, .. }) = plan_node else {
+            unreachable!()
+        };
+        PlanNode::SeqScan(SeqScanPlanNode { predicate })
+    }
+}
+
+// Return the first indexed column the predicate requires to equal a text
+// value, along with the value, looking through the operands of ANDs.
+fn indexed_value(predicate: &Expression, indexes: &[String]) -> Option<(String, String)> {
+    match predicate {
+        Expression::And(left, right) => {
+            indexed_value(left, indexes).or_else(|| indexed_value(right, indexes))
+        }
+        Expression::Comparison {
+            left,
+            op: ComparisonOp::Equal,
+            right,
+        } => match (left.as_ref(), right.as_ref()) {
+            (Expression::Column(column), Expression::Constant(Value::Text(value)))
+            | (Expression::Constant(Value::Text(value)), Expression::Column(column))
+                if indexes.contains(column) =>
+            {
+                Some((column.clone(), value.clone()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Return the first indexed column the predicate requires to match a LIKE
+// pattern with a literal prefix, along with the range of values starting
+// with the prefix, looking through the operands of ANDs.
+fn indexed_prefix(predicate: &Expression, indexes: &[String]) -> Option<(String, IndexLookup)> {
+    match predicate {
+        Expression::And(left, right) => {
+            indexed_prefix(left, indexes).or_else(|| indexed_prefix(right, indexes))
+        }
+        Expression::Like {
+            expression,
+            pattern,
+        } => match expression.as_ref() {
+            Expression::Column(column) if indexes.contains(column) => {
+                let (start, end) = Expression::like_prefix_bounds(pattern)?;
+                Some((column.clone(), IndexLookup::Range { start, end }))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Return an error naming the first column of the expression that isn't
+// in the schema.
+fn check_columns(expression: &Expression, schema: &Schema) -> Result<(), String> {
+    match expression {
+        Expression::Column(name) if schema.column(name).is_none() => {
+            Err(format!("unknown column '{name}'"))
+        }
+        Expression::Column(_) | Expression::Constant(_) => Ok(()),
+        Expression::Comparison { left, right, .. }
+        | Expression::And(left, right)
+        | Expression::Or(left, right) => {
+            check_columns(left, schema)?;
+            check_columns(right, schema)
+        }
+        Expression::Like { expression, .. } => check_columns(expression, schema),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::catalog::IndexInfo;
+    use crate::query::IndexScanPlanNode;
+    use crate::row::Row;
+    use crate::statistics::TableStatistics;
+
+    #[test]
+    fn plan_statements() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let planner = Planner::new(Arc::new(Catalog::open(&path).unwrap()));
+        let plan = |input| SqlStatement::parse(input).and_then(|s| planner.plan(s));
+
+        assert_eq!(
+            plan("delete from users where id = 5"),
+            Ok(PlanNode::Delete(DeletePlanNode {
+                child: Box::new(PlanNode::IndexScan(IndexScanPlanNode { key: 5 })),
+            }))
+        );
+        assert_eq!(
+            plan("select * from users where username = 'john'")
+                .unwrap()
+                .to_string(),
+            "SeqScan predicate=(username = 'john')"
+        );
+        assert_eq!(
+            plan("select * from users where id > 5 order by id desc")
+                .unwrap()
+                .to_string(),
+            "Sort order_by=id DESC\n  RangeScan range=6.. predicate=(id > 5)"
+        );
+        assert_eq!(
+            plan("select * from users order by id asc")
+                .unwrap()
+                .to_string(),
+            "SeqScan"
+        );
+        assert_eq!(
+            plan("select * from users join users on id = id where id > 5")
+                .unwrap()
+                .to_string(),
+            "MergeJoin left_key=id right_key=id\n  \
+             RangeScan range=6.. predicate=(id > 5)\n  \
+             SeqScan"
+        );
+        assert_eq!(
+            plan("select * from users join users on username = email order by id")
+                .unwrap()
+                .to_string(),
+            "Sort order_by=id ASC\n  \
+             MergeJoin left_key=username right_key=email\n    \
+             Sort order_by=username ASC\n      \
+             SeqScan\n    \
+             Sort order_by=email ASC\n      \
+             SeqScan"
+        );
+        assert_eq!(
+            plan("select * from users where id < 10 and id in (select id from users where id > 5)")
+                .unwrap()
+                .to_string(),
+            "SemiJoin predicate=(id IN id)\n  \
+             RangeScan range=0..10 predicate=(id < 10)\n  \
+             RangeScan range=6.. predicate=(id > 5)"
+        );
+        assert_eq!(
+            plan("select * from users where exists (select * from users where id = 1)")
+                .unwrap()
+                .to_string(),
+            "SemiJoin predicate=EXISTS\n  SeqScan\n  IndexScan key=1"
+        );
+        assert_eq!(
+            plan("insert into users values (1, 'john', 'john@email.com')"),
+            Ok(PlanNode::Insert(InsertPlanNode::values(vec![Row::new(
+                "1",
+                "john",
+                "john@email.com"
+            )
+            .unwrap()])))
+        );
+
+        assert_eq!(
+            plan("select * from orders").unwrap_err(),
+            "unknown table 'orders'"
+        );
+        assert_eq!(
+            plan("select * from users where name = 'john'").unwrap_err(),
+            "unknown column 'name'"
+        );
+        assert_eq!(
+            plan("delete from users where id = 1 or age > 5").unwrap_err(),
+            "unknown column 'age'"
+        );
+        assert_eq!(
+            plan("select * from users join users on id = age").unwrap_err(),
+            "unknown column 'age'"
+        );
+        assert_eq!(
+            plan("select * from users where id in (select name from users)").unwrap_err(),
+            "unknown column 'name'"
+        );
+        assert_eq!(
+            plan("select * from users where exists (select * from orders)").unwrap_err(),
+            "a subquery on 'users' can only select from 'users'"
+        );
+        assert_eq!(
+            plan("select * from users order by name").unwrap_err(),
+            "unknown column 'name'"
+        );
+        assert_eq!(
+            plan("create table orders (id int primary key)").unwrap_err(),
+            "CREATE TABLE has no plan, see `Table::create_table`"
+        );
+        assert_eq!(
+            plan("insert into users values (1, 'john')").unwrap_err(),
+            "expected 3 values but got 2"
+        );
+        assert_eq!(
+            plan("insert into users values ('1', 'john', 'john@email.com')").unwrap_err(),
+            "column 'id' is not a Text"
+        );
+        assert_eq!(
+            plan("insert into users values (-1, 'john', 'john@email.com')").unwrap_err(),
+            "id must not be negative"
+        );
+    }
+
+    #[test]
+    fn plan_scans_by_statistics() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let catalog = Arc::new(Catalog::open(&path).unwrap());
+        let planner = Planner::new(catalog.clone());
+        let plan = |input| {
+            let statement = SqlStatement::parse(input).unwrap();
+            planner.plan(statement).unwrap().to_string()
+        };
+
+        // Without statistics, any range of ids is read by a range scan.
+        assert_eq!(
+            plan("select * from users where id > 10"),
+            "RangeScan range=11.. predicate=(id > 10)"
+        );
+
+        let keys: Vec<u32> = (1..=100).collect();
+        catalog
+            .set_statistics("users", TableStatistics::build(&keys))
+            .unwrap();
+        assert_eq!(
+            plan("select * from users where id > 10"),
+            "SeqScan predicate=(id > 10)"
+        );
+        assert_eq!(
+            plan("delete from users where id >= 60"),
+            "Delete\n  RangeScan range=60.. predicate=(id >= 60)"
+        );
+        assert_eq!(
+            plan("select * from users where id = 50"),
+            "IndexScan key=50"
+        );
+
+        // Rows inserted since are counted too.
+        for key in 101..=200 {
+            let row = Row::new(&key.to_string(), "john", "john@email.com").unwrap();
+            catalog.record_insert("users", &row);
+        }
+        assert_eq!(
+            plan("delete from users where id >= 60"),
+            "Delete\n  SeqScan predicate=(id >= 60)"
+        );
+
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+    }
+
+    #[test]
+    fn plan_join_across_tables() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let catalog = Arc::new(Catalog::open(&path).unwrap());
+        catalog
+            .create("orders", &Schema::users(), || Ok(3))
+            .unwrap();
+        let planner = Planner::new(catalog);
+        let plan = |input| SqlStatement::parse(input).and_then(|s| planner.plan(s));
+
+        // Both sides are scanned by id, so neither of them is sorted.
+        assert_eq!(
+            plan("select * from users join orders on id = id")
+                .unwrap()
+                .to_string(),
+            "MergeJoin left_key=id right_key=id table=orders\n  SeqScan\n  SeqScan"
+        );
+        assert_eq!(
+            plan("select * from users join orders on id = username")
+                .unwrap()
+                .to_string(),
+            "MergeJoin left_key=id right_key=username table=orders\n  \
+             SeqScan\n  \
+             Sort order_by=username ASC\n    \
+             SeqScan"
+        );
+        assert_eq!(
+            plan("select * from users join orders on id = name").unwrap_err(),
+            "unknown column 'name'"
+        );
+        assert_eq!(
+            plan("select * from users join items on id = id").unwrap_err(),
+            "unknown table 'items'"
+        );
+
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+    }
+
+    #[test]
+    fn plan_top_n_up_to_max_rows() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let planner = Planner::new(Arc::new(Catalog::open(&path).unwrap())).with_max_top_n(100);
+        let plan = |input| {
+            let statement = SqlStatement::parse(input).unwrap();
+            planner.plan(statement).unwrap().to_string()
+        };
+
+        assert_eq!(
+            plan("select * from users order by username limit 50 offset 50"),
+            "Limit limit=50 offset=50\n  TopN limit=100 order_by=username ASC\n    SeqScan"
+        );
+        assert_eq!(
+            plan("select * from users order by username limit 50 offset 51"),
+            "Limit limit=50 offset=51\n  Sort order_by=username ASC\n    SeqScan"
+        );
+    }
+
+    #[test]
+    fn plan_scans_by_secondary_index() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let catalog = Arc::new(Catalog::open(&path).unwrap());
+        let planner = Planner::new(catalog.clone());
+        let plan = |input| {
+            let statement = SqlStatement::parse(input).unwrap();
+            planner.plan(statement).unwrap().to_string()
+        };
+
+        catalog
+            .add_index(
+                "users",
+                IndexInfo {
+                    column: "username".to_string(),
+                    unique: false,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            plan("select * from users where username = 'john'"),
+            "SecondaryIndexScan column=username value='john' predicate=(username = 'john')"
+        );
+        assert_eq!(
+            plan("delete from users where email LIKE '%@email.com' and 'john' = username"),
+            "Delete\n  SecondaryIndexScan column=username value='john' \
+             predicate=((email LIKE '%@email.com') AND ('john' = username))"
+        );
+        // A range of ids is still scanned through the primary index.
+        assert_eq!(
+            plan("select * from users where id = 1 and username = 'john'"),
+            "RangeScan range=1..2 predicate=((id = 1) AND (username = 'john'))"
+        );
+        assert_eq!(
+            plan("select * from users where username = 'john' or id = 1"),
+            "SeqScan predicate=((username = 'john') OR (id = 1))"
+        );
+        assert_eq!(
+            plan("select * from users where email = 'john@email.com'"),
+            "SeqScan predicate=(email = 'john@email.com')"
+        );
+        assert_eq!(
+            plan("select * from users where username like 'jo%'"),
+            "SecondaryIndexScan column=username range='jo'..'jp' \
+             predicate=(username LIKE 'jo%')"
+        );
+        assert_eq!(
+            plan("select * from users where username like 'jo%' and username = 'john'"),
+            "SecondaryIndexScan column=username value='john' \
+             predicate=((username LIKE 'jo%') AND (username = 'john'))"
+        );
+        assert_eq!(
+            plan("select * from users where username like '%jo'"),
+            "SeqScan predicate=(username LIKE '%jo')"
+        );
+
+        // Each of two usernames is expected in half of the rows, while a
+        // table where every row has the same one is scanned instead.
+        let rows = |usernames: &[&str]| -> Vec<Row> {
+            (0..100)
+                .map(|i| {
+                    let username = usernames[i % usernames.len()];
+                    Row::new(&(i + 1).to_string(), username, "team@example.com").unwrap()
+                })
+                .collect()
+        };
+        let statistics = TableStatistics::build_from_rows(&rows(&["john", "jane"]));
+        catalog.set_statistics("users", statistics).unwrap();
+        assert_eq!(
+            plan("select * from users where username = 'john'"),
+            "SecondaryIndexScan column=username value='john' predicate=(username = 'john')"
+        );
+        let statistics = TableStatistics::build_from_rows(&rows(&["john"]));
+        catalog.set_statistics("users", statistics).unwrap();
+        assert_eq!(
+            plan("select * from users where username = 'john'"),
+            "SeqScan predicate=(username = 'john')"
+        );
+
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+    }
+}
@@ -1,44 +1,1204 @@
-use crate::row::Row;
+use super::expression::{ComparisonOp, Expression, Value};
+use crate::row::{Row, MAX_ID};
+use crate::schema::{ColumnType, Schema};
+use crate::storage::AccessPattern;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A node of a query plan.
+///
+/// Plans can be serialized with serde, e.g. to cache them keyed by the
+/// statement text, and are displayed in a stable textual format, one node
+/// per line with its children indented below it, e.g. for logging them or
+/// asserting the plan in tests without executing it:
+///
+/// ```text
+/// Filter predicate=(id > 10)
+///   IndexScan key=1
+/// ```
 pub enum PlanNode {
     SeqScan(SeqScanPlanNode),
+    Filter(FilterPlanNode),
+    Values(ValuesPlanNode),
+    TopN(TopNPlanNode),
+    Sort(SortPlanNode),
+    Limit(LimitPlanNode),
+    Paginate(PaginatePlanNode),
+    MergeJoin(MergeJoinPlanNode),
+    SemiJoin(SemiJoinPlanNode),
     IndexScan(IndexScanPlanNode),
+    RangeScan(RangeScanPlanNode),
+    SecondaryIndexScan(SecondaryIndexScanPlanNode),
     Insert(InsertPlanNode),
     Update(UpdatePlanNode),
     Delete(DeletePlanNode),
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SeqScanPlanNode {
-    pub predicate: String,
+    pub predicate: Option<Expression>,
 }
 
-#[derive(Clone)]
+impl SeqScanPlanNode {
+    // Every leaf is read once, even with a predicate.
+    pub fn access_pattern(&self) -> AccessPattern {
+        AccessPattern::Sequential
+    }
+}
+
+// Filter rows produced by its child with the predicate.
+//
+// When the child is a sequence scan without predicate, the
+// filter is pushed down into the sequence scan instead. See
+// `PlanNode::push_down_predicates`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterPlanNode {
+    pub predicate: Expression,
+    pub child: Box<PlanNode>,
+}
+
+// Emit the given rows as is without touching the storage.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValuesPlanNode {
+    pub rows: Vec<Row>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderBy {
+    pub expression: Expression,
+    pub direction: OrderDirection,
+}
+
+impl OrderBy {
+    pub fn asc(expression: Expression) -> Self {
+        Self {
+            expression,
+            direction: OrderDirection::Asc,
+        }
+    }
+
+    pub fn desc(expression: Expression) -> Self {
+        Self {
+            expression,
+            direction: OrderDirection::Desc,
+        }
+    }
+
+    /// Evaluate the sort key of the row for each of the order by.
+    pub fn sort_keys(order_by: &[OrderBy], row: &Row) -> Vec<Option<Value>> {
+        order_by
+            .iter()
+            .map(|o| o.expression.evaluate(row))
+            .collect()
+    }
+
+    /// Compare two sort keys produced by `sort_keys` with the given order.
+    ///
+    /// Rows without a value, e.g. when the column doesn't exist, are
+    /// ordered first, the same way NULL is ordered in ascending order.
+    pub fn compare_keys(
+        order_by: &[OrderBy],
+        a: &[Option<Value>],
+        b: &[Option<Value>],
+    ) -> Ordering {
+        for (i, o) in order_by.iter().enumerate() {
+            let ordering = match (&a[i], &b[i]) {
+                (Some(a), Some(b)) => a.compare(b).unwrap_or(Ordering::Equal),
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Less,
+                (Some(_), None) => Ordering::Greater,
+            };
+
+            let ordering = match o.direction {
+                OrderDirection::Asc => ordering,
+                OrderDirection::Desc => ordering.reverse(),
+            };
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        Ordering::Equal
+    }
+}
+
+// Return the first `limit` rows of its child in the given order.
+//
+// This is the fusion of ORDER BY and a small LIMIT, where instead of
+// sorting the whole input, we only keep the top N rows in a bounded heap.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopNPlanNode {
+    pub order_by: Vec<OrderBy>,
+    pub limit: usize,
+    pub child: Box<PlanNode>,
+}
+
+// Return every row of its child in the given order.
+//
+// Unlike a top N, the whole input has to be buffered before the first
+// row is returned, see `SortExecutor`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SortPlanNode {
+    pub order_by: Vec<OrderBy>,
+    pub child: Box<PlanNode>,
+}
+
+impl SortPlanNode {
+    /// Sort the rows of the child, unless it already produces them in
+    /// the given order.
+    pub fn plan(child: PlanNode, order_by: Vec<OrderBy>) -> PlanNode {
+        let ordered = match order_by.as_slice() {
+            [] => true,
+            [o] => o.direction == OrderDirection::Asc && child.is_ordered_by(&o.expression),
+            _ => false,
+        };
+        if ordered {
+            return child;
+        }
+
+        PlanNode::Sort(SortPlanNode {
+            order_by,
+            child: Box::new(child),
+        })
+    }
+}
+
+// Most rows a top-N keeps in its heap by default, see
+// `LimitPlanNode::plan`, which is as many as a sorted run of a sort.
+pub const DEFAULT_MAX_TOP_N_ROWS: usize = 4096;
+
+// Skip the first `offset` rows of its child, then return at most
+// `limit` rows, without pulling any more rows from the child after that.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LimitPlanNode {
+    pub limit: usize,
+    pub offset: usize,
+    pub child: Box<PlanNode>,
+}
+
+impl LimitPlanNode {
+    /// Limit the rows of the child. A sort below it only keeps the rows
+    /// up to the limit instead, see `TopNPlanNode`, unless there are more
+    /// than `max_top_n` of them, e.g. a large offset. A heap that large
+    /// holds more rows than a sort does at a time, and is slower to keep
+    /// ordered than sorting the whole child.
+    pub fn plan(child: PlanNode, limit: usize, offset: usize, max_top_n: usize) -> PlanNode {
+        let kept = limit.saturating_add(offset);
+        let child = match child {
+            PlanNode::Sort(SortPlanNode { order_by, child }) if kept <= max_top_n => {
+                PlanNode::TopN(TopNPlanNode {
+                    order_by,
+                    limit: kept,
+                    child,
+                })
+            }
+            child => child,
+        };
+
+        PlanNode::Limit(LimitPlanNode {
+            limit,
+            offset,
+            child: Box::new(child),
+        })
+    }
+}
+
+// Return a page of at most `page_size` rows of its child, where the
+// child produces rows in id order.
+//
+// The cursor is the id of the last row of the previous page, instead of
+// the number of rows returned so far. Rows inserted or deleted before the
+// cursor by other transactions between pages would shift a position based
+// cursor, causing rows to be skipped or repeated. See `PlanNode::paginate`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaginatePlanNode {
+    pub after: Option<u32>,
+    pub page_size: usize,
+    pub child: Box<PlanNode>,
+}
+
+// Join two children that both produce rows ordered by their join key,
+// by walking both of them in lockstep.
+//
+// The join emits both the left and the right row of each matching pair,
+// see `Executor::next_joined`, as a row only has the columns of a single
+// table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeJoinPlanNode {
+    pub left_key: Expression,
+    pub right_key: Expression,
+    pub left: Box<PlanNode>,
+    pub right: Box<PlanNode>,
+    // Table the right child runs against, or the table of the context
+    // if None, e.g. when a table is joined with itself.
+    pub table: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SubqueryPredicate {
+    // `<expression> in (select <column> ...)`
+    In {
+        expression: Expression,
+        column: Expression,
+    },
+    // `exists (select ...)`
+    Exists,
+}
+
+// Only return rows of its child that satisfy the subquery predicate.
+//
+// The subquery is an uncorrelated child pipeline, so it is executed
+// once and its result is materialized into an in-memory set before
+// the first row of the child is returned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SemiJoinPlanNode {
+    pub predicate: SubqueryPredicate,
+    pub child: Box<PlanNode>,
+    pub subquery: Box<PlanNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IndexScanPlanNode {
     pub key: u32,
 }
 
-#[derive(Clone)]
+// Only scan the leaves holding the keys in `[start, end)`, and return
+// the rows of the range that satisfy the predicate.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RangeScanPlanNode {
+    pub start: u32,
+    // The range is unbounded if there is no end.
+    pub end: Option<u32>,
+    pub predicate: Option<Expression>,
+}
+
+// Look the ids of the rows with the value up in the secondary index of
+// the column, then fetch the rows by their id and return the ones that
+// satisfy the predicate. See `SecondaryIndex`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecondaryIndexScanPlanNode {
+    pub column: String,
+    pub lookup: IndexLookup,
+    pub predicate: Option<Expression>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum IndexLookup {
+    Value(String),
+    // Values from the start up to, but excluding, the end, which is
+    // unbounded if there is none, e.g. the values starting with the
+    // literal prefix of a LIKE, see `Expression::like_prefix_bounds`.
+    Range { start: String, end: Option<String> },
+}
+
+// Insert the rows produced by its child, e.g. the rows of an `INSERT`
+// statement given by a values node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct InsertPlanNode {
-    pub row: Row,
+    pub child: Box<PlanNode>,
 }
 
-// Currently, we are hardcoding both
-// delete and update node to use sequence scan
-// to walk through all of our records.
-//
-// We need to make the child type Generic or Trait
-// so it could use different access methods to
-// retrive the affected rows.
-#[derive(Clone)]
+impl InsertPlanNode {
+    /// Plan an insert of the given rows.
+    pub fn values(rows: Vec<Row>) -> Self {
+        Self {
+            child: Box::new(PlanNode::Values(ValuesPlanNode { rows })),
+        }
+    }
+}
+
+// Both update and delete node apply to the rows produced by their child,
+// so the affected rows can be retrieved with any access method.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UpdatePlanNode {
     pub child: Box<PlanNode>,
-    pub new_row: Row,
-    pub columns: Vec<String>,
+    pub assignments: Vec<Assignment>,
+}
+
+// `SET <column> = <expression>`, where the expression is evaluated
+// against the row before any of the assignments are applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Assignment {
+    pub column: String,
+    pub expression: Expression,
+}
+
+impl UpdatePlanNode {
+    /// Plan an update of the rows produced by the child, checking the
+    /// assignments against the schema, so a mistyped assignment fails
+    /// once at plan time instead of on every row.
+    pub fn new(
+        child: PlanNode,
+        assignments: Vec<(&str, Expression)>,
+        schema: &Schema,
+    ) -> Result<Self, String> {
+        if assignments.is_empty() {
+            return Err("update must set at least one column".to_string());
+        }
+
+        for (i, (name, expression)) in assignments.iter().enumerate() {
+            let column = schema
+                .column(name)
+                .ok_or_else(|| format!("unknown column '{name}'"))?;
+            if column.options.primary_key {
+                return Err(format!("primary key '{name}' can't be updated"));
+            }
+            if assignments[..i].iter().any(|(other, _)| other == name) {
+                return Err(format!("column '{name}' is set more than once"));
+            }
+
+            match (column.column_type, expression_type(expression, schema)?) {
+                (ColumnType::Int, ColumnType::Int) => {}
+                (ColumnType::Text(max), ColumnType::Text(len)) if len <= max => {}
+                (ColumnType::Text(max), ColumnType::Text(_)) => {
+                    return Err(format!(
+                        "{expression} is longer than {max} bytes of '{name}'"
+                    ))
+                }
+                (ColumnType::Int, _) => return Err(format!("column '{name}' is not a Text")),
+                (ColumnType::Text(_), _) => return Err(format!("column '{name}' is not an Int")),
+            }
+        }
+
+        Ok(Self {
+            child: Box::new(child),
+            assignments: assignments
+                .into_iter()
+                .map(|(column, expression)| Assignment {
+                    column: column.to_string(),
+                    expression,
+                })
+                .collect(),
+        })
+    }
+
+    /// Parse the assignments of a SET clause, e.g.
+    /// `username = 'john', email = username`, then plan it with `new`.
+    ///
+    /// A value is either a quoted text, an integer or a column name.
+    pub fn parse(child: PlanNode, set: &str, schema: &Schema) -> Result<Self, String> {
+        let mut assignments = vec![];
+        for assignment in split_outside_quotes(set, ',') {
+            let (column, value) = assignment.split_once('=').ok_or_else(|| {
+                format!(
+                    "expected <column> = <value> but got '{}'",
+                    assignment.trim()
+                )
+            })?;
+            assignments.push((column.trim(), parse_value(value.trim())?));
+        }
+
+        Self::new(child, assignments, schema)
+    }
+
+    /// Name of the columns set by the update.
+    pub fn columns(&self) -> Vec<String> {
+        self.assignments
+            .iter()
+            .map(|assignment| assignment.column.clone())
+            .collect()
+    }
+}
+
+// The type of value the expression produces. A text constant is typed
+// by its length, so it can be checked against the size of the column.
+fn expression_type(expression: &Expression, schema: &Schema) -> Result<ColumnType, String> {
+    match expression {
+        Expression::Column(name) => schema
+            .column(name)
+            .map(|column| column.column_type)
+            .ok_or_else(|| format!("unknown column '{name}'")),
+        Expression::Constant(Value::Integer(_)) => Ok(ColumnType::Int),
+        Expression::Constant(Value::Text(value)) => Ok(ColumnType::Text(value.len())),
+        _ => Err(format!("'{expression}' doesn't produce a value")),
+    }
 }
 
-#[derive(Clone)]
+fn split_outside_quotes(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (i, c) in input.char_indices() {
+        if c == '\'' {
+            // An escaped quote toggles twice, so it stays quoted.
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(&input[start..i]);
+            start = i + 1;
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+fn parse_value(value: &str) -> Result<Expression, String> {
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        let text = value[1..value.len() - 1].replace("''", "'");
+        return Ok(Expression::text(&text));
+    }
+
+    if let Ok(integer) = value.parse::<i64>() {
+        return Ok(Expression::integer(integer));
+    }
+
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Ok(Expression::column(value));
+    }
+
+    Err(format!("invalid value '{value}'"))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DeletePlanNode {
-    pub child: SeqScanPlanNode,
+    pub child: Box<PlanNode>,
+}
+
+// Return the key if the predicate only matches the row with that id,
+// which is `id = <key>` in either order.
+pub(super) fn point_key(predicate: &Expression) -> Option<u32> {
+    let Expression::Comparison {
+        left,
+        op: ComparisonOp::Equal,
+        right,
+    } = predicate
+    else {
+        return None;
+    };
+
+    match (left.as_ref(), right.as_ref()) {
+        (Expression::Column(column), Expression::Constant(Value::Integer(key)))
+        | (Expression::Constant(Value::Integer(key)), Expression::Column(column))
+            if column == "id" =>
+        {
+            u32::try_from(*key).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Return the half open range `[start, end)` of ids the predicate is
+/// restricted to, e.g. `id >= 10 AND id < 100`, with an unbounded end if
+/// there is no upper bound. Return None if the predicate doesn't bound
+/// the id, e.g. `id > 10 OR username = 'john'`.
+///
+/// The range is empty, i.e. `start >= end`, when no id can match.
+pub fn key_range(predicate: &Expression) -> Option<(u32, Option<u32>)> {
+    match predicate {
+        Expression::And(left, right) => match (key_range(left), key_range(right)) {
+            (Some(left), Some(right)) => Some(intersect(left, right)),
+            (Some(range), None) | (None, Some(range)) => Some(range),
+            (None, None) => None,
+        },
+        Expression::Comparison { left, op, right } => {
+            let (op, key) = match (left.as_ref(), right.as_ref()) {
+                (Expression::Column(column), Expression::Constant(Value::Integer(key)))
+                    if column == "id" =>
+                {
+                    (*op, *key)
+                }
+                (Expression::Constant(Value::Integer(key)), Expression::Column(column))
+                    if column == "id" =>
+                {
+                    (op.flip(), *key)
+                }
+                _ => return None,
+            };
+
+            let (start, end) = match op {
+                ComparisonOp::Equal => (key, Some(key.saturating_add(1))),
+                ComparisonOp::GreaterThan => (key.saturating_add(1), None),
+                ComparisonOp::GreaterThanOrEqual => (key, None),
+                ComparisonOp::LessThan => (0, Some(key)),
+                ComparisonOp::LessThanOrEqual => (0, Some(key.saturating_add(1))),
+                ComparisonOp::NotEqual => return None,
+            };
+
+            // Clamp the bounds to the ids, where an end past the largest
+            // id is the same as no end.
+            if start > MAX_ID as i64 {
+                return Some((0, Some(0)));
+            }
+            let end = end
+                .filter(|end| *end <= MAX_ID as i64)
+                .map(|end| end.max(0) as u32);
+            Some((start.max(0) as u32, end))
+        }
+        _ => None,
+    }
+}
+
+fn intersect(left: (u32, Option<u32>), right: (u32, Option<u32>)) -> (u32, Option<u32>) {
+    let end = match (left.1, right.1) {
+        (Some(left), Some(right)) => Some(left.min(right)),
+        (end, None) | (None, end) => end,
+    };
+    (left.0.max(right.0), end)
+}
+
+impl PlanNode {
+    /// Return true if the rows produced by this node are guaranteed
+    /// to be in ascending order of the given expression.
+    ///
+    /// Our table is a B+ tree keyed by id, so scanning it always
+    /// produce rows in id order.
+    pub fn is_ordered_by(&self, expression: &Expression) -> bool {
+        match self {
+            PlanNode::SeqScan(_)
+            | PlanNode::IndexScan(_)
+            | PlanNode::RangeScan(_)
+            | PlanNode::SecondaryIndexScan(_) => *expression == Expression::column("id"),
+            PlanNode::Filter(plan_node) => plan_node.child.is_ordered_by(expression),
+            PlanNode::TopN(plan_node) => plan_node
+                .order_by
+                .first()
+                .is_some_and(|o| o.direction == OrderDirection::Asc && o.expression == *expression),
+            PlanNode::Sort(plan_node) => plan_node
+                .order_by
+                .first()
+                .is_some_and(|o| o.direction == OrderDirection::Asc && o.expression == *expression),
+            PlanNode::Limit(plan_node) => plan_node.child.is_ordered_by(expression),
+            PlanNode::Paginate(plan_node) => plan_node.child.is_ordered_by(expression),
+            PlanNode::MergeJoin(plan_node) => plan_node.left.is_ordered_by(expression),
+            PlanNode::SemiJoin(plan_node) => plan_node.child.is_ordered_by(expression),
+            _ => false,
+        }
+    }
+
+    /// The join whose rows this node produces, if any, through the nodes
+    /// that only pass the rows of their child on, e.g. a sort.
+    pub fn join(&self) -> Option<&MergeJoinPlanNode> {
+        match self {
+            PlanNode::MergeJoin(plan_node) => Some(plan_node),
+            PlanNode::TopN(plan_node) => plan_node.child.join(),
+            PlanNode::Sort(plan_node) => plan_node.child.join(),
+            PlanNode::Limit(plan_node) => plan_node.child.join(),
+            _ => None,
+        }
+    }
+
+    /// Plan a scan of the rows matching the predicate, or of every row if
+    /// there is none.
+    ///
+    /// A predicate on a single id, e.g. `id = 5`, looks the row up through
+    /// the index, and one restricting the ids to a range, e.g. `id > 10`,
+    /// only scans the leaves of the range. Anything else scans the whole
+    /// table.
+    pub fn scan(predicate: Option<Expression>) -> PlanNode {
+        let Some(predicate) = predicate else {
+            return PlanNode::SeqScan(SeqScanPlanNode { predicate: None });
+        };
+
+        if let Some(key) = point_key(&predicate) {
+            return PlanNode::IndexScan(IndexScanPlanNode { key });
+        }
+
+        match key_range(&predicate) {
+            Some((start, end)) => PlanNode::RangeScan(RangeScanPlanNode {
+                start,
+                end,
+                predicate: Some(predicate),
+            }),
+            None => PlanNode::SeqScan(SeqScanPlanNode {
+                predicate: Some(predicate),
+            }),
+        }
+    }
+
+    /// Plan a merge join between the two nodes if both of them produce
+    /// rows in the order of their join key. Otherwise, return None as
+    /// the inputs would need to be sorted first.
+    ///
+    /// The right node runs against `table`, or the same table as the left
+    /// one if it's None.
+    pub fn merge_join(
+        left: PlanNode,
+        right: PlanNode,
+        left_key: Expression,
+        right_key: Expression,
+        table: Option<String>,
+    ) -> Option<PlanNode> {
+        if !left.is_ordered_by(&left_key) || !right.is_ordered_by(&right_key) {
+            return None;
+        }
+
+        Some(PlanNode::MergeJoin(MergeJoinPlanNode {
+            left_key,
+            right_key,
+            left: Box::new(left),
+            right: Box::new(right),
+            table,
+        }))
+    }
+
+    /// Plan a page of rows of the child that come strictly after the row
+    /// with id `after`, or the first page if it's None. Pass the id of the
+    /// last row returned as `after` to get the next page.
+    ///
+    /// Return an error if the child doesn't produce rows in id order, as
+    /// the cursor can't tell which rows were already returned otherwise.
+    pub fn paginate(
+        child: PlanNode,
+        after: Option<u32>,
+        page_size: usize,
+    ) -> Result<PlanNode, String> {
+        if !child.is_ordered_by(&Expression::column("id")) {
+            return Err("pagination requires rows ordered by id".to_string());
+        }
+
+        // Skip rows before the cursor while scanning, instead of only
+        // after they reach the paginate executor.
+        let child = match after {
+            Some(after) => PlanNode::Filter(FilterPlanNode {
+                predicate: Expression::comparison(
+                    Expression::column("id"),
+                    ComparisonOp::GreaterThan,
+                    Expression::integer(after as i64),
+                ),
+                child: Box::new(child),
+            }),
+            None => child,
+        };
+
+        Ok(PlanNode::Paginate(PaginatePlanNode {
+            after,
+            page_size,
+            child: Box::new(child),
+        }))
+    }
+
+    /// Push filter predicates down into the sequence scan below it,
+    /// so rows are filtered while scanning instead of in a separate
+    /// executor.
+    ///
+    /// For now, we only push down when it's trivially possible, which
+    /// is when a filter is directly on top of a sequence scan that
+    /// has no predicate.
+    pub fn push_down_predicates(self) -> PlanNode {
+        match self {
+            PlanNode::Filter(FilterPlanNode { predicate, child }) => {
+                match child.push_down_predicates() {
+                    PlanNode::SeqScan(SeqScanPlanNode { predicate: None }) => {
+                        PlanNode::SeqScan(SeqScanPlanNode {
+                            predicate: Some(predicate),
+                        })
+                    }
+                    child => PlanNode::Filter(FilterPlanNode {
+                        predicate,
+                        child: Box::new(child),
+                    }),
+                }
+            }
+            PlanNode::Update(mut plan_node) => {
+                plan_node.child = Box::new(plan_node.child.push_down_predicates());
+                PlanNode::Update(plan_node)
+            }
+            PlanNode::Delete(mut plan_node) => {
+                plan_node.child = Box::new(plan_node.child.push_down_predicates());
+                PlanNode::Delete(plan_node)
+            }
+            PlanNode::TopN(mut plan_node) => {
+                plan_node.child = Box::new(plan_node.child.push_down_predicates());
+                PlanNode::TopN(plan_node)
+            }
+            PlanNode::Sort(mut plan_node) => {
+                plan_node.child = Box::new(plan_node.child.push_down_predicates());
+                PlanNode::Sort(plan_node)
+            }
+            PlanNode::Limit(mut plan_node) => {
+                plan_node.child = Box::new(plan_node.child.push_down_predicates());
+                PlanNode::Limit(plan_node)
+            }
+            PlanNode::Paginate(mut plan_node) => {
+                plan_node.child = Box::new(plan_node.child.push_down_predicates());
+                PlanNode::Paginate(plan_node)
+            }
+            PlanNode::MergeJoin(mut plan_node) => {
+                plan_node.left = Box::new(plan_node.left.push_down_predicates());
+                plan_node.right = Box::new(plan_node.right.push_down_predicates());
+                PlanNode::MergeJoin(plan_node)
+            }
+            PlanNode::SemiJoin(mut plan_node) => {
+                plan_node.child = Box::new(plan_node.child.push_down_predicates());
+                plan_node.subquery = Box::new(plan_node.subquery.push_down_predicates());
+                PlanNode::SemiJoin(plan_node)
+            }
+            plan_node => plan_node,
+        }
+    }
+}
+
+impl fmt::Display for PlanNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+impl PlanNode {
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(f, "{:indent$}", "", indent = depth * 2)?;
+
+        let children: Vec<&PlanNode> = match self {
+            PlanNode::SeqScan(plan_node) => {
+                write!(f, "SeqScan")?;
+                if let Some(predicate) = &plan_node.predicate {
+                    write!(f, " predicate={predicate}")?;
+                }
+                vec![]
+            }
+            PlanNode::Filter(plan_node) => {
+                write!(f, "Filter predicate={}", plan_node.predicate)?;
+                vec![&plan_node.child]
+            }
+            PlanNode::Values(plan_node) => {
+                write!(f, "Values rows={}", plan_node.rows.len())?;
+                vec![]
+            }
+            PlanNode::TopN(plan_node) => {
+                let order_by: Vec<String> =
+                    plan_node.order_by.iter().map(|o| o.to_string()).collect();
+                write!(
+                    f,
+                    "TopN limit={} order_by={}",
+                    plan_node.limit,
+                    order_by.join(",")
+                )?;
+                vec![&plan_node.child]
+            }
+            PlanNode::Sort(plan_node) => {
+                let order_by: Vec<String> =
+                    plan_node.order_by.iter().map(|o| o.to_string()).collect();
+                write!(f, "Sort order_by={}", order_by.join(","))?;
+                vec![&plan_node.child]
+            }
+            PlanNode::Limit(plan_node) => {
+                write!(f, "Limit limit={}", plan_node.limit)?;
+                if plan_node.offset > 0 {
+                    write!(f, " offset={}", plan_node.offset)?;
+                }
+                vec![&plan_node.child]
+            }
+            PlanNode::Paginate(plan_node) => {
+                write!(f, "Paginate page_size={}", plan_node.page_size)?;
+                if let Some(after) = plan_node.after {
+                    write!(f, " after={after}")?;
+                }
+                vec![&plan_node.child]
+            }
+            PlanNode::MergeJoin(plan_node) => {
+                write!(
+                    f,
+                    "MergeJoin left_key={} right_key={}",
+                    plan_node.left_key, plan_node.right_key
+                )?;
+                if let Some(table) = &plan_node.table {
+                    write!(f, " table={table}")?;
+                }
+                vec![&plan_node.left, &plan_node.right]
+            }
+            PlanNode::SemiJoin(plan_node) => {
+                match &plan_node.predicate {
+                    SubqueryPredicate::In { expression, column } => {
+                        write!(f, "SemiJoin predicate=({expression} IN {column})")?
+                    }
+                    SubqueryPredicate::Exists => write!(f, "SemiJoin predicate=EXISTS")?,
+                }
+                vec![&plan_node.child, &plan_node.subquery]
+            }
+            PlanNode::IndexScan(plan_node) => {
+                write!(f, "IndexScan key={}", plan_node.key)?;
+                vec![]
+            }
+            PlanNode::RangeScan(plan_node) => {
+                write!(f, "RangeScan range={}..", plan_node.start)?;
+                if let Some(end) = plan_node.end {
+                    write!(f, "{end}")?;
+                }
+                if let Some(predicate) = &plan_node.predicate {
+                    write!(f, " predicate={predicate}")?;
+                }
+                vec![]
+            }
+            PlanNode::SecondaryIndexScan(plan_node) => {
+                write!(f, "SecondaryIndexScan column={}", plan_node.column)?;
+                match &plan_node.lookup {
+                    IndexLookup::Value(value) => {
+                        write!(f, " value={}", Value::Text(value.clone()))?
+                    }
+                    IndexLookup::Range { start, end } => {
+                        write!(f, " range={}..", Value::Text(start.clone()))?;
+                        if let Some(end) = end {
+                            write!(f, "{}", Value::Text(end.clone()))?;
+                        }
+                    }
+                }
+                if let Some(predicate) = &plan_node.predicate {
+                    write!(f, " predicate={predicate}")?;
+                }
+                vec![]
+            }
+            PlanNode::Insert(plan_node) => {
+                write!(f, "Insert")?;
+                vec![&plan_node.child]
+            }
+            PlanNode::Update(plan_node) => {
+                let assignments: Vec<String> = plan_node
+                    .assignments
+                    .iter()
+                    .map(|a| format!("{}={}", a.column, a.expression))
+                    .collect();
+                write!(f, "Update set={}", assignments.join(","))?;
+                vec![&plan_node.child]
+            }
+            PlanNode::Delete(plan_node) => {
+                write!(f, "Delete")?;
+                vec![&plan_node.child]
+            }
+        };
+
+        for child in children {
+            writeln!(f)?;
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for OrderBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.direction {
+            OrderDirection::Asc => write!(f, "{} ASC", self.expression),
+            OrderDirection::Desc => write!(f, "{} DESC", self.expression),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn predicate() -> Expression {
+        Expression::comparison(
+            Expression::column("id"),
+            ComparisonOp::GreaterThan,
+            Expression::integer(10),
+        )
+    }
+
+    #[test]
+    fn push_down_filter_into_seq_scan() {
+        let plan_node = PlanNode::Filter(FilterPlanNode {
+            predicate: predicate(),
+            child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+        });
+
+        match plan_node.push_down_predicates() {
+            PlanNode::SeqScan(plan_node) => assert_eq!(plan_node.predicate, Some(predicate())),
+            _ => panic!("expected filter to be pushed down into seq scan"),
+        }
+    }
+
+    #[test]
+    fn do_not_push_down_filter_into_index_scan() {
+        let plan_node = PlanNode::Filter(FilterPlanNode {
+            predicate: predicate(),
+            child: Box::new(PlanNode::IndexScan(IndexScanPlanNode { key: 1 })),
+        });
+
+        match plan_node.push_down_predicates() {
+            PlanNode::Filter(plan_node) => {
+                assert!(matches!(*plan_node.child, PlanNode::IndexScan(_)))
+            }
+            _ => panic!("expected filter to stay on top of index scan"),
+        }
+    }
+
+    #[test]
+    fn display_and_serialize_plan() {
+        let plan_node = PlanNode::paginate(
+            PlanNode::Filter(FilterPlanNode {
+                predicate: Expression::like(Expression::column("username"), "it's%"),
+                child: Box::new(PlanNode::SeqScan(SeqScanPlanNode { predicate: None })),
+            }),
+            Some(5),
+            10,
+        )
+        .unwrap();
+        assert_eq!(
+            plan_node.to_string(),
+            "Paginate page_size=10 after=5
+  Filter predicate=(id > 5)
+    Filter predicate=(username LIKE 'it''s%')
+      SeqScan"
+        );
+        assert_eq!(
+            plan_node.clone().push_down_predicates().to_string(),
+            "Paginate page_size=10 after=5
+  Filter predicate=(id > 5)
+    SeqScan predicate=(username LIKE 'it''s%')"
+        );
+
+        let bytes = bincode::serialize(&plan_node).unwrap();
+        let deserialized: PlanNode = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(deserialized, plan_node);
+
+        let plan_node = PlanNode::Delete(DeletePlanNode {
+            child: Box::new(PlanNode::scan(Some(predicate()))),
+        });
+        assert_eq!(
+            plan_node.to_string(),
+            "Delete\n  RangeScan range=11.. predicate=(id > 10)"
+        );
+    }
+
+    #[test]
+    fn plan_delete_by_id_with_index_scan() {
+        let id_equal = |left, right| Some(Expression::comparison(left, ComparisonOp::Equal, right));
+        let delete = |predicate| DeletePlanNode {
+            child: Box::new(PlanNode::scan(predicate)),
+        };
+
+        let plan_node = delete(id_equal(Expression::column("id"), Expression::integer(5)));
+        assert_eq!(
+            *plan_node.child,
+            PlanNode::IndexScan(IndexScanPlanNode { key: 5 })
+        );
+
+        let plan_node = delete(id_equal(Expression::integer(5), Expression::column("id")));
+        assert_eq!(
+            *plan_node.child,
+            PlanNode::IndexScan(IndexScanPlanNode { key: 5 })
+        );
+
+        // Not a valid key, so no row can match it.
+        let plan_node = delete(id_equal(Expression::column("id"), Expression::integer(-1)));
+        assert_eq!(
+            plan_node.child.to_string(),
+            "RangeScan range=0..0 predicate=(id = -1)"
+        );
+
+        let plan_node = delete(id_equal(
+            Expression::column("username"),
+            Expression::text("john"),
+        ));
+        assert!(matches!(*plan_node.child, PlanNode::SeqScan(_)));
+
+        let plan_node = delete(None);
+        assert_eq!(
+            *plan_node.child,
+            PlanNode::SeqScan(SeqScanPlanNode { predicate: None })
+        );
+    }
+
+    #[test]
+    fn plan_range_scan_by_id() {
+        let id = |op, key| Expression::comparison(Expression::column("id"), op, key);
+        let range = |predicate: &Expression| key_range(predicate);
+
+        assert_eq!(
+            range(&id(ComparisonOp::GreaterThan, Expression::integer(10))),
+            Some((11, None))
+        );
+        assert_eq!(
+            range(&Expression::comparison(
+                Expression::integer(10),
+                ComparisonOp::GreaterThanOrEqual,
+                Expression::column("id"),
+            )),
+            Some((0, Some(11)))
+        );
+        assert_eq!(
+            range(&Expression::and(
+                id(ComparisonOp::GreaterThanOrEqual, Expression::integer(10)),
+                id(ComparisonOp::LessThan, Expression::integer(100)),
+            )),
+            Some((10, Some(100)))
+        );
+        assert_eq!(
+            range(&id(ComparisonOp::LessThan, Expression::integer(-5))),
+            Some((0, Some(0)))
+        );
+        assert_eq!(
+            range(&id(
+                ComparisonOp::LessThanOrEqual,
+                Expression::integer(u32::MAX as i64)
+            )),
+            Some((0, None))
+        );
+        assert_eq!(
+            range(&id(
+                ComparisonOp::GreaterThan,
+                Expression::integer(u32::MAX as i64)
+            )),
+            Some((0, Some(0)))
+        );
+
+        // Only one side of an OR bounds the id.
+        let predicate = Expression::or(
+            id(ComparisonOp::GreaterThan, Expression::integer(10)),
+            Expression::comparison(
+                Expression::column("username"),
+                ComparisonOp::Equal,
+                Expression::text("john"),
+            ),
+        );
+        assert_eq!(range(&predicate), None);
+        assert!(matches!(
+            PlanNode::scan(Some(predicate)),
+            PlanNode::SeqScan(_)
+        ));
+
+        let predicate = Expression::and(
+            id(ComparisonOp::GreaterThan, Expression::integer(10)),
+            Expression::like(Expression::column("username"), "user%"),
+        );
+        assert_eq!(
+            PlanNode::scan(Some(predicate)).to_string(),
+            "RangeScan range=11.. predicate=((id > 10) AND (username LIKE 'user%'))"
+        );
+    }
+
+    #[test]
+    fn type_check_update_assignments() {
+        let seq_scan = || PlanNode::SeqScan(SeqScanPlanNode { predicate: None });
+        let schema = Schema::users();
+        let plan = |set| UpdatePlanNode::parse(seq_scan(), set, &schema);
+
+        let plan_node = plan("username = 'john', email = username").unwrap();
+        assert_eq!(plan_node.columns(), vec!["username", "email"]);
+        assert_eq!(
+            PlanNode::Update(plan_node).to_string(),
+            "Update set=username='john',email=username\n  SeqScan"
+        );
+
+        assert_eq!(plan("age = 1").unwrap_err(), "unknown column 'age'");
+        assert_eq!(
+            plan("id = 2").unwrap_err(),
+            "primary key 'id' can't be updated"
+        );
+        assert_eq!(
+            plan("email = 'a', email = 'b'").unwrap_err(),
+            "column 'email' is set more than once"
+        );
+        assert_eq!(
+            plan("email = 5").unwrap_err(),
+            "column 'email' is not an Int"
+        );
+        assert_eq!(
+            plan("username = email").unwrap_err(),
+            "email is longer than 32 bytes of 'username'"
+        );
+        let too_long = format!("username = '{}'", "a".repeat(33));
+        assert_eq!(
+            plan(&too_long).unwrap_err(),
+            format!("'{}' is longer than 32 bytes of 'username'", "a".repeat(33))
+        );
+        assert_eq!(
+            plan("username").unwrap_err(),
+            "expected <column> = <value> but got 'username'"
+        );
+        assert_eq!(plan("username = 'a").unwrap_err(), "invalid value ''a'");
+
+        // A comma in a quoted text doesn't separate assignments.
+        let plan_node = plan("username = 'a,b'").unwrap();
+        assert_eq!(plan_node.assignments[0].expression, Expression::text("a,b"));
+    }
+
+    #[test]
+    fn plan_merge_join_only_when_ordered_by_join_key() {
+        let seq_scan = || PlanNode::SeqScan(SeqScanPlanNode { predicate: None });
+
+        let plan_node = PlanNode::merge_join(
+            seq_scan(),
+            PlanNode::Filter(FilterPlanNode {
+                predicate: predicate(),
+                child: Box::new(seq_scan()),
+            }),
+            Expression::column("id"),
+            Expression::column("id"),
+            None,
+        );
+        assert!(matches!(plan_node, Some(PlanNode::MergeJoin(_))));
+
+        let plan_node = PlanNode::merge_join(
+            seq_scan(),
+            seq_scan(),
+            Expression::column("id"),
+            Expression::column("username"),
+            None,
+        );
+        assert!(plan_node.is_none());
+
+        let plan_node = PlanNode::merge_join(
+            PlanNode::Values(ValuesPlanNode { rows: vec![] }),
+            seq_scan(),
+            Expression::column("id"),
+            Expression::column("id"),
+            None,
+        );
+        assert!(plan_node.is_none());
+    }
+
+    #[test]
+    fn plan_sort_only_when_not_ordered() {
+        let seq_scan = || PlanNode::SeqScan(SeqScanPlanNode { predicate: None });
+        let id = || Expression::column("id");
+
+        // Scans already produce rows in id order.
+        assert_eq!(
+            SortPlanNode::plan(seq_scan(), vec![OrderBy::asc(id())]),
+            seq_scan()
+        );
+        assert_eq!(SortPlanNode::plan(seq_scan(), vec![]), seq_scan());
+
+        let plan_node = SortPlanNode::plan(
+            seq_scan(),
+            vec![
+                OrderBy::desc(id()),
+                OrderBy::asc(Expression::column("username")),
+            ],
+        );
+        assert_eq!(
+            plan_node.to_string(),
+            "Sort order_by=id DESC,username ASC\n  SeqScan"
+        );
+        assert!(!plan_node.is_ordered_by(&id()));
+        assert!(
+            SortPlanNode::plan(seq_scan(), vec![OrderBy::asc(Expression::column("email"))])
+                .is_ordered_by(&Expression::column("email"))
+        );
+    }
+
+    #[test]
+    fn plan_limit_over_sort_as_top_n() {
+        let seq_scan = || PlanNode::SeqScan(SeqScanPlanNode { predicate: None });
+
+        let plan_node = LimitPlanNode::plan(seq_scan(), 10, 0, DEFAULT_MAX_TOP_N_ROWS);
+        assert_eq!(plan_node.to_string(), "Limit limit=10\n  SeqScan");
+        assert!(plan_node.is_ordered_by(&Expression::column("id")));
+
+        let sort = SortPlanNode::plan(seq_scan(), vec![OrderBy::desc(Expression::column("id"))]);
+        let plan_node = LimitPlanNode::plan(sort, 10, 5, DEFAULT_MAX_TOP_N_ROWS);
+        assert_eq!(
+            plan_node.to_string(),
+            "Limit limit=10 offset=5\n  TopN limit=15 order_by=id DESC\n    SeqScan"
+        );
+
+        // Too many rows to keep in a heap are sorted instead.
+        let sort = SortPlanNode::plan(seq_scan(), vec![OrderBy::desc(Expression::column("id"))]);
+        let plan_node = LimitPlanNode::plan(sort, 10, 5, 14);
+        assert_eq!(
+            plan_node.to_string(),
+            "Limit limit=10 offset=5\n  Sort order_by=id DESC\n    SeqScan"
+        );
+    }
 }
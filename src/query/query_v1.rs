@@ -1,6 +1,20 @@
+use super::executor::{ExecutionContext, ExecutionEngine};
+use super::expression::{ComparisonOp, Expression};
+use super::parser::{values_to_row, SqlStatement};
+use super::planner::Planner;
+use super::query_plan::{
+    point_key, Assignment, DeletePlanNode, IndexScanPlanNode, InsertPlanNode, OrderBy, PlanNode,
+    UpdatePlanNode,
+};
+use super::result_set::ResultSet;
+use crate::concurrency::{IsolationLevel, Transaction};
+use crate::error::DbError;
 use crate::row::Row;
+use crate::schema::Schema;
 use crate::table::*;
+use parking_lot::RwLock;
 use std::str::FromStr;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub enum MetaCommand {
@@ -9,6 +23,34 @@ pub enum MetaCommand {
     Exit,
     PrintTree,
     PrintPages,
+    Scrub,
+    Ping,
+    Health,
+    Warmup,
+    Occupancy,
+    Tables,
+    Analyze,
+    Stats,
+    // Bytes of the file it can read, see `Table::sync_check`.
+    SyncCheck(usize),
+    // E.g. `.page evict 3`, see `Table::page_command`.
+    Page(PageAction),
+    // Latency of each operation, with percentiles and buckets if it's
+    // `.metrics detail`, see `Table::metrics`.
+    Metrics { detail: bool },
+    // Segment, flushed LSN and retention of the WAL, see `LogStatus`.
+    WalStatus,
+}
+
+/// Action of `.page` on a page of the file by its id, or on every page
+/// of the file in the buffer pool if none is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageAction {
+    List,
+    Pin(usize),
+    Unpin(usize),
+    Evict(usize),
+    Inspect(usize),
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -16,6 +58,9 @@ pub enum StatementType {
     Select,
     Insert,
     Delete,
+    CreateTable,
+    CreateIndex { unique: bool },
+    Update,
 }
 
 impl FromStr for StatementType {
@@ -25,6 +70,7 @@ impl FromStr for StatementType {
             "select" => Ok(StatementType::Select),
             "insert" => Ok(StatementType::Insert),
             "delete" => Ok(StatementType::Delete),
+            "update" => Ok(StatementType::Update),
             _ => Err("unrecognized statement".into()),
         }
     }
@@ -34,8 +80,61 @@ impl FromStr for StatementType {
 pub struct Statement {
     statement_type: StatementType,
     pub row: Option<Row>,
+    // Every row of a multi-row insert, where `row` is unset, see
+    // `Table::insert_batch`.
+    pub rows: Vec<Row>,
+    // Only select every row that satisfy the predicate. Selecting by id
+    // uses `row` instead, so it's a lookup rather than a scan.
+    pub predicate: Option<Expression>,
+    // Table named by a SQL statement, where our positional statements
+    // run against the table they are executed on.
+    pub table: Option<String>,
+    // Columns of the table to create.
+    pub schema: Option<Schema>,
+    // Column of the table to index.
+    pub column: Option<String>,
+    // Columns set by an update, see `UpdatePlanNode`.
+    pub assignments: Vec<Assignment>,
+    // Order of the selected rows, which is by id if empty.
+    pub order_by: Vec<OrderBy>,
+    // Selected rows to return, if limited, after skipping `offset` rows.
+    pub limit: Option<usize>,
+    pub offset: usize,
+    // A select the table can't run by itself, e.g. with a join or a
+    // subquery, which runs through its plan instead, see `query_planned`.
+    pub query: Option<SqlStatement>,
 }
 
+impl Statement {
+    pub fn statement_type(&self) -> &StatementType {
+        &self.statement_type
+    }
+}
+
+/// Meta commands recognized by `handle_meta_command`, e.g. for the REPL
+/// to complete them.
+pub const META_COMMANDS: [&str; 15] = [
+    ".exit",
+    ".tree",
+    ".pages",
+    ".scrub",
+    ".ping",
+    ".health",
+    ".warmup",
+    ".occupancy",
+    ".tables",
+    ".analyze",
+    ".stats",
+    ".sync-check",
+    ".page",
+    ".metrics",
+    ".wal status",
+];
+
+// Bytes `.sync-check` reads from the file unless given a budget, e.g.
+// `.sync-check 65536`, which is 256 pages.
+const SYNC_CHECK_BYTES: usize = 1 << 20;
+
 pub fn handle_meta_command(command: &str) -> MetaCommand {
     if command.eq(".exit") {
         MetaCommand::Exit
@@ -43,40 +142,664 @@ pub fn handle_meta_command(command: &str) -> MetaCommand {
         MetaCommand::PrintTree
     } else if command.eq(".pages") {
         MetaCommand::PrintPages
+    } else if command.eq(".scrub") {
+        MetaCommand::Scrub
+    } else if command.eq(".ping") {
+        MetaCommand::Ping
+    } else if command.eq(".health") {
+        MetaCommand::Health
+    } else if command.eq(".warmup") {
+        MetaCommand::Warmup
+    } else if command.eq(".occupancy") {
+        MetaCommand::Occupancy
+    } else if command.eq(".tables") {
+        MetaCommand::Tables
+    } else if command.eq(".analyze") {
+        MetaCommand::Analyze
+    } else if command.eq(".stats") {
+        MetaCommand::Stats
+    } else if let Some(budget) = command.strip_prefix(".sync-check") {
+        match budget.trim() {
+            "" => MetaCommand::SyncCheck(SYNC_CHECK_BYTES),
+            budget => budget
+                .parse()
+                .map_or(MetaCommand::Unrecognized, MetaCommand::SyncCheck),
+        }
+    } else if command.eq(".metrics") {
+        MetaCommand::Metrics { detail: false }
+    } else if command.eq(".metrics detail") {
+        MetaCommand::Metrics { detail: true }
+    } else if command.eq(".wal status") {
+        MetaCommand::WalStatus
+    } else if command.eq(".page") {
+        MetaCommand::Page(PageAction::List)
+    } else if let Some(args) = command.strip_prefix(".page ") {
+        let args = args.trim();
+        let (action, page_id) = args.split_once(' ').unwrap_or((args, ""));
+        let Ok(page_id) = page_id.trim().parse() else {
+            return MetaCommand::Unrecognized;
+        };
+        match action {
+            "pin" => MetaCommand::Page(PageAction::Pin(page_id)),
+            "unpin" => MetaCommand::Page(PageAction::Unpin(page_id)),
+            "evict" => MetaCommand::Page(PageAction::Evict(page_id)),
+            "inspect" => MetaCommand::Page(PageAction::Inspect(page_id)),
+            _ => MetaCommand::Unrecognized,
+        }
     } else {
         MetaCommand::Unrecognized
     }
 }
 
+/// Prepare either a SQL statement, see `SqlStatement`, or one of our
+/// positional statements, e.g. `insert 1 john john@email.com`.
 pub fn prepare_statement(input: &str) -> Result<Statement, String> {
+    if SqlStatement::is_sql(input) {
+        return Statement::try_from(SqlStatement::parse(input)?);
+    }
+
     match input.split_once(' ') {
         None => {
             let statement_type = StatementType::from_str(input)?;
 
             if statement_type == StatementType::Insert {
                 Err("missing row value for insert".to_string())
+            } else if statement_type == StatementType::Update {
+                Err("missing id and values for update".to_string())
             } else {
                 Ok(Statement {
                     statement_type,
                     row: None,
+                    rows: Vec::new(),
+                    predicate: None,
+                    table: None,
+                    schema: None,
+                    column: None,
+                    assignments: Vec::new(),
+                    order_by: Vec::new(),
+                    limit: None,
+                    offset: 0,
+                    query: None,
                 })
             }
         }
+        Some(("select", rest)) if rest.starts_with("limit") => {
+            let (limit, offset) = limit_offset(rest)?;
+            Ok(Statement {
+                statement_type: StatementType::Select,
+                row: None,
+                rows: Vec::new(),
+                predicate: None,
+                table: None,
+                schema: None,
+                column: None,
+                assignments: Vec::new(),
+                order_by: Vec::new(),
+                limit: Some(limit),
+                offset,
+                query: None,
+            })
+        }
+        Some(("select", range)) if range.contains("..") => Ok(Statement {
+            statement_type: StatementType::Select,
+            row: None,
+            rows: Vec::new(),
+            predicate: Some(range_predicate(range)?),
+            table: None,
+            schema: None,
+            column: None,
+            assignments: Vec::new(),
+            order_by: Vec::new(),
+            limit: None,
+            offset: 0,
+            query: None,
+        }),
+        Some((action, rest)) if action.eq_ignore_ascii_case("update") => update_statement(rest),
         Some((action, rest)) => Ok(Statement {
             statement_type: StatementType::from_str(action)?,
             row: Some(Row::from_str(rest)?),
+            rows: Vec::new(),
+            predicate: None,
+            table: None,
+            schema: None,
+            column: None,
+            assignments: Vec::new(),
+            order_by: Vec::new(),
+            limit: None,
+            offset: 0,
+            query: None,
         }),
     }
 }
 
-pub fn execute_statement(table: &mut Table, statement: &Statement) -> String {
-    match statement.statement_type {
-        StatementType::Select => table.select(statement),
-        StatementType::Insert => table.insert(statement.row.as_ref().unwrap()),
-        StatementType::Delete => table.delete(statement.row.as_ref().unwrap()),
+// Parse `<id> <column>=<value> ...` of an update, e.g.
+// `update 1 username=john email=john@email.com`. Values are always text,
+// as they aren't quoted, so they can't have spaces either.
+//
+// The SQL form, `<table> SET <column> = <value>, ... WHERE id = <id>`, is
+// parsed by `sql_update_statement` instead.
+fn update_statement(input: &str) -> Result<Statement, String> {
+    let is_sql = input
+        .split_whitespace()
+        .nth(1)
+        .is_some_and(|word| word.eq_ignore_ascii_case("set"));
+    if is_sql {
+        return sql_update_statement(input);
+    }
+
+    let mut words = input.split_whitespace();
+    let row = Row::new(words.next().unwrap_or_default(), "", "")?;
+    let assignments = words
+        .map(|word| {
+            let (column, value) = word
+                .split_once('=')
+                .ok_or_else(|| format!("expected <column>=<value> but got '{word}'"))?;
+            Ok(Assignment {
+                column: column.to_string(),
+                expression: Expression::text(value),
+            })
+        })
+        .collect::<Result<_, String>>()?;
+
+    Ok(Statement {
+        statement_type: StatementType::Update,
+        row: Some(row),
+        rows: Vec::new(),
+        predicate: None,
+        table: None,
+        schema: None,
+        column: None,
+        assignments,
+        order_by: Vec::new(),
+        limit: None,
+        offset: 0,
+        query: None,
+    })
+}
+
+// Parse `<table> SET <column> = <value>, ... WHERE id = <id>`, where a
+// value is a quoted text, an integer or a column, see
+// `UpdatePlanNode::parse`.
+fn sql_update_statement(input: &str) -> Result<Statement, String> {
+    let (table, rest) = input.trim().split_once(char::is_whitespace).unwrap();
+    let rest = &rest.trim_start()["set".len()..];
+
+    // Values are quoted, so the last WHERE is the one of the statement.
+    let (set, predicate) = rest
+        .to_ascii_lowercase()
+        .rfind(" where ")
+        .map(|i| (&rest[..i], &rest[i + " where ".len()..]))
+        .ok_or_else(|| "UPDATE requires WHERE id = <id>".to_string())?;
+    let id = predicate
+        .split_once('=')
+        .filter(|(column, _)| column.trim().eq_ignore_ascii_case("id"))
+        .map(|(_, id)| id.trim())
+        .ok_or_else(|| "only WHERE id = <id> is supported".to_string())?;
+    let row = Row::new(id, "", "")?;
+
+    let plan_node = UpdatePlanNode::parse(
+        PlanNode::IndexScan(IndexScanPlanNode { key: row.id }),
+        set,
+        &Schema::users(),
+    )?;
+    Ok(Statement {
+        statement_type: StatementType::Update,
+        row: Some(row),
+        rows: Vec::new(),
+        predicate: None,
+        table: Some(table.to_string()),
+        schema: None,
+        column: None,
+        assignments: plan_node.assignments,
+        order_by: Vec::new(),
+        limit: None,
+        offset: 0,
+        query: None,
+    })
+}
+
+// Parse `limit <count> [offset <count>]`.
+fn limit_offset(input: &str) -> Result<(usize, usize), String> {
+    let count = |count: &str| {
+        count
+            .parse::<usize>()
+            .map_err(|_| format!("invalid count '{count}'"))
+    };
+
+    match input.split_whitespace().collect::<Vec<_>>()[..] {
+        ["limit", limit] => Ok((count(limit)?, 0)),
+        ["limit", limit, "offset", offset] => Ok((count(limit)?, count(offset)?)),
+        _ => Err("expected 'select limit <count> [offset <count>]'".to_string()),
+    }
+}
+
+// Turn `<start>..<end>` into `id >= <start> AND id < <end>`, where the
+// end can be left out to select every row from the start.
+fn range_predicate(range: &str) -> Result<Expression, String> {
+    let (start, end) = range.trim().split_once("..").unwrap();
+    let key = |key: &str| {
+        key.parse::<u32>()
+            .map(|key| Expression::integer(key as i64))
+            .map_err(|_| "invalid range provided".to_string())
+    };
+
+    let start = Expression::comparison(
+        Expression::column("id"),
+        ComparisonOp::GreaterThanOrEqual,
+        key(start)?,
+    );
+    if end.is_empty() {
+        return Ok(start);
+    }
+
+    let end = Expression::comparison(Expression::column("id"), ComparisonOp::LessThan, key(end)?);
+    Ok(Expression::and(start, end))
+}
+
+// TODO (Executor): The REPL runs statements through `Table` rather than
+// the executor, as they don't run in a transaction, and tables only delete
+// a single row by id, so that's the only predicate accepted for DELETE
+// here for now. `Planner` accepts any predicate, see `explain`.
+impl TryFrom<SqlStatement> for Statement {
+    type Error = String;
+
+    fn try_from(statement: SqlStatement) -> Result<Self, Self::Error> {
+        let table = Some(statement.table().to_string());
+
+        let where_id = |predicate: Option<Expression>| match predicate {
+            Some(predicate) => point_key(&predicate)
+                .map(|id| Row::new(&id.to_string(), "", ""))
+                .unwrap_or_else(|| Err("only WHERE id = <id> is supported".to_string()))
+                .map(Some),
+            None => Ok(None),
+        };
+
+        let statement = match statement {
+            SqlStatement::Select { join: Some(_), .. }
+            | SqlStatement::Select {
+                subquery: Some(_), ..
+            } => Ok(Statement {
+                statement_type: StatementType::Select,
+                row: None,
+                rows: Vec::new(),
+                predicate: None,
+                table: None,
+                schema: None,
+                column: None,
+                assignments: Vec::new(),
+                order_by: Vec::new(),
+                limit: None,
+                offset: 0,
+                query: Some(statement),
+            }),
+            SqlStatement::Select {
+                predicate,
+                order_by,
+                limit,
+                offset,
+                ..
+            } => match predicate {
+                Some(predicate) if point_key(&predicate).is_none() => Ok(Statement {
+                    statement_type: StatementType::Select,
+                    row: None,
+                    rows: Vec::new(),
+                    predicate: Some(predicate),
+                    table: None,
+                    schema: None,
+                    column: None,
+                    assignments: Vec::new(),
+                    order_by,
+                    limit,
+                    offset,
+                    query: None,
+                }),
+                predicate => Ok(Statement {
+                    statement_type: StatementType::Select,
+                    row: where_id(predicate)?,
+                    rows: Vec::new(),
+                    predicate: None,
+                    table: None,
+                    schema: None,
+                    column: None,
+                    assignments: Vec::new(),
+                    order_by,
+                    limit,
+                    offset,
+                    query: None,
+                }),
+            },
+            SqlStatement::Insert { rows, .. } if rows.len() == 1 => Ok(Statement {
+                statement_type: StatementType::Insert,
+                row: Some(values_to_row(&rows[0])?),
+                rows: Vec::new(),
+                predicate: None,
+                table: None,
+                schema: None,
+                column: None,
+                assignments: Vec::new(),
+                order_by: Vec::new(),
+                limit: None,
+                offset: 0,
+                query: None,
+            }),
+            SqlStatement::Insert { rows, .. } => Ok(Statement {
+                statement_type: StatementType::Insert,
+                row: None,
+                rows: rows
+                    .iter()
+                    .map(|values| values_to_row(values))
+                    .collect::<Result<_, _>>()?,
+                predicate: None,
+                table: None,
+                schema: None,
+                column: None,
+                assignments: Vec::new(),
+                order_by: Vec::new(),
+                limit: None,
+                offset: 0,
+                query: None,
+            }),
+            SqlStatement::Delete {
+                predicate: None, ..
+            } => Err("DELETE requires WHERE id = <id>".to_string()),
+            SqlStatement::Delete { predicate, .. } => Ok(Statement {
+                statement_type: StatementType::Delete,
+                row: where_id(predicate)?,
+                rows: Vec::new(),
+                predicate: None,
+                table: None,
+                schema: None,
+                column: None,
+                assignments: Vec::new(),
+                order_by: Vec::new(),
+                limit: None,
+                offset: 0,
+                query: None,
+            }),
+            SqlStatement::CreateTable { schema, .. } => Ok(Statement {
+                statement_type: StatementType::CreateTable,
+                row: None,
+                rows: Vec::new(),
+                predicate: None,
+                table: None,
+                schema: Some(schema),
+                column: None,
+                assignments: Vec::new(),
+                order_by: Vec::new(),
+                limit: None,
+                offset: 0,
+                query: None,
+            }),
+            SqlStatement::CreateIndex { column, unique, .. } => Ok(Statement {
+                statement_type: StatementType::CreateIndex { unique },
+                row: None,
+                rows: Vec::new(),
+                predicate: None,
+                table: None,
+                schema: None,
+                column: Some(column),
+                assignments: Vec::new(),
+                order_by: Vec::new(),
+                limit: None,
+                offset: 0,
+                query: None,
+            }),
+        }?;
+
+        Ok(Statement { table, ..statement })
+    }
+}
+
+/// Execute the statement against the table it names, which is another
+/// table of the same database file, or the given table if it names none.
+pub fn execute_statement(table: &Arc<Table>, statement: &Statement) -> String {
+    if statement.statement_type == StatementType::CreateTable {
+        let name = statement.table.as_deref().unwrap();
+        return match table.create_table(name, statement.schema.as_ref().unwrap()) {
+            Ok(()) => format!("created table '{name}'"),
+            Err(err) => err.to_string(),
+        };
+    }
+    if statement.statement_type == StatementType::Update {
+        return execute_update(table, statement).unwrap_or_else(|reason| reason);
+    }
+
+    if let Some(query) = &statement.query {
+        return match query_planned(table, query) {
+            Ok(result_set) => result_set.to_string(),
+            Err(err) => err.to_string(),
+        };
+    }
+
+    table
+        .with_table(statement.table.as_deref(), |table| {
+            match statement.statement_type {
+                StatementType::Select => table.select(statement),
+                StatementType::Insert => match &statement.row {
+                    Some(row) => table.insert(row),
+                    None => table.insert_many(&statement.rows),
+                },
+                StatementType::Delete => table.delete(statement.row.as_ref().unwrap()),
+                StatementType::CreateIndex { unique } => table
+                    .create_index(statement.column.as_deref().unwrap(), unique)
+                    .unwrap_or_else(|err| err.to_string()),
+                StatementType::CreateTable | StatementType::Update => unreachable!(),
+            }
+        })
+        .unwrap_or_else(|err| err.to_string())
+}
+
+/// Select the rows of a statement the table can't select by itself, see
+/// `Statement::query`, through its plan, in a transaction of its own that
+/// runs against the table the statement names.
+pub fn query_planned(table: &Arc<Table>, query: &SqlStatement) -> Result<ResultSet, DbError> {
+    let table = match query.table() {
+        name if name != table.name() => Arc::new(table.open_table(name)?),
+        _ => table.clone(),
+    };
+    let plan_node = Planner::new(table.catalog())
+        .with_max_top_n(table.max_top_n_rows())
+        .plan(query.clone())
+        .map_err(DbError::InvalidInput)?;
+    query_plan(&table, plan_node)
+}
+
+/// Select a page of at most `page_size` rows in id order, which come
+/// strictly after the row with id `after`, or the first page if it's None,
+/// see `PlanNode::paginate`.
+pub fn query_page(
+    table: &Arc<Table>,
+    after: Option<u32>,
+    page_size: usize,
+) -> Result<ResultSet, DbError> {
+    let after_predicate = after.map(|after| {
+        Expression::comparison(
+            Expression::column("id"),
+            ComparisonOp::GreaterThan,
+            Expression::integer(after as i64),
+        )
+    });
+    let plan_node = PlanNode::paginate(PlanNode::scan(after_predicate), after, page_size)
+        .map_err(DbError::InvalidInput)?;
+    query_plan(table, plan_node)
+}
+
+// Run the plan of a select in a transaction of its own.
+fn query_plan(table: &Arc<Table>, plan_node: PlanNode) -> Result<ResultSet, DbError> {
+    let tm = table.transaction_manager();
+    let transaction = tm.begin(IsolationLevel::ReadCommited);
+    let ctx = ExecutionContext::new(table.clone(), table.lock_manager(), transaction.clone());
+    let result_set = ExecutionEngine::new(Arc::new(ctx)).query(plan_node);
+
+    let mut t = transaction.write();
+    match result_set {
+        Ok(_) => tm.commit(table, &mut t)?,
+        Err(_) => tm.abort(table, &mut t),
+    }
+    result_set
+}
+
+/// Update the row of the statement through `UpdateExecutor`, in a
+/// transaction of its own that's committed once the row is updated, or
+/// aborted if it fails, e.g. on a unique index.
+pub fn execute_update(table: &Arc<Table>, statement: &Statement) -> Result<String, String> {
+    let table = &match statement.table.as_deref() {
+        Some(name) if name != table.name() => {
+            Arc::new(table.open_table(name).map_err(|err| err.to_string())?)
+        }
+        _ => table.clone(),
+    };
+    let key = statement.row.as_ref().unwrap().id;
+    let assignments = statement
+        .assignments
+        .iter()
+        .map(|assignment| (assignment.column.as_str(), assignment.expression.clone()))
+        .collect();
+    let plan_node = UpdatePlanNode::new(
+        PlanNode::IndexScan(IndexScanPlanNode { key }),
+        assignments,
+        &Schema::users(),
+    )?;
+
+    let tm = table.transaction_manager();
+    let transaction = tm.begin(IsolationLevel::ReadCommited);
+    let ctx = ExecutionContext::new(table.clone(), table.lock_manager(), transaction.clone());
+    let updated = ExecutionEngine::new(Arc::new(ctx)).execute(PlanNode::Update(plan_node));
+
+    let mut t = transaction.write();
+    match updated {
+        Ok(rows) if rows.is_empty() => {
+            tm.abort(table, &mut t);
+            Ok(format!("row {key} not found"))
+        }
+        Ok(_) => {
+            tm.commit(table, &mut t)
+                .map_err(|err| format!("fail to update row {key}: {err}"))?;
+            Ok(format!("updated {key}"))
+        }
+        Err(err) => {
+            tm.abort(table, &mut t);
+            Err(format!("fail to update row {key}: {err}"))
+        }
     }
 }
 
+/// Execute the statement within a transaction of the table's transaction
+/// manager, e.g. of a session between `begin` and `commit`, through the
+/// executors, so the transaction keeps the locks of the rows it reads and
+/// writes until it ends.
+///
+/// A statement failing while it runs, e.g. on a lock timeout, rolls the
+/// whole transaction back.
+pub fn execute_in(
+    table: &Arc<Table>,
+    statement: &Statement,
+    transaction: &Arc<RwLock<Transaction>>,
+) -> Result<String, DbError> {
+    let result_set = query_in(table, statement, transaction)?;
+    Ok(match statement.statement_type {
+        StatementType::Select => result_set.to_string(),
+        StatementType::Insert => format!("inserted {} rows", result_set.len()),
+        StatementType::Delete => format!("deleted {} rows", result_set.len()),
+        _ => format!("updated {} rows", result_set.len()),
+    })
+}
+
+/// Same as `execute_in`, but return the rows selected, or written by a
+/// write, instead of printing them.
+///
+/// TODO (Transactions): Only the rows of the table the transaction runs
+/// on can be read or written, without ordering or limiting a select, as
+/// the write set doesn't record the table of each write yet.
+pub fn query_in(
+    table: &Arc<Table>,
+    statement: &Statement,
+    transaction: &Arc<RwLock<Transaction>>,
+) -> Result<ResultSet, DbError> {
+    if statement
+        .table
+        .as_deref()
+        .is_some_and(|name| name != table.name())
+    {
+        return Err(DbError::InvalidInput(format!(
+            "a transaction can only use the table '{}'",
+            table.name()
+        )));
+    }
+
+    let row_key = || IndexScanPlanNode {
+        key: statement.row.as_ref().unwrap().id,
+    };
+    let plan_node = match statement.statement_type {
+        StatementType::Select if !statement.order_by.is_empty() || statement.limit.is_some() => {
+            return Err(DbError::InvalidInput(
+                "order by and limit aren't supported in a transaction yet".to_string(),
+            ))
+        }
+        StatementType::Select if statement.query.is_some() => {
+            let query = statement.query.clone().unwrap();
+            Planner::new(table.catalog())
+                .with_max_top_n(table.max_top_n_rows())
+                .plan(query)
+                .map_err(DbError::InvalidInput)?
+        }
+        StatementType::Select if statement.row.is_some() => PlanNode::IndexScan(row_key()),
+        StatementType::Select => PlanNode::scan(statement.predicate.clone()),
+        StatementType::Insert => PlanNode::Insert(InsertPlanNode::values(
+            statement
+                .row
+                .iter()
+                .chain(&statement.rows)
+                .cloned()
+                .collect(),
+        )),
+        StatementType::Delete => PlanNode::Delete(DeletePlanNode {
+            child: Box::new(PlanNode::IndexScan(row_key())),
+        }),
+        StatementType::Update => {
+            let assignments = statement
+                .assignments
+                .iter()
+                .map(|assignment| (assignment.column.as_str(), assignment.expression.clone()))
+                .collect();
+            PlanNode::Update(
+                UpdatePlanNode::new(
+                    PlanNode::IndexScan(row_key()),
+                    assignments,
+                    &Schema::users(),
+                )
+                .map_err(DbError::InvalidInput)?,
+            )
+        }
+        StatementType::CreateTable | StatementType::CreateIndex { .. } => {
+            return Err(DbError::InvalidInput(
+                "tables and indexes can't be created in a transaction".to_string(),
+            ))
+        }
+    };
+
+    let ctx = ExecutionContext::new(table.clone(), table.lock_manager(), transaction.clone());
+    ExecutionEngine::new(Arc::new(ctx))
+        .query(plan_node)
+        .map_err(|err| {
+            table
+                .transaction_manager()
+                .abort(table, &mut transaction.write());
+            DbError::TransactionAborted(Box::new(err))
+        })
+}
+
+/// Plan a SQL statement against the catalog of the table without
+/// executing it, e.g. `EXPLAIN SELECT * FROM users WHERE id > 5`, and
+/// return the plan the executor would run, see `ExecutionEngine::stream`.
+pub fn explain(table: &Table, input: &str) -> Result<String, String> {
+    let statement = SqlStatement::parse(input)?;
+    let plan_node = Planner::new(table.catalog())
+        .with_max_top_n(table.max_top_n_rows())
+        .plan(statement)?;
+    Ok(plan_node.push_down_predicates().to_string())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -111,6 +834,80 @@ mod test {
         assert_eq!(statement.row, Some(Row::new("1", "", "").unwrap()));
     }
 
+    #[test]
+    fn parse_sql_statement() {
+        let statement = prepare_statement("SELECT * FROM users WHERE id = 5;").unwrap();
+        assert_eq!(statement.statement_type, StatementType::Select);
+        assert_eq!(statement.row, Some(Row::new("5", "", "").unwrap()));
+
+        let statement = prepare_statement("select * from users").unwrap();
+        assert_eq!(statement.row, None);
+
+        let statement =
+            prepare_statement("INSERT INTO users VALUES (1, 'john', 'john@email.com')").unwrap();
+        assert_eq!(statement.statement_type, StatementType::Insert);
+        assert_eq!(
+            statement.row,
+            Some(Row::new("1", "john", "john@email.com").unwrap())
+        );
+
+        let statement = prepare_statement("DELETE FROM users WHERE 5 = id").unwrap();
+        assert_eq!(statement.statement_type, StatementType::Delete);
+        assert_eq!(statement.row, Some(Row::new("5", "", "").unwrap()));
+
+        assert_eq!(
+            prepare_statement("delete from users").unwrap_err(),
+            "DELETE requires WHERE id = <id>"
+        );
+        assert_eq!(
+            prepare_statement("delete from users where id > 5").unwrap_err(),
+            "only WHERE id = <id> is supported"
+        );
+
+        let statement =
+            prepare_statement("select * from users where id > 5 and username = 'john'").unwrap();
+        assert_eq!(statement.row, None);
+        assert_eq!(
+            statement.predicate.unwrap().to_string(),
+            "((id > 5) AND (username = 'john'))"
+        );
+    }
+
+    #[test]
+    fn parse_select_with_range() {
+        let statement = prepare_statement("select 10..100").unwrap();
+        assert_eq!(statement.statement_type, StatementType::Select);
+        assert_eq!(statement.row, None);
+        assert_eq!(
+            statement.predicate.unwrap().to_string(),
+            "((id >= 10) AND (id < 100))"
+        );
+
+        let statement = prepare_statement("select 10..").unwrap();
+        assert_eq!(statement.predicate.unwrap().to_string(), "(id >= 10)");
+
+        assert_eq!(
+            prepare_statement("select 10..a").unwrap_err(),
+            "invalid range provided"
+        );
+    }
+
+    #[test]
+    fn parse_select_with_limit() {
+        let statement = prepare_statement("select limit 10 offset 5").unwrap();
+        assert_eq!(statement.limit, Some(10));
+        assert_eq!(statement.offset, 5);
+
+        let statement = prepare_statement("select limit 3").unwrap();
+        assert_eq!((statement.limit, statement.offset), (Some(3), 0));
+
+        assert_eq!(
+            prepare_statement("select limit ten").unwrap_err(),
+            "invalid count 'ten'"
+        );
+        assert!(prepare_statement("select limit 10 5").is_err());
+    }
+
     #[test]
     fn error_when_parse_action_with_non_u32_id() {
         let result = prepare_statement("select apple");
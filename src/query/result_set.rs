@@ -0,0 +1,258 @@
+use crate::row::Row;
+use crate::schema::{Column, Schema};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Format the rows of a `ResultSet` are written in, see `.export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    // One JSON object per row and line, i.e. JSON lines.
+    Json,
+}
+
+impl ExportFormat {
+    /// Format matching the extension of the path, which is CSV unless
+    /// it's `.json` or `.jsonl`.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json" | "jsonl") => ExportFormat::Json,
+            _ => ExportFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!("unknown format '{format}', expected csv or json")),
+        }
+    }
+}
+
+/// Rows returned by a query along with their columns, which are printed
+/// as `(1, user1, user1@email.com)`, one row per line, or written out as
+/// CSV or JSON lines.
+///
+/// A row of a join holds the row of every table it joins, in the order
+/// of the join, where the columns are prefixed by the name of their table,
+/// e.g. `users.id`, see `ResultSet::joined`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResultSet {
+    columns: Vec<Column>,
+    rows: Vec<Vec<Row>>,
+}
+
+impl ResultSet {
+    pub fn new(rows: Vec<Row>) -> Self {
+        Self::joined(&[], rows.into_iter().map(|row| vec![row]).collect())
+    }
+
+    /// Rows of a join of the tables, with a row per table in each of them.
+    /// The columns are only prefixed by their table with more than one
+    /// table.
+    pub fn joined(tables: &[&str], rows: Vec<Vec<Row>>) -> Self {
+        let schema = Schema::users();
+        let columns = match tables {
+            [] | [_] => schema.columns().to_vec(),
+            tables => tables
+                .iter()
+                .flat_map(|table| {
+                    schema.columns().iter().map(move |column| Column {
+                        name: format!("{table}.{}", column.name),
+                        ..column.clone()
+                    })
+                })
+                .collect(),
+        };
+        Self { columns, rows }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn rows(&self) -> &[Vec<Row>] {
+        &self.rows
+    }
+
+    pub fn write(&self, writer: impl Write, format: ExportFormat) -> io::Result<()> {
+        match format {
+            ExportFormat::Csv => self.write_csv(writer),
+            ExportFormat::Json => self.write_json_lines(writer),
+        }
+    }
+
+    /// Write a header with the names of the columns, followed by a line
+    /// per row, quoting the fields that need it as RFC 4180 does.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        let header: Vec<_> = self
+            .columns
+            .iter()
+            .map(|column| csv_field(&column.name))
+            .collect();
+        writeln!(writer, "{}", header.join(","))?;
+        for rows in &self.rows {
+            let fields: Vec<_> = rows
+                .iter()
+                .flat_map(|row| {
+                    [
+                        row.id.to_string(),
+                        csv_field(&row.username()),
+                        csv_field(&row.email()),
+                    ]
+                })
+                .collect();
+            writeln!(writer, "{}", fields.join(","))?;
+        }
+        writer.flush()
+    }
+
+    /// Write a JSON object per row and line, keyed by the names of the
+    /// columns.
+    pub fn write_json_lines(&self, mut writer: impl Write) -> io::Result<()> {
+        for rows in &self.rows {
+            let values = rows.iter().flat_map(|row| {
+                [
+                    row.id.to_string(),
+                    json_string(&row.username()),
+                    json_string(&row.email()),
+                ]
+            });
+            let fields: Vec<_> = self
+                .columns
+                .iter()
+                .zip(values)
+                .map(|(column, value)| format!("{}:{value}", json_string(&column.name)))
+                .collect();
+            writeln!(writer, "{{{}}}", fields.join(","))?;
+        }
+        writer.flush()
+    }
+}
+
+impl fmt::Display for ResultSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rows in &self.rows {
+            let fields: Vec<_> = rows
+                .iter()
+                .map(|row| format!("{}, {}, {}", row.id, row.username(), row.email()))
+                .collect();
+            writeln!(f, "({})", fields.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut output = String::with_capacity(value.len() + 2);
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_csv_and_json_lines() {
+        let result_set = ResultSet::new(vec![
+            Row::new("1", "john", "john@email.com").unwrap(),
+            Row::new("2", "wick,jr", "say \"hi\"").unwrap(),
+        ]);
+        assert_eq!(
+            result_set.to_string(),
+            "(1, john, john@email.com)\n(2, wick,jr, say \"hi\")\n"
+        );
+
+        let mut csv = Vec::new();
+        result_set.write(&mut csv, ExportFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "id,username,email\n1,john,john@email.com\n2,\"wick,jr\",\"say \"\"hi\"\"\"\n"
+        );
+
+        let mut json = Vec::new();
+        result_set.write(&mut json, ExportFormat::Json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"id\":1,\"username\":\"john\",\"email\":\"john@email.com\"}\n\
+             {\"id\":2,\"username\":\"wick,jr\",\"email\":\"say \\\"hi\\\"\"}\n"
+        );
+
+        assert_eq!(
+            ExportFormat::from_path(Path::new("users.jsonl")),
+            ExportFormat::Json
+        );
+        assert_eq!(
+            ExportFormat::from_path(Path::new("users.txt")),
+            ExportFormat::Csv
+        );
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn write_joined_rows() {
+        let result_set = ResultSet::joined(
+            &["users", "orders"],
+            vec![vec![
+                Row::new("1", "john", "john@email.com").unwrap(),
+                Row::new("7", "book", "john@email.com").unwrap(),
+            ]],
+        );
+        assert_eq!(
+            result_set.to_string(),
+            "(1, john, john@email.com, 7, book, john@email.com)\n"
+        );
+
+        let mut csv = Vec::new();
+        result_set.write(&mut csv, ExportFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "users.id,users.username,users.email,orders.id,orders.username,orders.email\n\
+             1,john,john@email.com,7,book,john@email.com\n"
+        );
+
+        let mut json = Vec::new();
+        result_set.write(&mut json, ExportFormat::Json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"users.id\":1,\"users.username\":\"john\",\"users.email\":\"john@email.com\",\
+             \"orders.id\":7,\"orders.username\":\"book\",\"orders.email\":\"john@email.com\"}\n"
+        );
+    }
+}
@@ -2,14 +2,50 @@ use tracing::trace;
 
 use super::log_record::LogRecord;
 use crate::storage::DiskManager;
-use std::{io::Read, path::Path, sync::atomic::AtomicU32, sync::Mutex, thread::JoinHandle};
+use std::{
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::atomic::AtomicU32,
+    sync::Mutex,
+    thread::JoinHandle,
+};
 
 const LOG_BUFFER_SIZE: usize = 4096;
+const DEFAULT_SEGMENT_SIZE: usize = 16 * 1024 * 1024;
+
+// TODO (PITR): Point-in-time recovery needs a base backup plus every log
+// record written after it, replayed up to a target LSN.
+//
+// Writes are logged with their row images now, but the log isn't read
+// back on open yet. Once there's a redo pass, closed log files can be
+// copied to an archive dir and replayed on top of a `restore`.
+
+// The log is split into segment files, e.g. `data.wal.000001`, so old
+// records can be removed a whole file at a time once a checkpoint no
+// longer needs them.
+struct Segments {
+    active: DiskManager,
+    active_id: usize,
+    active_size: usize,
+    // Closed segments that are still retained, oldest first, with the LSN
+    // every record in the segment is smaller than.
+    closed: Vec<(usize, u32)>,
+}
 
-struct LogManager {
-    disk_manager: DiskManager,
+/// Write-ahead log of the changes to the rows of a database file.
+///
+/// A page is only written to the file once every record up to the LSN of
+/// the page is flushed, see `flush_until`, so the changes in the file can
+/// always be redone or undone from the log after a crash.
+pub struct LogManager {
+    path: PathBuf,
+    segment_size: usize,
+    segments: Mutex<Segments>,
     next_lsn: AtomicU32,
-    persistent_lsn: Option<AtomicU32>,
+    // 0 until the first record is flushed, as LSN starts from 1.
+    persistent_lsn: AtomicU32,
+    checkpoint_lsn: AtomicU32,
 
     // Alternatively, we should wrap the following 3 fields
     // in its own data structure and so we can just use a single Mutex to
@@ -21,12 +57,73 @@ struct LogManager {
     join_handle: Option<JoinHandle<()>>,
 }
 
+/// The current segment, flushed LSN and retained segments of the log.
+#[derive(Debug, PartialEq)]
+pub struct LogStatus {
+    pub segment: PathBuf,
+    pub flushed_lsn: Option<u32>,
+    pub checkpoint_lsn: Option<u32>,
+    pub retained_segments: usize,
+}
+
+impl fmt::Display for LogStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lsn = |lsn: Option<u32>| lsn.map_or("none".to_string(), |lsn| lsn.to_string());
+        write!(
+            f,
+            "segment: {}, flushed lsn: {}, checkpoint lsn: {}, retained segments: {}",
+            self.segment.display(),
+            lsn(self.flushed_lsn),
+            lsn(self.checkpoint_lsn),
+            self.retained_segments
+        )
+    }
+}
+
+// The buffers are too large to print, so only print where the log is.
+impl fmt::Debug for LogManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogManager")
+            .field("path", &self.path)
+            .field("next_lsn", &self.next_lsn())
+            .field("persistent_lsn", &self.persistent_lsn())
+            .finish()
+    }
+}
+
+pub fn segment_path(path: &Path, id: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{id:06}"));
+    PathBuf::from(path)
+}
+
 impl LogManager {
     pub fn new(path: impl AsRef<Path>) -> Self {
+        Self::with_segment_size(path, DEFAULT_SEGMENT_SIZE)
+    }
+
+    /// Same as `new`, except a new segment is started once writing to the
+    /// current one would make it larger than `segment_size` bytes.
+    ///
+    /// TRADEOFF: Segments of a previous run are appended to, starting from
+    /// the first one, as the log isn't read back on open yet.
+    pub fn with_segment_size(path: impl AsRef<Path>, segment_size: usize) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let active = DiskManager::new(segment_path(&path, 1));
+        let active_size = active.file_len;
+
         Self {
-            disk_manager: DiskManager::new(path),
+            path,
+            segment_size,
+            segments: Mutex::new(Segments {
+                active,
+                active_id: 1,
+                active_size,
+                closed: Vec::new(),
+            }),
             next_lsn: AtomicU32::new(1),
-            persistent_lsn: None,
+            persistent_lsn: AtomicU32::new(0),
+            checkpoint_lsn: AtomicU32::new(0),
             log_buffer: Mutex::new([0; LOG_BUFFER_SIZE]),
             flush_buffer: Mutex::new([0; LOG_BUFFER_SIZE]),
             offset: Mutex::new(0),
@@ -39,9 +136,16 @@ impl LogManager {
     }
 
     pub fn persistent_lsn(&self) -> Option<u32> {
-        self.persistent_lsn
-            .as_ref()
-            .map(|p_lsn| p_lsn.load(std::sync::atomic::Ordering::SeqCst))
+        Some(
+            self.persistent_lsn
+                .load(std::sync::atomic::Ordering::SeqCst),
+        )
+        .filter(|lsn| *lsn > 0)
+    }
+
+    /// LSN of the last record appended, which may not be flushed yet.
+    pub fn last_lsn(&self) -> Option<u32> {
+        Some(self.next_lsn() - 1).filter(|lsn| *lsn > 0)
     }
 
     pub fn offset(&self) -> usize {
@@ -53,16 +157,26 @@ impl LogManager {
     }
 
     pub fn append_log(&self, log_record: &mut LogRecord) -> u32 {
-        // One Lock
+        // The LSN is assigned while holding the offset lock, so records
+        // are buffered in LSN order, and a flushed buffer holds every
+        // record before the next LSN.
+        let mut offset = self.offset.lock().unwrap();
         let lsn = self
             .next_lsn
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         log_record.lsn = Some(lsn);
 
-        let bytes = bincode::serialize(&log_record).unwrap();
+        // Records are prefixed by their length, as their size depends
+        // on the rows they carry.
+        let record = bincode::serialize(&log_record).unwrap();
+        let mut bytes = (record.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&record);
+        assert!(
+            bytes.len() <= LOG_BUFFER_SIZE,
+            "log record of {} bytes doesn't fit in the log buffer",
+            bytes.len()
+        );
 
-        // Another lock
-        let mut offset = self.offset.lock().unwrap();
         let mut log_buffer = self.log_buffer.lock().unwrap();
         let mut end = *offset + bytes.len();
 
@@ -85,7 +199,7 @@ impl LogManager {
             drop(flush_buffer);
 
             // Flush manually once we full.
-            self.flush(*offset);
+            self.flush(*offset, lsn);
 
             // Reset the range as well.
             *offset = 0;
@@ -98,44 +212,137 @@ impl LogManager {
         lsn
     }
 
-    pub fn flush(&self, offset: usize) {
+    /// Flush the flush buffer up to `offset`, where every record in it has
+    /// a smaller LSN than `end_lsn`.
+    pub fn flush(&self, offset: usize, end_lsn: u32) {
         trace!("flush WAL to disk up to offset {offset}");
         let mut flush_buffer = self.flush_buffer.lock().unwrap();
-        self.disk_manager.append(&flush_buffer[0..offset]).unwrap();
+        self.write(&flush_buffer[0..offset], end_lsn);
         *flush_buffer = [0; LOG_BUFFER_SIZE];
     }
 
     pub fn flush_log_buffer(&self) {
-        let log_buffer = self.log_buffer.lock().unwrap();
-        let offset = self.offset.lock().unwrap();
+        let mut offset = self.offset.lock().unwrap();
+        let mut log_buffer = self.log_buffer.lock().unwrap();
         trace!("flush WAL from log_buffer up to offset: {offset}");
-        self.disk_manager.append(&log_buffer[0..*offset]).unwrap();
+        self.write(&log_buffer[0..*offset], self.next_lsn());
+        *log_buffer = [0; LOG_BUFFER_SIZE];
+        *offset = 0;
     }
 
-    pub fn get_logs(&self) -> Vec<LogRecord> {
-        let mut reader = self.disk_manager.reader();
-        let mut bytes = [0; 18];
-        let mut records = Vec::new();
+    /// Flush the log buffer unless every record up to `lsn` is already
+    /// flushed, which is the WAL rule before writing a page with that LSN.
+    pub fn flush_until(&self, lsn: u32) {
+        if lsn == 0
+            || self
+                .persistent_lsn()
+                .is_some_and(|persistent| persistent >= lsn)
+        {
+            return;
+        }
+
+        self.flush_log_buffer();
+    }
+
+    // Append the bytes to the current segment, starting a new segment
+    // first if they don't fit. Records are never split across segments.
+    fn write(&self, bytes: &[u8], end_lsn: u32) {
+        let mut segments = self.segments.lock().unwrap();
+        if segments.active_size > 0 && segments.active_size + bytes.len() > self.segment_size {
+            let closed_id = segments.active_id;
+            // The records still in the buffer being written aren't part
+            // of the closed segment, so the first of them is its end.
+            let closed_end_lsn = self
+                .persistent_lsn
+                .load(std::sync::atomic::Ordering::SeqCst)
+                + 1;
+            trace!("rotate WAL segment {closed_id}, ending at lsn {closed_end_lsn}");
+
+            segments.closed.push((closed_id, closed_end_lsn));
+            segments.active_id += 1;
+            segments.active = DiskManager::new(segment_path(&self.path, segments.active_id));
+            segments.active_size = segments.active.file_len;
+        }
+
+        segments.active.append(bytes).unwrap();
+        segments.active_size += bytes.len();
+        self.persistent_lsn
+            .store(end_lsn - 1, std::sync::atomic::Ordering::SeqCst);
+    }
 
-        while let Ok(()) = reader.read_exact(&mut bytes) {
-            if let Ok(record) = bincode::deserialize(&bytes) {
-                records.push(record);
-            } else {
-                // If we failed to deserialize it could be
-                // that we have some padding in between, so let's
-                // move the offset and try again.
-                let _ = reader.seek_relative(1);
+    /// Record that every change up to `lsn` is persisted in the data file,
+    /// and remove the closed segments that only hold records up to it.
+    pub fn checkpoint(&self, lsn: u32) {
+        self.checkpoint_lsn
+            .fetch_max(lsn, std::sync::atomic::Ordering::SeqCst);
+
+        let mut segments = self.segments.lock().unwrap();
+        let checkpoint_lsn = self
+            .checkpoint_lsn
+            .load(std::sync::atomic::Ordering::SeqCst);
+        segments.closed.retain(|(id, end_lsn)| {
+            if *end_lsn > checkpoint_lsn + 1 {
+                return true;
             }
+
+            trace!("remove WAL segment {id}, as checkpoint is at lsn {checkpoint_lsn}");
+            let _ = std::fs::remove_file(segment_path(&self.path, *id));
+            false
+        });
+    }
+
+    pub fn status(&self) -> LogStatus {
+        let segments = self.segments.lock().unwrap();
+        let checkpoint_lsn = self
+            .checkpoint_lsn
+            .load(std::sync::atomic::Ordering::SeqCst);
+
+        LogStatus {
+            segment: segment_path(&self.path, segments.active_id),
+            flushed_lsn: self.persistent_lsn(),
+            checkpoint_lsn: Some(checkpoint_lsn).filter(|lsn| *lsn > 0),
+            retained_segments: segments.closed.len(),
+        }
+    }
+
+    /// Read the records of every retained segment, oldest first.
+    pub fn get_logs(&self) -> Vec<LogRecord> {
+        let segments = self.segments.lock().unwrap();
+        let mut records = Vec::new();
+        for (id, _) in &segments.closed {
+            let disk_manager = DiskManager::new(segment_path(&self.path, *id));
+            read_logs(&disk_manager, &mut records);
         }
+        read_logs(&segments.active, &mut records);
 
         records
     }
 }
 
+// Read the records of the segment, stopping at the first one that is
+// partially written, e.g. by a crash in the middle of a flush.
+fn read_logs(disk_manager: &DiskManager, records: &mut Vec<LogRecord>) {
+    let mut reader = disk_manager.reader();
+    let mut len = [0; 4];
+
+    while let Ok(()) = reader.read_exact(&mut len) {
+        let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+        if reader.read_exact(&mut bytes).is_err() {
+            return;
+        }
+
+        match bincode::deserialize(&bytes) {
+            Ok(record) => records.push(record),
+            Err(_) => return,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::recovery::log_record::LogRecordType;
+    use crate::row::Row;
     use std::sync::Arc;
 
     #[test]
@@ -152,7 +359,7 @@ mod test {
         lm.append_log(&mut lr);
         assert_eq!(lr.lsn, Some(2));
 
-        let _ = std::fs::remove_file(file);
+        cleanup(&file);
     }
 
     #[test]
@@ -178,7 +385,7 @@ mod test {
             lsn += 1;
         }
 
-        let _ = std::fs::remove_file(file);
+        cleanup(&file);
     }
 
     #[test]
@@ -202,7 +409,7 @@ mod test {
             h.join().unwrap();
         }
 
-        let _ = std::fs::remove_file(file);
+        cleanup(&file);
     }
 
     #[test]
@@ -236,6 +443,81 @@ mod test {
             lsn += 1;
         }
 
-        let _ = std::fs::remove_file(file);
+        cleanup(&file);
+    }
+
+    #[test]
+    fn rotate_segments_and_remove_them_after_checkpoint() {
+        let file = format!("test_{:?}.wal", std::thread::current().id());
+        let log_manager = LogManager::with_segment_size(&file, LOG_BUFFER_SIZE);
+
+        // Every swap flushes 157 records of 26 bytes, including their
+        // length, which fills a segment, so each flush starts a new one.
+        for i in 1..500 {
+            let mut lr = LogRecord::new(i, None, LogRecordType::Insert);
+            log_manager.append_log(&mut lr);
+        }
+        log_manager.flush_log_buffer();
+
+        assert_eq!(
+            log_manager.status(),
+            LogStatus {
+                segment: segment_path(Path::new(&file), 4),
+                flushed_lsn: Some(499),
+                checkpoint_lsn: None,
+                retained_segments: 3,
+            }
+        );
+        assert_eq!(log_manager.get_logs().len(), 499);
+
+        // The second segment still has records after the checkpoint.
+        log_manager.checkpoint(300);
+        assert!(!segment_path(Path::new(&file), 1).exists());
+        assert_eq!(log_manager.status().retained_segments, 2);
+        assert_eq!(log_manager.get_logs().len(), 499 - 157);
+
+        log_manager.checkpoint(489);
+        assert_eq!(
+            log_manager.status().to_string(),
+            format!(
+                "segment: {}, flushed lsn: 499, checkpoint lsn: 489, retained segments: 0",
+                segment_path(Path::new(&file), 4).display()
+            )
+        );
+
+        cleanup(&file);
+    }
+
+    #[test]
+    fn read_back_row_images_and_flush_until_lsn() {
+        let file = format!("test_{:?}.wal", std::thread::current().id());
+        let log_manager = LogManager::new(&file);
+        assert_eq!(log_manager.last_lsn(), None);
+
+        let old_row = Row::new("1", "user1", "user1@email.com").unwrap();
+        let new_row = Row::new("1", "user2", "user2@email.com").unwrap();
+        let mut insert = LogRecord::row_change(1, None, LogRecordType::Insert, 0, &old_row);
+        let lsn = log_manager.append_log(&mut insert);
+        let mut update = LogRecord::update(1, Some(lsn), 0, &old_row, &new_row);
+        log_manager.append_log(&mut update);
+        assert_eq!(log_manager.last_lsn(), Some(2));
+
+        log_manager.flush_until(0);
+        assert_eq!(log_manager.persistent_lsn(), None);
+        log_manager.flush_until(1);
+        assert_eq!(log_manager.persistent_lsn(), Some(2));
+
+        let records = log_manager.get_logs();
+        assert_eq!(records, vec![insert, update]);
+        assert_eq!(records[1].old_row(), Some(&old_row));
+        assert_eq!(records[1].row(), Some(&new_row));
+
+        cleanup(&file);
+    }
+
+    fn cleanup(file: &str) {
+        for id in 1..10 {
+            let _ = std::fs::remove_file(segment_path(Path::new(file), id));
+        }
     }
 }
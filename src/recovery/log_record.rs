@@ -1,7 +1,7 @@
-// use crate::{concurrency::RowID, row::Row};
+use crate::row::Row;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LogRecordType {
     Invalid,
     Insert,
@@ -13,9 +13,17 @@ pub enum LogRecordType {
     Commit,
     Abort,
     NewPage,
+    CreateTable,
+    DropTable,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// TODO (Parallel redo): Redo could be split by page id across worker
+// threads, keeping the records of a page in LSN order within a worker,
+// with a dirty page table shared between them.
+//
+// Records carry the page they changed and the row images now, but there's
+// no redo pass that replays them on open yet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LogRecord {
     // Common Header
     log_type: LogRecordType,
@@ -27,22 +35,17 @@ pub struct LogRecord {
     // as we could just tranverse the log records of a transaction through
     // following the prev_lsn link.
     prev_lsn: Option<u32>,
-    // Insert
-    // insert_rid: Option<RowID>,
-    // insert_row: Option<Row>,
-
-    // Delete
-    // delete_rid: Option<RowID>,
-    // delete_row: Option<Row>,
-
-    // Update
-    // update_rid: Option<RowID>,
-    // old_row: Option<Row>,
-    // new_row: Option<Row>,
-
-    // New Page
-    // prev_page_id: Option<usize>,
-    // page_id: Option<usize>,
+
+    // Page the row is changed in. Rows move between pages on splits and
+    // merges, so the row is redone by its key rather than by page.
+    page_id: Option<usize>,
+    // Row inserted or deleted, or the row after an update. It's the redo
+    // image of an insert and the undo image of a delete.
+    row: Option<Row>,
+    // Row before an update, which is its undo image.
+    old_row: Option<Row>,
+    // Table created or dropped, whose root is `page_id`.
+    table: Option<String>,
 }
 
 impl LogRecord {
@@ -53,21 +56,79 @@ impl LogRecord {
             txn_id,
             prev_lsn,
             log_type,
-            // insert_rid: None,
-            // insert_row: None,
+            page_id: None,
+            row: None,
+            old_row: None,
+            table: None,
+        }
+    }
 
-            // delete_rid: None,
-            // delete_row: None,
+    /// Record of a change to a row in the page, where the row is the
+    /// inserted or deleted row, or the row after an update.
+    pub fn row_change(
+        txn_id: u32,
+        prev_lsn: Option<u32>,
+        log_type: LogRecordType,
+        page_id: usize,
+        row: &Row,
+    ) -> Self {
+        Self {
+            page_id: Some(page_id),
+            row: Some(row.clone()),
+            ..Self::new(txn_id, prev_lsn, log_type)
+        }
+    }
 
-            // update_rid: None,
-            // old_row: None,
-            // new_row: None,
+    /// Record of an update of the row in the page from `old_row`.
+    pub fn update(
+        txn_id: u32,
+        prev_lsn: Option<u32>,
+        page_id: usize,
+        old_row: &Row,
+        new_row: &Row,
+    ) -> Self {
+        Self {
+            old_row: Some(old_row.clone()),
+            ..Self::row_change(txn_id, prev_lsn, LogRecordType::Update, page_id, new_row)
+        }
+    }
 
-            // prev_page_id: None,
-            // page_id: None,
+    /// Record of a change to the catalog, i.e. a table created, or dropped
+    /// when its creation is rolled back.
+    pub fn table_change(
+        txn_id: u32,
+        prev_lsn: Option<u32>,
+        log_type: LogRecordType,
+        name: &str,
+        root_page_num: usize,
+    ) -> Self {
+        Self {
+            page_id: Some(root_page_num),
+            table: Some(name.to_string()),
+            ..Self::new(txn_id, prev_lsn, log_type)
         }
     }
 
+    pub fn log_type(&self) -> LogRecordType {
+        self.log_type
+    }
+
+    pub fn page_id(&self) -> Option<usize> {
+        self.page_id
+    }
+
+    pub fn row(&self) -> Option<&Row> {
+        self.row.as_ref()
+    }
+
+    pub fn old_row(&self) -> Option<&Row> {
+        self.old_row.as_ref()
+    }
+
+    pub fn table(&self) -> Option<&str> {
+        self.table.as_deref()
+    }
+
     pub fn from_bytes(bytes: Vec<u8>) -> Self {
         bincode::deserialize(&bytes).unwrap()
     }
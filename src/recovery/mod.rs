@@ -1,2 +1,9 @@
 mod log_manager;
 mod log_record;
+mod statement_log;
+
+pub use self::{
+    log_manager::LogManager,
+    log_record::{LogRecord, LogRecordType},
+    statement_log::{LoggedTransaction, StatementLog},
+};
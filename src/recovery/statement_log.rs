@@ -0,0 +1,247 @@
+use crate::storage::crc32;
+use parking_lot::Mutex;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+// First line of the log, which names the version of the format, so a log
+// written by another version is rejected instead of misread.
+const HEADER: &str = "-- mini-db statement log v1";
+
+/// A committed transaction of the statement log, in commit order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggedTransaction {
+    pub txn_id: u32,
+    pub statements: Vec<String>,
+}
+
+/// Logical log of the committed statements of a database file, which can
+/// be replayed on a fresh database to reproduce its state, e.g. to
+/// bootstrap a replica. See `Table::with_statement_log`.
+///
+/// Each transaction is written as a `BEGIN` record, its statements and a
+/// `COMMIT` record, one record per line, prefixed by the CRC-32 of the
+/// rest of the line:
+///
+/// ```text
+/// -- mini-db statement log v1
+/// df27d416 1 BEGIN
+/// 0dcb0c2e 1 insert into users values (1, 'john', 'john@email.com')
+/// ec16fd9e 1 COMMIT
+/// ```
+///
+/// TRADEOFF: Statements are logged in the order their transactions
+/// commit, which is only the order they were applied in as long as
+/// concurrent transactions don't write the same rows, as rows aren't
+/// locked by the auto-commit writes.
+///
+/// TODO (WAL shipping): Once the write-ahead log is replayed on open,
+/// replicas can follow its records instead, which also covers the
+/// changes made without a statement, e.g. `Table::analyze`.
+pub struct StatementLog {
+    // The file and the id of the next transaction, locked together so
+    // transactions are written whole and with increasing ids.
+    file: Mutex<(File, u32)>,
+}
+
+impl StatementLog {
+    /// Open the log to append to, creating it if it doesn't exist.
+    ///
+    /// A transaction without its commit record at the end of the log,
+    /// e.g. from a crash while appending, is cut off.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<StatementLog> {
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        if content.is_empty() {
+            writeln!(file, "{HEADER}")?;
+            file.sync_data()?;
+            return Ok(StatementLog {
+                file: Mutex::new((file, 1)),
+            });
+        }
+
+        let log = parse(&content).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if log.committed_len < content.len() {
+            file.set_len(log.committed_len as u64)?;
+            file.sync_data()?;
+        }
+        Ok(StatementLog {
+            file: Mutex::new((file, log.last_txn_id + 1)),
+        })
+    }
+
+    /// Append the statements as a committed transaction, returning its id
+    /// once it's synced to disk.
+    pub fn append(&self, statements: &[String]) -> io::Result<u32> {
+        if let Some(statement) = statements.iter().find(|s| s.contains('\n')) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("statement spans several lines: {statement:?}"),
+            ));
+        }
+
+        let mut file = self.file.lock();
+        let txn_id = file.1;
+        let mut records = record(txn_id, "BEGIN");
+        for statement in statements {
+            records.push_str(&record(txn_id, statement));
+        }
+        records.push_str(&record(txn_id, "COMMIT"));
+
+        file.0.write_all(records.as_bytes())?;
+        file.0.sync_data()?;
+        file.1 += 1;
+        Ok(txn_id)
+    }
+
+    /// Read the committed transactions of the log, failing on the first
+    /// record that doesn't match its checksum.
+    pub fn read(path: impl AsRef<Path>) -> Result<Vec<LoggedTransaction>, String> {
+        let content = std::fs::read_to_string(&path)
+            .map_err(|err| format!("{}: {err}", path.as_ref().display()))?;
+        parse(&content).map(|log| log.transactions)
+    }
+}
+
+fn record(txn_id: u32, statement: &str) -> String {
+    let body = format!("{txn_id} {statement}");
+    format!("{:08x} {body}\n", crc32(body.as_bytes()))
+}
+
+struct ParsedLog {
+    transactions: Vec<LoggedTransaction>,
+    // Bytes up to the end of the last commit record.
+    committed_len: usize,
+    last_txn_id: u32,
+}
+
+fn parse(content: &str) -> Result<ParsedLog, String> {
+    let mut lines = content.split_inclusive('\n');
+    if lines.next().map(str::trim_end) != Some(HEADER) {
+        return Err(format!("not a statement log, expected '{HEADER}'"));
+    }
+
+    let mut log = ParsedLog {
+        transactions: Vec::new(),
+        committed_len: HEADER.len() + 1,
+        last_txn_id: 0,
+    };
+    let mut current: Option<LoggedTransaction> = None;
+    let mut offset = log.committed_len;
+
+    for (i, line) in lines.enumerate() {
+        let line_num = i + 2;
+        offset += line.len();
+        // A record is only complete with its newline, so a partial write
+        // at the end of the log is ignored.
+        let Some(line) = line.strip_suffix('\n') else {
+            break;
+        };
+
+        let (checksum, body) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("line {line_num}: expected '<checksum> <record>'"))?;
+        if u32::from_str_radix(checksum, 16) != Ok(crc32(body.as_bytes())) {
+            return Err(format!("line {line_num}: checksum mismatch"));
+        }
+        let (txn_id, statement) = body
+            .split_once(' ')
+            .and_then(|(txn_id, statement)| Some((txn_id.parse::<u32>().ok()?, statement)))
+            .ok_or_else(|| format!("line {line_num}: expected '<txn id> <statement>'"))?;
+
+        match (statement, current.as_mut()) {
+            ("BEGIN", None) => {
+                current = Some(LoggedTransaction {
+                    txn_id,
+                    statements: Vec::new(),
+                });
+            }
+            ("COMMIT", Some(transaction)) if transaction.txn_id == txn_id => {
+                log.last_txn_id = log.last_txn_id.max(txn_id);
+                log.transactions.push(current.take().unwrap());
+                log.committed_len = offset;
+            }
+            (statement, Some(transaction))
+                if transaction.txn_id == txn_id && statement != "BEGIN" =>
+            {
+                transaction.statements.push(statement.to_string());
+            }
+            _ => {
+                return Err(format!(
+                    "line {line_num}: unexpected record of transaction {txn_id}"
+                ))
+            }
+        }
+    }
+
+    Ok(log)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn append_and_read_statement_log() {
+        let path = format!("test-{:?}.statements", std::thread::current().id());
+        let _ = std::fs::remove_file(&path);
+        let statements = |ids: &[u32]| -> Vec<String> {
+            ids.iter()
+                .map(|i| format!("insert into users values ({i}, 'user{i}', 'it''s me')"))
+                .collect()
+        };
+
+        assert_eq!(record(1, "BEGIN"), "df27d416 1 BEGIN\n");
+
+        let log = StatementLog::open(&path).unwrap();
+        assert_eq!(log.append(&statements(&[1, 2])).unwrap(), 1);
+        assert_eq!(log.append(&statements(&[3])).unwrap(), 2);
+        assert!(log.append(&["select\n1".to_string()]).is_err());
+        drop(log);
+
+        // A transaction cut short by a crash is dropped on open, and the
+        // ids continue after the last transaction.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        let partial = record(3, "BEGIN") + &record(3, "delete from users where id = 1");
+        file.write_all(partial.as_bytes()).unwrap();
+        file.write_all(b"1a2b").unwrap();
+        assert_eq!(StatementLog::read(&path).unwrap().len(), 2);
+        let log = StatementLog::open(&path).unwrap();
+        assert_eq!(log.append(&statements(&[4])).unwrap(), 3);
+
+        let transactions = StatementLog::read(&path).unwrap();
+        assert_eq!(
+            transactions,
+            vec![
+                LoggedTransaction {
+                    txn_id: 1,
+                    statements: statements(&[1, 2]),
+                },
+                LoggedTransaction {
+                    txn_id: 2,
+                    statements: statements(&[3]),
+                },
+                LoggedTransaction {
+                    txn_id: 3,
+                    statements: statements(&[4]),
+                },
+            ]
+        );
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, content.replacen("user3", "user9", 1)).unwrap();
+        assert_eq!(
+            StatementLog::read(&path),
+            Err("line 7: checksum mismatch".to_string())
+        );
+        std::fs::write(&path, "1 BEGIN\n").unwrap();
+        assert!(StatementLog::open(&path).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::recovery::LoggedTransaction;
+use crate::table::Table;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Replay a captured statement log against a database, e.g. to reproduce
+// a performance issue reported by users.
+//
+// Each line of the log is a statement prefixed by the milliseconds since
+// the capture started, e.g.
+//
+//     0 insert 1 john john@email.com
+//     15 select 1
+//
+// Empty lines and lines starting with `--` are skipped.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub offset: Duration,
+    pub statement: String,
+}
+
+pub fn parse_log(log: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    for (i, line) in log.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("--") {
+            continue;
+        }
+
+        let (offset, statement) = line
+            .split_once(' ')
+            .and_then(|(offset, statement)| Some((offset.parse::<u64>().ok()?, statement)))
+            .ok_or_else(|| format!("line {}: expected '<offset ms> <statement>'", i + 1))?;
+        entries.push(Entry {
+            offset: Duration::from_millis(offset),
+            statement: statement.to_string(),
+        });
+    }
+
+    Ok(entries)
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub statements: usize,
+    pub errors: usize,
+    pub elapsed: Duration,
+    // Sorted latency of every statement.
+    latencies: Vec<Duration>,
+}
+
+impl Report {
+    /// Latency of the given percentile, e.g. 0.99 for p99.
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let index = ((self.latencies.len() as f64 * p).ceil() as usize).saturating_sub(1);
+        self.latencies[index.min(self.latencies.len() - 1)]
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "replayed {} statements ({} failed) in {:?}, p50 {:?}, p99 {:?}, max {:?}",
+            self.statements,
+            self.errors,
+            self.elapsed,
+            self.percentile(0.5),
+            self.percentile(0.99),
+            self.percentile(1.0)
+        )
+    }
+}
+
+/// Replay the entries with `jobs` workers, where entries are assigned to
+/// workers round robin, and each worker runs its entries in order.
+///
+/// With a `speed` of 1.0, each statement starts at its offset, 2.0 replays
+/// twice as fast, and so on. With `None`, statements are replayed as fast
+/// as possible.
+///
+/// TRADEOFF: Statements assigned to different workers can run in another
+/// order than they were captured, so a replay with more than one job only
+/// reproduces the load, not the exact result of the captured workload.
+pub fn replay(table: Arc<Table>, entries: Vec<Entry>, jobs: usize, speed: Option<f64>) -> Report {
+    let mut assigned: Vec<Vec<Entry>> = vec![Vec::new(); jobs];
+    for (i, entry) in entries.into_iter().enumerate() {
+        assigned[i % jobs].push(entry);
+    }
+
+    let start = Instant::now();
+    let handles: Vec<_> = assigned
+        .into_iter()
+        .map(|entries| {
+            let table = table.clone();
+            std::thread::spawn(move || {
+                let mut report = Report::default();
+                for entry in entries {
+                    if let Some(speed) = speed {
+                        let due = entry.offset.div_f64(speed);
+                        if let Some(wait) = due.checked_sub(start.elapsed()) {
+                            std::thread::sleep(wait);
+                        }
+                    }
+
+                    let statement_start = Instant::now();
+                    if crate::execute_input(&table, &entry.statement).is_err() {
+                        report.errors += 1;
+                    }
+                    report.latencies.push(statement_start.elapsed());
+                    report.statements += 1;
+                }
+                report
+            })
+        })
+        .collect();
+
+    let mut report = Report::default();
+    for handle in handles {
+        let worker = handle.join().unwrap();
+        report.statements += worker.statements;
+        report.errors += worker.errors;
+        report.latencies.extend(worker.latencies);
+    }
+    report.elapsed = start.elapsed();
+    report.latencies.sort_unstable();
+    report
+}
+
+/// Replay the transactions of a statement log, see `StatementLog`, on a
+/// fresh database, so it ends up with the tables and rows of the database
+/// the log was written for. Return the number of transactions replayed.
+///
+/// TRADEOFF: Transactions are replayed one statement at a time, so a
+/// crash halfway leaves a partial transaction behind, and the bootstrap
+/// has to start over from a fresh database.
+pub fn bootstrap(
+    table: &Arc<Table>,
+    transactions: Vec<LoggedTransaction>,
+) -> Result<usize, String> {
+    if table.table_names().len() > 1 || table.iter().next().is_some() {
+        return Err("bootstrap needs a fresh database".to_string());
+    }
+
+    for transaction in &transactions {
+        for statement in &transaction.statements {
+            crate::execute_input(table, statement)
+                .map_err(|err| format!("transaction {}: {err}", transaction.txn_id))?;
+        }
+    }
+    Ok(transactions.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::concurrency::{IsolationLevel, LockManager, TransactionManager};
+    use crate::query::prepare_statement;
+    use crate::recovery::StatementLog;
+    use crate::row::Row;
+    use crate::schema::Schema;
+    use std::str::FromStr;
+
+    #[test]
+    fn parse_statement_log() {
+        let log = "
+            -- captured from production
+            0 insert 1 john john@email.com
+            15 select 1
+        ";
+        assert_eq!(
+            parse_log(log),
+            Ok(vec![
+                Entry {
+                    offset: Duration::ZERO,
+                    statement: "insert 1 john john@email.com".to_string()
+                },
+                Entry {
+                    offset: Duration::from_millis(15),
+                    statement: "select 1".to_string()
+                },
+            ])
+        );
+        assert_eq!(
+            parse_log("select 1"),
+            Err("line 1: expected '<offset ms> <statement>'".to_string())
+        );
+    }
+
+    #[test]
+    fn replay_with_concurrency_and_speed() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let table = Arc::new(Table::new(&path, 8));
+
+        let mut log: String = (1..=40)
+            .map(|i| format!("{i} insert {i} user{i} user{i}@email.com\n"))
+            .collect();
+        log.push_str("40 unknown statement\n");
+        let entries = parse_log(&log).unwrap();
+
+        // At 4x, the last statement is due after 10ms.
+        let report = replay(table.clone(), entries, 4, Some(4.0));
+        assert_eq!(report.statements, 41);
+        assert_eq!(report.errors, 1);
+        assert!(report.elapsed >= Duration::from_millis(10));
+        assert!(report.percentile(0.5) <= report.percentile(1.0));
+
+        let rows = table.select_rows(&prepare_statement("select").unwrap());
+        assert_eq!(rows.len(), 40);
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn bootstrap_from_statement_log() {
+        let id = format!("{:?}", std::thread::current().id());
+        let (source, target, log_path) = (
+            format!("test-{id}.db"),
+            format!("test-{id}-replica.db"),
+            format!("test-{id}.statements"),
+        );
+        let cleanup = || {
+            for path in [&source, &target, &log_path] {
+                let _ = std::fs::remove_file(path);
+                let _ = std::fs::remove_file(format!("{path}.catalog"));
+            }
+        };
+        cleanup();
+
+        let lock_manager = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lock_manager.clone());
+        let table = Table::with_lock_manager(&source, 8, lock_manager)
+            .with_statement_log(Arc::new(StatementLog::open(&log_path).unwrap()));
+        let table = Arc::new(table);
+        for i in 1..=5 {
+            table.insert(&Row::from_str(&format!("{i} user{i} it's{i}@email.com")).unwrap());
+        }
+        table.delete(&Row::new("2", "", "").unwrap());
+        table.create_table("orders", &Schema::users()).unwrap();
+        let orders = table.open_table("orders").unwrap();
+        orders.insert(&Row::new("1", "order1", "order1@email.com").unwrap());
+
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        let row = Row::new("3", "user3", "it's3@email.com").unwrap();
+        let new_row = Row::new("3", "john", "").unwrap();
+        let rid = table.get_row_id(3, &mut t).unwrap();
+        let columns = vec!["username".to_string()];
+        table
+            .update(&row, &new_row, &columns, &rid, &mut t)
+            .unwrap();
+        table
+            .insert_in(&Row::new("6", "user6", "user6@email.com").unwrap(), &mut t)
+            .unwrap();
+        tm.commit(&table, &mut t).unwrap();
+        drop(t);
+
+        // Aborted transactions aren't logged.
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        table
+            .insert_in(&Row::new("7", "user7", "user7@email.com").unwrap(), &mut t)
+            .unwrap();
+        tm.abort(&table, &mut t);
+        drop(t);
+
+        let transactions = StatementLog::read(&log_path).unwrap();
+        assert_eq!(transactions.len(), 9);
+        let replica = Arc::new(Table::new(&target, 8));
+        assert_eq!(bootstrap(&replica, transactions.clone()), Ok(9));
+        assert!(bootstrap(&replica, transactions).is_err());
+
+        for statement in ["select", "select * from orders"] {
+            let statement = prepare_statement(statement).unwrap();
+            assert_eq!(
+                crate::query::execute_statement(&replica, &statement),
+                crate::query::execute_statement(&table, &statement)
+            );
+        }
+        let statement = prepare_statement("select 3").unwrap();
+        assert_eq!(replica.select(&statement), "(3, john, it's3@email.com)\n");
+
+        cleanup();
+    }
+}
@@ -2,10 +2,34 @@ use crate::BigArray;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
-const USERNAME_SIZE: usize = 32;
-const EMAIL_SIZE: usize = 255;
+pub const USERNAME_SIZE: usize = 32;
+pub const EMAIL_SIZE: usize = 255;
 pub const ROW_SIZE: usize = USERNAME_SIZE + EMAIL_SIZE + 4 + std::mem::size_of::<bool>(); // u32 is 4 x u8;
 
+/// Largest id of a row. Every id from 0 up to it is valid, none of them
+/// is reserved as a sentinel.
+///
+/// TRADEOFF: There's no 64-bit id mode. Keys take 4 bytes in the cells,
+/// the internal nodes and the log records, so wider ids would need a new
+/// page format version and a migration of existing files, rather than an
+/// option. Ids past `MAX_ID` are rejected by `parse_id` instead.
+pub const MAX_ID: u32 = u32::MAX;
+
+/// Parse the id of a row, telling a negative or too large id apart from
+/// one that isn't a number at all.
+pub fn parse_id(id: &str) -> Result<u32, String> {
+    let digits = id.strip_prefix('-').unwrap_or(id);
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err("invalid id provided".to_string());
+    }
+
+    if id.starts_with('-') {
+        return Err("id must not be negative".to_string());
+    }
+    id.parse::<u32>()
+        .map_err(|_| format!("id must be at most {MAX_ID}"))
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct Row {
     pub id: u32,
@@ -18,9 +42,7 @@ pub struct Row {
 
 impl Row {
     pub fn new(id: &str, u: &str, m: &str) -> Result<Row, String> {
-        let id = id
-            .parse::<u32>()
-            .map_err(|_e| "invalid id provided".to_string())?;
+        let id = parse_id(id)?;
 
         let mut username: [u8; USERNAME_SIZE] = [0; USERNAME_SIZE];
         let mut email: [u8; EMAIL_SIZE] = [0; EMAIL_SIZE];
@@ -83,19 +105,32 @@ impl FromStr for Row {
         let columns: Vec<&str> = row.split(' ').collect();
         match columns[..] {
             [id] => Self::new(id, "", ""),
-            [id, name, email] => {
-                if name.len() > USERNAME_SIZE {
-                    return Err("Name is too long.".to_string());
-                }
+            [id, name, email] => Self::from_columns(id, name, email),
+            _ => Err(format!("Unrecognized keyword at start of '{row}'.")),
+        }
+    }
+}
 
-                if email.len() > EMAIL_SIZE {
-                    return Err("Email is too long.".to_string());
-                }
+impl Row {
+    /// Parse a row from a comma separated line, e.g. `1,john,john@email.com`.
+    pub fn from_csv(line: &str) -> Result<Self, String> {
+        let columns: Vec<&str> = line.split(',').map(str::trim).collect();
+        match columns[..] {
+            [id, name, email] => Self::from_columns(id, name, email),
+            _ => Err(format!("expected 3 columns but got {}", columns.len())),
+        }
+    }
 
-                Self::new(id, name, email)
-            }
-            _ => Err(format!("Unrecognized keyword at start of '{row}'.")),
+    pub fn from_columns(id: &str, name: &str, email: &str) -> Result<Self, String> {
+        if name.len() > USERNAME_SIZE {
+            return Err("Name is too long.".to_string());
+        }
+
+        if email.len() > EMAIL_SIZE {
+            return Err("Email is too long.".to_string());
         }
+
+        Self::new(id, name, email)
     }
 }
 
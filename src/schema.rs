@@ -0,0 +1,286 @@
+use crate::row::{Row, EMAIL_SIZE, USERNAME_SIZE};
+use serde::{Deserialize, Serialize};
+
+// Define the schema of a table at runtime, e.g.
+//
+//     let schema = Schema::builder()
+//         .col("id", Int, primary())
+//         .col("username", Text(32), none())
+//         .col("email", Text(255), none())
+//         .build()?;
+//
+// TODO (Catalog): The catalog stores the schema of every table, but rows
+// still have a fixed layout, so `Table::create` and `Table::create_table`
+// only accept schemas matching `Schema::users`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColumnType {
+    Int,
+    // Maximum length in bytes.
+    Text(usize),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnOptions {
+    pub primary_key: bool,
+}
+
+pub fn primary() -> ColumnOptions {
+    ColumnOptions { primary_key: true }
+}
+
+pub fn none() -> ColumnOptions {
+    ColumnOptions::default()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Column {
+    pub name: String,
+    pub column_type: ColumnType,
+    pub options: ColumnOptions,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schema {
+    columns: Vec<Column>,
+}
+
+#[derive(Debug, Default)]
+pub struct SchemaBuilder {
+    columns: Vec<Column>,
+}
+
+impl SchemaBuilder {
+    pub fn col(mut self, name: &str, column_type: ColumnType, options: ColumnOptions) -> Self {
+        self.columns.push(Column {
+            name: name.to_string(),
+            column_type,
+            options,
+        });
+        self
+    }
+
+    /// Validate the columns, where column names must be unique, and there
+    /// must be exactly one primary key, which is an `Int`.
+    pub fn build(self) -> Result<Schema, String> {
+        if self.columns.is_empty() {
+            return Err("schema must have at least one column".to_string());
+        }
+
+        for (i, column) in self.columns.iter().enumerate() {
+            if column.name.is_empty() {
+                return Err(format!("column {i} has no name"));
+            }
+            if self.columns[..i].iter().any(|c| c.name == column.name) {
+                return Err(format!("duplicate column '{}'", column.name));
+            }
+            if column.column_type == ColumnType::Text(0) {
+                return Err(format!("column '{}' has a zero length", column.name));
+            }
+        }
+
+        let primary_keys: Vec<_> = self
+            .columns
+            .iter()
+            .filter(|column| column.options.primary_key)
+            .collect();
+        match primary_keys[..] {
+            [column] if column.column_type == ColumnType::Int => Ok(Schema {
+                columns: self.columns,
+            }),
+            [column] => Err(format!("primary key '{}' must be an Int", column.name)),
+            [] => Err("schema must have a primary key".to_string()),
+            _ => Err("schema must have only one primary key".to_string()),
+        }
+    }
+}
+
+impl Schema {
+    pub fn builder() -> SchemaBuilder {
+        SchemaBuilder::default()
+    }
+
+    /// Schema of the fixed row layout of `Row`.
+    pub fn users() -> Schema {
+        Schema::builder()
+            .col("id", ColumnType::Int, primary())
+            .col("username", ColumnType::Text(USERNAME_SIZE), none())
+            .col("email", ColumnType::Text(EMAIL_SIZE), none())
+            .build()
+            .unwrap()
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Column definitions as written in `CREATE TABLE`, e.g.
+    /// `(id int primary key, username text(32))`.
+    pub fn to_sql(&self) -> String {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| {
+                let column_type = match column.column_type {
+                    ColumnType::Int => "int".to_string(),
+                    ColumnType::Text(length) => format!("text({length})"),
+                };
+                let options = if column.options.primary_key {
+                    " primary key"
+                } else {
+                    ""
+                };
+                format!("{} {column_type}{options}", column.name)
+            })
+            .collect();
+        format!("({})", columns.join(", "))
+    }
+
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+
+    /// Accessor of an `Int` column, failing if the column doesn't exist or
+    /// isn't an `Int`, so a mistyped column is caught once up front
+    /// instead of on every row.
+    pub fn int(&self, name: &str) -> Result<IntColumn, String> {
+        match self.typed_column(name)?.column_type {
+            ColumnType::Int => Ok(IntColumn {
+                index: self.index(name),
+            }),
+            _ => Err(format!("column '{name}' is not an Int")),
+        }
+    }
+
+    /// Accessor of a `Text` column, see `int`.
+    pub fn text(&self, name: &str) -> Result<TextColumn, String> {
+        match self.typed_column(name)?.column_type {
+            ColumnType::Text(_) => Ok(TextColumn {
+                index: self.index(name),
+            }),
+            _ => Err(format!("column '{name}' is not a Text")),
+        }
+    }
+
+    fn typed_column(&self, name: &str) -> Result<&Column, String> {
+        if *self != Schema::users() {
+            return Err("only the users schema has typed accessors for now".to_string());
+        }
+        self.column(name)
+            .ok_or_else(|| format!("unknown column '{name}'"))
+    }
+
+    fn index(&self, name: &str) -> usize {
+        self.columns
+            .iter()
+            .position(|column| column.name == name)
+            .unwrap()
+    }
+}
+
+/// Typed accessor of an `Int` column of a row.
+#[derive(Debug, Clone, Copy)]
+pub struct IntColumn {
+    index: usize,
+}
+
+impl IntColumn {
+    pub fn get(&self, row: &Row) -> u32 {
+        match self.index {
+            0 => row.id,
+            _ => unreachable!("users schema only has id as an Int"),
+        }
+    }
+}
+
+/// Typed accessor of a `Text` column of a row.
+#[derive(Debug, Clone, Copy)]
+pub struct TextColumn {
+    index: usize,
+}
+
+impl TextColumn {
+    pub fn get(&self, row: &Row) -> String {
+        match self.index {
+            1 => row.username(),
+            2 => row.email(),
+            _ => unreachable!("users schema only has username and email as Text"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ColumnType::*;
+    use super::*;
+
+    #[test]
+    fn build_and_validate_schema() {
+        let schema = Schema::builder()
+            .col("id", Int, primary())
+            .col("username", Text(32), none())
+            .col("email", Text(255), none())
+            .build()
+            .unwrap();
+        assert_eq!(schema, Schema::users());
+        assert_eq!(schema.column("email").unwrap().column_type, Text(255));
+
+        let build = |builder: SchemaBuilder| builder.build().unwrap_err();
+        assert_eq!(
+            build(Schema::builder()),
+            "schema must have at least one column"
+        );
+        assert_eq!(
+            build(Schema::builder().col("id", Int, none())),
+            "schema must have a primary key"
+        );
+        assert_eq!(
+            build(Schema::builder().col("id", Text(8), primary())),
+            "primary key 'id' must be an Int"
+        );
+        assert_eq!(
+            build(
+                Schema::builder()
+                    .col("id", Int, primary())
+                    .col("id", Int, none())
+            ),
+            "duplicate column 'id'"
+        );
+        assert_eq!(
+            build(
+                Schema::builder()
+                    .col("id", Int, primary())
+                    .col("key", Int, primary())
+            ),
+            "schema must have only one primary key"
+        );
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let schema = Schema::users();
+        let id = schema.int("id").unwrap();
+        let email = schema.text("email").unwrap();
+
+        let row = Row::new("1", "john", "john@email.com").unwrap();
+        assert_eq!(id.get(&row), 1);
+        assert_eq!(email.get(&row), "john@email.com");
+
+        assert_eq!(
+            schema.int("email").unwrap_err(),
+            "column 'email' is not an Int"
+        );
+        assert_eq!(schema.text("age").unwrap_err(), "unknown column 'age'");
+    }
+
+    #[test]
+    fn create_table_with_schema() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        assert!(crate::table::Table::create(&path, 8, &Schema::users()).is_ok());
+
+        let schema = Schema::builder().col("id", Int, primary()).build().unwrap();
+        assert!(crate::table::Table::create(&path, 8, &schema).is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
+}
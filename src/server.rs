@@ -0,0 +1,264 @@
+use crate::session::Session;
+use crate::session_manager::SessionManager;
+use crate::table::Table;
+use parking_lot::Mutex;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::Arc;
+
+// Session commands reading or writing files, which would be the files of
+// the server rather than of the client.
+const LOCAL_COMMANDS: [&str; 2] = [".run", ".export"];
+
+/// A server speaking a line protocol, so several processes can share a
+/// database instead of only the process running the REPL.
+///
+/// A client sends a statement per line, as typed into the REPL, and the
+/// server answers each of them with `OK <n>`, or `ERROR <n>` if it fails,
+/// followed by the `n` lines of its output:
+///
+/// ```text
+/// > begin
+/// < OK 1
+/// < BEGIN
+/// > insert 1 john john@email.com
+/// < OK 1
+/// < inserted 1 rows
+/// > .bogus
+/// < ERROR 1
+/// < Unrecognized command '.bogus'.
+/// ```
+///
+/// Each connection has a `Session` of its own, so `begin` starts a
+/// transaction the statements of the connection run in until `commit`
+/// or `rollback`, and the transaction is rolled back if the client goes
+/// away. Statements outside of a transaction are committed right away.
+///
+/// Connections are tracked by a `SessionManager`, which turns a client
+/// away with an `ERROR` once there are too many of them, and `.connections`
+/// lists them.
+pub struct Server {
+    listener: TcpListener,
+    sessions: Arc<SessionManager>,
+}
+
+impl Server {
+    pub fn new(listener: TcpListener, table: Arc<Table>) -> Self {
+        Self {
+            listener,
+            sessions: Arc::new(SessionManager::new(table)),
+        }
+    }
+
+    /// Serve up to `max_connections` clients at once.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        let table = self.sessions.table().clone();
+        self.sessions = Arc::new(SessionManager::new(table).with_max_connections(max_connections));
+        self
+    }
+
+    /// Bind the address, e.g. `127.0.0.1:5433`, to serve the table on.
+    pub fn listen(addr: impl ToSocketAddrs, table: Arc<Table>) -> io::Result<Self> {
+        Ok(Self::new(TcpListener::bind(addr)?, table))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept connections and serve each of them in its own thread.
+    pub fn serve(&self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let sessions = self.sessions.clone();
+            std::thread::spawn(move || {
+                let peer = stream
+                    .peer_addr()
+                    .map_or_else(|_| "unknown".to_string(), |addr| addr.to_string());
+                let result = stream.try_clone().and_then(|reader| {
+                    handle_connection(BufReader::new(reader), stream, &sessions, &peer)
+                });
+                if let Err(err) = result {
+                    eprintln!("connection closed: {err}");
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Serve a single client until it closes the connection or sends `.exit`,
+/// after which its session is closed, see `SessionManager::disconnect`.
+pub fn handle_connection(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    sessions: &SessionManager,
+    peer: &str,
+) -> io::Result<()> {
+    let (id, session) = match sessions.connect(peer) {
+        Ok(connection) => connection,
+        Err(reason) => {
+            writeln!(writer, "ERROR 1\n{reason}")?;
+            return writer.flush();
+        }
+    };
+
+    let result = serve_session(reader, &mut writer, sessions, &session);
+    sessions.disconnect(id);
+    result
+}
+
+fn serve_session(
+    reader: impl BufRead,
+    writer: &mut impl Write,
+    sessions: &SessionManager,
+    session: &Mutex<Session>,
+) -> io::Result<()> {
+    for line in reader.lines() {
+        let input = line?;
+        let input = input.trim();
+        if input == ".exit" {
+            return Ok(());
+        }
+
+        let is_local = LOCAL_COMMANDS
+            .iter()
+            .any(|command| input.split_whitespace().next() == Some(command));
+        let result = if is_local {
+            Err(format!("'{input}' isn't available over the network"))
+        } else if input.is_empty() {
+            Ok(String::new())
+        } else if input == ".connections" {
+            Ok(sessions.describe())
+        } else {
+            session.lock().execute(sessions.table(), input)
+        };
+
+        let (status, output) = match &result {
+            Ok(output) => ("OK", output),
+            Err(reason) => ("ERROR", reason),
+        };
+        let lines: Vec<&str> = output.lines().collect();
+        writeln!(writer, "{status} {}", lines.len())?;
+        for line in lines {
+            writeln!(writer, "{line}")?;
+        }
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Cursor, Read};
+    use std::net::TcpStream;
+
+    fn run(sessions: &SessionManager, input: &str) -> String {
+        let mut output = Vec::new();
+        handle_connection(Cursor::new(input), &mut output, sessions, "client").unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn line_protocol() {
+        let sessions = &SessionManager::new(setup_test_table());
+
+        let output = run(
+            sessions,
+            "insert 1 john john@email.com\n\
+             .bogus\n\
+             \n\
+             begin\n\
+             insert 2 wick wick@email.com\n\
+             select\n\
+             .run script.sql\n\
+             .exit\n\
+             select\n",
+        );
+        assert_eq!(
+            output,
+            "OK 1\ninserting into page: 0, cell: 0...\n\
+             ERROR 1\nUnrecognized command '.bogus'.\n\
+             OK 0\n\
+             OK 1\nBEGIN\n\
+             OK 1\ninserted 1 rows\n\
+             OK 2\n(1, john, john@email.com)\n(2, wick, wick@email.com)\n\
+             ERROR 1\n'.run script.sql' isn't available over the network\n"
+        );
+
+        // The transaction left open by the client is rolled back.
+        assert_eq!(
+            run(sessions, "select\n"),
+            "OK 1\n(1, john, john@email.com)\n"
+        );
+        assert!(sessions.is_empty());
+
+        clean_test();
+    }
+
+    #[test]
+    fn share_database_between_connections() {
+        let table = setup_test_table();
+        let server = Server::listen("127.0.0.1:0", table)
+            .unwrap()
+            .with_max_connections(2);
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.serve());
+
+        let connect = || {
+            let stream = TcpStream::connect(addr).unwrap();
+            (BufReader::new(stream.try_clone().unwrap()), stream)
+        };
+        let send = |(reader, writer): &mut (BufReader<TcpStream>, TcpStream), input: &str| {
+            writeln!(writer, "{input}").unwrap();
+            let mut status = String::new();
+            reader.read_line(&mut status).unwrap();
+            let count: usize = status.trim().split(' ').nth(1).unwrap().parse().unwrap();
+            let mut output = status;
+            for _ in 0..count {
+                reader.read_line(&mut output).unwrap();
+            }
+            output
+        };
+
+        let mut writer = connect();
+        let mut reader = connect();
+        assert_eq!(send(&mut writer, "begin"), "OK 1\nBEGIN\n");
+        assert_eq!(
+            send(&mut writer, "insert 1 john john@email.com"),
+            "OK 1\ninserted 1 rows\n"
+        );
+        assert_eq!(send(&mut writer, "commit"), "OK 1\nCOMMIT\n");
+        assert_eq!(
+            send(&mut reader, "select"),
+            "OK 1\n(1, john, john@email.com)\n"
+        );
+
+        // No room is left for a third connection.
+        let mut rejected = connect();
+        let mut status = String::new();
+        rejected.0.read_to_string(&mut status).unwrap();
+        assert_eq!(status, "ERROR 1\ntoo many connections (max 2)\n");
+        assert!(send(&mut writer, ".connections")
+            .ends_with("2 of 2 connections, no transactions in progress\n"));
+
+        clean_test();
+    }
+
+    fn setup_test_table() -> Arc<Table> {
+        Arc::new(Table::new(
+            format!("test-{:?}.db", std::thread::current().id()),
+            8,
+        ))
+    }
+
+    fn clean_test() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+        let _ = std::fs::remove_file(format!("{path}.hot"));
+    }
+}
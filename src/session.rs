@@ -0,0 +1,1075 @@
+use crate::concurrency::{IsolationLevel, Transaction, TransactionState};
+use crate::line_editor::DEFAULT_HISTORY_SIZE;
+use crate::query::{execute_in, prepare_statement, query_in, ExportFormat, ResultSet};
+use crate::table::{ScanCursor, ScanDirection, Table};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+const SET_USAGE: &str = "usage: \\set [name [value]]";
+const RUN_USAGE: &str = "usage: .run [--continue] <file>";
+const SCAN_USAGE: &str = "usage: .scan [asc|desc]";
+const EXPORT_USAGE: &str = "usage: .export <file> [csv|json]";
+const PREPARE_USAGE: &str = "usage: prepare <name> as <statement>";
+
+/// Commands handled by the session rather than the table.
+pub const SESSION_COMMANDS: [&str; 11] = [
+    "\\set",
+    ".run",
+    ".scan",
+    ".next",
+    ".export",
+    "begin",
+    "commit",
+    "rollback",
+    "prepare",
+    "execute",
+    "deallocate",
+];
+
+/// Variables changing the behaviour of the session.
+pub const SETTINGS: [&str; 4] = [
+    "max_rows",
+    "history_size",
+    "idle_in_transaction_timeout",
+    "isolation_level",
+];
+
+// Rows printed for a select, unless `max_rows` is set.
+const DEFAULT_MAX_ROWS: usize = 1000;
+
+// Milliseconds a transaction can stay idle before it's rolled back,
+// unless `idle_in_transaction_timeout` is set.
+const DEFAULT_IDLE_IN_TRANSACTION_TIMEOUT: u64 = 5 * 60 * 1000;
+
+// What to do when a statement of a script fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnError {
+    Stop,
+    Continue,
+}
+
+/// State of a REPL session, which is kept across inputs.
+///
+/// - `\set name value` sets a variable, and `\set` alone lists them.
+/// - `:name` in the input is replaced by the value of the variable.
+/// - `.run [--continue] <file>` executes every line of the file as if
+///   it's typed into the REPL, stopping at the first failed line unless
+///   `--continue` is given.
+/// - `\set max_rows N` limits the rows printed for a select, where 0
+///   prints every row.
+/// - `\set history_size N` keeps the last N statements in the history
+///   of the REPL, where 0 disables it, see `LineEditor`.
+/// - `.scan [asc|desc]` prints the first `max_rows` rows in key order,
+///   and `.next` prints the rows after them, see `ScanCursor`.
+/// - `begin` starts a transaction, which the statements up to `commit`
+///   or `rollback` run in, see `execute_in`. They can be spelled as in
+///   SQL too, e.g. `BEGIN TRANSACTION;`, `START TRANSACTION` or `END;`.
+/// - `\set idle_in_transaction_timeout N` rolls the transaction back once
+///   it's been idle for N milliseconds, where 0 never does, so a
+///   forgotten session doesn't keep its locks and block everyone else.
+///   The next input is then told so, see `TransactionManager::reap_idle`.
+/// - `.export <file> [csv|json]` writes the rows of the next select to
+///   the file instead of printing them, as CSV unless the file ends with
+///   `.json`, see `ResultSet`.
+/// - `\set isolation_level L` begins the next transactions at level L,
+///   one of `read_uncommitted`, `read_committed`, `repeatable_read`, the
+///   default, or `serializable`, see `IsolationLevel`.
+/// - `prepare <name> as <statement>` keeps the statement as is, and
+///   `execute <name>` runs it, where its `:name` variables are replaced
+///   by their values at the time, so they act as its parameters.
+///   `deallocate <name>` forgets it.
+#[derive(Debug, Default)]
+pub struct Session {
+    variables: HashMap<String, String>,
+    // Statements kept by `prepare`, before their variables are replaced.
+    prepared: HashMap<String, String>,
+    scan: Option<ScanCursor>,
+    transaction: Option<Arc<RwLock<Transaction>>>,
+    // File the next select is exported to, see `.export`.
+    export: Option<(PathBuf, ExportFormat)>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle_input(&mut self, table: &Arc<Table>, input: &str) -> String {
+        self.execute(table, input).unwrap_or_else(|reason| reason)
+    }
+
+    /// Execute the input, and return an error if it fails, e.g. when it
+    /// refers to an undefined variable.
+    pub fn execute(&mut self, table: &Arc<Table>, input: &str) -> Result<String, String> {
+        // A transaction rolled back while the session was idle is reported
+        // ahead of whatever the input outputs.
+        match self.reaped_notice(table) {
+            Some(notice) => self
+                .execute_input(table, input)
+                .map(|output| format!("{notice}{output}"))
+                .map_err(|reason| format!("{notice}{reason}")),
+            None => self.execute_input(table, input),
+        }
+    }
+
+    fn execute_input(&mut self, table: &Arc<Table>, input: &str) -> Result<String, String> {
+        if let Some(args) = input.strip_prefix("\\set") {
+            return self.set(args);
+        }
+        // The variables of a prepared statement are replaced each time
+        // it's executed instead.
+        let (command, args) = input.split_once(' ').unwrap_or((input, ""));
+        if command.eq_ignore_ascii_case("prepare") {
+            return self.prepare(args);
+        }
+
+        let input = self.interpolate(input)?;
+        let (command, args) = input.split_once(' ').unwrap_or((&input, ""));
+        if command.eq_ignore_ascii_case("execute") {
+            return self.execute_prepared(table, args);
+        }
+        if command.eq_ignore_ascii_case("deallocate") {
+            let name = args.trim();
+            return match self.prepared.remove(name) {
+                Some(_) => Ok("DEALLOCATE".to_string()),
+                None => Err(format!("prepared statement '{name}' does not exist")),
+            };
+        }
+        if let Some(args) = input.strip_prefix(".run") {
+            return self.run(table, args);
+        }
+        if let Some(args) = input.strip_prefix(".scan") {
+            return self.scan(table, args);
+        }
+        if input == ".next" {
+            return self.next_page(table);
+        }
+        if let Some(args) = input.strip_prefix(".export") {
+            return self.export(args);
+        }
+        match transaction_command(&input) {
+            Some(TransactionCommand::Begin) => return self.begin(table),
+            Some(TransactionCommand::Commit) => {
+                return self.end(table, TransactionState::Committed)
+            }
+            Some(TransactionCommand::Rollback) => {
+                return self.end(table, TransactionState::Aborted)
+            }
+            None => {}
+        }
+        let is_select = input
+            .split_whitespace()
+            .next()
+            .is_some_and(|word| word.eq_ignore_ascii_case("select"));
+        if is_select && self.export.is_some() {
+            return self.export_select(table, &input);
+        }
+        if self.transaction.is_some() && !input.starts_with('.') {
+            let output = self.in_transaction(|transaction| {
+                execute_in(table, &prepare_statement(&input)?, transaction)
+                    .map_err(|err| err.to_string())
+            })?;
+            return Ok(self.limit_rows(output));
+        }
+
+        let output = crate::execute_input(table, &input).map_err(|err| err.to_string())?;
+        if is_select {
+            return Ok(self.limit_rows(output));
+        }
+
+        Ok(output)
+    }
+
+    fn export(&mut self, args: &str) -> Result<String, String> {
+        let (path, format) = match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [path] => (Path::new(path), ExportFormat::from_path(Path::new(path))),
+            [path, format] => (Path::new(path), format.parse()?),
+            _ => return Err(EXPORT_USAGE.to_string()),
+        };
+
+        self.export = Some((path.to_path_buf(), format));
+        Ok(String::new())
+    }
+
+    // Write the rows of the select to the file given to `.export`, rather
+    // than printing them, so they aren't limited by `max_rows` either.
+    fn export_select(&mut self, table: &Arc<Table>, input: &str) -> Result<String, String> {
+        let (path, format) = self.export.take().unwrap();
+        let statement = prepare_statement(input)?;
+        let result_set = if self.transaction.is_some() {
+            self.in_transaction(|transaction| {
+                query_in(table, &statement, transaction).map_err(|err| err.to_string())
+            })?
+        } else {
+            table
+                .with_table(statement.table.as_deref(), |table| table.query(&statement))
+                .map_err(|err| err.to_string())?
+        };
+
+        write_result_set(&result_set, &path, format)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        Ok(format!(
+            "exported {} rows to {}",
+            result_set.len(),
+            path.display()
+        ))
+    }
+
+    fn prepare(&mut self, args: &str) -> Result<String, String> {
+        let (name, rest) = args.trim().split_once(' ').ok_or(PREPARE_USAGE)?;
+        let (keyword, statement) = rest.trim_start().split_once(' ').ok_or(PREPARE_USAGE)?;
+        let statement = statement.trim();
+        if !keyword.eq_ignore_ascii_case("as") || statement.is_empty() {
+            return Err(PREPARE_USAGE.to_string());
+        }
+        if !is_variable_name(name) {
+            return Err(format!("invalid prepared statement name '{name}'"));
+        }
+
+        // Executing a prepared statement mustn't execute another one, or
+        // change the transaction it runs in.
+        let command = statement.split_whitespace().next().unwrap_or_default();
+        let is_session_command = SESSION_COMMANDS
+            .iter()
+            .any(|session_command| command.eq_ignore_ascii_case(session_command));
+        if is_session_command
+            || transaction_command(statement).is_some()
+            || command.starts_with('.')
+            || command.starts_with('\\')
+        {
+            return Err(format!("only statements can be prepared, not '{command}'"));
+        }
+
+        self.prepared
+            .insert(name.to_string(), statement.to_string());
+        Ok("PREPARE".to_string())
+    }
+
+    fn execute_prepared(&mut self, table: &Arc<Table>, args: &str) -> Result<String, String> {
+        let name = args.trim();
+        let statement = self
+            .prepared
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("prepared statement '{name}' does not exist"))?;
+        self.execute_input(table, &statement)
+    }
+
+    fn begin(&mut self, table: &Arc<Table>) -> Result<String, String> {
+        if self.transaction.is_some() {
+            return Err("a transaction is already in progress".to_string());
+        }
+
+        // At `repeatable_read`, rows read are locked until the transaction
+        // ends too, so they can be written without another transaction
+        // changing them first.
+        let tm = table.transaction_manager();
+        let transaction = tm.begin(self.isolation_level());
+        transaction
+            .write()
+            .set_idle_timeout(self.idle_in_transaction_timeout());
+        tm.start_reaper(table);
+
+        self.transaction = Some(transaction);
+        Ok("BEGIN".to_string())
+    }
+
+    fn end(&mut self, table: &Table, state: TransactionState) -> Result<String, String> {
+        let transaction = self
+            .transaction
+            .take()
+            .ok_or("no transaction in progress")?;
+
+        let tm = table.transaction_manager();
+        let mut t = transaction.write();
+        if state == TransactionState::Committed {
+            tm.commit(table, &mut t)
+                .map_err(|err| format!("fail to commit: {err}"))?;
+            Ok("COMMIT".to_string())
+        } else {
+            tm.abort(table, &mut t);
+            Ok("ROLLBACK".to_string())
+        }
+    }
+
+    /// Roll back the transaction in progress, if any, and forget the
+    /// state of the session, e.g. when its client disconnects, so the
+    /// locks of the transaction don't outlive the session.
+    pub fn close(&mut self, table: &Table) {
+        if let Some(transaction) = self.transaction.take() {
+            let mut t = transaction.write();
+            // The reaper may have rolled it back already.
+            if !matches!(
+                t.state,
+                TransactionState::Committed | TransactionState::Aborted
+            ) {
+                table.transaction_manager().abort(table, &mut t);
+            }
+        }
+        self.scan = None;
+        self.export = None;
+        self.prepared.clear();
+    }
+
+    /// Id of the transaction in progress, if any.
+    pub fn transaction_id(&self) -> Option<u32> {
+        self.transaction
+            .as_ref()
+            .map(|transaction| transaction.read().txn_id)
+    }
+
+    /// Level the next transaction begins at, see `isolation_level`.
+    pub fn isolation_level(&self) -> IsolationLevel {
+        self.variables
+            .get("isolation_level")
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(IsolationLevel::RepeatableRead)
+    }
+
+    pub fn prepared_statements(&self) -> usize {
+        self.prepared.len()
+    }
+
+    // Run a statement in the transaction of the session, which is
+    // forgotten if the statement rolls it back.
+    fn in_transaction<T>(
+        &mut self,
+        f: impl FnOnce(&Arc<RwLock<Transaction>>) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let transaction = self.transaction.clone().unwrap();
+
+        // The reaper skips the transaction while it runs the statement,
+        // however long the statement takes.
+        transaction.write().set_busy(true);
+        let output = f(&transaction);
+        transaction.write().set_busy(false);
+
+        if transaction.read().state == TransactionState::Aborted {
+            self.transaction = None;
+        }
+        output
+    }
+
+    // Forget the transaction of the session if it was rolled back for
+    // being idle, returning the notice to print about it.
+    fn reaped_notice(&mut self, table: &Table) -> Option<String> {
+        let transaction = self.transaction.as_ref()?;
+        let txn_id = {
+            let t = transaction.read();
+            if t.state != TransactionState::Aborted {
+                return None;
+            }
+            t.txn_id
+        };
+
+        self.transaction = None;
+        let notice = table.transaction_manager().take_notice(txn_id)?;
+        Some(format!("NOTICE: {notice}\n"))
+    }
+
+    fn idle_in_transaction_timeout(&self) -> Option<Duration> {
+        let timeout = self
+            .variables
+            .get("idle_in_transaction_timeout")
+            .and_then(|timeout| timeout.parse().ok())
+            .unwrap_or(DEFAULT_IDLE_IN_TRANSACTION_TIMEOUT);
+        (timeout > 0).then(|| Duration::from_millis(timeout))
+    }
+
+    // Keep the first `max_rows` rows of the output of a select, followed
+    // by the number of rows left out.
+    //
+    // TRADEOFF: The whole output is still built before it's cut, as the
+    // table returns it as a single string.
+    fn limit_rows(&self, output: String) -> String {
+        let max_rows = self.max_rows();
+        let rows = output.lines().count();
+        if max_rows == 0 || rows <= max_rows {
+            return output;
+        }
+
+        let mut output: String = output
+            .lines()
+            .take(max_rows)
+            .map(|line| format!("{line}\n"))
+            .collect();
+        output.push_str(&format!(
+            "... {} more rows, \\set max_rows 0 to print every row\n",
+            rows - max_rows
+        ));
+        output
+    }
+
+    fn max_rows(&self) -> usize {
+        self.variables
+            .get("max_rows")
+            .and_then(|max_rows| max_rows.parse().ok())
+            .unwrap_or(DEFAULT_MAX_ROWS)
+    }
+
+    pub fn history_size(&self) -> usize {
+        self.variables
+            .get("history_size")
+            .and_then(|history_size| history_size.parse().ok())
+            .unwrap_or(DEFAULT_HISTORY_SIZE)
+    }
+
+    fn scan(&mut self, table: &Table, args: &str) -> Result<String, String> {
+        let direction = match args.trim() {
+            "" | "asc" => ScanDirection::Forward,
+            "desc" => ScanDirection::Backward,
+            _ => return Err(SCAN_USAGE.to_string()),
+        };
+
+        self.scan = Some(table.scan_cursor(direction));
+        self.next_page(table)
+    }
+
+    // Print the next `max_rows` rows of the scan, and keep the cursor for
+    // the rows after them, if any.
+    fn next_page(&mut self, table: &Table) -> Result<String, String> {
+        let cursor = self.scan.take().ok_or("no scan to continue, see .scan")?;
+        let limit = match self.max_rows() {
+            0 => usize::MAX,
+            max_rows => max_rows,
+        };
+
+        let (rows, next) = table.scan(&cursor, limit);
+        let mut output: String = rows
+            .iter()
+            .map(|row| format!("{}\n", row.to_string()))
+            .collect();
+        if table.epoch() != cursor.epoch {
+            output.push_str("... the table has changed since the scan started\n");
+        }
+        if next.is_some() {
+            output.push_str("... .next to print the next rows\n");
+        }
+
+        self.scan = next;
+        Ok(output)
+    }
+
+    fn set(&mut self, args: &str) -> Result<String, String> {
+        if !args.is_empty() && !args.starts_with(' ') {
+            return Err(SET_USAGE.to_string());
+        }
+
+        let args = args.trim();
+        if args.is_empty() {
+            let mut variables: Vec<_> = self.variables.iter().collect();
+            variables.sort();
+
+            let output = variables
+                .iter()
+                .map(|(name, value)| format!("{name} = '{value}'\n"))
+                .collect();
+            return Ok(output);
+        }
+
+        let (name, value) = args.split_once(' ').unwrap_or((args, ""));
+        if !is_variable_name(name) {
+            return Err(format!("invalid variable name '{name}'"));
+        }
+
+        // Like psql, the value can refer to other variables.
+        let value = self.interpolate(value.trim())?;
+        if name == "max_rows" && value.parse::<usize>().is_err() {
+            return Err("max_rows must be a number, where 0 means no limit".to_string());
+        }
+        if name == "history_size" && value.parse::<usize>().is_err() {
+            return Err("history_size must be a number, where 0 disables the history".to_string());
+        }
+        if name == "idle_in_transaction_timeout" && value.parse::<u64>().is_err() {
+            return Err(
+                "idle_in_transaction_timeout must be a number of milliseconds, where 0 disables it"
+                    .to_string(),
+            );
+        }
+        if name == "isolation_level" {
+            value.parse::<IsolationLevel>()?;
+        }
+        self.variables.insert(name.to_string(), value);
+
+        // The timeout applies to the transaction in progress too.
+        if let Some(transaction) = self
+            .transaction
+            .as_ref()
+            .filter(|_| name == "idle_in_transaction_timeout")
+        {
+            transaction
+                .write()
+                .set_idle_timeout(self.idle_in_transaction_timeout());
+        }
+        Ok(String::new())
+    }
+
+    /// Replace every `:name` in the input with the value of the variable.
+    ///
+    /// A `:` that is not followed by a variable name is kept as is.
+    pub fn interpolate(&self, input: &str) -> Result<String, String> {
+        let mut output = String::with_capacity(input.len());
+        let mut rest = input;
+
+        while let Some(i) = rest.find(':') {
+            output.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            let len = rest
+                .find(|c: char| !is_variable_char(c))
+                .unwrap_or(rest.len());
+            if len == 0 {
+                output.push(':');
+                continue;
+            }
+
+            let name = &rest[..len];
+            let value = self
+                .variables
+                .get(name)
+                .ok_or_else(|| format!("undefined variable ':{name}'"))?;
+            output.push_str(value);
+            rest = &rest[len..];
+        }
+
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    // Run the statements of a script, where empty lines and lines
+    // starting with `--` are skipped.
+    //
+    // `.exit` in a script only stops the script, not the REPL.
+    fn run(&mut self, table: &Arc<Table>, args: &str) -> Result<String, String> {
+        let (on_error, path) = match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [path] => (OnError::Stop, path),
+            ["--continue", path] => (OnError::Continue, path),
+            _ => return Err(RUN_USAGE.to_string()),
+        };
+
+        let script = std::fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        self.run_script(table, Path::new(path), &script, on_error)
+    }
+
+    pub fn run_script(
+        &mut self,
+        table: &Arc<Table>,
+        path: &Path,
+        script: &str,
+        on_error: OnError,
+    ) -> Result<String, String> {
+        let mut output = String::new();
+        let mut failed = false;
+
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("--") {
+                continue;
+            }
+
+            match self.execute(table, line) {
+                Ok(result) if result == "Exit" => break,
+                Ok(result) => {
+                    output.push_str(&result);
+                    if !result.is_empty() && !result.ends_with('\n') {
+                        output.push('\n');
+                    }
+                }
+                Err(reason) => {
+                    let reason = format!("{}:{}: {reason}\n", path.display(), i + 1);
+                    output.push_str(&reason);
+                    failed = true;
+
+                    if on_error == OnError::Stop {
+                        return Err(output);
+                    }
+                }
+            }
+        }
+
+        if failed {
+            Err(output)
+        } else {
+            Ok(output)
+        }
+    }
+}
+
+fn write_result_set(
+    result_set: &ResultSet,
+    path: &Path,
+    format: ExportFormat,
+) -> std::io::Result<()> {
+    let file = File::create(path)?;
+    result_set.write(BufWriter::new(file), format)
+}
+
+fn is_variable_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(is_variable_char)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransactionCommand {
+    Begin,
+    Commit,
+    Rollback,
+}
+
+// `begin`, `commit` or `rollback`, either as typed in the REPL or as
+// spelled in SQL, with an optional `transaction` or `work` and `;`.
+fn transaction_command(input: &str) -> Option<TransactionCommand> {
+    let input = input.trim().trim_end_matches(';').to_lowercase();
+    match input.split_whitespace().collect::<Vec<_>>()[..] {
+        ["begin"] | ["begin", "transaction" | "work"] | ["start", "transaction"] => {
+            Some(TransactionCommand::Begin)
+        }
+        ["commit" | "end"] | ["commit" | "end", "transaction" | "work"] => {
+            Some(TransactionCommand::Commit)
+        }
+        ["rollback" | "abort"] | ["rollback" | "abort", "transaction" | "work"] => {
+            Some(TransactionCommand::Rollback)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recovery::LogManager;
+
+    #[test]
+    fn set_and_interpolate_variables() {
+        let mut session = Session::new();
+        let table = setup_test_table();
+
+        assert_eq!(session.execute(&table, "\\set id 1"), Ok(String::new()));
+        assert_eq!(
+            session.execute(&table, "\\set name john"),
+            Ok(String::new())
+        );
+        assert_eq!(
+            session.execute(&table, "\\set email :name@email.com"),
+            Ok(String::new())
+        );
+        assert_eq!(
+            session.execute(&table, "\\set"),
+            Ok("email = 'john@email.com'\nid = '1'\nname = 'john'\n".to_string())
+        );
+
+        session.execute(&table, "insert :id :name :email").unwrap();
+        assert_eq!(
+            session.execute(&table, "select :id"),
+            Ok("(1, john, john@email.com)\n".to_string())
+        );
+
+        assert_eq!(
+            session.execute(&table, "select :missing"),
+            Err("undefined variable ':missing'".to_string())
+        );
+        assert!(session.execute(&table, "\\set a-b 1").is_err());
+        assert_eq!(session.interpolate("a : b :").unwrap(), "a : b :");
+
+        clean_test();
+    }
+
+    #[test]
+    fn run_script_stop_or_continue_on_error() {
+        let mut session = Session::new();
+        let table = setup_test_table();
+        let path = Path::new("script.sql");
+        let script = "
+            -- Insert a few rows
+            \\set domain email.com
+            insert 1 john john@:domain
+            unknown statement
+            insert 2 wick wick@:domain
+        ";
+
+        let output = session.run_script(&table, path, script, OnError::Stop);
+        assert_eq!(
+            output,
+            Err(
+                "inserting into page: 0, cell: 0...\nscript.sql:5: unrecognized statement\n"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            session.execute(&table, "select"),
+            Ok("(1, john, john@email.com)\n".to_string())
+        );
+
+        let script = script.replace("insert 1", "delete 1");
+        let output = session.run_script(&table, path, &script, OnError::Continue);
+        assert_eq!(
+            output,
+            Err("deleted 1\nscript.sql:5: unrecognized statement\ninserting into page: 0, cell: 0...\n".to_string())
+        );
+        assert_eq!(
+            session.execute(&table, "select"),
+            Ok("(2, wick, wick@email.com)\n".to_string())
+        );
+
+        assert_eq!(session.execute(&table, ".run"), Err(RUN_USAGE.to_string()));
+
+        clean_test();
+    }
+
+    #[test]
+    fn limit_rows_of_select() {
+        let mut session = Session::new();
+        let table = setup_test_table();
+        for i in 1..=5 {
+            session
+                .execute(&table, &format!("insert {i} user{i} user{i}@email.com"))
+                .unwrap();
+        }
+
+        session.execute(&table, "\\set max_rows 2").unwrap();
+        assert_eq!(
+            session.execute(&table, "select"),
+            Ok("(1, user1, user1@email.com)\n(2, user2, user2@email.com)\n... 3 more rows, \\set max_rows 0 to print every row\n".to_string())
+        );
+        assert_eq!(
+            session.execute(&table, "select 3"),
+            Ok("(3, user3, user3@email.com)\n".to_string())
+        );
+
+        session.execute(&table, "\\set max_rows 0").unwrap();
+        assert_eq!(
+            session
+                .execute(&table, "select")
+                .map(|output| output.lines().count()),
+            Ok(5)
+        );
+        assert!(session.execute(&table, "\\set max_rows all").is_err());
+
+        clean_test();
+    }
+
+    #[test]
+    fn paginate_with_scan() {
+        let mut session = Session::new();
+        let table = setup_test_table();
+        for i in 1..=5 {
+            session
+                .execute(&table, &format!("insert {i} user{i} user{i}@email.com"))
+                .unwrap();
+        }
+
+        assert_eq!(
+            session.execute(&table, ".next"),
+            Err("no scan to continue, see .scan".to_string())
+        );
+        session.execute(&table, "\\set max_rows 2").unwrap();
+        assert_eq!(
+            session.execute(&table, ".scan"),
+            Ok("(1, user1, user1@email.com)\n(2, user2, user2@email.com)\n... .next to print the next rows\n".to_string())
+        );
+        session
+            .execute(&table, "insert 6 user6 user6@email.com")
+            .unwrap();
+        assert_eq!(
+            session.execute(&table, ".next"),
+            Ok("(3, user3, user3@email.com)\n(4, user4, user4@email.com)\n... the table has changed since the scan started\n... .next to print the next rows\n".to_string())
+        );
+
+        session.execute(&table, "\\set max_rows 0").unwrap();
+        assert_eq!(
+            session.execute(&table, ".scan desc").map(|output| output
+                .lines()
+                .next()
+                .unwrap()
+                .to_string()),
+            Ok("(6, user6, user6@email.com)".to_string())
+        );
+        assert!(session.execute(&table, ".next").is_err());
+        assert_eq!(
+            session.execute(&table, ".scan sideways"),
+            Err(SCAN_USAGE.to_string())
+        );
+
+        clean_test();
+    }
+
+    #[test]
+    fn roll_back_idle_transaction() {
+        let mut session = Session::new();
+        let table = setup_test_table();
+        session
+            .execute(&table, "insert 1 john john@email.com")
+            .unwrap();
+
+        assert_eq!(
+            session.execute(&table, "commit"),
+            Err("no transaction in progress".to_string())
+        );
+        assert_eq!(session.execute(&table, "begin"), Ok("BEGIN".to_string()));
+        assert!(session.execute(&table, "begin").is_err());
+        assert_eq!(
+            session.execute(&table, "insert 2 wick wick@email.com"),
+            Ok("inserted 1 rows".to_string())
+        );
+        assert_eq!(
+            session.execute(&table, "select"),
+            Ok("(1, john, john@email.com)\n(2, wick, wick@email.com)\n".to_string())
+        );
+        assert_eq!(session.execute(&table, "commit"), Ok("COMMIT".to_string()));
+
+        // Left idle, the transaction is rolled back along with its delete,
+        // and the next input is told so.
+        session
+            .execute(&table, "\\set idle_in_transaction_timeout 20")
+            .unwrap();
+        session.execute(&table, "begin").unwrap();
+        assert_eq!(
+            session.execute(&table, "delete 1"),
+            Ok("deleted 1 rows".to_string())
+        );
+        std::thread::sleep(Duration::from_millis(500));
+
+        let output = session.execute(&table, "select 1").unwrap();
+        assert!(
+            output.starts_with("NOTICE: transaction 2 was rolled back after being idle"),
+            "{output}"
+        );
+        assert!(output.ends_with("(1, john, john@email.com)\n"), "{output}");
+        assert_eq!(
+            session.execute(&table, "rollback"),
+            Err("no transaction in progress".to_string())
+        );
+
+        // Its locks are released, so another transaction can delete the row.
+        session
+            .execute(&table, "\\set idle_in_transaction_timeout 0")
+            .unwrap();
+        session.execute(&table, "begin").unwrap();
+        assert_eq!(
+            session.execute(&table, "delete 1"),
+            Ok("deleted 1 rows".to_string())
+        );
+        assert_eq!(session.execute(&table, "commit"), Ok("COMMIT".to_string()));
+        assert_eq!(
+            session.execute(&table, "select"),
+            Ok("(2, wick, wick@email.com)\n".to_string())
+        );
+
+        clean_test();
+    }
+
+    #[test]
+    fn transaction_commands_spelled_as_sql() {
+        let mut session = Session::new();
+        let table = setup_test_table();
+
+        assert_eq!(
+            session.execute(&table, "BEGIN TRANSACTION;"),
+            Ok("BEGIN".to_string())
+        );
+        session
+            .execute(&table, "insert 1 john john@email.com")
+            .unwrap();
+        assert_eq!(session.execute(&table, "end;"), Ok("COMMIT".to_string()));
+
+        session.execute(&table, "start transaction").unwrap();
+        session.execute(&table, "delete 1").unwrap();
+        assert_eq!(
+            session.execute(&table, "Rollback Work"),
+            Ok("ROLLBACK".to_string())
+        );
+        assert_eq!(
+            session.execute(&table, "select"),
+            Ok("(1, john, john@email.com)\n".to_string())
+        );
+
+        assert_eq!(
+            session.execute(&table, "prepare go as begin work"),
+            Err("only statements can be prepared, not 'begin'".to_string())
+        );
+        assert!(session.execute(&table, "begin the transaction").is_err());
+
+        clean_test();
+    }
+
+    #[test]
+    fn export_next_select() {
+        let mut session = Session::new();
+        let table = setup_test_table();
+        for i in 1..=3 {
+            session
+                .execute(&table, &format!("insert {i} user{i} user{i}@email.com"))
+                .unwrap();
+        }
+        let csv = format!("test-{:?}.csv", std::thread::current().id());
+        let json = format!("test-{:?}.json", std::thread::current().id());
+
+        assert_eq!(
+            session.execute(&table, &format!(".export {csv}")),
+            Ok(String::new())
+        );
+        session.execute(&table, "delete 3").unwrap();
+        assert_eq!(
+            session.execute(&table, "select"),
+            Ok(format!("exported 2 rows to {csv}"))
+        );
+        assert_eq!(
+            std::fs::read_to_string(&csv).unwrap(),
+            "id,username,email\n1,user1,user1@email.com\n2,user2,user2@email.com\n"
+        );
+
+        // Only the next select is exported.
+        assert_eq!(
+            session.execute(&table, "select 1"),
+            Ok("(1, user1, user1@email.com)\n".to_string())
+        );
+
+        session.execute(&table, &format!(".export {json}")).unwrap();
+        assert_eq!(
+            session.execute(&table, "select 2"),
+            Ok(format!("exported 1 rows to {json}"))
+        );
+        assert_eq!(
+            std::fs::read_to_string(&json).unwrap(),
+            "{\"id\":2,\"username\":\"user2\",\"email\":\"user2@email.com\"}\n"
+        );
+
+        assert!(session
+            .execute(&table, &format!(".export {json} xml"))
+            .is_err());
+        assert_eq!(
+            session.execute(&table, ".export"),
+            Err(EXPORT_USAGE.to_string())
+        );
+
+        let _ = std::fs::remove_file(csv);
+        let _ = std::fs::remove_file(json);
+        clean_test();
+    }
+
+    #[test]
+    fn prepare_and_execute_statements() {
+        let mut session = Session::new();
+        let table = setup_test_table();
+
+        assert_eq!(
+            session.execute(
+                &table,
+                "prepare add as insert :id user:id user:id@email.com"
+            ),
+            Ok("PREPARE".to_string())
+        );
+        assert_eq!(
+            session.execute(&table, "execute add"),
+            Err("undefined variable ':id'".to_string())
+        );
+        for id in 1..=2 {
+            session.execute(&table, &format!("\\set id {id}")).unwrap();
+            session.execute(&table, "execute add").unwrap();
+        }
+        assert_eq!(
+            session.execute(&table, "select"),
+            Ok("(1, user1, user1@email.com)\n(2, user2, user2@email.com)\n".to_string())
+        );
+        assert_eq!(session.prepared_statements(), 1);
+
+        assert_eq!(
+            session.execute(&table, "prepare again as execute add"),
+            Err("only statements can be prepared, not 'execute'".to_string())
+        );
+        assert_eq!(
+            session.execute(&table, "prepare add"),
+            Err(PREPARE_USAGE.to_string())
+        );
+        assert_eq!(
+            session.execute(&table, "deallocate add"),
+            Ok("DEALLOCATE".to_string())
+        );
+        assert_eq!(
+            session.execute(&table, "execute add"),
+            Err("prepared statement 'add' does not exist".to_string())
+        );
+
+        // The isolation level applies to the transactions begun next.
+        assert!(session
+            .execute(&table, "\\set isolation_level snapshot")
+            .is_err());
+        session
+            .execute(&table, "\\set isolation_level read_committed")
+            .unwrap();
+        session.execute(&table, "begin").unwrap();
+        let transaction = session.transaction.clone().unwrap();
+        assert_eq!(transaction.read().iso_level, IsolationLevel::ReadCommited);
+        assert_eq!(session.transaction_id(), Some(transaction.read().txn_id));
+
+        // Closing the session rolls its transaction back.
+        session.execute(&table, "delete 1").unwrap();
+        session.close(&table);
+        assert_eq!(session.transaction_id(), None);
+        assert_eq!(transaction.read().state, TransactionState::Aborted);
+        assert_eq!(table.transaction_manager().active_transactions(), 0);
+        assert_eq!(
+            session.execute(&table, "select").unwrap().lines().count(),
+            2
+        );
+
+        clean_test();
+    }
+
+    #[test]
+    fn wal_status() {
+        let mut session = Session::new();
+        let table = setup_test_table();
+        assert_eq!(
+            session.execute(&table, ".wal status"),
+            Ok("the database isn't logged to a WAL".to_string())
+        );
+        drop(table);
+        clean_test();
+
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let wal_path = format!("test-{:?}.wal", std::thread::current().id());
+        let log_manager = Arc::new(LogManager::new(&wal_path));
+        let table = Arc::new(Table::with_log_manager(&path, 8, log_manager.clone()));
+        assert_eq!(
+            session.execute(&table, ".wal status"),
+            Ok(format!(
+                "segment: {wal_path}.000001, flushed lsn: none, checkpoint lsn: none, retained segments: 0"
+            ))
+        );
+
+        session.execute(&table, "begin").unwrap();
+        session
+            .execute(&table, "insert 1 john john@email.com")
+            .unwrap();
+        session.execute(&table, "commit").unwrap();
+        let status = session.execute(&table, ".wal status").unwrap();
+        assert_eq!(status, log_manager.status().to_string());
+        assert!(log_manager.status().flushed_lsn.is_some());
+
+        let _ = std::fs::remove_file(format!("{wal_path}.000001"));
+        clean_test();
+    }
+
+    fn setup_test_table() -> Arc<Table> {
+        Arc::new(Table::new(
+            format!("test-{:?}.db", std::thread::current().id()),
+            8,
+        ))
+    }
+
+    fn clean_test() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+        let _ = std::fs::remove_file(format!("{path}.hot"));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::session::Session;
+use crate::table::Table;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Clients connected at once, unless `with_max_connections` is given.
+const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Sessions of the clients connected to a `Server`, one per connection.
+///
+/// A client connecting once `max_connections` clients are connected is
+/// turned away, and the state of a client that disconnects, e.g. in the
+/// middle of a transaction, is cleaned up rather than its transaction
+/// holding on to its locks, see `Session::close`.
+pub struct SessionManager {
+    table: Arc<Table>,
+    max_connections: usize,
+    next_id: AtomicU64,
+    connections: Mutex<BTreeMap<u64, Connection>>,
+}
+
+struct Connection {
+    peer: String,
+    session: Arc<Mutex<Session>>,
+}
+
+impl SessionManager {
+    pub fn new(table: Arc<Table>) -> Self {
+        Self {
+            table,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            next_id: AtomicU64::new(1),
+            connections: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    pub fn table(&self) -> &Arc<Table> {
+        &self.table
+    }
+
+    /// Start a session for the client at `peer`, returning the id of its
+    /// connection, or an error if there are too many connections already.
+    pub fn connect(&self, peer: &str) -> Result<(u64, Arc<Mutex<Session>>), String> {
+        let mut connections = self.connections.lock();
+        if connections.len() >= self.max_connections {
+            return Err(format!(
+                "too many connections (max {})",
+                self.max_connections
+            ));
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let session = Arc::new(Mutex::new(Session::new()));
+        connections.insert(
+            id,
+            Connection {
+                peer: peer.to_string(),
+                session: session.clone(),
+            },
+        );
+        Ok((id, session))
+    }
+
+    /// End the session of the connection, rolling back its transaction if
+    /// it's still in progress.
+    pub fn disconnect(&self, id: u64) {
+        let Some(connection) = self.connections.lock().remove(&id) else {
+            return;
+        };
+        connection.session.lock().close(&self.table);
+    }
+
+    pub fn len(&self) -> usize {
+        self.connections.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A line per connection with what its session is doing, followed by
+    /// the number of connections, e.g. for `.connections`.
+    pub fn describe(&self) -> String {
+        let connections = self.connections.lock();
+        let mut output = String::new();
+        for (id, connection) in connections.iter() {
+            // A session is locked while it runs a statement, which isn't
+            // waited for.
+            let state = match connection.session.try_lock() {
+                None => "running a statement".to_string(),
+                Some(session) => match session.transaction_id() {
+                    Some(txn_id) => {
+                        format!("in transaction {txn_id} ({})", session.isolation_level())
+                    }
+                    None => "idle".to_string(),
+                },
+            };
+            output.push_str(&format!("{id} {} {state}\n", connection.peer));
+        }
+
+        let tm = self.table.transaction_manager();
+        let transactions = match tm.active_transactions() {
+            0 => "no transactions in progress".to_string(),
+            active => format!(
+                "{active} transactions in progress, the oldest being {}",
+                tm.watermark()
+            ),
+        };
+        output.push_str(&format!(
+            "{} of {} connections, {transactions}\n",
+            connections.len(),
+            self.max_connections,
+        ));
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limit_and_clean_up_connections() {
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let sessions = SessionManager::new(Arc::new(Table::new(&path, 8))).with_max_connections(2);
+        let table = sessions.table().clone();
+
+        let (first, session) = sessions.connect("127.0.0.1:1000").unwrap();
+        let (second, _) = sessions.connect("127.0.0.1:2000").unwrap();
+        assert_eq!(
+            sessions.connect("127.0.0.1:3000").map(|(id, _)| id),
+            Err("too many connections (max 2)".to_string())
+        );
+
+        session.lock().execute(&table, "begin").unwrap();
+        session
+            .lock()
+            .execute(&table, "insert 1 john john@email.com")
+            .unwrap();
+        let txn_id = session.lock().transaction_id().unwrap();
+        assert_eq!(
+            sessions.describe(),
+            format!(
+                "{first} 127.0.0.1:1000 in transaction {txn_id} (repeatable_read)\n\
+                 {second} 127.0.0.1:2000 idle\n\
+                 2 of 2 connections, 1 transactions in progress, the oldest being {txn_id}\n"
+            )
+        );
+
+        // The transaction left open is rolled back, and the connection
+        // makes room for another one.
+        sessions.disconnect(first);
+        assert_eq!(session.lock().transaction_id(), None);
+        assert_eq!(table.transaction_manager().active_transactions(), 0);
+        assert_eq!(Session::new().execute(&table, "select"), Ok(String::new()));
+        assert!(sessions.connect("127.0.0.1:3000").is_ok());
+        assert_eq!(sessions.len(), 2);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
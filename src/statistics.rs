@@ -0,0 +1,284 @@
+use crate::query::Expression;
+use crate::row::Row;
+use crate::schema::Schema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Buckets of the histogram of the keys of a table.
+const BUCKETS: usize = 16;
+
+// Bits of the hash of a value picking its register of a sketch, for 1024
+// registers with a standard error of about 3%.
+const PRECISION: u32 = 10;
+
+/// Number of rows of a table, and how their keys are distributed, for the
+/// planner to estimate how many rows a range of keys returns. See
+/// `Planner`.
+///
+/// They're built by `Table::analyze`, and kept up to date by the inserts
+/// and deletes made after it. The histogram has buckets of equal width
+/// over the keys at the time of the analyze, where the first and last
+/// buckets also count the keys inserted below and above them since.
+///
+/// The number of distinct values of each column is estimated by a
+/// `DistinctCount` sketch, which rows inserted since are added to.
+///
+/// TRADEOFF: The range of the keys only widens, as deleting the smallest
+/// or largest key would need a lookup to find the next one, so estimates
+/// get less precise until the table is analyzed again. Likewise, deleted
+/// rows stay counted by the sketches, which can't remove a value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableStatistics {
+    pub row_count: usize,
+    pub min_key: u32,
+    pub max_key: u32,
+    // Start and width of the buckets of the histogram, as of the analyze.
+    start: u32,
+    width: u32,
+    buckets: Vec<usize>,
+    distinct: BTreeMap<String, DistinctCount>,
+}
+
+impl TableStatistics {
+    /// Build the statistics of the keys of a table, in ascending order.
+    pub fn build(keys: &[u32]) -> Self {
+        let min_key = keys.first().copied().unwrap_or(0);
+        let max_key = keys.last().copied().unwrap_or(0);
+        let width = (max_key - min_key) / BUCKETS as u32 + 1;
+
+        let mut statistics = Self {
+            row_count: 0,
+            min_key,
+            max_key,
+            start: min_key,
+            width,
+            buckets: vec![0; BUCKETS],
+            distinct: BTreeMap::new(),
+        };
+        for key in keys {
+            statistics.insert(*key);
+        }
+        statistics
+    }
+
+    /// Build the statistics of the rows of a table, in ascending order of
+    /// their keys, including the distinct values of their columns.
+    pub fn build_from_rows(rows: &[Row]) -> Self {
+        let keys: Vec<u32> = rows.iter().map(|row| row.id).collect();
+        let mut statistics = Self::build(&keys);
+        for column in Schema::users().columns() {
+            statistics
+                .distinct
+                .insert(column.name.clone(), DistinctCount::new());
+        }
+        rows.iter().for_each(|row| statistics.count_values(row));
+        statistics
+    }
+
+    pub fn insert_row(&mut self, row: &Row) {
+        self.insert(row.id);
+        self.count_values(row);
+    }
+
+    pub fn insert(&mut self, key: u32) {
+        if self.row_count == 0 {
+            (self.min_key, self.max_key, self.start) = (key, key, key);
+        }
+        self.row_count += 1;
+        self.min_key = self.min_key.min(key);
+        self.max_key = self.max_key.max(key);
+        let bucket = self.bucket(key);
+        self.buckets[bucket] += 1;
+    }
+
+    pub fn delete(&mut self, key: u32) {
+        self.row_count = self.row_count.saturating_sub(1);
+        let bucket = self.bucket(key);
+        self.buckets[bucket] = self.buckets[bucket].saturating_sub(1);
+    }
+
+    /// Estimate the number of rows with keys in `[start, end)`, assuming
+    /// the keys of a bucket are spread evenly across it.
+    pub fn estimate_range(&self, start: u32, end: Option<u32>) -> usize {
+        let start = start as u64;
+        let end = end.map_or(u32::MAX as u64 + 1, u64::from);
+        let last = self.buckets.len() - 1;
+
+        let mut estimate = 0.0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            let low = if i == 0 {
+                self.min_key as u64
+            } else {
+                self.start as u64 + i as u64 * self.width as u64
+            };
+            let high = if i == last {
+                self.max_key as u64 + 1
+            } else {
+                self.start as u64 + (i as u64 + 1) * self.width as u64
+            };
+            let overlap = end.min(high).saturating_sub(start.max(low));
+            if high > low {
+                estimate += *count as f64 * overlap as f64 / (high - low) as f64;
+            }
+        }
+        estimate.round() as usize
+    }
+
+    /// Estimated number of distinct values of the column, if it's counted.
+    pub fn distinct(&self, column: &str) -> Option<usize> {
+        self.distinct.get(column).map(DistinctCount::estimate)
+    }
+
+    /// Estimate the number of rows where the column equals a value,
+    /// assuming every value is as common.
+    ///
+    /// TODO (Joins): The planner doesn't plan joins or aggregates yet, but
+    /// their rows would be estimated the same way, e.g. a join on a column
+    /// returns about `rows * rows / distinct` rows.
+    pub fn estimate_equal(&self, column: &str) -> Option<usize> {
+        let distinct = self.distinct(column)?.max(1);
+        Some(self.row_count.div_ceil(distinct))
+    }
+
+    fn count_values(&mut self, row: &Row) {
+        for (column, sketch) in self.distinct.iter_mut() {
+            if let Some(value) = Expression::column(column).evaluate(row) {
+                sketch.insert(value.to_string().as_bytes());
+            }
+        }
+    }
+
+    fn bucket(&self, key: u32) -> usize {
+        ((key.saturating_sub(self.start) / self.width) as usize).min(self.buckets.len() - 1)
+    }
+}
+
+/// HyperLogLog sketch of the distinct values of a column, estimating
+/// their number in a fixed 1 KiB however many values are added.
+///
+/// Each value is hashed, where the first bits of the hash pick a register,
+/// which keeps the longest run of leading zeros seen in the rest of the
+/// hash. The more distinct values, the longer the runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DistinctCount {
+    registers: Vec<u8>,
+}
+
+impl DistinctCount {
+    pub fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << PRECISION],
+        }
+    }
+
+    pub fn insert(&mut self, value: &[u8]) {
+        let hash = hash(value);
+        let register = (hash >> (64 - PRECISION)) as usize;
+        let zeros = ((hash << PRECISION) | (1 << (PRECISION - 1))).leading_zeros() as u8 + 1;
+        self.registers[register] = self.registers[register].max(zeros);
+    }
+
+    pub fn estimate(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|zeros| 2f64.powi(-(*zeros as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+
+        // Few values leave registers empty, which linear counting is more
+        // precise for.
+        let empty = self.registers.iter().filter(|zeros| **zeros == 0).count();
+        if estimate <= 2.5 * m && empty > 0 {
+            (m * (m / empty as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+}
+
+// FNV-1a, mixed by the finalizer of MurmurHash3 so the high bits picking
+// the register depend on every byte. Unlike the hasher of the standard
+// library, it's stable across releases, as sketches are saved.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in bytes {
+        hash = (hash ^ *byte as u64).wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn estimate_rows_of_range() {
+        let keys: Vec<u32> = (1..=160).collect();
+        let mut statistics = TableStatistics::build(&keys);
+        assert_eq!(statistics.row_count, 160);
+        assert_eq!((statistics.min_key, statistics.max_key), (1, 160));
+        assert_eq!(statistics.estimate_range(0, None), 160);
+        assert_eq!(statistics.estimate_range(11, Some(21)), 10);
+        assert_eq!(statistics.estimate_range(150, None), 11);
+        assert_eq!(statistics.estimate_range(200, None), 0);
+
+        // Keys inserted past the last bucket are counted by it.
+        for key in 1001..=1100 {
+            statistics.insert(key);
+        }
+        assert_eq!(statistics.max_key, 1100);
+        assert_eq!(statistics.estimate_range(0, None), 260);
+        assert_eq!(statistics.estimate_range(0, Some(151)), 150);
+
+        for key in 1..=100 {
+            statistics.delete(key);
+        }
+        assert_eq!(statistics.row_count, 160);
+        assert_eq!(statistics.estimate_range(0, Some(101)), 0);
+
+        let empty = TableStatistics::build(&[]);
+        assert_eq!(empty.estimate_range(0, None), 0);
+        let mut one = empty;
+        one.insert(42);
+        assert_eq!((one.min_key, one.max_key), (42, 42));
+        assert_eq!(one.estimate_range(42, Some(43)), 1);
+    }
+
+    #[test]
+    fn estimate_distinct_values() {
+        let mut sketch = DistinctCount::new();
+        assert_eq!(sketch.estimate(), 0);
+        for i in 0..100_000 {
+            sketch.insert(format!("user{}", i % 20_000).as_bytes());
+        }
+        let estimate = sketch.estimate() as f64;
+        assert!((estimate - 20_000.0).abs() < 20_000.0 * 0.1, "{estimate}");
+
+        let rows: Vec<Row> = (1..=1000)
+            .map(|i| {
+                let username = format!("user{}", i % 10);
+                Row::new(&i.to_string(), &username, "team@example.com").unwrap()
+            })
+            .collect();
+        let mut statistics = TableStatistics::build_from_rows(&rows);
+        // Two of the usernames land in the same register.
+        assert_eq!(statistics.distinct("username"), Some(9));
+        assert_eq!(statistics.estimate_equal("username"), Some(112));
+        assert_eq!(statistics.distinct("email"), Some(1));
+        assert_eq!(statistics.estimate_equal("email"), Some(1000));
+        assert_eq!(statistics.distinct("name"), None);
+        let id = statistics.distinct("id").unwrap() as f64;
+        assert!((id - 1000.0).abs() < 1000.0 * 0.1, "{id}");
+
+        statistics.insert_row(&Row::new("1001", "john", "john@example.com").unwrap());
+        assert_eq!(statistics.row_count, 1001);
+        assert_eq!(statistics.distinct("email"), Some(2));
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use super::node::crc32;
+use super::page::{PageHeader, PageType, PAGE_FORMAT_VERSION, PAGE_HEADER_BYTES};
+use crate::row::Row;
+use crate::storage::PAGE_SIZE;
+
+// A string field encoded by a dictionary starts with a byte that never
+// starts a UTF-8 string, followed by the page of the dictionary and the
+// id of the string in it:
+//
+//   marker (1) | dictionary page id (4) | string id (4)
+//
+// The page id is part of the field, so a row can be decoded by the pager
+// without knowing which table it belongs to.
+const ENCODED_MARKER: u8 = 0xFF;
+const ENCODED_BYTES: usize = 9;
+
+/// Strings shared by the rows of a table, so a string field of a cell can
+/// store the id of its string instead of its bytes. See `Table::analyze`.
+///
+/// It's saved in a page of its own and never changes once written. A
+/// table gets a new dictionary, in a new page, when it's analyzed again.
+///
+/// TRADEOFF: An encoded field always takes `ENCODED_BYTES` in its cell,
+/// so encoding only frees space in the page for strings longer than
+/// that, see `Cell::size_for`.
+#[derive(Debug, PartialEq)]
+pub struct Dictionary {
+    page_id: usize,
+    strings: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+impl Dictionary {
+    fn new(page_id: usize, strings: Vec<String>) -> Self {
+        let ids = strings
+            .iter()
+            .enumerate()
+            .map(|(id, string)| (string.clone(), id as u32))
+            .collect();
+
+        Self {
+            page_id,
+            strings,
+            ids,
+        }
+    }
+
+    /// Build the dictionary from the string fields of every row, keeping
+    /// the strings that save the most bytes until the page is full.
+    ///
+    /// Strings that only appear once, or aren't longer than an encoded
+    /// field, aren't worth an entry.
+    pub fn build(page_id: usize, values: impl IntoIterator<Item = String>) -> Self {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for value in values {
+            if value.len() > ENCODED_BYTES {
+                *counts.entry(value).or_default() += 1;
+            }
+        }
+
+        let mut candidates: Vec<(String, usize)> =
+            counts.into_iter().filter(|(_, count)| *count > 1).collect();
+        candidates.sort_by(|(a, a_count), (b, b_count)| {
+            (b.len() * b_count)
+                .cmp(&(a.len() * a_count))
+                .then_with(|| a.cmp(b))
+        });
+
+        let mut strings = Vec::new();
+        let mut size = bincode::serialized_size(&strings).unwrap() as usize;
+        for (string, _) in candidates {
+            // Every entry is prefixed by its length.
+            let entry_size = 8 + string.len();
+            if size + entry_size > PAGE_SIZE - PAGE_HEADER_BYTES {
+                continue;
+            }
+            size += entry_size;
+            strings.push(string);
+        }
+
+        Self::new(page_id, strings)
+    }
+
+    pub fn page_id(&self) -> usize {
+        self.page_id
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Replace the string fields of the row that are in the dictionary by
+    /// their id.
+    pub fn encode(&self, row: &mut Row) {
+        self.encode_field(&mut row.username);
+        self.encode_field(&mut row.email);
+    }
+
+    fn encode_field(&self, field: &mut [u8]) {
+        let len = field
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(field.len());
+        let Some(&id) = std::str::from_utf8(&field[..len])
+            .ok()
+            .and_then(|string| self.ids.get(string))
+        else {
+            return;
+        };
+
+        field.fill(0);
+        field[0] = ENCODED_MARKER;
+        field[1..5].copy_from_slice(&(self.page_id as u32).to_le_bytes());
+        field[5..9].copy_from_slice(&id.to_le_bytes());
+    }
+
+    fn decode_field(&self, field: &mut [u8], id: u32) {
+        if let Some(string) = self.strings.get(id as usize) {
+            field.fill(0);
+            field[..string.len()].copy_from_slice(string.as_bytes());
+        }
+    }
+
+    pub fn as_bytes(&self) -> [u8; PAGE_SIZE] {
+        let body = bincode::serialize(&self.strings).unwrap();
+        let mut bytes = [0; PAGE_SIZE];
+        bytes[PAGE_HEADER_BYTES..PAGE_HEADER_BYTES + body.len()].copy_from_slice(&body);
+
+        let header = PageHeader {
+            page_type: PageType::Dictionary,
+            version: PAGE_FORMAT_VERSION,
+            flags: 0,
+            slot_count: self.strings.len() as u16,
+            page_id: self.page_id,
+            free_page: 0,
+            lsn: 0,
+            checksum: crc32(&bytes[PAGE_HEADER_BYTES..]),
+        };
+        bytes[..PAGE_HEADER_BYTES].copy_from_slice(&header.encode());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let header = PageHeader::decode(&bytes[..PAGE_HEADER_BYTES])?;
+        if header.page_type != PageType::Dictionary {
+            return Err(format!(
+                "page {} is a {:?} page, not a dictionary",
+                header.page_id, header.page_type
+            ));
+        }
+        if crc32(&bytes[PAGE_HEADER_BYTES..]) != header.checksum {
+            return Err(format!("dictionary page {} is corrupted", header.page_id));
+        }
+
+        let strings: Vec<String> = bincode::deserialize(&bytes[PAGE_HEADER_BYTES..])
+            .map_err(|err| format!("fail to read dictionary page {}: {err}", header.page_id))?;
+        Ok(Self::new(header.page_id, strings))
+    }
+}
+
+/// Page of the dictionary and id of the string an encoded field refers
+/// to, or `None` if the field holds the bytes of its string.
+pub(super) fn encoded_id(field: &[u8]) -> Option<(usize, u32)> {
+    if field[0] != ENCODED_MARKER {
+        return None;
+    }
+
+    let page_id = u32::from_le_bytes(field[1..5].try_into().unwrap()) as usize;
+    let id = u32::from_le_bytes(field[5..9].try_into().unwrap());
+    Some((page_id, id))
+}
+
+/// Dictionaries a row refers to, so they can be loaded before decoding it.
+pub fn dictionary_pages(row: &Row) -> impl Iterator<Item = usize> {
+    [encoded_id(&row.username), encoded_id(&row.email)]
+        .into_iter()
+        .flatten()
+        .map(|(page_id, _)| page_id)
+}
+
+/// Replace the encoded string fields of the row by their string, where
+/// `dictionary` returns the dictionary saved in the given page.
+///
+/// Fields of a dictionary that can't be found are left encoded.
+pub fn decode<'a, F>(row: &mut Row, dictionary: F)
+where
+    F: Fn(usize) -> Option<&'a Dictionary>,
+{
+    for field in [&mut row.username[..], &mut row.email[..]] {
+        if let Some((page_id, id)) = encoded_id(field) {
+            if let Some(dictionary) = dictionary(page_id) {
+                dictionary.decode_field(field, id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode_rows() {
+        let values = [
+            "john",
+            "john@example.com",
+            "john@example.com",
+            "jane@example.com",
+        ];
+        let dictionary = Dictionary::build(3, values.into_iter().map(String::from));
+        // "john" is too short and "jane@example.com" only appears once.
+        assert_eq!(dictionary.len(), 1);
+
+        let row = Row::new("1", "john", "john@example.com").unwrap();
+        let mut encoded = row.clone();
+        dictionary.encode(&mut encoded);
+        assert_eq!(encoded.username, row.username);
+        assert_ne!(encoded.email, row.email);
+        assert_eq!(dictionary_pages(&encoded).collect::<Vec<_>>(), vec![3]);
+
+        let mut decoded = encoded.clone();
+        decode(&mut decoded, |_| None);
+        assert_eq!(decoded, encoded);
+        decode(&mut decoded, |page_id| {
+            (page_id == 3).then_some(&dictionary)
+        });
+        assert_eq!(decoded, row);
+
+        let bytes = dictionary.as_bytes();
+        assert_eq!(Dictionary::from_bytes(&bytes), Ok(dictionary));
+
+        let mut corrupted = bytes;
+        corrupted[PAGE_HEADER_BYTES + 8] ^= 1;
+        assert_eq!(
+            Dictionary::from_bytes(&corrupted),
+            Err("dictionary page 3 is corrupted".to_string())
+        );
+    }
+}
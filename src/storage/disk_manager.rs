@@ -1,22 +1,73 @@
+use super::page::{PageHeader, PAGE_HEADER_BYTES};
 use super::pager::PAGE_SIZE;
 use std::{
+    borrow::Cow,
     fs::{File, OpenOptions},
     io::SeekFrom,
     io::{BufReader, Read, Seek, Write},
     path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
     sync::Mutex,
 };
 
+/// Whether the pages written are synced to disk, see
+/// `DbOptions::with_sync_mode`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncMode {
+    // Pages are only handed to the OS, so they survive a crash of the
+    // process, but not of the machine, unless they're in the WAL.
+    #[default]
+    Off,
+    // Each page is synced before its write returns.
+    Full,
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    // The write fails without writing anything.
+    FailWrite,
+    // Only the first half of the bytes is written before the write fails,
+    // like a torn page on power loss.
+    TornWrite,
+    // The bytes are written but syncing them to disk fails.
+    FailSync,
+}
+
+// Faults to inject into the upcoming writes, keyed by the index of the
+// write (counting both `write_page` and `append`) it applies to.
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct FaultInjector {
+    writes: usize,
+    faults: Vec<(usize, Fault)>,
+}
+
 #[derive(Debug)]
 pub struct DiskManager {
+    // Reads and writes go through separate file handles with their own
+    // lock, so a page read never queue behind a batch of page flushes.
+    //
+    // TRADEOFF: Reads are still serialized with each other, as they share
+    // the same file cursor. Using positional reads would avoid that.
     write_file: Mutex<File>,
     read_file: Mutex<File>,
     path: String,
     pub file_len: usize,
+    sync_mode: SyncMode,
+    // First free page of a file of pages, see `DiskManager::set_free_page`.
+    free_page: AtomicUsize,
+
+    #[cfg(test)]
+    fault_injector: Mutex<FaultInjector>,
 }
 
 impl DiskManager {
     pub fn new(path: impl AsRef<Path>) -> Self {
+        Self::with_sync_mode(path, SyncMode::Off)
+    }
+
+    pub fn with_sync_mode(path: impl AsRef<Path>, sync_mode: SyncMode) -> Self {
         let write_file = OpenOptions::new()
             .write(true)
             .create(true)
@@ -31,13 +82,66 @@ impl DiskManager {
             read_file: Mutex::new(read_file),
             path: path.as_ref().to_str().unwrap().into(),
             file_len,
+            sync_mode,
+            free_page: AtomicUsize::new(0),
+            #[cfg(test)]
+            fault_injector: Mutex::new(FaultInjector::default()),
         }
     }
 
+    /// Inject a fault into the `nth` write from now, where 0 is the next write.
+    #[cfg(test)]
+    pub fn inject_fault(&self, nth: usize, fault: Fault) {
+        let mut injector = self.fault_injector.lock().unwrap();
+        let index = injector.writes + nth;
+        injector.faults.push((index, fault));
+    }
+
+    #[cfg(test)]
+    pub fn clear_faults(&self) {
+        self.fault_injector.lock().unwrap().faults.clear();
+    }
+
+    #[cfg(test)]
+    fn next_fault(&self) -> Option<Fault> {
+        let mut injector = self.fault_injector.lock().unwrap();
+        let index = injector.writes;
+        injector.writes += 1;
+
+        let position = injector.faults.iter().position(|(i, _)| *i == index)?;
+        Some(injector.faults.remove(position).1)
+    }
+
+    // Write the bytes and sync them, applying the injected fault if any.
+    fn write_and_sync(
+        &self,
+        file: &mut File,
+        bytes: &[u8],
+        sync: impl FnOnce(&mut File) -> Result<(), std::io::Error>,
+    ) -> Result<(), std::io::Error> {
+        #[cfg(test)]
+        if let Some(fault) = self.next_fault() {
+            let error = std::io::Error::other(format!("injected fault: {fault:?}"));
+
+            match fault {
+                Fault::FailWrite => {}
+                Fault::TornWrite => {
+                    file.write_all(&bytes[..bytes.len() / 2])?;
+                    file.flush()?;
+                }
+                Fault::FailSync => file.write_all(bytes)?,
+            }
+
+            return Err(error);
+        }
+
+        file.write_all(bytes)?;
+        sync(file)
+    }
+
     pub fn append(&self, bytes: &[u8]) -> Result<(), std::io::Error> {
         let mut file = self.write_file.lock().unwrap();
-        file.write_all(bytes)?;
-        file.sync_all()
+        self.write_and_sync(&mut file, bytes, |file| file.sync_all())
     }
 
     pub fn read_exact(&self, buf: &mut [u8]) {
@@ -52,10 +156,80 @@ impl DiskManager {
     pub fn write_page(&self, page_id: usize, page_bytes: &[u8]) -> Result<(), std::io::Error> {
         let offset = page_id * PAGE_SIZE;
         let mut write_file = self.write_file.lock().unwrap();
+
+        // Page 0 is serialized without the first free page, which only
+        // the disk manager keeps track of.
+        let free_page = self.free_page.load(Ordering::Acquire);
+        let bytes = if page_id == 0 && free_page != 0 {
+            let mut bytes = page_bytes.to_vec();
+            PageHeader::set_free_page(&mut bytes, free_page);
+            Cow::Owned(bytes)
+        } else {
+            Cow::Borrowed(page_bytes)
+        };
+
         write_file.seek(SeekFrom::Start(offset as u64))?;
-        write_file.write_all(page_bytes)?;
-        // TODO: We probably need to call sync_all(), to ensure that fsync is executed.
-        write_file.flush()
+        self.write_and_sync(&mut write_file, &bytes, |file| self.sync(file))
+    }
+
+    fn sync(&self, file: &mut File) -> Result<(), std::io::Error> {
+        match self.sync_mode {
+            SyncMode::Off => file.flush(),
+            SyncMode::Full => file.sync_data(),
+        }
+    }
+
+    /// Return the first page of the free page list of the file, or 0 if
+    /// it's empty, see `PageHeader::free_page`.
+    pub fn free_page(&self) -> usize {
+        self.free_page.load(Ordering::Acquire)
+    }
+
+    /// Read the first free page from the header of page 0, for a file of
+    /// pages rather than e.g. a log segment.
+    pub fn load_free_page(&self) {
+        if let Ok(bytes) = self.read_page(0) {
+            if let Ok(header) = PageHeader::decode(&bytes[..PAGE_HEADER_BYTES]) {
+                self.free_page.store(header.free_page, Ordering::Release);
+            }
+        }
+    }
+
+    /// Set the first free page, writing it to the header of page 0 right
+    /// away.
+    ///
+    /// TRADEOFF: If page 0 isn't written yet, or is of a format without a
+    /// free page, it's only written along with page 0 the next time it is.
+    /// The free pages are lost if the file is closed before, and never
+    /// reused.
+    pub fn set_free_page(&self, page_id: usize) -> Result<(), std::io::Error> {
+        let mut write_file = self.write_file.lock().unwrap();
+
+        let mut header = [0; PAGE_HEADER_BYTES];
+        let read = {
+            let mut read_file = self.read_file.lock().unwrap();
+            read_file
+                .seek(SeekFrom::Start(0))
+                .and_then(|_| read_file.read_exact(&mut header))
+        };
+        if read.is_ok() && PageHeader::set_free_page(&mut header, page_id) {
+            write_file.seek(SeekFrom::Start(0))?;
+            self.write_and_sync(&mut write_file, &header, |file| self.sync(file))?;
+        }
+
+        self.free_page.store(page_id, Ordering::Release);
+        Ok(())
+    }
+
+    /// Return an error if pages can't be written to the file, e.g. when it
+    /// has been made read only or removed since it was opened.
+    pub fn check_writable(&self) -> Result<(), std::io::Error> {
+        let metadata = std::fs::metadata(&self.path)?;
+        if metadata.permissions().readonly() {
+            return Err(std::io::Error::other(format!("{} is read only", self.path)));
+        }
+
+        Ok(())
     }
 
     pub fn read_page(&self, page_id: usize) -> Result<[u8; PAGE_SIZE], std::io::Error> {
@@ -186,4 +360,48 @@ mod test {
 
         let _ = std::fs::remove_file(file);
     }
+
+    #[test]
+    fn inject_fault_into_nth_write() {
+        let file = format!("test_file_{:?}", std::thread::current().id());
+        let disk_manager = DiskManager::new(&file);
+        disk_manager.write_page(0, &[1; PAGE_SIZE]).unwrap();
+
+        disk_manager.inject_fault(1, Fault::FailWrite);
+        disk_manager.write_page(0, &[2; PAGE_SIZE]).unwrap();
+        assert!(disk_manager.write_page(1, &[2; PAGE_SIZE]).is_err());
+        assert!(disk_manager.read_page(1).is_err());
+
+        // Fault only apply once
+        disk_manager.write_page(1, &[3; PAGE_SIZE]).unwrap();
+        assert_eq!(disk_manager.read_page(1).unwrap(), [3; PAGE_SIZE]);
+
+        disk_manager.inject_fault(0, Fault::FailWrite);
+        disk_manager.clear_faults();
+        disk_manager.write_page(1, &[4; PAGE_SIZE]).unwrap();
+        assert_eq!(disk_manager.read_page(1).unwrap(), [4; PAGE_SIZE]);
+
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn inject_torn_write_and_failed_sync() {
+        let file = format!("test_file_{:?}", std::thread::current().id());
+        let disk_manager = DiskManager::new(&file);
+        disk_manager.write_page(0, &[1; PAGE_SIZE]).unwrap();
+
+        // Only half of the new page make it to disk.
+        disk_manager.inject_fault(0, Fault::TornWrite);
+        assert!(disk_manager.write_page(0, &[2; PAGE_SIZE]).is_err());
+        let page = disk_manager.read_page(0).unwrap();
+        assert_eq!(page[..PAGE_SIZE / 2], [2; PAGE_SIZE / 2]);
+        assert_eq!(page[PAGE_SIZE / 2..], [1; PAGE_SIZE / 2]);
+
+        // The bytes are written, but the caller is told it failed.
+        disk_manager.inject_fault(0, Fault::FailSync);
+        assert!(disk_manager.write_page(0, &[3; PAGE_SIZE]).is_err());
+        assert_eq!(disk_manager.read_page(0).unwrap(), [3; PAGE_SIZE]);
+
+        let _ = std::fs::remove_file(file);
+    }
 }
@@ -0,0 +1,578 @@
+use super::dictionary::encoded_id;
+use super::node::{
+    crc32, COMMON_NODE_HEADER_SIZE, INTERNAL_NODE_CELL_SIZE, INTERNAL_NODE_HEADER_SIZE,
+    LEAF_NODE_HEADER_SIZE, LEAF_NODE_SLOT_SIZE, LEAF_NODE_SPACE_FOR_CELLS, LEAF_NODE_V1_CELL_SIZE,
+};
+use super::page::{
+    PageType, FREE_PAGE_FORMAT_VERSION, MIN_PAGE_FORMAT_VERSION, PAGE_FORMAT_VERSION,
+    PAGE_HEADER_BYTES,
+};
+use super::PAGE_SIZE;
+use crate::row::{EMAIL_SIZE, USERNAME_SIZE};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+// Internal nodes fit at most one cell per child beyond the right child.
+const INTERNAL_NODE_MAX_CELLS: usize =
+    (PAGE_SIZE - PAGE_HEADER_BYTES - INTERNAL_NODE_HEADER_SIZE) / INTERNAL_NODE_CELL_SIZE;
+
+/// Print the byte layout of a page of the database file, a line per
+/// field with its offset in the page, its size, its name and its value,
+/// e.g. to document the on-disk format or to look into a page that fails
+/// to open.
+///
+/// The page is read straight from the file rather than through the
+/// pager, so a page the pager rejects, e.g. of another format version,
+/// is printed too.
+pub fn format_dump(path: impl AsRef<Path>, page_id: usize) -> Result<String, String> {
+    let path = path.as_ref();
+    let read_page = || -> std::io::Result<Option<Vec<u8>>> {
+        let mut file = File::open(path)?;
+        if ((page_id + 1) * PAGE_SIZE) as u64 > file.metadata()?.len() {
+            return Ok(None);
+        }
+
+        let mut bytes = vec![0; PAGE_SIZE];
+        file.seek(SeekFrom::Start((page_id * PAGE_SIZE) as u64))?;
+        file.read_exact(&mut bytes)?;
+        Ok(Some(bytes))
+    };
+
+    let bytes = read_page()
+        .map_err(|err| format!("{}: {err}", path.display()))?
+        .ok_or_else(|| format!("page {page_id} is past the end of the file"))?;
+    Ok(describe_page(page_id, &bytes))
+}
+
+/// Same as `format_dump`, but for the bytes of a page read already.
+pub fn describe_page(page_id: usize, bytes: &[u8]) -> String {
+    let mut layout = Layout::new(bytes);
+    let page_type = PageType::try_from(bytes[0]);
+
+    layout.field("page type", 1, |field| match &page_type {
+        Ok(page_type) => format!("{} ({page_type:?})", field[0]),
+        Err(reason) => format!("{} ({reason})", field[0]),
+    });
+    layout.field("format version", 1, |field| {
+        if (MIN_PAGE_FORMAT_VERSION..=PAGE_FORMAT_VERSION).contains(&field[0]) {
+            field[0].to_string()
+        } else {
+            format!(
+                "{} (unsupported, expected {MIN_PAGE_FORMAT_VERSION} to {PAGE_FORMAT_VERSION})",
+                field[0]
+            )
+        }
+    });
+    layout.field("flags", 2, |field| format!("{:#06x}", u16_at(field)));
+    layout.field("slot count", 2, |field| u16_at(field).to_string());
+    // See `PageHeader::free_page`.
+    let page_id_size = if bytes[1] < FREE_PAGE_FORMAT_VERSION {
+        8
+    } else {
+        4
+    };
+    layout.field("page id", page_id_size, |field| {
+        let mut id = [0; 8];
+        id[..field.len()].copy_from_slice(field);
+        let id = u64::from_le_bytes(id);
+        if id == page_id as u64 {
+            id.to_string()
+        } else {
+            format!("{id} (mismatch, the page is at {page_id})")
+        }
+    });
+    if page_id_size == 4 {
+        layout.field("free page", 4, |field| match u32_at(field) {
+            0 => "0 (none)".to_string(),
+            page => page.to_string(),
+        });
+    }
+    layout.field("lsn", 4, |field| u32_at(field).to_string());
+    layout.field("checksum", 4, |field| {
+        checksum(u32_at(field), &bytes[PAGE_HEADER_BYTES..])
+    });
+
+    match page_type {
+        Ok(PageType::Leaf) => describe_leaf(&mut layout, bytes[1]),
+        Ok(PageType::Internal) => describe_internal(&mut layout),
+        // A free page has nothing but its header.
+        Ok(PageType::Free) => {}
+        // Dictionaries and metadata are serialized with bincode, so their
+        // fields aren't at fixed offsets.
+        Ok(page_type) => {
+            layout.field("body", PAGE_SIZE - PAGE_HEADER_BYTES, |_| {
+                format!("{page_type:?} serialized with bincode")
+            });
+        }
+        Err(_) => {}
+    }
+    layout.rest();
+
+    let page_type = match page_type {
+        Ok(page_type) => format!("{page_type:?}").to_lowercase(),
+        Err(_) => "unknown".to_string(),
+    };
+    format!(
+        "page {page_id}: {page_type} page of {PAGE_SIZE} bytes\n{:>6}  {:>4}  {:<20}  value\n{}",
+        "offset",
+        "size",
+        "field",
+        layout.lines.concat()
+    )
+}
+
+fn describe_node_header(layout: &mut Layout) {
+    layout.field("node type", 1, |field| match field[0] {
+        0 => "0 (internal)".to_string(),
+        1 => "1 (leaf)".to_string(),
+        other => format!("{other} (unknown)"),
+    });
+    layout.field("is root", 1, |field| (field[0] != 0).to_string());
+    layout.field("parent page", 4, |field| u32_at(field).to_string());
+    debug_assert_eq!(layout.offset, PAGE_HEADER_BYTES + COMMON_NODE_HEADER_SIZE);
+}
+
+fn describe_leaf(layout: &mut Layout, version: u8) {
+    describe_node_header(layout);
+    let mut num_of_cells = 0;
+    layout.field("cell count", 4, |field| {
+        num_of_cells = u32_at(field) as usize;
+        num_of_cells.to_string()
+    });
+    layout.field("next leaf page", 4, |field| match u32_at(field) {
+        0 => "0 (none)".to_string(),
+        page => page.to_string(),
+    });
+    debug_assert_eq!(layout.offset, PAGE_HEADER_BYTES + LEAF_NODE_HEADER_SIZE);
+
+    if version == 1 {
+        describe_leaf_cells_v1(layout, num_of_cells);
+    } else {
+        describe_slotted_cells(layout, num_of_cells);
+    }
+}
+
+fn describe_leaf_cells_v1(layout: &mut Layout, num_of_cells: usize) {
+    for i in 0..num_of_cells.min(LEAF_NODE_SPACE_FOR_CELLS / LEAF_NODE_V1_CELL_SIZE) {
+        let cell = &layout.bytes[layout.offset..layout.offset + LEAF_NODE_V1_CELL_SIZE];
+        let checked = &cell[..LEAF_NODE_V1_CELL_SIZE - 4];
+        layout.field(&format!("cell {i} key"), 4, |field| {
+            u32_at(field).to_string()
+        });
+        layout.field(&format!("cell {i} row id"), 4, |field| {
+            u32_at(field).to_string()
+        });
+        layout.field(&format!("cell {i} username"), USERNAME_SIZE, text);
+        layout.field(&format!("cell {i} email"), EMAIL_SIZE, text);
+        layout.field(&format!("cell {i} is deleted"), 1, |field| {
+            (field[0] != 0).to_string()
+        });
+        layout.field(&format!("cell {i} checksum"), 4, |field| {
+            checksum(u32_at(field), checked)
+        });
+    }
+}
+
+// The slots in key order, then the free space and the cells in the order
+// they're in the page, i.e. from the last one.
+fn describe_slotted_cells(layout: &mut Layout, num_of_cells: usize) {
+    let mut cells = Vec::new();
+    for i in 0..num_of_cells.min(LEAF_NODE_SPACE_FOR_CELLS / LEAF_NODE_SLOT_SIZE) {
+        let (mut offset, mut len) = (0, 0);
+        layout.field(&format!("slot {i} offset"), 2, |field| {
+            offset = u16_at(field) as usize;
+            offset.to_string()
+        });
+        layout.field(&format!("slot {i} length"), 2, |field| {
+            len = u16_at(field) as usize;
+            len.to_string()
+        });
+        cells.push((PAGE_HEADER_BYTES + offset, len, i));
+    }
+    cells.sort_unstable();
+
+    // Cells are described as long as they follow each other up to the end
+    // of the page, which they do unless the page is corrupted, otherwise
+    // they're left to the unused bytes.
+    let Some(&(start, ..)) = cells.first() else {
+        return;
+    };
+    let mut end = start;
+    for &(offset, len, _) in &cells {
+        if offset != end || len < 9 {
+            return;
+        }
+        end += len;
+    }
+    if start < layout.offset || end != PAGE_SIZE {
+        return;
+    }
+
+    if start > layout.offset {
+        layout.field("free space", start - layout.offset, zeroed);
+    }
+    for (start, len, i) in cells {
+        let checked = &layout.bytes[start..start + len - 4];
+        layout.field(&format!("cell {i} key"), 4, |field| {
+            u32_at(field).to_string()
+        });
+        layout.field(&format!("cell {i} is deleted"), 1, |field| {
+            (field[0] != 0).to_string()
+        });
+
+        let mut rest = len - 9;
+        for (name, size) in [("username", USERNAME_SIZE), ("email", EMAIL_SIZE)] {
+            if rest == 0 {
+                break;
+            }
+            let mut field_len = 0;
+            layout.field(&format!("cell {i} {name} len"), 1, |field| {
+                field_len = field[0] as usize;
+                field_len.to_string()
+            });
+            let field_len = field_len.min(rest - 1).min(size);
+            layout.field(&format!("cell {i} {name}"), field_len, |field| {
+                // Fields are stored without their padding.
+                let mut padded = vec![0; size];
+                padded[..field.len()].copy_from_slice(field);
+                text(&padded)
+            });
+            rest -= 1 + field_len;
+        }
+        if rest > 0 {
+            layout.field(&format!("cell {i} unused"), rest, zeroed);
+        }
+        layout.field(&format!("cell {i} checksum"), 4, |field| {
+            checksum(u32_at(field), checked)
+        });
+    }
+}
+
+fn describe_internal(layout: &mut Layout) {
+    describe_node_header(layout);
+    let mut num_of_keys = 0;
+    layout.field("key count", 4, |field| {
+        num_of_keys = u32_at(field) as usize;
+        num_of_keys.to_string()
+    });
+    layout.field("right child page", 4, |field| u32_at(field).to_string());
+    debug_assert_eq!(layout.offset, PAGE_HEADER_BYTES + INTERNAL_NODE_HEADER_SIZE);
+
+    for i in 0..num_of_keys.min(INTERNAL_NODE_MAX_CELLS) {
+        layout.field(&format!("cell {i} child page"), 4, |field| {
+            u32_at(field).to_string()
+        });
+        layout.field(&format!("cell {i} key"), 4, |field| {
+            u32_at(field).to_string()
+        });
+    }
+}
+
+// Lines of the layout of a page, each field following the previous one.
+struct Layout<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    lines: Vec<String>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+            lines: Vec::new(),
+        }
+    }
+
+    fn field(&mut self, name: &str, size: usize, value: impl FnOnce(&[u8]) -> String) {
+        let field = &self.bytes[self.offset..self.offset + size];
+        self.lines.push(format!(
+            "{:>6}  {size:>4}  {name:<20}  {}\n",
+            self.offset,
+            value(field)
+        ));
+        self.offset += size;
+    }
+
+    // The bytes after the last field, which are zeroed unless the page
+    // is corrupted.
+    fn rest(&mut self) {
+        let size = self.bytes.len() - self.offset;
+        if size == 0 {
+            return;
+        }
+        self.field("unused", size, zeroed);
+    }
+}
+
+// Bytes that are expected to be zeroed.
+fn zeroed(field: &[u8]) -> String {
+    match field.iter().filter(|byte| **byte != 0).count() {
+        0 => "zeroed".to_string(),
+        non_zero => format!("{non_zero} non-zero bytes"),
+    }
+}
+
+fn u16_at(field: &[u8]) -> u16 {
+    u16::from_le_bytes(field[..2].try_into().unwrap())
+}
+
+fn u32_at(field: &[u8]) -> u32 {
+    u32::from_le_bytes(field[..4].try_into().unwrap())
+}
+
+fn checksum(stored: u32, bytes: &[u8]) -> String {
+    if stored == crc32(bytes) {
+        format!("{stored:#010x} (ok)")
+    } else {
+        format!("{stored:#010x} (mismatch, expected {:#010x})", crc32(bytes))
+    }
+}
+
+// A string field padded with zeroes, or encoded by a dictionary.
+fn text(field: &[u8]) -> String {
+    if let Some((page_id, id)) = encoded_id(field) {
+        return format!("string {id} of the dictionary in page {page_id}");
+    }
+
+    let len = field
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(field.len());
+    format!("{:?}", String::from_utf8_lossy(&field[..len]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::prepare_statement;
+    use crate::row::Row;
+    use crate::schema::Schema;
+    use crate::storage::page::{Page, PageHeader};
+    use crate::table::Table;
+    use std::path::PathBuf;
+
+    // Database files written by each format, which every later version
+    // has to keep reading. They are only written again, by
+    // `cargo test --release write_format_fixtures -- --ignored`, along
+    // with a bump of `PAGE_FORMAT_VERSION`, while the fixtures of the
+    // previous versions are kept.
+    const FIXTURES: [&str; 2] = ["users", "tables"];
+    // Pages of each fixture whose layout is kept, see `format_dump`.
+    const LAYOUT_PAGES: [usize; 2] = [0, 1];
+
+    fn fixtures() -> impl Iterator<Item = (u8, &'static str)> {
+        (MIN_PAGE_FORMAT_VERSION..=PAGE_FORMAT_VERSION)
+            .flat_map(|version| FIXTURES.map(|name| (version, name)))
+    }
+
+    fn fixture_dir(version: u8) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(format!("format-v{version}"))
+    }
+
+    // Copy the fixture next to the tests, as opening a table can write to
+    // its file. A catalog is only written once it changes, so there is
+    // none for a file with the default table only.
+    fn open_fixture(version: u8, name: &str) -> (Table, String) {
+        let db = format!("test-{:?}-{name}.db", std::thread::current().id());
+        let source = fixture_dir(version).join(format!("{name}.db"));
+        std::fs::copy(&source, &db).unwrap();
+        let catalog = source.with_extension("db.catalog");
+        if catalog.exists() {
+            std::fs::copy(catalog, format!("{db}.catalog")).unwrap();
+        }
+        (Table::new(&db, 8), db)
+    }
+
+    fn clean_fixture(db: &str) {
+        let _ = std::fs::remove_file(db);
+        let _ = std::fs::remove_file(format!("{db}.catalog"));
+    }
+
+    // Everything the fixture holds, compared with the `.expected` file
+    // written along with it.
+    fn contents(table: &Table) -> String {
+        let select = prepare_statement("select").unwrap();
+        let mut names = table.table_names();
+        names.sort();
+
+        let mut output = String::new();
+        for name in names {
+            let rows = table
+                .with_table(Some(&name), |table| table.select(&select))
+                .unwrap();
+            output.push_str(&format!("table {name}:\n{rows}"));
+        }
+        let version = table
+            .meta_get("app.version")
+            .unwrap()
+            .map(|version| String::from_utf8_lossy(&version).into_owned());
+        output.push_str(&format!("metadata app.version: {version:?}\n"));
+        output
+    }
+
+    #[test]
+    #[ignore]
+    fn write_format_fixtures() {
+        let dir = fixture_dir(PAGE_FORMAT_VERSION);
+        std::fs::create_dir_all(&dir).unwrap();
+        // Rows of different sizes, so a leaf holds a few dozens of them.
+        let row = |i: u32| {
+            Row::new(
+                &i.to_string(),
+                &format!("user{i}"),
+                &format!("user{i}@{}.com", "mail".repeat(i as usize % 50)),
+            )
+        };
+
+        for name in FIXTURES {
+            let db = dir.join(format!("{name}.db"));
+            let _ = std::fs::remove_file(&db);
+            let _ = std::fs::remove_file(db.with_extension("db.catalog"));
+            let table = Table::new(&db, 8);
+
+            // A tree of a few levels, with a couple of rows deleted.
+            for i in 1..=40 {
+                table.insert(&row(i).unwrap());
+            }
+            table.delete(&row(7).unwrap());
+            table.delete(&row(21).unwrap());
+            // And enough of the last ones for leaves to merge, so the file
+            // has free pages.
+            for i in 25..=40 {
+                table.delete(&row(i).unwrap());
+            }
+
+            // Another table, an index, a dictionary and metadata.
+            if name == "tables" {
+                table.create_table("orders", &Schema::users()).unwrap();
+                let orders = table.open_table("orders").unwrap();
+                for i in 1..=5 {
+                    orders.insert(&row(i * 100).unwrap());
+                }
+                // A username shared by a few rows ends up in the dictionary,
+                // and the rows written with it once analyzed are encoded.
+                let member =
+                    |i: u32| Row::new(&i.to_string(), "team-member", &format!("user{i}@email.com"));
+                for i in 41..=44 {
+                    table.insert(&member(i).unwrap());
+                }
+                table.create_index("email", true).unwrap();
+                table.analyze().unwrap();
+                for i in [3, 4] {
+                    table.delete(&row(i).unwrap());
+                    table.insert(&member(i).unwrap());
+                }
+                table.meta_put("app.version", b"1.0").unwrap();
+            }
+
+            table.flush();
+            std::fs::write(dir.join(format!("{name}.expected")), contents(&table)).unwrap();
+            drop(table);
+            for page in LAYOUT_PAGES {
+                std::fs::write(
+                    dir.join(format!("{name}.page-{page}.layout")),
+                    format_dump(&db, page).unwrap(),
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn open_format_fixtures() {
+        for (version, name) in fixtures() {
+            let (table, db) = open_fixture(version, name);
+
+            // Every page is of the version of the fixture, and its body
+            // matches its checksum, except the pages allocated but never
+            // written, e.g. the internal nodes replaced by a split of the
+            // root.
+            let bytes = std::fs::read(&db).unwrap();
+            assert_eq!(bytes.len() % PAGE_SIZE, 0, "{name}");
+            for (page_id, page) in bytes.chunks(PAGE_SIZE).enumerate() {
+                if page.iter().all(|byte| *byte == 0) {
+                    continue;
+                }
+                let header = PageHeader::decode(&page[..PAGE_HEADER_BYTES]).unwrap();
+                assert_eq!(header.page_id, page_id, "{name}");
+                assert_eq!(header.version, version, "{name}: page {page_id}");
+                assert!(Page::verify_checksum(page), "{name}: page {page_id}");
+            }
+
+            assert_eq!(table.check(), Ok(()), "v{version} {name}");
+            assert_eq!(table.scrub(), "No checksum mismatch found.", "{name}");
+            let expected =
+                std::fs::read_to_string(fixture_dir(version).join(format!("{name}.expected")))
+                    .unwrap();
+            assert_eq!(contents(&table), expected, "v{version} {name}");
+
+            drop(table);
+            clean_fixture(&db);
+        }
+    }
+
+    #[test]
+    fn format_dump_fixtures() {
+        for (version, name) in fixtures() {
+            let db = fixture_dir(version).join(format!("{name}.db"));
+            for page in LAYOUT_PAGES {
+                let layout = fixture_dir(version).join(format!("{name}.page-{page}.layout"));
+                let expected = std::fs::read_to_string(layout).unwrap();
+                assert_eq!(
+                    format_dump(&db, page).unwrap(),
+                    expected,
+                    "v{version} {name}"
+                );
+            }
+        }
+        let db = fixture_dir(PAGE_FORMAT_VERSION).join("users.db");
+        assert!(format_dump(db, 1000).is_err());
+    }
+
+    #[test]
+    fn rewrite_pages_of_an_older_format() {
+        let (table, db) = open_fixture(MIN_PAGE_FORMAT_VERSION, "users");
+        let row = Row::new("41", "a-much-longer-username", "user41@email.com").unwrap();
+        table.insert(&row);
+        table.flush();
+        drop(table);
+
+        // Only the pages written since are of the current format.
+        let bytes = std::fs::read(&db).unwrap();
+        let versions: Vec<u8> = bytes.chunks(PAGE_SIZE).map(|page| page[1]).collect();
+        assert!(versions.contains(&MIN_PAGE_FORMAT_VERSION));
+        assert!(versions.contains(&PAGE_FORMAT_VERSION));
+
+        let table = Table::new(&db, 8);
+        assert_eq!(table.check(), Ok(()));
+        assert_eq!(table.scrub(), "No checksum mismatch found.");
+        let expected = std::fs::read_to_string(fixture_dir(1).join("users.expected")).unwrap();
+        let expected = expected.replace(
+            "metadata",
+            "(41, a-much-longer-username, user41@email.com)\nmetadata",
+        );
+        assert_eq!(contents(&table), expected);
+
+        drop(table);
+        clean_fixture(&db);
+    }
+
+    #[test]
+    fn describe_corrupted_page() {
+        let mut page = vec![0; PAGE_SIZE];
+        page[0] = 9;
+        page[1] = PAGE_FORMAT_VERSION + 1;
+        page[100] = 1;
+
+        let layout = describe_page(3, &page);
+        assert!(layout.starts_with("page 3: unknown page of 4096 bytes\n"));
+        assert!(layout.contains("page type             9 (unknown page type 9)\n"));
+        assert!(layout.contains("(unsupported, expected 1 to 3)\n"));
+        assert!(layout.contains("page id               0 (mismatch, the page is at 3)\n"));
+        assert!(layout.contains("unused                1 non-zero bytes\n"));
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use super::node::crc32;
+use super::page::{PageHeader, PageType, PAGE_FORMAT_VERSION, PAGE_HEADER_BYTES};
+use crate::storage::PAGE_SIZE;
+
+/// Key-value pairs an application embedding the database stores next to
+/// its data, e.g. its settings or counters. See `Table::meta_put`.
+///
+/// Like a `Dictionary`, it's saved in a page of its own and never changes
+/// once written. Every change writes the whole namespace to a new page,
+/// which the catalog then points to, so a crash in between leaves the
+/// previous version in place.
+///
+/// TRADEOFF: The namespace has to fit in a single page, as it's meant for
+/// a few small values rather than as a table without a schema.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    page_id: usize,
+    entries: BTreeMap<String, Vec<u8>>,
+}
+
+impl Metadata {
+    pub fn new(page_id: usize, entries: BTreeMap<String, Vec<u8>>) -> Result<Self, String> {
+        let size = bincode::serialized_size(&entries).unwrap() as usize;
+        if size > PAGE_SIZE - PAGE_HEADER_BYTES {
+            return Err(format!(
+                "metadata of {size} bytes doesn't fit in a page of {} bytes",
+                PAGE_SIZE - PAGE_HEADER_BYTES
+            ));
+        }
+
+        Ok(Self { page_id, entries })
+    }
+
+    pub fn page_id(&self) -> usize {
+        self.page_id
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    pub fn entries(&self) -> &BTreeMap<String, Vec<u8>> {
+        &self.entries
+    }
+
+    pub fn as_bytes(&self) -> [u8; PAGE_SIZE] {
+        let body = bincode::serialize(&self.entries).unwrap();
+        let mut bytes = [0; PAGE_SIZE];
+        bytes[PAGE_HEADER_BYTES..PAGE_HEADER_BYTES + body.len()].copy_from_slice(&body);
+
+        let header = PageHeader {
+            page_type: PageType::Metadata,
+            version: PAGE_FORMAT_VERSION,
+            flags: 0,
+            slot_count: self.entries.len() as u16,
+            page_id: self.page_id,
+            free_page: 0,
+            lsn: 0,
+            checksum: crc32(&bytes[PAGE_HEADER_BYTES..]),
+        };
+        bytes[..PAGE_HEADER_BYTES].copy_from_slice(&header.encode());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let header = PageHeader::decode(&bytes[..PAGE_HEADER_BYTES])?;
+        if header.page_type != PageType::Metadata {
+            return Err(format!(
+                "page {} is a {:?} page, not metadata",
+                header.page_id, header.page_type
+            ));
+        }
+        if crc32(&bytes[PAGE_HEADER_BYTES..]) != header.checksum {
+            return Err(format!("metadata page {} is corrupted", header.page_id));
+        }
+
+        let entries = bincode::deserialize(&bytes[PAGE_HEADER_BYTES..])
+            .map_err(|err| format!("fail to read metadata page {}: {err}", header.page_id))?;
+        Ok(Self {
+            page_id: header.page_id,
+            entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn metadata_as_bytes_from_bytes() {
+        let entries = BTreeMap::from([
+            ("app.version".to_string(), b"1.2.0".to_vec()),
+            ("app.counter".to_string(), 42u64.to_le_bytes().to_vec()),
+        ]);
+        let metadata = Metadata::new(7, entries).unwrap();
+        assert_eq!(metadata.get("app.version"), Some(&b"1.2.0"[..]));
+        assert_eq!(metadata.get("app.missing"), None);
+
+        let bytes = metadata.as_bytes();
+        assert_eq!(Metadata::from_bytes(&bytes), Ok(metadata));
+
+        let mut corrupted = bytes;
+        corrupted[PAGE_HEADER_BYTES + 8] ^= 1;
+        assert_eq!(
+            Metadata::from_bytes(&corrupted),
+            Err("metadata page 7 is corrupted".to_string())
+        );
+
+        let too_big = BTreeMap::from([("blob".to_string(), vec![0; PAGE_SIZE])]);
+        assert!(Metadata::new(8, too_big).is_err());
+    }
+}
@@ -1,14 +1,21 @@
+mod dictionary;
 mod disk_manager;
+mod format;
+mod metadata;
 mod node;
 mod page;
 mod pager;
+mod snapshot;
 
 // Reexport so we can refer it from other mod
 // as crate::storage::DiskManager instead of
 // crate::storage::disk_manager::DiskManager
 pub use self::{
-    disk_manager::DiskManager,
-    node::{Node, NodeType, LEAF_NODE_CELL_SIZE},
+    dictionary::Dictionary,
+    disk_manager::{DiskManager, SyncMode},
+    format::format_dump,
+    node::{crc32, Node, NodeType},
     page::Page,
     pager::*,
+    snapshot::Snapshot,
 };
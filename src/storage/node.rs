@@ -1,7 +1,6 @@
 use super::page::PAGE_HEADER_BYTES;
 use super::{Cursor, PAGE_SIZE};
-use crate::row::{Row, ROW_SIZE};
-use crate::BigArray;
+use crate::row::{Row, EMAIL_SIZE, ROW_SIZE, USERNAME_SIZE};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Copy, Clone)]
@@ -33,15 +32,29 @@ pub const COMMON_NODE_HEADER_SIZE: usize =
 
 pub const LEAF_NODE_HEADER_SIZE: usize =
     COMMON_NODE_HEADER_SIZE + std::mem::size_of::<u32>() + std::mem::size_of::<u32>();
-const LEAF_NODE_SPACE_FOR_CELLS: usize = MAX_NODE_SIZE - LEAF_NODE_HEADER_SIZE;
+pub const LEAF_NODE_SPACE_FOR_CELLS: usize = MAX_NODE_SIZE - LEAF_NODE_HEADER_SIZE;
 
+// A leaf is a slotted page. The header is followed by a slot per cell,
+// in key order, and the cells are written from the end of the node, so
+// the free space of the leaf is between the slots and the cells:
+//
+//   slot: offset in the node (2) | length (2)
+//   cell: key (4) | is deleted (1) | username length (1) | username
+//         | email length (1) | email | checksum (4)
+//
+// where the username and email are stored without their padding.
+pub const LEAF_NODE_SLOT_SIZE: usize = 2 * std::mem::size_of::<u16>();
 const LEAF_NODE_KEY_SIZE: usize = std::mem::size_of::<u32>();
-const LEAF_NODE_VALUE_SIZE: usize = ROW_SIZE;
-pub const LEAF_NODE_CELL_SIZE: usize = LEAF_NODE_KEY_SIZE + LEAF_NODE_VALUE_SIZE;
-pub const LEAF_NODE_MAX_CELLS: usize = LEAF_NODE_SPACE_FOR_CELLS / LEAF_NODE_CELL_SIZE;
-pub const LEAF_NODE_RIGHT_SPLIT_COUNT: usize = (LEAF_NODE_MAX_CELLS + 1) / 2;
-pub const LEAF_NODE_LEFT_SPLIT_COUNT: usize =
-    (LEAF_NODE_MAX_CELLS + 1) - LEAF_NODE_RIGHT_SPLIT_COUNT;
+const LEAF_NODE_DELETED_OFFSET: usize = LEAF_NODE_KEY_SIZE;
+const LEAF_NODE_MAX_VALUE_SIZE: usize = 1 + 1 + USERNAME_SIZE + 1 + EMAIL_SIZE;
+const LEAF_NODE_CHECKSUM_SIZE: usize = std::mem::size_of::<u32>();
+/// Largest space a cell takes in its leaf, slot included, i.e. the cell
+/// of a row with the longest username and email.
+pub const LEAF_NODE_CELL_SIZE: usize =
+    LEAF_NODE_SLOT_SIZE + LEAF_NODE_KEY_SIZE + LEAF_NODE_MAX_VALUE_SIZE + LEAF_NODE_CHECKSUM_SIZE;
+/// Size of every cell of a leaf of format version 1, where cells are
+/// packed after the header and hold the row serialized by bincode.
+pub const LEAF_NODE_V1_CELL_SIZE: usize = LEAF_NODE_KEY_SIZE + ROW_SIZE + LEAF_NODE_CHECKSUM_SIZE;
 
 pub const INTERNAL_NODE_RIGHT_CHILD_SIZE: usize = std::mem::size_of::<u32>();
 pub const INTERNAL_NODE_NUM_KEYS_SIZE: usize = std::mem::size_of::<u32>();
@@ -54,65 +67,188 @@ pub const INTERNAL_NODE_CELL_SIZE: usize = std::mem::size_of::<u32>() + std::mem
 // Hardcoded to 3 for testing
 pub const INTERNAL_NODE_MAX_CELLS: usize = 3;
 
-// We have to define a custom type in order to have a  define
-// serde attributes in Vec<T>.
-//
-// See: https://github.com/serde-rs/serde/issues/723
+/// Page offset pointing to no page, e.g. the next leaf of the last leaf.
+/// Page 0 is the root of the default table, so it's never a sibling.
+pub const NULL_PAGE: u32 = 0;
+
+/// A row of a leaf, which takes as many bytes as its values need, see
+/// `Cell::size_for`.
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
-pub struct Cell(#[serde(with = "BigArray")] [u8; LEAF_NODE_CELL_SIZE]);
+pub struct Cell(Vec<u8>);
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct InternalCell([u8; INTERNAL_NODE_CELL_SIZE]);
 
 impl Cell {
-    pub fn key(&self) -> u32 {
-        let key_bytes = &self.0[0..4];
-        bincode::deserialize(key_bytes).unwrap()
+    pub fn new(row: &Row) -> Self {
+        let mut cell = Self(vec![0; Self::size_for(row) - LEAF_NODE_SLOT_SIZE]);
+        cell.0[..LEAF_NODE_KEY_SIZE].copy_from_slice(&row.id.to_le_bytes());
+        cell.write_value(row);
+        cell
     }
 
-    pub fn value(&self) -> &[u8] {
-        let offset = LEAF_NODE_KEY_SIZE;
-        &self.0[offset..offset + LEAF_NODE_VALUE_SIZE]
+    /// The size in bytes the cell takes in its leaf, slot included.
+    pub fn size(&self) -> usize {
+        LEAF_NODE_SLOT_SIZE + self.0.len()
     }
 
-    fn write_key(&mut self, key: u32) {
-        for (i, byte) in key.to_le_bytes().into_iter().enumerate() {
-            self.0[i] = byte;
-        }
+    /// The size in bytes of the cell needed to store the row.
+    pub fn size_for(row: &Row) -> usize {
+        LEAF_NODE_SLOT_SIZE + LEAF_NODE_KEY_SIZE + encode_value(row).len() + LEAF_NODE_CHECKSUM_SIZE
+    }
+
+    pub fn key(&self) -> u32 {
+        u32::from_le_bytes(self.0[..LEAF_NODE_KEY_SIZE].try_into().unwrap())
+    }
+
+    /// Decode the row of the cell. A value cut short, e.g. by a corrupted
+    /// length, gives empty fields rather than failing, as the checksum of
+    /// the cell already tells it apart, see `verify_checksum`.
+    pub fn row(&self) -> Row {
+        let mut value = self
+            .0
+            .get(LEAF_NODE_DELETED_OFFSET + 1..self.checksum_offset())
+            .unwrap_or_default();
+        let mut row = Row {
+            id: self.key(),
+            username: [0; USERNAME_SIZE],
+            email: [0; EMAIL_SIZE],
+            is_deleted: self.0.get(LEAF_NODE_DELETED_OFFSET) == Some(&1),
+        };
+
+        let username = take_field(&mut value, USERNAME_SIZE);
+        row.username[..username.len()].copy_from_slice(username);
+        let email = take_field(&mut value, EMAIL_SIZE);
+        row.email[..email.len()].copy_from_slice(email);
+        row
     }
 
     pub fn mark_as_deleted(&mut self) {
-        let offset = LEAF_NODE_KEY_SIZE;
-        self.0[offset + ROW_SIZE - 1] = 1;
+        self.0[LEAF_NODE_DELETED_OFFSET] = 1;
+        self.write_checksum();
     }
 
     pub fn mark_as_undeleted(&mut self) {
-        let offset = LEAF_NODE_KEY_SIZE;
-        self.0[offset + ROW_SIZE - 1] = 0;
+        self.0[LEAF_NODE_DELETED_OFFSET] = 0;
+        self.write_checksum();
+    }
+
+    fn checksum_offset(&self) -> usize {
+        self.0.len() - LEAF_NODE_CHECKSUM_SIZE
+    }
+
+    // Every cell store a checksum of its key and value, which is
+    // rewritten whenever the cell is modified. This let us detect
+    // a corrupted row without relying on page level checksum.
+    fn checksum(&self) -> u32 {
+        crc32(&self.0[..self.checksum_offset()])
+    }
+
+    fn write_checksum(&mut self) {
+        let checksum = self.checksum().to_le_bytes();
+        let offset = self.checksum_offset();
+        self.0[offset..].clone_from_slice(&checksum);
+    }
+
+    /// Return true if the stored checksum match the content of the cell.
+    pub fn verify_checksum(&self) -> bool {
+        let stored = &self.0[self.checksum_offset()..];
+        stored == self.checksum().to_le_bytes()
     }
 
     // TRADEOFF: We are a clustered table.
     //
     // Where our rows is not stored in a separate heap file but together
     // with the B+ Tree file.
+    //
+    // TRADEOFF: A cell grows with its row, but never shrinks, so the row
+    // it held before always fits again, e.g. when an update is rolled
+    // back. The bytes left over are zeroed until the leaf is compacted,
+    // see `Node::compact`.
     pub fn write_value(&mut self, row: &Row) {
-        let offset = LEAF_NODE_KEY_SIZE;
-        let row_in_bytes = bincode::serialize(row).unwrap();
+        let value = encode_value(row);
+        let len = LEAF_NODE_KEY_SIZE + value.len() + LEAF_NODE_CHECKSUM_SIZE;
+        if len > self.0.len() {
+            self.0.resize(len, 0);
+        }
 
-        self.0[offset..(ROW_SIZE + offset)].clone_from_slice(&row_in_bytes[..ROW_SIZE]);
+        let checksum_offset = self.checksum_offset();
+        self.0[LEAF_NODE_KEY_SIZE..checksum_offset].fill(0);
+        self.0[LEAF_NODE_KEY_SIZE..LEAF_NODE_KEY_SIZE + value.len()].copy_from_slice(&value);
+        self.write_checksum();
     }
 
     pub fn update(&mut self, columns: &Vec<String>, new_row: &Row) {
-        let offset = LEAF_NODE_KEY_SIZE;
-        let row_in_bytes = &self.0[offset..(ROW_SIZE + offset)];
-        let mut row: Row = bincode::deserialize(row_in_bytes).unwrap();
+        self.write_value(&self.updated(columns, new_row));
+    }
 
+    /// Shrink the cell to the size its row needs, returning the number of
+    /// bytes freed. A corrupted cell is kept as is, as its row can't be
+    /// trusted to be written again.
+    pub fn shrink(&mut self) -> usize {
+        if !self.verify_checksum() {
+            return 0;
+        }
+
+        let shrunk = Cell::new(&self.row());
+        let freed = self.size() - shrunk.size();
+        *self = shrunk;
+        freed
+    }
+
+    fn updated(&self, columns: &Vec<String>, new_row: &Row) -> Row {
+        let mut row = self.row();
         for column in columns {
             row.update(column, new_row);
         }
+        row
+    }
+}
+
+// The value of the cell of the row, see the layout of a leaf above.
+fn encode_value(row: &Row) -> Vec<u8> {
+    let username = trim_padding(&row.username);
+    let email = trim_padding(&row.email);
+
+    let mut value = Vec::with_capacity(3 + username.len() + email.len());
+    value.push(row.is_deleted as u8);
+    value.push(username.len() as u8);
+    value.extend_from_slice(username);
+    value.push(email.len() as u8);
+    value.extend_from_slice(email);
+    value
+}
 
-        self.write_value(&row);
+fn trim_padding(field: &[u8]) -> &[u8] {
+    let len = field
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |i| i + 1);
+    &field[..len]
+}
+
+// Split the field off the value, after its length, which is clamped to
+// the bytes left and to the size of the field.
+fn take_field<'a>(value: &mut &'a [u8], max_len: usize) -> &'a [u8] {
+    let Some((len, rest)) = value.split_first() else {
+        return &[];
+    };
+    let (field, rest) = rest.split_at((*len as usize).min(rest.len()).min(max_len));
+    *value = rest;
+    field
+}
+
+// CRC-32 (IEEE), computed bit by bit since cells and pages are small.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
     }
+    !crc
 }
 
 impl std::fmt::Debug for Cell {
@@ -140,6 +276,12 @@ impl InternalCell {
         }
     }
 
+    // TODO (Generalized keys): Keys are always the u32 id of the row.
+    //
+    // A key value facade, e.g. GET/SET/DEL/SCAN over string keys and
+    // blob values, needs variable length keys compared by bytes, which
+    // our internal cells can't store, and leaf cells holding any value
+    // rather than a username and an email.
     pub fn key(&self) -> u32 {
         let bytes = &self.0[4..8];
         bincode::deserialize(bytes).unwrap()
@@ -186,13 +328,106 @@ pub struct Node {
 
     // Internal
     pub right_child_offset: u32,
+    // Next leaf in key order, or `NULL_PAGE` for the last leaf.
     pub next_leaf_offset: u32,
 
     // Body
+    //
+    // TRADEOFF: Cells are always kept packed and in key order.
+    //
+    // Deleting a cell shift the remaining cells, so a page never
+    // has holes and never need to be defragmented. A leaf is a slotted
+    // page on disk, but it's written from the cells every time, so it's
+    // compacted whenever it's written, see `to_bytes`.
     pub cells: Vec<Cell>,
     pub internal_cells: Vec<InternalCell>,
 
     pub has_initialize: bool,
+
+    #[serde(skip)]
+    pub insert_history: InsertHistory,
+}
+
+// The number of inserts recorded before the history is used to pick the
+// split point, how far the average insert position has to be from the
+// middle for the node to be skewed, and the split ratio of a skewed node.
+const INSERT_HISTORY_MIN_INSERTS: u32 = 4;
+const SKEWED_POSITION: u32 = 250;
+const SKEWED_SPLIT_RATIO: u32 = 700;
+// Split ratio of sequential inserts while ingesting, see
+// `InsertHistory::ingest_split_ratio`.
+const INGEST_SPLIT_RATIO: u32 = 900;
+
+/// Where recent inserts into a leaf node landed, as a moving average of
+/// the insert position relative to its cells, from 0 (before the first
+/// cell) to 1000 (after the last cell).
+///
+/// TRADEOFF: The history only lives in memory, so it's lost when the
+/// page is evicted and the node splits evenly until enough inserts are
+/// recorded again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsertHistory {
+    position: u32,
+    inserts: u32,
+    // True if every recorded insert was appended after the last cell.
+    appends_only: bool,
+}
+
+impl Default for InsertHistory {
+    fn default() -> Self {
+        Self {
+            position: 500,
+            inserts: 0,
+            appends_only: true,
+        }
+    }
+}
+
+impl InsertHistory {
+    pub fn record(&mut self, cell_num: usize, num_of_cells: usize) {
+        // The first insert into an empty node doesn't have a direction.
+        let position = (cell_num.min(num_of_cells) * 1000)
+            .checked_div(num_of_cells)
+            .unwrap_or(500) as u32;
+
+        self.position = (self.position * 7 + position) / 8;
+        self.inserts = self.inserts.saturating_add(1);
+        self.appends_only &= cell_num >= num_of_cells;
+    }
+
+    /// The bytes to keep in the left node when splitting, per mille.
+    ///
+    /// If inserts mostly land at the end of the node, 70% of the bytes are
+    /// kept in the left node, which won't receive many inserts anymore, and
+    /// the other way around. Otherwise, the node is split evenly.
+    ///
+    /// TODO (Right-leaning split): Purely sequential inserts still split
+    /// evenly. They could keep every cell in the left node instead, as no
+    /// insert will ever go there again.
+    pub fn split_ratio(&self) -> u32 {
+        if self.inserts < INSERT_HISTORY_MIN_INSERTS || self.appends_only {
+            return 500;
+        }
+
+        if self.position >= 1000 - SKEWED_POSITION {
+            SKEWED_SPLIT_RATIO
+        } else if self.position <= SKEWED_POSITION {
+            1000 - SKEWED_SPLIT_RATIO
+        } else {
+            500
+        }
+    }
+
+    /// Same as `split_ratio`, but sequential inserts keep 90% of the bytes
+    /// in the left node, so a bulk load of sorted rows leaves packed
+    /// leaves behind instead of half empty ones. See `Pager::begin_ingest`.
+    pub fn ingest_split_ratio(&self) -> u32 {
+        if self.inserts >= INSERT_HISTORY_MIN_INSERTS && self.appends_only {
+            INGEST_SPLIT_RATIO
+        } else {
+            self.split_ratio()
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -204,10 +439,10 @@ pub fn print_constant() {
     LEAF_NODE_HEADER_SIZE: {LEAF_NODE_HEADER_SIZE},
     LEAF_NODE_CELL_SIZE: {LEAF_NODE_CELL_SIZE},
     LEAF_NODE_SPACE_FOR_CELLS: {LEAF_NODE_SPACE_FOR_CELLS},
-    LEAF_NODE_MAX_CELLS: {LEAF_NODE_MAX_CELLS},
+    LEAF_NODE_SLOT_SIZE: {LEAF_NODE_SLOT_SIZE},
 
     LEAF_NODE_KEY_SIZE: {LEAF_NODE_KEY_SIZE},
-    LEAF_NODE_VALUE_SIZE: {LEAF_NODE_VALUE_SIZE},
+    LEAF_NODE_MAX_VALUE_SIZE: {LEAF_NODE_MAX_VALUE_SIZE},
     MAX_NODE_SIZE: {MAX_NODE_SIZE},
     "
     );
@@ -220,11 +455,12 @@ impl Node {
             is_root,
             parent_offset: 0,
             right_child_offset: 0,
-            next_leaf_offset: 0,
+            next_leaf_offset: NULL_PAGE,
             num_of_cells: 0,
             has_initialize: true,
             cells: Vec::new(),
             internal_cells: Vec::new(),
+            insert_history: InsertHistory::default(),
         }
     }
 
@@ -234,11 +470,12 @@ impl Node {
             is_root: true,
             parent_offset: 0,
             right_child_offset: 0,
-            next_leaf_offset: 0,
+            next_leaf_offset: NULL_PAGE,
             num_of_cells: 0,
             has_initialize: true,
             cells: Vec::new(),
             internal_cells: Vec::new(),
+            insert_history: InsertHistory::default(),
         }
     }
 
@@ -248,13 +485,19 @@ impl Node {
         node
     }
 
-    pub fn new_from_bytes(bytes: &[u8]) -> Self {
+    /// Decode the node of a page of the given format version, see
+    /// `PAGE_FORMAT_VERSION`.
+    pub fn new_from_bytes(bytes: &[u8], version: u8) -> Self {
         let mut node = Node::uninitialize();
         node.set_common_header(&bytes[0..COMMON_NODE_HEADER_SIZE]);
 
         if node.node_type == NodeType::Leaf {
             node.set_leaf_header(&bytes[COMMON_NODE_HEADER_SIZE..LEAF_NODE_HEADER_SIZE]);
-            node.set_leaf_cells(&bytes[LEAF_NODE_HEADER_SIZE..]);
+            if version == 1 {
+                node.set_leaf_cells_v1(&bytes[LEAF_NODE_HEADER_SIZE..]);
+            } else {
+                node.set_leaf_cells(bytes);
+            }
         }
 
         if node.node_type == NodeType::Internal {
@@ -269,15 +512,26 @@ impl Node {
         let mut bytes = self.header();
 
         if self.node_type == NodeType::Leaf {
-            for c in &self.cells {
-                let mut cell_bytes = bincode::serialize(c).unwrap();
-                bytes.append(&mut cell_bytes);
-            }
-        } else {
-            for c in &self.internal_cells {
-                let mut cell_bytes = bincode::serialize(c).unwrap();
-                bytes.append(&mut cell_bytes);
+            // The cells are written from the end of the node in key
+            // order, so the first cell is the last one of the node, and
+            // a leaf is always written without gaps between its cells.
+            bytes.resize(MAX_NODE_SIZE, 0);
+            let mut end = MAX_NODE_SIZE;
+            for (i, cell) in self.cells.iter().enumerate() {
+                let start = end - cell.0.len();
+                bytes[start..end].copy_from_slice(&cell.0);
+
+                let slot = LEAF_NODE_HEADER_SIZE + i * LEAF_NODE_SLOT_SIZE;
+                bytes[slot..slot + 2].copy_from_slice(&(start as u16).to_le_bytes());
+                bytes[slot + 2..slot + 4].copy_from_slice(&(cell.0.len() as u16).to_le_bytes());
+                end = start;
             }
+            return bytes;
+        }
+
+        for c in &self.internal_cells {
+            let mut cell_bytes = bincode::serialize(c).unwrap();
+            bytes.append(&mut cell_bytes);
         }
 
         // Outdated a bit:
@@ -326,31 +580,62 @@ impl Node {
         self.right_child_offset = bincode::deserialize(right_child_offset_bytes).unwrap();
     }
 
-    pub fn set_leaf_cells(&mut self, cell_bytes: &[u8]) {
-        // The reason we can't use bincode to directly deserialize our bytes
-        // into Vec<Cell> is because Vec<Cell> binary format will includes a
-        // u64 (8 bytes) column that (seems like) representing the length of the
-        // Vec<Cell>.
-        //
-        // However, when we persist a cell to disk, we are only writing Cell
-        // binary format and we are tracking our own number of cells by using a
-        // separate column with a u32 (4 bytes) data type.
-        //
-        // Hence, to deserialize with bincode directly into Vec<Cell>,  we append
-        // the num_of_cells as 8 bytes into our bytes first.
-        let max_size = self.num_of_cells as usize * LEAF_NODE_CELL_SIZE;
-        let mut bytes = (self.num_of_cells as u64).to_le_bytes().to_vec();
-        let cell_bytes = &mut cell_bytes[0..max_size].to_vec();
-        bytes.append(cell_bytes);
-        self.cells = bincode::deserialize_from(&bytes[..]).unwrap();
+    /// Read the cells of the leaf through its slots, see the layout of a
+    /// leaf above, given the bytes of the whole node.
+    ///
+    /// A slot pointing outside of the node, e.g. of a corrupted page,
+    /// gives an empty cell, which fails its checksum like any other
+    /// corrupted cell, see `corrupted_keys`.
+    pub fn set_leaf_cells(&mut self, bytes: &[u8]) {
+        let slots = (bytes.len() - LEAF_NODE_HEADER_SIZE) / LEAF_NODE_SLOT_SIZE;
+        self.cells = (0..(self.num_of_cells as usize).min(slots))
+            .map(|i| {
+                let slot = &bytes[LEAF_NODE_HEADER_SIZE + i * LEAF_NODE_SLOT_SIZE..];
+                let offset = u16::from_le_bytes([slot[0], slot[1]]) as usize;
+                let len = u16::from_le_bytes([slot[2], slot[3]]) as usize;
+                match bytes.get(offset..offset + len) {
+                    Some(cell) if len >= LEAF_NODE_KEY_SIZE + LEAF_NODE_CHECKSUM_SIZE => {
+                        Cell(cell.to_vec())
+                    }
+                    _ => Cell(vec![0; LEAF_NODE_KEY_SIZE + LEAF_NODE_CHECKSUM_SIZE]),
+                }
+            })
+            .collect();
+        self.num_of_cells = self.cells.len() as u32;
+    }
 
-        // Alternatively, we could just deserialize a cell bytes into Cell
-        // and insert into Vec<Cell> manually.
-        // for i in 0..self.num_of_cells as usize {
-        //     let offset = i * LEAF_NODE_CELL_SIZE;
-        //     let cell = bincode::deserialize(&bytes[offset..offset + LEAF_NODE_CELL_SIZE]).unwrap();
-        //     self.cells.insert(i, cell);
-        // }
+    // Cells of format version 1 are packed after the header, each taking
+    // `LEAF_NODE_V1_CELL_SIZE` bytes whatever its row. They're converted
+    // to cells of the current format, which are written out with the
+    // next write of the page, and a cell failing its checksum still
+    // fails it once converted.
+    fn set_leaf_cells_v1(&mut self, cell_bytes: &[u8]) {
+        let checksum_offset = LEAF_NODE_V1_CELL_SIZE - LEAF_NODE_CHECKSUM_SIZE;
+        self.cells = cell_bytes
+            .chunks_exact(LEAF_NODE_V1_CELL_SIZE)
+            .take(self.num_of_cells as usize)
+            .map(|bytes| {
+                // The row is serialized as id (4), username, email and
+                // is deleted (1), of which the id repeats the key.
+                let value = &bytes[LEAF_NODE_KEY_SIZE + 4..checksum_offset];
+                let row = Row {
+                    id: u32::from_le_bytes(bytes[..LEAF_NODE_KEY_SIZE].try_into().unwrap()),
+                    username: value[..USERNAME_SIZE].try_into().unwrap(),
+                    email: value[USERNAME_SIZE..USERNAME_SIZE + EMAIL_SIZE]
+                        .try_into()
+                        .unwrap(),
+                    is_deleted: value[USERNAME_SIZE + EMAIL_SIZE] == 1,
+                };
+
+                let mut cell = Cell::new(&row);
+                if crc32(&bytes[..checksum_offset]).to_le_bytes() != bytes[checksum_offset..] {
+                    let offset = cell.checksum_offset();
+                    cell.0[offset] ^= 0xFF;
+                }
+                cell
+            })
+            .collect();
+        self.num_of_cells = self.cells.len() as u32;
     }
 
     pub fn set_internal_cells(&mut self, cell_bytes: &[u8]) {
@@ -426,42 +711,107 @@ impl Node {
         Ok(child_pointer as usize)
     }
 
+    /// Return the keys of the cells that fail the checksum verification.
+    pub fn corrupted_keys(&self) -> Vec<u32> {
+        self.cells
+            .iter()
+            .filter(|cell| !cell.verify_checksum())
+            .map(Cell::key)
+            .collect()
+    }
+
+    /// Bytes used by the cells of the leaf node.
+    pub fn used_space(&self) -> usize {
+        self.cells.iter().map(Cell::size).sum()
+    }
+
+    /// Bytes available for new cells in the leaf node.
+    pub fn free_space(&self) -> usize {
+        LEAF_NODE_SPACE_FOR_CELLS.saturating_sub(self.used_space())
+    }
+
+    /// Return true if the row can be inserted without splitting.
+    pub fn can_fit(&self, row: &Row) -> bool {
+        self.free_space() >= Cell::size_for(row)
+    }
+
+    /// Return true if the leaf node is at most half full.
+    pub fn is_underflow(&self) -> bool {
+        self.used_space() <= LEAF_NODE_SPACE_FOR_CELLS / 2
+    }
+
+    /// Return true if deleting the cell would leave a leaf that's merged
+    /// with its siblings, see `Pager::concurrent_maybe_merge_nodes`.
+    pub fn underflows_without(&self, cell_num: usize) -> bool {
+        self.node_type == NodeType::Leaf
+            && !self.is_root
+            && self.used_space() - self.cells[cell_num].size() <= LEAF_NODE_SPACE_FOR_CELLS / 2
+    }
+
+    /// Return the number of cells to keep in the left node when
+    /// splitting, so the bytes are split by the ratio of the insert
+    /// history, see `InsertHistory::split_ratio`.
+    ///
+    /// Both nodes always keep at least one cell.
+    pub fn split_point(&self) -> usize {
+        self.split_point_at(self.insert_history.split_ratio())
+    }
+
+    /// Same as `split_point`, but keep the given ratio of the bytes in the
+    /// left node, per mille.
+    pub fn split_point_at(&self, ratio: u32) -> usize {
+        let right_ratio = 1000 - ratio as usize;
+        let right_target = self.used_space() * right_ratio / 1000;
+        let mut right_space = 0;
+        let mut split_at = self.cells.len();
+
+        while split_at > 1 {
+            let size = self.cells[split_at - 1].size();
+            if right_space + size > right_target && split_at < self.cells.len() {
+                break;
+            }
+
+            right_space += size;
+            split_at -= 1;
+        }
+
+        split_at
+    }
+
     pub fn get_mut_cell(&mut self, cell_num: usize) -> Option<&mut Cell> {
         self.cells.get_mut(cell_num)
     }
 
     pub fn get_row(&self, cell_num: usize) -> Option<Row> {
-        self.cells.get(cell_num).map(|cell| {
-            let bytes = cell.value();
-            bincode::deserialize(bytes).unwrap()
-        })
+        self.cells.get(cell_num).map(Cell::row)
     }
 
     pub fn get(&self, cell_num: usize) -> Row {
-        let bytes = self.cells[cell_num].value();
-        bincode::deserialize(bytes).unwrap()
+        self.cells[cell_num].row()
     }
 
-    pub fn insert(&mut self, row: &Row, cursor: &Cursor) {
-        let num_of_cells = self.num_of_cells as usize;
+    /// Return true if the cell still fits in the leaf once the columns
+    /// are updated, as a cell grows with its row, see `Cell::write_value`.
+    pub fn can_update(&self, cell_num: usize, columns: &Vec<String>, new_row: &Row) -> bool {
+        self.cells.get(cell_num).is_some_and(|cell| {
+            let size = Cell::size_for(&cell.updated(columns, new_row));
+            size.saturating_sub(cell.size()) <= self.free_space()
+        })
+    }
 
-        // Make room for new cell.
-        //
-        // Else, the current cell at cell_num will be override by
-        // new cell.
-        if cursor.cell_num < num_of_cells {
-            self.cells
-                .insert(cursor.cell_num, Cell([0; LEAF_NODE_CELL_SIZE]));
-        }
+    /// Shrink every cell of the leaf to the size of its row, so the bytes
+    /// left over by rows that got shorter can be used again. Return the
+    /// number of bytes freed.
+    pub fn compact(&mut self) -> usize {
+        self.cells.iter_mut().map(Cell::shrink).sum()
+    }
 
-        if self.cells.get(cursor.cell_num).is_none() {
-            self.cells
-                .insert(cursor.cell_num, Cell([0; LEAF_NODE_CELL_SIZE]))
-        }
+    pub fn insert(&mut self, row: &Row, cursor: &Cursor) {
+        let num_of_cells = self.num_of_cells as usize;
+        self.insert_history.record(cursor.cell_num, num_of_cells);
 
+        self.cells.insert(cursor.cell_num, Cell::new(row));
         self.num_of_cells += 1;
-        self.cells[cursor.cell_num].write_key(row.id);
-        self.cells[cursor.cell_num].write_value(row);
     }
 
     pub fn delete(&mut self, cell_num: usize) {
@@ -544,9 +894,169 @@ impl Node {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::storage::page::PAGE_FORMAT_VERSION;
 
     #[test]
     fn basic() {
         print_constant();
     }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn cell_checksum() {
+        let mut node = Node::root();
+        let cursor = Cursor {
+            page_num: 0,
+            cell_num: 0,
+            key_existed: false,
+            end_of_table: false,
+        };
+        node.insert(&Row::new("1", "john", "john@email.com").unwrap(), &cursor);
+        assert!(node.corrupted_keys().is_empty());
+
+        // Every modification rewrite the checksum.
+        let cell = node.get_mut_cell(0).unwrap();
+        cell.mark_as_deleted();
+        assert!(cell.verify_checksum());
+        cell.update(
+            &vec!["username".to_string()],
+            &Row::new("1", "wick", "").unwrap(),
+        );
+        assert!(cell.verify_checksum());
+
+        // Flip a bit in the username.
+        cell.0[LEAF_NODE_KEY_SIZE + 4] ^= 1;
+        assert!(!cell.verify_checksum());
+        assert_eq!(node.corrupted_keys(), vec![1]);
+    }
+
+    #[test]
+    fn slotted_leaf_round_trip() {
+        let mut node = Node::root();
+        let rows = [
+            Row::new("1", "jo", "jo@email.com").unwrap(),
+            Row::new("2", &"x".repeat(32), &"y".repeat(255)).unwrap(),
+            Row::new("3", "", "").unwrap(),
+        ];
+        for (i, row) in rows.iter().enumerate() {
+            let cursor = Cursor {
+                page_num: 0,
+                cell_num: i,
+                key_existed: false,
+                end_of_table: false,
+            };
+            node.insert(row, &cursor);
+        }
+        node.get_mut_cell(2).unwrap().mark_as_deleted();
+
+        // Cells take the space of their row only, the longest one up to
+        // `LEAF_NODE_CELL_SIZE`.
+        let sizes: Vec<usize> = node.cells.iter().map(Cell::size).collect();
+        assert_eq!(sizes, vec![29, LEAF_NODE_CELL_SIZE, 15]);
+
+        let bytes = node.to_bytes();
+        assert_eq!(bytes.len(), MAX_NODE_SIZE);
+        let slot = |i: usize| {
+            let slot = &bytes[LEAF_NODE_HEADER_SIZE + i * LEAF_NODE_SLOT_SIZE..];
+            (
+                u16::from_le_bytes([slot[0], slot[1]]),
+                u16::from_le_bytes([slot[2], slot[3]]),
+            )
+        };
+        assert_eq!(slot(0), ((MAX_NODE_SIZE - 25) as u16, 25));
+
+        let read = Node::new_from_bytes(&bytes, PAGE_FORMAT_VERSION);
+        assert_eq!(read.cells, node.cells);
+        assert_eq!(read.get(1), rows[1]);
+        assert!(read.get(2).is_deleted);
+        assert!(read.corrupted_keys().is_empty());
+
+        // A slot pointing past the end of the node gives a corrupted cell.
+        let mut bytes = bytes;
+        let slot = LEAF_NODE_HEADER_SIZE + LEAF_NODE_SLOT_SIZE;
+        bytes[slot..slot + 2].copy_from_slice(&(MAX_NODE_SIZE as u16).to_le_bytes());
+        let read = Node::new_from_bytes(&bytes, PAGE_FORMAT_VERSION);
+        assert_eq!(read.corrupted_keys(), vec![0]);
+    }
+
+    #[test]
+    fn free_space_accounting() {
+        let mut node = Node::root();
+        assert_eq!(node.used_space(), 0);
+        assert_eq!(node.free_space(), LEAF_NODE_SPACE_FOR_CELLS);
+        assert!(node.is_underflow());
+
+        let row = Row::new("1", "john", "john@email.com").unwrap();
+        let max_cells = LEAF_NODE_SPACE_FOR_CELLS / Cell::size_for(&row);
+        for i in 0..max_cells {
+            assert!(node.can_fit(&row));
+
+            let cursor = Cursor {
+                page_num: 0,
+                cell_num: i,
+                key_existed: false,
+                end_of_table: false,
+            };
+            node.insert(&row, &cursor);
+        }
+
+        assert_eq!(node.used_space(), max_cells * Cell::size_for(&row));
+        assert!(!node.can_fit(&row));
+        assert!(!node.is_underflow());
+
+        // Half of the cells when every cell has the same size.
+        let cursor = Cursor {
+            page_num: 0,
+            cell_num: max_cells,
+            key_existed: false,
+            end_of_table: false,
+        };
+        node.insert(&row, &cursor);
+        assert_eq!(node.split_point(), max_cells.div_ceil(2));
+    }
+
+    #[test]
+    fn split_by_insert_history() {
+        let row = Row::new("1", "john", "john@email.com").unwrap();
+        let cells = LEAF_NODE_SPACE_FOR_CELLS / Cell::size_for(&row) + 1;
+        let fill = |cell_num: fn(usize) -> usize| {
+            let mut node = Node::root();
+            for i in 0..cells {
+                let cursor = Cursor {
+                    page_num: 0,
+                    cell_num: cell_num(i),
+                    key_existed: false,
+                    end_of_table: false,
+                };
+                node.insert(&row, &cursor);
+            }
+            node
+        };
+
+        // Inserting right before the last cell is skewed to the right, but
+        // not sequential, so 70% of the cells are kept in the left node.
+        let node = fill(|i| i.saturating_sub(1));
+        assert_eq!(node.insert_history.split_ratio(), 700);
+        assert_eq!(node.split_point(), cells - cells * 3 / 10);
+
+        let node = fill(|_| 0);
+        assert_eq!(node.insert_history.split_ratio(), 300);
+        assert_eq!(node.split_point(), cells - cells * 7 / 10);
+
+        // Sequential and evenly spread inserts split in the middle.
+        let node = fill(|i| i);
+        assert_eq!(node.insert_history.split_ratio(), 500);
+        let node = fill(|i| i / 2);
+        assert_eq!(node.insert_history.split_ratio(), 500);
+        assert_eq!(node.split_point(), cells / 2);
+
+        // Only sequential inserts are packed while ingesting.
+        assert_eq!(fill(|i| i).insert_history.ingest_split_ratio(), 900);
+        assert_eq!(node.insert_history.ingest_split_ratio(), 500);
+        assert_eq!(node.split_point_at(900), cells - cells / 10);
+    }
 }
@@ -1,30 +1,182 @@
-use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
 
-use super::node::Node;
+use super::node::{crc32, Node, NodeType};
+use super::PAGE_SIZE;
 use crate::row::Row;
 
-// Since bincode serialize Option<usize> as [0, 0, 0, 0, 0]
-//                                           -  ----------
-//                                           ^       ^
-//                                        Option   usize
+/// Version of the on-disk page format, to be bumped whenever the layout
+/// of the page header or of the body of any page type changes.
+///
+/// Version 2 lays out leaves as slotted pages, see `Node::to_bytes`.
+/// Version 3 adds the free page to the header, see `PageHeader`.
+pub const PAGE_FORMAT_VERSION: u8 = 3;
+
+/// Oldest version of the page format still read. A page of an older
+/// version is written in the current one once it's modified.
+pub const MIN_PAGE_FORMAT_VERSION: u8 = 1;
+
+// Every page starts with the same header, regardless of its type:
 //
-// Hence, we need to add one more byte.
-pub const PAGE_HEADER_BYTES: usize = 1 + std::mem::size_of::<usize>() + std::mem::size_of::<u32>();
+//   type (1) | version (1) | flags (2) | slot count (2) | page id (4) | free page (4) | lsn (4) | checksum (4)
+//
+// where integers are little endian, and the checksum is a CRC-32 of
+// the body, i.e. everything after the header. Before version 3, the
+// page id took the 8 bytes of both the page id and the free page.
+pub const PAGE_HEADER_BYTES: usize = 22;
+
+/// First version of the page format with a free page in the header.
+pub const FREE_PAGE_FORMAT_VERSION: u8 = 3;
+// Offset of the free page in the header.
+const FREE_PAGE_OFFSET: usize = 10;
+
+/// Type of a page, which decides how the body is laid out.
+///
+/// New page types, e.g. overflow or free list pages, should be added
+/// here with a new value, so the body of existing pages is never read
+/// as the wrong type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageType {
+    Internal = 1,
+    Leaf = 2,
+    // See `Dictionary`.
+    Dictionary = 3,
+    // See `Metadata`.
+    Metadata = 4,
+    // A page deleted from the tree, waiting to be reused. See
+    // `Pager::allocate_page_id`.
+    Free = 5,
+}
+
+impl TryFrom<u8> for PageType {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(PageType::Internal),
+            2 => Ok(PageType::Leaf),
+            3 => Ok(PageType::Dictionary),
+            4 => Ok(PageType::Metadata),
+            5 => Ok(PageType::Free),
+            _ => Err(format!("unknown page type {value}")),
+        }
+    }
+}
+
+impl From<NodeType> for PageType {
+    fn from(node_type: NodeType) -> Self {
+        match node_type {
+            NodeType::Internal => PageType::Internal,
+            NodeType::Leaf => PageType::Leaf,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageHeader {
+    pub page_type: PageType,
+    pub version: u8,
+    pub flags: u16,
+    pub slot_count: u16,
+    pub page_id: usize,
+    // Free pages are linked in a list, whose first page is the free page
+    // of page 0, which is never free itself. The free page of a free page
+    // is the next one of the list. 0 if there is none, or for any other
+    // page.
+    //
+    // TRADEOFF: Since there is no file header, the header of page 0 holds
+    // the first free page of the file, rather than a page of its own that
+    // every existing file would lack.
+    pub free_page: usize,
+    pub lsn: u32,
+    pub checksum: u32,
+}
+
+impl PageHeader {
+    pub fn encode(&self) -> [u8; PAGE_HEADER_BYTES] {
+        let mut bytes = [0; PAGE_HEADER_BYTES];
+        bytes[0] = self.page_type as u8;
+        bytes[1] = self.version;
+        bytes[2..4].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.slot_count.to_le_bytes());
+        if self.version < FREE_PAGE_FORMAT_VERSION {
+            bytes[6..14].copy_from_slice(&(self.page_id as u64).to_le_bytes());
+        } else {
+            bytes[6..10].copy_from_slice(&(self.page_id as u32).to_le_bytes());
+            bytes[10..14].copy_from_slice(&(self.free_page as u32).to_le_bytes());
+        }
+        bytes[14..18].copy_from_slice(&self.lsn.to_le_bytes());
+        bytes[18..22].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Decode the header, failing if the page is of an unknown type or
+    /// written by a version of the page format that isn't supported.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let version = bytes[1];
+        if !(MIN_PAGE_FORMAT_VERSION..=PAGE_FORMAT_VERSION).contains(&version) {
+            return Err(format!(
+                "unsupported page format version {version}, expected {MIN_PAGE_FORMAT_VERSION} to {PAGE_FORMAT_VERSION}"
+            ));
+        }
+
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let (page_id, free_page) = if version < FREE_PAGE_FORMAT_VERSION {
+            (
+                u64::from_le_bytes(bytes[6..14].try_into().unwrap()) as usize,
+                0,
+            )
+        } else {
+            (u32_at(6) as usize, u32_at(10) as usize)
+        };
+        Ok(Self {
+            page_type: PageType::try_from(bytes[0])?,
+            version,
+            flags: u16_at(2),
+            slot_count: u16_at(4),
+            page_id,
+            free_page,
+            lsn: u32_at(14),
+            checksum: u32_at(18),
+        })
+    }
+
+    /// Overwrite the free page in the header of the page, returning false
+    /// if the page is of a version without one. It isn't covered by the
+    /// checksum, which is of the body only.
+    pub fn set_free_page(bytes: &mut [u8], free_page: usize) -> bool {
+        if bytes[1] < FREE_PAGE_FORMAT_VERSION {
+            return false;
+        }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+        bytes[FREE_PAGE_OFFSET..FREE_PAGE_OFFSET + 4]
+            .copy_from_slice(&(free_page as u32).to_le_bytes());
+        true
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Page {
     // Header
     pub page_id: Option<usize>,
     pub lsn: u32,
+    pub flags: u16,
 
-    // Body (we will serialize/deserialize manually)
-    #[serde(skip)]
-    pub node: Option<Node>,
+    // Body
+    //
+    // We keep the raw body bytes read from disk around and only
+    // deserialize them into a Node the first time the node is accessed.
+    // As long as the node is not mutated, the raw bytes stay valid, so
+    // writing a clean page back to disk doesn't need to serialize the
+    // node again.
+    //
+    // The header read along with the raw bytes is kept as well, since
+    // the type and slot count of the page come from the node.
+    bytes: Option<(PageHeader, Vec<u8>)>,
+    node: OnceLock<Node>,
 
     // Metadata (in mem only)
-    #[serde(skip)]
     pub is_dirty: bool,
-    #[serde(skip)]
     pub pin_count: usize,
 }
 
@@ -33,41 +185,190 @@ impl Page {
         Self {
             page_id,
             lsn: 0,
+            flags: 0,
             is_dirty: false,
             pin_count: 0,
-            node: None,
+            bytes: None,
+            node: OnceLock::new(),
         }
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        let header_bytes = &bytes[..PAGE_HEADER_BYTES];
-        let mut page: Page = bincode::deserialize(header_bytes).unwrap();
+    /// Build a page from its on disk representation. Only the header
+    /// is decoded here, the node is deserialized lazily on first access.
+    ///
+    /// TRADEOFF: The checksum is not verified here, as a corrupted row
+    /// is reported by scrub through the checksum of its cell instead of
+    /// failing to read the whole page. See `verify_checksum`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let header = PageHeader::decode(&bytes[..PAGE_HEADER_BYTES])?;
+        if header.page_type == PageType::Free {
+            return Err(format!("page {} is free", header.page_id));
+        }
+
+        let mut page = Page::new(Some(header.page_id));
+        page.lsn = header.lsn;
+        page.flags = header.flags;
+        page.bytes = Some((header, bytes[PAGE_HEADER_BYTES..].to_vec()));
+
+        Ok(page)
+    }
+
+    /// Serialize a free page, which only links to the next free page of
+    /// the list, see `PageHeader::free_page`.
+    pub fn free_page_bytes(page_id: usize, next_free_page: usize) -> Vec<u8> {
+        let body = vec![0; PAGE_SIZE - PAGE_HEADER_BYTES];
+        let header = PageHeader {
+            page_type: PageType::Free,
+            version: PAGE_FORMAT_VERSION,
+            flags: 0,
+            slot_count: 0,
+            page_id,
+            free_page: next_free_page,
+            lsn: 0,
+            checksum: crc32(&body),
+        };
 
-        let body_bytes = &bytes[PAGE_HEADER_BYTES..];
-        let node = Node::new_from_bytes(body_bytes);
-        page.node = Some(node);
+        let mut bytes = header.encode().to_vec();
+        bytes.extend(body);
+        bytes
+    }
 
-        page
+    /// Return true if the body of the page matches the checksum in its
+    /// header.
+    pub fn verify_checksum(bytes: &[u8]) -> bool {
+        PageHeader::decode(&bytes[..PAGE_HEADER_BYTES])
+            .is_ok_and(|header| header.checksum == crc32(&bytes[PAGE_HEADER_BYTES..]))
     }
 
     pub fn deallocate(&mut self) {
         self.page_id = None;
-        self.node = None;
+        self.clear_node();
         self.is_dirty = false;
         self.pin_count = 0;
     }
 
+    pub fn has_node(&self) -> bool {
+        self.bytes.is_some() || self.node.get().is_some()
+    }
+
+    /// Return the node of the page, deserializing it from the raw
+    /// bytes if it's the first time it is accessed.
+    pub fn node(&self) -> Option<&Node> {
+        match &self.bytes {
+            Some((header, bytes)) => Some(
+                self.node
+                    .get_or_init(|| Node::new_from_bytes(bytes, header.version)),
+            ),
+            None => self.node.get(),
+        }
+    }
+
+    /// Return a mutable reference to the node of the page.
+    ///
+    /// Since the caller might modify the node, the raw bytes we have
+    /// is no longer guaranteed to be in sync with the node, hence we
+    /// drop it here.
+    pub fn node_mut(&mut self) -> Option<&mut Node> {
+        if let Some((header, bytes)) = self.bytes.take() {
+            if self.node.get().is_none() {
+                let _ = self.node.set(Node::new_from_bytes(&bytes, header.version));
+            }
+        }
+
+        self.node.get_mut()
+    }
+
+    pub fn set_node(&mut self, node: Node) {
+        self.bytes = None;
+        self.node = OnceLock::from(node);
+    }
+
+    pub fn take_node(&mut self) -> Option<Node> {
+        self.node_mut();
+        self.node.take()
+    }
+
+    pub fn clear_node(&mut self) {
+        self.bytes = None;
+        self.node = OnceLock::new();
+    }
+
+    /// Return true if the page can be written back to disk
+    /// without serializing the node. This is the case when
+    /// the node hasn't been modified since it was read from disk.
+    pub fn has_raw_bytes(&self) -> bool {
+        self.bytes.is_some()
+    }
+
+    /// Version of the format the page is written in, which is only older
+    /// than `PAGE_FORMAT_VERSION` for a page read from disk and never
+    /// modified since.
+    pub fn format_version(&self) -> u8 {
+        match &self.bytes {
+            Some((header, _)) => header.version,
+            None => PAGE_FORMAT_VERSION,
+        }
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         // To ensure that we can only serialize if page_id and node
         // is not None.
         assert!(self.page_id.is_some());
-        assert!(self.node.is_some());
+        assert!(self.has_node());
+
+        match &self.bytes {
+            Some((header, bytes)) => self.encode(
+                header.version,
+                header.page_type,
+                header.slot_count,
+                bytes.clone(),
+            ),
+            None => self.encode_node(self.node().unwrap()),
+        }
+    }
 
-        let mut header_bytes = bincode::serialize(&self).unwrap();
-        let mut body_bytes = self.node.as_ref().unwrap().to_bytes();
+    /// Serialize the node of the page, even if the page still holds the
+    /// raw bytes read from disk, which are deserialized without being
+    /// kept. See `Pager::sync_check`.
+    pub fn node_image(&self) -> Vec<u8> {
+        assert!(self.page_id.is_some());
+        match (self.node.get(), &self.bytes) {
+            (Some(node), _) => self.encode_node(node),
+            (None, Some((header, bytes))) => {
+                self.encode_node(&Node::new_from_bytes(bytes, header.version))
+            }
+            (None, None) => panic!("page {} has no node", self.page_id.unwrap()),
+        }
+    }
 
-        header_bytes.append(&mut body_bytes);
-        header_bytes
+    fn encode_node(&self, node: &Node) -> Vec<u8> {
+        let slot_count = match node.node_type {
+            NodeType::Leaf => node.cells.len(),
+            NodeType::Internal => node.internal_cells.len(),
+        } as u16;
+        self.encode(
+            PAGE_FORMAT_VERSION,
+            node.node_type.into(),
+            slot_count,
+            node.to_bytes(),
+        )
+    }
+
+    fn encode(&self, version: u8, page_type: PageType, slot_count: u16, body: Vec<u8>) -> Vec<u8> {
+        let header = PageHeader {
+            page_type,
+            version,
+            flags: self.flags,
+            slot_count,
+            page_id: self.page_id.unwrap(),
+            free_page: 0,
+            lsn: self.lsn,
+            checksum: crc32(&body),
+        };
+
+        let mut bytes = header.encode().to_vec();
+        bytes.extend(body);
+        bytes
     }
 
     // TRADEOFF: We are always cloning/copying the row values
@@ -79,12 +380,11 @@ impl Page {
     // We should consider exploring the using of references to our data. But
     // this mean I have to potentially deal with lifetime and borrowing issue.
     pub fn get_row(&self, slot_num: usize) -> Option<Row> {
-        self.node.as_ref().and_then(|node| node.get_row(slot_num))
+        self.node().and_then(|node| node.get_row(slot_num))
     }
 
     pub fn mark_row_as_deleted(&mut self, slot_num: usize) -> bool {
-        self.node
-            .as_mut()
+        self.node_mut()
             .and_then(|node| node.get_mut_cell(slot_num))
             .map_or(false, |cell| {
                 cell.mark_as_deleted();
@@ -93,8 +393,7 @@ impl Page {
     }
 
     pub fn mark_row_as_undeleted(&mut self, slot_num: usize) -> bool {
-        self.node
-            .as_mut()
+        self.node_mut()
             .and_then(|node| node.get_mut_cell(slot_num))
             .map_or(false, |cell| {
                 cell.mark_as_undeleted();
@@ -102,14 +401,25 @@ impl Page {
             })
     }
 
+    /// Return true if the row can be updated in place, see
+    /// `Node::can_update`.
+    pub fn can_update_row(&self, slot_num: usize, new_row: &Row, columns: &Vec<String>) -> bool {
+        self.node()
+            .is_some_and(|node| node.can_update(slot_num, columns, new_row))
+    }
+
+    /// Shrink the cells of the leaf to the size of their rows, returning
+    /// the number of bytes freed, see `Node::compact`.
+    pub fn compact(&mut self) -> usize {
+        self.node_mut().map_or(0, Node::compact)
+    }
+
     pub fn update_row(&mut self, slot_num: usize, new_row: &Row, columns: &Vec<String>) -> bool {
-        self.node
-            .as_mut()
+        self.node_mut()
+            .filter(|node| node.can_update(slot_num, columns, new_row))
             .and_then(|node| node.get_mut_cell(slot_num))
-            .map_or(false, |cell| {
-                cell.update(columns, new_row);
-                true
-            })
+            .map(|cell| cell.update(columns, new_row))
+            .is_some()
     }
 }
 
@@ -123,11 +433,11 @@ mod test {
         let mut page = Page::new(Some(1));
         page.pin_count = 2;
         page.is_dirty = true;
-        page.node = Some(Node::new(true, NodeType::Internal));
+        page.set_node(Node::new(true, NodeType::Internal));
         page.deallocate();
 
         assert_eq!(page.page_id, None);
-        assert_eq!(page.node, None);
+        assert_eq!(page.node(), None);
         assert_eq!(page.pin_count, 0);
         assert!(!page.is_dirty);
     }
@@ -146,11 +456,11 @@ mod test {
         };
         let row = Row::new("1", "name", "email").unwrap();
         node.insert(&row, &cursor);
-        page.node = Some(node);
+        page.set_node(node);
         page.lsn = 10;
 
         let bytes = page.as_bytes();
-        let from_byte_page = Page::from_bytes(&bytes);
+        let from_byte_page = Page::from_bytes(&bytes).unwrap();
 
         // struct is equal
         // assert_eq!(from_byte_page, page);
@@ -159,6 +469,107 @@ mod test {
         assert_eq!(bytes, from_byte_page.as_bytes());
     }
 
+    #[test]
+    fn common_page_header() {
+        let mut page = Page::new(Some(3));
+        let mut node = Node::new(false, NodeType::Leaf);
+        let cursor = Cursor {
+            page_num: 3,
+            cell_num: 0,
+            end_of_table: false,
+            key_existed: false,
+        };
+        node.insert(&Row::new("1", "name", "email").unwrap(), &cursor);
+        page.set_node(node);
+        page.lsn = 7;
+
+        let mut bytes = page.as_bytes();
+        let header = PageHeader::decode(&bytes).unwrap();
+        assert_eq!(
+            header,
+            PageHeader {
+                page_type: PageType::Leaf,
+                version: PAGE_FORMAT_VERSION,
+                flags: 0,
+                slot_count: 1,
+                page_id: 3,
+                free_page: 0,
+                lsn: 7,
+                checksum: crc32(&bytes[PAGE_HEADER_BYTES..]),
+            }
+        );
+        assert_eq!(header.encode(), bytes[..PAGE_HEADER_BYTES]);
+        assert!(Page::verify_checksum(&bytes));
+
+        bytes[PAGE_HEADER_BYTES + 100] ^= 1;
+        assert!(!Page::verify_checksum(&bytes));
+
+        bytes[0] = 9;
+        assert_eq!(
+            Page::from_bytes(&bytes),
+            Err("unknown page type 9".to_string())
+        );
+        bytes[1] = 4;
+        assert_eq!(
+            Page::from_bytes(&bytes),
+            Err("unsupported page format version 4, expected 1 to 3".to_string())
+        );
+    }
+
+    #[test]
+    fn free_page_header() {
+        let mut bytes = Page::free_page_bytes(5, 2);
+        let header = PageHeader::decode(&bytes).unwrap();
+        assert_eq!(header.page_type, PageType::Free);
+        assert_eq!((header.page_id, header.free_page), (5, 2));
+        assert!(Page::verify_checksum(&bytes));
+        assert_eq!(Page::from_bytes(&bytes), Err("page 5 is free".to_string()));
+
+        assert!(PageHeader::set_free_page(&mut bytes, 9));
+        assert_eq!(PageHeader::decode(&bytes).unwrap().free_page, 9);
+        assert!(Page::verify_checksum(&bytes));
+
+        // Before version 3, the page id takes the bytes of the free page.
+        bytes[1] = 2;
+        assert!(!PageHeader::set_free_page(&mut bytes, 7));
+        let header = PageHeader::decode(&bytes).unwrap();
+        assert_eq!((header.page_id, header.free_page), (5 + (9 << 32), 0));
+    }
+
+    #[test]
+    fn from_bytes_deserialize_node_lazily() {
+        let mut page = Page::new(Some(0));
+        let mut node = Node::new(true, NodeType::Leaf);
+        let cursor = Cursor {
+            page_num: 0,
+            cell_num: 0,
+            end_of_table: false,
+            key_existed: false,
+        };
+        let row = Row::new("1", "name", "email").unwrap();
+        node.insert(&row, &cursor);
+        page.set_node(node);
+        let bytes = page.as_bytes();
+
+        // Node is not deserialized until it is accessed, and
+        // the raw bytes are reused when writing back a clean page.
+        let page = Page::from_bytes(&bytes).unwrap();
+        assert!(page.node.get().is_none());
+        assert!(page.has_raw_bytes());
+        assert_eq!(bytes, page.as_bytes());
+
+        assert_eq!(page.get_row(0).unwrap().id, 1);
+        assert!(page.node.get().is_some());
+        assert!(page.has_raw_bytes());
+
+        // Raw bytes is dropped once the node might be modified.
+        let mut page = page;
+        assert!(page.mark_row_as_deleted(0));
+        assert!(!page.has_raw_bytes());
+        assert!(page.get_row(0).unwrap().is_deleted);
+        assert_ne!(bytes, page.as_bytes());
+    }
+
     #[test]
     fn get_row() {
         let mut page = Page::new(Some(0));
@@ -173,7 +584,7 @@ mod test {
         };
         let row = Row::new("1", "name", "email").unwrap();
         node.insert(&row, &cursor);
-        page.node = Some(node);
+        page.set_node(node);
         let row = page.get_row(0);
         assert!(row.is_some());
 
@@ -195,7 +606,7 @@ mod test {
         };
         let row = Row::new("1", "name", "email").unwrap();
         node.insert(&row, &cursor);
-        page.node = Some(node);
+        page.set_node(node);
         assert!(page.mark_row_as_deleted(0));
 
         let row = page.get_row(0).unwrap();
@@ -1,21 +1,45 @@
 use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard, RwLockWriteGuard};
-use std::collections::HashMap;
-use std::path::Path;
-use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
 use tracing::{debug, warn};
 
+use super::dictionary::{self, Dictionary};
+use super::metadata::Metadata;
 use super::node::{
-    InternalCell, Node, INTERNAL_NODE_MAX_CELLS, LEAF_NODE_LEFT_SPLIT_COUNT, LEAF_NODE_MAX_CELLS,
-    LEAF_NODE_RIGHT_SPLIT_COUNT,
+    Cell, InternalCell, Node, INTERNAL_NODE_MAX_CELLS, LEAF_NODE_CELL_SIZE,
+    LEAF_NODE_SPACE_FOR_CELLS, NULL_PAGE,
 };
-use crate::row::Row;
-use crate::storage::{DiskManager, NodeType, Page};
+use super::page::{PageHeader, PageType, PAGE_FORMAT_VERSION, PAGE_HEADER_BYTES};
+use crate::error::DbError;
+use crate::options::DbOptions;
+use crate::recovery::{LogManager, LogRecord, LogRecordType};
+use crate::row::{Row, MAX_ID};
+use crate::storage::{DiskManager, NodeType, Page, Snapshot};
+use std::fmt;
 use std::time::Instant;
 
 pub const PAGE_SIZE: usize = 4096;
 const SLEEP_MS: u64 = 10;
 const MAX_RETRY: usize = 3000 / SLEEP_MS as usize;
+/// Percentage of the buffer pool that can be dirty before writers
+/// start flushing pages themselves. See `Pager::throttle_writes`.
+pub const DEFAULT_MAX_DIRTY_PERCENT: usize = 75;
+// Percentage of the buffer pool flushed on top of the pages over the
+// limit while ingesting, so writers flush in batches instead of a page
+// on every write. See `Pager::begin_ingest`.
+const INGEST_FLUSH_BATCH_PERCENT: usize = 25;
+/// Number of pages of a tree read by `Pager::check_tree` on open, so
+/// checking a file stays quick however large it is.
+pub const CHECK_SAMPLE_PAGES: usize = 256;
+
+// Leaves packed by `Pager::bulk_load` are filled above half, so they
+// aren't merged by the next delete.
+const MIN_FILL_PERCENT: usize = 51;
+
+// Page and cell number a row is inserted into, or why it's rejected.
+type Inserted = Result<(usize, usize), DbError>;
 
 #[derive(PartialEq, Eq)]
 pub enum Operation {
@@ -31,21 +55,304 @@ pub struct Cursor {
     pub end_of_table: bool,
 }
 
+/// Write guards of the pages held while modifying the tree, e.g. the
+/// ancestors of a node being split or merged, which are unpinned with
+/// their dirty flag when the scope is dropped, deepest page first.
+///
+/// This way, every path through a split or merge releases the pages
+/// it doesn't hand over, instead of unpinning them one by one.
+pub struct PinScope<'a> {
+    pager: &'a Pager,
+    pages: Vec<(RwLockWriteGuard<'a, Page>, bool)>,
+    // See `PinScope::optimistic`.
+    optimistic: bool,
+}
+
+impl<'a> PinScope<'a> {
+    pub fn new(pager: &'a Pager) -> Self {
+        Self {
+            pager,
+            pages: Vec::new(),
+            optimistic: false,
+        }
+    }
+
+    /// Scope of a write under the shared SMO latch, where the structure
+    /// can't change, so pages are unpinned as soon as they are pushed and
+    /// the write must back off instead of splitting or merging. See
+    /// `LatchProtocol::SmoLatch`.
+    pub fn optimistic(pager: &'a Pager) -> Self {
+        Self {
+            pager,
+            pages: Vec::new(),
+            optimistic: true,
+        }
+    }
+
+    pub fn is_optimistic(&self) -> bool {
+        self.optimistic
+    }
+
+    pub fn push(&mut self, page: RwLockWriteGuard<'a, Page>, is_dirty: bool) {
+        if self.optimistic {
+            self.pager.unpin_page_with_write_guard(page, is_dirty);
+        } else {
+            self.pages.push((page, is_dirty));
+        }
+    }
+
+    /// Take the last page out of the scope, which the caller is now
+    /// responsible to unpin, or to push back.
+    pub fn pop(&mut self) -> Option<RwLockWriteGuard<'a, Page>> {
+        self.pages.pop().map(|(page, _)| page)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// Unpin every page in the scope now, deepest page first.
+    pub fn release(&mut self) {
+        while let Some((page, is_dirty)) = self.pages.pop() {
+            self.pager.unpin_page_with_write_guard(page, is_dirty);
+        }
+    }
+}
+
+impl Drop for PinScope<'_> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// How writers keep structure modifications, i.e. splits and merges,
+/// from racing each other. It's selectable on the pager, so they can be
+/// benchmarked against each other, see `DbOptions::with_latch_protocol`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LatchProtocol {
+    // Latch crabbing alone: writers keep the ancestors that might be
+    // modified latched down to the leaf, so structure modifications of
+    // different subtrees run concurrently.
+    #[default]
+    Crabbing,
+    // Writers first run under a shared SMO latch, releasing every page on
+    // the way down to the leaf. A write that would split or merge backs
+    // off and runs again under the exclusive SMO latch, so there is only
+    // one structure modification at a time, and no writer racing it.
+    //
+    // TRADEOFF: Writes that split or merge descend twice and stall every
+    // other writer, in exchange for not latching ancestors otherwise.
+    SmoLatch,
+}
+
+// Result of a write that might back off from a split or merge in an
+// optimistic `PinScope`.
+enum Smo<T> {
+    Done(T),
+    Needed,
+}
+
+impl<T> Smo<T> {
+    fn done(self) -> T {
+        match self {
+            Smo::Done(value) => value,
+            Smo::Needed => unreachable!("only optimistic writes back off"),
+        }
+    }
+}
+
+/// How a page is expected to be accessed, given as a hint by the
+/// executors so the pager can treat the page accordingly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessPattern {
+    // A one-off scan through every leaf, e.g. a full table scan.
+    Sequential,
+    // A scan through a bounded range of leaves.
+    Range,
+}
+
+/// How full the leaves of a tree are, to see how well the split
+/// point fits the inserts, see `InsertHistory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Occupancy {
+    pub leaves: usize,
+    pub used_bytes: usize,
+}
+
+impl Occupancy {
+    /// Percentage of the leaves' space used by cells.
+    pub fn percent(&self) -> usize {
+        if self.leaves == 0 {
+            return 0;
+        }
+
+        self.used_bytes * 100 / (self.leaves * LEAF_NODE_SPACE_FOR_CELLS)
+    }
+}
+
+impl std::fmt::Display for Occupancy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "leaves: {}, occupancy: {}%", self.leaves, self.percent())
+    }
+}
+
+/// Shape of a tree, see `Pager::tree_stats`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeStats {
+    pub height: usize,
+    pub internal_nodes: usize,
+    // Number of children of every internal node.
+    pub children: usize,
+    // Percentage of the space used by cells of each leaf, sorted.
+    pub leaf_fill: Vec<usize>,
+}
+
+impl TreeStats {
+    pub fn leaves(&self) -> usize {
+        self.leaf_fill.len()
+    }
+
+    pub fn average_fill(&self) -> usize {
+        self.leaf_fill
+            .iter()
+            .sum::<usize>()
+            .checked_div(self.leaves())
+            .unwrap_or(0)
+    }
+
+    /// Fill of the leaf at the given percentile, e.g. 0.1 for the leaf
+    /// that 10% of the leaves are emptier than.
+    pub fn fill_percentile(&self, p: f64) -> usize {
+        if self.leaf_fill.is_empty() {
+            return 0;
+        }
+
+        let index = ((self.leaves() as f64 * p).ceil() as usize).saturating_sub(1);
+        self.leaf_fill[index.min(self.leaves() - 1)]
+    }
+
+    /// Average number of children of the internal nodes, in tenths.
+    pub fn average_fanout_tenths(&self) -> usize {
+        (self.children * 10)
+            .checked_div(self.internal_nodes)
+            .unwrap_or(0)
+    }
+}
+
+/// Result of comparing the cached pages of a file with the file, see
+/// `Pager::sync_check`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncCheck {
+    pub checked: usize,
+    // Dirty pages that differ from disk, as they're yet to be flushed.
+    pub unflushed: usize,
+    // Pages left unchecked once the byte budget ran out.
+    pub skipped: usize,
+    pub bytes_read: usize,
+    // Clean pages that differ from disk, one line each.
+    pub divergences: Vec<String>,
+}
+
+impl fmt::Display for SyncCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for divergence in &self.divergences {
+            writeln!(f, "{divergence}")?;
+        }
+        write!(
+            f,
+            "checked {} pages ({} bytes read): {} diverged, {} unflushed, {} skipped",
+            self.checked,
+            self.bytes_read,
+            self.divergences.len(),
+            self.unflushed,
+            self.skipped
+        )
+    }
+}
+
+/// State of a page in the buffer pool, see `Pager::inspect_page`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageInfo {
+    pub page_id: usize,
+    pub frame_id: usize,
+    pub pin_count: usize,
+    // Whether one of the pins is taken by `Pager::pin_page`.
+    pub pinned: bool,
+    pub is_dirty: bool,
+    pub lsn: u32,
+    // Type of the node, unless the page is empty.
+    pub node_type: Option<NodeType>,
+    pub cells: usize,
+}
+
+fn page_info(frame_id: usize, page: &Page, pinned: &HashSet<usize>) -> PageInfo {
+    let page_id = page.page_id.unwrap();
+    let node = page.node();
+    PageInfo {
+        page_id,
+        frame_id,
+        pin_count: page.pin_count,
+        pinned: pinned.contains(&page_id),
+        is_dirty: page.is_dirty,
+        lsn: page.lsn,
+        node_type: node.map(|node| node.node_type),
+        cells: node.map_or(0, |node| node.num_of_cells as usize),
+    }
+}
+
+impl fmt::Display for PageInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let node = match self.node_type {
+            Some(NodeType::Leaf) => format!("leaf with {} cells", self.cells),
+            Some(NodeType::Internal) => format!("internal node with {} keys", self.cells),
+            None => "empty".to_string(),
+        };
+        write!(
+            f,
+            "page {} in frame {}: {node}, pin count {}{}, {}, lsn {}",
+            self.page_id,
+            self.frame_id,
+            self.pin_count,
+            if self.pinned { " (pinned)" } else { "" },
+            if self.is_dirty { "dirty" } else { "clean" },
+            self.lsn
+        )
+    }
+}
+
 #[derive(Debug)]
 struct PageMetadata {
     frame_id: usize,
+    // The file of the page in the frame, see `BufferPool`.
+    file_id: usize,
     last_accessed_at: Instant,
+    // Cold pages are evicted before any other pages.
+    cold: bool,
 }
 
 impl PageMetadata {
-    pub fn new(frame_id: usize) -> Self {
+    pub fn new(frame_id: usize, file_id: usize) -> Self {
         Self {
             frame_id,
+            file_id,
             last_accessed_at: Instant::now(),
+            cold: false,
         }
     }
 }
 
+/// Which unpinned frame the buffer pool evicts to load another page, see
+/// `DbOptions::with_replacement_policy`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplacementPolicy {
+    // The frame accessed least recently.
+    #[default]
+    Lru,
+    // The frame accessed the fewest times since its page is loaded, so
+    // pages read by every lookup, such as the root, stay in the pool.
+    Lfu,
+}
+
 // TRADEOFF: We are using the most naive replacement policies.
 //
 // We are replacing pages by considering the recency of a page instead
@@ -58,7 +365,7 @@ impl PageMetadata {
 // Hence, it can be contradicting sometime to replace based on recency.
 //
 // So, a better algorithms will be using Least Frequencyly Used (LFU)
-// replacement policies.
+// replacement policies, which can be picked with `ReplacementPolicy::Lfu`.
 #[derive(Debug)]
 struct LRUReplacer {
     // We are using Vec instead of HashMap as the size
@@ -68,21 +375,49 @@ struct LRUReplacer {
     // And it's a bit easier to deal with Vec than
     // HashMap for the time being.
     page_table: RwLock<Vec<PageMetadata>>,
+    policy: ReplacementPolicy,
+    // Times each frame is pinned since its page is loaded, by frame id.
+    accesses: Vec<AtomicUsize>,
 }
 
 impl LRUReplacer {
     pub fn new(pool_size: usize) -> Self {
         Self {
             page_table: RwLock::new(Vec::with_capacity(pool_size)),
+            policy: ReplacementPolicy::default(),
+            accesses: (0..pool_size).map(|_| AtomicUsize::new(0)).collect(),
         }
     }
 
     /// Return frame metadata that are accessed least recently
-    /// as compared to the other frame.
+    /// as compared to the other frame, or the least often with
+    /// `ReplacementPolicy::Lfu`.
+    ///
+    /// Cold frames are always evicted first. Then, when the pool is shared
+    /// by several files, frames of the file with the most frames to evict
+    /// go first, so a busy file can't push every other file out of the pool.
     pub fn victim(&self) -> Option<PageMetadata> {
         let mut page_table = self.page_table.write();
-        page_table.sort_by(|a, b| b.last_accessed_at.cmp(&a.last_accessed_at));
-        page_table.pop()
+        let mut frames_per_file: HashMap<usize, usize> = HashMap::new();
+        for md in page_table.iter() {
+            *frames_per_file.entry(md.file_id).or_default() += 1;
+        }
+
+        let accesses = |md: &PageMetadata| match self.policy {
+            ReplacementPolicy::Lru => 0,
+            ReplacementPolicy::Lfu => self.accesses[md.frame_id].load(Ordering::Relaxed),
+        };
+        page_table.sort_by(|a, b| {
+            a.cold
+                .cmp(&b.cold)
+                .then(frames_per_file[&a.file_id].cmp(&frames_per_file[&b.file_id]))
+                .then(accesses(b).cmp(&accesses(a)))
+                .then(b.last_accessed_at.cmp(&a.last_accessed_at))
+        });
+        let victim = page_table.pop()?;
+        // The frame is about to hold another page.
+        self.accesses[victim.frame_id].store(0, Ordering::Relaxed);
+        Some(victim)
     }
 
     /// This should be called after our Pager place the page into
@@ -90,6 +425,9 @@ impl LRUReplacer {
     /// replacer. I guess this prevent it from the page being
     /// evicted
     pub fn pin(&self, frame_id: usize) {
+        if let Some(accesses) = self.accesses.get(frame_id) {
+            accesses.fetch_add(1, Ordering::Relaxed);
+        }
         let mut page_table = self.page_table.write();
         if let Some(index) = page_table.iter().position(|md| md.frame_id == frame_id) {
             page_table.remove(index);
@@ -99,41 +437,53 @@ impl LRUReplacer {
     /// This should be called by our Pager when the page pin_count
     /// becomes 0. Here, unpin a frame means adding it to our
     /// replacer. This allow the page to be evicted.
-    pub fn unpin(&self, frame_id: usize) {
+    pub fn unpin(&self, frame_id: usize, file_id: usize) {
         let mut page_table = self.page_table.write();
-        page_table.push(PageMetadata::new(frame_id));
+        page_table.push(PageMetadata::new(frame_id, file_id));
     }
-}
 
-#[derive(Debug)]
-pub enum PagerError {
-    NoFreePageAvailable,
-    FailToAcquirePageLock,
+    /// Same as `unpin`, but the frame is evicted before any other frame
+    /// that is not cold.
+    pub fn unpin_cold(&self, frame_id: usize, file_id: usize) {
+        let mut page_table = self.page_table.write();
+        let mut metadata = PageMetadata::new(frame_id, file_id);
+        metadata.cold = true;
+        page_table.push(metadata);
+    }
 }
 
-// TRADEOFF: This isn't exactly a Pager or Buffer Pool manager.
-//
-// Since, we includes the B+ tree operations here in this module as well.
-// This isn't the best structure for our code. Ideally, the logic of B+ tree
-// operations should be move to a separate module and it would use Pager
-// to access page as needed.
+/// The frames of a buffer pool, which can be shared by the pagers of
+/// several files, see `Pager::with_pool`.
+///
+/// Pages are cached by their file and page id, so a process hosting
+/// several small databases can share a single pool instead of having a
+/// pool per file that mostly sits idle.
 #[derive(Debug)]
-pub struct Pager {
-    disk_manager: DiskManager,
-    replacer: LRUReplacer,
-    pages: Arc<Vec<RwLock<Page>>>,
-    next_page_id: AtomicUsize,
+pub struct BufferPool {
+    pages: Vec<RwLock<Page>>,
+    // File id of the page in each frame. It's only updated while holding
+    // the write latch of the frame.
+    owners: Vec<AtomicUsize>,
     // Indexes in our `pages` that are "free", which mean
     // it is uninitialize.
     free_list: Mutex<Vec<usize>>,
-    // Mapping page id to frame id
-    page_table: Arc<RwLock<HashMap<usize, usize>>>,
-
-    flushed_lsn: Option<AtomicU32>,
+    // Mapping file id and page id to frame id
+    page_table: RwLock<HashMap<(usize, usize), usize>>,
+    replacer: LRUReplacer,
+    // Number of dirty pages, so writers can check it without latching
+    // every frame. Only updated through `mark_dirty` and `mark_clean`.
+    dirty_pages: AtomicUsize,
+
+    // Files with pages in the pool, so a dirty page can be flushed to its
+    // file when its frame is taken by another file.
+    files: RwLock<HashMap<usize, Arc<DiskManager>>>,
+    // Logs of the files that are written ahead, see `flush_frame`.
+    logs: RwLock<HashMap<usize, Arc<LogManager>>>,
+    next_file_id: AtomicUsize,
 }
 
-impl Pager {
-    pub fn new(path: impl AsRef<Path>, pool_size: usize) -> Pager {
+impl BufferPool {
+    pub fn new(pool_size: usize) -> Self {
         // Initialize free list.
         let mut free_list = Vec::with_capacity(pool_size);
         for i in (0..pool_size).rev() {
@@ -150,146 +500,1279 @@ impl Pager {
         //
         // Hence, for the sake of simplicity, I'll preallocate empty page first....
         let mut pages = Vec::with_capacity(pool_size);
+        let mut owners = Vec::with_capacity(pool_size);
         for _ in 0..pool_size {
             pages.push(RwLock::new(Page::new(None)));
+            owners.push(AtomicUsize::new(0));
+        }
+
+        Self {
+            pages,
+            owners,
+            free_list: Mutex::new(free_list),
+            page_table: RwLock::new(HashMap::new()),
+            replacer: LRUReplacer::new(pool_size),
+            dirty_pages: AtomicUsize::new(0),
+            files: RwLock::new(HashMap::new()),
+            logs: RwLock::new(HashMap::new()),
+            next_file_id: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pick the frame to evict with the policy instead of LRU.
+    pub fn with_replacement_policy(mut self, policy: ReplacementPolicy) -> Self {
+        self.replacer.policy = policy;
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.pages.len()
+    }
+
+    fn mark_dirty(&self, page: &mut Page) {
+        if !page.is_dirty {
+            page.is_dirty = true;
+            self.dirty_pages.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn mark_clean(&self, page: &mut Page) {
+        if page.is_dirty {
+            page.is_dirty = false;
+            self.dirty_pages.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn register(&self, disk_manager: Arc<DiskManager>) -> usize {
+        let file_id = self.next_file_id.fetch_add(1, Ordering::Relaxed);
+        self.files.write().insert(file_id, disk_manager);
+        file_id
+    }
+
+    // Free the frames of the file once its pager is dropped. Like dropping a
+    // pager with a pool of its own, dirty pages that aren't flushed are lost.
+    fn unregister(&self, file_id: usize) {
+        let mut page_table = self.page_table.write();
+        let frames: Vec<usize> = page_table
+            .iter()
+            .filter(|((id, _), _)| *id == file_id)
+            .map(|(_, &frame_id)| frame_id)
+            .collect();
+        page_table.retain(|(id, _), _| *id != file_id);
+
+        for frame_id in frames {
+            let mut page = self.pages[frame_id].write();
+            self.mark_clean(&mut page);
+            page.deallocate();
+            drop(page);
+            self.replacer.pin(frame_id);
+            self.free_list.lock().push(frame_id);
+        }
+        drop(page_table);
+
+        self.files.write().remove(&file_id);
+        self.logs.write().remove(&file_id);
+    }
+
+    // Write the page in the frame to the file it belongs to.
+    fn flush_frame(&self, frame_id: usize, page: &Page) -> Result<(), std::io::Error> {
+        // Make sure all of the logs that lead to the changes of the page
+        // are flushed to disk first. Thus, enabling recovery if crash
+        // happens in between.
+        let file_id = self.owners[frame_id].load(Ordering::Acquire);
+        if let Some(log_manager) = self.logs.read().get(&file_id) {
+            log_manager.flush_until(page.lsn);
         }
+        match self.files.read().get(&file_id) {
+            Some(disk_manager) => disk_manager.write_page(page.page_id.unwrap(), &page.as_bytes()),
+            None => Ok(()),
+        }
+    }
+
+    // Flush the dirty page of the frame before it's taken for another
+    // page. If the write fails, the frame is given back to the replacer,
+    // so the page stays cached until it can be flushed.
+    fn flush_victim(&self, frame_id: usize, page: &Page) -> Result<(), DbError> {
+        self.flush_frame(frame_id, page).map_err(|err| {
+            let file_id = self.owners[frame_id].load(Ordering::Acquire);
+            self.replacer.unpin(frame_id, file_id);
+            DbError::from(err)
+        })
+    }
+
+    // Frames holding a page of the file, ordered by frame id.
+    //
+    // The page table isn't locked while the frames are latched afterward,
+    // so callers have to check the frame still holds the same page.
+    fn frames_of(&self, file_id: usize) -> Vec<(usize, usize)> {
+        let mut frames: Vec<(usize, usize)> = self
+            .page_table
+            .read()
+            .iter()
+            .filter(|((id, _), _)| *id == file_id)
+            .map(|(&(_, page_id), &frame_id)| (frame_id, page_id))
+            .collect();
+        frames.sort_unstable();
+        frames
+    }
+
+    fn holds(&self, frame_id: usize, file_id: usize, page: &Page, page_id: usize) -> bool {
+        page.page_id == Some(page_id) && self.owners[frame_id].load(Ordering::Acquire) == file_id
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        self.pool.unregister(self.file_id);
+    }
+}
+
+// TRADEOFF: This isn't exactly a Pager or Buffer Pool manager.
+//
+// Since, we includes the B+ tree operations here in this module as well.
+// This isn't the best structure for our code. Ideally, the logic of B+ tree
+// operations should be move to a separate module and it would use Pager
+// to access page as needed.
+#[derive(Debug)]
+pub struct Pager {
+    disk_manager: Arc<DiskManager>,
+    pool: Arc<BufferPool>,
+    // Id of our file in the buffer pool.
+    file_id: usize,
+    next_page_id: AtomicUsize,
+    // Pages deleted since the last flush, which are reused before the
+    // free page list of the file. See `Pager::allocate_page_id`.
+    freed_pages: Mutex<Vec<usize>>,
+
+    // Tree level latch. Operations that modify the tree hold it in shared mode,
+    // so they can still run concurrently with each other through latch crabbing.
+    //
+    // Diagnostics that need to walk the whole tree, e.g. printing the tree, hold it
+    // in exclusive mode so that they observe a consistent structure instead of one
+    // that's halfway through a split or merge.
+    tree_latch: RwLock<()>,
+
+    // See `LatchProtocol::SmoLatch`, which is only taken if
+    // `smo_latching` is set.
+    smo_latch: RwLock<()>,
+    smo_latching: AtomicBool,
+    // Number of writes that backed off to run under the exclusive SMO
+    // latch.
+    smo_retries: AtomicUsize,
+    // See `Pager::epoch`.
+    epoch: AtomicU64,
+
+    max_dirty_percent: AtomicUsize,
+    // See `Pager::begin_ingest`.
+    ingesting: AtomicBool,
+    // Number of pages flushed by writers due to backpressure.
+    throttled_flushes: AtomicUsize,
+
+    // Number of levels of each tree of the file by its root page, where
+    // a missing tree isn't computed yet. See `Pager::tree_height`.
+    tree_heights: Mutex<HashMap<usize, usize>>,
+
+    // Snapshots that are still being read. Pages are copied aside
+    // for them before they are modified. See `Pager::snapshot`.
+    snapshots: RwLock<Vec<Weak<Snapshot>>>,
+
+    // Where the ids of the pages in the buffer pool are saved on a clean
+    // shutdown, so they are loaded back when the file is opened again.
+    // See `Pager::save_hot_pages`.
+    hot_pages_path: PathBuf,
+
+    // Write-ahead log of the file, if any. See `Pager::with_log_manager`.
+    log_manager: Option<Arc<LogManager>>,
+
+    // Dictionaries of the tables of the file by their page, loaded the
+    // first time a row encoded by them is read. See `Pager::decode_row`.
+    dictionaries: RwLock<HashMap<usize, Arc<Dictionary>>>,
+
+    // Latest metadata of the file, see `Pager::metadata`.
+    metadata: RwLock<Option<Arc<Metadata>>>,
+
+    // Pages pinned by an operator until they're unpinned, see
+    // `Pager::pin_page`.
+    pinned: Mutex<HashSet<usize>>,
+}
+
+impl Pager {
+    pub fn new(path: impl AsRef<Path>, pool_size: usize) -> Pager {
+        Self::with_options(path, &DbOptions::new().with_pool_size(pool_size))
+    }
 
-        let disk_manager = DiskManager::new(path);
+    /// Same as `new`, but with the pool size, replacement policy and sync
+    /// mode of the options. The WAL isn't attached, see `with_log_manager`.
+    pub fn with_options(path: impl AsRef<Path>, options: &DbOptions) -> Pager {
+        let pool = BufferPool::new(options.pool_size())
+            .with_replacement_policy(options.replacement_policy());
+        let disk_manager = DiskManager::with_sync_mode(&path, options.sync_mode());
+        let pager = Self::with_disk_manager(path, disk_manager, Arc::new(pool));
+        pager.set_max_dirty_percent(options.max_dirty_percent());
+        pager.set_latch_protocol(options.latch_protocol());
+        pager
+    }
+
+    /// Same as `new`, but cache the pages in a buffer pool that can be
+    /// shared with the pagers of other files.
+    pub fn with_pool(path: impl AsRef<Path>, pool: Arc<BufferPool>) -> Pager {
+        let disk_manager = DiskManager::new(&path);
+        Self::with_disk_manager(path, disk_manager, pool)
+    }
+
+    fn with_disk_manager(
+        path: impl AsRef<Path>,
+        disk_manager: DiskManager,
+        pool: Arc<BufferPool>,
+    ) -> Pager {
+        let mut hot_pages_path = path.as_ref().as_os_str().to_owned();
+        hot_pages_path.push(".hot");
+        let disk_manager = Arc::new(disk_manager);
         let next_page_id = disk_manager.file_len / PAGE_SIZE;
+        disk_manager.load_free_page();
+        let file_id = pool.register(disk_manager.clone());
 
-        Pager {
+        let pager = Pager {
             disk_manager,
-            replacer: LRUReplacer::new(pool_size),
-            pages: Arc::new(pages),
+            pool,
+            file_id,
             next_page_id: AtomicUsize::new(next_page_id),
-            free_list: Mutex::new(free_list),
-            page_table: Arc::new(RwLock::new(HashMap::new())),
-            flushed_lsn: None,
+            freed_pages: Mutex::new(Vec::new()),
+            tree_latch: RwLock::new(()),
+            smo_latch: RwLock::new(()),
+            smo_latching: AtomicBool::new(false),
+            smo_retries: AtomicUsize::new(0),
+            epoch: AtomicU64::new(0),
+            max_dirty_percent: AtomicUsize::new(DEFAULT_MAX_DIRTY_PERCENT),
+            ingesting: AtomicBool::new(false),
+            throttled_flushes: AtomicUsize::new(0),
+            tree_heights: Mutex::new(HashMap::new()),
+            snapshots: RwLock::new(Vec::new()),
+            hot_pages_path: PathBuf::from(hot_pages_path),
+            log_manager: None,
+            dictionaries: RwLock::new(HashMap::new()),
+            metadata: RwLock::new(None),
+            pinned: Mutex::new(HashSet::new()),
+        };
+        pager.load_hot_pages();
+        pager
+    }
+
+    /// Log the changes to the rows ahead of writing the pages, so the
+    /// pages are only written once their changes are in the log.
+    ///
+    /// TRADEOFF: Only rows are logged. Splits and merges aren't, so a
+    /// redo replays the rows by their key instead of the page they were
+    /// changed in, rebuilding the structure of the tree on the way.
+    pub fn with_log_manager(mut self, log_manager: Arc<LogManager>) -> Pager {
+        self.pool
+            .logs
+            .write()
+            .insert(self.file_id, log_manager.clone());
+        self.log_manager = Some(log_manager);
+        self
+    }
+
+    /// Append the record to the log, returning its LSN, or `None` if the
+    /// file isn't logged. The record is only built if it's appended.
+    ///
+    /// It has to be appended before the page is changed and unpinned, so
+    /// the LSN of the page covers it.
+    pub fn append_log<F>(&self, record: F) -> Option<u32>
+    where
+        F: FnOnce() -> LogRecord,
+    {
+        self.log_manager
+            .as_ref()
+            .map(|log_manager| log_manager.append_log(&mut record()))
+    }
+
+    /// Flush the log up to `lsn`, e.g. so a transaction is durable once
+    /// its commit record is.
+    pub fn flush_log_until(&self, lsn: u32) {
+        if let Some(log_manager) = &self.log_manager {
+            log_manager.flush_until(lsn);
         }
     }
 
-    fn new_page(&self) -> Option<RwLockWriteGuard<Page>> {
-        let mut page_table = self.page_table.write();
+    pub fn log_manager(&self) -> Option<&Arc<LogManager>> {
+        self.log_manager.as_ref()
+    }
+
+    /// Build a dictionary from the string fields and save it in a new page.
+    ///
+    /// TRADEOFF: The page is written to the file directly instead of going
+    /// through the buffer pool, as it's read once and cached as a whole.
+    /// The page of a dictionary that's replaced is never freed, as rows
+    /// encoded by it could still be around.
+    pub fn write_dictionary(
+        &self,
+        values: impl IntoIterator<Item = String>,
+    ) -> Result<Arc<Dictionary>, String> {
+        let page_id = self.allocate_page_id();
+        let dictionary = Arc::new(Dictionary::build(page_id, values));
+        self.disk_manager
+            .write_page(page_id, &dictionary.as_bytes())
+            .map_err(|err| format!("fail to write dictionary page {page_id}: {err}"))?;
+
+        self.dictionaries
+            .write()
+            .insert(page_id, dictionary.clone());
+        Ok(dictionary)
+    }
+
+    /// Save the metadata entries in a new page, see `Metadata`.
+    ///
+    /// TRADEOFF: Like a dictionary, the page is written to the file
+    /// directly and the page of the metadata it replaces is never freed,
+    /// as the catalog points to it until it's saved again.
+    pub fn write_metadata(
+        &self,
+        entries: BTreeMap<String, Vec<u8>>,
+    ) -> Result<Arc<Metadata>, String> {
+        let page_id = self.allocate_page_id();
+        let metadata = Arc::new(Metadata::new(page_id, entries)?);
+        self.disk_manager
+            .write_page(page_id, &metadata.as_bytes())
+            .map_err(|err| format!("fail to write metadata page {page_id}: {err}"))?;
+
+        *self.metadata.write() = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Metadata saved in the page, which is read from disk only once.
+    pub fn metadata(&self, page_id: usize) -> Result<Arc<Metadata>, String> {
+        if let Some(metadata) = self.metadata.read().as_ref() {
+            if metadata.page_id() == page_id {
+                return Ok(metadata.clone());
+            }
+        }
+
+        let bytes = self
+            .disk_manager
+            .read_page(page_id)
+            .map_err(|err| format!("fail to read metadata page {page_id}: {err}"))?;
+        let metadata = Arc::new(Metadata::from_bytes(&bytes)?);
+        *self.metadata.write() = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Dictionary saved in the page, which is read from disk only once.
+    pub fn dictionary(&self, page_id: usize) -> Result<Arc<Dictionary>, String> {
+        if let Some(dictionary) = self.dictionaries.read().get(&page_id) {
+            return Ok(dictionary.clone());
+        }
+
+        let bytes = self
+            .disk_manager
+            .read_page(page_id)
+            .map_err(|err| format!("fail to read dictionary page {page_id}: {err}"))?;
+        let dictionary = Arc::new(Dictionary::from_bytes(&bytes)?);
+        self.dictionaries
+            .write()
+            .insert(page_id, dictionary.clone());
+        Ok(dictionary)
+    }
+
+    /// Replace the string fields encoded by a dictionary by their string,
+    /// so rows are only seen encoded within their cells.
+    pub fn decode_row(&self, mut row: Row) -> Row {
+        let mut pages = dictionary::dictionary_pages(&row).peekable();
+        if pages.peek().is_none() {
+            return row;
+        }
+
+        for page_id in pages {
+            if let Err(err) = self.dictionary(page_id) {
+                warn!("fail to decode row {}: {err}", row.id);
+            }
+        }
+        let dictionaries = self.dictionaries.read();
+        dictionary::decode(&mut row, |page_id| {
+            dictionaries.get(&page_id).map(Arc::as_ref)
+        });
+        row
+    }
+
+    // Allocate a new page, waiting for a frame to be unpinned if the
+    // buffer pool is full.
+    //
+    // `check_pool_size` makes sure there are enough frames for a split,
+    // so running out of frames here means the pages are pinned elsewhere
+    // for too long. Return `DbError::NoFreeFrame` instead of waiting
+    // forever.
+    fn new_page(&self) -> Result<RwLockWriteGuard<'_, Page>, DbError> {
+        for _ in 0..MAX_RETRY {
+            if let Some(page) = self.try_new_page()? {
+                return Ok(page);
+            }
+
+            let duration = std::time::Duration::from_millis(SLEEP_MS);
+            std::thread::sleep(duration);
+        }
+
+        Err(DbError::NoFreeFrame)
+    }
+
+    fn try_new_page(&self) -> Result<Option<RwLockWriteGuard<Page>>, DbError> {
+        let mut page_table = self.pool.page_table.write();
 
         // Pop unused page index from free list.
-        let mut free_list = self.free_list.lock();
+        let mut free_list = self.pool.free_list.lock();
         let frame_id = free_list
             .pop()
-            .or_else(|| self.replacer.victim().map(|md| md.frame_id));
+            .or_else(|| self.pool.replacer.victim().map(|md| md.frame_id));
         drop(free_list);
 
         if let Some(frame_id) = frame_id {
-            let unlock_page = self.pages.get(frame_id).unwrap();
+            let unlock_page = self.pool.pages.get(frame_id).unwrap();
             let mut page = unlock_page.write();
 
             // Check if page is dirty. Flush page to disk
-            // if needed
+            // if needed, which could be a page of another file.
             if page.is_dirty {
-                let dirty_page_id = page.page_id.unwrap();
-                self.flush_write_page(dirty_page_id, &page);
+                self.pool.flush_victim(frame_id, &page)?;
             }
 
-            let page_id = self.next_page_id.fetch_add(1, Ordering::Acquire);
+            let page_id = self.allocate_page_id();
 
             // Update page table
             page_table.retain(|_, &mut fid| fid != frame_id);
-            page_table.insert(page_id, frame_id);
+            page_table.insert((self.file_id, page_id), frame_id);
+            self.pool.owners[frame_id].store(self.file_id, Ordering::Release);
 
             // Reset page
-            page.is_dirty = false;
+            self.pool.mark_clean(&mut page);
             page.pin_count = 0;
             page.page_id = Some(page_id);
-            page.node = None;
+            page.clear_node();
 
             if page_id == 0 {
-                page.node = Some(Node::root());
+                page.set_node(Node::root());
             }
 
             page.pin_count += 1;
-            self.replacer.pin(frame_id);
+            self.pool.replacer.pin(frame_id);
             drop(page_table);
 
-            Some(page)
+            Ok(Some(page))
         } else {
             drop(page_table);
-
-            let duration = std::time::Duration::from_millis(SLEEP_MS);
-            std::thread::sleep(duration);
-
-            self.new_page()
+            Ok(None)
         }
     }
 
-    pub fn flush_write_page(&self, page_id: usize, page: &RwLockWriteGuard<Page>) {
-        // TODO (Recovery): Check page_lsn and flushed_lsn before flushing to disk.
-        //
-        // This is to ensure that all of the logs that lead to the changes of the
-        // page is flushed to disk. Thus, enabling recovery if crash happens.
-        let bytes = page.as_bytes();
-        self.disk_manager.write_page(page_id, &bytes).unwrap();
+    /// Allocate the root leaf of a new tree in the file, e.g. for another
+    /// table of the catalog, and return its page id.
+    pub fn create_tree(&self) -> Result<usize, DbError> {
+        // Page 0 is the root of the first tree, and is only set up when
+        // it's first fetched, so fetch it before it's taken by the new
+        // root of an empty file. It's marked dirty so it's written before
+        // the pages after it.
+        let page = self.fetch_read_page_with_retry(0)?;
+        self.unpin_page_with_read_guard(page, true);
+
+        let mut page = self.new_page()?;
+        page.set_node(Node::root());
+        let page_id = page.page_id.unwrap();
+        self.unpin_page_with_write_guard(page, true);
+        Ok(page_id)
     }
 
-    pub fn flush_all_pages(&self) {
-        for page in self.pages.iter() {
-            let page = page.read();
-            if page.page_id.is_none() {
-                break;
+    /// Delete every page of the tree, e.g. of a table whose creation is
+    /// rolled back, and link them into the free page list. Return the
+    /// number of pages freed.
+    pub fn free_tree(&self, root_page_num: usize) -> Result<usize, DbError> {
+        let _tree_latch = self.tree_latch.write();
+
+        let mut page_ids = Vec::new();
+        let mut queue = VecDeque::from([root_page_num]);
+        while let Some(page_id) = queue.pop_front() {
+            let page = self.fetch_read_page_with_retry(page_id)?;
+            let node = page.node().unwrap();
+            if node.node_type == NodeType::Internal {
+                for cell in &node.internal_cells[..node.num_of_cells as usize] {
+                    queue.push_back(cell.child_pointer() as usize);
+                }
+                queue.push_back(node.right_child_offset as usize);
             }
+            self.unpin_page_with_read_guard(page, false);
+            page_ids.push(page_id);
+        }
 
-            if page.node.is_some() {
-                let bytes = page.as_bytes();
-                self.disk_manager
-                    .write_page(page.page_id.unwrap(), &bytes)
-                    .unwrap();
+        let mut freed = 0;
+        for page_id in page_ids {
+            let page = self.fetch_write_page_guard_with_retry(page_id)?;
+            if self.delete_page_with_write_guard(page) {
+                freed += 1;
             }
         }
+
+        self.flush_all_pages()?;
+        Ok(freed)
     }
 
-    pub fn delete_page_with_write_guard(&self, mut page: RwLockWriteGuard<Page>) -> bool {
-        let page_id = page.page_id.unwrap();
+    /// Write every page in the buffer pool to disk.
+    ///
+    /// Return the first IO error encountered. Pages are left as is, so
+    /// the flush can be retried to repair partially written pages.
+    pub fn flush_all_pages(&self) -> Result<(), std::io::Error> {
+        // Only the pages deleted before the flush starts are linked into
+        // the free page list, as the pages that pointed to them are then
+        // flushed, so the tree on disk never points to a free page.
+        let freed_pages = std::mem::take(&mut *self.freed_pages.lock());
+        if let Err(err) = self.write_pages() {
+            self.freed_pages.lock().extend(freed_pages);
+            return Err(err);
+        }
 
-        assert!(page.pin_count >= 1);
-        // unpin the page first.
-        //
-        // no need to call replacer here as to delete a page
-        // require a thread to hold a page, which means it's pinned
-        // and shouldn't be in a replacer.
-        page.pin_count -= 1;
-        self.replacer.pin(page_id);
+        self.link_free_pages(freed_pages)
+    }
 
-        let mut page_table = self.page_table.write();
-        if let Some(&frame_id) = page_table.get(&page_id) {
-            if page.pin_count == 0 {
-                page.deallocate();
-                page_table.remove(&page_id);
-                drop(page_table);
-                drop(page);
+    fn write_pages(&self) -> Result<(), std::io::Error> {
+        for (frame_id, page_id) in self.pool.frames_of(self.file_id) {
+            let page = self.pool.pages[frame_id].read();
+            if !self.pool.holds(frame_id, self.file_id, &page, page_id) {
+                continue;
+            }
 
-                self.free_list.lock().push(frame_id);
+            // Clean page that still holds the raw bytes read from
+            // disk is identical to what we have on disk, so there is
+            // no need to serialize and write it again.
+            if page.has_raw_bytes() && !page.is_dirty {
+                continue;
+            }
 
-                true
-            } else {
-                drop(page);
-                drop(page_table);
-                false
+            if page.has_node() {
+                self.flush_log_until(page.lsn);
+                let bytes = page.as_bytes();
+                self.disk_manager
+                    .write_page(page.page_id.unwrap(), &bytes)?;
             }
-        } else {
-            drop(page);
-            drop(page_table);
-            true
         }
+
+        Ok(())
     }
 
-    pub fn unpin_page_with_write_guard(&self, mut page: RwLockWriteGuard<Page>, is_dirty: bool) {
-        let page_table = self.page_table.read();
-        if let Some(&frame_id) = page_table.get(&page.page_id.unwrap()) {
-            if !page.is_dirty {
-                page.is_dirty = is_dirty;
+    // Write the pages as free pages, each linked to the previous first
+    // free page of the file, before making the last one the first. A
+    // crash in between leaves them out of the list, rather than the list
+    // pointing to a page that isn't free.
+    fn link_free_pages(&self, pages: Vec<usize>) -> Result<(), std::io::Error> {
+        if pages.is_empty() {
+            return Ok(());
+        }
+
+        // Hold the list, so the first free page isn't taken meanwhile.
+        let mut freed_pages = self.freed_pages.lock();
+        let mut free_page = self.disk_manager.free_page();
+        for &page_id in &pages {
+            let bytes = Page::free_page_bytes(page_id, free_page);
+            if let Err(err) = self.disk_manager.write_page(page_id, &bytes) {
+                freed_pages.extend(pages);
+                return Err(err);
+            }
+            free_page = page_id;
+        }
+
+        self.disk_manager.set_free_page(free_page).inspect_err(|_| {
+            freed_pages.extend(&pages);
+        })
+    }
+
+    /// Return the id of a page to write a new page to: a page deleted
+    /// since the last flush, or else the first free page of the file, or
+    /// else a page past the end of the file.
+    ///
+    /// TRADEOFF: The file never shrinks, as the free pages are only
+    /// reused rather than truncated, even when they're at the end.
+    fn allocate_page_id(&self) -> usize {
+        let mut freed_pages = self.freed_pages.lock();
+        if let Some(page_id) = freed_pages.pop() {
+            return page_id;
+        }
+
+        let page_id = self.disk_manager.free_page();
+        if page_id != 0 {
+            let header = self
+                .disk_manager
+                .read_page(page_id)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| PageHeader::decode(&bytes[..PAGE_HEADER_BYTES]));
+            match header {
+                Ok(header) if header.page_type == PageType::Free => {
+                    // The page is only taken once the list no longer
+                    // points to it.
+                    if self.disk_manager.set_free_page(header.free_page).is_ok() {
+                        return page_id;
+                    }
+                }
+                // The list is broken, e.g. by a torn write of the page,
+                // so it's dropped rather than handing out a page that may
+                // be in use.
+                _ => {
+                    let _ = self.disk_manager.set_free_page(0);
+                }
+            }
+        }
+
+        self.next_page_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Number of pages deleted since the last flush, plus the number of
+    /// free pages in the file.
+    #[cfg(test)]
+    pub fn num_of_free_pages(&self) -> usize {
+        let mut count = self.freed_pages.lock().len();
+        let mut page_id = self.disk_manager.free_page();
+        while page_id != 0 {
+            let Ok(bytes) = self.disk_manager.read_page(page_id) else {
+                break;
+            };
+            match PageHeader::decode(&bytes[..PAGE_HEADER_BYTES]) {
+                Ok(header) if header.page_type == PageType::Free => {
+                    count += 1;
+                    page_id = header.free_page;
+                }
+                _ => break,
+            }
+        }
+
+        count
+    }
+
+    /// Save the ids of the pages in the buffer pool, so they are loaded
+    /// when the file is opened again instead of starting with a cold pool.
+    ///
+    /// Internal nodes come first, as every lookup goes through them, then
+    /// leaves from the most recently accessed.
+    pub fn save_hot_pages(&self) -> Result<(), std::io::Error> {
+        // Frames that are pinned aren't in the replacer, and are the most
+        // recently accessed of all.
+        let accessed_at: HashMap<usize, Instant> = self
+            .pool
+            .replacer
+            .page_table
+            .read()
+            .iter()
+            .map(|md| (md.frame_id, md.last_accessed_at))
+            .collect();
+
+        let mut internal_pages = Vec::new();
+        let mut leaf_pages = Vec::new();
+        for (frame_id, page_id) in self.pool.frames_of(self.file_id) {
+            // Never wait for a latch here, the page is in use anyway.
+            let Some(page) = self.pool.pages[frame_id].try_read() else {
+                continue;
+            };
+            if !self.pool.holds(frame_id, self.file_id, &page, page_id) || !page.has_node() {
+                continue;
+            }
+
+            if page.node().unwrap().node_type == NodeType::Internal {
+                internal_pages.push(page_id);
+            } else {
+                leaf_pages.push((accessed_at.get(&frame_id).copied(), page_id));
+            }
+        }
+        internal_pages.sort_unstable();
+        leaf_pages.sort_by(|(a, _), (b, _)| match (a, b) {
+            (None, None) => std::cmp::Ordering::Equal,
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (Some(a), Some(b)) => b.cmp(a),
+        });
+
+        let page_ids: Vec<String> = internal_pages
+            .into_iter()
+            .chain(leaf_pages.into_iter().map(|(_, page_id)| page_id))
+            .map(|page_id| page_id.to_string())
+            .collect();
+        std::fs::write(&self.hot_pages_path, page_ids.join("\n"))
+    }
+
+    // Load the pages saved by `save_hot_pages`, up to the size of the pool.
+    //
+    // TRADEOFF: The list isn't removed after it's loaded, so it can be
+    // outdated after an unclean shutdown. It's only a hint for what to
+    // cache though, and pages that no longer exist in the file are skipped.
+    fn load_hot_pages(&self) {
+        let Ok(content) = std::fs::read_to_string(&self.hot_pages_path) else {
+            return;
+        };
+
+        let num_of_pages = self.next_page_id.load(Ordering::Acquire);
+        let mut loaded = 0;
+        for page_id in content
+            .lines()
+            .filter_map(|line| line.parse::<usize>().ok())
+        {
+            if loaded == self.pool.size() {
+                break;
+            }
+
+            if page_id < num_of_pages {
+                if let Ok(page) = self.fetch_read_page_guard(page_id) {
+                    self.unpin_page_with_read_guard(page, false);
+                    loaded += 1;
+                }
+            }
+        }
+        debug!("loaded {loaded} hot pages");
+    }
+
+    /// Load the internal nodes of the tree into the buffer pool, level by
+    /// level from the root, up to the size of the pool.
+    ///
+    /// Return the number of pages loaded.
+    pub fn warmup(&self, root_page_num: usize) -> Result<usize, DbError> {
+        let _tree_latch = self.tree_latch.read();
+        let height = self.tree_height(root_page_num)?;
+
+        let mut loaded = 0;
+        let mut queue = VecDeque::from([(root_page_num, 1)]);
+        while let Some((page_id, level)) = queue.pop_front() {
+            if loaded == self.pool.size() {
+                break;
+            }
+
+            let page = self.fetch_read_page_with_retry(page_id)?;
+            let node = page.node().unwrap();
+            // Children of the last internal level are leaves.
+            if node.node_type == NodeType::Internal && level + 1 < height {
+                for cell in &node.internal_cells[..node.num_of_cells as usize] {
+                    queue.push_back((cell.child_pointer() as usize, level + 1));
+                }
+                queue.push_back((node.right_child_offset as usize, level + 1));
+            }
+            self.unpin_page_with_read_guard(page, false);
+            loaded += 1;
+        }
+
+        Ok(loaded)
+    }
+
+    /// Walk the whole tree to measure its shape, e.g. how full its leaves
+    /// are, see `TreeStats`.
+    pub fn tree_stats(&self, root_page_num: usize) -> Result<TreeStats, DbError> {
+        let _tree_latch = self.tree_latch.read();
+        let mut stats = TreeStats {
+            height: 0,
+            internal_nodes: 0,
+            children: 0,
+            leaf_fill: Vec::new(),
+        };
+
+        let mut queue = VecDeque::from([(root_page_num, 1)]);
+        while let Some((page_id, level)) = queue.pop_front() {
+            let page = self.fetch_read_page_with_retry(page_id)?;
+            let node = page.node().unwrap();
+            stats.height = stats.height.max(level);
+            if node.node_type == NodeType::Internal {
+                stats.internal_nodes += 1;
+                stats.children += node.num_of_cells as usize + 1;
+                for cell in &node.internal_cells[..node.num_of_cells as usize] {
+                    queue.push_back((cell.child_pointer() as usize, level + 1));
+                }
+                queue.push_back((node.right_child_offset as usize, level + 1));
+            } else {
+                stats
+                    .leaf_fill
+                    .push(node.used_space() * 100 / LEAF_NODE_SPACE_FOR_CELLS);
+            }
+            self.unpin_page_with_read_guard(page, false);
+        }
+
+        stats.leaf_fill.sort_unstable();
+        Ok(stats)
+    }
+
+    /// Compare the cached pages of the file with the file, reading at
+    /// most `byte_budget` bytes from it, e.g. to debug pages that are
+    /// flushed out of order or don't serialize back to what was read.
+    ///
+    /// Each page is compared by serializing its node, rather than the raw
+    /// bytes it was read from, so a clean page has to serialize to exactly
+    /// the bytes on disk. A dirty page differing from disk is expected
+    /// until it's flushed, so it's only counted. A clean page of an older
+    /// format version serializes to the current one, so it isn't checked.
+    pub fn sync_check(&self, byte_budget: usize) -> SyncCheck {
+        let mut check = SyncCheck::default();
+        for (frame_id, page_id) in self.pool.frames_of(self.file_id) {
+            let page = self.pool.pages[frame_id].read();
+            if !self.pool.holds(frame_id, self.file_id, &page, page_id)
+                || !page.has_node()
+                || page.format_version() != PAGE_FORMAT_VERSION
+            {
+                continue;
+            }
+            if check.bytes_read + PAGE_SIZE > byte_budget {
+                check.skipped += 1;
+                continue;
+            }
+
+            let image = page.node_image();
+            let on_disk = match self.disk_manager.read_page(page_id) {
+                Ok(bytes) => {
+                    check.bytes_read += PAGE_SIZE;
+                    Some(bytes)
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => None,
+                Err(err) => {
+                    check
+                        .divergences
+                        .push(format!("page {page_id} can't be read: {err}"));
+                    continue;
+                }
+            };
+            check.checked += 1;
+
+            let divergence = match on_disk {
+                None => Some("isn't on disk".to_string()),
+                Some(bytes) => image
+                    .iter()
+                    .zip(bytes.iter())
+                    .position(|(a, b)| a != b)
+                    .map(|offset| {
+                        let differ = image.iter().zip(bytes.iter()).filter(|(a, b)| a != b);
+                        format!(
+                            "differs from disk in {} bytes, from offset {offset}",
+                            differ.count()
+                        )
+                    }),
+            };
+            match divergence {
+                Some(_) if page.is_dirty => check.unflushed += 1,
+                Some(divergence) => check
+                    .divergences
+                    .push(format!("page {page_id} is clean but {divergence}")),
+                None => {}
+            }
+        }
+
+        check
+    }
+
+    /// Load the page into the buffer pool and keep it pinned until it's
+    /// unpinned by `unpin_page`, so it can't be evicted, e.g. to reproduce
+    /// a pool where most frames are in use.
+    ///
+    /// Return false if the page is already pinned this way.
+    pub fn pin_page(&self, page_id: usize) -> Result<bool, String> {
+        if page_id >= self.next_page_id.load(Ordering::Acquire) {
+            return Err(format!("page {page_id} doesn't exist"));
+        }
+
+        let mut pinned = self.pinned.lock();
+        if pinned.contains(&page_id) {
+            return Ok(false);
+        }
+        match self.fetch_read_page_guard(page_id) {
+            Ok(page) => {
+                // The pin taken by the fetch is kept by not unpinning it.
+                drop(page);
+                pinned.insert(page_id);
+                Ok(true)
+            }
+            Err(DbError::NoFreeFrame) => Err(format!(
+                "page {page_id} can't be loaded: every frame is pinned"
+            )),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    /// Release the pin taken by `pin_page`.
+    pub fn unpin_page(&self, page_id: usize) -> Result<(), String> {
+        if !self.pinned.lock().remove(&page_id) {
+            return Err(format!("page {page_id} isn't pinned"));
+        }
+
+        // The page can't be evicted while it's pinned, so it's still in
+        // the same frame.
+        let frame_id = self.pool.page_table.read()[&(self.file_id, page_id)];
+        let page = self.pool.pages[frame_id].write();
+        self.unpin_page_with_write_guard(page, false);
+        Ok(())
+    }
+
+    /// Evict the page from the buffer pool, flushing it first if it's
+    /// dirty, e.g. to force the next access to read it from disk.
+    ///
+    /// Return whether it was flushed. A page that is pinned, or latched,
+    /// isn't evicted.
+    pub fn evict_page(&self, page_id: usize) -> Result<bool, String> {
+        let mut page_table = self.pool.page_table.write();
+        let Some(&frame_id) = page_table.get(&(self.file_id, page_id)) else {
+            return Err(format!("page {page_id} isn't in the buffer pool"));
+        };
+        let Some(mut page) = self.pool.pages[frame_id].try_write() else {
+            return Err(format!("page {page_id} is latched, try again"));
+        };
+        if page.pin_count > 0 {
+            return Err(format!("page {page_id} is pinned {} times", page.pin_count));
+        }
+
+        let flushed = page.is_dirty;
+        if flushed {
+            self.pool
+                .flush_frame(frame_id, &page)
+                .map_err(|err| format!("fail to flush page {page_id}: {err}"))?;
+        }
+        self.pool.mark_clean(&mut page);
+        page.deallocate();
+        page_table.remove(&(self.file_id, page_id));
+        self.pool.replacer.pin(frame_id);
+        self.pool.free_list.lock().push(frame_id);
+        Ok(flushed)
+    }
+
+    /// State of the page in the buffer pool, or `None` if it isn't
+    /// cached. The page isn't loaded, so inspecting it doesn't change
+    /// what's cached.
+    pub fn inspect_page(&self, page_id: usize) -> Option<PageInfo> {
+        // Pinned pages are looked up before latching the page, as
+        // `pin_page` holds them while it waits for a frame to load into.
+        let pinned = self.pinned.lock().clone();
+        let frame_id = *self.pool.page_table.read().get(&(self.file_id, page_id))?;
+        let page = self.pool.pages[frame_id].read();
+        if !self.pool.holds(frame_id, self.file_id, &page, page_id) {
+            return None;
+        }
+
+        Some(page_info(frame_id, &page, &pinned))
+    }
+
+    /// State of the pages of the file in the buffer pool, by their frame.
+    pub fn cached_pages(&self) -> Vec<PageInfo> {
+        let pinned = self.pinned.lock().clone();
+        self.pool
+            .frames_of(self.file_id)
+            .into_iter()
+            .filter_map(|(frame_id, page_id)| {
+                let page = self.pool.pages[frame_id].read();
+                self.pool
+                    .holds(frame_id, self.file_id, &page, page_id)
+                    .then(|| page_info(frame_id, &page, &pinned))
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub fn disk_manager(&self) -> &DiskManager {
+        &self.disk_manager
+    }
+
+    pub fn set_max_dirty_percent(&self, percent: usize) {
+        self.max_dirty_percent
+            .store(percent.min(100), Ordering::Relaxed);
+    }
+
+    pub fn max_dirty_percent(&self) -> usize {
+        self.max_dirty_percent.load(Ordering::Relaxed)
+    }
+
+    /// Switch to a write-optimized mode for bulk loads, until `end_ingest`:
+    ///
+    /// - Leaves aren't merged when they underflow, so deletes never
+    ///   restructure the tree.
+    /// - Leaves filled by sequential inserts are packed instead of split
+    ///   evenly, see `InsertHistory::ingest_split_ratio`.
+    /// - Writers over the dirty page limit flush a larger batch of pages
+    ///   at once.
+    ///
+    /// TRADEOFF: Secondary indexes are still maintained on every insert,
+    /// rather than built in one pass by `end_ingest`. They're only kept in
+    /// memory, see `SecondaryIndex`, so maintaining them costs no page
+    /// writes, and a unique index has to reject a duplicate when it's
+    /// inserted rather than once the rows are already in the tree.
+    pub fn begin_ingest(&self) {
+        self.ingesting.store(true, Ordering::Relaxed);
+    }
+
+    /// Leave the ingestion mode and merge the leaves of the tree that
+    /// underflowed in the meantime. Return the number of leaves merged.
+    pub fn end_ingest(&self, root_page_num: usize) -> Result<usize, DbError> {
+        self.ingesting.store(false, Ordering::Relaxed);
+        self.consolidate(root_page_num)
+    }
+
+    pub fn is_ingesting(&self) -> bool {
+        self.ingesting.load(Ordering::Relaxed)
+    }
+
+    /// Number of frames that are pinned. A frame that is latched is
+    /// counted as pinned, as it's being used by someone.
+    pub fn pinned_pages(&self) -> usize {
+        self.pool
+            .pages
+            .iter()
+            .filter(|page| page.try_read().is_none_or(|page| page.pin_count > 0))
+            .count()
+    }
+
+    /// Return an error if every frame is pinned, where new requests have
+    /// to wait until a frame is unpinned.
+    pub fn check_pool(&self) -> Result<(), String> {
+        let pinned_pages = self.pinned_pages();
+        if pinned_pages == self.pool.size() {
+            return Err(format!(
+                "buffer pool exhausted: all {pinned_pages} frames are pinned"
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_disk(&self) -> Result<(), String> {
+        self.disk_manager
+            .check_writable()
+            .map_err(|err| format!("database file not writable: {err}"))
+    }
+
+    /// Number of dirty pages in the buffer pool, shared with the other
+    /// files of the pool.
+    pub fn dirty_pages(&self) -> usize {
+        self.pool.dirty_pages.load(Ordering::Relaxed)
+    }
+
+    pub fn dirty_ratio(&self) -> f64 {
+        self.dirty_pages() as f64 / self.pool.size() as f64
+    }
+
+    /// Number of pages flushed by writers to get back under the dirty
+    /// page limit, see `throttle_writes`.
+    pub fn throttled_flushes(&self) -> usize {
+        self.throttled_flushes.load(Ordering::Relaxed)
+    }
+
+    pub fn set_latch_protocol(&self, protocol: LatchProtocol) {
+        self.smo_latching
+            .store(protocol == LatchProtocol::SmoLatch, Ordering::Relaxed);
+    }
+
+    pub fn latch_protocol(&self) -> LatchProtocol {
+        if self.smo_latching.load(Ordering::Relaxed) {
+            LatchProtocol::SmoLatch
+        } else {
+            LatchProtocol::Crabbing
+        }
+    }
+
+    /// Number of writes that backed off to split or merge under the
+    /// exclusive SMO latch, see `LatchProtocol::SmoLatch`.
+    pub fn smo_retries(&self) -> usize {
+        self.smo_retries.load(Ordering::Relaxed)
+    }
+
+    /// Number of pages modified so far, which only grows, so a reader can
+    /// tell if any page of the file changed between two reads, e.g. the
+    /// batches of a `ScanCursor`.
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    // Run a write with the latch protocol, where the write starts from
+    // the given scope and returns `Smo::Needed` if it backs off from a
+    // split or merge, which only happens in an optimistic scope.
+    fn with_latch_protocol<'a, T, F>(&'a self, write: F) -> Option<T>
+    where
+        F: Fn(PinScope<'a>) -> Option<Smo<T>>,
+    {
+        if self.latch_protocol() == LatchProtocol::Crabbing {
+            return write(PinScope::new(self)).map(Smo::done);
+        }
+
+        let smo_latch = self.smo_latch.read();
+        match write(PinScope::optimistic(self)) {
+            Some(Smo::Needed) => {}
+            result => return result.map(Smo::done),
+        }
+        drop(smo_latch);
+
+        self.smo_retries.fetch_add(1, Ordering::Relaxed);
+        let _smo_latch = self.smo_latch.write();
+        write(PinScope::new(self)).map(Smo::done)
+    }
+
+    // Under write heavy bursts, the pool fills up with dirty pages and
+    // every eviction has to synchronously flush the victim first, which
+    // cause latency spikes for whoever need a free frame.
+    //
+    // So before modifying the tree, writers check the dirty ratio and
+    // flush unpinned dirty pages until it's back under the limit.
+    //
+    // TRADEOFF: We flush in the writer thread instead of a background
+    // flusher. This slows down the writer that cross the limit, but it
+    // is simpler and naturally throttle writers to the disk speed.
+    fn throttle_writes(&self) -> Result<(), DbError> {
+        let max_dirty_pages = self.pool.size() * self.max_dirty_percent() / 100;
+        let dirty_pages = self.dirty_pages();
+        if dirty_pages <= max_dirty_pages {
+            return Ok(());
+        }
+
+        let mut pages_to_flush = dirty_pages - max_dirty_pages;
+        if self.is_ingesting() {
+            pages_to_flush += self.pool.size() * INGEST_FLUSH_BATCH_PERCENT / 100;
+        }
+        for (frame_id, page) in self.pool.pages.iter().enumerate() {
+            if pages_to_flush == 0 {
+                break;
+            }
+
+            // Never wait for a latch here, the page is in use anyway.
+            if let Some(mut page) = page.try_write() {
+                if page.is_dirty && page.pin_count == 0 && page.has_node() {
+                    self.pool.flush_frame(frame_id, &page)?;
+                    self.pool.mark_clean(&mut page);
+
+                    pages_to_flush -= 1;
+                    self.throttled_flushes.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the number of levels of the tree, where a tree with only
+    /// the root leaf has a height of 1.
+    ///
+    /// The height is computed by walking down the leftmost path on first
+    /// use and then maintained on root splits and root collapses.
+    pub fn tree_height(&self, root_page_num: usize) -> Result<usize, DbError> {
+        if let Some(&height) = self.tree_heights.lock().get(&root_page_num) {
+            return Ok(height);
+        }
+
+        let mut height = 1;
+        let mut page_num = root_page_num;
+        loop {
+            let page = self.fetch_read_page_with_retry(page_num)?;
+            let node = page.node().unwrap();
+            if node.node_type == NodeType::Leaf {
+                self.unpin_page_with_read_guard(page, false);
+                break;
+            }
+
+            page_num = node.search(0).unwrap();
+            self.unpin_page_with_read_guard(page, false);
+            height += 1;
+        }
+
+        // Another writer could have computed it while we are walking.
+        Ok(*self
+            .tree_heights
+            .lock()
+            .entry(root_page_num)
+            .or_insert(height))
+    }
+
+    /// Return the minimum number of frames needed to modify a tree of
+    /// the given height.
+    ///
+    /// In the worst case, a write keeps every page from the root to the
+    /// leaf pinned while allocating the new sibling of the split leaf.
+    /// Ancestors are only split after their children are unpinned, while
+    /// splitting the root keeps the root and 2 new pages pinned as the
+    /// parent offset of their children are updated.
+    pub fn min_pool_size(height: usize) -> usize {
+        (height + 1).max(4)
+    }
+
+    // Check if the buffer pool is large enough to modify the tree.
+    //
+    // Without it, a write that needs more frames than the pool has would
+    // keep retrying until it starves, as the frames it's waiting for are
+    // pinned by itself.
+    fn check_pool_size(&self, root_page_num: usize) -> Result<(), DbError> {
+        let pool_size = self.pool.size();
+        let height = self.tree_height(root_page_num)?;
+        let min_pool_size = Pager::min_pool_size(height);
+
+        if pool_size < min_pool_size {
+            return Err(DbError::PoolTooSmall(format!(
+                "buffer pool too small: a tree of height {height} needs at least {min_pool_size} pages, but the pool only has {pool_size}"
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Keep the tree height up to date on root splits and collapses. It's
+    // left alone if it's not computed yet, as it will be computed from
+    // the tree on first use.
+    fn update_tree_height(&self, root_page_num: usize, grow: bool) {
+        if let Some(height) = self.tree_heights.lock().get_mut(&root_page_num) {
+            if grow {
+                *height += 1;
+            } else {
+                *height -= 1;
+            }
+        }
+    }
+
+    pub fn delete_page_with_write_guard(&self, mut page: RwLockWriteGuard<Page>) -> bool {
+        let page_id = page.page_id.unwrap();
+
+        assert!(page.pin_count >= 1);
+        // unpin the page first.
+        //
+        // no need to call replacer here as to delete a page
+        // require a thread to hold a page, which means it's pinned
+        // and shouldn't be in a replacer.
+        page.pin_count -= 1;
+
+        let mut page_table = self.pool.page_table.write();
+        if let Some(&frame_id) = page_table.get(&(self.file_id, page_id)) {
+            if page.pin_count == 0 {
+                // The page is reused from now on, while snapshots may
+                // still read it.
+                self.preserve_for_snapshots(page_id, &page);
+                self.pool.mark_clean(&mut page);
+                page.deallocate();
+                page_table.remove(&(self.file_id, page_id));
+                drop(page_table);
+                drop(page);
+
+                self.pool.free_list.lock().push(frame_id);
+                self.freed_pages.lock().push(page_id);
+
+                true
+            } else {
+                drop(page);
+                drop(page_table);
+                false
+            }
+        } else {
+            drop(page);
+            drop(page_table);
+            true
+        }
+    }
+
+    pub fn unpin_page_with_write_guard(&self, mut page: RwLockWriteGuard<Page>, is_dirty: bool) {
+        if is_dirty {
+            self.epoch.fetch_add(1, Ordering::AcqRel);
+
+            // TRADEOFF: The page gets the last LSN of the log rather than
+            // the LSN of the record that changed it, which also covers the
+            // pages changed by a split or merge, at the cost of flushing
+            // more of the log than needed before writing it.
+            if let Some(lsn) = self.log_manager.as_ref().and_then(|log| log.last_lsn()) {
+                page.lsn = page.lsn.max(lsn);
+            }
+        }
+
+        let page_table = self.pool.page_table.read();
+        if let Some(&frame_id) = page_table.get(&(self.file_id, page.page_id.unwrap())) {
+            if is_dirty {
+                self.pool.mark_dirty(&mut page);
             }
             page.pin_count -= 1;
 
             if page.pin_count == 0 {
-                self.replacer.unpin(frame_id);
+                self.pool.replacer.unpin(frame_id, self.file_id);
             };
 
             drop(page_table);
@@ -300,80 +1783,633 @@ impl Pager {
         }
     }
 
-    pub fn unpin_page_with_read_guard(
-        &self,
-        page: RwLockUpgradableReadGuard<Page>,
-        is_dirty: bool,
-    ) {
-        let page_id = page.page_id.unwrap();
-        let page_table = self.page_table.read();
-        if let Some(&frame_id) = page_table.get(&page_id) {
-            let mut page = RwLockUpgradableReadGuard::upgrade(page);
-            if !page.is_dirty {
-                page.is_dirty = is_dirty;
+    pub fn unpin_page_with_read_guard(
+        &self,
+        page: RwLockUpgradableReadGuard<Page>,
+        is_dirty: bool,
+    ) {
+        self.unpin_read_page(page, is_dirty, false);
+    }
+
+    /// Unpin a page that is read with the given access pattern.
+    ///
+    /// Pages read by a sequential scan are unlikely to be read again soon,
+    /// so they are not promoted in the replacer. This way, a one-off full
+    /// scan doesn't evict the pages that are frequently accessed, e.g.
+    /// the internal nodes.
+    ///
+    /// TRADEOFF: We don't prefetch the next leaf for sequential scans, as
+    /// reading pages is synchronous and it would only move the IO earlier.
+    pub fn unpin_page_with_hint(
+        &self,
+        page: RwLockUpgradableReadGuard<Page>,
+        access_pattern: AccessPattern,
+    ) {
+        let cold = access_pattern == AccessPattern::Sequential;
+        self.unpin_read_page(page, false, cold);
+    }
+
+    fn unpin_read_page(&self, page: RwLockUpgradableReadGuard<Page>, is_dirty: bool, cold: bool) {
+        let page_id = page.page_id.unwrap();
+        let page_table = self.pool.page_table.read();
+        if let Some(&frame_id) = page_table.get(&(self.file_id, page_id)) {
+            let mut page = RwLockUpgradableReadGuard::upgrade(page);
+            if is_dirty {
+                self.pool.mark_dirty(&mut page);
+            }
+            page.pin_count -= 1;
+
+            if page.pin_count == 0 {
+                if cold {
+                    self.pool.replacer.unpin_cold(frame_id, self.file_id);
+                } else {
+                    self.pool.replacer.unpin(frame_id, self.file_id);
+                }
+            };
+
+            drop(page_table);
+            drop(page);
+        } else {
+            drop(page_table);
+            let duration = std::time::Duration::from_millis(SLEEP_MS);
+            std::thread::sleep(duration);
+
+            self.unpin_read_page(page, is_dirty, cold);
+        }
+    }
+
+    /// Call `f` with every row in key order.
+    pub fn for_each_row<F>(&self, root_page_num: usize, mut f: F)
+    where
+        F: FnMut(Row),
+    {
+        let access_pattern = AccessPattern::Range;
+        let mut page = self.search_page(root_page_num, 0);
+        let mut last_key = None;
+        while let Some((leaf, cell_num)) =
+            self.leaf_after(root_page_num, page, last_key, access_pattern)
+        {
+            let node = leaf.node().unwrap();
+            for cell_num in cell_num..node.num_of_cells as usize {
+                let row = self.decode_row(node.get(cell_num));
+                last_key = Some(row.id);
+                f(row);
+            }
+            page = leaf;
+        }
+    }
+
+    /// Return the leaf holding the first row after the last key a scan
+    /// returned, or the first row if it hasn't returned any, along with
+    /// the cell of the row, starting from the given leaf and following
+    /// the next leaf offsets. Return `None` past the last row.
+    ///
+    /// Leaves are latched hand-over-hand: a leaf is only released once
+    /// the next one is latched, so it can't be split, or the next leaf
+    /// merged away, while the scan moves to it.
+    ///
+    /// The next leaf is only tried once though, as a writer holding it
+    /// might wait for this leaf to merge them, see
+    /// `concurrent_merge_leaf_nodes`. The scan then releases the leaf and
+    /// searches the leaf of the last key from the root instead, as it does
+    /// when the leaf was merged into its sibling since it was read.
+    pub fn leaf_after<'a>(
+        &'a self,
+        root_page_num: usize,
+        mut page: RwLockUpgradableReadGuard<'a, Page>,
+        last_key: Option<u32>,
+        access_pattern: AccessPattern,
+    ) -> Option<(RwLockUpgradableReadGuard<'a, Page>, usize)> {
+        loop {
+            let Some(node) = page.node().filter(|node| node.node_type == NodeType::Leaf) else {
+                self.unpin_page_with_hint(page, access_pattern);
+                page = self.search_page(root_page_num, last_key.unwrap_or(0));
+                continue;
+            };
+
+            let cell_num = match last_key {
+                Some(key) => node.search(key).map_or_else(|cell_num| cell_num, |i| i + 1),
+                None => 0,
+            };
+            if cell_num < node.num_of_cells as usize {
+                return Some((page, cell_num));
+            }
+
+            let next_leaf_offset = node.next_leaf_offset;
+            if next_leaf_offset == NULL_PAGE {
+                self.unpin_page_with_hint(page, access_pattern);
+                return None;
+            }
+            page = match self.fetch_read_page_guard(next_leaf_offset as usize) {
+                Ok(next_page) => {
+                    self.unpin_page_with_hint(page, access_pattern);
+                    next_page
+                }
+                Err(_) => {
+                    self.unpin_page_with_hint(page, access_pattern);
+                    std::thread::sleep(std::time::Duration::from_millis(SLEEP_MS));
+                    self.search_page(root_page_num, last_key.unwrap_or(0))
+                }
+            };
+        }
+    }
+
+    /// Return the rows with keys in `[start_key, end_key)`, in key order,
+    /// or up to the last row if there is no end key.
+    ///
+    /// Only the leaf holding the start key and the leaves after it, up to
+    /// the end key, are read, by following their next leaf offset.
+    pub fn scan_range(
+        &self,
+        root_page_num: usize,
+        start_key: u32,
+        end_key: Option<u32>,
+    ) -> Vec<Row> {
+        let access_pattern = AccessPattern::Range;
+        let mut rows = Vec::new();
+        let mut page = self.search_page(root_page_num, start_key);
+        let mut last_key = start_key.checked_sub(1);
+        while let Some((leaf, cell_num)) =
+            self.leaf_after(root_page_num, page, last_key, access_pattern)
+        {
+            let node = leaf.node().unwrap();
+            for cell_num in cell_num..node.num_of_cells as usize {
+                let row = self.decode_row(node.get(cell_num));
+                if end_key.is_some_and(|end_key| row.id >= end_key) {
+                    self.unpin_page_with_hint(leaf, access_pattern);
+                    return rows;
+                }
+                last_key = Some(row.id);
+                rows.push(row);
+            }
+            page = leaf;
+        }
+        rows
+    }
+
+    /// Return up to `limit` rows with keys below the end key, or every key
+    /// if there is none, from the largest key down.
+    ///
+    /// Leaves are only linked forward, so the tree is walked from the root
+    /// instead, visiting the children left of the end key from the right.
+    ///
+    /// TRADEOFF: Nodes are read one at a time without holding their
+    /// parents, so a leaf split while the scan is on its left could move
+    /// rows to a sibling the scan has already passed. A scan resumed by
+    /// key, see `ScanCursor`, gives the same guarantee anyway.
+    pub fn scan_backward(
+        &self,
+        root_page_num: usize,
+        end_key: Option<u32>,
+        limit: usize,
+    ) -> Result<Vec<Row>, DbError> {
+        let mut rows = Vec::new();
+        let last_key = match end_key {
+            Some(0) => return Ok(rows),
+            Some(end_key) => end_key - 1,
+            None => MAX_ID,
+        };
+
+        let mut pages = vec![root_page_num];
+        while let Some(page_num) = pages.pop() {
+            if rows.len() >= limit {
+                break;
+            }
+
+            let page = self.fetch_read_page_with_retry(page_num)?;
+            let node = page.node().unwrap();
+            if node.node_type == NodeType::Internal {
+                let last_child = node.internal_search(last_key);
+                for i in 0..=last_child {
+                    pages.push(match node.internal_cells.get(i) {
+                        Some(cell) => cell.child_pointer() as usize,
+                        None => node.right_child_offset as usize,
+                    });
+                }
+            } else {
+                let end = match node.search(last_key) {
+                    Ok(cell_num) => cell_num + 1,
+                    Err(cell_num) => cell_num,
+                };
+                rows.extend(
+                    (0..end)
+                        .rev()
+                        .take(limit - rows.len())
+                        .map(|i| self.decode_row(node.get(i))),
+                );
+            }
+            self.unpin_page_with_hint(page, AccessPattern::Range);
+        }
+
+        Ok(rows)
+    }
+
+    /// Partition the key space into half open ranges `[start, end)` by the
+    /// keys of the root node, so each range can be scanned independently.
+    ///
+    /// The last range is unbounded. A root that is a leaf is a single range.
+    pub fn key_ranges(&self, root_page_num: usize) -> Result<Vec<(u32, Option<u32>)>, DbError> {
+        let page = self.fetch_read_page_with_retry(root_page_num)?;
+        let ranges = Pager::node_key_ranges(page.node().unwrap());
+        self.unpin_page_with_read_guard(page, false);
+        Ok(ranges)
+    }
+
+    fn node_key_ranges(node: &Node) -> Vec<(u32, Option<u32>)> {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        if node.node_type == NodeType::Internal {
+            // Keys of an internal node is the max key of its left child.
+            for cell in &node.internal_cells[..node.num_of_cells as usize] {
+                let end = cell.key() + 1;
+                ranges.push((start, Some(end)));
+                start = end;
+            }
+        }
+        ranges.push((start, None));
+        ranges
+    }
+
+    /// Take a snapshot of the tree, which can be read through
+    /// `read_snapshot_node` without blocking writers.
+    ///
+    /// TRADEOFF: Since we don't have a WAL yet, the snapshot is taken at a
+    /// checkpoint instead of a LSN, where every page is flushed to disk
+    /// while holding the tree latch exclusively. Taking a snapshot is
+    /// expensive, but reading from it isn't.
+    pub fn snapshot(&self) -> Result<Arc<Snapshot>, std::io::Error> {
+        let _tree_latch = self.tree_latch.write();
+
+        let snapshot = Arc::new(Snapshot::new(self.next_page_id.load(Ordering::Acquire)));
+        let mut snapshots = self.snapshots.write();
+        snapshots.retain(|snapshot| snapshot.strong_count() > 0);
+        snapshots.push(Arc::downgrade(&snapshot));
+        drop(snapshots);
+
+        self.flush_all_pages()?;
+        Ok(snapshot)
+    }
+
+    // Copy the page aside for every snapshot that is still being read.
+    fn preserve_for_snapshots(&self, page_id: usize, page: &Page) {
+        let snapshots = self.snapshots.read();
+        for snapshot in snapshots.iter().filter_map(Weak::upgrade) {
+            snapshot.preserve(page_id, page);
+        }
+    }
+
+    /// Read the node of the page as it is when the snapshot is taken.
+    pub fn read_snapshot_node(&self, snapshot: &Snapshot, page_id: usize) -> Result<Node, DbError> {
+        let bytes = match snapshot.get(page_id) {
+            Some(bytes) => bytes,
+            None => {
+                let bytes = self
+                    .disk_manager
+                    .read_page(page_id)
+                    .map_err(|err| DbError::PageCorrupted {
+                        page_id,
+                        reason: err.to_string(),
+                    })?
+                    .to_vec();
+
+                // The page could be modified and flushed while we are reading
+                // it. Since it's always copied aside before it's modified, the
+                // copy is there if what we read is newer than the snapshot.
+                snapshot.get(page_id).unwrap_or(bytes)
+            }
+        };
+
+        let mut page = Page::from_bytes(&bytes)
+            .map_err(|reason| DbError::PageCorrupted { page_id, reason })?;
+        Ok(page.take_node().unwrap())
+    }
+
+    /// Same as `key_ranges`, but read from the snapshot.
+    pub fn snapshot_key_ranges(
+        &self,
+        snapshot: &Snapshot,
+        root_page_num: usize,
+    ) -> Result<Vec<(u32, Option<u32>)>, DbError> {
+        let node = self.read_snapshot_node(snapshot, root_page_num)?;
+        Ok(Pager::node_key_ranges(&node))
+    }
+
+    /// Call `f` with every row of the snapshot where `start <= row.id < end`,
+    /// in key order.
+    pub fn for_each_in_snapshot<F>(
+        &self,
+        snapshot: &Snapshot,
+        root_page_num: usize,
+        start: u32,
+        end: Option<u32>,
+        mut f: F,
+    ) -> Result<(), DbError>
+    where
+        F: FnMut(Row),
+    {
+        let mut node = self.read_snapshot_node(snapshot, root_page_num)?;
+        while node.node_type == NodeType::Internal {
+            let page_num = node.search(start).unwrap();
+            node = self.read_snapshot_node(snapshot, page_num)?;
+        }
+
+        loop {
+            for i in 0..node.num_of_cells as usize {
+                let row = self.decode_row(node.get(i));
+                if row.id < start {
+                    continue;
+                }
+
+                if end.is_some_and(|end| row.id >= end) {
+                    return Ok(());
+                }
+
+                f(row);
+            }
+
+            if node.next_leaf_offset == NULL_PAGE {
+                return Ok(());
+            }
+
+            node = self.read_snapshot_node(snapshot, node.next_leaf_offset as usize)?;
+        }
+    }
+
+    /// Verify the checksum of every row, and return the page id and key
+    /// of the rows that don't match.
+    pub fn scrub(&self, root_page_num: usize) -> Result<Vec<(usize, u32)>, DbError> {
+        let mut mismatches = Vec::new();
+        let mut page = self.search_page(root_page_num, 0);
+
+        loop {
+            let page_id = page.page_id.unwrap();
+            let node = page.node().unwrap();
+            for key in node.corrupted_keys() {
+                mismatches.push((page_id, key));
+            }
+
+            let next_leaf_offset = node.next_leaf_offset as usize;
+            self.unpin_page_with_read_guard(page, false);
+            if next_leaf_offset == NULL_PAGE as usize {
+                return Ok(mismatches);
+            }
+
+            page = self.fetch_read_page_with_retry(next_leaf_offset)?;
+        }
+    }
+
+    /// Check the file is made of whole pages, i.e. the last page isn't
+    /// partially written.
+    pub fn check_file(&self) -> Result<(), String> {
+        let len = self.disk_manager.file_len;
+        if !len.is_multiple_of(PAGE_SIZE) {
+            return Err(format!(
+                "page {} is partially written, as the file is {len} bytes, restore the file from a dump",
+                len / PAGE_SIZE
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check the headers and checksums of the pages of the tree, and that
+    /// its nodes are linked and ordered as expected, returning a problem
+    /// per page that isn't.
+    ///
+    /// Pages are read breadth first up to `max_pages`, so the upper levels
+    /// are always checked, and only some of the leaves of a large tree.
+    /// They are read from the file rather than the buffer pool, as it's
+    /// meant to run on open, before any page is modified.
+    pub fn check_tree(&self, root_page_num: usize, max_pages: usize) -> Vec<String> {
+        let num_of_pages = self.disk_manager.file_len / PAGE_SIZE;
+        let mut problems = Vec::new();
+        // The root of an empty file is only created once it's first read.
+        if num_of_pages == 0 && root_page_num == 0 {
+            return problems;
+        }
+
+        // Page to check, with its parent, the range of keys it can hold,
+        // excluding the lower bound, and its depth.
+        let mut pages = VecDeque::from([(root_page_num, None, None, None, 0)]);
+        let mut visited = std::collections::HashSet::new();
+        let mut leaf_depth = None;
+        while let Some((page_id, parent, low, high, depth)) = pages.pop_front() {
+            if visited.len() == max_pages {
+                break;
+            }
+            if page_id >= num_of_pages {
+                let referrer = parent.map_or("catalog".to_string(), |p| format!("page {p}"));
+                problems.push(format!(
+                    "{referrer} points to page {page_id}, past the end of the file ({num_of_pages} pages)"
+                ));
+                continue;
+            }
+            if !visited.insert(page_id) {
+                problems.push(format!(
+                    "page {page_id} is reachable from more than one parent"
+                ));
+                continue;
             }
-            page.pin_count -= 1;
 
-            if page.pin_count == 0 {
-                self.replacer.unpin(frame_id);
+            let node = match self.read_checked_node(page_id) {
+                Ok(node) => node,
+                Err(problem) => {
+                    problems.push(problem);
+                    continue;
+                }
             };
 
-            drop(page_table);
-            drop(page);
-        } else {
-            drop(page_table);
-            let duration = std::time::Duration::from_millis(SLEEP_MS);
-            std::thread::sleep(duration);
+            if node.is_root != parent.is_none() {
+                problems.push(format!(
+                    "page {page_id} is {}marked as a root",
+                    if node.is_root { "" } else { "not " }
+                ));
+            }
+            if let Some(parent) = parent {
+                if node.parent_offset as usize != parent {
+                    problems.push(format!(
+                        "page {page_id} points to parent {}, but it's a child of page {parent}",
+                        node.parent_offset
+                    ));
+                }
+            }
+
+            let keys: Vec<u32> = match node.node_type {
+                NodeType::Leaf => node.cells.iter().map(|cell| cell.key()).collect(),
+                NodeType::Internal => node.internal_cells.iter().map(|cell| cell.key()).collect(),
+            };
+            if keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+                problems.push(format!("keys of page {page_id} are out of order"));
+            }
+            if let Some(key) = keys.iter().find(|key| {
+                low.is_some_and(|low| **key <= low) || high.is_some_and(|high| **key > high)
+            }) {
+                problems.push(format!(
+                    "page {page_id} has key {key}, outside of the range of its parent"
+                ));
+            }
 
-            self.unpin_page_with_read_guard(page, is_dirty);
+            match node.node_type {
+                NodeType::Leaf => match leaf_depth {
+                    Some(leaf_depth) if leaf_depth != depth => problems.push(format!(
+                        "leaf page {page_id} is at depth {depth}, but other leaves are at depth {leaf_depth}"
+                    )),
+                    _ => leaf_depth = Some(depth),
+                },
+                NodeType::Internal => {
+                    let mut low = low;
+                    for cell in &node.internal_cells {
+                        let child = cell.child_pointer() as usize;
+                        pages.push_back((child, Some(page_id), low, Some(cell.key()), depth + 1));
+                        low = Some(cell.key());
+                    }
+                    let child = node.right_child_offset as usize;
+                    pages.push_back((child, Some(page_id), low, high, depth + 1));
+                }
+            }
         }
+
+        problems
     }
 
-    pub fn select(&self, root_page_num: usize) -> String {
-        let mut output = String::new();
+    // Read the node of the page from the file, once its header and
+    // checksum are known to be valid.
+    fn read_checked_node(&self, page_id: usize) -> Result<Node, String> {
+        let bytes = self
+            .disk_manager
+            .read_page(page_id)
+            .map_err(|err| format!("page {page_id} can't be read: {err}"))?;
+        let header = PageHeader::decode(&bytes[..PAGE_HEADER_BYTES]).map_err(|err| {
+            format!("page {page_id} has an invalid header: {err}, restore the file from a dump")
+        })?;
+
+        if header.page_id != page_id {
+            return Err(format!(
+                "page {page_id} has the header of page {}, restore the file from a dump",
+                header.page_id
+            ));
+        }
+        if !Page::verify_checksum(&bytes) {
+            return Err(format!(
+                "page {page_id} checksum mismatch, run .scrub to find the corrupted rows"
+            ));
+        }
+        if !matches!(header.page_type, PageType::Internal | PageType::Leaf) {
+            return Err(format!(
+                "page {page_id} is a {:?} page, not a node of the tree",
+                header.page_type
+            ));
+        }
+
+        Ok(Node::new_from_bytes(
+            &bytes[PAGE_HEADER_BYTES..],
+            header.version,
+        ))
+    }
 
+    /// Walk through every leaf and sum up the bytes used by their cells.
+    pub fn occupancy(&self, root_page_num: usize) -> Result<Occupancy, DbError> {
+        let mut occupancy = Occupancy {
+            leaves: 0,
+            used_bytes: 0,
+        };
         let mut page = self.search_page(root_page_num, 0);
 
-        let mut node = page.node.as_ref().unwrap();
-        assert_eq!(node.node_type, NodeType::Leaf);
+        loop {
+            let node = page.node().unwrap();
+            occupancy.leaves += 1;
+            occupancy.used_bytes += node.used_space();
 
-        if node.num_of_cells == 0 {
+            let next_leaf_offset = node.next_leaf_offset as usize;
             self.unpin_page_with_read_guard(page, false);
-            return output;
-        };
+            if next_leaf_offset == NULL_PAGE as usize {
+                return Ok(occupancy);
+            }
+
+            page = self.fetch_read_page_with_retry(next_leaf_offset)?;
+        }
+    }
 
+    /// Merge the leaves of the tree that underflow, e.g. the ones left by
+    /// deletes while ingesting, the same way a delete would. Return the
+    /// number of leaves merged away.
+    ///
+    /// TRADEOFF: Empty leaves are left as is, as there's no key to find
+    /// them by. They're reused by the inserts going there later.
+    pub fn consolidate(&self, root_page_num: usize) -> Result<usize, DbError> {
+        let leaves = self.occupancy(root_page_num)?.leaves;
+
+        // The first key of every leaf to merge, collected upfront, as the
+        // leaves change under us while merging.
+        let mut keys = Vec::new();
+        let mut page = self.search_page(root_page_num, 0);
         loop {
-            for i in 0..node.num_of_cells as usize {
-                let row = node.get(i);
-                output.push_str(&row.to_string());
-                output.push('\n');
+            let node = page.node().unwrap();
+            if !node.is_root && node.num_of_cells > 0 && node.is_underflow() {
+                keys.push(node.cells[0].key());
             }
 
-            if node.next_leaf_offset == 0 {
-                self.unpin_page_with_read_guard(page, false);
+            let next_leaf_offset = node.next_leaf_offset as usize;
+            self.unpin_page_with_read_guard(page, false);
+            if next_leaf_offset == NULL_PAGE as usize {
                 break;
-            } else {
-                let page_num = node.next_leaf_offset as usize;
-                self.unpin_page_with_read_guard(page, false);
+            }
+            page = self.fetch_read_page_with_retry(next_leaf_offset)?;
+        }
 
-                page = self.fetch_read_page_with_retry(page_num);
-                node = page.node.as_ref().unwrap();
+        for key in keys {
+            let _tree_latch = self.tree_latch.read();
+            if self.check_pool_size(root_page_num).is_err() {
+                break;
             }
+            self.with_latch_protocol(|scope| {
+                self.search_and_then(
+                    scope,
+                    root_page_num,
+                    key,
+                    Operation::Delete,
+                    |_cursor, parent_page_guards, page| {
+                        let node = page.node().unwrap();
+                        if node.is_root || !node.is_underflow() {
+                            self.unpin_page_with_write_guard(page, false);
+                            return Some(Smo::Done(Ok(())));
+                        }
+                        if parent_page_guards.is_optimistic() {
+                            self.unpin_page_with_write_guard(page, false);
+                            return Some(Smo::Needed);
+                        }
+
+                        Some(Smo::Done(
+                            self.concurrent_maybe_merge_nodes(page, parent_page_guards),
+                        ))
+                    },
+                )
+            })
+            .transpose()?;
         }
 
-        output
+        Ok(leaves.saturating_sub(self.occupancy(root_page_num)?.leaves))
+    }
+
+    fn search_page(&self, root_page_num: usize, key: u32) -> RwLockUpgradableReadGuard<'_, Page> {
+        self.search_page_from(root_page_num, root_page_num, key)
     }
 
-    fn search_page(&self, page_num: usize, key: u32) -> RwLockUpgradableReadGuard<Page> {
+    fn search_page_from(
+        &self,
+        root_page_num: usize,
+        page_num: usize,
+        key: u32,
+    ) -> RwLockUpgradableReadGuard<'_, Page> {
         match self.fetch_read_page_guard(page_num) {
             Err(_) => {
                 let duration = std::time::Duration::from_millis(SLEEP_MS);
                 std::thread::sleep(duration);
 
-                self.search_page(0, key)
+                self.search_page(root_page_num, key)
             }
             Ok(page) => {
-                let node = page.node.as_ref().unwrap();
+                let node = page.node().unwrap();
 
                 if node.node_type == NodeType::Leaf {
                     return page;
@@ -381,7 +2417,7 @@ impl Pager {
 
                 let next_page_num = node.search(key).unwrap();
                 self.unpin_page_with_read_guard(page, false);
-                self.search_page(next_page_num, key)
+                self.search_page_from(root_page_num, next_page_num, key)
             }
         }
     }
@@ -391,34 +2427,34 @@ impl Pager {
         page_num: usize,
         parent_page_guard: Option<RwLockUpgradableReadGuard<Page>>,
         key: u32,
-    ) -> String {
-        self.find_with_retry(page_num, parent_page_guard, key, MAX_RETRY)
+    ) -> Result<Option<Row>, DbError> {
+        self.find_with_retry(page_num, page_num, parent_page_guard, key, MAX_RETRY)
     }
 
     pub fn find_with_retry(
         &self,
+        root_page_num: usize,
         page_num: usize,
         parent_page_guard: Option<RwLockUpgradableReadGuard<Page>>,
         key: u32,
         retry: usize,
-    ) -> String {
+    ) -> Result<Option<Row>, DbError> {
         match self.fetch_read_page_guard(page_num) {
-            Err(_) => {
-                if retry == 0 {
-                    panic!("exceed max retry...");
-                }
-
+            Err(err) => {
                 if let Some(page) = parent_page_guard {
                     self.unpin_page_with_read_guard(page, false);
                 }
+                if retry == 0 || !err.is_transient() {
+                    return Err(err);
+                }
 
                 let duration = std::time::Duration::from_millis(SLEEP_MS);
                 std::thread::sleep(duration);
 
-                self.find_with_retry(0, None, key, retry - 1)
+                self.find_with_retry(root_page_num, root_page_num, None, key, retry - 1)
             }
             Ok(page) => {
-                let node = page.node.as_ref().unwrap();
+                let node = page.node().unwrap();
 
                 if let Some(page) = parent_page_guard {
                     self.unpin_page_with_read_guard(page, false);
@@ -427,17 +2463,17 @@ impl Pager {
                 if node.node_type == NodeType::Leaf {
                     match node.search(key) {
                         Ok(index) => {
-                            let row = node.get(index);
+                            let row = self.decode_row(node.get(index));
                             self.unpin_page_with_read_guard(page, false);
-                            format!("{}\n", row.to_string())
+                            Ok(Some(row))
                         }
                         Err(_index) => {
                             self.unpin_page_with_read_guard(page, false);
-                            "".to_string()
+                            Ok(None)
                         }
                     }
                 } else if let Ok(next_page_num) = node.search(key) {
-                    self.find_with_retry(next_page_num, Some(page), key, retry)
+                    self.find_with_retry(root_page_num, next_page_num, Some(page), key, retry)
                 } else {
                     unreachable!("this shouldn't happen!");
                 }
@@ -455,9 +2491,11 @@ impl Pager {
         min_key
     }
 
-    pub fn node_to_string(&self, node_index: usize, indent_level: usize) -> String {
-        let page = self.fetch_read_page_guard(node_index).unwrap();
-        let node = page.node.as_ref().unwrap();
+    fn node_to_string(&self, node_index: usize, indent_level: usize) -> Result<String, DbError> {
+        // Readers might be holding the page at the same time, so we
+        // retry instead of failing straight away.
+        let page = self.fetch_read_page_with_retry(node_index)?;
+        let node = page.node().unwrap();
         let mut result = String::new();
 
         if node.node_type == NodeType::Internal {
@@ -475,7 +2513,7 @@ impl Pager {
             self.unpin_page_with_read_guard(page, false);
 
             for (i, k) in child_pointers {
-                result += &self.node_to_string(i, indent_level + 1);
+                result += &self.node_to_string(i, indent_level + 1)?;
 
                 for _ in 0..indent_level + 1 {
                     result += "  ";
@@ -483,7 +2521,7 @@ impl Pager {
                 result += &format!("- key {}\n", k);
             }
 
-            result += &self.node_to_string(most_righ_child_index, indent_level + 1);
+            result += &self.node_to_string(most_righ_child_index, indent_level + 1)?;
         } else if node.node_type == NodeType::Leaf {
             for _ in 0..indent_level {
                 result += "  ";
@@ -500,89 +2538,100 @@ impl Pager {
             self.unpin_page_with_read_guard(page, false);
         }
 
-        result
+        Ok(result)
     }
 
-    pub fn to_tree_string(&self) -> String {
+    pub fn to_tree_string(&self) -> Result<String, DbError> {
+        // Block any writer from modifying the tree while we walk
+        // through it, so the tree printed is a consistent snapshot.
+        let _tree_latch = self.tree_latch.write();
+
         if self.next_page_id.load(Ordering::Acquire) != 0 {
             self.node_to_string(0, 0)
         } else {
-            "Empty tree...".to_string()
+            Ok("Empty tree...".to_string())
         }
     }
 
     // ---------------------
     // Concurrent Operations
     // ---------------------
-    fn retry<T, F>(&self, max_retry: usize, func: F) -> T
+    // Call `func` until it succeeds, as long as it fails with a transient
+    // error, up to `max_retry` times.
+    fn retry<T, F>(&self, max_retry: usize, func: F) -> Result<T, DbError>
     where
-        F: Fn() -> Result<T, PagerError>,
+        F: Fn() -> Result<T, DbError>,
     {
         match func() {
-            Err(_) => {
-                if max_retry == 0 {
-                    panic!("exceed max retry");
-                }
-
+            Err(err) if max_retry > 0 && err.is_transient() => {
                 let duration = std::time::Duration::from_millis(SLEEP_MS);
                 std::thread::sleep(duration);
 
                 self.retry(max_retry - 1, func)
             }
-            Ok(page) => page,
+            result => result,
         }
     }
 
-    fn fetch_write_page_guard_with_retry(&self, page_num: usize) -> RwLockWriteGuard<Page> {
+    pub fn fetch_write_page_guard_with_retry(
+        &self,
+        page_num: usize,
+    ) -> Result<RwLockWriteGuard<Page>, DbError> {
         self.retry(MAX_RETRY, || self.fetch_write_page_guard(page_num))
     }
 
-    pub fn fetch_read_page_with_retry(&self, page_num: usize) -> RwLockUpgradableReadGuard<Page> {
+    pub fn fetch_read_page_with_retry(
+        &self,
+        page_num: usize,
+    ) -> Result<RwLockUpgradableReadGuard<Page>, DbError> {
         self.retry(MAX_RETRY, || self.fetch_read_page_guard(page_num))
     }
 
     pub fn fetch_write_page_guard(
         &self,
         page_id: usize,
-    ) -> Result<RwLockWriteGuard<Page>, PagerError> {
-        let page_table = self.page_table.upgradable_read();
+    ) -> Result<RwLockWriteGuard<Page>, DbError> {
+        let page_table = self.pool.page_table.upgradable_read();
 
-        if let Some(&frame_id) = page_table.get(&page_id) {
-            let page = self.pages.get(frame_id).unwrap();
+        if let Some(&frame_id) = page_table.get(&(self.file_id, page_id)) {
+            let page = self.pool.pages.get(frame_id).unwrap();
 
             if let Some(mut page) = page.try_write() {
                 page.pin_count += 1;
-                self.replacer.pin(frame_id);
+                self.pool.replacer.pin(frame_id);
                 drop(page_table);
+                self.preserve_for_snapshots(page_id, &page);
 
                 return Ok(page);
             } else {
                 drop(page_table);
-                return Err(PagerError::FailToAcquirePageLock);
+                return Err(DbError::PageLatched(page_id));
             }
         }
 
-        self.replace_page(page_table, page_id)
+        self.replace_page(page_table, page_id).inspect(|page| {
+            self.preserve_for_snapshots(page_id, page);
+        })
     }
 
     pub fn fetch_read_page_guard(
         &self,
         page_id: usize,
-    ) -> Result<RwLockUpgradableReadGuard<Page>, PagerError> {
-        let page_table = self.page_table.upgradable_read();
+    ) -> Result<RwLockUpgradableReadGuard<Page>, DbError> {
+        let page_table = self.pool.page_table.upgradable_read();
 
-        if let Some(&frame_id) = page_table.get(&page_id) {
-            let page = self.pages.get(frame_id).unwrap();
+        if let Some(&frame_id) = page_table.get(&(self.file_id, page_id)) {
+            let page = self.pool.pages.get(frame_id).unwrap();
             if let Some(mut page) = page.try_write() {
                 page.pin_count += 1;
-                self.replacer.pin(frame_id);
+                self.pool.replacer.pin(frame_id);
                 drop(page_table);
 
                 let page = RwLockWriteGuard::downgrade_to_upgradable(page);
                 return Ok(page);
             } else {
                 drop(page_table);
-                return Err(PagerError::FailToAcquirePageLock);
+                return Err(DbError::PageLatched(page_id));
             }
         }
 
@@ -592,101 +2641,137 @@ impl Pager {
 
     fn replace_page(
         &self,
-        page_table: RwLockUpgradableReadGuard<HashMap<usize, usize>>,
+        page_table: RwLockUpgradableReadGuard<HashMap<(usize, usize), usize>>,
         page_id: usize,
-    ) -> Result<RwLockWriteGuard<Page>, PagerError> {
+    ) -> Result<RwLockWriteGuard<Page>, DbError> {
         let mut page_table = RwLockUpgradableReadGuard::upgrade(page_table);
-        let mut free_list = self.free_list.lock();
+        let mut free_list = self.pool.free_list.lock();
         let frame_id = free_list
             .pop()
-            .or_else(|| self.replacer.victim().map(|md| md.frame_id));
+            .or_else(|| self.pool.replacer.victim().map(|md| md.frame_id));
         drop(free_list);
 
         if let Some(frame_id) = frame_id {
-            let unlock_page = self.pages.get(frame_id).unwrap();
+            let unlock_page = self.pool.pages.get(frame_id).unwrap();
             let mut page = unlock_page.write();
 
-            // Update page table
-            page_table.retain(|_, &mut fid| fid != frame_id);
-            page_table.insert(page_id, frame_id);
-
             // Check if page is dirty. Flush page to disk
-            // if needed
+            // if needed, which could be a page of another file.
             if page.is_dirty {
-                let dirty_page_id = page.page_id.unwrap();
-                self.flush_write_page(dirty_page_id, &page);
+                self.pool.flush_victim(frame_id, &page)?;
             }
 
+            // Update page table
+            page_table.retain(|_, &mut fid| fid != frame_id);
+            page_table.insert((self.file_id, page_id), frame_id);
+            self.pool.owners[frame_id].store(self.file_id, Ordering::Release);
+
             // Reset page
-            page.is_dirty = false;
+            self.pool.mark_clean(&mut page);
             page.pin_count = 1;
             page.page_id = Some(page_id);
 
-            match self.disk_manager.read_page(page_id) {
-                Ok(bytes) => {
-                    let page_from_disk = Page::from_bytes(&bytes);
-                    page.lsn = page_from_disk.lsn;
-                    page.page_id = page_from_disk.page_id;
-                    page.node = page_from_disk.node;
+            let read = match self.disk_manager.read_page(page_id) {
+                // The node is only deserialized when it's first accessed.
+                Ok(bytes) => Page::from_bytes(&bytes)
+                    .map(|read| {
+                        *page = read;
+                        page.pin_count = 1;
+                    })
+                    .map_err(|reason| DbError::PageCorrupted { page_id, reason }),
+                // A page that's in the file, even partially, has to be read,
+                // instead of being silently replaced by an empty one.
+                Err(err) if page_id < self.disk_manager.file_len.div_ceil(PAGE_SIZE) => {
+                    Err(DbError::PageCorrupted {
+                        page_id,
+                        reason: err.to_string(),
+                    })
                 }
                 Err(_err) => {
-                    // This either mean the file is corrupted or is a partial page
-                    // or it's just a new file.
+                    // This is a new page past the end of the file, or the
+                    // root of a new file.
                     if page_id == 0 {
-                        page.node = Some(Node::root());
+                        page.set_node(Node::root());
                     }
 
                     self.next_page_id.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
                 }
             };
-            self.replacer.pin(frame_id);
+            if let Err(err) = read {
+                // Give the frame back, so the next fetch of the page reads
+                // it again instead of finding a frame without its node.
+                page_table.remove(&(self.file_id, page_id));
+                *page = Page::new(None);
+                self.pool.free_list.lock().push(frame_id);
+                return Err(err);
+            }
+            self.pool.replacer.pin(frame_id);
             drop(page_table);
 
             Ok(page)
         } else {
             drop(page_table);
-            Err(PagerError::NoFreePageAvailable)
+            Err(DbError::NoFreeFrame)
         }
     }
 
-    pub fn search_and_then<F, T>(
-        &self,
-        mut parent_page_guards: Vec<RwLockWriteGuard<Page>>,
+    pub fn search_and_then<'a, F, T>(
+        &'a self,
+        parent_page_guards: PinScope<'a>,
+        root_page_num: usize,
+        key: u32,
+        operation: Operation,
+        func: F,
+    ) -> Option<T>
+    where
+        F: FnOnce(Cursor, PinScope<'a>, RwLockWriteGuard<'a, Page>) -> Option<T>,
+    {
+        self.descend_and_then(
+            parent_page_guards,
+            root_page_num,
+            root_page_num,
+            key,
+            operation,
+            func,
+        )
+    }
+
+    fn descend_and_then<'a, F, T>(
+        &'a self,
+        mut parent_page_guards: PinScope<'a>,
+        root_page_num: usize,
         page_num: usize,
         key: u32,
         operation: Operation,
         func: F,
     ) -> Option<T>
     where
-        F: FnOnce(Cursor, Vec<RwLockWriteGuard<Page>>, RwLockWriteGuard<Page>) -> Option<T>,
+        F: FnOnce(Cursor, PinScope<'a>, RwLockWriteGuard<'a, Page>) -> Option<T>,
     {
         match self.fetch_write_page_guard(page_num) {
             Ok(page) => {
-                let node = page.node.as_ref().unwrap();
+                let node = page.node().unwrap();
                 let num_of_cells = node.num_of_cells as usize;
+                // For leaf nodes, we don't know the size of the cell being
+                // inserted or deleted yet, so assume the largest cell size.
                 let might_split_or_merge = if operation == Operation::Insert {
-                    let max_cell = if node.node_type == NodeType::Leaf {
-                        LEAF_NODE_MAX_CELLS
+                    if node.node_type == NodeType::Leaf {
+                        node.free_space() < LEAF_NODE_CELL_SIZE
                     } else {
-                        INTERNAL_NODE_MAX_CELLS
-                    };
-                    num_of_cells + 1 > max_cell
+                        num_of_cells + 1 > INTERNAL_NODE_MAX_CELLS
+                    }
                 } else if num_of_cells == 0 {
                     false
+                } else if node.node_type == NodeType::Leaf {
+                    node.used_space().saturating_sub(LEAF_NODE_CELL_SIZE)
+                        <= LEAF_NODE_SPACE_FOR_CELLS / 2
                 } else {
-                    let min_key_length = if node.node_type == NodeType::Leaf {
-                        LEAF_NODE_MAX_CELLS / 2
-                    } else {
-                        self.min_key(INTERNAL_NODE_MAX_CELLS)
-                    };
-
-                    num_of_cells - 1 <= min_key_length
+                    num_of_cells - 1 <= self.min_key(INTERNAL_NODE_MAX_CELLS)
                 };
 
                 if !might_split_or_merge {
-                    while let Some(page) = parent_page_guards.pop() {
-                        self.unpin_page_with_write_guard(page, false);
-                    }
+                    parent_page_guards.release();
                 }
                 if node.node_type == NodeType::Leaf {
                     match node.search(key) {
@@ -712,163 +2797,403 @@ impl Pager {
                         ),
                     }
                 } else if let Ok(next_page_num) = node.search(key) {
-                    let mut parent_page_guards = parent_page_guards;
-                    parent_page_guards.push(page);
-                    self.search_and_then(parent_page_guards, next_page_num, key, operation, func)
+                    parent_page_guards.push(page, false);
+                    self.descend_and_then(
+                        parent_page_guards,
+                        root_page_num,
+                        next_page_num,
+                        key,
+                        operation,
+                        func,
+                    )
                 } else {
                     unreachable!("this shouldn't happen!");
                 }
             }
             Err(_) => {
-                for page in parent_page_guards {
-                    self.unpin_page_with_write_guard(page, false);
-                }
+                drop(parent_page_guards);
 
                 let duration = std::time::Duration::from_millis(SLEEP_MS);
                 std::thread::sleep(duration);
 
                 // Restart at root
-                self.search_and_then(vec![], 0, key, operation, func)
+                self.search_and_then(PinScope::new(self), root_page_num, key, operation, func)
             }
         }
     }
 
     pub fn search(&self, root_page_num: usize, key: u32) -> Option<(usize, usize)> {
         self.search_and_then(
-            vec![],
+            PinScope::new(self),
             root_page_num,
             key,
             Operation::Insert,
-            |cursor, _parent_page_guards, _page| Some((cursor.page_num, cursor.cell_num)),
+            |cursor, _parent_page_guards, page| {
+                self.unpin_page_with_write_guard(page, false);
+                Some((cursor.page_num, cursor.cell_num))
+            },
         )
     }
 
-    pub fn insert_row(&self, root_page_num: usize, row: &Row) -> Result<(usize, usize), String> {
-        self.search_and_then(
-            vec![],
-            root_page_num,
-            row.id,
-            Operation::Insert,
-            |cursor, parent_page_guards, mut page| {
-                if cursor.key_existed {
-                    return None;
-                };
-
-                let node = page.node.as_ref().unwrap();
-                let num_of_cells = node.num_of_cells as usize;
-
-                // If num cell = MAX CELL, inserting into it cause it to overflow
-                // which mean we need to insert and split.
-                if num_of_cells >= LEAF_NODE_MAX_CELLS {
-                    self.concurrent_insert_and_split_node(parent_page_guards, page, &cursor, row);
-                } else {
-                    let node = page.node.as_mut().unwrap();
-                    node.insert(row, &cursor);
+    /// Insert the row, returning the page and cell it's inserted into.
+    pub fn insert_row(&self, root_page_num: usize, row: &Row) -> Result<(usize, usize), DbError> {
+        self.throttle_writes()?;
+        let _tree_latch = self.tree_latch.read();
+        self.check_pool_size(root_page_num)?;
+        self.with_latch_protocol(|scope| {
+            self.search_and_then(
+                scope,
+                root_page_num,
+                row.id,
+                Operation::Insert,
+                |cursor, parent_page_guards, mut page| {
+                    if cursor.key_existed {
+                        return None;
+                    };
 
-                    for page in parent_page_guards {
+                    let node = page.node().unwrap();
+
+                    // If there isn't enough free space for the row, inserting
+                    // into it cause it to overflow which mean we need to insert
+                    // and split.
+                    //
+                    // TRADEOFF: We are only splitting nodes when it's full.
+                    //
+                    // However, it could be done better by delaying splitting by moving cell
+                    // to sibling nodes when necessary, which is called load balancing.
+                    //
+                    // This result in higher occupancy and delayed of node splitting.
+                    if !node.can_fit(row) && parent_page_guards.is_optimistic() {
                         self.unpin_page_with_write_guard(page, false);
+                        return Some(Smo::Needed);
                     }
 
-                    self.unpin_page_with_write_guard(page, true);
-                }
+                    self.append_log(|| {
+                        LogRecord::row_change(0, None, LogRecordType::Insert, cursor.page_num, row)
+                    });
+                    if !node.can_fit(row) {
+                        if let Err(err) = self.concurrent_insert_and_split_node(
+                            parent_page_guards,
+                            page,
+                            &cursor,
+                            row,
+                        ) {
+                            return Some(Smo::Done(Err(err)));
+                        }
+                    } else {
+                        let node = page.node_mut().unwrap();
+                        node.insert(row, &cursor);
+                        self.unpin_page_with_write_guard(page, true);
+                    }
 
-                Some((cursor.page_num, cursor.cell_num))
-            },
-        )
-        .ok_or_else(|| "duplicate key".to_string())
+                    Some(Smo::Done(Ok((cursor.page_num, cursor.cell_num))))
+                },
+            )
+        })
+        .ok_or(DbError::DuplicateKey)?
     }
 
-    pub fn insert(&self, root_page_num: usize, row: &Row) -> Option<String> {
-        self.search_and_then(
-            vec![],
-            root_page_num,
-            row.id,
-            Operation::Insert,
-            |cursor, parent_page_guards, mut page| {
-                if cursor.key_existed {
-                    return Some("duplicate key\n".to_string());
-                };
-
-                let node = page.node.as_ref().unwrap();
-                let num_of_cells = node.num_of_cells as usize;
+    /// Insert the rows, in ascending order of their keys, returning where
+    /// each of them is inserted, or `DbError::DuplicateKey`.
+    ///
+    /// The rows going to the same leaf are inserted while it's latched, so
+    /// the tree is descended once per leaf rather than once per row. A row
+    /// that doesn't fit in its leaf is inserted by `insert_row`, which
+    /// splits the leaf.
+    ///
+    /// TRADEOFF: The separator of the leaf in its parent isn't known once
+    /// the leaf is reached, so a leaf only takes the rows below its
+    /// largest key, or any row if it's the last leaf. A row between the
+    /// largest key and the separator descends the tree again.
+    pub fn insert_rows(&self, root_page_num: usize, rows: &[Row]) -> Vec<Inserted> {
+        let mut results = Vec::with_capacity(rows.len());
+        while results.len() < rows.len() {
+            let rest = &rows[results.len()..];
+            match self.insert_into_leaf(root_page_num, rest) {
+                Ok(inserted) if !inserted.is_empty() => results.extend(inserted),
+                Ok(_) => results.push(self.insert_row(root_page_num, &rest[0])),
+                Err(err) => results.push(Err(err)),
+            }
+        }
+        results
+    }
 
-                // If num cell = MAX CELL, inserting into it cause it to overflow
-                // which mean we need to insert and split.
-                //
-                // TRADEOFF: We are only splitting nodes when it's full.
-                //
-                // However, it could be done better by delaying splitting by moving cell
-                // to sibling nodes when necessary, which is called load balancing.
-                //
-                // This result in higher occupancy and delayed of node splitting.
-                if num_of_cells >= LEAF_NODE_MAX_CELLS {
-                    self.concurrent_insert_and_split_node(parent_page_guards, page, &cursor, row);
-                } else {
-                    let node = page.node.as_mut().unwrap();
-                    node.insert(row, &cursor);
+    // Insert the first rows going to the leaf of the first row, until one
+    // doesn't fit, returning where each of them is inserted.
+    fn insert_into_leaf(
+        &self,
+        root_page_num: usize,
+        rows: &[Row],
+    ) -> Result<Vec<Inserted>, DbError> {
+        self.throttle_writes()?;
+        let _tree_latch = self.tree_latch.read();
+        self.check_pool_size(root_page_num)?;
+        let inserted = self.with_latch_protocol(|scope| {
+            self.search_and_then(
+                scope,
+                root_page_num,
+                rows[0].id,
+                Operation::Insert,
+                |cursor, parent_page_guards, mut page| {
+                    // The leaf is never split here, so its parents can go.
+                    drop(parent_page_guards);
+
+                    let mut results = Vec::new();
+                    for row in rows {
+                        let node = page.node().unwrap();
+                        let num_of_cells = node.num_of_cells as usize;
+                        let in_leaf = node.next_leaf_offset == NULL_PAGE
+                            || (num_of_cells > 0 && row.id < node.get_max_key());
+                        if !results.is_empty() && !in_leaf {
+                            break;
+                        }
 
-                    for page in parent_page_guards {
-                        self.unpin_page_with_write_guard(page, false);
+                        match node.search(row.id) {
+                            Ok(_) => results.push(Err(DbError::DuplicateKey)),
+                            Err(_) if !node.can_fit(row) => break,
+                            Err(cell_num) => {
+                                self.append_log(|| {
+                                    LogRecord::row_change(
+                                        0,
+                                        None,
+                                        LogRecordType::Insert,
+                                        cursor.page_num,
+                                        row,
+                                    )
+                                });
+                                let cursor = Cursor {
+                                    page_num: cursor.page_num,
+                                    cell_num,
+                                    key_existed: false,
+                                    end_of_table: cell_num == num_of_cells,
+                                };
+                                page.node_mut().unwrap().insert(row, &cursor);
+                                results.push(Ok((cursor.page_num, cell_num)));
+                            }
+                        }
                     }
 
-                    self.unpin_page_with_write_guard(page, true);
-                }
+                    let is_dirty = results.iter().any(Result::is_ok);
+                    self.unpin_page_with_write_guard(page, is_dirty);
+                    Some(Smo::Done(results))
+                },
+            )
+        });
+        Ok(inserted.unwrap_or_default())
+    }
 
-                Some(format!(
-                    "inserting into page: {}, cell: {}...\n",
-                    cursor.page_num, cursor.cell_num
-                ))
-            },
-        )
+    /// Return true if the tree has no rows, i.e. its root is an empty leaf.
+    pub fn is_empty(&self, root_page_num: usize) -> Result<bool, DbError> {
+        let page = self.fetch_read_page_with_retry(root_page_num)?;
+        let node = page.node().unwrap();
+        let is_empty = node.node_type == NodeType::Leaf && node.num_of_cells == 0;
+        self.unpin_page_with_read_guard(page, false);
+        Ok(is_empty)
     }
 
-    fn concurrent_insert_and_split_node(
+    /// Load rows in ascending order of their keys into an empty tree,
+    /// returning the number of rows loaded.
+    ///
+    /// Rather than inserting the rows one by one from the root, splitting
+    /// leaves along the way, leaves are packed up to `fill_percent` of
+    /// their space and written in order, then each level of internal
+    /// nodes is built from the one below, up to the root. The tree only
+    /// becomes visible once the root is written last.
+    ///
+    /// A row that isn't above the one before it stops the load with an
+    /// error, where the rows before it stay loaded.
+    ///
+    /// TRADEOFF: Rows aren't logged, so the pages are flushed before
+    /// returning instead, and a crash in the middle of the load leaves an
+    /// empty tree with pages that are never reclaimed.
+    pub fn bulk_load(
         &self,
-        parent_page_guards: Vec<RwLockWriteGuard<Page>>,
-        mut left_page: RwLockWriteGuard<Page>,
+        root_page_num: usize,
+        rows: impl Iterator<Item = Row>,
+        fill_percent: usize,
+    ) -> Result<usize, DbError> {
+        if !(MIN_FILL_PERCENT..=100).contains(&fill_percent) {
+            return Err(DbError::InvalidInput(format!(
+                "fill factor must be between {MIN_FILL_PERCENT}% and 100%, but got {fill_percent}%"
+            )));
+        }
+        let _tree_latch = self.tree_latch.write();
+        // The root of an empty file is only set up when it's first fetched,
+        // so it's marked dirty to be written before the pages after it,
+        // the same as `create_tree` does.
+        let root = self.fetch_write_page_guard_with_retry(root_page_num)?;
+        let node = root.node().unwrap();
+        let is_empty = node.node_type == NodeType::Leaf && node.num_of_cells == 0;
+        self.unpin_page_with_write_guard(root, true);
+        if !is_empty {
+            return Err(DbError::InvalidInput(
+                "bulk load needs an empty tree".to_string(),
+            ));
+        }
+
+        let max_space = LEAF_NODE_SPACE_FOR_CELLS * fill_percent / 100;
+        // Leaves written so far, with their max key. The last full leaf is
+        // only written once the next one is, as it could have to give
+        // rows to the last leaf.
+        let mut leaves = Vec::new();
+        let mut full_leaf: Option<Node> = None;
+        let mut leaf = Node::new(false, NodeType::Leaf);
+        let mut loaded = 0;
+        let mut last_key = None;
+        let mut result = Ok(());
+        for row in rows {
+            if last_key.is_some_and(|key| row.id <= key) {
+                result = Err(DbError::InvalidInput(format!(
+                    "bulk load needs rows in ascending order, but {} follows {}",
+                    row.id,
+                    last_key.unwrap()
+                )));
+                break;
+            }
+            last_key = Some(row.id);
+
+            if leaf.num_of_cells > 0 && leaf.used_space() + Cell::size_for(&row) > max_space {
+                if let Some(node) = full_leaf.take() {
+                    self.write_leaf(&mut leaves, node)?;
+                }
+                full_leaf = Some(std::mem::replace(
+                    &mut leaf,
+                    Node::new(false, NodeType::Leaf),
+                ));
+            }
+            let cursor = Cursor {
+                page_num: 0,
+                cell_num: leaf.num_of_cells as usize,
+                key_existed: false,
+                end_of_table: true,
+            };
+            leaf.insert(&row, &cursor);
+            loaded += 1;
+        }
+
+        let Some(mut full_leaf) = full_leaf else {
+            // Every row fits in the root.
+            let mut root = self.fetch_write_page_guard_with_retry(root_page_num)?;
+            leaf.is_root = true;
+            root.set_node(leaf);
+            self.unpin_page_with_write_guard(root, true);
+            self.flush_all_pages()?;
+            return result.map(|_| loaded);
+        };
+
+        // Even out the last two leaves, so the last one isn't left with a
+        // few rows.
+        if leaf.is_underflow() {
+            let mut cells = std::mem::take(&mut full_leaf.cells);
+            cells.append(&mut leaf.cells);
+            leaf.cells = cells.split_off(cells.len() / 2);
+            full_leaf.cells = cells;
+            full_leaf.num_of_cells = full_leaf.cells.len() as u32;
+            leaf.num_of_cells = leaf.cells.len() as u32;
+        }
+        self.write_leaf(&mut leaves, full_leaf)?;
+        self.write_leaf(&mut leaves, leaf)?;
+
+        let mut level = leaves;
+        let mut height = 2;
+        while level.len() > INTERNAL_NODE_MAX_CELLS + 1 {
+            let groups = level.len().div_ceil(INTERNAL_NODE_MAX_CELLS + 1);
+            let mut parents = Vec::with_capacity(groups);
+            let mut children = level.as_slice();
+            for i in 0..groups {
+                let (group, rest) = children.split_at(children.len() / (groups - i));
+                children = rest;
+                let mut page = self.new_page()?;
+                page.set_node(internal_node(group, false));
+                self.concurrent_update_children_parent_offset(&mut page)?;
+                parents.push((page.page_id.unwrap(), group.last().unwrap().1));
+                self.unpin_page_with_write_guard(page, true);
+            }
+            level = parents;
+            height += 1;
+        }
+
+        let mut root = self.fetch_write_page_guard_with_retry(root_page_num)?;
+        root.set_node(internal_node(&level, true));
+        self.concurrent_update_children_parent_offset(&mut root)?;
+        self.unpin_page_with_write_guard(root, true);
+        self.tree_heights.lock().insert(root_page_num, height);
+
+        self.flush_all_pages()?;
+        result.map(|_| loaded)
+    }
+
+    // Write the leaf into a new page, linked from the leaf written before.
+    fn write_leaf(&self, leaves: &mut Vec<(usize, u32)>, node: Node) -> Result<(), DbError> {
+        let max_key = node.get_max_key();
+        let mut page = self.new_page()?;
+        let page_id = page.page_id.unwrap();
+        page.set_node(node);
+        self.unpin_page_with_write_guard(page, true);
+
+        if let Some((prev_page_id, _)) = leaves.last() {
+            let mut prev = self.fetch_write_page_guard_with_retry(*prev_page_id)?;
+            prev.node_mut().unwrap().next_leaf_offset = page_id as u32;
+            self.unpin_page_with_write_guard(prev, true);
+        }
+        leaves.push((page_id, max_key));
+
+        Ok(())
+    }
+
+    fn concurrent_insert_and_split_node<'a>(
+        &'a self,
+        parent_page_guards: PinScope<'a>,
+        mut left_page: RwLockWriteGuard<'a, Page>,
         cursor: &Cursor,
         row: &Row,
-    ) {
-        let left_node = left_page.node.as_mut().unwrap();
+    ) -> Result<(), DbError> {
+        let left_node = left_page.node_mut().unwrap();
         let old_max = left_node.get_max_key();
         left_node.insert(row, cursor);
 
+        // Split by bytes instead of cell count, leaving more free space
+        // in the node the recent inserts went to.
+        let split_at = if self.is_ingesting() {
+            left_node.split_point_at(left_node.insert_history.ingest_split_ratio())
+        } else {
+            left_node.split_point()
+        };
         let mut right_node = Node::new(false, left_node.node_type);
-        for _i in 0..LEAF_NODE_RIGHT_SPLIT_COUNT {
-            let cell = left_node.cells.remove(LEAF_NODE_LEFT_SPLIT_COUNT);
-            left_node.num_of_cells -= 1;
-
-            right_node.cells.push(cell);
-            right_node.num_of_cells += 1;
-        }
+        right_node.insert_history = left_node.insert_history;
+        right_node.cells = left_node.cells.split_off(split_at);
+        right_node.num_of_cells = right_node.cells.len() as u32;
+        left_node.num_of_cells = left_node.cells.len() as u32;
 
         if left_node.is_root {
             let left_max_key = left_node.get_max_key();
 
             // If left node is root it shouldn't have any parent.
-            assert_eq!(parent_page_guards.len(), 0);
+            assert!(parent_page_guards.is_empty());
 
-            self.concurrent_create_new_root(left_page, right_node, left_max_key);
+            self.concurrent_create_new_root(left_page, right_node, left_max_key)?;
         } else {
             self.concurrent_split_node_and_update_parent(
                 parent_page_guards,
                 left_page,
                 right_node,
                 old_max,
-            );
+            )?;
         }
+
+        Ok(())
     }
 
-    fn concurrent_split_node_and_update_parent(
-        &self,
-        mut parent_page_guards: Vec<RwLockWriteGuard<Page>>,
-        mut left_page: RwLockWriteGuard<Page>,
+    fn concurrent_split_node_and_update_parent<'a>(
+        &'a self,
+        mut parent_page_guards: PinScope<'a>,
+        mut left_page: RwLockWriteGuard<'a, Page>,
         mut right_node: Node,
         max_key: u32,
-    ) {
-        let mut right_page = self.new_page().unwrap();
+    ) -> Result<(), DbError> {
+        let mut right_page = self.new_page()?;
         let right_page_id = right_page.page_id.unwrap();
-        let left_node = left_page.node.as_mut().unwrap();
+        let left_node = left_page.node_mut().unwrap();
         let new_max = left_node.get_max_key();
 
         right_node.next_leaf_offset = left_node.next_leaf_offset;
@@ -878,25 +3203,25 @@ impl Pager {
         right_node.parent_offset = left_node.parent_offset;
         self.unpin_page_with_write_guard(left_page, true);
 
-        right_page.node = Some(right_node);
+        right_page.set_node(right_node);
         self.unpin_page_with_write_guard(right_page, true);
 
         assert!(!parent_page_guards.is_empty());
         let mut parent_page = parent_page_guards.pop().unwrap();
-        let parent_node = parent_page.node.as_mut().unwrap();
+        let parent_node = parent_page.node_mut().unwrap();
         parent_node.update_internal_key(max_key, new_max);
 
         let split_at_page_num = right_page_id;
 
-        let parent_node = parent_page.node.as_ref().unwrap();
+        let parent_node = parent_page.node().unwrap();
         let parent_right_child_offset = parent_node.right_child_offset as usize;
 
-        let most_right_page = self.fetch_write_page_guard_with_retry(parent_right_child_offset);
-        let right_node = most_right_page.node.as_ref().unwrap();
+        let most_right_page = self.fetch_write_page_guard_with_retry(parent_right_child_offset)?;
+        let right_node = most_right_page.node().unwrap();
         let right_max_key = right_node.get_max_key();
         self.unpin_page_with_write_guard(most_right_page, false);
 
-        let parent_node = parent_page.node.as_mut().unwrap();
+        let parent_node = parent_page.node_mut().unwrap();
         parent_node.num_of_cells += 1;
 
         let index = parent_node.internal_search(new_child_max_key);
@@ -913,7 +3238,9 @@ impl Pager {
             );
         }
 
-        self.concurrent_split_internal_node(parent_page, parent_page_guards);
+        self.concurrent_split_internal_node(parent_page, parent_page_guards)?;
+
+        Ok(())
     }
 
     fn concurrent_create_new_root(
@@ -921,38 +3248,44 @@ impl Pager {
         mut page: RwLockWriteGuard<Page>,
         mut right_node: Node,
         max_key: u32,
-    ) {
-        let mut left_page = self.new_page().unwrap();
+    ) -> Result<(), DbError> {
+        let mut left_page = self.new_page()?;
         let left_page_id = left_page.page_id.unwrap() as u32;
 
-        let mut right_page = self.new_page().unwrap();
+        let mut right_page = self.new_page()?;
         let right_page_id = right_page.page_id.unwrap() as u32;
 
         let mut root_node = Node::new(true, NodeType::Internal);
         root_node.num_of_cells += 1;
         root_node.right_child_offset = right_page_id;
 
-        right_node.parent_offset = 0;
-        right_node.next_leaf_offset = 0;
+        // The root stays in its page, which is only page 0 for the first
+        // tree of the file.
+        let root_page_id = page.page_id.unwrap();
+        right_node.parent_offset = root_page_id as u32;
+        right_node.next_leaf_offset = NULL_PAGE;
 
-        let mut left_node = page.node.take().unwrap();
+        let mut left_node = page.take_node().unwrap();
         left_node.is_root = false;
         left_node.next_leaf_offset = right_page_id;
-        left_node.parent_offset = 0;
+        left_node.parent_offset = root_page_id as u32;
 
         let cell = InternalCell::new(left_page_id, max_key);
         root_node.internal_cells.insert(0, cell);
 
-        page.node = Some(root_node);
-        left_page.node = Some(left_node);
-        right_page.node = Some(right_node);
+        page.set_node(root_node);
+        left_page.set_node(left_node);
+        right_page.set_node(right_node);
+        self.update_tree_height(root_page_id, true);
 
-        self.concurrent_update_children_parent_offset(&mut left_page);
+        self.concurrent_update_children_parent_offset(&mut left_page)?;
         self.unpin_page_with_write_guard(left_page, true);
 
-        self.concurrent_update_children_parent_offset(&mut right_page);
+        self.concurrent_update_children_parent_offset(&mut right_page)?;
         self.unpin_page_with_write_guard(right_page, true);
         self.unpin_page_with_write_guard(page, true);
+
+        Ok(())
     }
 
     // TRADEOFF (Parent pointer):
@@ -962,15 +3295,24 @@ impl Pager {
     // child pointer. If we were to update around 250 child nodes parent offset
     // during a split/merge (since only half of the childrens will be move),
     // the cost of page in/out and potentially disk I/O will add up.
-    pub fn update_parent_offset(&self, page_id: usize, parent_page_id: usize) {
-        let mut page = self.fetch_write_page_guard_with_retry(page_id);
-        let child = page.node.as_mut().unwrap();
+    pub fn update_parent_offset(
+        &self,
+        page_id: usize,
+        parent_page_id: usize,
+    ) -> Result<(), DbError> {
+        let mut page = self.fetch_write_page_guard_with_retry(page_id)?;
+        let child = page.node_mut().unwrap();
         child.parent_offset = parent_page_id as u32;
         self.unpin_page_with_write_guard(page, true);
+
+        Ok(())
     }
 
-    pub fn concurrent_update_children_parent_offset(&self, page: &mut RwLockWriteGuard<Page>) {
-        let node = page.node.as_ref().unwrap();
+    pub fn concurrent_update_children_parent_offset(
+        &self,
+        page: &mut RwLockWriteGuard<Page>,
+    ) -> Result<(), DbError> {
+        let node = page.node().unwrap();
         let parent_page_id = page.page_id.unwrap();
 
         let mut child_pointers = vec![node.right_child_offset as usize];
@@ -980,28 +3322,25 @@ impl Pager {
 
         child_pointers.retain(|&i| i != 0);
         for i in child_pointers {
-            self.update_parent_offset(i, parent_page_id);
+            self.update_parent_offset(i, parent_page_id)?;
         }
+
+        Ok(())
     }
 
-    pub fn concurrent_split_internal_node(
-        &self,
-        mut left_page: RwLockWriteGuard<Page>,
-        mut parent_page_guards: Vec<RwLockWriteGuard<Page>>,
-    ) {
+    pub fn concurrent_split_internal_node<'a>(
+        &'a self,
+        mut left_page: RwLockWriteGuard<'a, Page>,
+        mut parent_page_guards: PinScope<'a>,
+    ) -> Result<(), DbError> {
         // Check if our internal node need to be split. If it is equal to or less than MAX,
         // no split is required.
-        if left_page.node.as_ref().unwrap().num_of_cells <= INTERNAL_NODE_MAX_CELLS as u32 {
-            for page in parent_page_guards {
-                self.unpin_page_with_write_guard(page, false);
-            }
-
-            self.unpin_page_with_write_guard(left_page, true);
-
-            return;
+        if left_page.node().unwrap().num_of_cells <= INTERNAL_NODE_MAX_CELLS as u32 {
+            parent_page_guards.push(left_page, true);
+            return Ok(());
         }
 
-        let left_node = left_page.node.as_mut().unwrap();
+        let left_node = left_page.node_mut().unwrap();
         let split_at_index = left_node.num_of_cells as usize / 2;
 
         let mut right_node = Node::new(false, NodeType::Internal);
@@ -1020,23 +3359,23 @@ impl Pager {
             right_node.num_of_cells += 1;
         }
 
-        let left_node = left_page.node.as_ref().unwrap();
+        let left_node = left_page.node().unwrap();
 
         if left_node.is_root {
-            assert_eq!(parent_page_guards.len(), 0);
-            self.concurrent_create_new_root(left_page, right_node, ic.key());
+            assert!(parent_page_guards.is_empty());
+            self.concurrent_create_new_root(left_page, right_node, ic.key())?;
         } else {
             let page_num = left_page.page_id.unwrap();
 
             assert!(!parent_page_guards.is_empty());
             let mut parent_page = parent_page_guards.pop().unwrap();
-            let parent = parent_page.node.as_mut().unwrap();
+            let parent = parent_page.node_mut().unwrap();
             let index = parent.internal_search_child_pointer(page_num as u32);
 
-            let mut right_page = self.new_page().unwrap();
+            let mut right_page = self.new_page()?;
             let right_page_id = right_page.page_id.unwrap() as u32;
-            right_page.is_dirty = true;
-            right_page.node = Some(right_node);
+            self.pool.mark_dirty(&mut right_page);
+            right_page.set_node(right_node);
 
             if parent.num_of_cells == index as u32 {
                 parent.right_child_offset = right_page_id;
@@ -1054,71 +3393,111 @@ impl Pager {
             }
 
             self.unpin_page_with_write_guard(left_page, true);
-            self.concurrent_update_children_parent_offset(&mut right_page);
+            self.concurrent_update_children_parent_offset(&mut right_page)?;
             self.unpin_page_with_write_guard(right_page, true);
 
-            self.concurrent_split_internal_node(parent_page, parent_page_guards);
+            self.concurrent_split_internal_node(parent_page, parent_page_guards)?;
         }
+
+        Ok(())
     }
 
-    pub fn delete_by_key(&self, root_page_num: usize, key: u32) -> Option<String> {
-        self.search_and_then(
-            vec![],
-            root_page_num,
-            key,
-            Operation::Delete,
-            |cursor, parent_page_guards, mut page| {
-                if cursor.key_existed {
-                    let node = page.node.as_mut().unwrap();
-                    node.delete(cursor.cell_num);
-                    self.concurrent_maybe_merge_nodes(page, parent_page_guards);
-
-                    Some(format!("deleted {}", key))
-                } else {
-                    for page in parent_page_guards {
+    pub fn delete_by_key(&self, root_page_num: usize, key: u32) -> Result<String, DbError> {
+        self.throttle_writes()?;
+        let _tree_latch = self.tree_latch.read();
+        self.check_pool_size(root_page_num)?;
+        self.with_latch_protocol(|scope| {
+            self.search_and_then(
+                scope,
+                root_page_num,
+                key,
+                Operation::Delete,
+                |cursor, parent_page_guards, mut page| {
+                    if cursor.key_existed {
+                        let node = page.node().unwrap();
+                        if parent_page_guards.is_optimistic()
+                            && !self.is_ingesting()
+                            && node.underflows_without(cursor.cell_num)
+                        {
+                            self.unpin_page_with_write_guard(page, false);
+                            return Some(Smo::Needed);
+                        }
+
+                        self.append_log(|| {
+                            LogRecord::row_change(
+                                0,
+                                None,
+                                LogRecordType::ApplyDelete,
+                                cursor.page_num,
+                                &node.get(cursor.cell_num),
+                            )
+                        });
+                        let node = page.node_mut().unwrap();
+                        node.delete(cursor.cell_num);
+                        let merged = self.concurrent_maybe_merge_nodes(page, parent_page_guards);
+
+                        Some(Smo::Done(merged.map(|_| format!("deleted {}", key))))
+                    } else {
                         self.unpin_page_with_write_guard(page, false);
+                        None
                     }
-
-                    self.unpin_page_with_write_guard(page, false);
-
-                    Some(format!("item not found with id {}", key))
-                }
-            },
-        )
+                },
+            )
+        })
+        .unwrap_or_else(|| Ok(format!("item not found with id {}", key)))
     }
 
-    pub fn delete(&self, root_page_num: usize, row: &Row) -> Option<String> {
-        self.search_and_then(
-            vec![],
-            root_page_num,
-            row.id,
-            Operation::Delete,
-            |cursor, parent_page_guards, mut page| {
-                if cursor.key_existed {
-                    let node = page.node.as_mut().unwrap();
-                    node.delete(cursor.cell_num);
-                    self.concurrent_maybe_merge_nodes(page, parent_page_guards);
-
-                    Some(format!("deleted {}", row.id))
-                } else {
-                    for page in parent_page_guards {
+    pub fn delete(&self, root_page_num: usize, row: &Row) -> Result<String, DbError> {
+        self.throttle_writes()?;
+        let _tree_latch = self.tree_latch.read();
+        self.check_pool_size(root_page_num)?;
+        self.with_latch_protocol(|scope| {
+            self.search_and_then(
+                scope,
+                root_page_num,
+                row.id,
+                Operation::Delete,
+                |cursor, parent_page_guards, mut page| {
+                    if cursor.key_existed {
+                        let node = page.node().unwrap();
+                        if parent_page_guards.is_optimistic()
+                            && !self.is_ingesting()
+                            && node.underflows_without(cursor.cell_num)
+                        {
+                            self.unpin_page_with_write_guard(page, false);
+                            return Some(Smo::Needed);
+                        }
+
+                        self.append_log(|| {
+                            LogRecord::row_change(
+                                0,
+                                None,
+                                LogRecordType::ApplyDelete,
+                                cursor.page_num,
+                                &node.get(cursor.cell_num),
+                            )
+                        });
+                        let node = page.node_mut().unwrap();
+                        node.delete(cursor.cell_num);
+                        let merged = self.concurrent_maybe_merge_nodes(page, parent_page_guards);
+
+                        Some(Smo::Done(merged.map(|_| format!("deleted {}", row.id))))
+                    } else {
                         self.unpin_page_with_write_guard(page, false);
+                        None
                     }
-
-                    self.unpin_page_with_write_guard(page, false);
-
-                    Some(format!("item not found with id {}", row.id))
-                }
-            },
-        )
+                },
+            )
+        })
+        .unwrap_or_else(|| Ok(format!("item not found with id {}", row.id)))
     }
 
-    fn concurrent_maybe_merge_nodes(
-        &self,
-        page: RwLockWriteGuard<Page>,
-        parent_page_guards: Vec<RwLockWriteGuard<Page>>,
-    ) {
-        let node = page.node.as_ref().unwrap();
+    fn concurrent_maybe_merge_nodes<'a>(
+        &'a self,
+        page: RwLockWriteGuard<'a, Page>,
+        mut parent_page_guards: PinScope<'a>,
+    ) -> Result<(), DbError> {
+        let node = page.node().unwrap();
 
         // TRADEOFF: We could leave the node to be underflow.
         //
@@ -1127,40 +3506,40 @@ impl Pager {
         //
         // Study has show that, rebalancing on deletion can be considered harmful.
         if node.node_type == NodeType::Leaf
-            && node.num_of_cells <= LEAF_NODE_MAX_CELLS as u32 / 2
+            && node.is_underflow()
             && !node.is_root
+            && !self.is_ingesting()
         {
             return self.concurrent_merge_leaf_nodes(page, parent_page_guards);
         }
 
-        for page in parent_page_guards {
-            self.unpin_page_with_write_guard(page, false);
-        }
+        parent_page_guards.push(page, true);
 
-        self.unpin_page_with_write_guard(page, true);
+        Ok(())
     }
 
-    fn concurrent_merge_leaf_nodes(
-        &self,
-        page: RwLockWriteGuard<Page>,
-        mut parent_page_guards: Vec<RwLockWriteGuard<Page>>,
-    ) {
+    fn concurrent_merge_leaf_nodes<'a>(
+        &'a self,
+        page: RwLockWriteGuard<'a, Page>,
+        mut parent_page_guards: PinScope<'a>,
+    ) -> Result<(), DbError> {
         let page_id = page.page_id.unwrap();
-        let node = page.node.as_ref().unwrap();
+        let node = page.node().unwrap();
         let node_cells_len = node.cells.len();
+        let node_used_space = node.used_space();
 
         let parent_page = parent_page_guards.pop().unwrap();
-        let parent = parent_page.node.as_ref().unwrap();
+        let parent = parent_page.node().unwrap();
         let (left_child_pointer, right_child_pointer) = parent.siblings(page_id as u32);
         debug!("-- merge leaf node {page_id}: {left_child_pointer:?}, {right_child_pointer:?}");
 
         if let Some(cp) = left_child_pointer {
             if cp != page_id && cp != 0 {
-                let left_page = self.fetch_write_page_guard_with_retry(cp);
-                let left_nb = left_page.node.as_ref().unwrap();
+                let left_page = self.fetch_write_page_guard_with_retry(cp)?;
+                let left_nb = left_page.node().unwrap();
 
                 // If merging both result does not exceed MAX, proceed
-                if left_nb.cells.len() + node_cells_len <= LEAF_NODE_MAX_CELLS {
+                if left_nb.used_space() + node_used_space <= LEAF_NODE_SPACE_FOR_CELLS {
                     debug!("-- merge leaf node {} with its left neighbour...", page_id);
                     return self.concurrent_do_merge_leaf_nodes(
                         parent_page,
@@ -1186,10 +3565,10 @@ impl Pager {
 
         if let Some(cp) = right_child_pointer {
             if cp != page_id && cp != 0 {
-                let right_page = self.fetch_write_page_guard_with_retry(cp);
-                let right_nb = right_page.node.as_ref().unwrap();
+                let right_page = self.fetch_write_page_guard_with_retry(cp)?;
+                let right_nb = right_page.node().unwrap();
 
-                if right_nb.cells.len() + node_cells_len <= LEAF_NODE_MAX_CELLS {
+                if right_nb.used_space() + node_used_space <= LEAF_NODE_SPACE_FOR_CELLS {
                     debug!("-- merge leaf node {} with its right neighbour...", page_id);
                     return self.concurrent_do_merge_leaf_nodes(
                         parent_page,
@@ -1213,29 +3592,26 @@ impl Pager {
             }
         }
 
-        // Drop parent guards lock
-        for page in parent_page_guards {
-            self.unpin_page_with_write_guard(page, false);
-        }
+        parent_page_guards.push(parent_page, false);
+        parent_page_guards.push(page, true);
 
-        self.unpin_page_with_write_guard(parent_page, false);
-        self.unpin_page_with_write_guard(page, true);
+        Ok(())
     }
 
-    fn concurrent_do_merge_leaf_nodes(
-        &self,
-        mut parent_page: RwLockWriteGuard<Page>,
-        mut left_page: RwLockWriteGuard<Page>,
-        mut right_page: RwLockWriteGuard<Page>,
-        parent_page_guards: Vec<RwLockWriteGuard<Page>>,
-    ) {
+    fn concurrent_do_merge_leaf_nodes<'a>(
+        &'a self,
+        mut parent_page: RwLockWriteGuard<'a, Page>,
+        mut left_page: RwLockWriteGuard<'a, Page>,
+        mut right_page: RwLockWriteGuard<'a, Page>,
+        parent_page_guards: PinScope<'a>,
+    ) -> Result<(), DbError> {
         let right_page_id = right_page.page_id.unwrap();
         let left_page_id = left_page.page_id.unwrap();
         // Take the node of right page and left page out of page.
         //
         // Free up the pages as we don't need it anymore.
-        let left_node = left_page.node.as_mut().unwrap();
-        let right_node = right_page.node.take().unwrap();
+        let left_node = left_page.node_mut().unwrap();
+        let right_node = right_page.take_node().unwrap();
 
         // Merge the leaf nodes cells
         for c in right_node.cells {
@@ -1243,10 +3619,10 @@ impl Pager {
             left_node.num_of_cells += 1;
         }
         left_node.next_leaf_offset = right_node.next_leaf_offset;
-        let parent = parent_page.node.as_mut().unwrap();
+        let parent = parent_page.node_mut().unwrap();
 
         if parent.num_of_cells == 1 && parent.is_root {
-            self.concurrent_promote_node_to_root(parent_page, left_page, right_page);
+            self.concurrent_promote_node_to_root(parent_page, left_page, right_page)
         } else {
             self.delete_page_with_write_guard(right_page);
 
@@ -1291,49 +3667,49 @@ impl Pager {
         mut parent_page: RwLockWriteGuard<Page>,
         mut left_page: RwLockWriteGuard<Page>,
         right_page: RwLockWriteGuard<Page>,
-    ) {
+    ) -> Result<(), DbError> {
         // Take left node out of left page as it will be used to replace
         // the node in our parent.
-        let mut left_node = left_page.node.take().unwrap();
+        let mut left_node = left_page.take_node().unwrap();
 
         // Replace the parent.node with our new combined left node
         left_node.is_root = true;
-        left_node.next_leaf_offset = 0;
-        parent_page.node = Some(left_node);
+        left_node.next_leaf_offset = NULL_PAGE;
+        parent_page.set_node(left_node);
+        let root_page_id = parent_page.page_id.unwrap();
+        self.update_tree_height(root_page_id, false);
 
         self.delete_page_with_write_guard(left_page);
         self.delete_page_with_write_guard(right_page);
 
-        self.concurrent_update_children_parent_offset(&mut parent_page);
+        self.concurrent_update_children_parent_offset(&mut parent_page)?;
         debug!("parent_page: {parent_page:?}");
         debug!("promote node to root (end)\n\n");
         self.unpin_page_with_write_guard(parent_page, true);
+
+        Ok(())
     }
 
-    fn concurrent_merge_internal_nodes(
-        &self,
-        page: RwLockWriteGuard<Page>,
-        mut parent_page_guards: Vec<RwLockWriteGuard<Page>>,
-    ) {
+    fn concurrent_merge_internal_nodes<'a>(
+        &'a self,
+        page: RwLockWriteGuard<'a, Page>,
+        mut parent_page_guards: PinScope<'a>,
+    ) -> Result<(), DbError> {
         let page_id = page.page_id.unwrap();
-        let node = page.node.as_ref().unwrap();
+        let node = page.node().unwrap();
         let node_num_of_cells = node.num_of_cells;
         let min_key_length = self.min_key(INTERNAL_NODE_MAX_CELLS) as u32;
 
         // Skip merging internal node if it has more than min_key length.
         // In our case > 1. If it's equals to, we will still need to merge.
         if node.num_of_cells > min_key_length || node.is_root {
-            for page in parent_page_guards {
-                self.unpin_page_with_write_guard(page, false);
-            }
-
-            self.unpin_page_with_write_guard(page, true);
-            return;
+            parent_page_guards.push(page, true);
+            return Ok(());
         }
 
         assert!(!parent_page_guards.is_empty());
         let parent_page = parent_page_guards.pop().unwrap();
-        let parent = parent_page.node.as_ref().unwrap();
+        let parent = parent_page.node().unwrap();
 
         let (left_child_pointer, right_child_pointer) = parent.siblings(page_id as u32);
         debug!(
@@ -1343,8 +3719,8 @@ impl Pager {
 
         if let Some(cp) = left_child_pointer {
             if cp != page_id && cp != 0 {
-                let left_page = self.fetch_write_page_guard_with_retry(cp);
-                let left_nb = left_page.node.as_ref().unwrap();
+                let left_page = self.fetch_write_page_guard_with_retry(cp)?;
+                let left_nb = left_page.node().unwrap();
 
                 if left_nb.num_of_cells + node_num_of_cells < INTERNAL_NODE_MAX_CELLS as u32 {
                     debug!("-- merge internal node {page_id} with left neighbour");
@@ -1353,12 +3729,12 @@ impl Pager {
                         left_page,
                         page,
                         parent_page_guards,
-                    );
-                    return;
+                    )?;
+                    return Ok(());
                 }
 
-                self.steal_from_sibling(parent_page, left_page, page, parent_page_guards);
-                return;
+                self.steal_from_sibling(parent_page, left_page, page, parent_page_guards)?;
+                return Ok(());
 
                 // self.unpin_page_with_write_guard(left_page, false);
             } else {
@@ -1368,8 +3744,8 @@ impl Pager {
 
         if let Some(cp) = right_child_pointer {
             if cp != page_id && cp != 0 {
-                let right_page = self.fetch_write_page_guard_with_retry(cp);
-                let right_nb = right_page.node.as_ref().unwrap();
+                let right_page = self.fetch_write_page_guard_with_retry(cp)?;
+                let right_nb = right_page.node().unwrap();
 
                 if right_nb.num_of_cells + node_num_of_cells <= INTERNAL_NODE_MAX_CELLS as u32 {
                     debug!("-- merge internal node {page_id} with right neighbour");
@@ -1378,12 +3754,12 @@ impl Pager {
                         page,
                         right_page,
                         parent_page_guards,
-                    );
-                    return;
+                    )?;
+                    return Ok(());
                 }
 
-                self.steal_from_sibling(parent_page, page, right_page, parent_page_guards);
-                return;
+                self.steal_from_sibling(parent_page, page, right_page, parent_page_guards)?;
+                return Ok(());
 
                 // self.unpin_page_with_write_guard(right_page, false);
             } else {
@@ -1391,28 +3767,25 @@ impl Pager {
             }
         }
 
-        // Drop parent guards lock
-        for page in parent_page_guards {
-            self.unpin_page_with_write_guard(page, false);
-        }
+        parent_page_guards.push(parent_page, false);
+        parent_page_guards.push(page, true);
 
-        self.unpin_page_with_write_guard(page, true);
-        self.unpin_page_with_write_guard(parent_page, false);
+        Ok(())
     }
 
-    fn steal_from_sibling(
-        &self,
-        mut parent_page: RwLockWriteGuard<Page>,
-        mut left_page: RwLockWriteGuard<Page>,
-        mut right_page: RwLockWriteGuard<Page>,
-        parent_page_guards: Vec<RwLockWriteGuard<Page>>,
-    ) {
+    fn steal_from_sibling<'a>(
+        &'a self,
+        mut parent_page: RwLockWriteGuard<'a, Page>,
+        mut left_page: RwLockWriteGuard<'a, Page>,
+        mut right_page: RwLockWriteGuard<'a, Page>,
+        mut parent_page_guards: PinScope<'a>,
+    ) -> Result<(), DbError> {
         debug!("-- steal from sibling");
         let min_key_length = self.min_key(INTERNAL_NODE_MAX_CELLS) as u32;
         let left_page_id = left_page.page_id.unwrap();
-        let left_node = left_page.node.as_mut().unwrap();
-        let right_node = right_page.node.as_mut().unwrap();
-        let parent_node = parent_page.node.as_mut().unwrap();
+        let left_node = left_page.node_mut().unwrap();
+        let right_node = right_page.node_mut().unwrap();
+        let parent_node = parent_page.node_mut().unwrap();
 
         // Left node have less cell so let's steal from our right node.
         if left_node.num_of_cells < min_key_length {
@@ -1441,7 +3814,7 @@ impl Pager {
             self.update_parent_offset(
                 new_most_right_child_page_id as usize,
                 left_page.page_id.unwrap(),
-            );
+            )?;
             debug!("-- left_page: {:?}", left_page);
             self.unpin_page_with_write_guard(left_page, true);
 
@@ -1449,13 +3822,8 @@ impl Pager {
             parent_node.internal_cells[index].write_key(min_internal_cell.key());
             debug!("-- parent_page: {:?}", parent_page);
             debug!("-- steal sibling (end)\n\n");
-
-            for page in parent_page_guards {
-                self.unpin_page_with_write_guard(page, false);
-            }
-
-            self.unpin_page_with_write_guard(parent_page, true);
-            return;
+            parent_page_guards.push(parent_page, true);
+            return Ok(());
         }
 
         // right node have less cell so let's steal from our left node.
@@ -1477,7 +3845,7 @@ impl Pager {
             self.update_parent_offset(
                 left_node.right_child_offset as usize,
                 right_page.page_id.unwrap(),
-            );
+            )?;
             debug!("-- right_page: {:?}", right_page);
             self.unpin_page_with_write_guard(right_page, true);
 
@@ -1496,49 +3864,40 @@ impl Pager {
 
             debug!("-- parent: {:?}", parent_page);
             debug!("-- steal sibling (end)\n\n",);
-
-            for page in parent_page_guards {
-                self.unpin_page_with_write_guard(page, false);
-            }
-
-            self.unpin_page_with_write_guard(parent_page, true);
-            return;
+            parent_page_guards.push(parent_page, true);
+            return Ok(());
         }
 
-        self.unpin_page_with_write_guard(right_page, true);
-        self.unpin_page_with_write_guard(left_page, true);
-
-        // Drop parent guards lock
-        for page in parent_page_guards {
-            self.unpin_page_with_write_guard(page, false);
-        }
+        parent_page_guards.push(parent_page, false);
+        parent_page_guards.push(left_page, true);
+        parent_page_guards.push(right_page, true);
 
-        self.unpin_page_with_write_guard(parent_page, false);
+        Ok(())
     }
 
-    fn concurrent_do_merge_internal_nodes(
-        &self,
-        mut parent_page: RwLockWriteGuard<Page>,
-        mut left_page: RwLockWriteGuard<Page>,
-        mut right_page: RwLockWriteGuard<Page>,
-        parent_page_guards: Vec<RwLockWriteGuard<Page>>,
-    ) {
+    fn concurrent_do_merge_internal_nodes<'a>(
+        &'a self,
+        mut parent_page: RwLockWriteGuard<'a, Page>,
+        mut left_page: RwLockWriteGuard<'a, Page>,
+        mut right_page: RwLockWriteGuard<'a, Page>,
+        parent_page_guards: PinScope<'a>,
+    ) -> Result<(), DbError> {
         debug!("-- concurrent do merge internal node");
         let right_page_id = right_page.page_id.unwrap();
         let left_page_id = left_page.page_id.unwrap();
 
-        let left_node = left_page.node.as_ref().unwrap();
+        let left_node = left_page.node().unwrap();
 
-        let left_max_key = self.get_node_max_key(left_node.right_child_offset as usize);
-        let left_node = left_page.node.as_mut().unwrap();
+        let left_max_key = self.get_node_max_key(left_node.right_child_offset as usize)?;
+        let left_node = left_page.node_mut().unwrap();
         left_node.internal_cells.push(InternalCell::new(
             left_node.right_child_offset,
             left_max_key,
         ));
         left_node.num_of_cells += 1;
 
-        let right_node = right_page.node.take().unwrap();
-        let left_node = left_page.node.as_mut().unwrap();
+        let right_node = right_page.take_node().unwrap();
+        let left_node = left_page.node_mut().unwrap();
 
         for c in right_node.internal_cells {
             left_node.internal_cells.push(c);
@@ -1546,16 +3905,16 @@ impl Pager {
         }
         left_node.right_child_offset = right_node.right_child_offset;
 
-        let new_left_max_key = self.get_node_max_key(left_node.right_child_offset as usize);
+        let new_left_max_key = self.get_node_max_key(left_node.right_child_offset as usize)?;
 
         // Update parent metadata
-        let parent = parent_page.node.as_ref().unwrap();
+        let parent = parent_page.node().unwrap();
 
         if parent.num_of_cells == 1 && parent.is_root {
             assert!(parent_page_guards.is_empty());
-            self.concurrent_promote_node_to_root(parent_page, left_page, right_page);
+            self.concurrent_promote_node_to_root(parent_page, left_page, right_page)?;
         } else {
-            let parent = parent_page.node.as_mut().unwrap();
+            let parent = parent_page.node_mut().unwrap();
             let parent_right_child_offset = parent.right_child_offset as usize;
             let index = parent.internal_search_child_pointer(left_page_id as u32);
 
@@ -1573,25 +3932,27 @@ impl Pager {
 
             self.delete_page_with_write_guard(right_page);
 
-            self.concurrent_update_children_parent_offset(&mut left_page);
+            self.concurrent_update_children_parent_offset(&mut left_page)?;
             debug!("-- left_page: {left_page:?}");
             self.unpin_page_with_write_guard(left_page, true);
 
             debug!("-- parent_page: {parent_page:?}");
             debug!("-- concurrent do merge internal node (end)\n\n");
-            self.concurrent_merge_internal_nodes(parent_page, parent_page_guards);
+            self.concurrent_merge_internal_nodes(parent_page, parent_page_guards)?;
         }
+
+        Ok(())
     }
 
-    pub fn get_node_max_key(&self, mut page_id: usize) -> u32 {
+    pub fn get_node_max_key(&self, mut page_id: usize) -> Result<u32, DbError> {
         loop {
-            let page = self.fetch_write_page_guard_with_retry(page_id);
-            let node = page.node.as_ref().unwrap();
+            let page = self.fetch_write_page_guard_with_retry(page_id)?;
+            let node = page.node().unwrap();
 
             if node.node_type == NodeType::Leaf {
                 let new_left_max_key = node.get_max_key();
                 self.unpin_page_with_write_guard(page, false);
-                return new_left_max_key;
+                return Ok(new_left_max_key);
             } else {
                 page_id = node.right_child_offset as usize;
                 self.unpin_page_with_write_guard(page, false);
@@ -1605,12 +3966,44 @@ impl Pager {
         for i in 0..self.next_page_id.load(Ordering::Relaxed) {
             let bytes = self.disk_manager.read_page(i).unwrap();
             writeln!(&mut result, "--- Page {} ---", i).unwrap();
-            writeln!(&mut result, "{:?}", Node::new_from_bytes(&bytes)).unwrap();
+            match PageHeader::decode(&bytes[..PAGE_HEADER_BYTES]) {
+                Ok(header) => {
+                    let checksum = if Page::verify_checksum(&bytes) {
+                        "ok"
+                    } else {
+                        "mismatch"
+                    };
+                    writeln!(&mut result, "{header:?} (checksum {checksum})").unwrap();
+                    if matches!(
+                        header.page_type,
+                        PageType::Dictionary | PageType::Metadata | PageType::Free
+                    ) {
+                        continue;
+                    }
+                    let node = Node::new_from_bytes(&bytes[PAGE_HEADER_BYTES..], header.version);
+                    writeln!(&mut result, "{node:?}").unwrap();
+                }
+                Err(err) => writeln!(&mut result, "{err}").unwrap(),
+            }
         }
         result
     }
 }
 
+// Internal node of the children, given by page id and max key, where the
+// last one is the right child.
+fn internal_node(children: &[(usize, u32)], is_root: bool) -> Node {
+    let (right_child, rest) = children.split_last().unwrap();
+    let mut node = Node::new(is_root, NodeType::Internal);
+    node.internal_cells = rest
+        .iter()
+        .map(|(page_id, max_key)| InternalCell::new(*page_id as u32, *max_key))
+        .collect();
+    node.num_of_cells = rest.len() as u32;
+    node.right_child_offset = right_child.0 as u32;
+    node
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1623,27 +4016,73 @@ mod test {
 
         // We have 3 candidates that can be choose to
         // be evicted by our buffer pool.
-        replacer.unpin(2);
+        replacer.unpin(2, 0);
         sleep(5);
-        replacer.unpin(0);
+        replacer.unpin(0, 0);
         sleep(5);
-        replacer.unpin(1);
+        replacer.unpin(1, 0);
 
         let evicted_page = replacer.victim().unwrap();
         assert_eq!(evicted_page.frame_id, 2);
     }
 
+    #[test]
+    fn lru_replacer_evict_cold_page_first() {
+        let replacer = LRUReplacer::new(4);
+
+        replacer.unpin(2, 0);
+        sleep(5);
+        replacer.unpin_cold(0, 0);
+        sleep(5);
+        replacer.unpin(1, 0);
+        sleep(5);
+        replacer.unpin_cold(3, 0);
+
+        // Cold pages are evicted first, least recently accessed first.
+        assert_eq!(replacer.victim().unwrap().frame_id, 0);
+        assert_eq!(replacer.victim().unwrap().frame_id, 3);
+        assert_eq!(replacer.victim().unwrap().frame_id, 2);
+        assert_eq!(replacer.victim().unwrap().frame_id, 1);
+    }
+
+    #[test]
+    fn lfu_replacer_evict_least_frequently_accessed_page() {
+        let mut replacer = LRUReplacer::new(4);
+        replacer.policy = ReplacementPolicy::Lfu;
+        for _ in 0..3 {
+            replacer.pin(0);
+        }
+        replacer.pin(1);
+        replacer.pin(2);
+        replacer.pin(2);
+
+        replacer.unpin(1, 0);
+        sleep(5);
+        replacer.unpin(2, 0);
+        sleep(5);
+        replacer.unpin(0, 0);
+
+        // Even though frame 0 is the most recently accessed.
+        assert_eq!(replacer.victim().unwrap().frame_id, 1);
+        assert_eq!(replacer.victim().unwrap().frame_id, 2);
+
+        // The count starts over once the frame holds another page.
+        replacer.pin(1);
+        replacer.unpin(1, 0);
+        assert_eq!(replacer.victim().unwrap().frame_id, 1);
+    }
+
     #[test]
     fn lru_replacer_do_not_evict_pin_page() {
         let replacer = LRUReplacer::new(4);
 
         // We have 3 candidates that can be choose to
         // be evicted by our buffer pool.
-        replacer.unpin(2);
+        replacer.unpin(2, 0);
         sleep(5);
-        replacer.unpin(0);
+        replacer.unpin(0, 0);
         sleep(5);
-        replacer.unpin(1);
+        replacer.unpin(1, 0);
         replacer.pin(2);
 
         let evicted_page = replacer.victim().unwrap();
@@ -1661,10 +4100,10 @@ mod test {
         let replacer = Arc::new(LRUReplacer::new(4));
 
         let re = replacer.clone();
-        let handle = thread::spawn(move || re.unpin(2));
+        let handle = thread::spawn(move || re.unpin(2, 0));
 
         let re = replacer.clone();
-        let handle2 = thread::spawn(move || re.unpin(3));
+        let handle2 = thread::spawn(move || re.unpin(3, 0));
 
         handle.join().unwrap();
         handle2.join().unwrap();
@@ -1768,9 +4207,9 @@ mod test {
     //     setup_test_db_file();
     //     let pager = setup_test_pager();
 
-    //     let page = pager.fetch_read_page_with_retry(0);
+    //     let page = pager.fetch_read_page_with_retry(0).unwrap();
     //     drop(page);
-    //     let page = pager.fetch_read_page_with_retry(0);
+    //     let page = pager.fetch_read_page_with_retry(0).unwrap();
 
     //     pager.unpin_page_with_read_guard(page, true);
     //     assert_eq!(pager.replacer.size(), 0);
@@ -1843,10 +4282,686 @@ mod test {
     //     cleanup_test_db_file();
     // }
 
+    #[test]
+    fn writers_flush_dirty_pages_when_over_the_limit() {
+        let pager = setup_test_pager();
+        pager.set_max_dirty_percent(25);
+
+        for i in 1..50 {
+            let row = wide_row(i);
+            pager.insert_row(0, &row).unwrap();
+        }
+        assert!(pager.throttled_flushes() > 0);
+
+        // Nothing is pinned now, so we can get back under the limit.
+        pager.throttle_writes();
+        assert!(pager.dirty_pages() <= 2);
+        assert!(pager.dirty_ratio() <= 0.25);
+
+        // The count is kept in step with the frames, without scanning them.
+        let dirty_frames = pager.pool.pages.iter().filter(|page| page.read().is_dirty);
+        assert_eq!(pager.dirty_pages(), dirty_frames.count());
+
+        // Flushed pages are still readable after being evicted.
+        let mut rows = 0;
+        pager.for_each_row(0, |_| rows += 1);
+        assert_eq!(rows, 49);
+        cleanup_test_db_file();
+
+        let pager = setup_test_pager();
+        pager.set_max_dirty_percent(100);
+        for i in 1..50 {
+            let row = wide_row(i);
+            pager.insert_row(0, &row).unwrap();
+        }
+        assert_eq!(pager.throttled_flushes(), 0);
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn retry_flush_after_torn_and_failed_writes() {
+        use crate::storage::disk_manager::Fault;
+
+        let pager = setup_test_pager();
+        for i in 1..30 {
+            let row = wide_row(i);
+            pager.insert_row(0, &row).unwrap();
+        }
+
+        pager.disk_manager().inject_fault(0, Fault::TornWrite);
+        assert!(pager.flush_all_pages().is_err());
+
+        pager.disk_manager().inject_fault(1, Fault::FailWrite);
+        assert!(pager.flush_all_pages().is_err());
+
+        pager.disk_manager().inject_fault(0, Fault::FailSync);
+        assert!(pager.flush_all_pages().is_err());
+
+        // Retrying the flush rewrite every page, including the torn one.
+        pager.flush_all_pages().unwrap();
+        drop(pager);
+
+        let pager = setup_test_pager();
+        let mut rows = 0;
+        pager.for_each_row(0, |_| rows += 1);
+        assert_eq!(rows, 29);
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn pin_scope_unpin_pages_on_drop() {
+        setup_test_db_file();
+        let pager = setup_test_pager();
+
+        let mut scope = PinScope::new(&pager);
+        scope.push(pager.fetch_write_page_guard(0).unwrap(), false);
+        scope.push(pager.fetch_write_page_guard(1).unwrap(), true);
+        scope.push(pager.fetch_write_page_guard(2).unwrap(), false);
+
+        // The page popped out of the scope is unpinned by the caller.
+        let page = scope.pop().unwrap();
+        pager.unpin_page_with_write_guard(page, false);
+        drop(scope);
+
+        let page_table = pager.pool.page_table.read();
+        for (page_id, is_dirty) in [(0, false), (1, true), (2, false)] {
+            let frame_id = page_table[&(pager.file_id, page_id)];
+            let page = pager.pool.pages[frame_id].read();
+            assert_eq!(page.pin_count, 0);
+            assert_eq!(page.is_dirty, is_dirty);
+        }
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn scan_range_across_leaves() {
+        let pager = setup_test_pager();
+        for i in (2..60).step_by(2) {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            pager.insert_row(0, &row).unwrap();
+        }
+
+        let ids = |start, end| -> Vec<u32> {
+            pager
+                .scan_range(0, start, end)
+                .iter()
+                .map(|row| row.id)
+                .collect()
+        };
+        assert_eq!(ids(11, Some(21)), vec![12, 14, 16, 18, 20]);
+        assert_eq!(ids(50, None), vec![50, 52, 54, 56, 58]);
+        assert_eq!(ids(0, Some(5)), vec![2, 4]);
+        assert!(ids(59, None).is_empty());
+        assert!(ids(30, Some(30)).is_empty());
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn scrub_report_corrupted_rows() {
+        use super::super::node::{LEAF_NODE_HEADER_SIZE, LEAF_NODE_SLOT_SIZE};
+        use crate::storage::page::PAGE_HEADER_BYTES;
+
+        let pager = setup_test_pager();
+        for i in 1..6 {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            pager.insert_row(0, &row).unwrap();
+        }
+        assert!(pager.scrub(0).unwrap().is_empty());
+        pager.flush_all_pages().unwrap();
+        drop(pager);
+
+        // Flip a bit of the username of the third row on disk.
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let mut bytes = std::fs::read(&path).unwrap();
+        let slot = PAGE_HEADER_BYTES + LEAF_NODE_HEADER_SIZE + 2 * LEAF_NODE_SLOT_SIZE;
+        let cell = u16::from_le_bytes([bytes[slot], bytes[slot + 1]]) as usize;
+        bytes[PAGE_HEADER_BYTES + cell + 6] ^= 1;
+        std::fs::write(&path, bytes).unwrap();
+
+        let pager = setup_test_pager();
+        assert_eq!(pager.scrub(0).unwrap(), vec![(0, 3)]);
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn sync_check_compares_cached_pages_with_disk() {
+        use crate::storage::page::PAGE_HEADER_BYTES;
+
+        let pager = setup_test_pager();
+        for i in 1..6 {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            pager.insert_row(0, &row).unwrap();
+        }
+        // The root is dirty and isn't on disk yet.
+        let check = pager.sync_check(usize::MAX);
+        assert_eq!((check.checked, check.unflushed), (1, 1));
+        assert!(check.divergences.is_empty());
+
+        pager.flush_all_pages().unwrap();
+        assert_eq!(
+            pager.sync_check(usize::MAX).to_string(),
+            "checked 1 pages (4096 bytes read): 0 diverged, 0 unflushed, 0 skipped"
+        );
+        drop(pager);
+
+        // A clean page read back from disk serializes to the same bytes,
+        // until the file is changed underneath it.
+        let pager = setup_test_pager();
+        assert!(pager.find(0, None, 1).unwrap().is_some());
+        assert!(pager.sync_check(usize::MAX).divergences.is_empty());
+
+        let mut bytes = pager.disk_manager().read_page(0).unwrap();
+        bytes[PAGE_HEADER_BYTES + 100] ^= 1;
+        pager.disk_manager().write_page(0, &bytes).unwrap();
+        assert_eq!(
+            pager.sync_check(usize::MAX).divergences,
+            vec![format!(
+                "page 0 is clean but differs from disk in 1 bytes, from offset {}",
+                PAGE_HEADER_BYTES + 100
+            )]
+        );
+
+        let check = pager.sync_check(PAGE_SIZE - 1);
+        assert_eq!((check.checked, check.skipped, check.bytes_read), (0, 1, 0));
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn reject_writes_when_pool_is_smaller_than_tree_height() {
+        let pager = Pager::new(format!("test-{:?}.db", std::thread::current().id()), 4);
+
+        let mut inserted = 0;
+        let err = (1..1000)
+            .map(|i| Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap())
+            .find_map(|row| match pager.insert_row(0, &row) {
+                Ok(_) => {
+                    inserted += 1;
+                    None
+                }
+                Err(err) => Some(err),
+            })
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "buffer pool too small: a tree of height 4 needs at least 5 pages, but the pool only has 4"
+        );
+        assert_eq!(pager.tree_height(0).unwrap(), 4);
+
+        // Rows inserted before the tree outgrows the pool are still readable,
+        // and the other writes are rejected the same way.
+        let mut rows = 0;
+        pager.for_each_row(0, |_| rows += 1);
+        assert_eq!(rows, inserted);
+        let row = Row::from_str("1 user1 user1@email.com").unwrap();
+        assert_eq!(pager.delete(0, &row), Err(err));
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn return_error_for_corrupted_page() {
+        setup_test_db_file();
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[..PAGE_HEADER_BYTES].fill(0xff);
+        std::fs::write(&path, bytes).unwrap();
+
+        let pager = setup_test_pager();
+        // The frame is given back, so the page is read again next time.
+        for _ in 0..2 {
+            assert!(matches!(
+                pager.fetch_read_page_guard(0),
+                Err(DbError::PageCorrupted { page_id: 0, .. })
+            ));
+        }
+        assert_eq!(pager.pinned_pages(), 0);
+        assert!(pager.fetch_read_page_guard(1).is_ok());
+        drop(pager);
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn concurrent_insert_and_delete_under_smo_latch() {
+        for _ in 0..10 {
+            let pager = setup_test_pager();
+            pager.set_latch_protocol(LatchProtocol::SmoLatch);
+            for i in 0..100 {
+                let row = wide_row(i);
+                pager.insert_row(0, &row).unwrap();
+            }
+
+            std::thread::scope(|scope| {
+                for t in 0..4 {
+                    let pager = &pager;
+                    scope.spawn(move || {
+                        for i in (t..100).step_by(4) {
+                            let j = i + 100;
+                            let row = wide_row(j);
+                            pager.insert_row(0, &row).unwrap();
+                            assert_eq!(pager.delete_by_key(0, i), Ok(format!("deleted {i}")));
+                        }
+                    });
+                }
+            });
+
+            assert!(pager.smo_retries() > 0);
+            assert_eq!(pager.pinned_pages(), 0);
+            for i in 0..200 {
+                assert_eq!(
+                    pager.find(0, None, i).unwrap().is_some(),
+                    i >= 100,
+                    "key {i}"
+                );
+            }
+            drop(pager);
+            cleanup_test_db_file();
+        }
+    }
+
+    #[test]
+    fn bulk_load_builds_tree_bottom_up() {
+        let pager = setup_test_pager();
+        let rows =
+            (1..=169).map(|i| Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap());
+        assert!(pager.bulk_load(0, rows.clone(), 50).is_err());
+        assert_eq!(pager.bulk_load(0, rows.clone(), 90), Ok(169));
+        assert_eq!(
+            pager.bulk_load(0, rows, 90),
+            Err(DbError::InvalidInput(
+                "bulk load needs an empty tree".to_string()
+            ))
+        );
+
+        let mut ids = Vec::new();
+        pager.for_each_row(0, |row| ids.push(row.id));
+        assert_eq!(ids, (1..=169).collect::<Vec<u32>>());
+        assert_eq!(
+            pager.find(0, None, 123).unwrap().unwrap().username(),
+            "user123"
+        );
+        let row = Row::from_str("170 john john@email.com").unwrap();
+        pager.insert_row(0, &row).unwrap();
+        let row = Row::from_str("100 john john@email.com").unwrap();
+        assert_eq!(pager.insert_row(0, &row), Err(DbError::DuplicateKey));
+
+        // Leaves are packed, except the last two, which share their rows
+        // rather than leaving the last one with a row. Fills are sorted.
+        let stats = pager.tree_stats(0).unwrap();
+        assert_eq!(pager.tree_height(0).unwrap(), stats.height);
+        assert!(stats.leaf_fill[2..].iter().all(|fill| *fill >= 85));
+        assert!(stats.leaf_fill[0] >= 40);
+
+        // Rows out of order stop the load, keeping the rows before them.
+        let root_page_num = pager.create_tree().unwrap();
+        let rows = [1, 2, 3, 2, 4]
+            .map(|i| Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap());
+        assert_eq!(
+            pager.bulk_load(root_page_num, rows.into_iter(), 90),
+            Err(DbError::InvalidInput(
+                "bulk load needs rows in ascending order, but 2 follows 3".to_string()
+            ))
+        );
+        assert_eq!(
+            pager
+                .find(root_page_num, None, 3)
+                .unwrap()
+                .unwrap()
+                .username(),
+            "user3"
+        );
+        assert_eq!(pager.find(root_page_num, None, 4).unwrap(), None);
+        pager.flush_all_pages().unwrap();
+
+        let pager = setup_test_pager();
+        assert_eq!(pager.check_tree(0, usize::MAX), Vec::<String>::new());
+        assert_eq!(
+            pager.check_tree(root_page_num, usize::MAX),
+            Vec::<String>::new()
+        );
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn create_tree_in_the_same_file() {
+        let pager = setup_test_pager();
+        let root_page_num = pager.create_tree().unwrap();
+        assert_eq!(root_page_num, 1);
+
+        for i in 1..50 {
+            let row = wide_row(i);
+            pager.insert_row(root_page_num, &row).unwrap();
+        }
+        let row = Row::from_str("1 john john@email.com").unwrap();
+        pager.insert_row(0, &row).unwrap();
+
+        // Each tree keeps its own root and height.
+        assert_eq!(pager.tree_height(root_page_num).unwrap(), 3);
+        assert_eq!(pager.tree_height(0).unwrap(), 1);
+        assert_eq!(pager.find(0, None, 1).unwrap().unwrap().username(), "john");
+        assert_eq!(
+            pager.find(root_page_num, None, 49).unwrap(),
+            Some(wide_row(49))
+        );
+        assert_eq!(pager.find(0, None, 49).unwrap(), None);
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn check_tree_on_open() {
+        let pager = setup_test_pager();
+        assert!(pager.check_tree(0, CHECK_SAMPLE_PAGES).is_empty());
+        for i in 1..200 {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            pager.insert_row(0, &row).unwrap();
+        }
+        for i in (1..200).step_by(3) {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            pager.delete(0, &row);
+        }
+        pager.flush_all_pages().unwrap();
+        drop(pager);
+
+        let pager = setup_test_pager();
+        assert_eq!(pager.check_file(), Ok(()));
+        assert_eq!(
+            pager.check_tree(0, CHECK_SAMPLE_PAGES),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            pager.check_tree(100, CHECK_SAMPLE_PAGES),
+            vec![format!(
+                "catalog points to page 100, past the end of the file ({} pages)",
+                pager.disk_manager.file_len / PAGE_SIZE
+            )]
+        );
+
+        // Flip a byte in the body of a leaf, and cut the last page short.
+        let leaf = pager.search(0, 1).unwrap().0;
+        let mut bytes = pager.disk_manager.read_page(leaf).unwrap();
+        bytes[PAGE_SIZE - 1] ^= 1;
+        pager.disk_manager.write_page(leaf, &bytes).unwrap();
+        drop(pager);
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        std::io::Write::write_all(&mut file, &[0; 10]).unwrap();
+
+        let pager = setup_test_pager();
+        assert_eq!(
+            pager.check_tree(0, CHECK_SAMPLE_PAGES),
+            vec![format!(
+                "page {leaf} checksum mismatch, run .scrub to find the corrupted rows"
+            )]
+        );
+        let num_of_pages = pager.disk_manager.file_len / PAGE_SIZE;
+        assert_eq!(
+            pager.check_file(),
+            Err(format!(
+                "page {num_of_pages} is partially written, as the file is {} bytes, restore the file from a dump",
+                num_of_pages * PAGE_SIZE + 10
+            ))
+        );
+
+        // Only the root is read with a single page to sample.
+        assert!(pager.check_tree(0, 1).is_empty());
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn compute_tree_height_of_existing_tree() {
+        let pager = setup_test_pager();
+        assert_eq!(pager.tree_height(0).unwrap(), 1);
+        for i in 1..50 {
+            let row = wide_row(i);
+            pager.insert_row(0, &row).unwrap();
+        }
+        pager.flush_all_pages().unwrap();
+
+        // The height maintained on root splits is the same as the height
+        // computed from the tree on disk.
+        let height = pager.tree_height(0).unwrap();
+        assert_eq!(height, 3);
+        assert_eq!(setup_test_pager().tree_height(0), Ok(height));
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn snapshot_is_not_affected_by_later_writes() {
+        let pager = Arc::new(setup_test_pager());
+        for i in 1..50 {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            pager.insert_row(0, &row).unwrap();
+        }
+
+        let snapshot = pager.snapshot().unwrap();
+        assert_eq!(snapshot.num_of_copied_pages(), 0);
+
+        // Writers are not blocked while the snapshot is being read.
+        let writer = {
+            let pager = pager.clone();
+            std::thread::spawn(move || {
+                for i in 50..100 {
+                    let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+                    pager.insert_row(0, &row).unwrap();
+                }
+                for i in 1..10 {
+                    pager.delete_by_key(0, i);
+                }
+            })
+        };
+
+        let mut ids = Vec::new();
+        pager
+            .for_each_in_snapshot(&snapshot, 0, 0, None, |row| ids.push(row.id))
+            .unwrap();
+        writer.join().unwrap();
+        assert_eq!(ids, (1..50).collect::<Vec<_>>());
+        assert!(snapshot.num_of_copied_pages() > 0);
+
+        // Reading it again after the writes are done return the same rows.
+        let mut ids = Vec::new();
+        for (start, end) in pager.snapshot_key_ranges(&snapshot, 0).unwrap() {
+            pager
+                .for_each_in_snapshot(&snapshot, 0, start, end, |row| ids.push(row.id))
+                .unwrap();
+        }
+        assert_eq!(ids, (1..50).collect::<Vec<_>>());
+        let mut rows = 0;
+        pager.for_each_row(0, |_| rows += 1);
+        assert_eq!(rows, 90);
+
+        // Pages are no longer copied once the snapshot is dropped.
+        drop(snapshot);
+        let row = Row::from_str("100 user100 user100@email.com").unwrap();
+        pager.insert_row(0, &row).unwrap();
+        assert!(pager.snapshots.read().iter().all(|s| s.upgrade().is_none()));
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn reuse_deleted_pages() {
+        let pager = setup_test_pager();
+        for i in 1..200 {
+            pager.insert_row(0, &wide_row(i)).unwrap();
+        }
+        for i in 1..150 {
+            pager.delete_by_key(0, i);
+        }
+        let num_of_pages = pager.next_page_id.load(Ordering::Acquire);
+        let num_of_free_pages = pager.num_of_free_pages();
+        assert!(num_of_free_pages > 0);
+
+        // Deleted pages are linked into the free page list on flush, and
+        // the first one is kept in the header of page 0.
+        pager.flush_all_pages().unwrap();
+        assert!(pager.freed_pages.lock().is_empty());
+        let free_page = pager.disk_manager.free_page();
+        let header = PageHeader::decode(&pager.disk_manager.read_page(0).unwrap()).unwrap();
+        assert_eq!(header.free_page, free_page);
+        let header = PageHeader::decode(&pager.disk_manager.read_page(free_page).unwrap());
+        assert_eq!(header.unwrap().page_type, PageType::Free);
+        drop(pager);
+
+        // They're reused once the file is opened again, before the file
+        // grows.
+        let pager = setup_test_pager();
+        assert_eq!(pager.num_of_free_pages(), num_of_free_pages);
+        assert_eq!(
+            pager.check_tree(0, CHECK_SAMPLE_PAGES),
+            Vec::<String>::new()
+        );
+        for i in 1..150 {
+            pager.insert_row(0, &wide_row(i)).unwrap();
+        }
+        assert_eq!(pager.num_of_free_pages(), 0);
+        assert_eq!(pager.disk_manager.free_page(), 0);
+        assert!(pager.next_page_id.load(Ordering::Acquire) - num_of_pages < num_of_free_pages);
+
+        pager.flush_all_pages().unwrap();
+        drop(pager);
+        let pager = setup_test_pager();
+        assert_eq!(
+            pager.check_tree(0, CHECK_SAMPLE_PAGES),
+            Vec::<String>::new()
+        );
+        let mut rows = 0;
+        pager.for_each_row(0, |_| rows += 1);
+        assert_eq!(rows, 199);
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn load_hot_pages_saved_at_clean_shutdown() {
+        let pager = setup_test_pager();
+        for i in 1..50 {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            pager.insert_row(0, &row).unwrap();
+        }
+        pager.flush_all_pages().unwrap();
+        pager.save_hot_pages().unwrap();
+
+        let mut cached: Vec<usize> = pager.pool.page_table.read().keys().map(|k| k.1).collect();
+        cached.sort_unstable();
+        drop(pager);
+
+        let pager = setup_test_pager();
+        let mut loaded: Vec<usize> = pager.pool.page_table.read().keys().map(|k| k.1).collect();
+        loaded.sort_unstable();
+        assert_eq!(loaded, cached);
+        assert_eq!(pager.pinned_pages(), 0);
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn share_buffer_pool_between_files() {
+        let pool = Arc::new(BufferPool::new(8));
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let other_path = format!("test-{:?}-other.db", std::thread::current().id());
+        let pager = Pager::with_pool(&path, pool.clone());
+        let other_pager = Pager::with_pool(&other_path, pool.clone());
+
+        // Neither tree fits in the pool with the other one, so pages of
+        // both files keep evicting each other.
+        for i in 1..50 {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            pager.insert_row(0, &row).unwrap();
+            let row = Row::from_str(&format!("{i} other{i} other{i}@email.com")).unwrap();
+            other_pager.insert_row(0, &row).unwrap();
+        }
+
+        let mut rows = Vec::new();
+        pager.for_each_row(0, |row| rows.push(row.to_string()));
+        assert_eq!(rows.len(), 49);
+        assert!(rows.iter().all(|row| row.contains("user")));
+        let mut rows = Vec::new();
+        other_pager.for_each_row(0, |row| rows.push(row.to_string()));
+        assert_eq!(rows.len(), 49);
+        assert!(rows.iter().all(|row| row.contains("other")));
+
+        // Frames of a file are freed once its pager is dropped.
+        other_pager.flush_all_pages().unwrap();
+        let other_file_id = other_pager.file_id;
+        drop(other_pager);
+        let page_table = pool.page_table.read();
+        assert!(page_table
+            .keys()
+            .all(|(file_id, _)| *file_id != other_file_id));
+        assert_eq!(pool.free_list.lock().len(), 8 - page_table.len());
+        drop(page_table);
+
+        let other_pager = Pager::with_pool(&other_path, pool);
+        let mut rows = 0;
+        other_pager.for_each_row(0, |_| rows += 1);
+        assert_eq!(rows, 49);
+
+        let _ = std::fs::remove_file(other_path);
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn delete_page_keeps_frames_of_other_files_evictable() {
+        let pool = Arc::new(BufferPool::new(8));
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let other_path = format!("test-{:?}-other.db", std::thread::current().id());
+        let other_pager = Pager::with_pool(&other_path, pool.clone());
+        other_pager.create_tree().unwrap();
+        let pager = Pager::with_pool(&path, pool.clone());
+        let root_page_num = pager.create_tree().unwrap();
+
+        // The page ids of both files overlap, and the other file holds the
+        // frame whose id is the one of the page deleted.
+        assert_eq!(pool.replacer.page_table.read().len(), 4);
+        assert_eq!(pager.free_tree(root_page_num).unwrap(), 1);
+        assert_eq!(pool.replacer.page_table.read().len(), 3);
+        assert_eq!(pool.free_list.lock().len(), 5);
+        for _ in 0..3 {
+            assert!(pool.replacer.victim().is_some());
+        }
+
+        drop(other_pager);
+        let _ = std::fs::remove_file(format!("{other_path}.hot"));
+        let _ = std::fs::remove_file(other_path);
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn evict_from_file_with_most_frames_first() {
+        let replacer = LRUReplacer::new(4);
+        replacer.unpin(0, 1);
+        replacer.unpin(1, 0);
+        replacer.unpin(2, 0);
+        replacer.unpin(3, 0);
+
+        // Even though the frame of file 1 is the least recently used.
+        assert_eq!(replacer.victim().unwrap().file_id, 0);
+        assert_eq!(replacer.victim().unwrap().file_id, 0);
+
+        // Cold frames are still evicted first.
+        replacer.unpin_cold(1, 1);
+        assert_eq!(replacer.victim().unwrap().frame_id, 1);
+    }
+
     fn setup_test_table() -> Table {
         Table::new(format!("test-{:?}.db", std::thread::current().id()), 8)
     }
 
+    // A row with the longest username and email, so a leaf holds 13 of
+    // them, which the tests of the shape of the tree are written for.
+    fn wide_row(i: u32) -> Row {
+        Row::new(
+            &i.to_string(),
+            &format!("{:x<32}", format!("user{i}")),
+            &format!("{:x<255}", format!("user{i}@email.com")),
+        )
+        .unwrap()
+    }
+
     fn setup_test_pager() -> Pager {
         Pager::new(format!("test-{:?}.db", std::thread::current().id()), 8)
     }
@@ -1864,6 +4979,7 @@ mod test {
 
     fn cleanup_test_db_file() {
         let _ = std::fs::remove_file(format!("test-{:?}.db", std::thread::current().id()));
+        let _ = std::fs::remove_file(format!("test-{:?}.db.hot", std::thread::current().id()));
     }
 
     fn sleep(duration_in_ms: u64) {
@@ -0,0 +1,54 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+
+use super::Page;
+
+/// A read-only view of the tree, frozen at the time it's taken.
+///
+/// Taking a snapshot flushes every page to disk first, so the file
+/// holds the snapshot version of every page. Afterwards, the first
+/// write to a page copies its bytes aside before it's modified. Readers
+/// of the snapshot read the copy if there is one, or the page on disk
+/// otherwise, without going through the buffer pool or its latches.
+///
+/// See `Pager::snapshot`.
+#[derive(Debug)]
+pub struct Snapshot {
+    // Pages allocated after the snapshot is taken are never
+    // reachable from it, so they don't need to be copied.
+    num_of_pages: usize,
+    pages: Mutex<HashMap<usize, Vec<u8>>>,
+}
+
+impl Snapshot {
+    pub fn new(num_of_pages: usize) -> Self {
+        Self {
+            num_of_pages,
+            pages: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Copy the page aside if it's part of the snapshot and hasn't been
+    /// copied yet. Must be called before the page is modified.
+    pub fn preserve(&self, page_id: usize, page: &Page) {
+        if page_id >= self.num_of_pages || !page.has_node() {
+            return;
+        }
+
+        self.pages
+            .lock()
+            .entry(page_id)
+            .or_insert_with(|| page.as_bytes());
+    }
+
+    /// Return the copy of the page, if it has been modified after the
+    /// snapshot is taken.
+    pub fn get(&self, page_id: usize) -> Option<Vec<u8>> {
+        self.pages.lock().get(&page_id).cloned()
+    }
+
+    /// Number of pages copied aside so far.
+    pub fn num_of_copied_pages(&self) -> usize {
+        self.pages.lock().len()
+    }
+}
@@ -1,60 +1,2042 @@
-use crate::query::Statement;
+use crate::catalog::{Catalog, IndexInfo, DEFAULT_TABLE};
+use crate::concurrency::{
+    LockManager, Transaction, TransactionManager, TransactionState, WriteRecord, WriteRecordType,
+};
+use crate::error::DbError;
+use crate::export::arrow::{BatchBuilder, RecordBatch};
+use crate::index::SecondaryIndex;
+use crate::mapping;
+use crate::metrics::{prometheus_metric, LatencyMetrics, Operation, TreeMetrics};
+use crate::options::DbOptions;
+use crate::query::{
+    key_range, Expression, OrderBy, PageAction, ResultSet, Statement, Value, DEFAULT_MAX_TOP_N_ROWS,
+};
+use crate::recovery::{LogManager, LogRecord, LogRecordType, StatementLog};
 use crate::row::Row;
-use crate::storage::Pager;
+use crate::schema::{ColumnType, Schema};
+use crate::statistics::TableStatistics;
+use crate::storage::{
+    AccessPattern, BufferPool, Dictionary, Node, NodeType, Page, Pager, Snapshot,
+    CHECK_SAMPLE_PAGES,
+};
+use parking_lot::{Condvar, Mutex, RwLock, RwLockUpgradableReadGuard, RwLockWriteGuard};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::BufRead;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::warn;
+
+// Number of rows buffered and sorted before inserting them when copying.
+const COPY_CHUNK_SIZE: usize = 1000;
+
+// Space of the leaves filled by `Table::bulk_load`, leaving room for a
+// few inserts before they split.
+const BULK_LOAD_FILL_PERCENT: usize = 90;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CopyResult {
+    pub rows: usize,
+    // Line number, starting from 1, and the reason it's rejected. Line 0
+    // is for the whole copy, e.g. when the table is closed.
+    pub rejected: Vec<(usize, String)>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RowID {
+    page_id: usize,
+    slot_num: usize,
+}
+
+// Page of detached row ids, which no file can have as many pages as.
+const DETACHED_PAGE: usize = usize::MAX;
+
+impl RowID {
+    pub fn new(page_id: usize, slot_num: usize) -> Self {
+        Self { page_id, slot_num }
+    }
+
+    /// Row ID for rows that are not stored in any page, e.g. literal rows
+    /// produced by a values plan node.
+    pub fn detached(slot_num: usize) -> Self {
+        Self {
+            page_id: DETACHED_PAGE,
+            slot_num,
+        }
+    }
+
+    pub fn is_detached(&self) -> bool {
+        self.page_id == DETACHED_PAGE
+    }
+}
 
+/// A table backed by a B+ tree, with two sets of methods:
+///
+/// - Auto-commit methods, e.g. `insert`, `delete` and `select`, used by
+///   the REPL, where each call is applied to the tree right away.
+/// - Transaction-scoped methods, e.g. `insert_in`, `delete_in`, `get` and
+///   `update`, used by the executors, where each write is recorded in the
+///   write set of the transaction, so `TransactionManager` can commit or
+///   roll it back.
+///
+/// Every table of a database file shares its pager and catalog, where
+/// the table opened by `new` is the default one, rooted at page 0.
 pub struct Table {
+    name: String,
     root_page_num: usize,
-    pager: Pager,
+    pager: Arc<Pager>,
+    catalog: Arc<Catalog>,
+    lock_manager: Arc<LockManager>,
+    // Begins the transactions of the REPL, e.g. a session between `begin`
+    // and `commit`, so their ids are unique across the lock manager.
+    transaction_manager: Arc<TransactionManager>,
+    // Dictionary the string fields of the rows written are encoded by,
+    // if the table is analyzed. See `Table::analyze`.
+    dictionary: RwLock<Option<Arc<Dictionary>>>,
+    // Secondary indexes of the table, see `Table::create_index`.
+    indexes: RwLock<Vec<Arc<SecondaryIndex>>>,
+    // See `Table::with_statement_log`.
+    statement_log: Option<Arc<StatementLog>>,
+    // Shape of the tree over time, sampled by `Table::stats`.
+    metrics: Mutex<TreeMetrics>,
+    // Latency of the operations on the tables of the file, see
+    // `Table::metrics`.
+    latency: Arc<LatencyMetrics>,
+    // See `DbOptions::with_max_top_n_rows`.
+    max_top_n_rows: usize,
+    // See `Table::read_only`.
+    read_only: bool,
+    ops: Mutex<Ops>,
+    ops_done: Condvar,
+}
+
+// Operations in flight, which `Table::close` waits for.
+#[derive(Default)]
+struct Ops {
+    in_flight: usize,
+    closed: bool,
+}
+
+/// An operation in flight on a table, which keeps the table from being
+/// closed until it's dropped.
+pub struct TableOp<'a> {
+    table: &'a Table,
+}
+
+impl Drop for TableOp<'_> {
+    fn drop(&mut self) {
+        let mut ops = self.table.ops.lock();
+        ops.in_flight -= 1;
+        if ops.in_flight == 0 {
+            self.table.ops_done.notify_all();
+        }
+    }
+}
+
+/// Iterator over the rows of a table, in key order.
+///
+/// Each leaf is copied, rather than latched while its rows are returned,
+/// as the caller might wait for the lock of a row in between. Once the
+/// copy is exhausted, the leaf is latched again to move to the next one
+/// hand-over-hand, see `Pager::leaf_after`, where the rows after the last
+/// key returned are looked up, so rows inserted into the leaf since are
+/// returned too.
+pub struct TableIntoIter {
+    pager: Arc<Pager>,
+    root_page_num: usize,
+    node: Option<Node>,
+    page_id: usize,
+    slot_num: usize,
+    // Key of the last row returned, if any.
+    last_key: Option<u32>,
+    access_pattern: AccessPattern,
+    // Stop before the first row with a key not smaller than the end key.
+    end_key: Option<u32>,
+    // Error that ended the iterator early, see `take_error`.
+    error: Option<DbError>,
+}
+
+impl TableIntoIter {
+    // Move to the leaf of the row after the last key, starting from the
+    // given leaf, or end the iterator past the last row.
+    fn seek(&mut self, page: RwLockUpgradableReadGuard<Page>) {
+        let pager = self.pager.clone();
+        let leaf = pager.leaf_after(self.root_page_num, page, self.last_key, self.access_pattern);
+        self.node = None;
+        if let Some((page, slot_num)) = leaf {
+            self.page_id = page.page_id.unwrap();
+            self.node = page.node().cloned();
+            self.slot_num = slot_num;
+            pager.unpin_page_with_hint(page, self.access_pattern);
+        }
+    }
+
+    /// Take the error that ended the iterator before the last row, e.g.
+    /// the next leaf couldn't be fetched, if any.
+    pub fn take_error(&mut self) -> Option<DbError> {
+        self.error.take()
+    }
+}
+
+impl Iterator for TableIntoIter {
+    type Item = (RowID, Row);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.node.as_ref()?;
+            if self.slot_num >= node.num_of_cells as usize {
+                let pager = self.pager.clone();
+                match pager.fetch_read_page_with_retry(self.page_id) {
+                    Ok(page) => self.seek(page),
+                    Err(err) => {
+                        self.node = None;
+                        self.error = Some(err);
+                    }
+                }
+                continue;
+            }
+
+            let rid = RowID::new(self.page_id, self.slot_num);
+            let item = self.pager.decode_row(node.get_row(self.slot_num)?);
+            if self.end_key.is_some_and(|end_key| item.id >= end_key) {
+                self.node = None;
+                return None;
+            }
+
+            self.slot_num += 1;
+            self.last_key = Some(item.id);
+            return Some((rid, item));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScanDirection {
+    Forward,
+    Backward,
+}
+
+/// Position of a scan that's resumed across calls, e.g. the pages of a
+/// paginated select, see `Table::scan`.
+///
+/// Unlike the cursor of the pager, which points to a page and cell that
+/// are only valid while the page is latched, it's resumed from the last
+/// key returned, so it survives splits and merges in between, and can
+/// be handed to a client as a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanCursor {
+    // Last key returned, or `None` if the scan hasn't returned any row.
+    pub last_key: Option<u32>,
+    pub direction: ScanDirection,
+    // Epoch of the pager when the scan started, see `Pager::epoch`.
+    pub epoch: u64,
+}
+
+impl ScanCursor {
+    pub fn to_token(self) -> String {
+        bincode::serialize(&self)
+            .unwrap()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    pub fn from_token(token: &str) -> Result<ScanCursor, String> {
+        let invalid = || format!("invalid scan cursor '{token}'");
+        if !token.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+
+        let bytes = (0..token.len())
+            .step_by(2)
+            .map(|i| {
+                token
+                    .get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        bincode::deserialize(&bytes).map_err(|_| invalid())
+    }
+}
+
+// Open the catalog of the file, or one with only the default table if it
+// can't be read, which `Table::check` reports, so the file can still be
+// inspected, e.g. by `sqlite check`.
+fn open_catalog(path: impl AsRef<Path>) -> Catalog {
+    Catalog::open(&path).unwrap_or_else(|err| {
+        warn!("{err}");
+        Catalog::unreadable(path, err)
+    })
 }
 
 impl Table {
     pub fn new(path: impl AsRef<Path>, pool_size: usize) -> Table {
-        let pager = Pager::new(path, pool_size);
+        Self::with_options(path, &DbOptions::new().with_pool_size(pool_size))
+    }
+
+    /// Same as `new`, but tuned by the options, e.g. to sync every page
+    /// written, or to time out lock waits.
+    pub fn with_options(path: impl AsRef<Path>, options: &DbOptions) -> Table {
+        let catalog = open_catalog(&path);
+        let mut pager = Pager::with_options(path, options);
+        if let Some(wal) = options.wal() {
+            pager = pager.with_log_manager(Arc::new(LogManager::new(wal)));
+        }
+        let lock_manager = match options.lock_timeout() {
+            Some(timeout) => LockManager::with_timeout(timeout),
+            None => LockManager::new(),
+        };
+        let mut table = Self::with_pager(pager, catalog, Arc::new(lock_manager));
+        table.max_top_n_rows = options.max_top_n_rows();
+        table
+    }
+
+    /// Same as `new`, but share the lock manager with the executors of
+    /// transactions running against this table.
+    pub fn with_lock_manager(
+        path: impl AsRef<Path>,
+        pool_size: usize,
+        lock_manager: Arc<LockManager>,
+    ) -> Table {
+        let catalog = open_catalog(&path);
+        Self::with_pager(Pager::new(path, pool_size), catalog, lock_manager)
+    }
+
+    /// Same as `new`, but write the changes to the rows ahead to the log
+    /// before their pages are written to the file.
+    pub fn with_log_manager(
+        path: impl AsRef<Path>,
+        pool_size: usize,
+        log_manager: Arc<LogManager>,
+    ) -> Table {
+        let catalog = open_catalog(&path);
+        Self::with_pager(
+            Pager::new(path, pool_size).with_log_manager(log_manager),
+            catalog,
+            Arc::new(LockManager::new()),
+        )
+    }
+
+    /// Same as `new`, but cache the pages in a buffer pool shared with
+    /// other tables, e.g. a server hosting several small databases.
+    pub fn with_buffer_pool(path: impl AsRef<Path>, pool: Arc<BufferPool>) -> Table {
+        let catalog = open_catalog(&path);
+        Self::with_pager(
+            Pager::with_pool(path, pool),
+            catalog,
+            Arc::new(LockManager::new()),
+        )
+    }
+
+    fn with_pager(pager: Pager, catalog: Catalog, lock_manager: Arc<LockManager>) -> Table {
+        let dictionary = Self::load_dictionary(&pager, &catalog, DEFAULT_TABLE);
+        let indexes = Self::load_indexes(&pager, &catalog, DEFAULT_TABLE, 0);
         Table {
+            name: DEFAULT_TABLE.to_string(),
             root_page_num: 0,
-            pager,
+            pager: Arc::new(pager),
+            catalog: Arc::new(catalog),
+            transaction_manager: Arc::new(TransactionManager::new(lock_manager.clone())),
+            lock_manager,
+            dictionary: RwLock::new(dictionary),
+            indexes: RwLock::new(indexes),
+            statement_log: None,
+            ops: Mutex::new(Ops::default()),
+            ops_done: Condvar::new(),
+            metrics: Mutex::new(TreeMetrics::new()),
+            latency: Arc::new(LatencyMetrics::new()),
+            max_top_n_rows: DEFAULT_MAX_TOP_N_ROWS,
+            read_only: false,
+        }
+    }
+
+    /// Reject every write with `DbError::ReadOnly`, e.g. for a replica of
+    /// a database serving reads from a buffer pool shared with other
+    /// files, see `with_buffer_pool`. Tables opened from it are read-only
+    /// too.
+    ///
+    /// TRADEOFF: Pages are cached per pager, so a replica of a file that
+    /// another pager writes to only sees the writes flushed before its
+    /// pages are loaded.
+    pub fn read_only(mut self) -> Table {
+        self.read_only = true;
+        self
+    }
+
+    fn check_writable(&self) -> Result<(), DbError> {
+        if self.read_only {
+            return Err(DbError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Append the committed writes to the tables of the file to the
+    /// statement log, as statements replaying them on a fresh database.
+    /// Auto-commit writes are logged as a transaction of their own.
+    pub fn with_statement_log(mut self, statement_log: Arc<StatementLog>) -> Table {
+        self.statement_log = Some(statement_log);
+        self
+    }
+
+    // A dictionary that can't be read only stops new rows from being
+    // encoded by it, so it's logged instead of failing to open the table.
+    fn load_dictionary(pager: &Pager, catalog: &Catalog, name: &str) -> Option<Arc<Dictionary>> {
+        let page_id = catalog.get(name)?.dictionary_page?;
+        pager
+            .dictionary(page_id)
+            .map_err(|err| warn!("fail to load dictionary of table '{name}': {err}"))
+            .ok()
+    }
+
+    // Rebuild the indexes of the table from its rows, as they're only kept
+    // in memory, see `SecondaryIndex`.
+    fn load_indexes(
+        pager: &Pager,
+        catalog: &Catalog,
+        name: &str,
+        root_page_num: usize,
+    ) -> Vec<Arc<SecondaryIndex>> {
+        let indexes: Vec<_> = catalog
+            .indexes(name)
+            .iter()
+            .map(|index| Arc::new(SecondaryIndex::new(&index.column).with_unique(index.unique)))
+            .collect();
+        if !indexes.is_empty() {
+            pager.for_each_row(root_page_num, |row| {
+                for index in &indexes {
+                    if let Err(err) = index.insert(&row) {
+                        warn!("fail to index row {} of table '{name}': {err}", row.id);
+                    }
+                }
+            });
+        }
+        indexes
+    }
+
+    /// Open a table with the given schema, which has to match the row
+    /// layout, see `Schema::users`.
+    pub fn create(
+        path: impl AsRef<Path>,
+        pool_size: usize,
+        schema: &Schema,
+    ) -> Result<Table, String> {
+        if *schema != Schema::users() {
+            return Err("only the (id, username, email) schema is supported for now".to_string());
+        }
+        Ok(Table::new(path, pool_size))
+    }
+
+    /// Create another table in the same database file, with its own tree
+    /// and an entry in the catalog, so it can be opened by `open_table`.
+    pub fn create_table(&self, name: &str, schema: &Schema) -> Result<(), DbError> {
+        self.create_table_with_root(name, schema)?;
+        self.log_statements(vec![format!("create table {name} {}", schema.to_sql())]);
+        Ok(())
+    }
+
+    /// Create the table within the transaction, see `create_table` for
+    /// the auto-commit version. The table is removed from the catalog if
+    /// the transaction aborts, so a failed migration doesn't leave half
+    /// of its tables behind.
+    ///
+    /// TRADEOFF: There are no locks on the catalog yet, so other
+    /// transactions see the table before it's committed, and lose their
+    /// writes to it if it's rolled back.
+    ///
+    /// TODO (Recovery): A crash before the commit keeps the table, until
+    /// recovery rolls back the `CreateTable` records of the transactions
+    /// without a commit record.
+    pub fn create_table_in(
+        &self,
+        name: &str,
+        schema: &Schema,
+        transaction: &mut RwLockWriteGuard<Transaction>,
+    ) -> Result<(), DbError> {
+        let root_page_num = self.create_table_with_root(name, schema)?;
+        self.append_log(transaction, |txn_id, prev_lsn| {
+            LogRecord::table_change(
+                txn_id,
+                prev_lsn,
+                LogRecordType::CreateTable,
+                name,
+                root_page_num,
+            )
+        });
+        transaction.push_write_set(WriteRecord::create_table(name));
+        transaction.push_statement(format!("create table {name} {}", schema.to_sql()));
+        Ok(())
+    }
+
+    /// Remove the table created by an aborted transaction from the
+    /// catalog, and return the pages of its tree to the free page list.
+    pub fn rollback_create_table(&self, name: &str, transaction: &mut Transaction) {
+        match self.catalog.remove(name) {
+            Ok(info) => {
+                self.append_log(transaction, |txn_id, prev_lsn| {
+                    LogRecord::table_change(
+                        txn_id,
+                        prev_lsn,
+                        LogRecordType::DropTable,
+                        name,
+                        info.root_page_num,
+                    )
+                });
+                if let Err(err) = self.pager.free_tree(info.root_page_num) {
+                    warn!("fail to free the pages of table '{name}': {err}");
+                }
+            }
+            Err(err) => warn!("fail to roll back the creation of table '{name}': {err}"),
+        }
+    }
+
+    fn create_table_with_root(&self, name: &str, schema: &Schema) -> Result<usize, DbError> {
+        self.check_writable()?;
+        if *schema != Schema::users() {
+            return Err(DbError::InvalidInput(
+                "only the (id, username, email) schema is supported for now".to_string(),
+            ));
+        }
+        let _op = self.enter()?;
+
+        // The error of the tree is kept aside, as the catalog only passes
+        // messages through.
+        let mut tree_error = None;
+        let created = self.catalog.create(name, schema, || {
+            let created = self.pager.create_tree().and_then(|root_page_num| {
+                self.pager.flush_all_pages()?;
+                Ok(root_page_num)
+            });
+            created.map_err(|err| {
+                let reason = err.to_string();
+                tree_error = Some(err);
+                reason
+            })
+        });
+        match (created, tree_error) {
+            (Ok(info), _) => Ok(info.root_page_num),
+            (Err(_), Some(err)) => Err(err),
+            (Err(reason), None) => Err(DbError::Catalog(reason)),
+        }
+    }
+
+    /// Open a table of the same database file by its name, sharing the
+    /// pager and the lock manager with this table.
+    ///
+    /// TRADEOFF: Each table is closed on its own, so closing the default
+    /// table doesn't reject operations on the tables opened from it.
+    pub fn open_table(&self, name: &str) -> Result<Table, DbError> {
+        let info = self
+            .catalog
+            .get(name)
+            .ok_or_else(|| DbError::Catalog(format!("unknown table '{name}'")))?;
+
+        Ok(Table {
+            name: name.to_string(),
+            root_page_num: info.root_page_num,
+            pager: self.pager.clone(),
+            catalog: self.catalog.clone(),
+            lock_manager: self.lock_manager.clone(),
+            transaction_manager: self.transaction_manager.clone(),
+            dictionary: RwLock::new(Self::load_dictionary(&self.pager, &self.catalog, name)),
+            indexes: RwLock::new(Self::load_indexes(
+                &self.pager,
+                &self.catalog,
+                name,
+                info.root_page_num,
+            )),
+            statement_log: self.statement_log.clone(),
+            ops: Mutex::new(Ops::default()),
+            ops_done: Condvar::new(),
+            metrics: Mutex::new(TreeMetrics::new()),
+            latency: self.latency.clone(),
+            max_top_n_rows: self.max_top_n_rows,
+            read_only: self.read_only,
+        })
+    }
+
+    /// Run `f` on the table with the given name, which is this table if
+    /// no name is given, e.g. for statements that don't name a table.
+    pub fn with_table<T>(
+        &self,
+        name: Option<&str>,
+        f: impl FnOnce(&Table) -> T,
+    ) -> Result<T, DbError> {
+        match name {
+            Some(name) if name != self.name => Ok(f(&self.open_table(name)?)),
+            _ => Ok(f(self)),
         }
     }
 
+    pub fn table_names(&self) -> Vec<String> {
+        self.catalog.table_names()
+    }
+
+    pub fn catalog(&self) -> Arc<Catalog> {
+        self.catalog.clone()
+    }
+
+    pub fn lock_manager(&self) -> Arc<LockManager> {
+        self.lock_manager.clone()
+    }
+
+    pub fn transaction_manager(&self) -> Arc<TransactionManager> {
+        self.transaction_manager.clone()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn max_top_n_rows(&self) -> usize {
+        self.max_top_n_rows
+    }
+
     pub fn flush(&self) {
-        self.pager.flush_all_pages();
+        self.pager
+            .flush_all_pages()
+            .expect("fail to flush pages to disk");
+    }
+
+    /// Start an operation, or return an error if the table is closed.
+    ///
+    /// Auto-commit methods, e.g. `select`, `insert` and `delete`, start
+    /// their own operation. Callers running several methods for a single
+    /// request, e.g. a statement of a client, can hold one across them, so
+    /// the request either runs entirely or is rejected up front.
+    pub fn enter(&self) -> Result<TableOp<'_>, DbError> {
+        let mut ops = self.ops.lock();
+        if ops.closed {
+            return Err(DbError::TableClosed);
+        }
+
+        ops.in_flight += 1;
+        Ok(TableOp { table: self })
+    }
+
+    /// Close the table while it might still be shared with other threads.
+    ///
+    /// New operations are rejected with an error from now on, and the
+    /// pages are flushed once the operations in flight are done. The ids
+    /// of the cached pages are saved as well, so the next open starts
+    /// with them loaded.
+    pub fn close(&self) -> Result<(), DbError> {
+        let mut ops = self.ops.lock();
+        if ops.closed {
+            return Err(DbError::TableClosed);
+        }
+
+        ops.closed = true;
+        while ops.in_flight > 0 {
+            self.ops_done.wait(&mut ops);
+        }
+        drop(ops);
+
+        self.pager.flush_all_pages()?;
+        Ok(self.pager.save_hot_pages()?)
+    }
+
+    pub fn select(&self, statement: &Statement) -> String {
+        let Ok(_op) = self.enter() else {
+            return DbError::TableClosed.to_string();
+        };
+        self.query(statement).to_string()
+    }
+
+    /// Same as `select`, but return the rows as a `ResultSet`, e.g. to
+    /// export them as CSV.
+    pub fn query(&self, statement: &Statement) -> ResultSet {
+        ResultSet::new(self.select_rows(statement))
+    }
+
+    /// Same as `select`, but return the rows instead of printing them.
+    pub fn select_rows(&self, statement: &Statement) -> Vec<Row> {
+        let Ok(_op) = self.enter() else {
+            return Vec::new();
+        };
+        let started = Instant::now();
+        let page_num = self.root_page_num;
+        let rows = if let Some(row) = &statement.row {
+            match self.pager.find(page_num, None, row.id) {
+                Ok(row) => row.into_iter().collect(),
+                Err(err) => {
+                    warn!(
+                        "fail to select row {} of table '{}': {err}",
+                        row.id, self.name
+                    );
+                    Vec::new()
+                }
+            }
+        } else if let Some(predicate) = &statement.predicate {
+            self.select_matching(predicate)
+        } else {
+            let mut rows = Vec::new();
+            self.pager.for_each_row(page_num, |row| rows.push(row));
+            rows
+        };
+
+        // TODO (Planner): Same as a `SortExecutor` and a `LimitExecutor`,
+        // until statements are run through their plan.
+        let rows = if statement.order_by.is_empty() {
+            rows
+        } else {
+            let mut keyed: Vec<(Vec<Option<Value>>, Row)> = rows
+                .into_iter()
+                .map(|row| (OrderBy::sort_keys(&statement.order_by, &row), row))
+                .collect();
+            keyed.sort_by(|(a, _), (b, _)| OrderBy::compare_keys(&statement.order_by, a, b));
+            keyed.into_iter().map(|(_, row)| row).collect()
+        };
+        let rows = rows
+            .into_iter()
+            .skip(statement.offset)
+            .take(statement.limit.unwrap_or(usize::MAX))
+            .collect();
+        self.latency.record(select_operation(statement), started);
+        rows
+    }
+
+    // Only scan the leaves of the range of ids the predicate is
+    // restricted to, if any.
+    fn select_matching(&self, predicate: &Expression) -> Vec<Row> {
+        let mut rows = match key_range(predicate) {
+            Some((start, end)) => self.pager.scan_range(self.root_page_num, start, end),
+            None => {
+                let mut rows = Vec::new();
+                self.pager
+                    .for_each_row(self.root_page_num, |row| rows.push(row));
+                rows
+            }
+        };
+        rows.retain(|row| predicate.matches(row));
+        rows
+    }
+
+    pub fn insert(&self, row: &Row) -> String {
+        let Ok(_op) = self.enter() else {
+            return DbError::TableClosed.to_string();
+        };
+        match self.insert_encoded(row) {
+            Ok((page_num, cell_num)) => {
+                self.log_statements(vec![self.insert_statement(row)]);
+                format!("inserting into page: {page_num}, cell: {cell_num}...\n")
+            }
+            Err(err) => format!("{err}\n"),
+        }
+    }
+
+    /// Same as `insert`, but return where the row is inserted, or the
+    /// error, e.g. `DbError::DuplicateKey` if the key already exists.
+    pub fn insert_row(&self, row: &Row) -> Result<RowID, DbError> {
+        let _op = self.enter()?;
+        let (page_id, slot_num) = self.insert_encoded(row)?;
+        self.log_statements(vec![self.insert_statement(row)]);
+        Ok(RowID { page_id, slot_num })
+    }
+
+    // The row is indexed before it's inserted, so a unique index rejects
+    // it without touching the tree, and unindexed if the insert fails.
+    fn insert_encoded(&self, row: &Row) -> Result<(usize, usize), DbError> {
+        self.check_writable()?;
+        let indexes = self.indexes.read();
+        let indexed = index_row(&indexes, row)?;
+        let started = Instant::now();
+        let inserted = match self.encoded(row) {
+            Some(row) => self.pager.insert_row(self.root_page_num, &row),
+            None => self.pager.insert_row(self.root_page_num, row),
+        };
+        self.latency.record(Operation::Insert, started);
+        if inserted.is_err() {
+            indexed.iter().for_each(|index| index.remove(row));
+        }
+        let inserted = inserted?;
+        self.catalog.record_insert(&self.name, row);
+        Ok(inserted)
+    }
+
+    /// Same as `insert_batch`, but report each row the way `insert` does,
+    /// in the order they're given.
+    pub fn insert_many(&self, rows: &[Row]) -> String {
+        self.insert_batch(rows)
+            .into_iter()
+            .map(|result| match result {
+                Ok(RowID { page_id, slot_num }) => {
+                    format!("inserting into page: {page_id}, cell: {slot_num}...\n")
+                }
+                Err(err) => format!("{err}\n"),
+            })
+            .collect()
+    }
+
+    /// Insert the rows, returning where each of them is inserted, in the
+    /// order they're given, or why it's rejected, e.g.
+    /// `DbError::DuplicateKey` if its key already exists.
+    ///
+    /// The rows are sorted by key and inserted by `Pager::insert_rows`,
+    /// so the tree is descended once per leaf rather than once per row.
+    /// They're logged as one transaction.
+    pub fn insert_batch(&self, rows: &[Row]) -> Vec<Result<RowID, DbError>> {
+        let Ok(_op) = self.enter() else {
+            return rows.iter().map(|_| Err(DbError::TableClosed)).collect();
+        };
+        self.insert_sorted(rows)
+    }
+
+    fn insert_sorted(&self, rows: &[Row]) -> Vec<Result<RowID, DbError>> {
+        if let Err(err) = self.check_writable() {
+            return rows.iter().map(|_| Err(err.clone())).collect();
+        }
+        let mut order: Vec<usize> = (0..rows.len()).collect();
+        order.sort_by_key(|i| rows[*i].id);
+
+        let indexes = self.indexes.read();
+        let mut results = vec![None; rows.len()];
+        let mut indexed = Vec::with_capacity(rows.len());
+        for i in order {
+            match index_row(&indexes, &rows[i]) {
+                Ok(added) => indexed.push((i, added)),
+                Err(err) => results[i] = Some(Err(err)),
+            }
+        }
+
+        let encoded: Vec<Row> = indexed
+            .iter()
+            .map(|(i, _)| self.encoded(&rows[*i]).unwrap_or_else(|| rows[*i].clone()))
+            .collect();
+        let inserted = self.pager.insert_rows(self.root_page_num, &encoded);
+        let mut statements = Vec::new();
+        for ((i, added), inserted) in indexed.into_iter().zip(inserted) {
+            let row = &rows[i];
+            results[i] = Some(match inserted {
+                Ok((page_id, slot_num)) => {
+                    self.catalog.record_insert(&self.name, row);
+                    if self.statement_log.is_some() {
+                        statements.push(self.insert_statement(row));
+                    }
+                    Ok(RowID { page_id, slot_num })
+                }
+                Err(err) => {
+                    added.iter().for_each(|index| index.remove(row));
+                    Err(err)
+                }
+            });
+        }
+        self.log_statements(statements);
+
+        results.into_iter().map(Option::unwrap).collect()
+    }
+
+    /// Index the text column of the table, e.g. `username`, so a predicate
+    /// of `column = 'value'` looks the rows up through the index rather
+    /// than scanning the table, see `Planner`.
+    ///
+    /// A unique index can't be created if rows already share a value, and
+    /// rejects the writes giving a row the value of another one after.
+    pub fn create_index(&self, column: &str, unique: bool) -> Result<String, DbError> {
+        let _op = self.enter()?;
+        self.check_writable()?;
+        let schema = Schema::users();
+        let Some(def) = schema.column(column) else {
+            return Err(DbError::InvalidInput(format!("unknown column '{column}'")));
+        };
+        if def.options.primary_key {
+            return Err(DbError::InvalidInput(format!(
+                "column '{column}' is already indexed as the primary key"
+            )));
+        }
+        if !matches!(def.column_type, ColumnType::Text(_)) {
+            return Err(DbError::InvalidInput(format!(
+                "only Text columns can be indexed, not '{column}'"
+            )));
+        }
+
+        let index = SecondaryIndex::new(column).with_unique(unique);
+        let mut duplicate = None;
+        self.pager.for_each_row(self.root_page_num, |row| {
+            if let Err(err) = index.insert(&row) {
+                duplicate.get_or_insert(err);
+            }
+        });
+        if let Some(err) = duplicate {
+            return Err(DbError::UniqueViolation(format!(
+                "fail to create index on '{}.{column}': {err}",
+                self.name
+            )));
+        }
+
+        let info = IndexInfo {
+            column: column.to_string(),
+            unique,
+        };
+        self.catalog
+            .add_index(&self.name, info)
+            .map_err(DbError::Catalog)?;
+        self.indexes.write().push(Arc::new(index));
+        let kind = if unique { "unique index" } else { "index" };
+        Ok(format!("created {kind} on '{}.{column}'", self.name))
+    }
+
+    /// Ids of the rows with the value in the indexed column, or `None` if
+    /// the column isn't indexed.
+    pub fn index_lookup(&self, column: &str, value: &str) -> Option<Vec<u32>> {
+        self.indexes
+            .read()
+            .iter()
+            .find(|index| index.column() == column)
+            .map(|index| index.lookup(value))
+    }
+
+    /// Same as `index_lookup`, for the rows with a value in the range,
+    /// see `SecondaryIndex::lookup_range`.
+    pub fn index_range_lookup(
+        &self,
+        column: &str,
+        start: &str,
+        end: Option<&str>,
+    ) -> Option<Vec<u32>> {
+        self.indexes
+            .read()
+            .iter()
+            .find(|index| index.column() == column)
+            .map(|index| index.lookup_range(start, end))
+    }
+
+    // Remove the row with the key from the indexes, before it's removed
+    // from the tree.
+    fn unindex(&self, key: u32) {
+        let indexes = self.indexes.read();
+        if indexes.is_empty() {
+            return;
+        }
+        match self.pager.find(self.root_page_num, None, key) {
+            Ok(Some(row)) => indexes.iter().for_each(|index| index.remove(&row)),
+            Ok(None) => {}
+            Err(err) => warn!("fail to unindex row {key} of table '{}': {err}", self.name),
+        }
+    }
+
+    // The row with its string fields encoded by the dictionary of the
+    // table, or `None` if the table doesn't have one.
+    fn encoded(&self, row: &Row) -> Option<Row> {
+        let dictionary = self.dictionary.read().clone()?;
+        let mut row = row.clone();
+        dictionary.encode(&mut row);
+        Some(row)
+    }
+
+    /// Build a dictionary of the strings shared by the rows of the table,
+    /// which the string fields of the rows written from now on are encoded
+    /// by, and decoded by on read, along with the statistics the planner
+    /// estimates the rows of a scan with, see `TableStatistics`.
+    ///
+    /// TRADEOFF: Rows written before keep their bytes until they're
+    /// written again, as there's no vacuum to rewrite every cell yet.
+    pub fn analyze(&self) -> Result<String, DbError> {
+        let _op = self.enter()?;
+        self.check_writable()?;
+        let mut rows = Vec::new();
+        self.pager
+            .for_each_row(self.root_page_num, |row| rows.push(row));
+        let values = rows.iter().flat_map(|row| [row.username(), row.email()]);
+
+        let dictionary = self.pager.write_dictionary(values).map_err(DbError::Io)?;
+        self.catalog
+            .set_dictionary(&self.name, dictionary.page_id())
+            .map_err(DbError::Catalog)?;
+        self.catalog
+            .set_statistics(&self.name, TableStatistics::build_from_rows(&rows))
+            .map_err(DbError::Catalog)?;
+        let output = format!(
+            "analyzed table '{}', dictionary size: {}, rows: {}",
+            self.name,
+            dictionary.len(),
+            rows.len()
+        );
+        *self.dictionary.write() = Some(dictionary);
+
+        Ok(output)
+    }
+
+    /// Insert a struct as a row, mapping its fields to the columns by name.
+    pub fn insert_serde<T: Serialize>(&self, value: &T) -> Result<(), DbError> {
+        let row = mapping::to_row(value).map_err(DbError::InvalidInput)?;
+        self.insert_row(&row)?;
+        Ok(())
+    }
+
+    /// Select rows as structs, mapping the columns to its fields by name.
+    pub fn query_as<T: DeserializeOwned>(&self, statement: &Statement) -> Result<Vec<T>, String> {
+        self.select_rows(statement)
+            .iter()
+            .map(mapping::from_row)
+            .collect()
+    }
+
+    pub fn delete(&self, row: &Row) -> String {
+        let Ok(_op) = self.enter() else {
+            return DbError::TableClosed.to_string();
+        };
+        if let Err(err) = self.check_writable() {
+            return err.to_string();
+        }
+        let page_num = self.root_page_num;
+        let started = Instant::now();
+        self.unindex(row.id);
+        let output = self.pager.delete(page_num, row).unwrap();
+        self.latency.record(Operation::Delete, started);
+        if output.starts_with("deleted") {
+            self.catalog.record_delete(&self.name, row.id);
+            self.log_statements(vec![self.delete_statement(row.id)]);
+        }
+        output
+    }
+
+    /// Same as `delete`, but return whether the row existed.
+    pub fn delete_row(&self, row: &Row) -> bool {
+        self.delete(row).starts_with("deleted")
+    }
+
+    /// Ingest rows from CSV lines, one row per line.
+    ///
+    /// Rows are buffered into chunks and sorted by key before inserting,
+    /// so consecutive inserts mostly land on the same leaf page. Lines that
+    /// fail to parse or have a duplicate key are rejected, without stopping
+    /// the copy.
+    pub fn copy_from(&self, reader: impl BufRead) -> CopyResult {
+        let mut result = CopyResult::default();
+        let Ok(_op) = self.enter() else {
+            result.rejected.push((0, DbError::TableClosed.to_string()));
+            return result;
+        };
+        let mut chunk = Vec::with_capacity(COPY_CHUNK_SIZE);
+
+        for (i, line) in reader.lines().enumerate() {
+            let line_num = i + 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    result.rejected.push((line_num, err.to_string()));
+                    break;
+                }
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match Row::from_csv(&line) {
+                Ok(row) => chunk.push((line_num, row)),
+                Err(reason) => result.rejected.push((line_num, reason)),
+            }
+
+            if chunk.len() >= COPY_CHUNK_SIZE {
+                self.copy_chunk(&mut chunk, &mut result);
+            }
+        }
+
+        self.copy_chunk(&mut chunk, &mut result);
+        result.rejected.sort_by_key(|(line_num, _)| *line_num);
+        result
+    }
+
+    /// Load rows in ascending order of their ids into the empty table by
+    /// building the tree bottom-up, see `Pager::bulk_load`, returning the
+    /// number of rows loaded. The rows are logged as one transaction.
+    ///
+    /// The table can't have indexes yet, they're created once the rows are
+    /// loaded instead, see `create_index`.
+    pub fn bulk_load(&self, rows: impl Iterator<Item = Row>) -> Result<usize, DbError> {
+        let _op = self.enter()?;
+        self.check_writable()?;
+        if !self.indexes.read().is_empty() {
+            return Err(DbError::InvalidInput(format!(
+                "fail to bulk load '{}': create its indexes after loading",
+                self.name
+            )));
+        }
+
+        // Rows out of order are caught here rather than by the pager, so
+        // only the rows loaded are recorded.
+        let mut last_id = None;
+        let mut out_of_order = None;
+        let mut statements = Vec::new();
+        let rows = rows
+            .take_while(|row| {
+                if last_id.is_some_and(|id| row.id <= id) {
+                    out_of_order = Some((row.id, last_id.unwrap()));
+                    return false;
+                }
+                last_id = Some(row.id);
+                true
+            })
+            .map(|row| {
+                self.catalog.record_insert(&self.name, &row);
+                if self.statement_log.is_some() {
+                    statements.push(self.insert_statement(&row));
+                }
+                self.encoded(&row).unwrap_or(row)
+            });
+        let loaded = self
+            .pager
+            .bulk_load(self.root_page_num, rows, BULK_LOAD_FILL_PERCENT);
+        self.log_statements(statements);
+
+        let loaded = loaded.map_err(|err| match err {
+            DbError::InvalidInput(reason) => {
+                DbError::InvalidInput(format!("fail to bulk load '{}': {reason}", self.name))
+            }
+            err => err,
+        })?;
+        match out_of_order {
+            Some((id, last_id)) => Err(DbError::InvalidInput(format!(
+                "fail to bulk load '{}': rows must be in ascending order of id, but {id} follows {last_id}, {loaded} rows loaded",
+                self.name
+            ))),
+            None => Ok(loaded),
+        }
+    }
+
+    /// Return true if the table has no rows.
+    pub fn is_empty(&self) -> Result<bool, DbError> {
+        self.pager.is_empty(self.root_page_num)
+    }
+
+    // The rows of a chunk are logged as one transaction.
+    fn copy_chunk(&self, chunk: &mut Vec<(usize, Row)>, result: &mut CopyResult) {
+        let (line_nums, rows): (Vec<_>, Vec<_>) = chunk.drain(..).unzip();
+        for (line_num, inserted) in line_nums.into_iter().zip(self.insert_sorted(&rows)) {
+            match inserted {
+                Ok(_) => result.rows += 1,
+                Err(err) => result.rejected.push((line_num, err.to_string())),
+            }
+        }
+    }
+
+    pub fn key_ranges(&self) -> Result<Vec<(u32, Option<u32>)>, DbError> {
+        self.pager.key_ranges(self.root_page_num)
+    }
+
+    /// Take a snapshot of the table, where long running readers such as
+    /// dump can read from without blocking writers.
+    pub fn snapshot(&self) -> std::io::Result<Arc<Snapshot>> {
+        let _op = self.enter().map_err(std::io::Error::other)?;
+        self.pager.snapshot()
+    }
+
+    /// Call `f` with every row in key order, by walking the leaf chain.
+    pub fn for_each_row<F>(&self, f: F)
+    where
+        F: FnMut(Row),
+    {
+        let Ok(_op) = self.enter() else {
+            return;
+        };
+        self.pager.for_each_row(self.root_page_num, f)
+    }
+
+    /// Read every row into column batches of at most `chunk_size` rows,
+    /// e.g. to hand the table to dataframe libraries without going
+    /// through CSV.
+    pub fn to_arrow_batches(&self, chunk_size: usize) -> Vec<RecordBatch> {
+        let mut builder = BatchBuilder::new(chunk_size);
+        self.for_each_row(|row| builder.push(&row));
+        builder.finish()
+    }
+
+    /// Start a scan in the given direction, which is read by `scan`.
+    pub fn scan_cursor(&self, direction: ScanDirection) -> ScanCursor {
+        ScanCursor {
+            last_key: None,
+            direction,
+            epoch: self.pager.epoch(),
+        }
+    }
+
+    /// Read the next `limit` rows of the scan, and return them with the
+    /// cursor to read the rows after them, or `None` if the scan is done.
+    ///
+    /// Rows inserted or deleted in between are seen or not depending on
+    /// which side of the cursor they are on. The caller can tell if that
+    /// could happen by comparing the epoch of the cursor with `epoch`.
+    pub fn scan(&self, cursor: &ScanCursor, limit: usize) -> (Vec<Row>, Option<ScanCursor>) {
+        let Ok(_op) = self.enter() else {
+            return (Vec::new(), None);
+        };
+
+        let rows: Vec<Row> = match cursor.direction {
+            ScanDirection::Forward => {
+                // The scan is done if the last key is the largest key.
+                let start_key = match cursor.last_key {
+                    Some(last_key) => last_key.checked_add(1),
+                    None => Some(0),
+                };
+                match start_key {
+                    Some(start_key) => self
+                        .iter_range(start_key, None)
+                        .take(limit)
+                        .map(|(_, row)| row)
+                        .collect(),
+                    None => Vec::new(),
+                }
+            }
+            ScanDirection::Backward => {
+                match self
+                    .pager
+                    .scan_backward(self.root_page_num, cursor.last_key, limit)
+                {
+                    Ok(rows) => rows,
+                    Err(err) => {
+                        warn!("fail to scan table '{}': {err}", self.name);
+                        return (Vec::new(), None);
+                    }
+                }
+            }
+        };
+
+        let next = match rows.last() {
+            Some(row) if rows.len() == limit => Some(ScanCursor {
+                last_key: Some(row.id),
+                ..*cursor
+            }),
+            _ => None,
+        };
+        (rows, next)
+    }
+
+    /// See `Pager::epoch`.
+    pub fn epoch(&self) -> u64 {
+        self.pager.epoch()
+    }
+
+    pub fn snapshot_key_ranges(
+        &self,
+        snapshot: &Snapshot,
+    ) -> Result<Vec<(u32, Option<u32>)>, DbError> {
+        self.pager.snapshot_key_ranges(snapshot, self.root_page_num)
+    }
+
+    pub fn for_each_in_snapshot<F>(
+        &self,
+        snapshot: &Snapshot,
+        start: u32,
+        end: Option<u32>,
+        f: F,
+    ) -> Result<(), DbError>
+    where
+        F: FnMut(Row),
+    {
+        self.pager
+            .for_each_in_snapshot(snapshot, self.root_page_num, start, end, f)
+    }
+
+    pub fn check_pool(&self) -> Result<(), String> {
+        self.pager.check_pool()
+    }
+
+    /// Check the database file against the catalog, i.e. that every table
+    /// has a schema matching the row layout, a tree that looks sound, see
+    /// `Pager::check_tree`, and a dictionary that can be read, returning
+    /// every problem found, one per line.
+    pub fn check(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+        if let Some(err) = self.catalog.error() {
+            problems.push(format!(
+                "{err}, the tables besides '{DEFAULT_TABLE}' can't be found"
+            ));
+        }
+        if let Err(problem) = self.pager.check_file() {
+            problems.push(problem);
+        }
+
+        let mut roots: HashMap<usize, String> = HashMap::new();
+        for name in self.catalog.table_names() {
+            let Some(info) = self.catalog.get(&name) else {
+                continue;
+            };
+            if info.schema != Schema::users() {
+                let columns: Vec<&str> = info
+                    .schema
+                    .columns()
+                    .iter()
+                    .map(|column| column.name.as_str())
+                    .collect();
+                problems.push(format!(
+                    "table '{name}' has columns ({}), which don't match the (id, username, email) rows",
+                    columns.join(", ")
+                ));
+            }
+            if let Some(other) = roots.insert(info.root_page_num, name.clone()) {
+                problems.push(format!(
+                    "tables '{other}' and '{name}' share root page {}",
+                    info.root_page_num
+                ));
+                continue;
+            }
+
+            for problem in self
+                .pager
+                .check_tree(info.root_page_num, CHECK_SAMPLE_PAGES)
+            {
+                problems.push(format!("table '{name}': {problem}"));
+            }
+            if let Some(Err(err)) = info.dictionary_page.map(|page| self.pager.dictionary(page)) {
+                problems.push(format!("table '{name}': {err}"));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n"))
+        }
+    }
+
+    pub fn check_disk(&self) -> Result<(), String> {
+        self.pager.check_disk()
+    }
+
+    /// Compare the cached pages of the file with the file, reading at
+    /// most `byte_budget` bytes from it. See `Pager::sync_check`.
+    pub fn sync_check(&self, byte_budget: usize) -> String {
+        self.pager.sync_check(byte_budget).to_string()
+    }
+
+    /// Run one of the `.page` commands on a page of the file, to
+    /// reproduce eviction bugs or investigate hot pages.
+    pub fn page_command(&self, action: PageAction) -> Result<String, String> {
+        match action {
+            PageAction::List => {
+                let pages: Vec<String> = self
+                    .pager
+                    .cached_pages()
+                    .iter()
+                    .map(|info| info.to_string())
+                    .collect();
+                Ok(pages.join("\n"))
+            }
+            PageAction::Pin(page_id) => match self.pager.pin_page(page_id)? {
+                true => Ok(format!("pinned page {page_id}")),
+                false => Ok(format!("page {page_id} is already pinned")),
+            },
+            PageAction::Unpin(page_id) => self
+                .pager
+                .unpin_page(page_id)
+                .map(|()| format!("unpinned page {page_id}")),
+            PageAction::Evict(page_id) => match self.pager.evict_page(page_id)? {
+                true => Ok(format!("flushed and evicted page {page_id}")),
+                false => Ok(format!("evicted page {page_id}")),
+            },
+            PageAction::Inspect(page_id) => self
+                .pager
+                .inspect_page(page_id)
+                .map(|info| info.to_string())
+                .ok_or_else(|| format!("page {page_id} isn't in the buffer pool")),
+        }
+    }
+
+    pub fn scrub(&self) -> String {
+        let mismatches = match self.pager.scrub(self.root_page_num) {
+            Ok(mismatches) => mismatches,
+            Err(err) => return err.to_string(),
+        };
+        if mismatches.is_empty() {
+            return "No checksum mismatch found.".to_string();
+        }
+
+        mismatches
+            .iter()
+            .map(|(page_id, key)| format!("checksum mismatch: page {page_id}, key {key}\n"))
+            .collect()
+    }
+
+    pub fn pages(&self) -> String {
+        self.pager.debug_pages()
+    }
+
+    pub fn occupancy(&self) -> String {
+        match self.pager.occupancy(self.root_page_num) {
+            Ok(occupancy) => occupancy.to_string(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    /// Load the internal nodes into the buffer pool, e.g. after opening a
+    /// big table, so the first lookups don't all go to disk.
+    pub fn warmup(&self) -> String {
+        match self.pager.warmup(self.root_page_num) {
+            Ok(pages) => format!("warmed up {pages} pages"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    /// Sample the shape of the tree, e.g. its height and how full its
+    /// leaves are, and show how it changed since the first sample. A
+    /// warning is logged if the leaves are getting too empty. Once the
+    /// table is analyzed, the estimated distinct values of each column
+    /// follow, see `TableStatistics`.
+    pub fn stats(&self) -> String {
+        let stats = match self.pager.tree_stats(self.root_page_num) {
+            Ok(stats) => stats,
+            Err(err) => return err.to_string(),
+        };
+        let mut metrics = self.metrics.lock();
+        for alert in metrics.record(stats) {
+            warn!("table '{}': {alert}, consider a vacuum", self.name);
+        }
+        let mut output = metrics.to_string();
+        if let Some(statistics) = self.catalog.statistics(&self.name) {
+            let distinct: Vec<String> = Schema::users()
+                .columns()
+                .iter()
+                .filter_map(|column| {
+                    let distinct = statistics.distinct(&column.name)?;
+                    Some(format!("{} ~{distinct}", column.name))
+                })
+                .collect();
+            output.push_str(&format!(
+                "\nrows: {}, distinct values: {}",
+                statistics.row_count,
+                distinct.join(", ")
+            ));
+        }
+        output
+    }
+
+    /// Latency of each operation on the tables of the file, see
+    /// `LatencyMetrics::report`, followed by how much of the buffer pool
+    /// is dirty and the pages writers flushed to keep it under the limit,
+    /// see `DbOptions::with_max_dirty_percent`, and the writes that split
+    /// or merged under the SMO latch, see `DbOptions::with_latch_protocol`.
+    pub fn metrics(&self, detail: bool) -> String {
+        format!(
+            "{}\ndirty pages: {:.0}% of the pool, max {}%, {} throttled flushes\n\
+             latch protocol: {:?}, {} smo retries",
+            self.latency.report(detail),
+            self.pager.dirty_ratio() * 100.0,
+            self.pager.max_dirty_percent(),
+            self.pager.throttled_flushes(),
+            self.pager.latch_protocol(),
+            self.pager.smo_retries()
+        )
+    }
+
+    /// Show the current segment of the WAL, how far it's flushed and the
+    /// segments retained until the next checkpoint, see `LogStatus`.
+    pub fn wal_status(&self) -> String {
+        match self.pager.log_manager() {
+            Some(log_manager) => log_manager.status().to_string(),
+            None => "the database isn't logged to a WAL".to_string(),
+        }
+    }
+
+    /// Same as `metrics`, in the Prometheus text format.
+    pub fn prometheus_metrics(&self) -> String {
+        let mut output = self.latency.prometheus();
+        output.push_str(&prometheus_metric(
+            "minidb_dirty_page_ratio",
+            "Fraction of the buffer pool that is dirty.",
+            "gauge",
+            self.pager.dirty_ratio(),
+        ));
+        output.push_str(&prometheus_metric(
+            "minidb_throttled_flushes_total",
+            "Pages flushed by writers over the dirty page limit.",
+            "counter",
+            self.pager.throttled_flushes(),
+        ));
+        output.push_str(&prometheus_metric(
+            "minidb_smo_retries_total",
+            "Writes that split or merged under the exclusive SMO latch.",
+            "counter",
+            self.pager.smo_retries(),
+        ));
+        output
+    }
+
+    /// Record the latency of an operation started outside the table, e.g.
+    /// a commit by `TransactionManager`.
+    pub fn record_latency(&self, operation: Operation, started: Instant) {
+        self.latency.record(operation, started);
+    }
+
+    /// Switch the file to a write-optimized mode for an initial load of
+    /// many rows, see `Pager::begin_ingest`. It applies to every table of
+    /// the file until `end_ingest`.
+    pub fn begin_ingest(&self) {
+        self.pager.begin_ingest();
+    }
+
+    /// Leave the ingestion mode and merge the leaves of the table that
+    /// underflowed while ingesting.
+    pub fn end_ingest(&self) -> String {
+        match self.pager.end_ingest(self.root_page_num) {
+            Ok(merged) => format!("merged {merged} leaves"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    /// Look up the row with the given key straight from the tree, skipping
+    /// the executor, the transaction and the row lock a `select` goes
+    /// through. Pages are only held with read latches while the tree is
+    /// searched.
+    ///
+    /// It has read-uncommitted semantics: the row may be updated by a
+    /// transaction that later aborts, and a row marked as deleted by an
+    /// uncommitted transaction is already gone.
+    ///
+    /// TRADEOFF: Meant for hot point lookups where a dirty read is
+    /// acceptable. Fetching the pages from the buffer pool dominates either
+    /// way, so it only saves about 10% over the transactional path for now,
+    /// see `bench_point_lookup`.
+    pub fn get_fast(&self, key: u32) -> Result<Option<Row>, DbError> {
+        let row = self.pager.find(self.root_page_num, None, key)?;
+        Ok(row.filter(|row| !row.is_deleted))
+    }
+
+    /// Value of the key in the metadata of the file, see `Metadata`. The
+    /// metadata is shared by every table of the file.
+    pub fn meta_get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        let Some(page_id) = self.catalog.metadata_page() else {
+            return Ok(None);
+        };
+        let metadata = self.pager.metadata(page_id)?;
+        Ok(metadata.get(key).map(<[u8]>::to_vec))
+    }
+
+    /// Set the value of the key in the metadata of the file, which is
+    /// durable once it returns.
+    pub fn meta_put(&self, key: &str, value: &[u8]) -> Result<(), String> {
+        self.apply_metadata(BTreeMap::from([(key.to_string(), value.to_vec())]))
+    }
+
+    /// Write the entries to the metadata of the file at once, keeping the
+    /// other keys as they are.
+    pub fn apply_metadata(&self, entries: BTreeMap<String, Vec<u8>>) -> Result<(), String> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.check_writable().map_err(|err| err.to_string())?;
+
+        self.catalog.update_metadata(|page_id| {
+            let mut merged = match page_id {
+                Some(page_id) => self.pager.metadata(page_id)?.entries().clone(),
+                None => BTreeMap::new(),
+            };
+            merged.extend(entries);
+            Ok(self.pager.write_metadata(merged)?.page_id())
+        })?;
+        Ok(())
+    }
+}
+
+// Transaction-scoped methods.
+impl Table {
+    /// Same as `meta_get`, except the values written by the transaction
+    /// are read first.
+    pub fn meta_get_in(
+        &self,
+        key: &str,
+        transaction: &RwLockWriteGuard<Transaction>,
+    ) -> Result<Option<Vec<u8>>, String> {
+        match transaction.metadata(key) {
+            Some(value) => Ok(Some(value.to_vec())),
+            None => self.meta_get(key),
+        }
+    }
+
+    /// Set the value of the key in the metadata of the file once the
+    /// transaction commits, along with its other writes. Nothing is
+    /// written if it aborts.
+    ///
+    /// TRADEOFF: Keys aren't locked, so the last transaction to commit
+    /// wins when two of them write the same key.
+    pub fn meta_put_in(
+        &self,
+        key: &str,
+        value: &[u8],
+        transaction: &mut RwLockWriteGuard<Transaction>,
+    ) {
+        transaction.put_metadata(key, value);
+    }
+
+    pub fn get_row_id(
+        &self,
+        key: u32,
+        _transaction: &mut RwLockWriteGuard<Transaction>,
+    ) -> Option<RowID> {
+        self.pager
+            .search(self.root_page_num, key)
+            .map(|(page_id, slot_num)| RowID::new(page_id, slot_num))
+    }
+
+    pub fn iter(&self) -> TableIntoIter {
+        self.iter_with_hint(AccessPattern::Range)
+    }
+
+    /// Iterate through the table, where the leaf pages are read
+    /// with the given access pattern.
+    pub fn iter_with_hint(&self, access_pattern: AccessPattern) -> TableIntoIter {
+        self.iter_from(0, None, access_pattern)
+    }
+
+    /// Iterate through the rows with keys in `[start_key, end_key)`,
+    /// starting from the leaf holding the start key instead of the first
+    /// leaf. See `Pager::scan_range`.
+    pub fn iter_range(&self, start_key: u32, end_key: Option<u32>) -> TableIntoIter {
+        self.iter_from(start_key, end_key, AccessPattern::Range)
+    }
+
+    fn iter_from(
+        &self,
+        start_key: u32,
+        end_key: Option<u32>,
+        access_pattern: AccessPattern,
+    ) -> TableIntoIter {
+        let page = self.search_page(self.root_page_num, start_key);
+        let mut iter = TableIntoIter {
+            pager: self.pager.clone(),
+            root_page_num: self.root_page_num,
+            node: None,
+            page_id: page.page_id.unwrap(),
+            slot_num: 0,
+            last_key: start_key.checked_sub(1),
+            access_pattern,
+            end_key,
+            error: None,
+        };
+        iter.seek(page);
+        iter
+    }
+
+    fn search_page(&self, page_num: usize, key: u32) -> RwLockUpgradableReadGuard<'_, Page> {
+        match self.pager.fetch_read_page_guard(page_num) {
+            Err(_) => {
+                let duration = std::time::Duration::from_millis(1000);
+                std::thread::sleep(duration);
+
+                self.search_page(page_num, key)
+            }
+            Ok(page) => {
+                let node = page.node().unwrap();
+
+                if node.node_type == NodeType::Leaf {
+                    return page;
+                }
+
+                let next_page_num = node.search(key).unwrap();
+                self.pager.unpin_page_with_read_guard(page, false);
+                self.search_page(next_page_num, key)
+            }
+        }
+    }
+
+    pub fn get(&self, rid: RowID, transaction: &mut RwLockWriteGuard<Transaction>) -> Option<Row> {
+        if let Ok(page) = self.pager.fetch_read_page_guard(rid.page_id) {
+            let row = page.get_row(rid.slot_num);
+            self.pager.unpin_page_with_read_guard(page, false);
+            row.map(|row| self.pager.decode_row(row))
+        } else {
+            transaction.set_state(TransactionState::Aborted);
+            None
+        }
+    }
+
+    /// Insert the row within the transaction, see `insert` for the
+    /// auto-commit version.
+    pub fn insert_in(
+        &self,
+        row: &Row,
+        transaction: &mut RwLockWriteGuard<Transaction>,
+    ) -> Result<RowID, DbError> {
+        transaction.check_write_set()?;
+        // A row violating a unique index aborts the transaction, so the
+        // writes before it are rolled back. See `SecondaryIndex`.
+        let (page_id, slot_num) = self.insert_encoded(row).inspect_err(|err| {
+            if matches!(err, DbError::UniqueViolation(_)) {
+                transaction.set_state(TransactionState::Aborted);
+            }
+        })?;
+        // The RID probably need to be added to the row
+        // as well? It's currently unused by row/tuple.
+        let rid = RowID { page_id, slot_num };
+        transaction.push_write_set(WriteRecord::new(WriteRecordType::Insert, rid, row.id));
+        transaction.push_statement(self.insert_statement(row));
+        Ok(rid)
+    }
+
+    pub fn apply_delete(&self, key: u32) {
+        self.unindex(key);
+        match self.pager.delete_by_key(self.root_page_num, key) {
+            Ok(output) if output.starts_with("deleted") => {
+                self.catalog.record_delete(&self.name, key);
+            }
+            Ok(_) => {}
+            Err(err) => warn!("fail to delete row {key} of table '{}': {err}", self.name),
+        }
+    }
+
+    pub fn rollback_delete(&self, rid: &RowID, transaction: &mut Transaction) {
+        let mut page = match self.pager.fetch_write_page_guard_with_retry(rid.page_id) {
+            Ok(page) => page,
+            Err(err) => {
+                warn!("fail to roll back the delete of {rid:?}: {err}");
+                return;
+            }
+        };
+        if let Some(row) = page.get_row(rid.slot_num) {
+            self.append_log(transaction, |txn_id, prev_lsn| {
+                LogRecord::row_change(
+                    txn_id,
+                    prev_lsn,
+                    LogRecordType::RollbackDelete,
+                    rid.page_id,
+                    &row,
+                )
+            });
+        }
+        page.mark_row_as_undeleted(rid.slot_num);
+        self.pager.unpin_page_with_write_guard(page, true);
+    }
+
+    /// Mark the row as deleted within the transaction, where it's only
+    /// removed from the tree on commit by `apply_delete`. See `delete` for
+    /// the auto-commit version.
+    pub fn delete_in(
+        &self,
+        row: &Row,
+        rid: &RowID,
+        transaction: &mut RwLockWriteGuard<Transaction>,
+    ) -> Result<bool, DbError> {
+        self.check_writable()?;
+        transaction.check_write_set()?;
+        if !self.lock_for_write(transaction, rid)? {
+            return Ok(false);
+        }
+
+        let started = Instant::now();
+        let deleted = if let Ok(mut page) = self.pager.fetch_write_page_guard(rid.page_id) {
+            self.append_log(transaction, |txn_id, prev_lsn| {
+                LogRecord::row_change(
+                    txn_id,
+                    prev_lsn,
+                    LogRecordType::MarkDelete,
+                    rid.page_id,
+                    row,
+                )
+            });
+            page.mark_row_as_deleted(rid.slot_num);
+            self.pager.unpin_page_with_write_guard(page, true);
+
+            transaction.push_write_set(WriteRecord::new(WriteRecordType::Delete, *rid, row.id));
+            transaction.push_statement(self.delete_statement(row.id));
+            true
+        } else {
+            false
+        };
+        self.latency.record(Operation::Delete, started);
+        Ok(deleted)
+    }
+
+    pub fn update(
+        &self,
+        row: &Row,
+        new_row: &Row,
+        columns: &Vec<String>,
+        rid: &RowID,
+        transaction: &mut RwLockWriteGuard<Transaction>,
+    ) -> Result<bool, DbError> {
+        self.check_writable()?;
+        transaction.check_write_set()?;
+        // Make sure we have access to a lock first before we acquire the write page
+        // from our pager.
+        if !self.lock_for_write(transaction, rid)? {
+            return Ok(false);
+        }
+
+        let updated = |column: &str| columns.iter().any(|c| c == column);
+        let username = if updated("username") { new_row } else { row };
+        let email = if updated("email") { new_row } else { row };
+        let updated_row =
+            Row::new(&row.id.to_string(), &username.username(), &email.email()).unwrap();
+
+        // The indexes are updated before the row, so a unique index
+        // rejecting its new value aborts the transaction before it changes.
+        let indexes = self.indexes.read();
+        let restore = |indexes: &[Arc<SecondaryIndex>]| {
+            for index in indexes {
+                if let Err(err) = index.update(&updated_row, row) {
+                    warn!("fail to restore index of row {}: {err}", row.id);
+                }
+            }
+        };
+        for (i, index) in indexes.iter().enumerate() {
+            if let Err(err) = index.update(row, &updated_row) {
+                restore(&indexes[..i]);
+                transaction.set_state(TransactionState::Aborted);
+                return Err(err);
+            }
+        }
+
+        if let Ok(mut page) = self.pager.fetch_write_page_guard(rid.page_id) {
+            let encoded = self.encoded(new_row);
+            let new_row = encoded.as_ref().unwrap_or(new_row);
+            // A row growing out of its leaf takes the bytes other rows of
+            // the leaf left over first, and is moved to another leaf if it
+            // still doesn't fit, see `relocate_row`.
+            let compacted =
+                !page.can_update_row(rid.slot_num, new_row, columns) && page.compact() > 0;
+            let rid = if page.can_update_row(rid.slot_num, new_row, columns) {
+                self.append_log(transaction, |txn_id, prev_lsn| {
+                    LogRecord::update(txn_id, prev_lsn, rid.page_id, row, new_row)
+                });
+                assert!(page.update_row(rid.slot_num, new_row, columns));
+                self.pager.unpin_page_with_write_guard(page, true);
+                *rid
+            } else {
+                let current = page.get_row(rid.slot_num);
+                self.pager.unpin_page_with_write_guard(page, compacted);
+                let Some(current) = current else {
+                    restore(&indexes);
+                    return Ok(false);
+                };
+                let relocated = match self.relocate_row(&current, new_row, columns, transaction) {
+                    Ok(relocated) => relocated,
+                    Err(err) => {
+                        restore(&indexes);
+                        transaction.set_state(TransactionState::Aborted);
+                        return Err(err);
+                    }
+                };
+                if !self.lock_for_write(transaction, &relocated)? {
+                    return Ok(false);
+                }
+                relocated
+            };
+
+            let mut write_record = WriteRecord::new(WriteRecordType::Update, rid, row.id);
+            write_record.old_row = Some(row.clone());
+            write_record.columns = columns.clone();
+            transaction.push_write_set(write_record);
+
+            // TODO (Update statement): Log an UPDATE once SQL can parse
+            // one, instead of replacing the row, as the values of our
+            // positional update can't have spaces.
+            transaction.push_statement(self.delete_statement(row.id));
+            transaction.push_statement(self.insert_statement(&updated_row));
+
+            Ok(true)
+        } else {
+            restore(&indexes);
+            Ok(false)
+        }
+    }
+
+    // Update the columns of the row, stored as `current`, by deleting it
+    // and inserting it again, so the leaf it goes to is split if needed.
+    // Return where the row is now.
+    //
+    // TRADEOFF: The row is briefly missing from the tree, and its RowID
+    // changes. The write set refers to the new RowID, so a rollback
+    // updates the row where it's now.
+    fn relocate_row(
+        &self,
+        current: &Row,
+        new_row: &Row,
+        columns: &Vec<String>,
+        transaction: &mut Transaction,
+    ) -> Result<RowID, DbError> {
+        let mut relocated = current.clone();
+        for column in columns {
+            relocated.update(column, new_row);
+        }
+
+        self.pager.delete_by_key(self.root_page_num, current.id)?;
+        let inserted = self
+            .pager
+            .insert_row(self.root_page_num, &relocated)
+            .inspect_err(|err| {
+                warn!("fail to move row {}: {err}", current.id);
+                if let Err(err) = self.pager.insert_row(self.root_page_num, current) {
+                    warn!("fail to restore row {}: {err}", current.id);
+                }
+            })?;
+        // The row may end up in the new sibling of the leaf it's inserted
+        // into, so it's looked up again.
+        let (page_id, slot_num) = self
+            .pager
+            .search(self.root_page_num, current.id)
+            .unwrap_or(inserted);
+        self.append_log(transaction, |txn_id, prev_lsn| {
+            LogRecord::update(txn_id, prev_lsn, page_id, current, &relocated)
+        });
+        Ok(RowID { page_id, slot_num })
+    }
+
+    // Take the exclusive lock of the row, held until the transaction ends
+    // whatever its isolation level, upgrading its shared lock if any.
+    // Return false if the transaction is aborted.
+    fn lock_for_write(&self, transaction: &mut Transaction, rid: &RowID) -> Result<bool, DbError> {
+        if transaction.is_exclusive_lock(rid) {
+            Ok(true)
+        } else if transaction.is_shared_lock(rid) {
+            self.lock_manager.lock_upgrade(transaction, *rid)
+        } else {
+            self.lock_manager.lock_exclusive(transaction, *rid)
+        }
+    }
+
+    pub fn rollback_update(
+        &self,
+        rid: &RowID,
+        row: &Row,
+        columns: &Vec<String>,
+        transaction: &mut Transaction,
+    ) {
+        if let Ok(mut page) = self.pager.fetch_write_page_guard(rid.page_id) {
+            let old_row = row;
+            let encoded = self.encoded(row);
+            let row = encoded.as_ref().unwrap_or(row);
+            let Some(current) = page.get_row(rid.slot_num) else {
+                self.pager.unpin_page_with_write_guard(page, false);
+                return;
+            };
+
+            let decoded = self.pager.decode_row(current.clone());
+            let mut restored = decoded.clone();
+            for column in columns {
+                restored.update(column, old_row);
+            }
+            for index in self.indexes.read().iter() {
+                if let Err(err) = index.update(&decoded, &restored) {
+                    warn!("fail to restore index of row {}: {err}", decoded.id);
+                }
+            }
+
+            // The leaf may have been compacted since, so the old row no
+            // longer fits, see `update`.
+            if !page.can_update_row(rid.slot_num, row, columns) {
+                page.compact();
+            }
+            if page.can_update_row(rid.slot_num, row, columns) {
+                self.append_log(transaction, |txn_id, prev_lsn| {
+                    LogRecord::update(txn_id, prev_lsn, rid.page_id, &current, row)
+                });
+                page.update_row(rid.slot_num, row, columns);
+                self.pager.unpin_page_with_write_guard(page, true);
+            } else {
+                self.pager.unpin_page_with_write_guard(page, true);
+                if let Err(err) = self.relocate_row(&current, row, columns, transaction) {
+                    warn!("fail to roll back update of row {}: {err}", current.id);
+                }
+            }
+        }
+    }
+
+    /// Log the commit or abort of the transaction, depending on its state.
+    /// A commit is flushed before returning, so it survives a crash.
+    pub fn log_end(&self, transaction: &mut Transaction) {
+        let log_type = match transaction.state {
+            TransactionState::Committed => LogRecordType::Commit,
+            _ => LogRecordType::Abort,
+        };
+        let lsn = self.append_log(transaction, |txn_id, prev_lsn| {
+            LogRecord::new(txn_id, prev_lsn, log_type)
+        });
+        if let (Some(lsn), LogRecordType::Commit) = (lsn, log_type) {
+            self.pager.flush_log_until(lsn);
+        }
     }
 
-    pub fn select(&self, statement: &Statement) -> String {
-        let page_num = self.root_page_num;
-        if let Some(row) = &statement.row {
-            self.pager.find(page_num, None, row.id)
-        } else {
-            self.pager.select(page_num)
+    /// Append the statements to the statement log as a committed
+    /// transaction, if the table has one. A failure is only logged, as the
+    /// writes are already applied.
+    pub fn log_statements(&self, statements: Vec<String>) {
+        let Some(statement_log) = &self.statement_log else {
+            return;
+        };
+        if statements.is_empty() {
+            return;
+        }
+        if let Err(err) = statement_log.append(&statements) {
+            warn!("fail to append to the statement log: {err}");
         }
     }
 
-    pub fn insert(&self, row: &Row) -> String {
-        let page_num = self.root_page_num;
-        self.pager.insert(page_num, row).unwrap()
+    fn insert_statement(&self, row: &Row) -> String {
+        format!(
+            "insert into {} values ({}, {}, {})",
+            self.name,
+            row.id,
+            Value::Text(row.username()),
+            Value::Text(row.email())
+        )
     }
 
-    pub fn delete(&self, row: &Row) -> String {
-        let page_num = self.root_page_num;
-        self.pager.delete(page_num, row).unwrap()
+    fn delete_statement(&self, key: u32) -> String {
+        format!("delete from {} where id = {key}", self.name)
     }
 
-    pub fn pages(&self) -> String {
-        self.pager.debug_pages()
+    // Append the record of the transaction, chaining it to the previous
+    // record of the transaction by its LSN.
+    //
+    // Inserts and deletes applied to the tree are logged by the pager
+    // instead, without a transaction, as they are only redone by key.
+    fn append_log<F>(&self, transaction: &mut Transaction, record: F) -> Option<u32>
+    where
+        F: FnOnce(u32, Option<u32>) -> LogRecord,
+    {
+        let (txn_id, prev_lsn) = (transaction.txn_id, transaction.prev_lsn());
+        let lsn = self.pager.append_log(|| record(txn_id, prev_lsn))?;
+        transaction.update_prev_lsn(lsn);
+        Some(lsn)
     }
 }
 
 impl std::string::ToString for Table {
     fn to_string(&self) -> String {
-        self.pager.to_tree_string()
+        self.pager
+            .to_tree_string()
+            .unwrap_or_else(|err| err.to_string())
+    }
+}
+
+// Index the row in every index, returning the ones it's newly added to,
+// so it can be unindexed if it isn't inserted after all.
+fn index_row<'a>(
+    indexes: &'a [Arc<SecondaryIndex>],
+    row: &Row,
+) -> Result<Vec<&'a Arc<SecondaryIndex>>, DbError> {
+    let mut indexed = Vec::new();
+    for index in indexes {
+        match index.insert(row) {
+            Ok(true) => indexed.push(index),
+            Ok(false) => {}
+            Err(err) => {
+                indexed.iter().for_each(|index| index.remove(row));
+                return Err(err);
+            }
+        }
+    }
+    Ok(indexed)
+}
+
+// Selects by id are lookups, where the others scan the tree, or the
+// range of ids their predicate is restricted to.
+fn select_operation(statement: &Statement) -> Operation {
+    if statement.row.is_some() {
+        Operation::PointSelect
+    } else {
+        Operation::FullScan
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::concurrency::{IsolationLevel, TransactionManager};
     use crate::query::prepare_statement;
+    use crate::storage::PAGE_SIZE;
     use pretty_assertions::assert_eq;
     use std::str::FromStr;
     use std::sync::Arc;
@@ -211,6 +2193,41 @@ mod test {
         cleanup_test_db_file();
     }
 
+    #[test]
+    fn insert_batch_out_of_order() {
+        let table = setup_test_table(8);
+        let row = |i: usize| {
+            Row::new(
+                &i.to_string(),
+                &format!("user{i}"),
+                &format!("user{i}@email.com"),
+            )
+            .unwrap()
+        };
+        table.insert(&row(20));
+
+        // Spans several leaves and splits them, out of order, with a key
+        // that already exists and one given twice.
+        let mut ids: Vec<usize> = (1..50).rev().collect();
+        ids.push(7);
+        let rows: Vec<Row> = ids.iter().map(|i| row(*i)).collect();
+        let results = table.insert_batch(&rows);
+
+        assert_eq!(results.len(), ids.len());
+        for (id, result) in ids.iter().zip(&results[..ids.len() - 1]) {
+            if *id == 20 {
+                assert_eq!(result, &Err(DbError::DuplicateKey));
+            } else {
+                assert!(result.is_ok(), "row {id}: {result:?}");
+            }
+        }
+        assert_eq!(results[ids.len() - 1], Err(DbError::DuplicateKey));
+
+        let statement = prepare_statement("select").unwrap();
+        assert_eq!(table.select(&statement), expected_output(1..50));
+        cleanup_test_db_file();
+    }
+
     #[test]
     fn delete_cells_from_root_node() {
         deletion_test(10);
@@ -486,6 +2503,71 @@ mod test {
         cleanup_test_db_file();
     }
 
+    #[test]
+    fn concurrent_scans_with_inserts_and_deletes() {
+        let table = setup_test_table(32);
+        for i in (0..600).step_by(2) {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            table.insert(&row);
+        }
+
+        // Every scan returns the rows in key order, including every row
+        // that's there from the start, while writers split and merge the
+        // leaves under it.
+        let check = |keys: Vec<u32>, start: u32| {
+            assert!(keys.windows(2).all(|keys| keys[0] < keys[1]), "{keys:?}");
+            let kept = keys.iter().filter(|key| *key % 2 == 0).count();
+            assert_eq!(kept, (600 - start as usize) / 2, "{keys:?}");
+        };
+        std::thread::scope(|scope| {
+            for t in 0..4 {
+                let table = &table;
+                scope.spawn(move || {
+                    for i in (t..300).step_by(4) {
+                        let i = i * 2 + 1;
+                        let row = Row::from_str(&format!("{i} user{i} user{i}@email.com"));
+                        table.insert(&row.unwrap());
+                        if i % 3 != 0 {
+                            table.delete(&Row::from_str(&format!("{i} a b")).unwrap());
+                        }
+                    }
+                });
+            }
+            for _ in 0..4 {
+                let table = &table;
+                scope.spawn(move || {
+                    let select = prepare_statement("select").unwrap();
+                    let range = prepare_statement("select * from users where id >= 200").unwrap();
+                    for _ in 0..20 {
+                        check(table.iter().map(|(_, row)| row.id).collect(), 0);
+                        check(
+                            table.iter_range(100, None).map(|(_, row)| row.id).collect(),
+                            100,
+                        );
+                        check(
+                            table
+                                .select_rows(&select)
+                                .iter()
+                                .map(|row| row.id)
+                                .collect(),
+                            0,
+                        );
+                        check(
+                            table.select_rows(&range).iter().map(|row| row.id).collect(),
+                            200,
+                        );
+                    }
+                });
+            }
+        });
+
+        let keys: Vec<u32> = table.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(keys.len(), 300 + 100);
+        assert_eq!(table.pager.pinned_pages(), 0);
+
+        cleanup_test_db_file();
+    }
+
     #[test]
     fn concurrent_select_single() {
         let thread_pool_size = 4;
@@ -829,6 +2911,48 @@ mod test {
         }
     }
 
+    #[test]
+    fn concurrent_insert_and_print_tree() {
+        let thread_pool_size = 8;
+        let row = 500;
+
+        let pool = ThreadPool::new(thread_pool_size);
+        let table = Arc::new(setup_test_table(16));
+
+        for i in 1..row {
+            let t = Arc::clone(&table);
+            pool.execute(move || {
+                let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+                t.insert(&row);
+            });
+
+            if i % 50 == 0 {
+                let table = Arc::clone(&table);
+                pool.execute(move || {
+                    // Every tree printed should be a valid tree, where
+                    // the keys in our leaf nodes are in sorted order.
+                    let keys: Vec<u32> = table
+                        .to_string()
+                        .lines()
+                        .filter_map(|line| line.trim().strip_prefix("- "))
+                        .filter_map(|key| key.parse().ok())
+                        .collect();
+
+                    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+                });
+            }
+        }
+
+        pool.join();
+        assert_eq!(pool.panic_count(), 0);
+
+        let statement = prepare_statement("select").unwrap();
+        let result = table.select(&statement);
+        assert_eq!(result, expected_output(1..row));
+
+        cleanup_test_db_file();
+    }
+
     fn expected_output<I>(range: I) -> String
     where
         I: IntoIterator,
@@ -841,6 +2965,643 @@ mod test {
             .join("")
     }
 
+    #[test]
+    fn copy_from_csv() {
+        cleanup_test_db_file();
+        let table = setup_test_table(8);
+
+        // Out of order keys, a bad line, an empty line, and a duplicate key.
+        let mut input = String::new();
+        for i in (1..30).rev() {
+            input.push_str(&format!("{i},user{i},user{i}@email.com\n"));
+        }
+        input.push_str("apple,user,user@email.com\n");
+        input.push('\n');
+        input.push_str("5,dup,dup@email.com\n");
+        input.push_str("30,user30\n");
+
+        let result = table.copy_from(input.as_bytes());
+        assert_eq!(result.rows, 29);
+        assert_eq!(
+            result.rejected,
+            vec![
+                (30, "invalid id provided".to_string()),
+                (32, "duplicate key".to_string()),
+                (33, "expected 3 columns but got 2".to_string()),
+            ]
+        );
+
+        let statement = prepare_statement("select").unwrap();
+        let output = table.select(&statement);
+        assert_eq!(output.lines().count(), 29);
+        assert!(output.starts_with("(1, user1, user1@email.com)"));
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn iter_in_transaction() {
+        let lock_manager = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lock_manager.clone());
+        let table = setup_transactional_table(&tm, lock_manager);
+
+        let mut rid = 1;
+        for (_, row) in table.iter() {
+            assert_eq!(row.id, rid);
+            rid += 1;
+        }
+
+        // Verify it can be iterate multiple times
+        // without table being consumed.
+        rid = 1;
+        for (_, row) in table.iter() {
+            assert_eq!(row.username(), format!("user{rid}"));
+            rid += 1;
+        }
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn update_row_in_transaction() {
+        let lock_manager = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lock_manager.clone());
+        let table = setup_transactional_table(&tm, lock_manager);
+
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        let rid = table.get_row_id(1, &mut t).unwrap();
+        let row = Row::new("1", "user1", "user1@email.com").unwrap();
+        let new_row = Row::new("1", "john", "john@email.com").unwrap();
+        let columns = vec!["username".to_string(), "email".to_string()];
+        assert_eq!(
+            table.update(&row, &new_row, &columns, &rid, &mut t),
+            Ok(true)
+        );
+
+        let row = table.get(rid, &mut t).unwrap();
+        assert_eq!(row.id, 1);
+        assert_eq!(row.username(), "john");
+        assert_eq!(row.email(), "john@email.com");
+        tm.commit(&table, &mut t).unwrap();
+
+        // The auto-commit methods see the committed row.
+        let statement = prepare_statement("select 1").unwrap();
+        assert_eq!(table.select(&statement), "(1, john, john@email.com)\n");
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn grow_row_in_full_leaf() {
+        let lock_manager = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lock_manager.clone());
+        let table = Table::with_lock_manager(
+            format!("test-{:?}.db", std::thread::current().id()),
+            4,
+            lock_manager,
+        );
+        // Rows of 270 bytes, slot included, so 15 of them leave 10 bytes
+        // of the root leaf free.
+        let email = "e".repeat(254);
+        for i in 1..=15 {
+            table.insert(&Row::new(&i.to_string(), "u", &email).unwrap());
+        }
+        let columns = vec!["username".to_string()];
+        let row = Row::new("1", "u", &email).unwrap();
+
+        // A row shrinking keeps its bytes, which the leaf gets back once
+        // it's compacted for a row growing.
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        let rid = table.get_row_id(2, &mut t).unwrap();
+        let shorter = Row::new("2", "u", &"e".repeat(220)).unwrap();
+        let email_column = vec!["email".to_string()];
+        let old = Row::new("2", "u", &email).unwrap();
+        assert_eq!(
+            table.update(&old, &shorter, &email_column, &rid, &mut t),
+            Ok(true)
+        );
+        tm.commit(&table, &mut t).unwrap();
+
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        let rid = table.get_row_id(1, &mut t).unwrap();
+        let longer = Row::new("1", "a-much-longer-username", &email).unwrap();
+        assert_eq!(
+            table.update(&row, &longer, &columns, &rid, &mut t),
+            Ok(true)
+        );
+        assert_eq!(
+            table.get(rid, &mut t).unwrap().username(),
+            "a-much-longer-username"
+        );
+        tm.commit(&table, &mut t).unwrap();
+        assert_eq!(table.pager.tree_height(table.root_page_num).unwrap(), 1);
+
+        // A row that still doesn't fit once the leaf is compacted is moved
+        // to another leaf, splitting its leaf.
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        let rid = table.get_row_id(3, &mut t).unwrap();
+        let row = Row::new("3", "u", &email).unwrap();
+        let longest = Row::new("3", &"a".repeat(32), &email).unwrap();
+        assert_eq!(
+            table.update(&row, &longest, &columns, &rid, &mut t),
+            Ok(true)
+        );
+        assert_eq!(t.state, TransactionState::Growing);
+        assert_eq!(
+            table.get_fast(3).unwrap().unwrap().username(),
+            "a".repeat(32)
+        );
+        assert_eq!(table.pager.tree_height(table.root_page_num).unwrap(), 2);
+
+        // The moved row is rolled back where it's now.
+        tm.abort(&table, &mut t);
+        assert_eq!(table.get_fast(3).unwrap().unwrap().username(), "u");
+        assert_eq!(
+            table
+                .select_rows(&prepare_statement("select").unwrap())
+                .len(),
+            15
+        );
+        assert_eq!(table.check(), Ok(()));
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn get_fast_reads_uncommitted_rows() {
+        let lock_manager = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lock_manager.clone());
+        let table = setup_transactional_table(&tm, lock_manager);
+        assert_eq!(table.get_fast(1).unwrap().unwrap().username(), "user1");
+        assert_eq!(table.get_fast(50), Ok(None));
+
+        let transaction = tm.begin(IsolationLevel::RepeatableRead);
+        let mut t = transaction.write();
+        let row = Row::new("1", "user1", "user1@email.com").unwrap();
+        let new_row = Row::new("1", "john", "john@email.com").unwrap();
+        let columns = vec!["username".to_string()];
+        let rid = table.get_row_id(1, &mut t).unwrap();
+        table
+            .update(&row, &new_row, &columns, &rid, &mut t)
+            .unwrap();
+        let row = Row::new("2", "user2", "user2@email.com").unwrap();
+        let rid = table.get_row_id(2, &mut t).unwrap();
+        table.delete_in(&row, &rid, &mut t).unwrap();
+
+        // The exclusive locks of the transaction aren't waited for.
+        assert_eq!(table.get_fast(1).unwrap().unwrap().username(), "john");
+        assert_eq!(table.get_fast(2), Ok(None));
+
+        tm.abort(&table, &mut t);
+        assert_eq!(table.get_fast(1).unwrap().unwrap().username(), "user1");
+        assert_eq!(table.get_fast(2).unwrap().unwrap().username(), "user2");
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn secondary_index_is_maintained() {
+        let lock_manager = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lock_manager.clone());
+        let table = setup_transactional_table(&tm, lock_manager);
+        assert_eq!(table.index_lookup("username", "user1"), None);
+        assert_eq!(
+            table.create_index("username", false),
+            Ok("created index on 'users.username'".to_string())
+        );
+        assert_eq!(table.index_lookup("username", "user1"), Some(vec![1]));
+        assert_eq!(
+            table.create_index("username", false),
+            Err(DbError::Catalog(
+                "index on 'users.username' already exists".to_string()
+            ))
+        );
+        assert_eq!(
+            table.create_index("id", false),
+            Err(DbError::InvalidInput(
+                "column 'id' is already indexed as the primary key".to_string()
+            ))
+        );
+        assert_eq!(
+            table.create_index("name", false),
+            Err(DbError::InvalidInput("unknown column 'name'".to_string()))
+        );
+
+        table.insert(&Row::new("50", "user1", "other@email.com").unwrap());
+        assert_eq!(table.index_lookup("username", "user1"), Some(vec![1, 50]));
+        table.delete(&Row::new("50", "", "").unwrap());
+        assert_eq!(table.index_lookup("username", "user1"), Some(vec![1]));
+
+        // Updates are undone in the index on abort, and deletes are only
+        // applied to it on commit.
+        let transaction = tm.begin(IsolationLevel::RepeatableRead);
+        let mut t = transaction.write();
+        let row = Row::new("1", "user1", "user1@email.com").unwrap();
+        let new_row = Row::new("1", "john", "john@email.com").unwrap();
+        let columns = vec!["username".to_string()];
+        let rid = table.get_row_id(1, &mut t).unwrap();
+        table
+            .update(&row, &new_row, &columns, &rid, &mut t)
+            .unwrap();
+        assert_eq!(table.index_lookup("username", "john"), Some(vec![1]));
+        assert_eq!(table.index_lookup("username", "user1"), Some(vec![]));
+        tm.abort(&table, &mut t);
+        drop(t);
+        assert_eq!(table.index_lookup("username", "john"), Some(vec![]));
+        assert_eq!(table.index_lookup("username", "user1"), Some(vec![1]));
+
+        let transaction = tm.begin(IsolationLevel::RepeatableRead);
+        let mut t = transaction.write();
+        let row = Row::new("2", "user2", "user2@email.com").unwrap();
+        let rid = table.get_row_id(2, &mut t).unwrap();
+        table.delete_in(&row, &rid, &mut t).unwrap();
+        assert_eq!(table.index_lookup("username", "user2"), Some(vec![2]));
+        tm.commit(&table, &mut t).unwrap();
+        drop(t);
+        assert_eq!(table.index_lookup("username", "user2"), Some(vec![]));
+
+        // The index is rebuilt from the rows when the table is opened.
+        table.close().unwrap();
+        drop(table);
+        let table = setup_test_table(4);
+        assert_eq!(table.index_lookup("username", "user3"), Some(vec![3]));
+        assert_eq!(table.index_lookup("username", "user2"), Some(vec![]));
+
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn metadata_is_written_on_commit() {
+        let lock_manager = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lock_manager.clone());
+        let table = setup_transactional_table(&tm, lock_manager);
+        assert_eq!(table.meta_get("app.version"), Ok(None));
+
+        table.meta_put("app.version", b"1").unwrap();
+        table.meta_put("app.counter", &[0]).unwrap();
+        assert_eq!(table.meta_get("app.version"), Ok(Some(b"1".to_vec())));
+
+        let transaction = tm.begin(IsolationLevel::RepeatableRead);
+        let mut t = transaction.write();
+        table.meta_put_in("app.version", b"2", &mut t);
+        assert_eq!(
+            table.meta_get_in("app.version", &t),
+            Ok(Some(b"2".to_vec()))
+        );
+        assert_eq!(table.meta_get("app.version"), Ok(Some(b"1".to_vec())));
+        tm.abort(&table, &mut t);
+        drop(t);
+        assert_eq!(table.meta_get("app.version"), Ok(Some(b"1".to_vec())));
+
+        let transaction = tm.begin(IsolationLevel::RepeatableRead);
+        let mut t = transaction.write();
+        table.meta_put_in("app.version", b"3", &mut t);
+        table.meta_put_in("app.counter", &[1], &mut t);
+        tm.commit(&table, &mut t).unwrap();
+        drop(t);
+
+        // Read back from disk by a table opened on the same file.
+        let reopened = Table::new(format!("test-{:?}.db", std::thread::current().id()), 10);
+        assert_eq!(reopened.meta_get("app.version"), Ok(Some(b"3".to_vec())));
+        assert_eq!(reopened.meta_get("app.counter"), Ok(Some(vec![1])));
+        assert!(table.meta_put("app.blob", &[0; PAGE_SIZE]).is_err());
+        assert_eq!(table.meta_get("app.blob"), Ok(None));
+
+        cleanup_test_db_file();
+        let _ = std::fs::remove_file(format!("test-{:?}.db.catalog", std::thread::current().id()));
+    }
+
+    // Benchmark of `get_fast` against the transactional path, run with
+    // `cargo test --release bench_point_lookup -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_point_lookup() {
+        const ROWS: u32 = 1_000;
+        const LOOKUPS: u32 = 100_000;
+
+        cleanup_test_db_file();
+        let lock_manager = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lock_manager.clone());
+        let table = Table::with_lock_manager(
+            format!("test-{:?}.db", std::thread::current().id()),
+            128,
+            lock_manager.clone(),
+        );
+        for i in 1..=ROWS {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            table.insert(&row);
+        }
+        let keys: Vec<u32> = (0..LOOKUPS).map(|i| i * 7919 % ROWS + 1).collect();
+
+        let start = std::time::Instant::now();
+        for key in &keys {
+            assert_eq!(table.get_fast(*key).unwrap().unwrap().id, *key);
+        }
+        let fast = start.elapsed();
+
+        let start = std::time::Instant::now();
+        for key in &keys {
+            let transaction = tm.begin(IsolationLevel::ReadCommited);
+            let mut t = transaction.write();
+            let rid = table.get_row_id(*key, &mut t).unwrap();
+            lock_manager.lock_shared(&mut t, rid).unwrap();
+            assert_eq!(table.get(rid, &mut t).unwrap().id, *key);
+            lock_manager.unlock_after_read(&mut t, &rid);
+            tm.commit(&table, &mut t).unwrap();
+        }
+        let transactional = start.elapsed();
+
+        println!(
+            "{LOOKUPS} point lookups: get_fast {:?} ({:?}/lookup), transactional {:?} ({:?}/lookup)",
+            fast,
+            fast / LOOKUPS,
+            transactional,
+            transactional / LOOKUPS
+        );
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn ingest_mode() {
+        cleanup_test_db_file();
+        let table = setup_test_table(16);
+        for i in 1..500 {
+            table.insert(&wide_row(i));
+        }
+        let evenly_split = table.pager.occupancy(table.root_page_num).unwrap();
+        drop(table);
+        cleanup_test_db_file();
+
+        // Sequential inserts leave packed leaves.
+        let table = setup_test_table(16);
+        table.begin_ingest();
+        for i in 1..500 {
+            table.insert(&wide_row(i));
+        }
+        let packed = table.pager.occupancy(table.root_page_num).unwrap();
+        assert!(packed.leaves < evenly_split.leaves);
+        assert!(packed.percent() > 80, "{packed}");
+
+        // Leaves aren't merged until the ingestion ends.
+        for i in (1..500).filter(|i| i % 4 != 0) {
+            let row = wide_row(i);
+            assert!(table.delete_row(&row));
+        }
+        assert_eq!(
+            table.pager.occupancy(table.root_page_num).unwrap().leaves,
+            packed.leaves
+        );
+
+        let merged = table.end_ingest();
+        assert_ne!(merged, "merged 0 leaves");
+        assert!(table.pager.occupancy(table.root_page_num).unwrap().leaves < packed.leaves);
+        assert_eq!(table.check(), Ok(()));
+        let ids: Vec<u32> = table.iter().map(|(_, row)| row.id).collect();
+        assert_eq!(ids, (4..500).step_by(4).collect::<Vec<_>>());
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn write_ahead_log_before_pages() {
+        cleanup_test_db_file();
+        let wal_path = format!("test-{:?}.wal", std::thread::current().id());
+        let log_manager = Arc::new(LogManager::new(&wal_path));
+        let table = Table::with_log_manager(
+            format!("test-{:?}.db", std::thread::current().id()),
+            4,
+            log_manager.clone(),
+        );
+
+        // With a pool of 4 pages, pages are evicted along the way, which
+        // flushes the log up to them first.
+        for i in 1..50 {
+            let row = wide_row(i);
+            table.insert(&row);
+        }
+        assert!(log_manager.persistent_lsn().is_some());
+
+        let tm = TransactionManager::new(Arc::new(LockManager::new()));
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        let rid = table.get_row_id(1, &mut t).unwrap();
+        let row = wide_row(1);
+        let new_row = Row::new("1", "john", "john@email.com").unwrap();
+        let columns = vec!["username".to_string(), "email".to_string()];
+        assert_eq!(
+            table.update(&row, &new_row, &columns, &rid, &mut t),
+            Ok(true)
+        );
+        tm.abort(&table, &mut t);
+
+        // The abort isn't flushed, unlike a commit.
+        assert_eq!(log_manager.last_lsn(), Some(52));
+        assert_eq!(log_manager.persistent_lsn(), Some(49));
+        table.flush();
+        assert_eq!(log_manager.persistent_lsn(), Some(52));
+
+        let records = log_manager.get_logs();
+        assert_eq!(records.len(), 52);
+        assert!(records[..49]
+            .iter()
+            .all(|record| record.log_type() == LogRecordType::Insert && record.txn_id == 0));
+        let txn_records: Vec<_> = records[49..]
+            .iter()
+            .map(|record| (record.log_type(), record.txn_id, record.row().cloned()))
+            .collect();
+        assert_eq!(
+            txn_records,
+            vec![
+                (LogRecordType::Update, t.txn_id, Some(new_row)),
+                (LogRecordType::Update, t.txn_id, Some(row)),
+                (LogRecordType::Abort, t.txn_id, None),
+            ]
+        );
+
+        for id in 1..3 {
+            let _ = std::fs::remove_file(format!("{wal_path}.{id:06}"));
+        }
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn check_tables_in_catalog() {
+        cleanup_test_db_file();
+        let table = setup_test_table(8);
+        table.create_table("orders", &Schema::users()).unwrap();
+        for i in 1..50 {
+            let row = Row::from_str(&format!("{i} user{i} team@example.com")).unwrap();
+            table.insert(&row);
+        }
+        table.analyze().unwrap();
+        let dictionary_page = table.catalog.get("users").unwrap().dictionary_page.unwrap();
+        table.close().unwrap();
+
+        let table = setup_test_table(8);
+        assert_eq!(table.check(), Ok(()));
+        drop(table);
+
+        // Flip a byte of the strings of the dictionary.
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[dictionary_page * PAGE_SIZE + 40] ^= 1;
+        std::fs::write(&path, bytes).unwrap();
+
+        let table = setup_test_table(8);
+        assert_eq!(
+            table.check(),
+            Err(format!(
+                "table 'users': dictionary page {dictionary_page} is corrupted"
+            ))
+        );
+        drop(table);
+
+        // A catalog that can't be decoded is reported instead of panicking.
+        std::fs::write(format!("{path}.catalog"), [0xff; 3]).unwrap();
+        let table = setup_test_table(8);
+        let problems = table.check().unwrap_err();
+        assert!(
+            problems.starts_with(&format!("catalog {path}.catalog is corrupted: ")),
+            "{problems}"
+        );
+        assert!(
+            problems.contains(", the tables besides 'users' can't be found"),
+            "{problems}"
+        );
+
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn report_duplicate_key_the_same_way_for_every_insert() {
+        let lm = Arc::new(LockManager::new());
+        let tm = TransactionManager::new(lm.clone());
+        let table = setup_transactional_table(&tm, lm);
+        let row = Row::from_str("1 user1 user1@email.com").unwrap();
+
+        assert_eq!(table.insert_row(&row), Err(DbError::DuplicateKey));
+        assert_eq!(table.insert(&row), "duplicate key\n");
+
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        assert_eq!(table.insert_in(&row, &mut t), Err(DbError::DuplicateKey));
+        drop(t);
+
+        let row = Row::from_str("50 user50 user50@email.com").unwrap();
+        let rid = table.insert_row(&row).unwrap();
+        let mut t = transaction.write();
+        assert_eq!(table.get(rid, &mut t), Some(row));
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn close_waits_for_operations_in_flight() {
+        let table = Arc::new(setup_test_table(8));
+        let row = Row::from_str("1 user1 user1@email.com").unwrap();
+
+        let op = table.enter().unwrap();
+        let closer = {
+            let table = table.clone();
+            std::thread::spawn(move || table.close())
+        };
+
+        // The table is closing, so new operations are rejected, while the
+        // one in flight can still finish.
+        while table.enter().is_ok() {
+            std::thread::yield_now();
+        }
+        assert!(!closer.is_finished());
+        assert_eq!(table.insert(&row), "table is closed");
+        assert_eq!(table.insert_row(&row), Err(DbError::TableClosed));
+        drop(op);
+
+        assert_eq!(closer.join().unwrap(), Ok(()));
+        assert_eq!(table.close(), Err(DbError::TableClosed));
+        let statement = prepare_statement("select").unwrap();
+        assert!(table.select_rows(&statement).is_empty());
+
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn resume_scan_from_cursor() {
+        let table = setup_test_table(8);
+        for i in (2..=200).step_by(2) {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            table.insert(&row);
+        }
+
+        for (direction, expected) in [
+            (
+                ScanDirection::Forward,
+                (2..=200).step_by(2).collect::<Vec<u32>>(),
+            ),
+            (
+                ScanDirection::Backward,
+                (1..=100).rev().map(|i| i * 2).collect(),
+            ),
+        ] {
+            let mut keys = Vec::new();
+            let mut cursor = Some(table.scan_cursor(direction));
+            while let Some(current) = cursor {
+                // Resume from a token, as a client would.
+                let current = ScanCursor::from_token(&current.to_token()).unwrap();
+                let (rows, next) = table.scan(&current, 7);
+                keys.extend(rows.iter().map(|row| row.id));
+                cursor = next;
+            }
+            assert_eq!(keys, expected);
+        }
+
+        // Rows inserted behind the cursor are skipped, while the epoch
+        // tells the table has changed.
+        let cursor = table.scan_cursor(ScanDirection::Forward);
+        let (_, cursor) = table.scan(&cursor, 3);
+        let cursor = cursor.unwrap();
+        table.insert(&Row::from_str("1 user1 user1@email.com").unwrap());
+        table.insert(&Row::from_str("7 user7 user7@email.com").unwrap());
+        let (rows, _) = table.scan(&cursor, 2);
+        assert_eq!(rows.iter().map(|row| row.id).collect::<Vec<_>>(), [7, 8]);
+        assert_ne!(table.epoch(), cursor.epoch);
+
+        assert!(ScanCursor::from_token("zz").is_err());
+
+        cleanup_test_db_file();
+    }
+
+    fn setup_transactional_table(tm: &TransactionManager, lm: Arc<LockManager>) -> Table {
+        let table =
+            Table::with_lock_manager(format!("test-{:?}.db", std::thread::current().id()), 4, lm);
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        for i in 1..50 {
+            let row = Row::from_str(&format!("{i} user{i} user{i}@email.com")).unwrap();
+            table.insert_in(&row, &mut t).unwrap();
+        }
+        tm.commit(&table, &mut t).unwrap();
+
+        table
+    }
+
+    // A row with the longest username and email, so a leaf holds 13 of
+    // them, which the tests of the shape of the tree are written for.
+    fn wide_row(i: u32) -> Row {
+        Row::new(
+            &i.to_string(),
+            &format!("{:x<32}", format!("user{i}")),
+            &format!("{:x<255}", format!("user{i}@email.com")),
+        )
+        .unwrap()
+    }
+
     fn setup_test_table(pool_size: usize) -> Table {
         return Table::new(
             format!("test-{:?}.db", std::thread::current().id()),
@@ -859,7 +3620,111 @@ mod test {
         table.flush();
     }
 
+    #[test]
+    fn roll_back_create_table_on_abort() {
+        cleanup_test_db_file();
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let wal_path = format!("test-{:?}.wal", std::thread::current().id());
+        let log_manager = Arc::new(LogManager::new(&wal_path));
+        let table = Table::with_log_manager(&path, 4, log_manager.clone());
+        let tm = TransactionManager::new(Arc::new(LockManager::new()));
+
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        table
+            .create_table_in("orders", &Schema::users(), &mut t)
+            .unwrap();
+        table
+            .create_table_in("items", &Schema::users(), &mut t)
+            .unwrap();
+        assert_eq!(table.table_names(), vec!["items", "orders", "users"]);
+        tm.abort(&table, &mut t);
+        assert_eq!(table.table_names(), vec!["users"]);
+        assert!(table.open_table("orders").is_err());
+        // The roots of both tables are returned to the free page list.
+        assert_eq!(table.pager.num_of_free_pages(), 2);
+
+        // The tables are dropped before the abort is logged.
+        log_manager.flush_log_buffer();
+        let records: Vec<_> = log_manager
+            .get_logs()
+            .into_iter()
+            .map(|record| (record.log_type(), record.table().map(String::from)))
+            .collect();
+        let table_change = |log_type, name: &str| (log_type, Some(name.to_string()));
+        assert_eq!(
+            records,
+            vec![
+                table_change(LogRecordType::CreateTable, "orders"),
+                table_change(LogRecordType::CreateTable, "items"),
+                table_change(LogRecordType::DropTable, "items"),
+                table_change(LogRecordType::DropTable, "orders"),
+                (LogRecordType::Abort, None),
+            ]
+        );
+
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        table
+            .create_table_in("orders", &Schema::users(), &mut t)
+            .unwrap();
+        tm.commit(&table, &mut t).unwrap();
+        assert_eq!(
+            Catalog::open(&path).unwrap().table_names(),
+            vec!["orders", "users"]
+        );
+
+        for id in 1..3 {
+            let _ = std::fs::remove_file(format!("{wal_path}.{id:06}"));
+        }
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+        cleanup_test_db_file();
+    }
+
+    #[test]
+    fn read_only_replica() {
+        cleanup_test_db_file();
+        let path = format!("test-{:?}.db", std::thread::current().id());
+        let table = Table::new(&path, 8);
+        table
+            .insert_row(&Row::new("1", "john", "john@email.com").unwrap())
+            .unwrap();
+        table.flush();
+
+        let pool = Arc::new(BufferPool::new(8));
+        let replica = Table::with_buffer_pool(&path, pool).read_only();
+        let statement = prepare_statement("select").unwrap();
+        assert_eq!(replica.select(&statement), "(1, john, john@email.com)\n");
+
+        let row = Row::new("2", "wick", "wick@email.com").unwrap();
+        assert_eq!(replica.insert_row(&row), Err(DbError::ReadOnly));
+        assert_eq!(
+            replica.insert_batch(&[row.clone()]),
+            vec![Err(DbError::ReadOnly)]
+        );
+        assert_eq!(replica.delete(&row), "table is read-only");
+        assert_eq!(
+            replica.create_index("username", false),
+            Err(DbError::ReadOnly)
+        );
+        assert_eq!(
+            replica.create_table("orders", &Schema::users()),
+            Err(DbError::ReadOnly)
+        );
+
+        let tm = replica.transaction_manager();
+        let transaction = tm.begin(IsolationLevel::ReadCommited);
+        let mut t = transaction.write();
+        assert_eq!(replica.insert_in(&row, &mut t), Err(DbError::ReadOnly));
+        tm.abort(&replica, &mut t);
+        assert_eq!(replica.select(&statement), "(1, john, john@email.com)\n");
+
+        let _ = std::fs::remove_file(format!("{path}.catalog"));
+        cleanup_test_db_file();
+    }
+
     fn cleanup_test_db_file() {
         let _ = std::fs::remove_file(format!("test-{:?}.db", std::thread::current().id()));
+        let _ = std::fs::remove_file(format!("test-{:?}.db.hot", std::thread::current().id()));
     }
 }